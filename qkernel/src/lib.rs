@@ -31,6 +31,7 @@
 #![allow(deprecated)]
 #![recursion_limit = "256"]
 
+extern crate aes;
 #[macro_use]
 extern crate alloc;
 extern crate bit_field;
@@ -208,8 +209,12 @@ pub fn SingletonInit() {
         socket::socket::InitSingleton();
         syscalls::sys_rlimit::InitSingleton();
         task::InitSingleton();
+        tcpip::stack::InitSingleton();
 
         qlib::InitSingleton();
+        // registers the socket metrics, so it needs the metric set above.
+        kernel::socket_store::InitSingleton();
+        memmgr::page_reporting::InitSingleton();
     }
 }
 
@@ -479,9 +484,26 @@ pub fn InitTsc() {
     VcpuFreqInit();
 }
 
-fn InitLoader() {
+// LoadProcess gets the root process from qvisor, a container spec which fails
+// to load shuts the VM down.
+fn LoadProcess() -> Process {
     let mut process = Process::default();
-    Kernel::HostSpace::LoadProcessKernel(&mut process as *mut _ as u64) as usize;
+    let ret = Kernel::HostSpace::LoadProcessKernel(&mut process as *mut _ as u64);
+    if ret < 0 {
+        error!(
+            "load process failure with error {}, shutting down...",
+            ret
+        );
+        SHARESPACE.StoreShutdown();
+        Kernel::HostSpace::ExitVM(2);
+        panic!("exiting ...");
+    }
+
+    return process;
+}
+
+fn InitLoader() {
+    let process = LoadProcess();
     LOADER.InitKernel(process).unwrap();
 }
 
@@ -547,6 +569,10 @@ pub extern "C" fn rust_main(
         }
 
         CreateTask(ControllerProcess as u64, ptr::null(), true);
+
+        if tcpip::stack::NetStack::Enabled() {
+            CreateTask(tcpip::stack::NetstackProcess as u64, ptr::null(), true);
+        }
     }
 
     WaitFn();
@@ -586,8 +612,7 @@ pub fn StartRootProcess() {
 fn StartRootContainer(_para: *const u8) -> ! {
     info!("StartRootContainer ....");
     let task = Task::Current();
-    let mut process = Process::default();
-    Kernel::HostSpace::LoadProcessKernel(&mut process as *mut _ as u64) as usize;
+    let process = LoadProcess();
 
     let (_tid, entry, userStackAddr, kernelStackAddr) = {
         let mut processArgs = LOADER.Lock(task).unwrap().Init(process);
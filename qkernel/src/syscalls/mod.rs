@@ -17,6 +17,7 @@ pub mod sys_capability;
 pub mod sys_chmod;
 pub mod sys_epoll;
 pub mod sys_eventfd;
+pub mod sys_fanotify;
 pub mod sys_file;
 pub mod sys_futex;
 pub mod sys_getdents;
@@ -27,11 +28,13 @@ pub mod sys_memfd;
 pub mod sys_mempolicy;
 pub mod sys_mmap;
 pub mod sys_mmap_socket;
+pub mod sys_mount;
 pub mod sys_msgqueue;
 pub mod sys_pipe;
 pub mod sys_poll;
 pub mod sys_prctl;
 pub mod sys_proxy;
+pub mod sys_quota;
 pub mod sys_random;
 pub mod sys_read;
 pub mod sys_rlimit;
@@ -296,7 +296,14 @@ pub fn PerformanceCallback(
 
     let inodeType = inode.InodeType();
 
-    if inodeType == InodeType::RegularFile {
+    // the plaintext cache of an encrypted file is in the guest memory, which
+    // the host can't read or write for the aio
+    let crypt = match inode.lock().InodeOp.HostInodeOp() {
+        None => false,
+        Some(iops) => iops.IsCrypt(),
+    };
+
+    if inodeType == InodeType::RegularFile && !crypt {
         return PerformanceUringCallback(task, file, cbAddr, cb, ctx, eventfops);
     }
 
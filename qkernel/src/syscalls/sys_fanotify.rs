@@ -0,0 +1,218 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::kernel::fd_table::*;
+use super::super::qlib::common::*;
+use super::super::qlib::kernel::fs::anon::*;
+use super::super::qlib::kernel::fs::dirent::*;
+use super::super::qlib::kernel::fs::fanotify::*;
+use super::super::qlib::kernel::fs::file::*;
+use super::super::qlib::kernel::fs::flags::*;
+use super::super::qlib::linux::fanotify::*;
+use super::super::qlib::linux_def::*;
+use super::super::syscalls::syscalls::*;
+use super::super::task::*;
+use super::sys_file::*;
+
+// EVENT_F_FLAGS are the flags accepted for the files opened for the events.
+const EVENT_F_FLAGS: u32 = (Flags::O_ACCMODE
+    | Flags::O_LARGEFILE
+    | Flags::O_CLOEXEC
+    | Flags::O_APPEND
+    | Flags::O_DSYNC
+    | Flags::O_NOATIME
+    | Flags::O_NONBLOCK
+    | Flags::O_SYNC) as u32;
+
+// FanotifyInit implements the fanotify_init() syscall.
+pub fn SysFanotifyInit(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let flags = args.arg0 as u32;
+    let eventFFlags = args.arg1 as u32;
+
+    if !task.Creds().HasCapability(Capability::CAP_SYS_ADMIN) {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    if flags & !FANOTIFY_INIT_FLAGS != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let class = flags & FAN_ALL_CLASS_BITS;
+    if class == FAN_ALL_CLASS_BITS {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    // the listeners reporting fids don't get the permission events
+    if flags & FAN_REPORT_FID != 0 && class != FAN_CLASS_NOTIF {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    if eventFFlags & !EVENT_F_FLAGS != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    match eventFFlags & Flags::O_ACCMODE as u32 {
+        x if x == Flags::O_RDONLY as u32 => (),
+        x if x == Flags::O_WRONLY as u32 => (),
+        x if x == Flags::O_RDWR as u32 => (),
+        _ => return Err(Error::SysError(SysErr::EINVAL)),
+    }
+
+    let inode = NewAnonInode(task);
+    let dirent = Dirent::New(&inode, "anon_inode:[fanotify]");
+
+    let fileFlags = FileFlags {
+        Read: true,
+        Write: true,
+        NonBlocking: flags & FAN_NONBLOCK != 0,
+        ..Default::default()
+    };
+
+    let fops = FanotifyFileOperations::New(flags, eventFFlags);
+    let file = File::New(&dirent, &fileFlags, fops.into());
+    let fd = task.NewFDFrom(
+        0,
+        &file,
+        &FDFlags {
+            CloseOnExec: flags & FAN_CLOEXEC != 0,
+        },
+    )?;
+
+    return Ok(fd as i64);
+}
+
+// FdToFanotify resolves an fd to a fanotify listener.
+pub fn FdToFanotify(task: &Task, fd: i32) -> Result<(FanotifyFileOperations, File)> {
+    let file = task.GetFile(fd)?;
+    let fanotify = match file
+        .FileOp
+        .as_any()
+        .downcast_ref::<FanotifyFileOperations>()
+    {
+        Some(f) => f.clone(),
+        None => return Err(Error::SysError(SysErr::EINVAL)),
+    };
+
+    return Ok((fanotify, file));
+}
+
+// FanotifyMark implements the fanotify_mark() syscall.
+pub fn SysFanotifyMark(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let fd = args.arg0 as i32;
+    let flags = args.arg1 as u32;
+    let mask = args.arg2 as u64;
+    let dirfd = args.arg3 as i32;
+    let addr = args.arg4 as u64;
+
+    if flags & !FANOTIFY_MARK_FLAGS != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let markType = flags & FAN_MARK_TYPE_MASK;
+    if markType == FAN_MARK_TYPE_MASK {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let op = flags & (FAN_MARK_ADD | FAN_MARK_REMOVE | FAN_MARK_FLUSH);
+    if op != FAN_MARK_ADD && op != FAN_MARK_REMOVE && op != FAN_MARK_FLUSH {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    if mask & !FANOTIFY_MARK_MASK != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let (fanotify, _file) = FdToFanotify(task, fd)?;
+
+    if op == FAN_MARK_FLUSH {
+        fanotify.Flush(markType);
+        return Ok(0);
+    }
+
+    if mask == 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    if mask & FANOTIFY_PERM_EVENTS != 0 && fanotify.Class() == FAN_CLASS_NOTIF {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    // the inode events can only be reported with the fids, and not for a
+    // whole mount
+    if mask & FANOTIFY_INODE_EVENTS != 0 {
+        if fanotify.flags & FAN_REPORT_FID == 0 || markType == FAN_MARK_MOUNT {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+    }
+
+    let resolve = flags & FAN_MARK_DONT_FOLLOW == 0;
+    let mut target = None;
+    let mut mark = |d: &Dirent| -> Result<()> {
+        let inode = d.Inode();
+        if flags & FAN_MARK_ONLYDIR != 0 && !inode.StableAttr().IsDir() {
+            return Err(Error::SysError(SysErr::ENOTDIR));
+        }
+
+        inode.CheckPermission(
+            task,
+            &PermMask {
+                read: true,
+                ..Default::default()
+            },
+        )?;
+
+        target = Some(match markType {
+            FAN_MARK_MOUNT => match task.mountNS.FindMount(d) {
+                None => return Err(Error::SysError(SysErr::EINVAL)),
+                Some(mount) => FanotifyMarkTarget::Mount(mount.lock().Id),
+            },
+            FAN_MARK_FILESYSTEM => {
+                let msrc = inode.lock().MountSource.clone();
+                FanotifyMarkTarget::Filesystem(msrc)
+            }
+            _ => FanotifyMarkTarget::Inode(d.clone()),
+        });
+        return Ok(());
+    };
+
+    // "If pathname is NULL, the filesystem object to be marked is determined
+    // by the file descriptor dirfd." -- fanotify_mark(2)
+    if addr == 0 {
+        let d = if dirfd == ATType::AT_FDCWD {
+            task.Workdir()
+        } else {
+            task.GetFile(dirfd)?.Dirent.clone()
+        };
+
+        mark(&d)?;
+    } else {
+        let (path, _) = copyInPath(task, addr, false)?;
+        fileOpOn(
+            task,
+            dirfd,
+            &path,
+            resolve,
+            &mut |_root: &Dirent, d: &Dirent, _remainingTraversals: u32| -> Result<()> {
+                return mark(d);
+            },
+        )?;
+    }
+
+    match target {
+        None => return Err(Error::SysError(SysErr::EINVAL)),
+        Some(target) => fanotify.Mark(target, mask, flags)?,
+    }
+
+    return Ok(0);
+}
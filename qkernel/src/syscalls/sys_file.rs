@@ -16,6 +16,7 @@ use alloc::string::String;
 use alloc::string::ToString;
 
 use super::super::fs::dirent::*;
+use super::super::fs::fanotify::*;
 use super::super::fs::file::*;
 use super::super::fs::flags::*;
 use super::super::fs::inode::*;
@@ -27,12 +28,15 @@ use super::super::kernel::pipe::reader::*;
 use super::super::kernel::pipe::reader_writer::*;
 use super::super::kernel::pipe::writer::*;
 use super::super::kernel::time::*;
+use super::super::kernel::unsupported::Unsupported;
 //use super::super::kernel_def::*;
 use super::super::qlib::auth::cap_set::*;
 use super::super::qlib::auth::id::*;
 use super::super::qlib::auth::*;
 use super::super::qlib::common::*;
+use super::super::qlib::control_msg::UnsupportedKind;
 use super::super::qlib::limits::*;
+use super::super::qlib::linux::fanotify::*;
 use super::super::qlib::linux::fcntl::*;
 use super::super::qlib::linux::time::*;
 use super::super::qlib::linux_def::*;
@@ -274,10 +278,16 @@ pub fn openAt(task: &Task, dirFd: i32, addr: u64, flags: u32) -> Result<i32> {
                             let parent = d.Parent().unwrap();
                             let parentIops = parent.Inode().lock().InodeOp.clone();
                             
-                            let dirfd = parentIops.HostDirOp().expect(&format!("inodeop type is {:?}", parentIops.InodeType())).HostFd();
-                            let name = d.Name();
+                            let dirOp = parentIops.HostDirOp().expect(&format!("inodeop type is {:?}", parentIops.InodeType()));
+                            let dirfd = dirOp.HostFd();
+                            let name = dirOp.HostName(&d.Name())?;
                             let cstr = CString::New(&name);
                             lkiops.TryOpenWrite(dirfd, cstr.Ptr())?;
+                            core::mem::drop(lkiops);
+
+                            // the host locks are released with the reopened host file
+                            let lockCtx = inode.lock().LockCtx.clone();
+                            lockCtx.SyncHost(task);
                         }
                     }
                     None => (),
@@ -324,8 +334,6 @@ pub fn openAt(task: &Task, dirFd: i32, addr: u64, flags: u32) -> Result<i32> {
                 if inode.StableAttr().IsDir() {
                     return Err(Error::SysError(SysErr::EISDIR));
                 }
-
-                inode.Truncate(task, d, 0)?;
             }
 
             let file = match inode.GetFile(task, &d, &fileFlags) {
@@ -338,6 +346,14 @@ pub fn openAt(task: &Task, dirFd: i32, addr: u64, flags: u32) -> Result<i32> {
                 }
             };
 
+            FanotifyPermission(task, &file, FAN_OPEN_PERM)?;
+
+            // the file is only truncated once the fanotify listeners allow
+            // the open
+            if flags & Flags::O_TRUNC as u32 != 0 {
+                inode.Truncate(task, d, 0)?;
+            }
+
             let newFd = task.NewFDFrom(
                 0,
                 &file,
@@ -553,10 +569,16 @@ pub fn createAt(task: &Task, dirFd: i32, addr: u64, flags: u32, mode: FileMode)
                     match iops.HostInodeOp() {
                         Some(iops) => {
                             let mut lkiops = iops.lock();
-                            let dirfd = parent.Inode().lock().InodeOp.HostDirOp().unwrap().HostFd();
+                            let dirOp = parent.Inode().lock().InodeOp.HostDirOp().unwrap();
+                            let dirfd = dirOp.HostFd();
                             if lkiops.SkipRw() {
-                                let cstr = CString::New(&name);
+                                let cstr = CString::New(&dirOp.HostName(&name)?);
                                 lkiops.TryOpenWrite(dirfd, cstr.Ptr())?;
+                                core::mem::drop(lkiops);
+
+                                // the host locks are released with the reopened host file
+                                let lockCtx = foundInode.lock().LockCtx.clone();
+                                lockCtx.SyncHost(task);
                             }
                         }
                         None => (),
@@ -607,6 +629,8 @@ pub fn createAt(task: &Task, dirFd: i32, addr: u64, flags: u32, mode: FileMode)
             e => return Err(e)
         };
 
+        FanotifyPermission(task, &newFile, FAN_OPEN_PERM)?;
+
         let newFd = task.NewFDFrom(
             0,
             &newFile,
@@ -763,7 +787,26 @@ pub fn Ioctl(task: &mut Task, fd: i32, request: u64, val: u64) -> Result<u64> {
             task.CopyOutObj(&who, val)?;
             return Ok(0);
         }
-        _ => return file.Ioctl(task, fd, request, val),
+        _ => {
+            let res = file.Ioctl(task, fd, request, val);
+            match res {
+                // TCGETS is how isatty probes a file, ENOTTY is expected for
+                // the files which are not a tty.
+                Err(Error::SysError(SysErr::ENOTTY)) | Err(Error::SysError(SysErr::ENOSYS))
+                    if request != IoCtlCmd::TCGETS =>
+                {
+                    Unsupported(
+                        task,
+                        UnsupportedKind::Ioctl,
+                        &format!("{:#x}", request),
+                        &format!("fd {} {:?} arg {:x}", fd, file.FileOp.FopsType(), val),
+                        false,
+                    );
+                }
+                _ => (),
+            }
+            return res;
+        }
     }
 }
 
@@ -1025,7 +1068,7 @@ pub fn SysLseek(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 pub fn Lseek(task: &mut Task, fd: i32, offset: i64, whence: i32) -> Result<i64> {
     let file = task.GetFile(fd)?;
 
-    if whence < SeekWhence::SEEK_SET || whence > SeekWhence::SEEK_END {
+    if whence < SeekWhence::SEEK_SET || whence > SeekWhence::SEEK_HOLE {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
@@ -1125,7 +1168,21 @@ pub fn FSetOwner(task: &Task, fd: i32, file: &File, who: i32) -> Result<()> {
     return Ok(());
 }
 
-pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool) -> Result<()> {
+// LockOwner returns the lock uid and owner of a fcntl(2) lock. The POSIX locks
+// are owned by the fd table, while the OFD locks are owned by the open file,
+// and are shared by its dups and released with its last reference.
+pub fn LockOwner(task: &Task, file: &File, ofd: bool) -> (u64, OwnerInfo) {
+    if ofd {
+        // fcntl(2): the OFD lock holder is reported with l_pid -1.
+        return (file.UniqueId(), OwnerInfo::New(-1));
+    }
+
+    // The lock uid is that of the fdtble's UniqueId.
+    let pid = task.Thread().ThreadGroup().ID();
+    return (task.fdTbl.Id(), OwnerInfo::New(pid));
+}
+
+pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool, ofd: bool) -> Result<()> {
     let inode = file.Dirent.Inode();
     // In Linux the file system can choose to provide lock operations for an inode.
     // Normally pipe and socket types lack lock operations. We diverge and use a heavy
@@ -1136,16 +1193,19 @@ pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool) -> Resul
 
     let flock: Flock = task.CopyInObj(flockAddr)?;
 
+    // fcntl(2): the l_pid field must be set to zero for the OFD lock commands.
+    if ofd && flock.Pid != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
     let rng = file.ComputeLockRange(task, flock.Start, flock.Len, flock.Whence as _)?;
 
-    // The lock uid is that of the fdtble's UniqueId.
-    let lockUniqueID = task.fdTbl.Id();
+    let (lockUniqueID, owner) = LockOwner(task, file, ofd);
 
     // These locks don't block; execute the non-blocking operation using the inode's lock
     // context directly.
     let fflags = file.Flags();
 
-    let pid = task.Thread().ThreadGroup().ID();
     match flock.Type as u64 {
         LibcConst::F_RDLCK => {
             if !fflags.Read {
@@ -1153,14 +1213,7 @@ pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool) -> Resul
             }
 
             let lock = inode.lock().LockCtx.Posix.clone();
-            if !lock.LockRegion(
-                task,
-                lockUniqueID,
-                OwnerInfo::New(pid),
-                LockType::ReadLock,
-                &rng,
-                block,
-            )? {
+            if !lock.LockRegion(task, lockUniqueID, owner, LockType::ReadLock, &rng, block)? {
                 return Err(Error::SysError(SysErr::EAGAIN));
             }
 
@@ -1172,14 +1225,7 @@ pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool) -> Resul
             }
 
             let lock = inode.lock().LockCtx.Posix.clone();
-            if !lock.LockRegion(
-                task,
-                lockUniqueID,
-                OwnerInfo::New(pid),
-                LockType::WriteLock,
-                &rng,
-                block,
-            )? {
+            if !lock.LockRegion(task, lockUniqueID, owner, LockType::WriteLock, &rng, block)? {
                 return Err(Error::SysError(SysErr::EAGAIN));
             }
 
@@ -1195,9 +1241,13 @@ pub fn PosixLock(task: &Task, flockAddr: u64, file: &File, block: bool) -> Resul
     }
 }
 
-pub fn PosixTestLock(task: &Task, flockAddr: u64, file: &File) -> Result<()> {
+pub fn PosixTestLock(task: &Task, flockAddr: u64, file: &File, ofd: bool) -> Result<()> {
     let flock: Flock = task.CopyInObj(flockAddr)?;
 
+    if ofd && flock.Pid != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
     let typ = match flock.Type as i32 {
         F_RDLCK => LockType::ReadLock,
         F_WRLCK => LockType::WriteLock,
@@ -1206,8 +1256,7 @@ pub fn PosixTestLock(task: &Task, flockAddr: u64, file: &File) -> Result<()> {
 
     let r = file.ComputeLockRange(task, flock.Start, flock.Len, flock.Whence as _)?;
 
-    // The lock uid is that of the fdtble's UniqueId.
-    let lockUniqueID = task.fdTbl.Id();
+    let (lockUniqueID, _) = LockOwner(task, file, ofd);
     let inode = file.Dirent.Inode();
     let lock = inode.lock().LockCtx.Posix.clone();
     let newFlock = lock.TestRegion(task, lockUniqueID, typ, &r);
@@ -1261,7 +1310,7 @@ pub fn SysFcntl(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
                 return Err(Error::SysError(SysErr::EBADF));
             }
 
-            PosixLock(task, val, &file, false)?;
+            PosixLock(task, val, &file, false, false)?;
             return Ok(0);
         }
         Cmd::F_SETLKW => {
@@ -1269,7 +1318,7 @@ pub fn SysFcntl(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
                 return Err(Error::SysError(SysErr::EBADF));
             }
 
-            PosixLock(task, val, &file, true)?;
+            PosixLock(task, val, &file, true, false)?;
             return Ok(0);
         }
         Cmd::F_GETLK => {
@@ -1277,7 +1326,31 @@ pub fn SysFcntl(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
                 return Err(Error::SysError(SysErr::EBADF));
             }
 
-            PosixTestLock(task, val, &file)?;
+            PosixTestLock(task, val, &file, false)?;
+            return Ok(0);
+        }
+        Cmd::F_OFD_SETLK => {
+            if file.Flags().Path {
+                return Err(Error::SysError(SysErr::EBADF));
+            }
+
+            PosixLock(task, val, &file, false, true)?;
+            return Ok(0);
+        }
+        Cmd::F_OFD_SETLKW => {
+            if file.Flags().Path {
+                return Err(Error::SysError(SysErr::EBADF));
+            }
+
+            PosixLock(task, val, &file, true, true)?;
+            return Ok(0);
+        }
+        Cmd::F_OFD_GETLK => {
+            if file.Flags().Path {
+                return Err(Error::SysError(SysErr::EBADF));
+            }
+
+            PosixTestLock(task, val, &file, true)?;
             return Ok(0);
         }
         Cmd::F_GETOWN => {
@@ -1459,7 +1532,16 @@ pub fn SysFcntl(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
                 }
             }
         }
-        _ => return Err(Error::SysError(SysErr::EINVAL)),
+        _ => {
+            Unsupported(
+                task,
+                UnsupportedKind::Fcntl,
+                &format!("{}", cmd),
+                &format!("fd {} arg {:x}", fd, val),
+                false,
+            );
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
     }
 }
 
@@ -2045,8 +2127,7 @@ pub fn SysFtruncate(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     inode.Truncate(task, &dirent, len)?;
 
     // File length modified, generate notification.
-    file.Dirent
-        .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::InodeEvent);
+    file.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::InodeEvent);
 
     return Ok(0);
 }
@@ -2432,7 +2513,7 @@ pub fn SysRenameat(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 // Fallocate implements linux system call fallocate(2).
 pub fn SysFallocate(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     let fd = args.arg0 as i32;
-    let mode = args.arg1 as i64;
+    let mode = args.arg1 as i32;
     let offset = args.arg2 as i64;
     let len = args.arg3 as i64;
 
@@ -2442,9 +2523,41 @@ pub fn SysFallocate(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
-    if mode != 0 {
-        //t.Kernel().EmitUnimplementedEvent(t)
-        return Err(Error::SysError(SysErr::ENOTSUP));
+    if mode & !FallocFlags::FALLOC_FL_SUPPORTED_MASK != 0 {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    // Punch hole and zero range are mutually exclusive.
+    let punchOrZero = FallocFlags::FALLOC_FL_PUNCH_HOLE | FallocFlags::FALLOC_FL_ZERO_RANGE;
+    if mode & punchOrZero == punchOrZero {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    // Punch hole must have keep size set.
+    if mode & FallocFlags::FALLOC_FL_PUNCH_HOLE != 0 && mode & FallocFlags::FALLOC_FL_KEEP_SIZE == 0
+    {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    // Collapse range should only be used exclusively.
+    if mode & FallocFlags::FALLOC_FL_COLLAPSE_RANGE != 0
+        && mode & !FallocFlags::FALLOC_FL_COLLAPSE_RANGE != 0
+    {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    // Insert range should only be used exclusively.
+    if mode & FallocFlags::FALLOC_FL_INSERT_RANGE != 0
+        && mode & !FallocFlags::FALLOC_FL_INSERT_RANGE != 0
+    {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    // Unshare range should only be used with allocate mode.
+    if mode & FallocFlags::FALLOC_FL_UNSHARE_RANGE != 0
+        && mode & !(FallocFlags::FALLOC_FL_UNSHARE_RANGE | FallocFlags::FALLOC_FL_KEEP_SIZE) != 0
+    {
+        return Err(Error::SysError(SysErr::EINVAL));
     }
 
     if !file.Flags().Write || file.Flags().Path {
@@ -2470,21 +2583,25 @@ pub fn SysFallocate(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
         return Err(Error::SysError(SysErr::EFBIG));
     }
 
-    let rlimitSize = task
-        .Thread()
-        .ThreadGroup()
-        .Limits()
-        .Get(LimitType::FileSize)
-        .Cur;
-    if len as u64 > rlimitSize {
-        return Err(Error::ErrExceedsFileSizeLimit);
+    // The file size limit only applies when the file may grow.
+    let mayGrow =
+        mode & (FallocFlags::FALLOC_FL_KEEP_SIZE | FallocFlags::FALLOC_FL_COLLAPSE_RANGE) == 0;
+    if mayGrow {
+        let rlimitSize = task
+            .Thread()
+            .ThreadGroup()
+            .Limits()
+            .Get(LimitType::FileSize)
+            .Cur;
+        if size as u64 > rlimitSize {
+            return Err(Error::ErrExceedsFileSizeLimit);
+        }
     }
 
     let dirent = file.Dirent.clone();
-    inode.Allocate(task, &dirent, offset, len)?;
+    inode.Allocate(task, &dirent, mode, offset, len)?;
 
-    file.Dirent
-        .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::InodeEvent);
+    file.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::InodeEvent);
 
     Ok(0)
 }
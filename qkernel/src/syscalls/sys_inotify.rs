@@ -18,6 +18,7 @@ use super::super::qlib::kernel::fs::anon::*;
 use super::super::qlib::kernel::fs::dirent::*;
 use super::super::qlib::kernel::fs::file::*;
 use super::super::qlib::kernel::fs::flags::*;
+use super::super::qlib::kernel::fs::host::hostinotify::*;
 use super::super::qlib::kernel::fs::inotify::*;
 use super::super::qlib::linux_def::*;
 use super::super::syscalls::syscalls::*;
@@ -113,6 +114,8 @@ pub fn SysInotifyAddWatch(task: &mut Task, args: &SyscallArguments) -> Result<i6
             }
 
             wd = ino.AddWatch(d, mask as u32);
+            // watch the host backed file for the changes made by the host
+            HOST_INOTIFY.Watch(d);
             return Ok(());
         },
     )?;
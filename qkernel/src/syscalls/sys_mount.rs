@@ -0,0 +1,176 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use alloc::string::ToString;
+
+use super::super::fs::dirent::*;
+use super::super::fs::filesystems::*;
+use super::super::qlib::common::*;
+use super::super::qlib::kernel::fs::fuse::fs::FUSE_FS_NAME;
+use super::super::qlib::linux_def::*;
+use super::super::syscalls::syscalls::*;
+use super::super::task::*;
+use super::super::util::cstring::*;
+use super::sys_file::*;
+
+// Mount implements Linux syscall mount(2).
+pub fn SysMount(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let sourceAddr = args.arg0 as u64;
+    let targetAddr = args.arg1 as u64;
+    let typeAddr = args.arg2 as u64;
+    let mut flags = args.arg3 as u64;
+    let dataAddr = args.arg4 as u64;
+
+    let fsType = CString::ToStringWithLen(task, typeAddr, MemoryDef::PAGE_SIZE as usize)?;
+    let (sourcePath, _) = copyInPath(task, sourceAddr, true)?;
+    let (targetPath, _) = copyInPath(task, targetAddr, false)?;
+
+    let mut data = "".to_string();
+    if dataAddr != 0 {
+        // In Linux, a full page is always copied in regardless of null
+        // character placement, and the address is passed to each file system.
+        // Most file systems always treat this data as a string, though, and so
+        // do all of the ones we implement.
+        data = CString::ToStringWithLen(task, dataAddr, MemoryDef::PAGE_SIZE as usize)?;
+    }
+
+    // Ignore magic value that was required before Linux 2.4.
+    if flags & LibcConst::MS_MGC_MSK == LibcConst::MS_MGC_VAL {
+        flags &= !LibcConst::MS_MGC_MSK;
+    }
+
+    // Must have CAP_SYS_ADMIN in the mount namespace's associated user
+    // namespace.
+    let userns = task.mountNS.UserNamespace();
+    if !task
+        .Creds()
+        .HasCapabilityIn(Capability::CAP_SYS_ADMIN, &userns)
+    {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    let unsupportedOps = LibcConst::MS_REMOUNT
+        | LibcConst::MS_BIND
+        | LibcConst::MS_SHARED
+        | LibcConst::MS_PRIVATE
+        | LibcConst::MS_SLAVE
+        | LibcConst::MS_UNBINDABLE
+        | LibcConst::MS_MOVE;
+
+    // Silently allow MS_NOSUID, since we don't implement set-id bits
+    // anyway. MS_NODEV and MS_NODIRATIME are also allowed, fusermount passes
+    // MS_NODEV and the fuse mount has no device files to open.
+    let unsupportedFlags = LibcConst::MS_STRICTATIME;
+
+    // Linux just allows passing any flags to mount(2) - it won't fail when
+    // unknown or unsupported flags are passed. Since we don't implement
+    // everything, we fail explicitly on flags that are unimplemented.
+    if flags & (unsupportedOps | unsupportedFlags) != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    // "fuse.<subtype>" names the daemon, the file system is fuse. Only the
+    // fuse file system can be mounted from the sandbox, the others are set
+    // up by the runtime.
+    let fsName = match fsType.split_once('.') {
+        Some(("fuse", _)) => "fuse",
+        _ => &fsType,
+    };
+
+    if fsName != FUSE_FS_NAME {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    let rsys = match FindFilesystem(fsName) {
+        None => return Err(Error::SysError(SysErr::ENODEV)),
+        Some(rsys) => rsys,
+    };
+
+    if !rsys.lock().AllowUserMount() {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    let superFlags = MountSourceFlags {
+        ReadOnly: flags & LibcConst::MS_RDONLY != 0,
+        NoAtime: flags & LibcConst::MS_NOATIME != 0,
+        NoExec: flags & LibcConst::MS_NOEXEC != 0,
+        ..Default::default()
+    };
+
+    let rootInode = rsys.lock().Mount(task, &sourcePath, &superFlags, &data)?;
+
+    fileOpOn(
+        task,
+        ATType::AT_FDCWD,
+        &targetPath,
+        true,
+        &mut |_root: &Dirent, d: &Dirent, _remainingTraversals: u32| -> Result<()> {
+            return task.mountNS.Mount(d, &rootInode);
+        },
+    )?;
+
+    return Ok(0);
+}
+
+// Umount2 implements Linux syscall umount2(2).
+pub fn SysUmount2(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let addr = args.arg0 as u64;
+    let flags = args.arg1 as u64;
+
+    // Must have CAP_SYS_ADMIN in the mount namespace's associated user
+    // namespace.
+    //
+    // Currently, this is the only check for CAP_SYS_ADMIN. Linux also
+    // checks that the mount is owned by the mount namespace of the task.
+    let userns = task.mountNS.UserNamespace();
+    if !task
+        .Creds()
+        .HasCapabilityIn(Capability::CAP_SYS_ADMIN, &userns)
+    {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    // The only supported flags are MNT_DETACH and UMOUNT_NOFOLLOW. MNT_FORCE
+    // and MNT_EXPIRE, which only apply to some file systems, are unsupported.
+    if flags & !(LibcConst::MNT_DETACH | LibcConst::UMOUNT_NOFOLLOW) != 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let (path, _) = copyInPath(task, addr, false)?;
+
+    let resolve = flags & LibcConst::UMOUNT_NOFOLLOW == 0;
+    let detachOnly = flags & LibcConst::MNT_DETACH != 0;
+
+    fileOpOn(
+        task,
+        ATType::AT_FDCWD,
+        &path,
+        resolve,
+        &mut |root: &Dirent, d: &Dirent, _remainingTraversals: u32| -> Result<()> {
+            // Traversal can lead to mount points, so we must also
+            // check root == d.
+            if root.ID() == d.ID() || task.mountNS.Root().ID() == d.ID() {
+                return Err(Error::SysError(SysErr::EBUSY));
+            }
+
+            // only the fuse mounts made from the sandbox can be unmounted
+            if d.Inode().lock().MountSource.lock().FileSystemType != FUSE_FS_NAME {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+
+            return task.mountNS.Unmount(d, detachOnly);
+        },
+    )?;
+
+    return Ok(0);
+}
@@ -20,6 +20,7 @@ use crate::qlib::common::*;
 use crate::syscalls::syscalls::*;
 use crate::task::*;
 use crate::qlib::kernel::Kernel::HostSpace;
+use crate::qlib::kernel::memmgr::pma::PinnedPages;
 use crate::qlib::linux_def::SysErr;
 use crate::qlib::proxy::*;
 use super::super::util::cstring::*;
@@ -187,6 +188,7 @@ pub fn CudaMemcpy(task: &Task, dst: u64, src: u64, count: u64, kind: CudaMemcpyK
             // src is the virtual addr
             let mut prs = Vec::new();
             task.V2P(src, count, &mut prs, true, false)?;
+            let _pinned = PinnedPages::New(&prs);
 
             let parameters = ProxyParameters {
                 para1: dst,
@@ -208,6 +210,7 @@ pub fn CudaMemcpy(task: &Task, dst: u64, src: u64, count: u64, kind: CudaMemcpyK
             // dst is the virtual addr
             let mut prs = Vec::new();
             task.V2P(dst, count, &mut prs, true, false)?;
+            let _pinned = PinnedPages::New(&prs);
 
             let parameters = ProxyParameters {
                 para1: &prs[0] as * const _ as u64,
@@ -0,0 +1,170 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::fs::dirent::*;
+use super::super::qlib::common::*;
+use super::super::qlib::kernel::fs::host::quota::*;
+use super::super::qlib::linux::quota::*;
+use super::super::qlib::linux_def::*;
+use super::super::syscalls::syscalls::*;
+use super::super::task::*;
+use super::sys_file::*;
+
+// Quotactl implements the quotactl() syscall. The host-backed mounts have no
+// block device in the sandbox, so special can be any path on the mount.
+pub fn SysQuotactl(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let cmd = args.arg0 as u32;
+    let special = args.arg1 as u64;
+    let id = args.arg2 as u32;
+    let addr = args.arg3 as u64;
+
+    if special == 0 {
+        // Q_SYNC without a device syncs all the file systems
+        if cmd >> SUBCMDSHIFT == Q_SYNC {
+            return Ok(0);
+        }
+
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let (path, _) = copyInPath(task, special, false)?;
+    let mut quota = None;
+    fileOpOn(
+        task,
+        ATType::AT_FDCWD,
+        &path,
+        true,
+        &mut |_root: &Dirent, d: &Dirent, _remainingTraversals: u32| -> Result<()> {
+            quota = MountQuota(&d.Inode());
+            return Ok(());
+        },
+    )?;
+
+    return quotactl(task, quota, cmd, id, addr);
+}
+
+// QuotactlFd implements the quotactl_fd() syscall.
+pub fn SysQuotactlFd(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    let fd = args.arg0 as i32;
+    let cmd = args.arg1 as u32;
+    let id = args.arg2 as u32;
+    let addr = args.arg3 as u64;
+
+    let file = task.GetFile(fd)?;
+    let quota = MountQuota(&file.Dirent.Inode());
+    return quotactl(task, quota, cmd, id, addr);
+}
+
+// quotactl runs the command on the quota of a host-backed mount. The mount
+// quota is reported as the project quota of project 0, which all the files
+// of the mount belong to. The limits set by the operator can only be lowered.
+fn quotactl(task: &Task, quota: Option<HostQuota>, cmd: u32, id: u32, addr: u64) -> Result<i64> {
+    let subcmd = cmd >> SUBCMDSHIFT;
+    let typ = cmd & SUBCMDMASK;
+
+    if typ >= MAXQUOTAS {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    match subcmd {
+        Q_SYNC | Q_GETFMT | Q_GETINFO => (),
+        _ => {
+            if !task.Creds().HasCapability(Capability::CAP_SYS_ADMIN) {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+    }
+
+    let quota = match quota {
+        None => return Err(Error::SysError(SysErr::ENOSYS)),
+        Some(q) => q,
+    };
+
+    if typ != PRJQUOTA {
+        return Err(Error::SysError(SysErr::ESRCH));
+    }
+
+    match subcmd {
+        Q_SYNC => return Ok(0),
+        Q_QUOTAON => {
+            quota.SetEnabled(true)?;
+            return Ok(0);
+        }
+        Q_QUOTAOFF => {
+            quota.SetEnabled(false)?;
+            return Ok(0);
+        }
+        _ => (),
+    }
+
+    if !quota.Enabled() {
+        return Err(Error::SysError(SysErr::ESRCH));
+    }
+
+    match subcmd {
+        Q_GETFMT => {
+            task.CopyOutObj(&QFMT_VFS_V1, addr)?;
+        }
+        Q_GETINFO => {
+            task.CopyOutObj(&quota.Dqinfo(), addr)?;
+        }
+        Q_SETINFO => {
+            let dqinfo: IfDqinfo = task.CopyInObj(addr)?;
+            quota.SetDqinfo(&dqinfo)?;
+        }
+        Q_GETQUOTA => {
+            // the other projects have no files
+            let dqblk = if id == 0 {
+                quota.Dqblk()
+            } else {
+                IfDqblk {
+                    Valid: QIF_ALL,
+                    ..Default::default()
+                }
+            };
+            task.CopyOutObj(&dqblk, addr)?;
+        }
+        Q_GETNEXTQUOTA => {
+            if id != 0 {
+                return Err(Error::SysError(SysErr::ENOENT));
+            }
+
+            let dqblk = quota.Dqblk();
+            let next = IfNextDqblk {
+                BHardLimit: dqblk.BHardLimit,
+                BSoftLimit: dqblk.BSoftLimit,
+                CurSpace: dqblk.CurSpace,
+                IHardLimit: dqblk.IHardLimit,
+                ISoftLimit: dqblk.ISoftLimit,
+                CurInodes: dqblk.CurInodes,
+                BTime: dqblk.BTime,
+                ITime: dqblk.ITime,
+                Valid: dqblk.Valid,
+                Id: 0,
+            };
+            task.CopyOutObj(&next, addr)?;
+        }
+        Q_SETQUOTA => {
+            if id != 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            let dqblk: IfDqblk = task.CopyInObj(addr)?;
+            quota.SetDqblk(&dqblk)?;
+        }
+        _ => return Err(Error::SysError(SysErr::EINVAL)),
+    }
+
+    return Ok(0);
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::super::fs::fanotify::*;
 use super::super::fs::file::*;
 use super::super::fs::inotify::*;
 use super::super::kernel::time::*;
@@ -19,6 +20,7 @@ use super::super::kernel::timer::*;
 use super::super::kernel::waiter::*;
 //use super::super::kernel_def::*;
 use super::super::qlib::common::*;
+use super::super::qlib::linux::fanotify::*;
 use super::super::qlib::linux_def::*;
 use super::super::qlib::mem::block::*;
 use super::super::syscalls::syscalls::*;
@@ -52,6 +54,8 @@ pub fn Read(task: &Task, fd: i32, addr: u64, size: i64) -> Result<i64> {
         return Ok(0);
     }
 
+    FanotifyPermission(task, &file, FAN_ACCESS_PERM)?;
+
     let iov = IoVec::NewFromAddr(addr, size as usize);
 
     let mut iovs: [IoVec; 1] = [iov];
@@ -134,6 +138,8 @@ pub fn Pread64(task: &Task, fd: i32, addr: u64, size: i64, offset: i64) -> Resul
         return Ok(0);
     }
 
+    FanotifyPermission(task, &file, FAN_ACCESS_PERM)?;
+
     let iov = IoVec::NewFromAddr(addr, size as usize);
     let mut iovs = [iov];
     let iovs = task.AdjustIOVecPermission(&mut iovs, true, true)?;
@@ -191,6 +197,8 @@ pub fn Readv(task: &Task, fd: i32, addr: u64, iovcnt: i32) -> Result<i64> {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
+    FanotifyPermission(task, &file, FAN_ACCESS_PERM)?;
+
     let mut dsts = task.IovsFromAddr(addr, iovcnt as usize)?;
 
     return readv(task, &file, &mut dsts);
@@ -269,6 +277,8 @@ pub fn Preadv(task: &Task, fd: i32, addr: u64, iovcnt: i32, offset: i64) -> Resu
         return Ok(0);
     }
 
+    FanotifyPermission(task, &file, FAN_ACCESS_PERM)?;
+
     let mut dsts = task.IovsFromAddr(addr, iovcnt as usize)?;
     let iovs = task.AdjustIOVecPermission(&mut dsts, true, true)?;
     let iovs = IOVecs::New(iovs);
@@ -312,8 +322,7 @@ fn RepReadv(task: &Task, f: &File, dsts: &mut [IoVec]) -> Result<i64> {
 
     if count > 0 {
         // Queue notification if we read anything.
-        f.Dirent
-            .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
+        f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
     }
     return Ok(count);
 }
@@ -336,8 +345,7 @@ fn readv(task: &Task, f: &File, dsts: &mut [IoVec]) -> Result<i64> {
         }
         Ok(n) => {
             // Queue notification if we read anything.
-            f.Dirent
-                .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
+            f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
             return Ok(n);
         }
     };
@@ -375,8 +383,7 @@ fn readv(task: &Task, f: &File, dsts: &mut [IoVec]) -> Result<i64> {
                 Err(e) => {
                     if count > 0 {
                         // Queue notification if we read anything.
-                        f.Dirent
-                            .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
+                        f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
                         return Ok(count);
                     }
                     return Err(e);
@@ -387,10 +394,12 @@ fn readv(task: &Task, f: &File, dsts: &mut [IoVec]) -> Result<i64> {
                     }
 
                     count += n;
-                    if count == len as i64 || f.Flags().NonBlocking {
+                    if count == len as i64
+                        || f.Flags().NonBlocking
+                        || f.FileOp.FopsType().IsMessage()
+                    {
                         // Queue notification if we read anything.
-                        f.Dirent
-                            .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
+                        f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
                         return Ok(count);
                     }
 
@@ -425,8 +434,7 @@ fn preadv(task: &Task, f: &File, dsts: &mut [IoVec], offset: i64) -> Result<i64>
         Ok(n) => {
             if n > 0 {
                 // Queue notification if we read anything.
-                f.Dirent
-                    .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent)
+                f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent)
             }
             return Ok(n);
         }
@@ -446,8 +454,7 @@ fn preadv(task: &Task, f: &File, dsts: &mut [IoVec], offset: i64) -> Result<i64>
             Ok(n) => {
                 if n > 0 {
                     // Queue notification if we read anything.
-                    f.Dirent
-                        .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent)
+                    f.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent)
                 }
                 return Ok(n);
             }
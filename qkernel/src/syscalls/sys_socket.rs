@@ -15,6 +15,7 @@
 use alloc::vec::Vec;
 
 use super::super::qlib::common::*;
+use super::super::qlib::control_msg::{KernelLogLevel, KernelLogType, UnsupportedKind};
 use super::super::qlib::linux_def::*;
 use super::super::socket::socket::*;
 use super::super::task::*;
@@ -23,7 +24,10 @@ use super::super::task::*;
 use super::super::fs::file::*;
 use super::super::fs::flags::*;
 use super::super::kernel::fd_table::*;
+use super::super::kernel::kernel::GetKernel;
 use super::super::kernel::time::*;
+use super::super::kernel::unsupported::Unsupported;
+use super::super::qlib::linux::netfilter::{IsNetfilterSockOpt, XT_MAX_OPT_LEN};
 use super::super::qlib::linux::time::*;
 use super::super::syscalls::syscalls::*;
 //use super::super::qlib::linux::socket::*;
@@ -160,7 +164,33 @@ pub fn SysConnect(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 
     let addrstr = CaptureAddress(task, addr, addrlen)?;
 
-    sock.Connect(task, &addrstr, blocking)?;
+    match sock.Connect(task, &addrstr, blocking) {
+        Err(Error::SysError(SysErr::EINPROGRESS)) => {
+            GetKernel().sockets.AccountConnect(&file, true);
+            return Err(Error::SysError(SysErr::EINPROGRESS));
+        }
+        Err(e) => {
+            GetKernel().sockets.AccountConnect(&file, false);
+            match e {
+                Error::SysError(SysErr::ECONNREFUSED)
+                | Error::SysError(SysErr::ENETUNREACH)
+                | Error::SysError(SysErr::EHOSTUNREACH)
+                | Error::SysError(SysErr::ETIMEDOUT) => {
+                    let thread = task.Thread();
+                    GetKernel().syslog.Push(
+                        KernelLogType::Network,
+                        KernelLogLevel::Warning,
+                        &thread.ContainerID(),
+                        thread.ThreadGroup().ID(),
+                        format!("connect fd {} fail with error {:?}", fd, e),
+                    );
+                }
+                _ => (),
+            }
+            return Err(e);
+        }
+        Ok(_) => GetKernel().sockets.AccountConnect(&file, true),
+    }
 
     return Ok(0);
 }
@@ -215,6 +245,8 @@ pub fn Accept4(task: &Task, fd: i32, addr: u64, addrlen: u64, flags: i32) -> Res
         Ok(nfd) => nfd,
     };
 
+    GetKernel().sockets.AccountAccept(&file);
+
     if peerRequested {
         task.CopyOutSlice(addrstr, addr, lenCopy as usize)?;
         //*task.GetTypeMut::<i32>(addrlen)? = len as i32;
@@ -318,8 +350,35 @@ pub fn SysGetSockOpt(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
         0
     };
 
+    // the netfilter options read their request from the option buffer and
+    // return the rulesets, they aren't bounded by MAX_OPT_LEN
+    if IsNetfilterSockOpt(level, name) {
+        if optlen as usize > XT_MAX_OPT_LEN {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut optVal: Vec<u8> = task.CopyInVec(optValAddr, optlen as usize)?;
+        let len = sock.GetSockOpt(task, level, name, &mut optVal)? as usize;
+        task.CopyOutSlice(&optVal[..len], optValAddr, len)?;
+        task.CopyOutObj(&(len as i32), optLenAddr)?;
+        return Ok(0);
+    }
+
+    let optlen = core::cmp::min(optlen, MAX_OPT_LEN as i32);
     let mut optVal: [u8; MAX_OPT_LEN as usize] = [0; MAX_OPT_LEN as usize];
-    let res = sock.GetSockOpt(task, level, name, &mut optVal[..optlen as usize])?;
+    let res = match sock.GetSockOpt(task, level, name, &mut optVal[..optlen as usize]) {
+        Err(Error::SysError(SysErr::ENOPROTOOPT)) => {
+            Unsupported(
+                task,
+                UnsupportedKind::SockOpt,
+                &format!("getsockopt level {} name {}", level, name),
+                &format!("fd {} optlen {}", fd, optlen),
+                false,
+            );
+            return Err(Error::SysError(SysErr::ENOPROTOOPT));
+        }
+        res => res?,
+    };
 
     if res < 0 {
         panic!("GetSockOpt: get negative optlen")
@@ -344,14 +403,33 @@ pub fn SysSetSockOpt(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 
     let sock = file.FileOp.clone();
 
+    // the ruleset of the netfilter options is passed in one setsockopt
+    let maxLen = if IsNetfilterSockOpt(level, name) {
+        XT_MAX_OPT_LEN as i32
+    } else {
+        MAX_OPT_LEN as i32
+    };
+
     // Linux allows optlen = 0, which is equivalent to optval = 0,
     // see `do_ip_setsockopt` in linux/source/net/ipv4/ip_sockglue.c
-    if optLen < 0 || optLen > MAX_OPT_LEN as i32 {
+    if optLen < 0 || optLen > maxLen {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
     let optVal = task.CopyInVec(optValAddr, optLen as usize)?;
-    let res = sock.SetSockOpt(task, level, name, &optVal[..optLen as usize])?;
+    let res = match sock.SetSockOpt(task, level, name, &optVal[..optLen as usize]) {
+        Err(Error::SysError(SysErr::ENOPROTOOPT)) => {
+            Unsupported(
+                task,
+                UnsupportedKind::SockOpt,
+                &format!("setsockopt level {} name {}", level, name),
+                &format!("fd {} optlen {}", fd, optLen),
+                false,
+            );
+            return Err(Error::SysError(SysErr::ENOPROTOOPT));
+        }
+        res => res?,
+    };
 
     return Ok(res);
 }
@@ -611,6 +689,9 @@ pub fn SysRecvMsg(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     }
 
     let res = recvSingleMsg(task, &sock, msgPtr, flags, deadline)?;
+    if flags & MsgType::MSG_PEEK == 0 {
+        GetKernel().sockets.AccountRecv(&file, res);
+    }
     return Ok(res);
 }
 
@@ -625,7 +706,12 @@ pub fn SysRecvMMsg(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 
     let sock = file.FileOp.clone();
 
-    if flags & !(MsgType::BASE_RECV_FLAGS | MsgType::MSG_CMSG_CLOEXEC | MsgType::MSG_ERRQUEUE) != 0
+    if flags
+        & !(MsgType::BASE_RECV_FLAGS
+            | MsgType::MSG_PEEK
+            | MsgType::MSG_CMSG_CLOEXEC
+            | MsgType::MSG_ERRQUEUE)
+        != 0
     {
         return Err(Error::SysError(SysErr::EINVAL));
     }
@@ -686,6 +772,9 @@ pub fn SysRecvMMsg(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
             break;
         }
 
+        if flags & MsgType::MSG_PEEK == 0 {
+            GetKernel().sockets.AccountRecv(&file, res);
+        }
         msgs[i].msgLen = res as u32;
         count += 1;
     }
@@ -777,6 +866,9 @@ pub fn SysRecvFrom(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
 
     let (bytes, _, sender, _) =
         sock.RecvMsg(task, &mut iovs, flags, deadline, nameLenPtr != 0, 0)?;
+    if flags & MsgType::MSG_PEEK == 0 {
+        GetKernel().sockets.AccountRecv(&file, bytes);
+    }
 
     if nameLenPtr != 0 && sender.is_some() {
         let (sender, senderLen) = sender.unwrap();
@@ -831,6 +923,7 @@ pub fn SysSendMsg(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     }
 
     let res = sendSingleMsg(task, &sock, msgPtr, flags, deadline)?;
+    GetKernel().sockets.AccountSend(&file, res);
     return Ok(res);
 }
 
@@ -891,6 +984,7 @@ pub fn SysSendMMsg(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
             break;
         }
 
+        GetKernel().sockets.AccountSend(&file, res);
         msgs[i].msgLen = res as u32;
         count += 1;
     }
@@ -967,5 +1061,6 @@ pub fn SysSendTo(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     }
 
     let res = sock.SendMsg(task, &iovs, flags, &mut pMsg, deadline)?;
+    GetKernel().sockets.AccountSend(&file, res);
     return Ok(res);
 }
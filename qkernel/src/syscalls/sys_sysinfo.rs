@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::super::kernel::kernel::GetKernel;
 use super::super::qlib::common::*;
 use super::super::qlib::linux_def::*;
 use super::super::qlib::qmsg::qcall::StatmInfo;
@@ -35,7 +36,16 @@ pub fn SysInfo(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     info!("pass to here, rss, {}", statm.rss);
 
     let totalUsage = statm.rss;
-    let totalSize = TotalMemory(0, totalUsage);
+    let mut totalSize = TotalMemory(0, totalUsage);
+    let limit = GetKernel().MemoryLimit();
+    if limit > 0 {
+        // the memory limit set by the container runtime overrides the default size
+        totalSize = if limit > totalUsage {
+            limit
+        } else {
+            totalUsage
+        };
+    }
 
     //let sysInfo: &mut LibcSysinfo = task.GetTypeMut(addr)?;
     info.procs = task.Thread().PIDNamespace().Tasks().len() as u16;
@@ -19,6 +19,8 @@ use super::super::syscalls::syscalls::*;
 use super::super::task::*;
 
 pub const SYSLOG_ACTION_READ_ALL: i32 = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: i32 = 4;
+pub const SYSLOG_ACTION_CLEAR: i32 = 5;
 pub const SYSLOG_ACTION_SIZE_BUFFER: i32 = 10;
 
 // logBufLen is the default syslog buffer size on Linux.
@@ -26,15 +28,26 @@ const LOG_BUF_LEN: usize = 1 << 17; //128 KB
 
 // Syslog implements part of Linux syscall syslog.
 //
-// Only the unpriviledged commands are implemented, allowing applications to
-// read a dummy dmesg.
+// The unpriviledged commands read the qkernel log of the container, clearing
+// it needs CAP_SYSLOG.
 pub fn SysSysLog(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     let cmd = args.arg0 as i32;
     let buf = args.arg1 as u64;
     let size = args.arg2 as i32;
 
     match cmd {
-        SYSLOG_ACTION_READ_ALL => {
+        SYSLOG_ACTION_READ_CLEAR | SYSLOG_ACTION_CLEAR => {
+            if !task.Creds().HasCapability(Capability::CAP_SYSLOG) {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+        _ => (),
+    }
+
+    // a container only reads and clears its own log
+    let cid = task.Thread().ContainerID();
+    match cmd {
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
             if size < 0 {
                 return Err(Error::SysError(SysErr::EINVAL));
             }
@@ -44,14 +57,23 @@ pub fn SysSysLog(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
                 size = LOG_BUF_LEN;
             }
 
-            let log = GetKernel().Syslog().Log();
+            let log = GetKernel().Syslog().Log(&cid);
             if size > log.len() {
                 size = log.len();
             }
 
-            task.CopyOutSlice(&log[0..size], buf, size)?;
+            // the newest entries are returned if the buffer is too small
+            let log = &log[log.len() - size..];
+            task.CopyOutSlice(log, buf, size)?;
+            if cmd == SYSLOG_ACTION_READ_CLEAR {
+                GetKernel().Syslog().Clear(&cid);
+            }
             return Ok(size as _);
         }
+        SYSLOG_ACTION_CLEAR => {
+            GetKernel().Syslog().Clear(&cid);
+            return Ok(0);
+        }
         SYSLOG_ACTION_SIZE_BUFFER => return Ok(LOG_BUF_LEN as _),
         _ => {
             return Err(Error::SysError(SysErr::ENOSYS));
@@ -25,6 +25,7 @@ pub use xmas_elf::{P32, P64};
 
 use super::super::asm::*;
 use super::super::kernel::cpuset::*;
+use super::super::kernel::strace::*;
 use super::super::loader::loader::*;
 use super::super::memmgr::mm::*;
 use super::super::qlib::common::*;
@@ -359,6 +360,7 @@ pub fn Execvat(
     };
 
     //need to clean object on stack before enter_user as the stack will be destroyed
+    FlushSyscallTrace(task, &Ok(0));
     task.AccountTaskEnter(SchedState::RunningApp);
 
     EnterUser(entry, usersp, kernelsp);
@@ -398,8 +400,11 @@ pub fn SysExitThreadGroup(task: &mut Task, args: &SyscallArguments) -> Result<i6
 
     let exitStatus = ExitStatus::New(exitcode as i32, 0);
 
+    // write the trace line before the container can be seen as exited
+    let ret = Err(Error::SysCallRetCtrl(TaskRunState::RunExit));
+    FlushSyscallTrace(task, &ret);
     task.Thread().PrepareGroupExit(exitStatus);
-    return Err(Error::SysCallRetCtrl(TaskRunState::RunExit));
+    return ret;
 }
 
 // Clone implements linux syscall clone(2).
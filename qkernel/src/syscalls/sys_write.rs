@@ -265,8 +265,7 @@ fn RepWritev(task: &Task, f: &File, srcs: &[IoVec]) -> Result<i64> {
     }
 
     if count > 0 {
-        f.Dirent
-            .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+        f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
     }
     return Ok(count);
 }
@@ -290,8 +289,7 @@ pub fn writev(task: &Task, f: &File, srcs: &[IoVec]) -> Result<i64> {
             }
             Ok(n) => {
                 if n > 0 {
-                    f.Dirent
-                        .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+                    f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
                 }
                 return Ok(n);
             }
@@ -370,8 +368,7 @@ pub fn writev(task: &Task, f: &File, srcs: &[IoVec]) -> Result<i64> {
     }
 
     if count > 0 {
-        f.Dirent
-            .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+        f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
     }
     return Ok(count);
 }
@@ -404,8 +401,7 @@ fn RepPwritev(task: &Task, f: &File, srcs: &[IoVec], offset: i64) -> Result<i64>
     }
 
     if count > 0 {
-        f.Dirent
-            .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+        f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
     }
 
     return Ok(count);
@@ -428,8 +424,7 @@ fn pwritev(task: &Task, f: &File, srcs: &[IoVec], offset: i64) -> Result<i64> {
         }
         Ok(n) => {
             if n > 0 {
-                f.Dirent
-                    .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+                f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
             }
             return Ok(n);
         }
@@ -448,8 +443,7 @@ fn pwritev(task: &Task, f: &File, srcs: &[IoVec], offset: i64) -> Result<i64> {
             }
             Ok(n) => {
                 if n > 0 {
-                    f.Dirent
-                        .InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
+                    f.InotifyEvent(InotifyEvent::IN_MODIFY, 0, EventType::PathEvent)
                 }
                 return Ok(n);
             }
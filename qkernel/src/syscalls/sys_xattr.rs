@@ -99,16 +99,13 @@ pub fn GetXAttr(
     CheckXattrPermissons(
         task,
         &inode,
+        &name,
         &PermMask {
             read: true,
             ..Default::default()
         },
     )?;
 
-    if !HasPrefix(&name, Xattr::XATTR_USER_PREFIX) {
-        return Err(Error::SysError(SysErr::EOPNOTSUPP));
-    }
-
     // If getxattr(2) is called with size 0, the size of the value will be
     // returned successfully even if it is nonzero. In that case, we need to
     // retrieve the entire attribute value so we can return the correct size.
@@ -218,6 +215,7 @@ pub fn SetXAttr(
     CheckXattrPermissons(
         task,
         &inode,
+        &name,
         &PermMask {
             write: true,
             ..Default::default()
@@ -230,10 +228,6 @@ pub fn SetXAttr(
 
     let buf = task.CopyInVec(valueAddr, size)?;
 
-    if !HasPrefix(&name, Xattr::XATTR_USER_PREFIX) {
-        return Err(Error::SysError(SysErr::EOPNOTSUPP));
-    }
-
     inode.Setxattr(task, d, &name, &buf, flags)?;
     d.InotifyEvent(InotifyEvent::IN_ATTRIB, 0, EventType::InodeEvent);
     return Ok(());
@@ -256,17 +250,47 @@ pub fn CopyInXattrName(task: &Task, nameAddr: u64) -> Result<String> {
     return Ok(name);
 }
 
-// Restrict xattrs to regular files and directories.
-//
-// In Linux, this restriction technically only applies to
-// xattrs in the "user.*" namespace. Make file type checks specific to the
-// namespace once we allow other xattr prefixes.
+// Restrict xattrs in the "user.*" namespace to regular files and directories.
 pub fn XattrFileTypeOk(i: &Inode) -> bool {
     return i.StableAttr().IsDir() || i.StableAttr().IsRegular();
 }
 
-pub fn CheckXattrPermissons(task: &Task, i: &Inode, perms: &PermMask) -> Result<()> {
-    // Restrict xattrs to regular files and directories.
+// CheckXattrPermissons implements the namespace rules of Linux
+// xattr_permission(). "trusted.*" is reserved to CAP_SYS_ADMIN, writing
+// "security.*" needs the capability guarding it and "user.*" follows the
+// file permissions.
+pub fn CheckXattrPermissons(task: &Task, i: &Inode, name: &str, perms: &PermMask) -> Result<()> {
+    if HasPrefix(name, Xattr::XATTR_TRUSTED_PREFIX) {
+        if !task.Creds().HasCapability(Capability::CAP_SYS_ADMIN) {
+            if perms.write {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+            return Err(Error::SysError(SysErr::ENODATA));
+        }
+
+        return Ok(());
+    }
+
+    if HasPrefix(name, Xattr::XATTR_SECURITY_PREFIX) {
+        if perms.write {
+            let cap = if name == Xattr::XATTR_NAME_CAPS {
+                Capability::CAP_SETFCAP
+            } else {
+                Capability::CAP_SYS_ADMIN
+            };
+
+            if !task.Creds().HasCapability(cap) {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+
+        return Ok(());
+    }
+
+    if !HasPrefix(name, Xattr::XATTR_USER_PREFIX) {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
     if !XattrFileTypeOk(i) {
         if perms.write {
             return Err(Error::SysError(SysErr::EPERM));
@@ -277,6 +301,19 @@ pub fn CheckXattrPermissons(task: &Task, i: &Inode, perms: &PermMask) -> Result<
     return i.CheckPermission(task, &perms);
 }
 
+// XattrListable returns whether the name is shown to the task by listxattr(2).
+pub fn XattrListable(task: &Task, i: &Inode, name: &str) -> bool {
+    if HasPrefix(name, Xattr::XATTR_USER_PREFIX) {
+        return XattrFileTypeOk(i);
+    }
+
+    if HasPrefix(name, Xattr::XATTR_TRUSTED_PREFIX) {
+        return task.Creds().HasCapability(Capability::CAP_SYS_ADMIN);
+    }
+
+    return HasPrefix(name, Xattr::XATTR_SECURITY_PREFIX);
+}
+
 // ListXattr implements linux syscall listxattr(2).
 pub fn SysListXattr(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     return ListXattrFromPath(task, args, true);
@@ -335,9 +372,6 @@ pub fn ListXattrFromPath(
 
 pub fn ListXAttr(task: &Task, d: &Dirent, addr: u64, size: usize) -> Result<i64> {
     let inode = d.Inode();
-    if !XattrFileTypeOk(&inode) {
-        return Ok(0);
-    }
 
     // If listxattr(2) is called with size 0, the buffer size needed to contain
     // the xattr list will be returned successfully even if it is nonzero. In
@@ -348,12 +382,15 @@ pub fn ListXAttr(task: &Task, d: &Dirent, addr: u64, size: usize) -> Result<i64>
         requestdatasize = Xattr::XATTR_SIZE_MAX;
     }
 
-    let xattrs = inode.Listxattr(requestdatasize)?;
+    let xattrs = match inode.Listxattr(requestdatasize) {
+        Err(Error::SysError(SysErr::EOPNOTSUPP)) => Vec::new(),
+        Err(e) => return Err(e),
+        Ok(xattrs) => xattrs,
+    };
 
     let mut listSize = 0;
     for name in &xattrs {
-        // todo: support namespaces other than "user".
-        if HasPrefix(&name, Xattr::XATTR_USER_PREFIX) {
+        if XattrListable(task, &inode, name) {
             listSize += name.len() + 1;
         }
     }
@@ -373,8 +410,7 @@ pub fn ListXAttr(task: &Task, d: &Dirent, addr: u64, size: usize) -> Result<i64>
 
     let mut buf = Vec::new();
     for name in xattrs {
-        // todo: support namespaces other than "user".
-        if HasPrefix(&name, Xattr::XATTR_USER_PREFIX) {
+        if XattrListable(task, &inode, &name) {
             buf.append(&mut name.as_bytes().to_vec());
             buf.push(0);
         }
@@ -446,16 +482,13 @@ pub fn RemoveAttr(task: &Task, d: &Dirent, nameAddr: u64) -> Result<()> {
     CheckXattrPermissons(
         task,
         &inode,
+        &name,
         &PermMask {
             write: true,
             ..Default::default()
         },
     )?;
 
-    if !HasPrefix(&name, Xattr::XATTR_USER_PREFIX) {
-        return Err(Error::SysError(SysErr::EOPNOTSUPP));
-    }
-
     inode.Removexattr(task, d, &name)?;
     d.InotifyEvent(InotifyEvent::IN_ATTRIB, 0, EventType::InodeEvent);
     return Ok(());
@@ -17,6 +17,7 @@ use super::super::syscalls::sys_capability::*;
 use super::super::syscalls::sys_chmod::*;
 use super::super::syscalls::sys_epoll::*;
 use super::super::syscalls::sys_eventfd::*;
+use super::super::syscalls::sys_fanotify::*;
 use super::super::syscalls::sys_file::*;
 use super::super::syscalls::sys_futex::*;
 use super::super::syscalls::sys_getdents::*;
@@ -27,11 +28,13 @@ use super::super::syscalls::sys_memfd::*;
 use super::super::syscalls::sys_mempolicy::*;
 use super::super::syscalls::sys_mmap::*;
 use super::super::syscalls::sys_mmap_socket::*;
+use super::super::syscalls::sys_mount::*;
 use super::super::syscalls::sys_msgqueue::*;
 use super::super::syscalls::sys_pipe::*;
 use super::super::syscalls::sys_poll::*;
 use super::super::syscalls::sys_prctl::*;
 use super::super::syscalls::sys_proxy::*;
+use super::super::syscalls::sys_quota::*;
 use super::super::syscalls::sys_random::*;
 use super::super::syscalls::sys_read::*;
 use super::super::syscalls::sys_rlimit::*;
@@ -56,6 +59,10 @@ use super::super::syscalls::sys_write::*;
 use super::super::syscalls::sys_xattr::*;
 
 use super::super::qlib::common::*;
+use super::super::qlib::control_msg::UnsupportedKind;
+use super::super::qlib::kernel::kernel::strace::FlushSyscallTrace;
+use super::super::qlib::kernel::kernel::strace::STRACE;
+use super::super::qlib::kernel::kernel::unsupported::Unsupported;
 use super::super::qlib::linux_def::*;
 use super::super::qlib::SysCallID;
 use super::super::task::*;
@@ -81,7 +88,19 @@ pub fn SysCall(task: &mut Task, nr: u64, args: &SyscallArguments) -> TaskRunStat
             .unwrap(),
     };
 
-    match func(task, args) {
+    // the trace is kept in the task so that the syscalls which don't return,
+    // e.g. a successful execve, can write it before the task leaves
+    task.syscallTrace = STRACE.Enter(
+        task,
+        nr,
+        &[
+            args.arg0, args.arg1, args.arg2, args.arg3, args.arg4, args.arg5,
+        ],
+    );
+    let ret = func(task, args);
+    FlushSyscallTrace(task, &ret);
+
+    match ret {
         Err(Error::SysCallRetCtrlWithRet(state, ret)) => {
             task.SetReturn(ret);
             return state;
@@ -300,8 +319,8 @@ pub const SYS_CALL_TABLE: &'static [SyscallFn] = &[
     SysSync,                // 162 sys_sync,
     SysCapErr,              // 163 sys_acct,
     SysCapErr,              // 164 sys_settimeofday,
-    NotImplementSyscall,    // 165 sys_mount,
-    NotImplementSyscall,    // 166 sys_umount2,
+    SysMount,               // 165 sys_mount,
+    SysUmount2,             // 166 sys_umount2,
     SysCapErr,              // 167 sys_swapon,
     SysCapErr,              // 168 sys_swapoff,
     SysCapErr,              // 169 sys_reboot,
@@ -314,7 +333,7 @@ pub const SYS_CALL_TABLE: &'static [SyscallFn] = &[
     SysCapErr,              // 176 sys_delete_module,
     SysNoSys,               // 177 sys_get_kernel_syms, Not supported in Linux > 2.6
     SysNoSys,               // 178 sys_query_module,    Not supported in Linux > 2.6
-    SysCapErr,              // 179 sys_quotactl,
+    SysQuotactl,            // 179 sys_quotactl,
     SysNoSys,               // 180 sys_nfsservctl,      Removed after Linux 3.1
     SysNoSys,               // 181 sys_getpmsg,         Not implemented in Linux.
     SysNoSys,               // 182 sys_putpmsg,         Not implemented in Linux.
@@ -435,8 +454,8 @@ pub const SYS_CALL_TABLE: &'static [SyscallFn] = &[
     SysRtTgsigqueueinfo,    // 297 sys_rt_tgsigqueueinfo,
     SysNoDev,               // 298 sys_perf_event_open,     No support for perf counters
     SysRecvMMsg,            // 299 sys_recvmmsg,
-    SysNoSys,               //	300 sys_fanotify_init,       Needs CONFIG_FANOTIFY
-    SysNoSys,               //	309 sys_fanotify_mark,       Needs CONFIG_FANOTIFY
+    SysFanotifyInit,        //	300 sys_fanotify_init,
+    SysFanotifyMark,        //	301 sys_fanotify_mark,
     SysPrlimit64,           //	308 sys_prlimit64,
     SysOpNotSupport,        //	307 sys_name_to_handle_at,
     SysOpNotSupport,        //	306 sys_open_by_handle_at,
@@ -584,7 +603,7 @@ pub const SYS_CALL_TABLE: &'static [SyscallFn] = &[
     NotImplementSyscall, //	440 sys_process_madvise
     SysPwait2,           //	441 sys_epoll_pwait2
     NotImplementSyscall, //	442 sys_mouLoad(nt_setattr
-    NotImplementSyscall, //	443 sys_quotactl_fd
+    SysQuotactlFd,       //	443 sys_quotactl_fd
     NotImplementSyscall, //	444 sys_landlock_create_ruleset
     NotImplementSyscall, //	445 sys_landlock_add_rule
     NotImplementSyscall, //	446 sys_landlock_restrict_self
@@ -595,18 +614,39 @@ pub const SYS_CALL_TABLE: &'static [SyscallFn] = &[
     NotExisting,         // 451 unknow syscall
 ];
 
-pub fn NotImplementSyscall(_task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+// UnsupportedSyscall records the syscall of the task which falls through to
+// a stub, fatal is set for the stubs which return ENOSYS or are not implemented.
+pub fn UnsupportedSyscall(task: &Task, args: &SyscallArguments, fatal: bool) {
+    let nr = task.GetPtRegs().orig_rax;
+    let name = if nr < SysCallID::UnknowSyscall as u64 {
+        let callId: SysCallID = unsafe { core::mem::transmute(nr as u64) };
+        format!("{:?}", callId)
+    } else {
+        format!("syscall_{}", nr)
+    };
+
+    let args = format!(
+        "{:x}, {:x}, {:x}, {:x}, {:x}, {:x}",
+        args.arg0, args.arg1, args.arg2, args.arg3, args.arg4, args.arg5
+    );
+    Unsupported(task, UnsupportedKind::Syscall, &name, &args, fatal);
+}
+
+pub fn NotImplementSyscall(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     error!("NotImplementSyscall syscall {:x?}", args);
+    UnsupportedSyscall(task, args, true);
     return Err(Error::SysCallNotImplement);
 }
 
-pub fn NotExisting(_task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+pub fn NotExisting(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     error!("NotExisting syscall {:x?}", args);
+    UnsupportedSyscall(task, args, false);
     return Err(Error::SysError(SysErr::ENODATA));
 }
 
-pub fn SysNoSupport(_task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+pub fn SysNoSupport(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     error!("SysNoSupport syscall {:x?}", args);
+    UnsupportedSyscall(task, args, false);
     return Err(Error::SysError(SysErr::ENODATA));
     //return Err(Error::SysError(SysErr::ENOTSUP));
 }
@@ -624,13 +664,15 @@ pub fn SysNoPermission(_task: &mut Task, _args: &SyscallArguments) -> Result<i64
     return Err(Error::SysError(SysErr::EPERM));
 }
 
-pub fn SysObsolete(_task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+pub fn SysObsolete(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
     error!("SysObsolete syscall {:x?}", args);
+    UnsupportedSyscall(task, args, true);
     return Err(Error::SysError(SysErr::ENOSYS));
     //return Err(Error::SysError(SysErr::ENOTSUP));
 }
 
-pub fn SysNoSys(_task: &mut Task, _args: &SyscallArguments) -> Result<i64> {
+pub fn SysNoSys(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    UnsupportedSyscall(task, args, true);
     return Err(Error::SysError(SysErr::ENOSYS));
 }
 
@@ -638,7 +680,8 @@ pub fn SysNoDev(_task: &mut Task, _args: &SyscallArguments) -> Result<i64> {
     return Err(Error::SysError(SysErr::ENODEV));
 }
 
-pub fn SysOpNotSupport(_task: &mut Task, _args: &SyscallArguments) -> Result<i64> {
+pub fn SysOpNotSupport(task: &mut Task, args: &SyscallArguments) -> Result<i64> {
+    UnsupportedSyscall(task, args, false);
     return Err(Error::SysError(SysErr::EOPNOTSUPP));
 }
 
@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::string::String;
+use alloc::string::ToString;
+use core::convert::TryFrom;
+use core::fmt;
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Config {
     pub DebugLevel: DebugLevel,
@@ -44,6 +49,34 @@ pub struct Config {
     pub Realtime: bool,
     pub EnableIOBuf: bool,
     pub EnableTsot: bool,
+    #[serde(default)]
+    pub EnableNetstack: bool,
+    #[serde(default)]
+    pub EnableReclaim: bool,
+    #[serde(default)]
+    pub ReclaimInterval: u64,
+    #[serde(default)]
+    pub ReclaimCompression: ReclaimCompression,
+    #[serde(default)]
+    pub ReclaimEncrypt: bool,
+    // ReclaimSwapPath is the host directory of the reclaim swap stores.
+    #[serde(default = "DefaultReclaimSwapPath")]
+    pub ReclaimSwapPath: ConfigPath,
+    #[serde(default)]
+    pub FreePageReporting: bool,
+    #[serde(default)]
+    pub RootfsOverlay: RootfsOverlay,
+    #[serde(default)]
+    pub UnsupportedStrict: bool,
+    // HostInotify watches the host backed files with guest inotify watches or
+    // cached listings for the changes made outside of the sandbox.
+    #[serde(default)]
+    pub HostInotify: bool,
+    // HostFileLock mirrors the fcntl(2) and flock(2) locks of the host backed
+    // regular files to the host fd, so that they are coherent with the other
+    // sandboxes and host processes sharing the volume.
+    #[serde(default)]
+    pub HostFileLock: bool,
 }
 
 impl Config {
@@ -90,6 +123,17 @@ impl Default for Config {
             Realtime: false,
             EnableIOBuf: false,
             EnableTsot: false,
+            EnableNetstack: false,
+            EnableReclaim: false,
+            ReclaimInterval: 10,
+            ReclaimCompression: ReclaimCompression::Lz4,
+            ReclaimEncrypt: false,
+            ReclaimSwapPath: DefaultReclaimSwapPath(),
+            FreePageReporting: false,
+            RootfsOverlay: RootfsOverlay::None,
+            UnsupportedStrict: false,
+            HostInotify: false,
+            HostFileLock: false,
         };
     }
 }
@@ -112,6 +156,101 @@ impl Default for DebugLevel {
 
 pub const ENABLE_BUFF_IO: bool = false;
 
+// ReclaimCompression is the compression of the cold pages written to the reclaim swap store
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReclaimCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Default for ReclaimCompression {
+    fn default() -> Self {
+        return Self::Lz4;
+    }
+}
+
+pub const DEFAULT_RECLAIM_SWAP_PATH: &str = "/var/lib/quark/swap";
+
+fn DefaultReclaimSwapPath() -> ConfigPath {
+    return ConfigPath::New(DEFAULT_RECLAIM_SWAP_PATH).unwrap();
+}
+
+// CONFIG_PATH_MAX is the max length of the paths in the config
+pub const CONFIG_PATH_MAX: usize = 256;
+
+// ConfigPath is a path in the config. It is kept inline so that the Config
+// shared with the guest kernel stays Copy, it is a string in the config file.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConfigPath {
+    len: usize,
+    buf: [u8; CONFIG_PATH_MAX],
+}
+
+impl ConfigPath {
+    pub fn New(path: &str) -> Result<Self, String> {
+        if path.len() > CONFIG_PATH_MAX {
+            return Err("config path is too long".to_string());
+        }
+
+        let mut buf = [0; CONFIG_PATH_MAX];
+        buf[..path.len()].copy_from_slice(path.as_bytes());
+        return Ok(Self {
+            len: path.len(),
+            buf: buf,
+        });
+    }
+
+    pub fn Str(&self) -> &str {
+        // the path is copied from a str
+        return core::str::from_utf8(&self.buf[..self.len]).unwrap_or("");
+    }
+}
+
+impl Default for ConfigPath {
+    fn default() -> Self {
+        return Self {
+            len: 0,
+            buf: [0; CONFIG_PATH_MAX],
+        };
+    }
+}
+
+impl fmt::Debug for ConfigPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.Str())
+    }
+}
+
+impl TryFrom<String> for ConfigPath {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<Self, String> {
+        return Self::New(&path);
+    }
+}
+
+impl From<ConfigPath> for String {
+    fn from(path: ConfigPath) -> String {
+        return path.Str().to_string();
+    }
+}
+
+// RootfsOverlay is the upper layer mounted over the container rootfs, the host
+// rootfs is the read only lower layer when it is not None
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RootfsOverlay {
+    None,
+    Tmpfs,
+}
+
+impl Default for RootfsOverlay {
+    fn default() -> Self {
+        return Self::None;
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum LogLevel {
     None,
@@ -103,6 +103,164 @@ pub struct StartArgs {
     pub process: Process,
 }
 
+/// UpdateResourcesArgs is payload for UpdateResources control msg to quark sandbox.
+/// A None field keeps the current setting.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UpdateResourcesArgs {
+    // ContainerID is the container whose resources are updated. The guest
+    // only has sandbox wide limits, so it has to be the root container.
+    pub ContainerID: String,
+
+    // CPUs is the number of cpus in the new cpuset.
+    pub CPUs: Option<u32>,
+
+    // CPUQuota and CPUPeriod are the CFS bandwidth in microseconds. A quota
+    // of -1 removes the limit.
+    pub CPUQuota: Option<i64>,
+    pub CPUPeriod: Option<u64>,
+    pub CPUShares: Option<u64>,
+
+    // MemoryLimit is the memory limit in bytes. -1 removes the limit.
+    pub MemoryLimit: Option<i64>,
+}
+
+/// EventsArgs is payload for Events control msg, it asks for the sandbox
+/// events of the container with sequence number no less than Since.
+/// An empty cid asks for the events of all containers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EventsArgs {
+    pub cid: String,
+    pub Since: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SandboxEventType {
+    Exit,
+    OOM,
+    Pause,
+    Resume,
+    Hibernate,
+    Wakeup,
+}
+
+/// SandboxEvent is an event recorded by the quark kernel. The pause and
+/// hibernate events have an empty cid as they apply to the whole sandbox.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SandboxEvent {
+    pub Seq: u64,
+    pub Type: SandboxEventType,
+    pub cid: String,
+    pub execId: String,
+    pub Pid: i32,
+    pub Status: i32,
+    // Time is the realtime in nanoseconds
+    pub Time: i64,
+}
+
+/// ContainerStats is the resource usage of a container collected by the
+/// quark kernel.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContainerStats {
+    pub cid: String,
+    pub Pids: u64,
+    // CPU time in nanoseconds
+    pub UserTime: i64,
+    pub SysTime: i64,
+    // Memory in bytes
+    pub MemoryUsage: u64,
+    pub MemoryMaxUsage: u64,
+    pub MemoryLimit: u64,
+    pub OOMKills: u64,
+    pub Paused: bool,
+    pub Hibernated: bool,
+}
+
+/// KernelLogArgs is payload for KernelLog control msg, it asks for the kernel
+/// log entries of the container with sequence number no less than Since.
+/// An empty cid asks for the entries of all containers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct KernelLogArgs {
+    pub cid: String,
+    pub Since: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum KernelLogType {
+    Kernel,
+    Task,
+    OOM,
+    Signal,
+    Syscall,
+    Mount,
+    Network,
+}
+
+/// KernelLogLevel is the syslog priority of a kernel log entry
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum KernelLogLevel {
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// KernelLogEntry is an entry of the quark kernel log, which is read by
+/// dmesg in the guest and "qvisor logs" on the host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KernelLogEntry {
+    pub Seq: u64,
+    pub Type: KernelLogType,
+    pub Level: KernelLogLevel,
+    pub cid: String,
+    pub Pid: i32,
+    pub Message: String,
+    // Time is the realtime in nanoseconds
+    pub Time: i64,
+    // Uptime is the time since the kernel start in nanoseconds
+    pub Uptime: i64,
+}
+
+impl KernelLogEntry {
+    // VisibleTo returns whether the entry is in the log of the container. The
+    // sandbox wide entries are in the log of all containers and an empty cid
+    // reads the log of the whole sandbox.
+    pub fn VisibleTo(&self, cid: &str) -> bool {
+        return cid.len() == 0 || self.cid.len() == 0 || self.cid == cid;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnsupportedKind {
+    Syscall,
+    Ioctl,
+    SockOpt,
+    Fcntl,
+    ProcFile,
+}
+
+/// UnsupportedRecord is a call of the container which fell through to a stub
+/// in the quark kernel, e.g. an unimplemented syscall or ioctl.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnsupportedRecord {
+    pub Kind: UnsupportedKind,
+    pub Name: String,
+    pub Count: u64,
+    // Args is the arguments summary of the first call
+    pub Args: String,
+    // Binaries is the path of the binaries which made the call
+    pub Binaries: Vec<String>,
+}
+
+/// TraceArgs enables the strace style syscall tracing of a container.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TraceArgs {
+    pub cid: String,
+    // Pid is the thread group to trace, 0 means all the processes of the container
+    pub Pid: i32,
+    // Syscalls is the syscall names to trace, e.g. "open", empty means all
+    pub Syscalls: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Payload {
     RootContainerStart(RootProcessStart),
@@ -117,6 +275,19 @@ pub enum Payload {
     CreateSubContainer(CreateArgs),
     StartSubContainer(StartArgs),
     WaitAll,
+    UpdateResources(UpdateResourcesArgs),
+    // Checkpoint pauses the sandbox and swaps its memory out to the
+    // hibernate swap files, Unpause swaps it back in.
+    Checkpoint,
+    Events(EventsArgs),
+    Stats(Cid),
+    KernelLog(KernelLogArgs),
+    Unsupported(Cid),
+    TraceStart(TraceArgs),
+    TraceStop(Cid),
+    // Served is a request which has been answered by the host, e.g. the trace
+    // read, the connection is closed by the host already
+    Served,
 }
 
 impl Default for Payload {
@@ -150,6 +321,18 @@ pub enum UCallResp {
     CreateSubContainerResp,
     StartSubContainerResp,
     WaitAllResp(WaitAllResp),
+    UpdateResourcesResp,
+    CheckpointResp,
+    EventsResp(Vec<SandboxEvent>),
+    StatsResp(ContainerStats),
+    KernelLogResp(Vec<KernelLogEntry>),
+    UnsupportedResp(Vec<UnsupportedRecord>),
+    TraceStartResp,
+    TraceStopResp,
+    // TraceReadResp is the trace lines of the container and the number of
+    // the lines of the sandbox dropped since the last read as the trace ring
+    // was full
+    TraceReadResp(Vec<String>, u64),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -371,6 +371,15 @@ impl HostSpace {
         return HostSpace::HCall(&mut msg, false) as i64;
     }
 
+    pub fn ReportFreePages(iovs: &[IoVec]) -> i64 {
+        let mut msg = Msg::ReportFreePages(ReportFreePages {
+            iovs: &iovs[0] as *const _ as u64,
+            count: iovs.len(),
+        });
+
+        return HostSpace::HCall(&mut msg, false) as i64;
+    }
+
     pub fn SysSync() -> i64 {
         let mut msg = Msg::SysSync(SysSync {});
 
@@ -724,6 +733,37 @@ impl HostSpace {
         return ret;
     }
 
+    pub fn HostInotifyInit() -> i64 {
+        let mut msg = Msg::HostInotifyInit(HostInotifyInit {});
+
+        return Self::Call(&mut msg, false) as i64;
+    }
+
+    pub fn HostInotifyAddWatch(fd: i32, target: i32, mask: u32) -> i64 {
+        let mut msg = Msg::HostInotifyAddWatch(HostInotifyAddWatch {
+            fd: fd,
+            target: target,
+            mask: mask,
+        });
+
+        return Self::Call(&mut msg, false) as i64;
+    }
+
+    pub fn HostInotifyRmWatch(fd: i32, wd: i32) -> i64 {
+        let mut msg = Msg::HostInotifyRmWatch(HostInotifyRmWatch { fd: fd, wd: wd });
+
+        return Self::Call(&mut msg, false) as i64;
+    }
+
+    pub fn HostFlock(fd: i32, operation: i32) -> i64 {
+        let mut msg = Msg::HostFlock(HostFlock {
+            fd: fd,
+            operation: operation,
+        });
+
+        return Self::Call(&mut msg, false) as i64;
+    }
+
     pub fn TsotRecvMsg(msgAddr: u64) -> i64 {
         let mut msg = Msg::TsotRecvMsg(TsotRecvMsg {
             msgAddr: msgAddr,
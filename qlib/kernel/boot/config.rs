@@ -13,8 +13,23 @@
 // limitations under the License.
 
 use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::super::super::loader::{DiskQuotaLimits, EmptyDirMount, EncryptedVolume, ImageMount};
 
 pub struct Config {
+    pub ContainerID: String,
     pub RootDir: String,
     pub Debug: bool,
+    pub RootfsOverlay: bool,
+    pub DiskQuota: DiskQuotaLimits,
+    pub EncryptedVolumes: Vec<EncryptedVolume>,
+    pub ImageMounts: Vec<ImageMount>,
+
+    // HostMounts are the destinations of the host bind mounts, they are
+    // mounted again over an image rootfs which hides them.
+    pub HostMounts: Vec<String>,
+
+    // EmptyDirs are the memory backed emptyDir volumes of the pod
+    pub EmptyDirs: Vec<EmptyDirMount>,
 }
@@ -17,7 +17,9 @@ use alloc::vec::Vec;
 use core::ptr;
 use core::sync::atomic;
 
-use crate::qlib::kernel::kernel::kernel::GetKernel;
+use crate::qlib::kernel::kernel::kernel;
+use crate::qlib::kernel::kernel::kernel::{GetKernel, PauseState};
+use crate::qlib::kernel::kernel::strace::STRACE;
 use crate::qlib::kernel::Kernel::HostSpace;
 //use crate::qlib::mem::list_allocator::*;
 use super::super::super::super::kernel_def::{
@@ -46,6 +48,104 @@ pub fn ControllerProcessHandler() -> Result<()> {
     }
 }
 
+// HibernatePause pauses the sandbox and swaps out its memory to the swap files.
+// It returns false if the sandbox has been hibernated already. The tasks of a
+// sandbox paused by the container runtime are stopped already. report tells
+// whether the hibernation is a pause of the containers, it is not for the
+// checkpoint.
+pub fn HibernatePause(report: bool) -> bool {
+    let kernel = GetKernel();
+    let mut state = kernel.pauseState.lock();
+    if SHARESPACE.hibernatePause.load(atomic::Ordering::Relaxed) {
+        // if the sandbox has been paused, return
+        return false;
+    }
+    if !state.paused {
+        kernel.Pause();
+    }
+    kernel.ClearFsCache();
+    HostSpace::SwapOut();
+    SHARESPACE
+        .hibernatePause
+        .store(true, atomic::Ordering::SeqCst);
+
+    state.hibernateEvent = report && !state.paused;
+    if state.hibernateEvent {
+        kernel
+            .events
+            .Push(SandboxEventType::Hibernate, "", "", 0, 0);
+    }
+    return true;
+}
+
+// HibernateResume swaps in the memory of a hibernated sandbox and resumes it.
+// It returns false if the sandbox is not hibernated. A sandbox paused by the
+// container runtime stays paused until Unpause.
+pub fn HibernateResume() -> bool {
+    let kernel = GetKernel();
+    let mut state = kernel.pauseState.lock();
+    if !SHARESPACE.hibernatePause.load(atomic::Ordering::Relaxed) {
+        return false;
+    }
+
+    SHARESPACE
+        .hibernatePause
+        .store(false, atomic::Ordering::SeqCst);
+    HostSpace::SwapIn();
+    if !state.paused {
+        kernel.Unpause();
+    }
+    HibernateWakeupEvent(&kernel, &mut state);
+    return true;
+}
+
+// HibernateWakeupEvent reports the end of a hibernation which has been
+// reported as a pause of the containers.
+pub fn HibernateWakeupEvent(kernel: &kernel::Kernel, state: &mut PauseState) {
+    if state.hibernateEvent {
+        state.hibernateEvent = false;
+        kernel.events.Push(SandboxEventType::Wakeup, "", "", 0, 0);
+    }
+}
+
+// RuntimePause pauses the sandbox for the container runtime. It returns false
+// if the sandbox has been paused already.
+pub fn RuntimePause() -> bool {
+    let kernel = GetKernel();
+    let mut state = kernel.pauseState.lock();
+    if state.paused {
+        return false;
+    }
+
+    // the tasks of a hibernated sandbox are stopped already
+    if !SHARESPACE.hibernatePause.load(atomic::Ordering::Relaxed) {
+        kernel.Pause();
+    }
+    state.paused = true;
+    // the containers stay paused after the wakeup now
+    state.hibernateEvent = false;
+    kernel.events.Push(SandboxEventType::Pause, "", "", 0, 0);
+    return true;
+}
+
+// RuntimeUnpause resumes the sandbox paused by the container runtime or by a
+// checkpoint, a hibernated sandbox is swapped in first. It returns false if the
+// sandbox is not paused.
+pub fn RuntimeUnpause() -> bool {
+    HibernateResume();
+
+    let kernel = GetKernel();
+    let mut state = kernel.pauseState.lock();
+    if !state.paused {
+        return false;
+    }
+
+    kernel.Unpause();
+    state.paused = false;
+    kernel.events.Push(SandboxEventType::Resume, "", "", 0, 0);
+    return true;
+}
+
 pub fn HandleSignal(signalArgs: &SignalArgs) {
     info!("HandleSignal: get signal {:?}", &signalArgs);
 
@@ -70,16 +170,7 @@ pub fn HandleSignal(signalArgs: &SignalArgs) {
     }*/
 
     if signalArgs.Signo == SIGSTOP.0 || signalArgs.Signo == SIGUSR2.0 {
-        if SHARESPACE.hibernatePause.load(atomic::Ordering::Relaxed) {
-            // if the sandbox has been paused, return
-            return;
-        }
-        GetKernel().Pause();
-        GetKernel().ClearFsCache();
-        HostSpace::SwapOut();
-        SHARESPACE
-            .hibernatePause
-            .store(true, atomic::Ordering::SeqCst);
+        HibernatePause(true);
         return;
 
         /*for vcpu in CPU_LOCAL.iter() {
@@ -91,13 +182,7 @@ pub fn HandleSignal(signalArgs: &SignalArgs) {
         || signalArgs.Signo == SIGKILL.0
         || signalArgs.Signo == SIGINT.0
     {
-        if SHARESPACE.hibernatePause.load(atomic::Ordering::Relaxed) {
-            SHARESPACE
-                .hibernatePause
-                .store(false, atomic::Ordering::SeqCst);
-            HostSpace::SwapIn();
-            GetKernel().Unpause();
-        }
+        HibernateResume();
 
         if signalArgs.Signo == SIGCONT.0 {
             return;
@@ -167,15 +252,54 @@ pub fn ControlMsgHandler(fd: *const u8) {
     //defer!(error!("payload handling ends"));
     match msg.payload {
         Payload::Pause => {
-            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
-            kernel.Pause();
+            RuntimePause();
             WriteControlMsgResp(fd, &UCallResp::PauseResp, true);
         }
         Payload::Unpause => {
-            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
-            kernel.Unpause();
+            RuntimeUnpause();
             WriteControlMsgResp(fd, &UCallResp::UnpauseResp, true);
         }
+        Payload::Events(args) => {
+            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
+            let events = kernel.events.Since(&args.cid, args.Since);
+            WriteControlMsgResp(fd, &UCallResp::EventsResp(events), true);
+        }
+        Payload::KernelLog(args) => {
+            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
+            let entries = kernel.syslog.Since(&args.cid, args.Since);
+            WriteControlMsgResp(fd, &UCallResp::KernelLogResp(entries), true);
+        }
+        Payload::Unsupported(cid) => {
+            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
+            let records = kernel.unsupported.Report(&cid);
+            WriteControlMsgResp(fd, &UCallResp::UnsupportedResp(records), true);
+        }
+        Payload::TraceStart(args) => {
+            STRACE.Start(&args);
+            WriteControlMsgResp(fd, &UCallResp::TraceStartResp, true);
+        }
+        Payload::TraceStop(cid) => {
+            STRACE.Stop(&cid);
+            WriteControlMsgResp(fd, &UCallResp::TraceStopResp, true);
+        }
+        Payload::Served => (),
+        Payload::Stats(cid) => {
+            let kernel = LOADER.Lock(task).unwrap().kernel.clone();
+            let stats = kernel.ContainerStats(&cid);
+            WriteControlMsgResp(fd, &UCallResp::StatsResp(stats), true);
+        }
+        Payload::Checkpoint => {
+            HibernatePause(false);
+            WriteControlMsgResp(fd, &UCallResp::CheckpointResp, true);
+        }
+        Payload::UpdateResources(args) => match LOADER.UpdateResources(&args) {
+            Ok(()) => {
+                WriteControlMsgResp(fd, &UCallResp::UpdateResourcesResp, true);
+            }
+            Err(e) => {
+                WriteControlMsgResp(fd, &UCallResp::UCallRespErr(format!("{:?}", e)), true);
+            }
+        },
         Payload::Ps(cid) => {
             let kernel = LOADER.Lock(task).unwrap().kernel.clone();
             let ps = Processes(&kernel, &cid);
@@ -246,6 +370,9 @@ pub fn ControlMsgHandler(fd: *const u8) {
 }
 
 pub fn WriteWaitAllResponse(cid: String, execId: String, status: i32) {
+    GetKernel()
+        .events
+        .Push(SandboxEventType::Exit, &cid, &execId, 0, status);
     let fd = WaitContainerfd();
     WriteControlMsgResp(
         fd,
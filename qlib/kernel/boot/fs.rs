@@ -14,23 +14,31 @@
 
 use crate::qlib::mutex::*;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use lazy_static::lazy_static;
 
 use super::super::super::auth::*;
 use super::super::super::common::*;
+use super::super::super::control_msg::{KernelLogLevel, KernelLogType};
 use super::super::super::linux_def::{FileMode, FilePermissions, SysErr};
+use super::super::super::loader::{DiskQuotaLimits, EmptyDirMount, EncryptedVolume, ImageMount};
 use super::super::super::path::*;
 use super::super::fs::dirent::*;
 use super::super::fs::filesystems::*;
+use super::super::fs::host::crypt::CryptPolicy;
 use super::super::fs::host::fs::*;
+use super::super::fs::host::quota::HostQuota;
 use super::super::fs::host::util::*;
+use super::super::fs::image::fs::{IsImageFilesystem, MountImageFile};
 use super::super::fs::inode::*;
 use super::super::fs::mount::*;
 use super::super::fs::overlay::*;
 use super::super::fs::ramfs::tree::*;
+use super::super::kernel::syslog::KernelLog;
 use super::super::task::*;
 
 use super::*;
@@ -47,28 +55,198 @@ const SYSFS: &str = "sysfs";
 const TMPFS: &str = "tmpfs";
 const NONEFS: &str = "none";
 
+// BIND is the type of the host bind mounts mounted over an image rootfs.
+const BIND: &str = "bind";
+
 fn CreateRootMount(
     task: &Task,
     spec: &oci::Spec,
     config: &config::Config,
     mounts: &Vec<oci::Mount>,
 ) -> Result<Inode> {
-    let mf = MountSourceFlags {
+    let mut mf = MountSourceFlags {
         ReadOnly: spec.root.readonly,
         ..Default::default()
     };
 
+    let overlay = config.RootfsOverlay && !spec.root.readonly;
+    if overlay {
+        // The host rootfs is the lower layer of the overlay, it is never written.
+        mf.ReadOnly = true;
+    }
+
     let rootStr = &config.RootDir;
-    let (fd, writeable, fstat) = TryOpenAt(-100, rootStr, false)?;
+    let rootImage = RootImage(config);
+    let rootfs = match rootImage {
+        // the image is parsed by the kernel, the rootfs directory isn't used
+        Some(image) => MountImageFile(&image.Type, &image.Source, &mf)?,
+        None => {
+            let (fd, writeable, fstat) = TryOpenAt(-100, rootStr, false)?;
+
+            let mut ms = MountSource::NewHostMountSource(
+                &rootStr,
+                &ROOT_OWNER,
+                &WhitelistFileSystem::New(),
+                &mf,
+                false,
+            );
+            // the usage is counted without limits too, quotactl can set them later
+            ms.quota = Some(HostQuota::New(&config.DiskQuota));
+            Inode::NewHostInode(
+                task,
+                &Arc::new(QMutex::new(ms)),
+                fd,
+                &fstat,
+                writeable,
+                false,
+                false,
+            )?
+        }
+    };
+
+    let submounts = SubTargets(&"/".to_string(), mounts);
+    //submounts.append(&mut vec!["/dev1".to_string(), "/sys".to_string(), "/proc".to_string(), "/tmp".to_string()]);
+
+    let mut rootInode = AddSubmountOverlay(task, &rootfs, &submounts)?;
+
+    if overlay {
+        debug!("adding overlay on top of root mount");
+        // Overlay a tmpfs filesystem on top of the root.
+        rootInode = AddOverlay(task, &rootInode, "root-overlay-upper", &mf)?;
+    }
+
+    let rootSource = match rootImage {
+        Some(image) => &image.Source,
+        None => rootStr,
+    };
+    KernelLog(
+        KernelLogType::Mount,
+        KernelLogLevel::Info,
+        &config.ContainerID,
+        0,
+        format!("mounted rootfs {} overlay {}", rootSource, overlay),
+    );
+    return Ok(rootInode);
+}
+
+// RootImage returns the image mounted as the rootfs, if any.
+fn RootImage(config: &config::Config) -> Option<&ImageMount> {
+    return config.ImageMounts.iter().find(|m| m.Destination == "/");
+}
+
+// EmptyDir is the tmpfs of a memory backed emptyDir volume and the containers
+// which mount it, the tmpfs is released with the last of them.
+struct EmptyDir {
+    root: Inode,
+    users: BTreeSet<String>,
+}
+
+lazy_static! {
+    // EMPTY_DIRS is the emptyDir volumes of the pod by their names, so that
+    // the containers of the pod share them.
+    static ref EMPTY_DIRS: QMutex<BTreeMap<String, EmptyDir>> = QMutex::new(BTreeMap::new());
+}
 
-    let ms = MountSource::NewHostMountSource(
-        &rootStr,
+// FindEmptyDir returns the emptyDir volume the host path of a bind mount is
+// in, and the path of the mount in the volume.
+fn FindEmptyDir<'a>(
+    config: &'a config::Config,
+    source: &str,
+) -> Option<(&'a EmptyDirMount, String)> {
+    let source = Clean(source);
+    for e in &config.EmptyDirs {
+        if source == e.Source {
+            return Some((e, "".to_string()));
+        }
+
+        if let Some(sub) = source.strip_prefix(&format!("{}/", e.Source)) {
+            return Some((e, sub.to_string()));
+        }
+    }
+
+    return None;
+}
+
+// ReleaseEmptyDirs drops the emptyDir volumes of the destroyed container, the
+// tmpfs of a volume is released when no container mounts it.
+pub fn ReleaseEmptyDirs(cid: &str) {
+    EMPTY_DIRS.lock().retain(|_, d| {
+        d.users.remove(cid);
+        d.users.len() > 0
+    });
+}
+
+// MountEmptyDir mounts a memory backed emptyDir volume as a tmpfs of the
+// sandbox, its pages are charged to the sandbox memory up to the size limit.
+// A mount of a subdirectory of the volume, e.g. a subPath, is the directory
+// of the shared tmpfs.
+fn MountEmptyDir(
+    task: &Task,
+    cid: &str,
+    e: &EmptyDirMount,
+    sub: &str,
+    mf: &MountSourceFlags,
+) -> Result<Inode> {
+    let mut dirs = EMPTY_DIRS.lock();
+    if !dirs.contains_key(&e.Name) {
+        // the kubelet creates the volume world writable
+        let mut data = "mode=777".to_string();
+        if e.Size > 0 {
+            data = format!("{},size={}", data, e.Size);
+        }
+        let tmpfs = MustFindFilesystem(TMPFS);
+        let root = tmpfs.lock().Mount(task, "none", mf, &data)?;
+        dirs.insert(
+            e.Name.clone(),
+            EmptyDir {
+                root: root,
+                users: BTreeSet::new(),
+            },
+        );
+    }
+
+    let dir = dirs.get_mut(&e.Name).unwrap();
+    let inode = if sub.len() == 0 {
+        dir.root.clone()
+    } else {
+        let mns = MountNs::New(task, &dir.root);
+        let root = mns.Root();
+        MakeMountPoint(task, &mns, &root, sub)?;
+        let mut maxTraversals = 0;
+        mns.FindDirent(
+            task,
+            &root,
+            Some(root.clone()),
+            sub,
+            &mut maxTraversals,
+            true,
+        )?
+        .Inode()
+    };
+
+    dir.users.insert(cid.to_string());
+    return Ok(inode);
+}
+
+// MountHostPath mounts the host file or directory at path, the mount gets its
+// own quota with the limits of the container.
+fn MountHostPath(
+    task: &Task,
+    path: &str,
+    mf: &MountSourceFlags,
+    limits: &DiskQuotaLimits,
+) -> Result<Inode> {
+    let (fd, writeable, fstat) = TryOpenAt(-100, path, false)?;
+    let mut ms = MountSource::NewHostMountSource(
+        &path.to_string(),
         &ROOT_OWNER,
         &WhitelistFileSystem::New(),
-        &mf,
+        mf,
         false,
     );
-    let hostRoot = Inode::NewHostInode(
+    ms.quota = Some(HostQuota::New(limits));
+
+    return Inode::NewHostInode(
         task,
         &Arc::new(QMutex::new(ms)),
         fd,
@@ -76,14 +254,37 @@ fn CreateRootMount(
         writeable,
         false,
         false,
-    )?;
+    );
+}
 
-    let submounts = SubTargets(&"/".to_string(), mounts);
-    //submounts.append(&mut vec!["/dev1".to_string(), "/sys".to_string(), "/proc".to_string(), "/tmp".to_string()]);
+// AddOverlay mounts a tmpfs as the upper layer of the lower inode, the writes
+// land on the tmpfs and are dropped with the container.
+pub fn AddOverlay(
+    task: &Task,
+    lower: &Inode,
+    name: &str,
+    lowerFlags: &MountSourceFlags,
+) -> Result<Inode> {
+    // Upper layer uses the same flags as lower, but it must be read-write.
+    let mut upperFlags = *lowerFlags;
+    upperFlags.ReadOnly = false;
+
+    // Replicate permissions and owner from lower to upper mount point. The
+    // upper layer has no size and inode limits, the container writes are
+    // bounded by the sandbox memory as before.
+    let attr = lower.UnstableAttr(task)?;
+    let data = format!(
+        "mode={:o},uid={},gid={},size=0,nr_inodes=0",
+        attr.Perms.LinuxMode(),
+        attr.Owner.UID.0,
+        attr.Owner.GID.0
+    );
 
-    let rootInode = AddSubmountOverlay(task, &hostRoot, &submounts)?;
+    let tmpfs = MustFindFilesystem(TMPFS);
+    let upper = tmpfs.lock().Mount(task, name, &upperFlags, &data)?;
 
-    return Ok(rootInode);
+    let overlayInode = NewOverlayRoot(task, &upper, lower, &upperFlags)?;
+    return Ok(overlayInode);
 }
 
 pub fn AddSubmountOverlay(task: &Task, inode: &Inode, submounts: &Vec<String>) -> Result<Inode> {
@@ -120,7 +321,10 @@ fn GetMountNameAndOptions(_conf: &config::Config, m: &oci::Mount) -> Result<(Str
         }
         TMPFS => {
             fsName = m.typ.to_string();
-            opts = ParseAndFilterOptions(&m.options, &vec!["mode", "uid", "gid"])?;
+            opts = ParseAndFilterOptions(
+                &m.options,
+                &vec!["mode", "uid", "gid", "size", "nr_inodes"],
+            )?;
         }
         _ => {
             info!("ignoring unknown filesystem type {}", m.typ);
@@ -153,10 +357,27 @@ pub fn InitTestSpec() -> oci::Spec {
     };
 }
 
-pub fn InitRootFs(task: &mut Task, root: &str) -> Result<MountNs> {
+pub fn InitRootFs(
+    task: &mut Task,
+    cid: &str,
+    root: &str,
+    rootfsOverlay: bool,
+    diskQuota: &DiskQuotaLimits,
+    encryptedVolumes: &Vec<EncryptedVolume>,
+    imageMounts: &Vec<ImageMount>,
+    hostMounts: &Vec<String>,
+    emptyDirs: &Vec<EmptyDirMount>,
+) -> Result<MountNs> {
     let config = config::Config {
+        ContainerID: cid.to_string(),
         RootDir: root.to_string(),
         Debug: true,
+        RootfsOverlay: rootfsOverlay,
+        DiskQuota: *diskQuota,
+        EncryptedVolumes: encryptedVolumes.clone(),
+        ImageMounts: imageMounts.clone(),
+        HostMounts: hostMounts.clone(),
+        EmptyDirs: emptyDirs.clone(),
     };
 
     debug!("init rootfs under {} for container", root);
@@ -170,7 +391,7 @@ pub fn SetupContainerFS(
     spec: &oci::Spec,
     conf: &config::Config,
 ) -> Result<MountNs> {
-    let mounts = CompileMounts(spec);
+    let mounts = CompileMounts(spec, conf);
 
     //error!("SetupRootContainerFS 1.0 mounts[0].destination is {:?}", &mounts[0].destination);
 
@@ -180,10 +401,61 @@ pub fn SetupContainerFS(
     let root = mns.Root();
 
     MountSubmounts(task, conf, &mns, &root, &mounts)?;
+    SetupEncryptedVolumes(task, conf, &mns, &root)?;
     return Ok(mns);
 }
 
-fn CompileMounts(spec: &oci::Spec) -> Vec<oci::Mount> {
+// SetupEncryptedVolumes sets the keys of the encrypted volumes on the host
+// directories the volumes are bind mounted on. The file content and the names
+// under them are encrypted from then on.
+fn SetupEncryptedVolumes(
+    task: &Task,
+    config: &config::Config,
+    mns: &MountNs,
+    root: &Dirent,
+) -> Result<()> {
+    for v in &config.EncryptedVolumes {
+        let mut maxTraversals = 0;
+        let dirent = mns.FindDirent(
+            task,
+            root,
+            Some(root.clone()),
+            &v.Path,
+            &mut maxTraversals,
+            true,
+        )?;
+
+        // the host directory is the upper layer of the submount overlay
+        let mut inode = dirent.Inode();
+        let upper = match &inode.lock().Overlay {
+            None => None,
+            Some(overlay) => overlay.read().upper.clone(),
+        };
+        if let Some(upper) = upper {
+            inode = upper;
+        }
+
+        let dirOp = match inode.lock().InodeOp.HostDirOp() {
+            None => return Err(Error::SysError(SysErr::ENOTDIR)),
+            Some(dirOp) => dirOp,
+        };
+
+        let policy = CryptPolicy::New(&v.Key)?;
+        dirOp.SetCryptPolicy(task, &policy)?;
+
+        KernelLog(
+            KernelLogType::Mount,
+            KernelLogLevel::Info,
+            &config.ContainerID,
+            0,
+            format!("encrypted volume at {}", v.Path),
+        );
+    }
+
+    return Ok(());
+}
+
+fn CompileMounts(spec: &oci::Spec, conf: &config::Config) -> Vec<oci::Mount> {
     let mut _procMounted = false;
     let mut _sysMounted = false;
     let mut mounts = Vec::new();
@@ -238,6 +510,34 @@ fn CompileMounts(spec: &oci::Spec) -> Vec<oci::Mount> {
         }
     }
 
+    // the image volumes are mounted by the kernel, the host only binds the
+    // image files into the sandbox root
+    for m in &conf.ImageMounts {
+        if m.Destination == "/" {
+            continue;
+        }
+
+        mounts.push(oci::Mount {
+            destination: m.Destination.to_string(),
+            typ: m.Type.to_string(),
+            source: m.Source.to_string(),
+            options: vec!["ro".to_string()],
+        });
+    }
+
+    // the host bind mounts are in the rootfs directory, which an image
+    // rootfs hides
+    if RootImage(conf).is_some() {
+        for dest in &conf.HostMounts {
+            mounts.push(oci::Mount {
+                destination: dest.to_string(),
+                typ: BIND.to_string(),
+                source: Join(&conf.RootDir, dest),
+                options: Vec::new(),
+            });
+        }
+    }
+
     let mut mandatoryMounts = Vec::new();
     /*if !procMounted {
         mandatoryMounts.push(oci::Mount {
@@ -322,18 +622,27 @@ fn MountSubmount(
     m: &oci::Mount,
     mounts: &Vec<oci::Mount>,
 ) -> Result<()> {
-    let (fsName, opts) = GetMountNameAndOptions(config, m)?;
-
-    if fsName.as_str() == "" {
-        return Ok(());
-    }
-
-    let filesystem = MustFindFilesystem(&fsName);
     let mf = mountFlags(&m.options);
+    let mut inode = if IsImageFilesystem(&m.typ) {
+        MountImageFile(&m.typ, &m.source, &mf)?
+    } else if m.typ == BIND {
+        match FindEmptyDir(config, &m.source) {
+            Some((e, sub)) => MountEmptyDir(task, &config.ContainerID, e, &sub, &mf)?,
+            None => MountHostPath(task, &m.source, &mf, &config.DiskQuota)?,
+        }
+    } else {
+        let (fsName, opts) = GetMountNameAndOptions(config, m)?;
 
-    let mut inode = filesystem
-        .lock()
-        .Mount(task, &"none".to_string(), &mf, &opts.join(","))?;
+        if fsName.as_str() == "" {
+            return Ok(());
+        }
+
+        let filesystem = MustFindFilesystem(&fsName);
+        let inode = filesystem
+            .lock()
+            .Mount(task, &"none".to_string(), &mf, &opts.join(","))?;
+        inode
+    };
     let submounts = SubTargets(&m.destination, mounts);
     if submounts.len() > 0 {
         info!("adding submount overlay over {}", m.destination);
@@ -352,6 +661,13 @@ fn MountSubmount(
     mns.Mount(&dirent, &inode)?;
 
     info!("Mounted {} to {} type {}", m.source, m.destination, m.typ);
+    KernelLog(
+        KernelLogType::Mount,
+        KernelLogLevel::Info,
+        &config.ContainerID,
+        0,
+        format!("mounted {} at {} type {}", m.source, m.destination, m.typ),
+    );
     return Ok(());
 }
 
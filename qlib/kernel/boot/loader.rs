@@ -27,6 +27,7 @@ use super::super::super::auth::cap_set::*;
 use super::super::super::auth::id::*;
 use super::super::super::auth::userns::*;
 use super::super::super::common::*;
+use super::super::super::control_msg::UpdateResourcesArgs;
 use super::super::super::cpuid::*;
 use super::super::super::limits::*;
 use super::super::super::linux_def::*;
@@ -38,6 +39,7 @@ use super::super::kernel::ipc_namespace::*;
 use super::super::kernel::kernel::*;
 use super::super::kernel::uts_namespace::*;
 use super::super::kernel::waiter::qlock::*;
+use super::super::quring::throttle::*;
 use super::super::task::*;
 use super::super::threadmgr::thread::*;
 use super::super::threadmgr::thread_group::*;
@@ -146,6 +148,26 @@ impl Loader {
         return Ok(tg.ExitStatus().Status());
     }
 
+    // UpdateResources applies the new limits of the root container to the
+    // sandbox, there is no per container cpu or memory limit in the guest.
+    pub fn UpdateResources(&self, args: &UpdateResourcesArgs) -> Result<()> {
+        let task = Task::Current();
+        let (kernel, sandboxID) = {
+            let loader = self.Lock(task)?;
+            (loader.kernel.clone(), loader.sandboxID.clone())
+        };
+
+        if args.ContainerID != sandboxID {
+            return Err(Error::Common(format!(
+                "Loader::UpdateResources container {} is not the sandbox container {}",
+                &args.ContainerID, &sandboxID
+            )));
+        }
+
+        kernel.UpdateResources(args);
+        return Ok(());
+    }
+
     pub fn WaitPID(&self, cid: String, pid: ThreadID, clearStatus: bool) -> Result<u32> {
         let task = Task::Current();
         let tg = match self.Lock(task)?.ThreadGroupFromID(&ExecID {
@@ -356,8 +378,19 @@ impl Loader {
             Some(&processSpec.TaskCaps()),
             &userns,
         );
-        let rootMounts = InitRootFs(Task::Current(), &processSpec.Root)
-            .expect("in loader::StartSubContainer, InitRootfs fail");
+        let rootMounts = InitRootFs(
+            Task::Current(),
+            &processSpec.ID,
+            &processSpec.Root,
+            processSpec.RootfsOverlay,
+            &processSpec.DiskQuota,
+            &processSpec.EncryptedVolumes,
+            &processSpec.ImageMounts,
+            &processSpec.HostMounts,
+            &processSpec.EmptyDirs,
+        )
+        .expect("in loader::StartSubContainer, InitRootfs fail");
+        IO_THROTTLES.Set(&processSpec.ID, &processSpec.BlockIO);
         kernel
             .mounts
             .write()
@@ -489,8 +522,19 @@ impl LoaderInternal {
         let kernel = Kernel::Init(kernelArgs);
         *SHARESPACE.kernel.lock() = Some(kernel.clone());
 
-        let rootMounts =
-            InitRootFs(Task::Current(), &process.Root).expect("in loader::New, InitRootfs fail");
+        let rootMounts = InitRootFs(
+            Task::Current(),
+            &process.ID,
+            &process.Root,
+            process.RootfsOverlay,
+            &process.DiskQuota,
+            &process.EncryptedVolumes,
+            &process.ImageMounts,
+            &process.HostMounts,
+            &process.EmptyDirs,
+        )
+        .expect("in loader::New, InitRootfs fail");
+        IO_THROTTLES.Set(&process.ID, &process.BlockIO);
         kernel.mounts.write().insert(sandboxID.clone(), rootMounts);
 
         let processArgs = NewProcess(process, &creds, &kernel);
@@ -683,6 +727,7 @@ impl LoaderInternal {
         }
 
         l.processes.remove(&execId);
+        ReleaseEmptyDirs(&cid);
 
         info!("Container {} destroyed", &cid);
         return Ok(());
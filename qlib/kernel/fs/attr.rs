@@ -46,6 +46,9 @@ pub enum InodeFileType {
     SimpleFileInode,
     SymlinkNode,
     DirNode,
+    FuseDevice,
+    Fuse,
+    Image,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -250,8 +250,19 @@ fn copyAttributesLocked(task: &Task, upper: &mut Inode, lower: &Inode) -> Result
             continue;
         }
 
-        let value = lower.Getxattr(task, name, Xattr::XATTR_SIZE_MAX)?;
-        upperInodeOp.Setxattr(upper, name, &value, 0)?;
+        // Like Linux ovl_copy_xattr, skip the attributes removed since the
+        // listing and the namespaces the upper file system can't store.
+        let value = match lower.Getxattr(task, name, Xattr::XATTR_SIZE_MAX) {
+            Err(Error::SysError(SysErr::ENODATA)) => continue,
+            Err(e) => return Err(e),
+            Ok(v) => v,
+        };
+
+        match upperInodeOp.Setxattr(upper, name, &value, 0) {
+            Err(Error::SysError(SysErr::EOPNOTSUPP)) => continue,
+            Err(e) => return Err(e),
+            Ok(()) => (),
+        }
     }
 
     return Ok(());
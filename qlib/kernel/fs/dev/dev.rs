@@ -25,10 +25,12 @@ use alloc::sync::Arc;
 
 use super::super::super::super::auth::*;
 use super::super::super::super::device::*;
+use super::super::super::super::linux::fuse::*;
 use super::super::super::super::linux_def::*;
 use super::super::super::task::*;
 use super::super::super::uid::NewUID;
 use super::super::attr::*;
+use super::super::fuse::dev::*;
 use super::super::inode::*;
 use super::super::mount::*;
 use super::super::ramfs::dir::*;
@@ -101,6 +103,32 @@ fn NewNullDevice(iops: NullDevice, msrc: &Arc<QMutex<MountSource>>) -> Inode {
     return Inode(Arc::new(QMutex::new(inodeInternal)));
 }
 
+fn NewFuseDevice(iops: FuseDevice, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+    let deviceId = DEV_DEVICE.lock().id.DeviceID();
+    let inodeId = DEV_DEVICE.lock().NextIno();
+
+    let stableAttr = StableAttr {
+        Type: InodeType::CharacterDevice,
+        DeviceId: deviceId,
+        InodeId: inodeId,
+        BlockSize: MemoryDef::PAGE_SIZE as i64,
+        DeviceFileMajor: FUSE_DEV_MAJOR,
+        DeviceFileMinor: FUSE_DEV_MINOR,
+    };
+
+    let inodeInternal = InodeIntern {
+        UniqueId: NewUID(),
+        InodeOp: iops.into(),
+        StableAttr: stableAttr,
+        LockCtx: LockCtx::default(),
+        MountSource: msrc.clone(),
+        Overlay: None,
+        ..Default::default()
+    };
+
+    return Inode(Arc::new(QMutex::new(inodeInternal)));
+}
+
 fn NewTestProxyDevice(iops: ProxyDevice, msrc: &Arc<QMutex<MountSource>>) -> Inode {
     let deviceId = DEV_DEVICE.lock().id.DeviceID();
     let inodeId = DEV_DEVICE.lock().NextIno();
@@ -359,6 +387,11 @@ pub fn NewDev(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
         NewFullDevice(FullDevice::New(task, &ROOT_OWNER, &FileMode(0o0666)), msrc),
     );
 
+    contents.insert(
+        "fuse".to_string(),
+        NewFuseDevice(FuseDevice::New(task, &ROOT_OWNER, &FileMode(0o0666)), msrc),
+    );
+
     contents.insert(
         "shm".to_string(),
         Inode::NewTmpDirInode(task, "/dev/shm").expect("create /dev/shm fail"),
@@ -200,6 +200,7 @@ impl InodeOperations for NullDevice {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: fops.into(),
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(f)));
@@ -245,7 +246,14 @@ impl InodeOperations for NullDevice {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 
@@ -242,7 +242,14 @@ impl InodeOperations for TTYDevice {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 
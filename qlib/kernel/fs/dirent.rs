@@ -27,6 +27,7 @@ use lazy_static::lazy_static;
 use spin::*;
 
 use super::super::super::common::*;
+use super::super::super::linux::fanotify::*;
 use super::super::super::linux_def::*;
 use super::super::super::singleton::*;
 use super::super::socket::unix::transport::unix::*;
@@ -34,8 +35,10 @@ use super::super::task::*;
 use super::super::uid::*;
 use super::super::SHARESPACE;
 use super::dentry::*;
+use super::fanotify::*;
 use super::file::*;
 use super::flags::*;
+use super::host::hostinotify::*;
 use super::inode::*;
 use super::inotify::*;
 use super::mount::*;
@@ -182,6 +185,10 @@ impl Drop for Dirent {
                 }
             }
 
+            if SHARESPACE.config.read().HostInotify {
+                HOST_INOTIFY.Unwatch(self.ID());
+            }
+
             if SHARESPACE.config.read().EnableInotify {
                 let watches = self.Watches();
 
@@ -489,6 +496,21 @@ impl Dirent {
         self.children.lock().remove(name);
     }
 
+    // InvalidateChild drops the cached child which is changed outside of the
+    // sandbox, it is looked up again on the next walk. The mount points are
+    // kept.
+    pub fn InvalidateChild(&self, name: &str) {
+        let _cl = self.cacheMu.lock();
+        let mounted = match self.GetCacheChild(name) {
+            None => return,
+            Some(child) => child.main.lock().mounted,
+        };
+
+        if !mounted {
+            self.children.lock().remove(name);
+        }
+    }
+
     pub fn IsRoot(&self) -> bool {
         return self.main.lock().IsRoot();
     }
@@ -557,6 +579,8 @@ impl Dirent {
             );
         }
 
+        FanotifyDirEvent(self, false, FAN_CREATE);
+
         return Ok(file);
     }
 
@@ -597,6 +621,7 @@ impl Dirent {
                     false,
                 );
             }
+            FanotifyDirEvent(self, false, FAN_CREATE);
             self.children.lock().remove(oldname);
             self.children.lock().remove(newname);
             return Ok(());
@@ -640,6 +665,8 @@ impl Dirent {
                     false,
                 );
             }
+            FanotifyEvent(&target, FAN_ATTRIB);
+            FanotifyDirEvent(self, false, FAN_CREATE);
             return Ok(());
         });
     }
@@ -664,6 +691,10 @@ impl Dirent {
                 );
             }
 
+            if ret.is_ok() {
+                FanotifyDirEvent(self, true, FAN_CREATE);
+            }
+
             self.children.lock().remove(name);
             return ret;
         });
@@ -705,6 +736,8 @@ impl Dirent {
             );
         }
 
+        FanotifyDirEvent(self, false, FAN_CREATE);
+
         return Ok(childDir);
     }
 
@@ -728,6 +761,7 @@ impl Dirent {
                     false,
                 );
             }
+            FanotifyDirEvent(self, false, FAN_CREATE);
             return Ok(());
         });
     }
@@ -844,6 +878,8 @@ impl Dirent {
             InotifyRemoveChild(task, Some(child.Watches()), Some(self.Watches()), name)
         }
 
+        FanotifyRemoveChild(task, self, &child, false);
+
         // trigger inode destroy
         drop(child);
         drop(childInode);
@@ -896,6 +932,8 @@ impl Dirent {
             );
         }
 
+        FanotifyRemoveChild(task, self, &child, true);
+
         return Ok(());
     }
 
@@ -1033,6 +1071,9 @@ impl Dirent {
             );
         }
 
+        let isDir = newInode.StableAttr().IsDir();
+        FanotifyRename(task, &renamed, oldParent, newParent, isDir);
+
         renamed.DropExtendedReference();
         renamed.Watches().Destroy();
         renamed.flush();
@@ -1135,6 +1176,9 @@ impl Dirent {
             );
         }
 
+        let isDir = newInode.StableAttr().IsDir();
+        FanotifyRename(task, &renamed, parent, parent, isDir);
+
         renamed.DropExtendedReference();
         renamed.flush();
 
@@ -1205,8 +1249,11 @@ impl Dirent {
     // of potential events. The events may not actually propagate up to the user,
     // depending on the event masks. InotifyEvent automatically provides the name of
     // the current dirent as the subject of the event as required, and adds the
-    // IN_ISDIR flag for dirents that refer to directories.
+    // IN_ISDIR flag for dirents that refer to directories. The event is also
+    // reported to the fanotify listeners.
     pub fn InotifyEvent(&self, event: u32, cookie: u32, et: EventType) {
+        FanotifyEvent(self, event as u64);
+
         if SHARESPACE.config.read().EnableInotify {
             let _ = RENAME.read();
 
@@ -0,0 +1,1135 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use core::ops::Deref;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use lazy_static::lazy_static;
+
+use super::super::super::common::*;
+use super::super::super::linux::fanotify::*;
+use super::super::super::linux_def::*;
+use super::super::kernel::fd_table::FDFlags;
+use super::super::kernel::waiter::*;
+use super::super::task::*;
+use super::super::threadmgr::thread::*;
+use super::super::uid::*;
+use super::attr::*;
+use super::dentry::*;
+use super::dirent::*;
+use super::file::*;
+use super::flags::*;
+use super::mount::*;
+use crate::qlib::kernel::memmgr::vma::MMappable;
+use crate::qlib::mutex::*;
+
+lazy_static! {
+    pub static ref FANOTIFY: FanotifyMarks = FanotifyMarks::New();
+}
+
+// FANOTIFY_ON_CHILD_EVENTS are the events a directory mark with
+// FAN_EVENT_ON_CHILD receives for its children.
+pub const FANOTIFY_ON_CHILD_EVENTS: u64 = FAN_ACCESS
+    | FAN_MODIFY
+    | FAN_ATTRIB
+    | FAN_CLOSE
+    | FAN_OPEN
+    | FAN_OPEN_EXEC
+    | FANOTIFY_PERM_EVENTS;
+
+// FanotifyObjBytes returns the wire representation of a fanotify structure.
+pub fn FanotifyObjBytes<T: Sized + Copy>(obj: &T) -> &[u8] {
+    let addr = obj as *const _ as *const u8;
+    return unsafe { core::slice::from_raw_parts(addr, size_of::<T>()) };
+}
+
+// FanotifyEncodeEvent writes an event of buf.len() bytes, the metadata
+// followed by the fid record of the device and the inode number if fid is set.
+pub fn FanotifyEncodeEvent(buf: &mut [u8], mask: u64, fd: i32, pid: i32, fid: Option<(u64, u64)>) {
+    let metadata = FanotifyEventMetadata {
+        EventLen: buf.len() as u32,
+        Vers: FANOTIFY_METADATA_VERSION,
+        Reserved: 0,
+        MetadataLen: FAN_EVENT_METADATA_LEN as u16,
+        Mask: mask,
+        Fd: fd,
+        Pid: pid,
+    };
+    buf[..FAN_EVENT_METADATA_LEN].copy_from_slice(FanotifyObjBytes(&metadata));
+
+    if let Some((dev, ino)) = fid {
+        let info = FanotifyEventInfoFid {
+            Hdr: FanotifyEventInfoHeader {
+                InfoType: FAN_EVENT_INFO_TYPE_FID,
+                Pad: 0,
+                Len: FAN_EVENT_INFO_FID_LEN as u16,
+            },
+            Fsid: [dev as i32, (dev >> 32) as i32],
+            HandleBytes: 12,
+            HandleType: FILEID_INO64_GEN,
+            Ino: ino as u32,
+            InoHigh: (ino >> 32) as u32,
+            Gen: 0,
+        };
+        buf[FAN_EVENT_METADATA_LEN..FAN_EVENT_METADATA_LEN + FAN_EVENT_INFO_FID_LEN]
+            .copy_from_slice(FanotifyObjBytes(&info));
+    }
+}
+
+#[derive(Clone)]
+pub enum FanotifyMarkTarget {
+    // The dirent the mark was added through, it pins the inode.
+    Inode(Dirent),
+    // The id of the mount.
+    Mount(u64),
+    Filesystem(Arc<QMutex<MountSource>>),
+}
+
+impl FanotifyMarkTarget {
+    pub fn Same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Inode(a), Self::Inode(b)) => return a.Inode().ID() == b.Inode().ID(),
+            (Self::Mount(a), Self::Mount(b)) => return a == b,
+            (Self::Filesystem(a), Self::Filesystem(b)) => return Arc::ptr_eq(a, b),
+            _ => return false,
+        }
+    }
+
+    pub fn MarkType(&self) -> u32 {
+        match self {
+            Self::Inode(_) => return FAN_MARK_INODE,
+            Self::Mount(_) => return FAN_MARK_MOUNT,
+            Self::Filesystem(_) => return FAN_MARK_FILESYSTEM,
+        }
+    }
+}
+
+pub struct FanotifyMark {
+    pub group: Weak<FanotifyGroupInternal>,
+    pub groupId: u64,
+    pub target: FanotifyMarkTarget,
+    pub mask: u64,
+    pub ignoredMask: u64,
+
+    // flags is FAN_MARK_IGNORED_SURV_MODIFY if the ignored mask survives
+    // the modification of the object.
+    pub flags: u32,
+}
+
+// FanotifyMarks are the marks of all the fanotify listeners of the sandbox.
+pub struct FanotifyMarks {
+    pub marks: QMutex<Vec<FanotifyMark>>,
+
+    // count is the number of marks, the events are only matched when there
+    // is any.
+    pub count: AtomicUsize,
+}
+
+impl FanotifyMarks {
+    pub fn New() -> Self {
+        return Self {
+            marks: QMutex::new(Vec::new()),
+            count: AtomicUsize::new(0),
+        };
+    }
+
+    pub fn Active(&self) -> bool {
+        return self.count.load(Ordering::Relaxed) > 0;
+    }
+
+    pub fn AddMark(
+        &self,
+        group: &Arc<FanotifyGroupInternal>,
+        target: FanotifyMarkTarget,
+        mask: u64,
+        flags: u32,
+    ) -> Result<()> {
+        let mut marks = self.marks.lock();
+        for m in marks.iter_mut() {
+            if m.groupId == group.id && m.target.Same(&target) {
+                if flags & FAN_MARK_IGNORED_MASK != 0 {
+                    m.ignoredMask |= mask;
+                    if flags & FAN_MARK_IGNORED_SURV_MODIFY != 0 {
+                        m.flags |= FAN_MARK_IGNORED_SURV_MODIFY;
+                    }
+                } else {
+                    m.mask |= mask;
+                }
+
+                return Ok(());
+            }
+        }
+
+        if group.flags & FAN_UNLIMITED_MARKS == 0 {
+            let count = marks.iter().filter(|m| m.groupId == group.id).count();
+            if count >= FANOTIFY_DEFAULT_MAX_MARKS {
+                return Err(Error::SysError(SysErr::ENOSPC));
+            }
+        }
+
+        let mut mark = FanotifyMark {
+            group: Arc::downgrade(group),
+            groupId: group.id,
+            target: target,
+            mask: 0,
+            ignoredMask: 0,
+            flags: 0,
+        };
+
+        if flags & FAN_MARK_IGNORED_MASK != 0 {
+            mark.ignoredMask = mask;
+            mark.flags = flags & FAN_MARK_IGNORED_SURV_MODIFY;
+        } else {
+            mark.mask = mask;
+        }
+
+        marks.push(mark);
+        self.count.store(marks.len(), Ordering::Relaxed);
+        return Ok(());
+    }
+
+    pub fn RemoveMark(
+        &self,
+        groupId: u64,
+        target: &FanotifyMarkTarget,
+        mask: u64,
+        flags: u32,
+    ) -> Result<()> {
+        // the removed mark is dropped out of the lock as it may hold the
+        // last reference of a dirent
+        let _removed;
+        {
+            let mut marks = self.marks.lock();
+            let idx = match marks
+                .iter()
+                .position(|m| m.groupId == groupId && m.target.Same(target))
+            {
+                None => return Err(Error::SysError(SysErr::ENOENT)),
+                Some(idx) => idx,
+            };
+
+            let m = &mut marks[idx];
+            if flags & FAN_MARK_IGNORED_MASK != 0 {
+                m.ignoredMask &= !mask;
+            } else {
+                m.mask &= !mask;
+            }
+
+            if m.mask != 0 || m.ignoredMask != 0 {
+                return Ok(());
+            }
+
+            _removed = marks.remove(idx);
+            self.count.store(marks.len(), Ordering::Relaxed);
+        }
+
+        return Ok(());
+    }
+
+    // Flush removes the marks of the type of a listener.
+    pub fn Flush(&self, groupId: u64, markType: u32) {
+        let mut removed = Vec::new();
+        {
+            let mut marks = self.marks.lock();
+            let mut i = 0;
+            while i < marks.len() {
+                if marks[i].groupId == groupId && marks[i].target.MarkType() == markType {
+                    removed.push(marks.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+
+            self.count.store(marks.len(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn RemoveGroup(&self, groupId: u64) {
+        let mut removed = Vec::new();
+        {
+            let mut marks = self.marks.lock();
+            let mut i = 0;
+            while i < marks.len() {
+                if marks[i].groupId == groupId {
+                    removed.push(marks.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+
+            self.count.store(marks.len(), Ordering::Relaxed);
+        }
+    }
+
+    // Match returns the listeners interested in the event and the part of
+    // the mask each of them gets. The marks of the parent apply when
+    // onChild is set and they have FAN_EVENT_ON_CHILD.
+    pub fn Match(
+        &self,
+        task: &Task,
+        d: &Dirent,
+        onChild: bool,
+        isDir: bool,
+        mask: u64,
+    ) -> Vec<(Arc<FanotifyGroupInternal>, u64)> {
+        let inode = d.Inode();
+        let inodeId = inode.ID();
+        let parentId = if onChild {
+            match d.Parent() {
+                None => None,
+                Some(p) => Some(p.Inode().ID()),
+            }
+        } else {
+            None
+        };
+
+        // the mount and the filesystem of the object are only looked up
+        // when a mark needs them
+        let mut mountId = None;
+        let mut msrc = None;
+
+        // (group id, group, mask, ignored mask)
+        let mut hits: Vec<(u64, Weak<FanotifyGroupInternal>, u64, u64)> = Vec::new();
+        {
+            let mut marks = self.marks.lock();
+            for m in marks.iter_mut() {
+                let hit = match &m.target {
+                    FanotifyMarkTarget::Inode(t) => {
+                        let id = t.Inode().ID();
+                        if id == inodeId {
+                            true
+                        } else {
+                            parentId == Some(id) && m.mask & FAN_EVENT_ON_CHILD != 0
+                        }
+                    }
+                    FanotifyMarkTarget::Mount(id) => {
+                        if mountId.is_none() {
+                            mountId = Some(match task.mountNS.FindMount(d) {
+                                None => Mount::INVALID_MOUNT_ID,
+                                Some(mount) => mount.lock().Id,
+                            });
+                        }
+
+                        mountId == Some(*id)
+                    }
+                    FanotifyMarkTarget::Filesystem(ms) => {
+                        if msrc.is_none() {
+                            msrc = Some(inode.lock().MountSource.clone());
+                        }
+
+                        Arc::ptr_eq(ms, msrc.as_ref().unwrap())
+                    }
+                };
+
+                if !hit {
+                    continue;
+                }
+
+                if mask & FAN_MODIFY != 0 && m.flags & FAN_MARK_IGNORED_SURV_MODIFY == 0 {
+                    m.ignoredMask = 0;
+                }
+
+                // the directories only match the marks with FAN_ONDIR
+                let mut markMask = m.mask;
+                let mut ignoredMask = m.ignoredMask;
+                if isDir && markMask & FAN_ONDIR == 0 {
+                    markMask = 0;
+                }
+
+                if isDir && ignoredMask & FAN_ONDIR == 0 {
+                    ignoredMask = 0;
+                }
+
+                match hits.iter_mut().find(|h| h.0 == m.groupId) {
+                    Some(h) => {
+                        h.2 |= markMask;
+                        h.3 |= ignoredMask;
+                    }
+                    None => hits.push((m.groupId, m.group.clone(), markMask, ignoredMask)),
+                }
+            }
+        }
+
+        let mut ret = Vec::new();
+        for (_, group, markMask, ignoredMask) in hits {
+            let mask = mask & markMask & !ignoredMask & (FANOTIFY_EVENTS | FANOTIFY_PERM_EVENTS);
+            if mask == 0 {
+                continue;
+            }
+
+            match group.upgrade() {
+                None => (),
+                Some(g) => ret.push((g, mask)),
+            }
+        }
+
+        return ret;
+    }
+
+    pub fn Notify(&self, task: &Task, d: &Dirent, onChild: bool, isDir: bool, mask: u64) {
+        let mask = mask & FANOTIFY_EVENTS;
+        if mask == 0 {
+            return;
+        }
+
+        for (group, mask) in self.Match(task, d, onChild, isDir, mask) {
+            group.QueueEvent(FanotifyEventRec::New(
+                task,
+                d,
+                group.EventMask(mask, isDir),
+                None,
+            ));
+        }
+    }
+
+    // Permission queues the permission event to the listeners, one after the
+    // other, and waits for their decisions. The pre-content listeners decide
+    // first.
+    pub fn Permission(&self, task: &Task, d: &Dirent, mask: u64) -> Result<()> {
+        let isDir = d.Inode().StableAttr().IsDir();
+        let mut hits = self.Match(task, d, true, isDir, mask);
+        hits.retain(|h| h.1 & FANOTIFY_PERM_EVENTS != 0);
+        hits.sort_by(|a, b| b.0.Class().cmp(&a.0.Class()));
+
+        // a listener going away allows the event, so they are not kept alive
+        // while waiting
+        let hits: Vec<(Weak<FanotifyGroupInternal>, u64)> = hits
+            .into_iter()
+            .map(|(g, mask)| (Arc::downgrade(&g), mask))
+            .collect();
+
+        for (group, mask) in hits {
+            let perm = FanotifyPerm::default();
+            let general = task.blocker.generalEntry.clone();
+            perm.queue.EventRegister(task, &general, EVENT_IN);
+            defer!(perm.queue.EventUnregister(task, &general));
+
+            let queued = match group.upgrade() {
+                None => false,
+                Some(g) => g.QueueEvent(FanotifyEventRec::New(task, d, mask, Some(perm.clone()))),
+            };
+
+            // the event is allowed when the queue overflows
+            if !queued {
+                continue;
+            }
+
+            let response = perm.Wait(task, &group)?;
+            if response == FAN_DENY {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+// FanotifyPerm is the decision of a listener on a permission event.
+#[derive(Clone, Default)]
+pub struct FanotifyPerm {
+    pub response: Arc<QMutex<u32>>,
+    pub queue: Queue,
+}
+
+impl FanotifyPerm {
+    pub fn Complete(&self, response: u32) {
+        *self.response.lock() = response;
+        self.queue.Notify(EVENT_IN);
+    }
+
+    pub fn Response(&self) -> u32 {
+        return *self.response.lock();
+    }
+
+    pub fn Same(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.response, &other.response);
+    }
+
+    // Wait waits for the decision. A signal only cancels the event if the
+    // listener hasn't read it yet, after that only SIGKILL stops the wait and
+    // the event gets the response of a listener going away.
+    pub fn Wait(&self, task: &Task, group: &Weak<FanotifyGroupInternal>) -> Result<u32> {
+        loop {
+            let response = self.Response();
+            if response != 0 {
+                return Ok(response);
+            }
+
+            match task.blocker.BlockWithMonoTimer(true, None) {
+                Err(Error::ErrInterrupted) => {
+                    let cancelled = match group.upgrade() {
+                        None => false,
+                        Some(g) => g.Cancel(self),
+                    };
+
+                    if cancelled {
+                        return Err(Error::SysError(SysErr::ERESTARTSYS));
+                    }
+
+                    // the interrupt stays pending for the syscall return
+                    defer!(task.blocker.interruptSelf());
+                    loop {
+                        let response = self.Response();
+                        if response != 0 {
+                            return Ok(response);
+                        }
+
+                        // clear the interrupt before the check, a later
+                        // SIGKILL wakes the wait
+                        task.blocker.Interrupted(true);
+                        if task.Thread().Killed() {
+                            return Ok(FAN_ALLOW);
+                        }
+
+                        task.blocker.BlockGeneral().ok();
+                    }
+                }
+                Err(e) => return Err(e),
+                Ok(()) => (),
+            }
+        }
+    }
+}
+
+// FanotifyEventRec is a queued fanotify event.
+pub struct FanotifyEventRec {
+    pub mask: u64,
+
+    // dirent is the object of the event, it is opened for the listeners
+    // which don't report fids. It's None for the overflow event.
+    pub dirent: Option<Dirent>,
+
+    // fid is the device and the inode number of the object.
+    pub fid: (u64, u64),
+
+    // thread is the thread generating the event.
+    pub thread: Option<Thread>,
+
+    pub perm: Option<FanotifyPerm>,
+}
+
+impl FanotifyEventRec {
+    pub fn New(task: &Task, d: &Dirent, mask: u64, perm: Option<FanotifyPerm>) -> Self {
+        let attr = d.Inode().StableAttr();
+        return Self {
+            mask: mask,
+            dirent: Some(d.clone()),
+            fid: (attr.DeviceId, attr.InodeId),
+            thread: task.thread.clone(),
+            perm: perm,
+        };
+    }
+
+    pub fn Overflow() -> Self {
+        return Self {
+            mask: FAN_Q_OVERFLOW,
+            dirent: None,
+            fid: (0, 0),
+            thread: None,
+            perm: None,
+        };
+    }
+
+    pub fn Sizeof(&self, flags: u32) -> usize {
+        if flags & FAN_REPORT_FID != 0 && self.dirent.is_some() {
+            return FAN_EVENT_METADATA_LEN + FAN_EVENT_INFO_FID_LEN;
+        }
+
+        return FAN_EVENT_METADATA_LEN;
+    }
+}
+
+#[derive(Default)]
+pub struct FanotifyEvents {
+    pub events: VecDeque<FanotifyEventRec>,
+
+    // pending are the permission events read by the listener and waiting
+    // for its response, keyed by the fd of the event.
+    pub pending: BTreeMap<i32, FanotifyPerm>,
+
+    // overflow is set while the overflow event is queued.
+    pub overflow: bool,
+}
+
+pub struct FanotifyGroupInternal {
+    // id identifies the marks of the listener.
+    pub id: u64,
+
+    // flags are the fanotify_init(2) flags.
+    pub flags: u32,
+
+    // eventFFlags are the flags of the files opened for the events.
+    pub eventFFlags: u32,
+
+    pub queue: Queue,
+    pub events: QMutex<FanotifyEvents>,
+}
+
+impl Drop for FanotifyGroupInternal {
+    fn drop(&mut self) {
+        // the listener is gone, remove its marks and allow the events it
+        // hasn't decided on
+        FANOTIFY.RemoveGroup(self.id);
+
+        let mut evs = self.events.lock();
+        for ev in evs.events.drain(..) {
+            if let Some(perm) = ev.perm {
+                perm.Complete(FAN_ALLOW);
+            }
+        }
+
+        for (_, perm) in &evs.pending {
+            perm.Complete(FAN_ALLOW);
+        }
+
+        evs.pending.clear();
+    }
+}
+
+impl FanotifyGroupInternal {
+    pub fn Class(&self) -> u32 {
+        return self.flags & FAN_ALL_CLASS_BITS;
+    }
+
+    // EventMask returns the mask reported to the listener, FAN_ONDIR is only
+    // reported with the fids.
+    pub fn EventMask(&self, mask: u64, isDir: bool) -> u64 {
+        if isDir && self.flags & FAN_REPORT_FID != 0 {
+            return mask | FAN_ONDIR;
+        }
+
+        return mask;
+    }
+
+    // QueueEvent queues the event, it returns false if the event is dropped
+    // because the queue overflowed.
+    pub fn QueueEvent(&self, ev: FanotifyEventRec) -> bool {
+        {
+            let mut evs = self.events.lock();
+            if ev.perm.is_none() {
+                match evs.events.back_mut() {
+                    None => (),
+                    Some(last) => {
+                        // merge with the last event on the same object
+                        if last.perm.is_none()
+                            && last.dirent.is_some()
+                            && last.fid == ev.fid
+                            && last.thread == ev.thread
+                        {
+                            last.mask |= ev.mask;
+                            return true;
+                        }
+                    }
+                }
+            }
+
+            if self.flags & FAN_UNLIMITED_QUEUE == 0
+                && evs.events.len() >= FANOTIFY_DEFAULT_MAX_EVENTS
+            {
+                if !evs.overflow {
+                    evs.overflow = true;
+                    evs.events.push_back(FanotifyEventRec::Overflow());
+                }
+            } else {
+                evs.events.push_back(ev);
+                drop(evs);
+                self.queue.Notify(READABLE_EVENT);
+                return true;
+            }
+        }
+
+        self.queue.Notify(READABLE_EVENT);
+        return false;
+    }
+
+    // Cancel removes a permission event the listener hasn't read.
+    pub fn Cancel(&self, perm: &FanotifyPerm) -> bool {
+        let mut evs = self.events.lock();
+        let idx = evs.events.iter().position(|ev| match &ev.perm {
+            None => false,
+            Some(p) => p.Same(perm),
+        });
+
+        match idx {
+            None => return false,
+            Some(idx) => {
+                evs.events.remove(idx);
+                return true;
+            }
+        }
+    }
+
+    // EventFd opens the object of the event in the listener.
+    pub fn EventFd(&self, task: &Task, ev: &FanotifyEventRec) -> Result<i32> {
+        if self.flags & FAN_REPORT_FID != 0 {
+            return Ok(FAN_NOFD);
+        }
+
+        let d = match &ev.dirent {
+            None => return Ok(FAN_NOFD),
+            Some(d) => d.clone(),
+        };
+
+        let mut flags = FileFlags::FromFlags(self.eventFFlags);
+        flags.LargeFile = true;
+        flags.NoNotify = true;
+
+        let file = d.Inode().GetFile(task, &d, &flags)?;
+        return task.NewFDFrom(
+            0,
+            &file,
+            &FDFlags {
+                CloseOnExec: self.eventFFlags & Flags::O_CLOEXEC as u32 != 0,
+            },
+        );
+    }
+
+    pub fn Respond(&self, resp: &FanotifyResponse) -> Result<()> {
+        if resp.Fd < 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut response = resp.Response;
+        if response & FAN_AUDIT != 0 {
+            if self.flags & FAN_ENABLE_AUDIT == 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            response &= !FAN_AUDIT;
+        }
+
+        if response != FAN_ALLOW && response != FAN_DENY {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let perm = match self.events.lock().pending.remove(&resp.Fd) {
+            None => return Err(Error::SysError(SysErr::ENOENT)),
+            Some(p) => p,
+        };
+
+        perm.Complete(response);
+        return Ok(());
+    }
+}
+
+#[derive(Clone)]
+pub struct FanotifyFileOperations(pub Arc<FanotifyGroupInternal>);
+
+impl Deref for FanotifyFileOperations {
+    type Target = Arc<FanotifyGroupInternal>;
+
+    fn deref(&self) -> &Arc<FanotifyGroupInternal> {
+        &self.0
+    }
+}
+
+impl FanotifyFileOperations {
+    pub fn New(flags: u32, eventFFlags: u32) -> Self {
+        let internal = FanotifyGroupInternal {
+            id: NewUID(),
+            flags: flags,
+            eventFFlags: eventFFlags,
+            queue: Queue::default(),
+            events: QMutex::new(FanotifyEvents::default()),
+        };
+
+        return Self(Arc::new(internal));
+    }
+
+    pub fn Mark(&self, target: FanotifyMarkTarget, mask: u64, flags: u32) -> Result<()> {
+        if flags & FAN_MARK_ADD != 0 {
+            return FANOTIFY.AddMark(&self.0, target, mask, flags);
+        }
+
+        return FANOTIFY.RemoveMark(self.id, &target, mask, flags);
+    }
+
+    pub fn Flush(&self, markType: u32) {
+        FANOTIFY.Flush(self.id, markType);
+    }
+}
+
+impl Waitable for FanotifyFileOperations {
+    fn Readiness(&self, _task: &Task, mask: EventMask) -> EventMask {
+        let ready = if self.events.lock().events.len() > 0 {
+            READABLE_EVENT
+        } else {
+            0
+        };
+
+        return mask & ready;
+    }
+
+    fn EventRegister(&self, task: &Task, e: &WaitEntry, mask: EventMask) {
+        let queue = self.queue.clone();
+        queue.EventRegister(task, e, mask);
+    }
+
+    fn EventUnregister(&self, task: &Task, e: &WaitEntry) {
+        let queue = self.queue.clone();
+        queue.EventUnregister(task, e);
+    }
+}
+
+impl SockOperations for FanotifyFileOperations {}
+impl SpliceOperations for FanotifyFileOperations {}
+
+impl FileOperations for FanotifyFileOperations {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::FanotifyFileOperations;
+    }
+
+    fn Seekable(&self) -> bool {
+        return false;
+    }
+
+    fn Seek(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _whence: i32,
+        _current: i64,
+        _offset: i64,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ESPIPE));
+    }
+
+    fn ReadDir(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _offset: i64,
+        _serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ENOTDIR));
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let dsts = task.AdjustIOVecPermission(dsts, true, true)?;
+        let size = IoVec::NumBytes(&dsts);
+
+        if size < FAN_EVENT_METADATA_LEN {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let size = if size >= MemoryDef::HUGE_PAGE_SIZE as usize {
+            MemoryDef::HUGE_PAGE_SIZE as usize
+        } else {
+            size
+        };
+        let mut buf = DataBuff::New(size);
+
+        let pidns = task.Thread().PIDNamespace();
+        let mut writelen = 0;
+        loop {
+            // the events lock isn't held while the object is opened
+            let ev = match self.events.lock().events.pop_front() {
+                None => break,
+                Some(e) => e,
+            };
+
+            let len = ev.Sizeof(self.flags);
+            if size - writelen < len {
+                self.events.lock().events.push_front(ev);
+                if writelen > 0 {
+                    break;
+                }
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            if ev.dirent.is_none() {
+                self.events.lock().overflow = false;
+            }
+
+            let fd = match self.EventFd(task, &ev) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    // the event the listener can't see is denied
+                    if let Some(perm) = &ev.perm {
+                        perm.Complete(FAN_DENY);
+                    }
+
+                    if writelen > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+            };
+
+            let pid = match &ev.thread {
+                None => 0,
+                Some(t) => {
+                    if self.flags & FAN_REPORT_TID != 0 {
+                        pidns.IDOfTask(t)
+                    } else {
+                        pidns.IDOfThreadGroup(&t.ThreadGroup())
+                    }
+                }
+            };
+
+            let fid = if len > FAN_EVENT_METADATA_LEN {
+                Some(ev.fid)
+            } else {
+                None
+            };
+            FanotifyEncodeEvent(
+                &mut buf.buf[writelen..writelen + len],
+                ev.mask,
+                fd,
+                pid,
+                fid,
+            );
+
+            if let Some(perm) = ev.perm {
+                self.events.lock().pending.insert(fd, perm);
+            }
+
+            writelen += len;
+        }
+
+        if writelen == 0 {
+            return Err(Error::SysError(SysErr::EAGAIN));
+        }
+
+        task.CopyDataOutToIovs(&buf.buf[0..writelen], &dsts, false)?;
+        return Ok(writelen as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        srcs: &[IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        // a write carries one response
+        let len = size_of::<FanotifyResponse>();
+        if IoVec::NumBytes(srcs) < len {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut buf = DataBuff::New(len);
+        task.CopyDataInFromIovs(&mut buf.buf, srcs, false)?;
+        let resp =
+            unsafe { core::ptr::read_unaligned(buf.buf.as_ptr() as *const FanotifyResponse) };
+
+        self.Respond(&resp)?;
+        return Ok(len as i64);
+    }
+
+    fn Append(&self, task: &Task, f: &File, srcs: &[IoVec]) -> Result<(i64, i64)> {
+        let n = self.WriteAt(task, f, srcs, 0, false)?;
+        return Ok((n, 0));
+    }
+
+    fn Fsync(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        _syncType: SyncType,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    fn Flush(&self, _task: &Task, _f: &File) -> Result<()> {
+        return Ok(());
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        return f.Dirent.Inode().UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, task: &Task, _f: &File, _fd: i32, request: u64, val: u64) -> Result<u64> {
+        match request {
+            IoCtlCmd::FIONREAD => {
+                let evs = self.events.lock();
+                let mut size: u32 = 0;
+                for ev in evs.events.iter() {
+                    size += ev.Sizeof(self.flags) as u32;
+                }
+
+                task.CopyOutObj(&size, val)?;
+                return Ok(0);
+            }
+            _ => return Err(Error::SysError(SysErr::ENOTTY)),
+        }
+    }
+
+    fn IterateDir(
+        &self,
+        _task: &Task,
+        _d: &Dirent,
+        _dirCtx: &mut DirCtx,
+        _offset: i32,
+    ) -> (i32, Result<i64>) {
+        return (0, Err(Error::SysError(SysErr::ENOTDIR)));
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
+
+// FanotifyEvent reports an event on d, to the marks of d and the marks of
+// its parent directory with FAN_EVENT_ON_CHILD.
+pub fn FanotifyEvent(d: &Dirent, mask: u64) {
+    if !FANOTIFY.Active() {
+        return;
+    }
+
+    let task = Task::Current();
+    let isDir = d.Inode().StableAttr().IsDir();
+    let onChild = mask & FANOTIFY_ON_CHILD_EVENTS != 0;
+    FANOTIFY.Notify(task, d, onChild, isDir, mask);
+}
+
+// FanotifyDirEvent reports the creation, removal or move of an entry of the
+// directory dir. isDir tells whether the entry is a directory.
+pub fn FanotifyDirEvent(dir: &Dirent, isDir: bool, mask: u64) {
+    if !FANOTIFY.Active() {
+        return;
+    }
+
+    let task = Task::Current();
+    FANOTIFY.Notify(task, dir, false, isDir, mask);
+}
+
+// FanotifyRemoveChild reports the removal of child from the directory
+// parent, and the deletion of child when it was the last link.
+pub fn FanotifyRemoveChild(task: &Task, parent: &Dirent, child: &Dirent, isDir: bool) {
+    if !FANOTIFY.Active() {
+        return;
+    }
+
+    FANOTIFY.Notify(task, parent, false, isDir, FAN_DELETE);
+
+    let links = if isDir {
+        0
+    } else {
+        match child.Inode().UnstableAttr(task) {
+            Err(_) => 0,
+            Ok(attr) => attr.Links,
+        }
+    };
+
+    if links == 0 {
+        FANOTIFY.Notify(task, child, false, isDir, FAN_DELETE_SELF);
+    }
+}
+
+// FanotifyRename reports the move of renamed from the directory oldParent
+// to the directory newParent.
+pub fn FanotifyRename(
+    task: &Task,
+    renamed: &Dirent,
+    oldParent: &Dirent,
+    newParent: &Dirent,
+    isDir: bool,
+) {
+    if !FANOTIFY.Active() {
+        return;
+    }
+
+    FANOTIFY.Notify(task, oldParent, false, isDir, FAN_MOVED_FROM);
+    FANOTIFY.Notify(task, newParent, false, isDir, FAN_MOVED_TO);
+    FANOTIFY.Notify(task, renamed, false, isDir, FAN_MOVE_SELF);
+}
+
+// FanotifyPermission asks the listeners whether the access to the file is
+// allowed.
+pub fn FanotifyPermission(task: &Task, file: &File, mask: u64) -> Result<()> {
+    if !FANOTIFY.Active() {
+        return Ok(());
+    }
+
+    let flags = file.Flags();
+    if flags.NoNotify || flags.Path {
+        return Ok(());
+    }
+
+    return FANOTIFY.Permission(task, &file.Dirent, mask);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn U32(buf: &[u8], off: usize) -> u32 {
+        return u32::from_ne_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+    }
+
+    #[test]
+    fn test_FanotifyEncodeEvent() {
+        // the sizes of struct fanotify_event_metadata and of struct
+        // fanotify_event_info_fid with a FILEID_INO64_GEN handle
+        assert_eq!(FAN_EVENT_METADATA_LEN, 24);
+        assert_eq!(FAN_EVENT_INFO_FID_LEN, 32);
+
+        let mut buf = [0xff; FAN_EVENT_METADATA_LEN];
+        FanotifyEncodeEvent(&mut buf, FAN_OPEN, 5, 42, None);
+        assert_eq!(U32(&buf, 0), FAN_EVENT_METADATA_LEN as u32);
+        assert_eq!(buf[4], FANOTIFY_METADATA_VERSION);
+        assert_eq!(buf[5], 0);
+        assert_eq!(u16::from_ne_bytes([buf[6], buf[7]]), 24);
+        let mut mask = [0; 8];
+        mask.copy_from_slice(&buf[8..16]);
+        assert_eq!(u64::from_ne_bytes(mask), FAN_OPEN);
+        assert_eq!(U32(&buf, 16) as i32, 5);
+        assert_eq!(U32(&buf, 20) as i32, 42);
+    }
+
+    #[test]
+    fn test_FanotifyEncodeFid() {
+        let len = FAN_EVENT_METADATA_LEN + FAN_EVENT_INFO_FID_LEN;
+        let mut buf = [0xff; FAN_EVENT_METADATA_LEN + FAN_EVENT_INFO_FID_LEN];
+        let dev = 0x1_0000_0803;
+        let ino = 0x2_0000_0011;
+        FanotifyEncodeEvent(&mut buf, FAN_MODIFY, FAN_NOFD, 1, Some((dev, ino)));
+        assert_eq!(U32(&buf, 0), len as u32);
+        assert_eq!(U32(&buf, 16) as i32, FAN_NOFD);
+
+        let fid = &buf[FAN_EVENT_METADATA_LEN..];
+        assert_eq!(fid[0], FAN_EVENT_INFO_TYPE_FID);
+        assert_eq!(fid[1], 0);
+        assert_eq!(u16::from_ne_bytes([fid[2], fid[3]]), 32);
+        assert_eq!(U32(fid, 4), 0x803);
+        assert_eq!(U32(fid, 8), 1);
+        assert_eq!(U32(fid, 12), 12);
+        assert_eq!(U32(fid, 16) as i32, FILEID_INO64_GEN);
+        assert_eq!(U32(fid, 20), 0x11);
+        assert_eq!(U32(fid, 24), 2);
+        assert_eq!(U32(fid, 28), 0);
+    }
+}
@@ -33,6 +33,7 @@ use super::super::super::mem::block::*;
 use super::super::super::metric::*;
 use super::super::super::range::*;
 use super::super::kernel::kernel::GetKernel;
+use super::super::kernel::socket_store::SocketStats;
 use super::super::kernel::time::*;
 use super::super::kernel::waiter::qlock::*;
 use super::super::kernel::waiter::*;
@@ -54,6 +55,10 @@ use crate::qlib::kernel::fs::dev::proxyfile::ProxyFileOperations;
 use crate::qlib::kernel::fs::dev::random::RandomFileOperations;
 use crate::qlib::kernel::fs::dev::tty::TTYFileOperations;
 use crate::qlib::kernel::fs::dev::zero::ZeroFileOperations;
+use crate::qlib::kernel::fs::fanotify::FanotifyFileOperations;
+use crate::qlib::kernel::fs::fuse::dev::FuseDevFileOperations;
+use crate::qlib::kernel::fs::fuse::file::FuseFileOps;
+use crate::qlib::kernel::fs::image::file::ImageFileOps;
 use crate::qlib::kernel::fs::file_overlay::OverlayFileOperations;
 use crate::qlib::kernel::fs::fsutil::file::dynamic_dir_file_operations::DynamicDirFileOperations;
 use crate::qlib::kernel::fs::fsutil::file::readonly_file::*;
@@ -61,6 +66,7 @@ use crate::qlib::kernel::fs::fsutil::file::static_dir_file_operations::StaticDir
 use crate::qlib::kernel::fs::fsutil::file::NoReadWriteFile;
 use crate::qlib::kernel::fs::fsutil::file::StaticFile;
 use crate::qlib::kernel::fs::host::hostdirfops::HostDirFops;
+use crate::qlib::kernel::fs::inotify::EventType;
 use crate::qlib::kernel::fs::inotify::Inotify;
 use crate::qlib::kernel::fs::procfs::proc::RootProcFile;
 use crate::qlib::kernel::fs::procfs::seqfile::SeqFileOperations;
@@ -82,6 +88,9 @@ use crate::qlib::kernel::socket::hostinet::hostsocket::HostSocketOperations;
 use crate::qlib::kernel::socket::hostinet::socket::SocketOperations;
 use crate::qlib::kernel::socket::hostinet::uring_socket::UringSocketOperations;
 use crate::qlib::kernel::socket::unix::unix::UnixSocketOperations;
+use crate::qlib::kernel::socket::epsocket::netstack_socket::NetstackSocketOperations;
+use crate::qlib::kernel::socket::epsocket::netstack_raw::NetstackRawSocketOperations;
+use crate::qlib::kernel::socket::epsocket::netstack_tcp::NetstackTcpSocketOperations;
 
 use super::attr::*;
 use super::dirent::*;
@@ -300,13 +309,42 @@ pub enum FileOpsType {
     Writer,
     SocketOperations,
     UnixSocketOperations,
+    NetstackSocketOperations,
+    NetstackTcpSocketOperations,
+    NetstackRawSocketOperations,
     ReadonlyFileOperations,
     DynamicDirFileOperations,
     SignalOperation,
     InotifyFileOperations,
     ProxyFileOperations,
     NvFrontendFileOptions,
-    UvmFileOptions
+    UvmFileOptions,
+    FuseDevFileOperations,
+    FuseFileOps,
+    FanotifyFileOperations,
+    ImageFileOps,
+}
+
+impl FileOpsType {
+    pub fn IsSocket(&self) -> bool {
+        match self {
+            FileOpsType::SocketOperations
+            | FileOpsType::UnixSocketOperations
+            | FileOpsType::NetstackSocketOperations
+            | FileOpsType::NetstackTcpSocketOperations
+            | FileOpsType::NetstackRawSocketOperations => true,
+            _ => false,
+        }
+    }
+
+    // IsMessage returns whether a read returns a single message, which
+    // mustn't be merged with the next one by a blocking read.
+    pub fn IsMessage(&self) -> bool {
+        match self {
+            FileOpsType::FuseDevFileOperations => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -349,12 +387,26 @@ pub enum FileOps {
     UringSocketOperations(UringSocketOperations),
     TsotSocketOperations(TsotSocketOperations),
     UnixSocketOperations(UnixSocketOperations),
+    NetstackSocketOperations(NetstackSocketOperations),
+    NetstackTcpSocketOperations(NetstackTcpSocketOperations),
+    NetstackRawSocketOperations(NetstackRawSocketOperations),
     RootProcFile(RootProcFile),
     NvFrontendFileOptions(NvFrontendFileOptions),
-    UvmFileOptions(UvmFileOptions)
+    UvmFileOptions(UvmFileOptions),
+    FuseDevFileOperations(FuseDevFileOperations),
+    FuseFileOps(FuseFileOps),
+    FanotifyFileOperations(FanotifyFileOperations),
+    ImageFileOps(ImageFileOps),
 }
 
 impl FileOps {
+    pub fn FuseDevFileOperations(&self) -> Option<FuseDevFileOperations> {
+        match self {
+            Self::FuseDevFileOperations(inner) => Some(inner.clone()),
+            _ => None,
+        }
+    }
+
     pub fn TTYFileOps(&self) -> Option<TTYFileOps> {
         match self {
             Self::TTYFileOps(inner) => Some(inner.clone()),
@@ -472,6 +524,9 @@ pub struct FileInternal {
     pub offset: QLock<i64>,
 
     pub FileOp: FileOps,
+
+    // the traffic counters of a socket, see File::NewSocket
+    pub sockStats: Option<Arc<SocketStats>>,
 }
 
 #[derive(Clone)]
@@ -501,20 +556,27 @@ impl Drop for File {
     fn drop(&mut self) {
         //error!("File::Drop {}", Arc::strong_count(&self.0));
         if Arc::strong_count(&self.0) == 1 {
-            let fopsType = self.FileOp.FopsType();
-            if fopsType == FileOpsType::SocketOperations
-                || fopsType == FileOpsType::UnixSocketOperations
-            {
+            if self.FileOp.FopsType().IsSocket() {
                 GetKernel().sockets.DeleteSocket(self);
             }
 
-            // Drop BSD style locks.
             let inode = self.Dirent.Inode();
+            if self.Flags().Write {
+                let iops = inode.lock().InodeOp.HostInodeOp();
+                if let Some(iops) = iops {
+                    iops.CryptRelease();
+                }
+            }
+
+            // Drop BSD style and OFD locks.
             let lockCtx = inode.lock().LockCtx.clone();
             let task = Task::Current();
 
             let lockUniqueID = self.UniqueId();
             lockCtx.BSD.UnlockRegion(task, lockUniqueID, &Range::Max());
+            lockCtx
+                .Posix
+                .UnlockRegion(task, lockUniqueID, &Range::Max());
 
             // Only unregister if we are currently registered. There is nothing
             // to register if f.async is nil (this happens when async mode is
@@ -659,6 +721,25 @@ impl File {
             //offsetLock: QLock::default(),
             offset: QLock::New(0),
             FileOp: fops,
+            sockStats: None,
+        };
+
+        return File(Arc::new(f));
+    }
+
+    // NewSocket creates the file of a socket with its per-socket counters, the
+    // caller adds it to the kernel's SocketStore
+    pub fn NewSocket(dirent: &Dirent, flags: &FileFlags, fops: FileOps) -> Self {
+        let uid = NewUID();
+        let (family, stype, _) = fops.Type();
+        let inodeId = dirent.Inode().StableAttr().InodeId;
+        let f = FileInternal {
+            UniqueId: uid,
+            Dirent: dirent.clone(),
+            flags: QMutex::new((*flags, None)),
+            offset: QLock::New(0),
+            FileOp: fops,
+            sockStats: Some(Arc::new(SocketStats::New(uid, inodeId, family, stype))),
         };
 
         return File(Arc::new(f));
@@ -816,6 +897,7 @@ impl File {
             //offsetLock: QLock::default(),
             offset: QLock::New(0),
             FileOp: fops,
+            sockStats: None,
         }));
     }
 
@@ -833,6 +915,16 @@ impl File {
         return self.flags.lock().0;
     }
 
+    // InotifyEvent notifies the watches of the file dirent, except for the
+    // files opened for a fanotify listener.
+    pub fn InotifyEvent(&self, event: u32, cookie: u32, et: EventType) {
+        if self.Flags().NoNotify {
+            return;
+        }
+
+        self.Dirent.InotifyEvent(event, cookie, et);
+    }
+
     pub fn SetFlags(&self, task: &Task, newFlags: SettableFileFlags) {
         let mut f = self.flags.lock();
         f.0.Direct = newFlags.Direct;
@@ -901,6 +993,9 @@ impl File {
         } else {
             let blocking = self.Blocking();
             let n = fops.ReadAt(task, self, dsts, 0, blocking)?;
+            if fops.FopsType().IsSocket() {
+                GetKernel().sockets.AccountRecv(self, n);
+            }
             return Ok(n);
         }
     }
@@ -986,6 +1081,9 @@ impl File {
         } else {
             let blocking = self.Blocking();
             let n = fops.WriteAt(task, self, srcs, 0, blocking)?;
+            if fops.FopsType().IsSocket() {
+                GetKernel().sockets.AccountSend(self, n);
+            }
 
             return Ok(n);
         }
@@ -32,6 +32,10 @@ pub struct FileFlags {
     pub Truncate: bool,
     pub NoFollow: bool,
     pub Path: bool,
+
+    // NoNotify is set on the files opened for a fanotify listener, their
+    // accesses don't generate events.
+    pub NoNotify: bool,
 }
 
 impl FileFlags {
@@ -121,6 +121,24 @@ pub fn SeekWithDirCursor(
             }
             _ => return Err(Error::SysError(SysErr::EINVAL)),
         }
+    } else if whence == SeekWhence::SEEK_DATA || whence == SeekWhence::SEEK_HOLE {
+        match fileType {
+            InodeType::RegularFile | InodeType::BlockDevice => {
+                // Without sparse region information the whole file is data
+                // followed by the implicit hole at end of file.
+                let sz = inode.UnstableAttr(task)?.Size;
+                if offset < 0 || offset >= sz {
+                    return Err(Error::SysError(SysErr::ENXIO));
+                }
+
+                if whence == SeekWhence::SEEK_DATA {
+                    return Ok(offset);
+                }
+
+                return Ok(sz);
+            }
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        }
     }
 
     return Ok(current);
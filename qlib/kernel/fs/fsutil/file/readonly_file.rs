@@ -31,6 +31,9 @@ use super::*;
 use crate::qlib::kernel::fs::procfs::meminfo::MeminfoFileNode;
 use crate::qlib::kernel::fs::procfs::net::NetTCPReadonlyFileNode;
 use crate::qlib::kernel::fs::procfs::net::NetUDPReadonlyFileNode;
+use crate::qlib::kernel::fs::procfs::net::NetNetstatReadonlyFileNode;
+use crate::qlib::kernel::fs::procfs::net::NetSnmpReadonlyFileNode;
+use crate::qlib::kernel::fs::procfs::net::NetSockstatReadonlyFileNode;
 use crate::qlib::kernel::fs::procfs::net::NetUnixReadonlyFileNode;
 use crate::qlib::kernel::fs::procfs::task::auxvec::AUXVecReadonlyFileNode;
 use crate::qlib::kernel::fs::procfs::task::comm::CommReadonlyFileNode;
@@ -82,6 +85,9 @@ pub enum ReadonlyFileNode {
     NetTCPReadonlyFileNode(NetTCPReadonlyFileNode),
     NetUDPReadonlyFileNode(NetUDPReadonlyFileNode),
     NetUnixReadonlyFileNode(NetUnixReadonlyFileNode),
+    NetSnmpReadonlyFileNode(NetSnmpReadonlyFileNode),
+    NetNetstatReadonlyFileNode(NetNetstatReadonlyFileNode),
+    NetSockstatReadonlyFileNode(NetSockstatReadonlyFileNode),
     AUXVecReadonlyFileNode(AUXVecReadonlyFileNode),
     CommReadonlyFileNode(CommReadonlyFileNode),
     ExecArgReadonlyFileNode(ExecArgReadonlyFileNode),
@@ -34,6 +34,7 @@ use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
 use super::super::inode::*;
+use super::super::overlay::XATTR_OVERLAY_WHITEOUT_PREFIX;
 use super::file::*;
 
 pub struct InodeSimpleExtendedAttributesInternal {
@@ -48,6 +49,78 @@ impl Default for InodeSimpleExtendedAttributesInternal {
     }
 }
 
+impl InodeSimpleExtendedAttributesInternal {
+    pub fn Getxattr(&self, name: &str) -> Result<Vec<u8>> {
+        match self.xattrs.get(name) {
+            None => Err(Error::SysError(SysErr::ENOATTR)),
+            Some(s) => Ok(s.clone()),
+        }
+    }
+
+    // Setxattr stores the value with the Linux simple_xattr semantics: flags
+    // decide between create and replace, and the names of one inode must fit
+    // in a single listxattr(2) buffer. The overlay whiteouts are kept out of
+    // the limits, there is one per removed lower entry and they are never
+    // listed.
+    pub fn Setxattr(&mut self, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        let whiteout = name.starts_with(XATTR_OVERLAY_WHITEOUT_PREFIX);
+        if name.len() == 0 || (!whiteout && name.len() > Xattr::XATTR_NAME_MAX) {
+            return Err(Error::SysError(SysErr::ERANGE));
+        }
+
+        if value.len() > Xattr::XATTR_SIZE_MAX {
+            return Err(Error::SysError(SysErr::E2BIG));
+        }
+
+        let exist = self.xattrs.contains_key(name);
+        if flags & Xattr::XATTR_CREATE != 0 && exist {
+            return Err(Error::SysError(SysErr::EEXIST));
+        }
+
+        if flags & Xattr::XATTR_REPLACE != 0 && !exist {
+            return Err(Error::SysError(SysErr::ENOATTR));
+        }
+
+        if !exist && !whiteout && self.ListSize() + name.len() + 1 > Xattr::XATTR_LIST_MAX {
+            return Err(Error::SysError(SysErr::ENOSPC));
+        }
+
+        self.xattrs.insert(name.to_string(), value.to_vec());
+        return Ok(());
+    }
+
+    pub fn Listxattr(&self) -> Vec<String> {
+        let mut res = Vec::new();
+        for (name, _) in &self.xattrs {
+            res.push(name.clone());
+        }
+
+        return res;
+    }
+
+    pub fn Removexattr(&mut self, name: &str) -> Result<()> {
+        match self.xattrs.remove(name) {
+            None => return Err(Error::SysError(SysErr::ENOATTR)),
+            Some(_) => return Ok(()),
+        }
+    }
+
+    // ListSize returns the size of the NUL separated name list, without the
+    // overlay whiteouts.
+    pub fn ListSize(&self) -> usize {
+        let mut size = 0;
+        for (name, _) in &self.xattrs {
+            if name.starts_with(XATTR_OVERLAY_WHITEOUT_PREFIX) {
+                continue;
+            }
+
+            size += name.len() + 1;
+        }
+
+        return size;
+    }
+}
+
 pub struct InodeSimpleExtendedAttributes(pub QRwLock<InodeSimpleExtendedAttributesInternal>);
 
 impl Default for InodeSimpleExtendedAttributes {
@@ -66,31 +139,19 @@ impl Deref for InodeSimpleExtendedAttributes {
 
 impl InodeSimpleExtendedAttributes {
     pub fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
-        match self.read().xattrs.get(name) {
-            None => Err(Error::SysError(SysErr::ENOATTR)),
-            Some(s) => Ok(s.clone()),
-        }
+        return self.read().Getxattr(name);
     }
 
-    pub fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], _flags: u32) -> Result<()> {
-        self.write().xattrs.insert(name.to_string(), value.to_vec());
-        return Ok(());
+    pub fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        return self.write().Setxattr(name, value, flags);
     }
 
     pub fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
-        let mut res = Vec::new();
-        for (name, _) in &self.read().xattrs {
-            res.push(name.clone());
-        }
-
-        return Ok(res);
+        return Ok(self.read().Listxattr());
     }
 
     pub fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
-        match self.write().xattrs.remove(name) {
-            None => return Err(Error::SysError(SysErr::ENOATTR)),
-            Some(_) => return Ok(()),
-        }
+        return self.write().Removexattr(name);
     }
 }
 
@@ -133,6 +194,7 @@ impl InodeStaticFileGetter {
                 content: self.read().content.clone(),
             }
             .into(),
+            sockStats: None,
         })));
     }
 }
@@ -338,7 +400,14 @@ impl InodeDenyWriteChecker {
 pub struct InodeNotAllocatable {}
 
 impl InodeNotAllocatable {
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 }
@@ -346,7 +415,14 @@ impl InodeNotAllocatable {
 pub struct InodeNoopAllocate {}
 
 impl InodeNoopAllocate {
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 }
@@ -354,7 +430,14 @@ impl InodeNoopAllocate {
 pub struct InodeIsDirAllocate {}
 
 impl InodeIsDirAllocate {
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EISDIR));
     }
 }
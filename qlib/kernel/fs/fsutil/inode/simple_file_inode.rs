@@ -37,6 +37,9 @@ use super::super::super::mount::*;
 use crate::qlib::kernel::fs::procfs::filesystems::FileSystemData;
 use crate::qlib::kernel::fs::procfs::loadavg::LoadAvgData;
 use crate::qlib::kernel::fs::procfs::meminfo::MeminfoInode;
+use crate::qlib::kernel::fs::procfs::net::NetNetstat;
+use crate::qlib::kernel::fs::procfs::net::NetSnmp;
+use crate::qlib::kernel::fs::procfs::net::NetSockstat;
 use crate::qlib::kernel::fs::procfs::net::NetTCP;
 use crate::qlib::kernel::fs::procfs::net::NetUDP;
 use crate::qlib::kernel::fs::procfs::net::NetUnix;
@@ -78,6 +81,9 @@ pub enum SimpleFileImpl {
     NetTCP(NetTCP),
     NetUDP(NetUDP),
     NetUnix(NetUnix),
+    NetSnmp(NetSnmp),
+    NetNetstat(NetNetstat),
+    NetSockstat(NetSockstat),
     TaskStatData(TaskStatData),
     UptimeInode(UptimeInode),
     AUXVecSimpleFileTrait(AUXVecSimpleFileTrait),
@@ -192,7 +198,14 @@ impl InodeOperations for SimpleFileInode {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
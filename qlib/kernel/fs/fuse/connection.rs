@@ -0,0 +1,609 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::fuse::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::waiter::*;
+use super::super::super::task::*;
+
+// FUSE_MAX_TRANSFER is the largest READ or WRITE sent to the daemon.
+pub const FUSE_MAX_TRANSFER: u32 = FUSE_DEFAULT_MAX_PAGES * MemoryDef::PAGE_SIZE as u32;
+
+// FUSE_INIT_FLAGS are the FUSE_INIT flags offered to the daemon.
+pub const FUSE_INIT_FLAGS: u32 = FUSE_ASYNC_READ
+    | FUSE_BIG_WRITES
+    | FUSE_DO_READDIRPLUS
+    | FUSE_NO_OPEN_SUPPORT
+    | FUSE_NO_OPENDIR_SUPPORT;
+
+// FuseObjBytes returns the wire representation of a FUSE structure.
+pub fn FuseObjBytes<T: Sized + Copy>(obj: &T) -> &[u8] {
+    let addr = obj as *const _ as *const u8;
+    return unsafe { core::slice::from_raw_parts(addr, size_of::<T>()) };
+}
+
+// FuseParse reads a FUSE structure from the head of a reply.
+pub fn FuseParse<T: Sized + Copy>(buf: &[u8]) -> Result<T> {
+    if buf.len() < size_of::<T>() {
+        return Err(Error::SysError(SysErr::EIO));
+    }
+
+    let ret = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const T) };
+    return Ok(ret);
+}
+
+// FuseParsePartial reads a FUSE structure which older daemons send shorter,
+// the missing tail is left zero.
+pub fn FuseParsePartial<T: Sized + Copy + Default>(buf: &[u8]) -> T {
+    let mut ret = T::default();
+    let len = core::cmp::min(buf.len(), size_of::<T>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(buf.as_ptr(), &mut ret as *mut _ as *mut u8, len);
+    }
+
+    return ret;
+}
+
+// FusePayload is the body of a FUSE request.
+#[derive(Default)]
+pub struct FusePayload(pub Vec<u8>);
+
+impl FusePayload {
+    pub fn New() -> Self {
+        return Self(Vec::new());
+    }
+
+    pub fn Obj<T: Sized + Copy>(mut self, obj: &T) -> Self {
+        self.0.extend_from_slice(FuseObjBytes(obj));
+        return self;
+    }
+
+    // Name appends a null terminated name.
+    pub fn Name(mut self, name: &str) -> Self {
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        return self;
+    }
+
+    pub fn Data(mut self, data: &[u8]) -> Self {
+        self.0.extend_from_slice(data);
+        return self;
+    }
+}
+
+// FuseMountOpts are the options of a FUSE mount.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseMountOpts {
+    pub rootMode: u32,
+    pub userId: u32,
+    pub groupId: u32,
+    pub defaultPermissions: bool,
+    pub allowOther: bool,
+    pub maxRead: u32,
+}
+
+pub struct FuseRequest {
+    pub unique: u64,
+    pub data: Vec<u8>,
+}
+
+// FuseCall is a request the caller waits the reply of.
+#[derive(Clone, Default)]
+pub struct FuseCall {
+    pub reply: Arc<QMutex<Option<Result<Vec<u8>>>>>,
+    pub queue: Queue,
+}
+
+impl FuseCall {
+    pub fn Complete(&self, reply: Result<Vec<u8>>) {
+        *self.reply.lock() = Some(reply);
+        self.queue.Notify(EVENT_IN);
+    }
+
+    pub fn Take(&self) -> Option<Result<Vec<u8>>> {
+        return self.reply.lock().take();
+    }
+}
+
+pub struct FuseConnInternal {
+    // mounted is set once the connection serves a mount.
+    pub mounted: bool,
+
+    // connected is cleared when the connection is aborted, e.g. the daemon
+    // closes /dev/fuse.
+    pub connected: bool,
+
+    // initialized is set by the FUSE_INIT reply, the other requests wait for it.
+    pub initialized: bool,
+    pub initUnique: u64,
+
+    pub nextUnique: u64,
+
+    // requests are the requests not read by the daemon yet.
+    pub requests: VecDeque<FuseRequest>,
+
+    // pending are the requests waiting for a reply, the reply of a background
+    // request such as FUSE_RELEASE is dropped.
+    pub pending: BTreeMap<u64, Option<FuseCall>>,
+
+    pub opts: FuseMountOpts,
+    pub deviceId: u64,
+
+    // negotiated by FUSE_INIT.
+    pub minor: u32,
+    pub flags: u32,
+    pub maxWrite: u32,
+
+    // queue notifies the daemon of the new requests.
+    pub queue: Queue,
+
+    // initQueue wakes the requests waiting for FUSE_INIT.
+    pub initQueue: Queue,
+}
+
+impl FuseConnInternal {
+    // ProcessInit applies the FUSE_INIT reply, the connection is unusable if
+    // it returns false.
+    fn ProcessInit(&mut self, reply: &Result<Vec<u8>>) -> bool {
+        let data = match reply {
+            Err(e) => {
+                info!("fuse: FUSE_INIT fails with {:?}", e);
+                return false;
+            }
+            Ok(d) => d,
+        };
+
+        let out: FuseInitOut = FuseParsePartial(data);
+        if data.len() < 8 || out.Major != FUSE_KERNEL_VERSION {
+            info!(
+                "fuse: unsupported protocol version {}.{}",
+                out.Major, out.Minor
+            );
+            return false;
+        }
+
+        self.minor = out.Minor;
+        self.flags = out.Flags & FUSE_INIT_FLAGS;
+        self.maxWrite = if self.flags & FUSE_BIG_WRITES != 0 {
+            out.MaxWrite
+                .max(MemoryDef::PAGE_SIZE as u32)
+                .min(FUSE_MAX_TRANSFER)
+        } else {
+            MemoryDef::PAGE_SIZE as u32
+        };
+        self.initialized = true;
+        self.initQueue.Notify(EVENT_IN);
+        return true;
+    }
+}
+
+#[derive(Clone)]
+pub struct FuseConnection(Arc<QMutex<FuseConnInternal>>);
+
+impl Deref for FuseConnection {
+    type Target = Arc<QMutex<FuseConnInternal>>;
+
+    fn deref(&self) -> &Arc<QMutex<FuseConnInternal>> {
+        &self.0
+    }
+}
+
+impl FuseConnection {
+    pub fn New() -> Self {
+        let internal = FuseConnInternal {
+            mounted: false,
+            connected: true,
+            initialized: false,
+            initUnique: 0,
+            nextUnique: 1,
+            requests: VecDeque::new(),
+            pending: BTreeMap::new(),
+            opts: FuseMountOpts::default(),
+            deviceId: 0,
+            minor: 0,
+            flags: 0,
+            maxWrite: MemoryDef::PAGE_SIZE as u32,
+            queue: Queue::default(),
+            initQueue: Queue::default(),
+        };
+
+        return Self(Arc::new(QMutex::new(internal)));
+    }
+
+    // Mount attaches the connection to a new mount and sends FUSE_INIT. The
+    // daemon usually starts to read requests after mount(2) returns, so the
+    // reply is not waited for here.
+    pub fn Mount(&self, task: &Task, opts: &FuseMountOpts, deviceId: u64) -> Result<()> {
+        {
+            let mut c = self.lock();
+            if !c.connected {
+                return Err(Error::SysError(SysErr::ENOTCONN));
+            }
+
+            if c.mounted {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            c.mounted = true;
+            c.opts = *opts;
+            c.deviceId = deviceId;
+
+            // no other request is queued before FUSE_INIT
+            c.initUnique = c.nextUnique;
+        }
+
+        let init = FuseInitIn {
+            Major: FUSE_KERNEL_VERSION,
+            Minor: FUSE_KERNEL_MINOR_VERSION,
+            MaxReadahead: FUSE_MAX_TRANSFER,
+            Flags: FUSE_INIT_FLAGS,
+        };
+
+        let payload = FusePayload::New().Obj(&init);
+        self.Enqueue(Some(task), FUSE_INIT, 0, &payload.0, None)?;
+        return Ok(());
+    }
+
+    pub fn Opts(&self) -> FuseMountOpts {
+        return self.lock().opts;
+    }
+
+    pub fn DeviceId(&self) -> u64 {
+        return self.lock().deviceId;
+    }
+
+    pub fn MaxWrite(&self) -> u32 {
+        return self.lock().maxWrite;
+    }
+
+    pub fn MaxRead(&self) -> u32 {
+        let maxRead = self.lock().opts.maxRead;
+        return maxRead.clamp(MemoryDef::PAGE_SIZE as u32, FUSE_MAX_TRANSFER);
+    }
+
+    pub fn ReaddirPlus(&self) -> bool {
+        return self.lock().flags & FUSE_DO_READDIRPLUS != 0;
+    }
+
+    // Enqueue queues a request for the daemon and returns its unique id.
+    // FUSE_FORGET has no reply and a FUSE_INTERRUPT reply is not awaited, the
+    // other requests without a caller are background requests.
+    pub fn Enqueue(
+        &self,
+        task: Option<&Task>,
+        opcode: u32,
+        nodeId: u64,
+        payload: &[u8],
+        call: Option<FuseCall>,
+    ) -> Result<u64> {
+        let unique = {
+            let mut c = self.lock();
+            if !c.connected {
+                return Err(Error::SysError(SysErr::ENOTCONN));
+            }
+
+            let unique = c.nextUnique;
+            c.nextUnique += 1;
+            unique
+        };
+
+        let (uid, gid, pid) = match task {
+            None => (0, 0, 0),
+            Some(task) => {
+                let owner = task.FileOwner();
+                let pid = task.Thread().ThreadGroup().ID();
+                (owner.UID.0, owner.GID.0, pid as u32)
+            }
+        };
+
+        let header = FuseHeaderIn {
+            Len: (size_of::<FuseHeaderIn>() + payload.len()) as u32,
+            Opcode: opcode,
+            Unique: unique,
+            NodeID: nodeId,
+            UID: uid,
+            GID: gid,
+            PID: pid,
+            Padding: 0,
+        };
+
+        let mut data = Vec::with_capacity(header.Len as usize);
+        data.extend_from_slice(FuseObjBytes(&header));
+        data.extend_from_slice(payload);
+
+        let mut c = self.lock();
+        if !c.connected {
+            return Err(Error::SysError(SysErr::ENOTCONN));
+        }
+
+        if opcode != FUSE_FORGET && opcode != FUSE_INTERRUPT {
+            c.pending.insert(unique, call);
+        }
+
+        c.requests.push_back(FuseRequest {
+            unique: unique,
+            data: data,
+        });
+        c.queue.Notify(READABLE_EVENT);
+        return Ok(unique);
+    }
+
+    // Cancel drops a request which the daemon hasn't read yet.
+    pub fn Cancel(&self, unique: u64) -> bool {
+        let mut c = self.lock();
+        let idx = match c.requests.iter().position(|r| r.unique == unique) {
+            None => return false,
+            Some(idx) => idx,
+        };
+
+        c.requests.remove(idx);
+        c.pending.remove(&unique);
+        return true;
+    }
+
+    pub fn WaitInit(&self, task: &Task) -> Result<()> {
+        let queue = self.lock().initQueue.clone();
+        let general = task.blocker.generalEntry.clone();
+        queue.EventRegister(task, &general, EVENT_IN);
+        defer!(queue.EventUnregister(task, &general));
+
+        loop {
+            {
+                let c = self.lock();
+                if !c.connected {
+                    return Err(Error::SysError(SysErr::ENOTCONN));
+                }
+
+                if c.initialized {
+                    return Ok(());
+                }
+            }
+
+            match task.blocker.BlockWithMonoTimer(true, None) {
+                Err(Error::ErrInterrupted) => return Err(Error::SysError(SysErr::ERESTARTSYS)),
+                Err(e) => return Err(e),
+                Ok(()) => (),
+            }
+        }
+    }
+
+    // Call sends a request and waits for its reply. A signal cancels the
+    // request if the daemon hasn't read it yet and the syscall is restarted,
+    // otherwise the daemon gets a
+    // FUSE_INTERRUPT and the reply is still awaited, only SIGKILL abandons it.
+    pub fn Call(&self, task: &Task, opcode: u32, nodeId: u64, payload: &[u8]) -> Result<Vec<u8>> {
+        self.WaitInit(task)?;
+
+        let call = FuseCall::default();
+        let general = task.blocker.generalEntry.clone();
+        call.queue.EventRegister(task, &general, EVENT_IN);
+        defer!(call.queue.EventUnregister(task, &general));
+
+        let unique = self.Enqueue(Some(task), opcode, nodeId, payload, Some(call.clone()))?;
+        loop {
+            match call.Take() {
+                None => (),
+                Some(reply) => return reply,
+            }
+
+            match task.blocker.BlockWithMonoTimer(true, None) {
+                Err(Error::ErrInterrupted) => {
+                    // the daemon never saw the request, it is restarted
+                    if self.Cancel(unique) {
+                        return Err(Error::SysError(SysErr::ERESTARTSYS));
+                    }
+
+                    return self.WaitInterrupted(task, &call, unique);
+                }
+                Err(e) => return Err(e),
+                Ok(()) => (),
+            }
+        }
+    }
+
+    // WaitInterrupted waits for the reply of a request which the daemon has
+    // read when the caller got a signal.
+    fn WaitInterrupted(&self, task: &Task, call: &FuseCall, unique: u64) -> Result<Vec<u8>> {
+        let interrupt = FuseInterruptIn { Unique: unique };
+        self.Background(FUSE_INTERRUPT, 0, &FusePayload::New().Obj(&interrupt).0);
+
+        // the interrupt stays pending for the syscall return
+        defer!(task.blocker.interruptSelf());
+        loop {
+            match call.Take() {
+                None => (),
+                Some(reply) => return reply,
+            }
+
+            // clear the interrupt before the check, a later SIGKILL wakes the wait
+            task.blocker.Interrupted(true);
+            if task.Thread().Killed() {
+                self.Abandon(unique);
+                return Err(Error::SysError(SysErr::EINTR));
+            }
+
+            task.blocker.BlockGeneral().ok();
+        }
+    }
+
+    // Abandon forgets the caller of a request, its reply is dropped.
+    pub fn Abandon(&self, unique: u64) {
+        let mut c = self.lock();
+        if let Some(call) = c.pending.get_mut(&unique) {
+            *call = None;
+        }
+    }
+
+    // CallObj is Call for the requests replied with a single structure.
+    pub fn CallObj<T: Sized + Copy>(
+        &self,
+        task: &Task,
+        opcode: u32,
+        nodeId: u64,
+        payload: &[u8],
+    ) -> Result<T> {
+        let reply = self.Call(task, opcode, nodeId, payload)?;
+        return FuseParse(&reply);
+    }
+
+    pub fn Forget(&self, nodeId: u64, nlookup: u64) {
+        let forget = FuseForgetIn { Nlookup: nlookup };
+        let payload = FusePayload::New().Obj(&forget);
+        self.Enqueue(None, FUSE_FORGET, nodeId, &payload.0, None)
+            .ok();
+    }
+
+    // Background sends a request without waiting for the reply.
+    pub fn Background(&self, opcode: u32, nodeId: u64, payload: &[u8]) {
+        self.Enqueue(None, opcode, nodeId, payload, None).ok();
+    }
+
+    pub fn Readiness(&self, mask: EventMask) -> EventMask {
+        let c = self.lock();
+        let mut ready = WRITEABLE_EVENT;
+        if !c.connected {
+            ready |= EVENT_ERR;
+        } else if c.requests.len() > 0 {
+            ready |= READABLE_EVENT;
+        }
+
+        return mask & ready;
+    }
+
+    // ReadRequest hands the oldest request to the daemon, a read returns
+    // exactly one request.
+    pub fn ReadRequest(&self, task: &Task, dsts: &mut [IoVec]) -> Result<i64> {
+        let size = IoVec::NumBytes(dsts);
+        if size < FUSE_MIN_READ_BUFFER {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let req = {
+            let mut c = self.lock();
+            if !c.connected {
+                return Err(Error::SysError(SysErr::ENODEV));
+            }
+
+            if !c.mounted {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+
+            let req = match c.requests.pop_front() {
+                None => return Err(Error::SysError(SysErr::EWOULDBLOCK)),
+                Some(req) => req,
+            };
+
+            if req.data.len() > size {
+                drop(c);
+                self.Complete(req.unique, Err(Error::SysError(SysErr::EIO)));
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            req
+        };
+
+        match task.CopyDataOutToIovs(&req.data, dsts, false) {
+            Err(e) => {
+                self.Complete(req.unique, Err(Error::SysError(SysErr::EIO)));
+                return Err(e);
+            }
+            Ok(n) => return Ok(n as i64),
+        }
+    }
+
+    // WriteReply delivers a reply written by the daemon.
+    pub fn WriteReply(&self, buf: &[u8]) -> Result<()> {
+        let header: FuseHeaderOut = match FuseParse(buf) {
+            Err(_) => return Err(Error::SysError(SysErr::EINVAL)),
+            Ok(h) => h,
+        };
+
+        if header.Len as usize != buf.len() || header.Error > 0 || header.Error <= -4096 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        // notifications from the daemon are not supported, they are dropped
+        if header.Unique == 0 {
+            return Ok(());
+        }
+
+        let reply = if header.Error != 0 {
+            Err(Error::SysError(-header.Error))
+        } else {
+            Ok(buf[size_of::<FuseHeaderOut>()..].to_vec())
+        };
+
+        let mut c = self.lock();
+        if !c.connected {
+            return Err(Error::SysError(SysErr::ENODEV));
+        }
+
+        let call = match c.pending.remove(&header.Unique) {
+            None => return Err(Error::SysError(SysErr::ENOENT)),
+            Some(call) => call,
+        };
+
+        if header.Unique == c.initUnique && !c.initialized {
+            if !c.ProcessInit(&reply) {
+                drop(c);
+                self.Abort();
+            }
+
+            return Ok(());
+        }
+
+        drop(c);
+        if let Some(call) = call {
+            call.Complete(reply);
+        }
+
+        return Ok(());
+    }
+
+    fn Complete(&self, unique: u64, reply: Result<Vec<u8>>) {
+        let call = self.lock().pending.remove(&unique);
+        if let Some(Some(call)) = call {
+            call.Complete(reply);
+        }
+    }
+
+    // Abort fails all the requests and the requests to come with ENOTCONN.
+    pub fn Abort(&self) {
+        let (pending, queue, initQueue) = {
+            let mut c = self.lock();
+            c.connected = false;
+            c.requests.clear();
+            let pending = core::mem::replace(&mut c.pending, BTreeMap::new());
+            (pending, c.queue.clone(), c.initQueue.clone())
+        };
+
+        for (_, call) in pending {
+            if let Some(call) = call {
+                call.Complete(Err(Error::SysError(SysErr::ENOTCONN)));
+            }
+        }
+
+        queue.Notify(READABLE_EVENT | WRITEABLE_EVENT | EVENT_ERR);
+        initQueue.Notify(EVENT_IN);
+    }
+}
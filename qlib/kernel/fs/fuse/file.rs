@@ -0,0 +1,430 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::fuse::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::waiter::*;
+use super::super::super::task::*;
+use super::super::host::hostinodeop::*;
+use super::super::host::util;
+
+use super::super::attr::*;
+use super::super::dentry::*;
+use super::super::dirent::*;
+use super::super::file::*;
+use super::super::flags::*;
+use super::super::fsutil::file::*;
+use super::super::inode::*;
+use super::connection::*;
+use super::inode::*;
+
+pub struct FuseFileIntern {
+    pub iops: FuseInodeOps,
+    pub fh: u64,
+
+    // opened is false when the daemon doesn't implement open, there is no
+    // handle to release then.
+    pub opened: bool,
+    pub dir: bool,
+    pub flags: u32,
+    pub dirCursor: QMutex<String>,
+}
+
+impl Drop for FuseFileIntern {
+    fn drop(&mut self) {
+        if !self.opened {
+            return;
+        }
+
+        let release = FuseReleaseIn {
+            Fh: self.fh,
+            Flags: self.flags,
+            ReleaseFlags: 0,
+            LockOwner: 0,
+        };
+        let opcode = if self.dir {
+            FUSE_RELEASEDIR
+        } else {
+            FUSE_RELEASE
+        };
+        let payload = FusePayload::New().Obj(&release);
+        self.iops
+            .conn
+            .Background(opcode, self.iops.nodeId, &payload.0);
+    }
+}
+
+#[derive(Clone)]
+pub struct FuseFileOps(pub Arc<FuseFileIntern>);
+
+impl Deref for FuseFileOps {
+    type Target = Arc<FuseFileIntern>;
+
+    fn deref(&self) -> &Arc<FuseFileIntern> {
+        &self.0
+    }
+}
+
+impl FuseFileOps {
+    pub fn New(iops: &FuseInodeOps, fh: u64, opened: bool, dir: bool, flags: &FileFlags) -> Self {
+        let intern = FuseFileIntern {
+            iops: iops.clone(),
+            fh: fh,
+            opened: opened,
+            dir: dir,
+            flags: flags.ToLinux() as u32,
+            dirCursor: QMutex::new("".to_string()),
+        };
+
+        return Self(Arc::new(intern));
+    }
+
+    pub fn Conn(&self) -> &FuseConnection {
+        return &self.iops.conn;
+    }
+
+    pub fn Read(&self, task: &Task, offset: i64, size: usize) -> Result<Vec<u8>> {
+        let maxRead = self.Conn().MaxRead() as usize;
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let count = core::cmp::min(size - data.len(), maxRead);
+            let read = FuseReadIn {
+                Fh: self.fh,
+                Offset: (offset + data.len() as i64) as u64,
+                Size: count as u32,
+                Flags: self.flags,
+                ..Default::default()
+            };
+            let payload = FusePayload::New().Obj(&read);
+            let reply = self
+                .Conn()
+                .Call(task, FUSE_READ, self.iops.nodeId, &payload.0)?;
+            let n = core::cmp::min(reply.len(), count);
+            data.extend_from_slice(&reply[..n]);
+
+            // a short read is the end of the file
+            if n < count {
+                break;
+            }
+        }
+
+        return Ok(data);
+    }
+
+    pub fn Write(&self, task: &Task, offset: i64, buf: &[u8]) -> Result<usize> {
+        let maxWrite = self.Conn().MaxWrite() as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let count = core::cmp::min(buf.len() - done, maxWrite);
+            let write = FuseWriteIn {
+                Fh: self.fh,
+                Offset: (offset + done as i64) as u64,
+                Size: count as u32,
+                Flags: self.flags,
+                ..Default::default()
+            };
+            let payload = FusePayload::New()
+                .Obj(&write)
+                .Data(&buf[done..done + count]);
+            let res =
+                self.Conn()
+                    .CallObj::<FuseWriteOut>(task, FUSE_WRITE, self.iops.nodeId, &payload.0);
+            let out = match res {
+                Err(e) => {
+                    if done > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+                Ok(out) => out,
+            };
+
+            if out.Size as usize > count {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+
+            done += out.Size as usize;
+            if (out.Size as usize) < count {
+                break;
+            }
+        }
+
+        self.iops.InvalidateAttr();
+        return Ok(done);
+    }
+
+    // ReadDirAll lists the directory with READDIR or READDIRPLUS.
+    pub fn ReadDirAll(&self, task: &Task) -> Result<DentMap> {
+        let plus = self.Conn().ReaddirPlus();
+        let opcode = if plus { FUSE_READDIRPLUS } else { FUSE_READDIR };
+        let size = MemoryDef::PAGE_SIZE as u32;
+
+        let mut entries = BTreeMap::new();
+        let mut offset = 0;
+        loop {
+            let read = FuseReadIn {
+                Fh: self.fh,
+                Offset: offset,
+                Size: size,
+                ..Default::default()
+            };
+            let payload = FusePayload::New().Obj(&read);
+            let reply = self
+                .Conn()
+                .Call(task, opcode, self.iops.nodeId, &payload.0)?;
+            if reply.len() == 0 {
+                break;
+            }
+
+            let start = offset;
+            let mut pos = 0;
+            while pos < reply.len() {
+                let buf = &reply[pos..];
+                let (entry, dirent, headerLen) = if plus {
+                    let d: FuseDirentplus = FuseParse(buf)?;
+                    (Some(d.EntryOut), d.Dirent, size_of::<FuseDirentplus>())
+                } else {
+                    let d: FuseDirent = FuseParse(buf)?;
+                    (None, d, size_of::<FuseDirent>())
+                };
+
+                let nameLen = dirent.NameLen as usize;
+                if nameLen == 0 || headerLen + nameLen > buf.len() {
+                    return Err(Error::SysError(SysErr::EIO));
+                }
+
+                let name =
+                    String::from_utf8_lossy(&buf[headerLen..headerLen + nameLen]).to_string();
+
+                // the entries of READDIRPLUS carry a lookup reference, the
+                // dirents are cached by the later lookups instead
+                if let Some(entry) = entry {
+                    if entry.NodeID != 0 && name != "." && name != ".." {
+                        self.Conn().Forget(entry.NodeID, 1);
+                    }
+                }
+
+                let dentry = DentAttr {
+                    Type: util::InodeType(DType::ModeType(dirent.Type as u8) as u32),
+                    InodeId: dirent.Ino,
+                };
+                entries.insert(name, dentry);
+
+                offset = dirent.Off;
+                pos += FuseDirentAlign(headerLen + nameLen);
+            }
+
+            // a daemon whose offset doesn't move would be read forever
+            if offset == start {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+        }
+
+        return Ok(DentMap::New(entries));
+    }
+}
+
+impl Waitable for FuseFileOps {}
+
+impl SpliceOperations for FuseFileOps {}
+
+impl FileOperations for FuseFileOps {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::FuseFileOps;
+    }
+
+    fn Seekable(&self) -> bool {
+        return true;
+    }
+
+    fn Seek(&self, task: &Task, f: &File, whence: i32, current: i64, offset: i64) -> Result<i64> {
+        if !self.dir {
+            return SeekWithDirCursor(task, f, whence, current, offset, None);
+        }
+
+        let mut dirCursor = self.dirCursor.lock();
+        let mut cursor = "".to_string();
+        let newOffset = SeekWithDirCursor(task, f, whence, current, offset, Some(&mut cursor))?;
+        *dirCursor = cursor;
+        return Ok(newOffset);
+    }
+
+    fn ReadDir(
+        &self,
+        task: &Task,
+        file: &File,
+        offset: i64,
+        serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        if !self.dir {
+            return Err(Error::SysError(SysErr::ENOTDIR));
+        }
+
+        let root = task.Root();
+        let mut dirCursor = self.dirCursor.lock();
+
+        let mut dirCtx = DirCtx {
+            Serializer: serializer,
+            DirCursor: (*dirCursor).to_string(),
+        };
+
+        let res = DirentReadDir(task, &file.Dirent, self, &root, &mut dirCtx, offset)?;
+        *dirCursor = dirCtx.DirCursor;
+        return Ok(res);
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        if self.dir {
+            return Err(Error::SysError(SysErr::EISDIR));
+        }
+
+        let size = IoVec::NumBytes(dsts);
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let data = self.Read(task, offset, size)?;
+        let n = task.CopyDataOutToIovs(&data, dsts, true)?;
+        return Ok(n as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        srcs: &[IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        if self.dir {
+            return Err(Error::SysError(SysErr::EISDIR));
+        }
+
+        let size = IoVec::NumBytes(srcs);
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        let n = self.Write(task, offset, &buf.buf[..len])?;
+        return Ok(n as i64);
+    }
+
+    fn Append(&self, task: &Task, f: &File, srcs: &[IoVec]) -> Result<(i64, i64)> {
+        // the file may have been extended by the daemon or other users
+        self.iops.InvalidateAttr();
+        let size = self.iops.UnstableAttr(task)?.Size;
+        let n = self.WriteAt(task, f, srcs, size, false)?;
+        return Ok((n, size + n));
+    }
+
+    fn Fsync(
+        &self,
+        task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        syncType: SyncType,
+    ) -> Result<()> {
+        let opcode = if self.dir { FUSE_FSYNCDIR } else { FUSE_FSYNC };
+        let fsync = FuseFsyncIn {
+            Fh: self.fh,
+            FsyncFlags: if syncType == SyncType::SyncData {
+                FUSE_FSYNC_FDATASYNC
+            } else {
+                0
+            },
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&fsync);
+        match self.Conn().Call(task, opcode, self.iops.nodeId, &payload.0) {
+            Ok(_) | Err(Error::SysError(SysErr::ENOSYS)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn Flush(&self, task: &Task, _f: &File) -> Result<()> {
+        if self.dir || !self.opened {
+            return Ok(());
+        }
+
+        let flush = FuseFlushIn {
+            Fh: self.fh,
+            ..Default::default()
+        };
+        let payload = FusePayload::New().Obj(&flush);
+        match self
+            .Conn()
+            .Call(task, FUSE_FLUSH, self.iops.nodeId, &payload.0)
+        {
+            Ok(_) | Err(Error::SysError(SysErr::ENOSYS)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        let inode = f.Dirent.Inode();
+        return inode.UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, _task: &Task, _f: &File, _fd: i32, _request: u64, _val: u64) -> Result<u64> {
+        return Err(Error::SysError(SysErr::ENOTTY));
+    }
+
+    fn IterateDir(
+        &self,
+        task: &Task,
+        _d: &Dirent,
+        dirCtx: &mut DirCtx,
+        offset: i32,
+    ) -> (i32, Result<i64>) {
+        let dentryMap = match self.ReadDirAll(task) {
+            Err(e) => return (offset, Err(e)),
+            Ok(entries) => entries,
+        };
+
+        return match dirCtx.ReadDir(task, &dentryMap) {
+            Err(e) => (offset, Err(e)),
+            Ok(count) => (offset + count as i32, Ok(0)),
+        };
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
+
+impl SockOperations for FuseFileOps {}
@@ -0,0 +1,194 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::qlib::mutex::*;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+
+use super::super::super::super::auth::id::*;
+use super::super::super::super::common::*;
+use super::super::super::super::device::*;
+use super::super::super::super::linux::fuse::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::task::*;
+use super::super::filesystems::*;
+use super::super::host::fs::*;
+use super::super::inode::*;
+use super::super::mount::*;
+use super::connection::*;
+use super::inode::*;
+
+// The name of the fuse file system, "fuse.<subtype>" mounts it too.
+pub const FUSE_FS_NAME: &str = "fuse";
+
+// The file descriptor of the opened /dev/fuse serving the mount.
+pub const FD_KEY: &str = "fd";
+
+// The file type of the root node, in octal.
+pub const ROOT_MODE_KEY: &str = "rootmode";
+
+// The user and group of the mounting process.
+pub const USER_ID_KEY: &str = "user_id";
+pub const GROUP_ID_KEY: &str = "group_id";
+
+// The kernel checks the file permissions instead of the daemon.
+pub const DEFAULT_PERMISSIONS_KEY: &str = "default_permissions";
+
+// The users other than the mounting one may access the mount.
+pub const ALLOW_OTHER_KEY: &str = "allow_other";
+
+// Max size of a read request.
+pub const MAX_READ_KEY: &str = "max_read";
+
+fn ParseOption(key: &str, value: &str, radix: u32) -> Result<u32> {
+    return match u32::from_str_radix(value, radix) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            info!("{} value not parsable '{}={}': {:?}", key, key, value, e);
+            Err(Error::SysError(SysErr::EINVAL))
+        }
+    };
+}
+
+pub struct FuseFileSystem {}
+
+impl Filesystem for FuseFileSystem {
+    fn Name(&self) -> String {
+        return FUSE_FS_NAME.to_string();
+    }
+
+    fn Flags(&self) -> FilesystemFlags {
+        return 0;
+    }
+
+    fn Mount(
+        &mut self,
+        task: &Task,
+        _device: &str,
+        flags: &MountSourceFlags,
+        data: &str,
+    ) -> Result<Inode> {
+        info!("fuse file system mount ...");
+
+        let mut options = WhitelistFileSystem::GenericMountSourceOptions(data);
+
+        let fd = match options.remove(FD_KEY) {
+            None => {
+                info!("fuse mount without the fd option");
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(v) => ParseOption(FD_KEY, &v, 10)? as i32,
+        };
+
+        let rootMode = match options.remove(ROOT_MODE_KEY) {
+            None => {
+                info!("fuse mount without the rootmode option");
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(v) => ParseOption(ROOT_MODE_KEY, &v, 8)?,
+        };
+
+        let creds = task.Creds();
+        let userns = creds.lock().UserNamespace.clone();
+
+        let userId = match options.remove(USER_ID_KEY) {
+            None => {
+                info!("fuse mount without the user_id option");
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(v) => {
+                let kuid = userns.MapToKUID(UID(ParseOption(USER_ID_KEY, &v, 10)?));
+                if !kuid.Ok() {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+                kuid.0
+            }
+        };
+
+        let groupId = match options.remove(GROUP_ID_KEY) {
+            None => {
+                info!("fuse mount without the group_id option");
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(v) => {
+                let kgid = userns.MapToKGID(GID(ParseOption(GROUP_ID_KEY, &v, 10)?));
+                if !kgid.Ok() {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+                kgid.0
+            }
+        };
+
+        let maxRead = match options.remove(MAX_READ_KEY) {
+            None => FUSE_MAX_TRANSFER,
+            Some(v) => ParseOption(MAX_READ_KEY, &v, 10)?,
+        };
+
+        let opts = FuseMountOpts {
+            rootMode: rootMode,
+            userId: userId,
+            groupId: groupId,
+            defaultPermissions: options.remove(DEFAULT_PERMISSIONS_KEY).is_some(),
+            allowOther: options.remove(ALLOW_OTHER_KEY).is_some(),
+            maxRead: maxRead,
+        };
+
+        // Fail if the caller passed us more options than we can parse. They may be
+        // expecting us to set something we can't set.
+        if options.len() > 0 {
+            info!("unsupported mount options: {:?}", options);
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let file = task.GetFile(fd)?;
+        let fops = match file.FileOp.FuseDevFileOperations() {
+            None => {
+                info!("fuse mount fd {} is not /dev/fuse", fd);
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(fops) => fops,
+        };
+
+        let device = NewAnonDevice();
+        let deviceId = device.lock().DeviceID();
+        let conn = fops.conn.clone();
+        conn.Mount(task, &opts, deviceId)?;
+
+        let msrc = MountSource::NewCachingMountSource(self, flags);
+
+        // the root node is never looked up, so it holds no lookup reference
+        let root = FuseEntryOut {
+            NodeID: FUSE_ROOT_ID,
+            Attr: FuseAttr {
+                Ino: FUSE_ROOT_ID,
+                Mode: rootMode,
+                Nlink: 1,
+                UID: userId,
+                GID: groupId,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        return Ok(NewFuseInode(&conn, &Arc::new(QMutex::new(msrc)), &root, 0));
+    }
+
+    fn AllowUserMount(&self) -> bool {
+        return true;
+    }
+
+    fn AllowUserList(&self) -> bool {
+        return true;
+    }
+}
@@ -0,0 +1,694 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::qlib::mutex::*;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ops::Deref;
+
+use super::super::super::super::auth::id::*;
+use super::super::super::super::auth::*;
+use super::super::super::super::common::*;
+use super::super::super::super::device::*;
+use super::super::super::super::linux::fuse::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::time::*;
+use super::super::super::kernel::timer::*;
+use super::super::super::socket::unix::transport::unix::*;
+use super::super::super::task::*;
+use super::super::host::hostinodeop::*;
+use super::super::host::util;
+
+use super::super::attr::*;
+use super::super::dirent::*;
+use super::super::file::*;
+use super::super::flags::*;
+use super::super::inode::*;
+use super::super::mount::*;
+use super::connection::*;
+use super::file::*;
+
+// XATTR_SIZE_MAX is the largest extended attribute value Linux accepts.
+const XATTR_SIZE_MAX: u32 = 65536;
+
+pub fn FuseUnstableAttr(attr: &FuseAttr) -> UnstableAttr {
+    return UnstableAttr {
+        Size: attr.Size as i64,
+        Usage: attr.Blocks as i64 * 512,
+        Perms: FileMode(attr.Mode as u16).FilePerms(),
+        Owner: FileOwner {
+            UID: KUID(attr.UID),
+            GID: KGID(attr.GID),
+        },
+        AccessTime: Time::FromUnix(attr.Atime as i64, attr.AtimeNsec as i64),
+        ModificationTime: Time::FromUnix(attr.Mtime as i64, attr.MtimeNsec as i64),
+        StatusChangeTime: Time::FromUnix(attr.Ctime as i64, attr.CtimeNsec as i64),
+        Links: attr.Nlink as u64,
+    };
+}
+
+// FuseValidUntil converts a validity period from a reply to the monotonic
+// time the cached value expires.
+pub fn FuseValidUntil(secs: u64, nsecs: u32) -> i64 {
+    let valid = (secs as i64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(nsecs as i64);
+    return MonotonicNow().saturating_add(valid);
+}
+
+#[derive(Default)]
+pub struct FuseAttrCache {
+    pub attr: UnstableAttr,
+
+    // expiry is the monotonic time the cached attr is valid till.
+    pub expiry: i64,
+}
+
+pub struct FuseInodeIntern {
+    pub conn: FuseConnection,
+    pub nodeId: u64,
+    pub inodeType: InodeType,
+
+    // nlookup is the number of lookup references the inode holds on the
+    // node. They are returned to the daemon with FUSE_FORGET on release.
+    pub nlookup: u64,
+    pub attr: QMutex<FuseAttrCache>,
+}
+
+impl Drop for FuseInodeIntern {
+    fn drop(&mut self) {
+        if self.nlookup > 0 {
+            self.conn.Forget(self.nodeId, self.nlookup);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FuseInodeOps(pub Arc<FuseInodeIntern>);
+
+impl Deref for FuseInodeOps {
+    type Target = Arc<FuseInodeIntern>;
+
+    fn deref(&self) -> &Arc<FuseInodeIntern> {
+        &self.0
+    }
+}
+
+// NewFuseInode creates the inode of a node returned by the daemon in a
+// LOOKUP/CREATE style reply, taking over the lookup reference of the reply.
+pub fn NewFuseInode(
+    conn: &FuseConnection,
+    msrc: &Arc<QMutex<MountSource>>,
+    entry: &FuseEntryOut,
+    nlookup: u64,
+) -> Inode {
+    let attr = &entry.Attr;
+    let inodeType = util::InodeType(attr.Mode);
+    let iops = FuseInodeOps(Arc::new(FuseInodeIntern {
+        conn: conn.clone(),
+        nodeId: entry.NodeID,
+        inodeType: inodeType,
+        nlookup: nlookup,
+        attr: QMutex::new(FuseAttrCache {
+            attr: FuseUnstableAttr(attr),
+            expiry: FuseValidUntil(entry.AttrValid, entry.AttrValidNsec),
+        }),
+    }));
+
+    let (major, minor) = DecodeDeviceId(attr.Rdev);
+    let blockSize = if attr.BlkSize == 0 {
+        MemoryDef::PAGE_SIZE as i64
+    } else {
+        attr.BlkSize as i64
+    };
+
+    let stableAttr = StableAttr {
+        Type: inodeType,
+        DeviceId: conn.DeviceId(),
+        InodeId: attr.Ino,
+        BlockSize: blockSize,
+        DeviceFileMajor: major,
+        DeviceFileMinor: minor,
+    };
+
+    return Inode::New(iops.into(), msrc, &stableAttr);
+}
+
+impl FuseInodeOps {
+    pub fn Conn(&self) -> FuseConnection {
+        return self.conn.clone();
+    }
+
+    pub fn NodeId(&self) -> u64 {
+        return self.nodeId;
+    }
+
+    pub fn InvalidateAttr(&self) {
+        self.attr.lock().expiry = 0;
+    }
+
+    pub fn UpdateAttr(&self, out: &FuseAttrOut) -> UnstableAttr {
+        let mut cache = self.attr.lock();
+        cache.attr = FuseUnstableAttr(&out.Attr);
+        cache.expiry = FuseValidUntil(out.AttrValid, out.AttrValidNsec);
+        return cache.attr;
+    }
+
+    // NewEntry sends a request creating a node and checks the reply.
+    fn NewEntry(&self, task: &Task, opcode: u32, payload: &[u8]) -> Result<FuseEntryOut> {
+        let entry: FuseEntryOut = self.conn.CallObj(task, opcode, self.nodeId, payload)?;
+        if entry.NodeID == 0 {
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        self.InvalidateAttr();
+        return Ok(entry);
+    }
+
+    // MakeNode is for the nodes the caller doesn't keep an inode of, the
+    // next lookup gets a fresh reference.
+    fn MakeNode(&self, task: &Task, opcode: u32, payload: &[u8]) -> Result<()> {
+        let entry = self.NewEntry(task, opcode, payload)?;
+        self.conn.Forget(entry.NodeID, 1);
+        return Ok(());
+    }
+
+    fn SetAttr(&self, task: &Task, setattr: &FuseSetAttrIn) -> Result<()> {
+        let payload = FusePayload::New().Obj(setattr);
+        let out: FuseAttrOut = self
+            .conn
+            .CallObj(task, FUSE_SETATTR, self.nodeId, &payload.0)?;
+        self.UpdateAttr(&out);
+        return Ok(());
+    }
+
+    fn Unlink(&self, task: &Task, opcode: u32, name: &str) -> Result<()> {
+        let payload = FusePayload::New().Name(name);
+        self.conn.Call(task, opcode, self.nodeId, &payload.0)?;
+        self.InvalidateAttr();
+        return Ok(());
+    }
+
+    pub fn Open(&self, task: &Task, dirent: &Dirent, flags: &FileFlags) -> Result<File> {
+        let dir = self.inodeType == InodeType::Directory;
+        let opcode = if dir { FUSE_OPENDIR } else { FUSE_OPEN };
+
+        let linuxFlags = flags.ToLinux() as u32
+            & !((Flags::O_CREAT | Flags::O_EXCL | Flags::O_NOCTTY | Flags::O_TRUNC) as u32);
+        let open = FuseOpenIn {
+            Flags: linuxFlags,
+            Unused: 0,
+        };
+        let payload = FusePayload::New().Obj(&open);
+
+        // a daemon without open support serves the file without a handle
+        let (fh, opened) =
+            match self
+                .conn
+                .CallObj::<FuseOpenOut>(task, opcode, self.nodeId, &payload.0)
+            {
+                Ok(out) => (out.Fh, true),
+                Err(Error::SysError(SysErr::ENOSYS)) => (0, false),
+                Err(e) => return Err(e),
+            };
+
+        return Ok(self.NewFile(dirent, flags, fh, opened));
+    }
+
+    pub fn NewFile(&self, dirent: &Dirent, flags: &FileFlags, fh: u64, opened: bool) -> File {
+        let dir = self.inodeType == InodeType::Directory;
+        let fops = FuseFileOps::New(self, fh, opened, dir, flags);
+
+        let mut flags = *flags;
+        if !dir {
+            flags.Pread = true;
+            flags.PWrite = true;
+        }
+
+        return File::New(dirent, &flags, fops.into());
+    }
+}
+
+impl InodeOperations for FuseInodeOps {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn IopsType(&self) -> IopsType {
+        return IopsType::FuseInodeOps;
+    }
+
+    fn InodeType(&self) -> InodeType {
+        return self.inodeType;
+    }
+
+    fn InodeFileType(&self) -> InodeFileType {
+        return InodeFileType::Fuse;
+    }
+
+    fn WouldBlock(&self) -> bool {
+        return false;
+    }
+
+    fn Lookup(&self, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
+        let payload = FusePayload::New().Name(name);
+        let entry: FuseEntryOut = self
+            .conn
+            .CallObj(task, FUSE_LOOKUP, self.nodeId, &payload.0)?;
+
+        // a zero node id is a cacheable negative entry
+        if entry.NodeID == 0 {
+            return Err(Error::SysError(SysErr::ENOENT));
+        }
+
+        let msrc = dir.lock().MountSource.clone();
+        let inode = NewFuseInode(&self.conn, &msrc, &entry, 1);
+        return Ok(Dirent::New(&inode, name));
+    }
+
+    fn Create(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        name: &str,
+        flags: &FileFlags,
+        perm: &FilePermissions,
+    ) -> Result<File> {
+        let mode = ModeType::S_IFREG as u32 | perm.LinuxMode();
+        let create = FuseCreateIn {
+            Flags: flags.ToLinux() as u32 | Flags::O_CREAT as u32,
+            Mode: mode,
+            Umask: 0,
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&create).Name(name);
+        let msrc = dir.lock().MountSource.clone();
+
+        match self.conn.Call(task, FUSE_CREATE, self.nodeId, &payload.0) {
+            Ok(reply) => {
+                let out: FuseCreateOut = FuseParse(&reply)?;
+                if out.Entry.NodeID == 0 {
+                    return Err(Error::SysError(SysErr::EIO));
+                }
+
+                self.InvalidateAttr();
+                let inode = NewFuseInode(&self.conn, &msrc, &out.Entry, 1);
+                let dirent = Dirent::New(&inode, name);
+                let iops = inode.lock().InodeOp.FuseInodeOps().unwrap();
+                return Ok(iops.NewFile(&dirent, flags, out.Open.Fh, true));
+            }
+            Err(Error::SysError(SysErr::ENOSYS)) => (),
+            Err(e) => return Err(e),
+        }
+
+        // the daemon doesn't implement FUSE_CREATE, fall back to mknod and open
+        let mknod = FuseMknodIn {
+            Mode: mode,
+            Rdev: 0,
+            Umask: 0,
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&mknod).Name(name);
+        let entry = self.NewEntry(task, FUSE_MKNOD, &payload.0)?;
+        let inode = NewFuseInode(&self.conn, &msrc, &entry, 1);
+        let dirent = Dirent::New(&inode, name);
+        let iops = inode.lock().InodeOp.FuseInodeOps().unwrap();
+        return iops.Open(task, &dirent, flags);
+    }
+
+    fn CreateDirectory(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        name: &str,
+        perm: &FilePermissions,
+    ) -> Result<()> {
+        let mkdir = FuseMkdirIn {
+            Mode: perm.LinuxMode(),
+            Umask: 0,
+        };
+        let payload = FusePayload::New().Obj(&mkdir).Name(name);
+        return self.MakeNode(task, FUSE_MKDIR, &payload.0);
+    }
+
+    fn CreateLink(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        oldname: &str,
+        newname: &str,
+    ) -> Result<()> {
+        let payload = FusePayload::New().Name(newname).Name(oldname);
+        return self.MakeNode(task, FUSE_SYMLINK, &payload.0);
+    }
+
+    fn CreateHardLink(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        target: &Inode,
+        name: &str,
+    ) -> Result<()> {
+        let targetIops = match target.lock().InodeOp.FuseInodeOps() {
+            Some(iops) => iops,
+            None => return Err(Error::SysError(SysErr::EXDEV)),
+        };
+
+        if !Arc::ptr_eq(&*targetIops.conn, &*self.conn) {
+            return Err(Error::SysError(SysErr::EXDEV));
+        }
+
+        let link = FuseLinkIn {
+            Oldnodeid: targetIops.nodeId,
+        };
+        let payload = FusePayload::New().Obj(&link).Name(name);
+        self.MakeNode(task, FUSE_LINK, &payload.0)?;
+        targetIops.InvalidateAttr();
+        return Ok(());
+    }
+
+    fn CreateFifo(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        name: &str,
+        perm: &FilePermissions,
+    ) -> Result<()> {
+        let mknod = FuseMknodIn {
+            Mode: ModeType::S_IFIFO as u32 | perm.LinuxMode(),
+            Rdev: 0,
+            Umask: 0,
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&mknod).Name(name);
+        return self.MakeNode(task, FUSE_MKNOD, &payload.0);
+    }
+
+    fn Remove(&self, task: &Task, _dir: &mut Inode, name: &str) -> Result<()> {
+        return self.Unlink(task, FUSE_UNLINK, name);
+    }
+
+    fn RemoveDirectory(&self, task: &Task, _dir: &mut Inode, name: &str) -> Result<()> {
+        return self.Unlink(task, FUSE_RMDIR, name);
+    }
+
+    fn Rename(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        oldParent: &Inode,
+        oldname: &str,
+        newParent: &Inode,
+        newname: &str,
+        _replacement: bool,
+    ) -> Result<()> {
+        let oldIops = oldParent.lock().InodeOp.FuseInodeOps();
+        let newIops = newParent.lock().InodeOp.FuseInodeOps();
+        let (oldIops, newIops) = match (oldIops, newIops) {
+            (Some(o), Some(n)) => (o, n),
+            _ => return Err(Error::SysError(SysErr::EXDEV)),
+        };
+
+        if !Arc::ptr_eq(&*oldIops.conn, &*newIops.conn) {
+            return Err(Error::SysError(SysErr::EXDEV));
+        }
+
+        let rename = FuseRenameIn {
+            Newdir: newIops.nodeId,
+        };
+        let payload = FusePayload::New().Obj(&rename).Name(oldname).Name(newname);
+        self.conn
+            .Call(task, FUSE_RENAME, oldIops.nodeId, &payload.0)?;
+        oldIops.InvalidateAttr();
+        newIops.InvalidateAttr();
+        return Ok(());
+    }
+
+    fn Bind(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        _name: &str,
+        _data: &BoundEndpoint,
+        _perms: &FilePermissions,
+    ) -> Result<Dirent> {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    fn BoundEndpoint(&self, _task: &Task, _inode: &Inode, _path: &str) -> Option<BoundEndpoint> {
+        return None;
+    }
+
+    fn GetFile(
+        &self,
+        task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        match self.inodeType {
+            InodeType::RegularFile | InodeType::Directory => (),
+            // device nodes, fifos and sockets on a fuse mount aren't served by the daemon
+            _ => return Err(Error::SysError(SysErr::ENXIO)),
+        }
+
+        return self.Open(task, dirent, &flags);
+    }
+
+    fn UnstableAttr(&self, task: &Task) -> Result<UnstableAttr> {
+        {
+            let cache = self.attr.lock();
+            if MonotonicNow() < cache.expiry {
+                return Ok(cache.attr);
+            }
+        }
+
+        let getattr = FuseGetAttrIn::default();
+        let payload = FusePayload::New().Obj(&getattr);
+        let out: FuseAttrOut = self
+            .conn
+            .CallObj(task, FUSE_GETATTR, self.nodeId, &payload.0)?;
+        return Ok(self.UpdateAttr(&out));
+    }
+
+    fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
+        let task = Task::Current();
+        let getxattr = FuseGetxattrIn {
+            Size: XATTR_SIZE_MAX,
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&getxattr).Name(name);
+        match self.conn.Call(task, FUSE_GETXATTR, self.nodeId, &payload.0) {
+            Err(Error::SysError(SysErr::ENOSYS)) => Err(Error::SysError(SysErr::EOPNOTSUPP)),
+            r => r,
+        }
+    }
+
+    fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        let task = Task::Current();
+        let setxattr = FuseSetxattrIn {
+            Size: value.len() as u32,
+            Flags: flags,
+        };
+        let payload = FusePayload::New().Obj(&setxattr).Name(name).Data(value);
+        match self.conn.Call(task, FUSE_SETXATTR, self.nodeId, &payload.0) {
+            Err(Error::SysError(SysErr::ENOSYS)) => Err(Error::SysError(SysErr::EOPNOTSUPP)),
+            Err(e) => Err(e),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
+        let task = Task::Current();
+        let listxattr = FuseGetxattrIn {
+            Size: XATTR_SIZE_MAX,
+            Padding: 0,
+        };
+        let payload = FusePayload::New().Obj(&listxattr);
+        let reply = match self
+            .conn
+            .Call(task, FUSE_LISTXATTR, self.nodeId, &payload.0)
+        {
+            Err(Error::SysError(SysErr::ENOSYS)) => {
+                return Err(Error::SysError(SysErr::EOPNOTSUPP))
+            }
+            r => r?,
+        };
+
+        let names = reply
+            .split(|c| *c == 0)
+            .filter(|name| name.len() > 0)
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect();
+        return Ok(names);
+    }
+
+    fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
+        let task = Task::Current();
+        let payload = FusePayload::New().Name(name);
+        match self
+            .conn
+            .Call(task, FUSE_REMOVEXATTR, self.nodeId, &payload.0)
+        {
+            Err(Error::SysError(SysErr::ENOSYS)) => Err(Error::SysError(SysErr::EOPNOTSUPP)),
+            Err(e) => Err(e),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
+        let opts = self.conn.Opts();
+
+        // without allow_other only the mounting user may access the mount
+        if !opts.allowOther {
+            let creds = task.Creds();
+            let creds = creds.lock();
+            if creds.EffectiveKUID.0 != opts.userId || creds.EffectiveKGID.0 != opts.groupId {
+                return Ok(false);
+            }
+        }
+
+        // otherwise the permission checks are left to the daemon
+        if !opts.defaultPermissions {
+            return Ok(true);
+        }
+
+        return ContextCanAccessFile(task, inode, reqPerms);
+    }
+
+    fn SetPermissions(&self, task: &Task, _dir: &mut Inode, f: FilePermissions) -> bool {
+        let setattr = FuseSetAttrIn {
+            Valid: FATTR_MODE,
+            Mode: f.LinuxMode(),
+            ..Default::default()
+        };
+        return self.SetAttr(task, &setattr).is_ok();
+    }
+
+    fn SetOwner(&self, task: &Task, _dir: &mut Inode, owner: &FileOwner) -> Result<()> {
+        let mut setattr = FuseSetAttrIn::default();
+        if owner.UID.Ok() {
+            setattr.Valid |= FATTR_UID;
+            setattr.UID = owner.UID.0;
+        }
+
+        if owner.GID.Ok() {
+            setattr.Valid |= FATTR_GID;
+            setattr.GID = owner.GID.0;
+        }
+
+        if setattr.Valid == 0 {
+            return Ok(());
+        }
+
+        return self.SetAttr(task, &setattr);
+    }
+
+    fn SetTimestamps(&self, task: &Task, _dir: &mut Inode, ts: &InterTimeSpec) -> Result<()> {
+        let mut setattr = FuseSetAttrIn::default();
+        if !ts.ATimeOmit {
+            setattr.Valid |= FATTR_ATIME;
+            if ts.ATimeSetSystemTime {
+                setattr.Valid |= FATTR_ATIME_NOW;
+            } else {
+                let (s, ns) = ts.ATime.Unix();
+                setattr.Atime = s as u64;
+                setattr.AtimeNsec = ns as u32;
+            }
+        }
+
+        if !ts.MTimeOmit {
+            setattr.Valid |= FATTR_MTIME;
+            if ts.MTimeSetSystemTime {
+                setattr.Valid |= FATTR_MTIME_NOW;
+            } else {
+                let (s, ns) = ts.MTime.Unix();
+                setattr.Mtime = s as u64;
+                setattr.MtimeNsec = ns as u32;
+            }
+        }
+
+        if setattr.Valid == 0 {
+            return Ok(());
+        }
+
+        return self.SetAttr(task, &setattr);
+    }
+
+    fn Truncate(&self, task: &Task, _dir: &mut Inode, size: i64) -> Result<()> {
+        let setattr = FuseSetAttrIn {
+            Valid: FATTR_SIZE,
+            Size: size as u64,
+            ..Default::default()
+        };
+        return self.SetAttr(task, &setattr);
+    }
+
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    fn ReadLink(&self, task: &Task, _dir: &Inode) -> Result<String> {
+        if self.inodeType != InodeType::Symlink {
+            return Err(Error::SysError(SysErr::ENOLINK));
+        }
+
+        let reply = self.conn.Call(task, FUSE_READLINK, self.nodeId, &[])?;
+        return Ok(String::from_utf8_lossy(&reply).to_string());
+    }
+
+    fn GetLink(&self, _task: &Task, _dir: &Inode) -> Result<Dirent> {
+        if self.inodeType != InodeType::Symlink {
+            return Err(Error::SysError(SysErr::ENOLINK));
+        }
+
+        return Err(Error::ErrResolveViaReadlink);
+    }
+
+    fn AddLink(&self, _task: &Task) {}
+
+    fn DropLink(&self, _task: &Task) {}
+
+    fn IsVirtual(&self) -> bool {
+        return false;
+    }
+
+    fn Sync(&self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn StatFS(&self, task: &Task) -> Result<FsInfo> {
+        let out: FuseStatfsOut = self.conn.CallObj(task, FUSE_STATFS, self.nodeId, &[])?;
+        return Ok(FsInfo {
+            Type: FUSE_SUPER_MAGIC,
+            TotalBlocks: out.St.Blocks,
+            FreeBlocks: out.St.Bfree,
+            TotalFiles: out.St.Files,
+            FreeFiles: out.St.Ffree,
+        });
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
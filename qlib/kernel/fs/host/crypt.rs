@@ -0,0 +1,932 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes256};
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::super::super::super::addr::*;
+use super::super::super::super::common::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::fd::*;
+use super::super::super::Kernel::HostSpace;
+use super::super::super::PAGE_MGR;
+use super::super::attr::*;
+use super::util::*;
+
+// CRYPT_KEY_SIZE is the size of the master key of an encrypted volume and of
+// the per-file keys, the two AES-256 keys of XTS.
+pub const CRYPT_KEY_SIZE: usize = 64;
+pub const CRYPT_NONCE_SIZE: usize = 16;
+
+// CRYPT_UNIT_SIZE is the data unit of the file content encryption, the unit
+// index is the XTS tweak.
+pub const CRYPT_UNIT_SIZE: usize = 4096;
+
+// CRYPT_XATTR keeps the nonce of an encrypted file or directory, the root
+// directory of an encrypted volume keeps the key descriptor after its nonce.
+pub const CRYPT_XATTR: &str = "user.quark.crypt";
+
+// CRYPT_NAME_MAX is the longest name whose encoded ciphertext fits in the 255
+// bytes of a host name, CRYPT_LINK_MAX is the longest symlink target whose
+// encoded ciphertext fits in the ReadLinkAt buffer.
+pub const CRYPT_NAME_MAX: usize = 176;
+pub const CRYPT_LINK_MAX: usize = 752;
+
+const AES_BLOCK_SIZE: usize = 16;
+const NAME_TWEAK: u64 = 0;
+const LINK_TWEAK: u64 = 1;
+const LINK_NONCE: &[u8; CRYPT_NONCE_SIZE] = b"quark.crypt.link";
+const DESCRIPTOR_NONCE: &[u8; CRYPT_NONCE_SIZE] = b"quark.crypt.desc";
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+lazy_static! {
+    // CRYPT_VOLUMES maps the host device and inode of the root directory of
+    // an encrypted volume to its policy, so that the policy is found again
+    // when the directory is looked up after being evicted from the dirent
+    // cache.
+    pub static ref CRYPT_VOLUMES: QMutex<BTreeMap<(u64, u64), CryptPolicy>> =
+        QMutex::new(BTreeMap::new());
+}
+
+// VolumePolicy returns the policy of the encrypted volume rooted at the host
+// directory.
+pub fn VolumePolicy(sattr: &StableAttr) -> Option<CryptPolicy> {
+    let volumes = CRYPT_VOLUMES.lock();
+    if volumes.len() == 0 {
+        return None;
+    }
+
+    return volumes.get(&(sattr.DeviceId, sattr.InodeId)).cloned();
+}
+
+// Base64Encode encodes with the url alphabet and without padding, so the
+// output is a valid file name.
+fn Base64Encode(data: &[u8]) -> String {
+    let mut ret = String::with_capacity((data.len() * 4 + 2) / 3);
+    for chunk in data.chunks(3) {
+        let mut v: u32 = 0;
+        for i in 0..3 {
+            v <<= 8;
+            if i < chunk.len() {
+                v |= chunk[i] as u32;
+            }
+        }
+
+        for i in 0..chunk.len() + 1 {
+            ret.push(BASE64_CHARS[((v >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+
+    return ret;
+}
+
+fn Base64Decode(s: &str) -> Option<Vec<u8>> {
+    let mut ret = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+
+        let mut v: u32 = 0;
+        for i in 0..4 {
+            v <<= 6;
+            if i < chunk.len() {
+                let c = chunk[i];
+                let d = match c {
+                    b'A'..=b'Z' => c - b'A',
+                    b'a'..=b'z' => c - b'a' + 26,
+                    b'0'..=b'9' => c - b'0' + 52,
+                    b'-' => 62,
+                    b'_' => 63,
+                    _ => return None,
+                };
+                v |= d as u32;
+            }
+        }
+
+        for i in 0..chunk.len() - 1 {
+            ret.push((v >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    return Some(ret);
+}
+
+// AesCipher is the AES block cipher, a 16 bytes key is AES-128 and a 32 bytes
+// key is AES-256.
+#[derive(Clone)]
+pub enum AesCipher {
+    Aes128(Aes128),
+    Aes256(Aes256),
+}
+
+impl AesCipher {
+    pub fn New(key: &[u8]) -> Self {
+        if key.len() == 16 {
+            return Self::Aes128(Aes128::new(GenericArray::from_slice(key)));
+        }
+
+        return Self::Aes256(Aes256::new(GenericArray::from_slice(key)));
+    }
+
+    pub fn EncryptBlock(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Self::Aes128(c) => c.encrypt_block(block),
+            Self::Aes256(c) => c.encrypt_block(block),
+        }
+    }
+
+    pub fn DecryptBlock(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Self::Aes128(c) => c.decrypt_block(block),
+            Self::Aes256(c) => c.decrypt_block(block),
+        }
+    }
+}
+
+// DeriveKey derives a key from the master key the way fscrypt v1 does, by
+// encrypting the master key with the nonce as the AES-128 key.
+pub fn DeriveKey(
+    master: &[u8; CRYPT_KEY_SIZE],
+    nonce: &[u8; CRYPT_NONCE_SIZE],
+) -> [u8; CRYPT_KEY_SIZE] {
+    let cipher = AesCipher::New(nonce);
+    let mut key = *master;
+    for block in key.chunks_mut(AES_BLOCK_SIZE) {
+        cipher.EncryptBlock(block);
+    }
+
+    return key;
+}
+
+// XtsCipher is AES-256-XTS. A unit shorter than the AES block can't steal
+// ciphertext, it is xored with the encrypted tweak instead.
+#[derive(Clone)]
+pub struct XtsCipher {
+    data: AesCipher,
+    tweak: AesCipher,
+}
+
+impl XtsCipher {
+    pub fn New(key: &[u8; CRYPT_KEY_SIZE]) -> Self {
+        return Self {
+            data: AesCipher::New(&key[..32]),
+            tweak: AesCipher::New(&key[32..]),
+        };
+    }
+
+    fn Tweak(&self, unit: u64) -> [u8; AES_BLOCK_SIZE] {
+        let mut t = [0; AES_BLOCK_SIZE];
+        t[..8].copy_from_slice(&unit.to_le_bytes());
+        self.tweak.EncryptBlock(&mut t);
+        return t;
+    }
+
+    // NextTweak multiplies the tweak by x in GF(2^128)
+    fn NextTweak(t: &mut [u8; AES_BLOCK_SIZE]) {
+        let carry = t[15] >> 7;
+        for i in (1..AES_BLOCK_SIZE).rev() {
+            t[i] = (t[i] << 1) | (t[i - 1] >> 7);
+        }
+        t[0] = (t[0] << 1) ^ (0x87 * carry);
+    }
+
+    fn EncryptBlock(&self, t: &[u8; AES_BLOCK_SIZE], block: &mut [u8]) {
+        for i in 0..AES_BLOCK_SIZE {
+            block[i] ^= t[i];
+        }
+        self.data.EncryptBlock(block);
+        for i in 0..AES_BLOCK_SIZE {
+            block[i] ^= t[i];
+        }
+    }
+
+    fn DecryptBlock(&self, t: &[u8; AES_BLOCK_SIZE], block: &mut [u8]) {
+        for i in 0..AES_BLOCK_SIZE {
+            block[i] ^= t[i];
+        }
+        self.data.DecryptBlock(block);
+        for i in 0..AES_BLOCK_SIZE {
+            block[i] ^= t[i];
+        }
+    }
+
+    fn XorStream(&self, unit: u64, buf: &mut [u8]) {
+        let mut t = self.Tweak(unit);
+        self.data.EncryptBlock(&mut t);
+        for i in 0..buf.len() {
+            buf[i] ^= t[i];
+        }
+    }
+
+    pub fn Encrypt(&self, unit: u64, buf: &mut [u8]) {
+        if buf.len() < AES_BLOCK_SIZE {
+            return self.XorStream(unit, buf);
+        }
+
+        let tail = buf.len() % AES_BLOCK_SIZE;
+        let mut full = buf.len() / AES_BLOCK_SIZE;
+        if tail != 0 {
+            full -= 1;
+        }
+
+        let mut t = self.Tweak(unit);
+        for i in 0..full {
+            self.EncryptBlock(&t, &mut buf[i * AES_BLOCK_SIZE..(i + 1) * AES_BLOCK_SIZE]);
+            Self::NextTweak(&mut t);
+        }
+
+        if tail != 0 {
+            // ciphertext stealing: the partial block takes the head of the
+            // ciphertext of the last full block, which is encrypted again
+            // with the partial block and its tail
+            let last = full * AES_BLOCK_SIZE;
+            self.EncryptBlock(&t, &mut buf[last..last + AES_BLOCK_SIZE]);
+            Self::NextTweak(&mut t);
+
+            let mut b = [0; AES_BLOCK_SIZE];
+            b[..tail].copy_from_slice(&buf[last + AES_BLOCK_SIZE..]);
+            b[tail..].copy_from_slice(&buf[last + tail..last + AES_BLOCK_SIZE]);
+            self.EncryptBlock(&t, &mut b);
+
+            let (head, rest) = buf[last..].split_at_mut(AES_BLOCK_SIZE);
+            rest.copy_from_slice(&head[..tail]);
+            head.copy_from_slice(&b);
+        }
+    }
+
+    pub fn Decrypt(&self, unit: u64, buf: &mut [u8]) {
+        if buf.len() < AES_BLOCK_SIZE {
+            return self.XorStream(unit, buf);
+        }
+
+        let tail = buf.len() % AES_BLOCK_SIZE;
+        let mut full = buf.len() / AES_BLOCK_SIZE;
+        if tail != 0 {
+            full -= 1;
+        }
+
+        let mut t = self.Tweak(unit);
+        for i in 0..full {
+            self.DecryptBlock(&t, &mut buf[i * AES_BLOCK_SIZE..(i + 1) * AES_BLOCK_SIZE]);
+            Self::NextTweak(&mut t);
+        }
+
+        if tail != 0 {
+            let last = full * AES_BLOCK_SIZE;
+            let mut next = t;
+            Self::NextTweak(&mut next);
+            self.DecryptBlock(&next, &mut buf[last..last + AES_BLOCK_SIZE]);
+
+            let mut b = [0; AES_BLOCK_SIZE];
+            b[..tail].copy_from_slice(&buf[last + AES_BLOCK_SIZE..]);
+            b[tail..].copy_from_slice(&buf[last + tail..last + AES_BLOCK_SIZE]);
+            self.DecryptBlock(&t, &mut b);
+
+            let (head, rest) = buf[last..].split_at_mut(AES_BLOCK_SIZE);
+            rest.copy_from_slice(&head[..tail]);
+            head.copy_from_slice(&b);
+        }
+    }
+}
+
+// NewNonce returns a random nonce for a new file or directory.
+pub fn NewNonce() -> Result<[u8; CRYPT_NONCE_SIZE]> {
+    let mut nonce = [0; CRYPT_NONCE_SIZE];
+    let ret = HostSpace::GetRandom(&mut nonce[0] as *mut _ as u64, nonce.len() as u64, 0);
+    if ret < 0 {
+        return Err(Error::SysError(-ret as i32));
+    }
+
+    return Ok(nonce);
+}
+
+// Nonce returns the nonce in the xattr of the host file or directory. A new
+// one without a nonce gets a random nonce.
+fn Nonce(fd: i32, new: bool) -> Result<[u8; CRYPT_NONCE_SIZE]> {
+    match Getxattr(fd, CRYPT_XATTR) {
+        Ok(v) => {
+            if v.len() < CRYPT_NONCE_SIZE {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+
+            let mut nonce = [0; CRYPT_NONCE_SIZE];
+            nonce.copy_from_slice(&v[..CRYPT_NONCE_SIZE]);
+            return Ok(nonce);
+        }
+        Err(Error::SysError(SysErr::ENODATA)) if new => {
+            let nonce = NewNonce()?;
+            Setxattr(fd, CRYPT_XATTR, &nonce, 0)?;
+            return Ok(nonce);
+        }
+        Err(Error::SysError(SysErr::ENODATA)) => {
+            return Err(Error::SysError(SysErr::ENOKEY));
+        }
+        Err(e) => return Err(e),
+    }
+}
+
+fn EncryptString(cipher: &XtsCipher, s: &str, tweak: u64) -> String {
+    // padded with NULs to the AES block size, like fscrypt
+    let len = (s.len() + AES_BLOCK_SIZE - 1) / AES_BLOCK_SIZE * AES_BLOCK_SIZE;
+    let mut buf = s.as_bytes().to_vec();
+    buf.resize(len, 0);
+    cipher.Encrypt(tweak, &mut buf);
+    return Base64Encode(&buf);
+}
+
+fn DecryptString(cipher: &XtsCipher, s: &str, tweak: u64) -> Option<String> {
+    let mut buf = Base64Decode(s)?;
+    if buf.len() == 0 || buf.len() % AES_BLOCK_SIZE != 0 {
+        return None;
+    }
+
+    cipher.Decrypt(tweak, &mut buf);
+    while buf.last() == Some(&0) {
+        buf.pop();
+    }
+
+    return String::from_utf8(buf).ok();
+}
+
+pub struct CryptPolicyIntern {
+    pub key: [u8; CRYPT_KEY_SIZE],
+    pub links: XtsCipher,
+}
+
+// CryptPolicy is the key of an encrypted volume. The file contents and the
+// names in a directory are encrypted with keys derived from it and the nonce
+// of the file or the directory. The symlink targets, which can't keep a
+// nonce, are encrypted with a volume-wide key.
+#[derive(Clone)]
+pub struct CryptPolicy(Arc<CryptPolicyIntern>);
+
+impl PartialEq for CryptPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl Eq for CryptPolicy {}
+
+impl CryptPolicy {
+    pub fn New(key: &[u8]) -> Result<Self> {
+        if key.len() != CRYPT_KEY_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut master = [0; CRYPT_KEY_SIZE];
+        master.copy_from_slice(key);
+        let links = XtsCipher::New(&DeriveKey(&master, LINK_NONCE));
+
+        return Ok(Self(Arc::new(CryptPolicyIntern {
+            key: master,
+            links: links,
+        })));
+    }
+
+    // Descriptor identifies the key, it is kept on the volume root so that a
+    // wrong key is rejected instead of garbling the volume.
+    pub fn Descriptor(&self) -> Vec<u8> {
+        let cipher = XtsCipher::New(&DeriveKey(&self.0.key, DESCRIPTOR_NONCE));
+        let mut d = [0; AES_BLOCK_SIZE];
+        cipher.Encrypt(0, &mut d);
+        return d[..8].to_vec();
+    }
+
+    pub fn EncryptLink(&self, target: &str) -> Result<String> {
+        if target.len() > CRYPT_LINK_MAX {
+            return Err(Error::SysError(SysErr::ENAMETOOLONG));
+        }
+
+        return Ok(EncryptString(&self.0.links, target, LINK_TWEAK));
+    }
+
+    pub fn DecryptLink(&self, target: &str) -> Result<String> {
+        match DecryptString(&self.0.links, target, LINK_TWEAK) {
+            None => return Err(Error::SysError(SysErr::EIO)),
+            Some(target) => return Ok(target),
+        }
+    }
+
+    pub fn Names(&self, nonce: &[u8; CRYPT_NONCE_SIZE]) -> CryptNames {
+        return CryptNames(XtsCipher::New(&DeriveKey(&self.0.key, nonce)));
+    }
+
+    // DirNames returns the cipher of the names in the host directory, keyed
+    // with the nonce in its xattr. A new directory gets a random nonce.
+    pub fn DirNames(&self, fd: i32, new: bool) -> Result<CryptNames> {
+        let nonce = Nonce(fd, new)?;
+        return Ok(self.Names(&nonce));
+    }
+
+    // FileCipher returns the cipher of the content of the host file, keyed
+    // with the nonce in its xattr. An empty file without a nonce is a new one
+    // and gets a random nonce.
+    pub fn FileCipher(&self, fd: i32, size: i64) -> Result<XtsCipher> {
+        let nonce = Nonce(fd, size == 0)?;
+        return Ok(XtsCipher::New(&DeriveKey(&self.0.key, &nonce)));
+    }
+}
+
+// CryptNames is the cipher of the names in a directory of an encrypted
+// volume. A name is encrypted deterministically, so that it is looked up by
+// its ciphertext, but the same name in two directories has two ciphertexts.
+#[derive(Clone)]
+pub struct CryptNames(XtsCipher);
+
+impl CryptNames {
+    pub fn EncryptName(&self, name: &str) -> Result<String> {
+        if name == "." || name == ".." {
+            return Ok(name.to_string());
+        }
+
+        if name.len() > CRYPT_NAME_MAX {
+            return Err(Error::SysError(SysErr::ENAMETOOLONG));
+        }
+
+        return Ok(EncryptString(&self.0, name, NAME_TWEAK));
+    }
+
+    // DecryptName returns None for a host name which is not the ciphertext of
+    // a name, it is hidden from the volume.
+    pub fn DecryptName(&self, name: &str) -> Option<String> {
+        if name == "." || name == ".." {
+            return Some(name.to_string());
+        }
+
+        return DecryptString(&self.0, name, NAME_TWEAK);
+    }
+}
+
+// CryptFile is the plaintext cache of an encrypted host file. The data ops
+// and the mmap of the file go to the cache, which is kept in guest pages so
+// that the plaintext never reaches the host. A chunk of the cache is
+// decrypted from the backing file on its first access, the dirty chunks are
+// encrypted back on sync, on close and on the release of the file. A page
+// which is not in the cache is zeros. Every unit is encrypted, the file grows
+// through Resize, so the backing file has no holes.
+pub struct CryptFile {
+    pub cipher: XtsCipher,
+    pub size: i64,
+    pub pages: BTreeMap<u64, u64>,
+    pub filled: BTreeSet<u64>,
+    pub dirty: BTreeSet<u64>,
+}
+
+impl Drop for CryptFile {
+    fn drop(&mut self) {
+        for (_, page) in &self.pages {
+            Self::FreePage(*page);
+        }
+    }
+}
+
+impl CryptFile {
+    pub fn New(policy: &CryptPolicy, fd: i32, size: i64) -> Result<Self> {
+        let cipher = policy.FileCipher(fd, size)?;
+        return Ok(Self {
+            cipher: cipher,
+            size: size,
+            pages: BTreeMap::new(),
+            filled: BTreeSet::new(),
+            dirty: BTreeSet::new(),
+        });
+    }
+
+    pub fn Size(&self) -> i64 {
+        return self.size;
+    }
+
+    // FreePage drops the reference of the cache, the page stays with the
+    // mappings which still have it.
+    fn FreePage(page: u64) {
+        if PAGE_MGR.Deref(page).unwrap() == 0 {
+            PAGE_MGR.FreePage(page).unwrap();
+        }
+    }
+
+    // Page returns the cache page of the offset, a missing one is allocated
+    // zeroed.
+    fn Page(&mut self, offset: u64) -> Result<u64> {
+        let offset = offset & !PAGE_MASK;
+        if let Some(page) = self.pages.get(&offset) {
+            return Ok(*page);
+        }
+
+        let page = PAGE_MGR.AllocPage(true)?;
+        self.pages.insert(offset, page);
+        return Ok(page);
+    }
+
+    // CopyOut copies the cache at the offset to the buffer.
+    fn CopyOut(&self, offset: u64, buf: &mut [u8]) {
+        let mut count = 0;
+        while count < buf.len() {
+            let addr = offset + count as u64;
+            let pageOffset = (addr & PAGE_MASK) as usize;
+            let len = core::cmp::min(PAGE_SIZE as usize - pageOffset, buf.len() - count);
+            let dst = &mut buf[count..count + len];
+            match self.pages.get(&(addr & !PAGE_MASK)) {
+                None => dst.fill(0),
+                Some(page) => {
+                    let src = unsafe {
+                        core::slice::from_raw_parts((*page + pageOffset as u64) as *const u8, len)
+                    };
+                    dst.copy_from_slice(src);
+                }
+            }
+            count += len;
+        }
+    }
+
+    // CopyIn copies the buffer to the cache at the offset.
+    fn CopyIn(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        let mut count = 0;
+        while count < buf.len() {
+            let addr = offset + count as u64;
+            let pageOffset = (addr & PAGE_MASK) as usize;
+            let len = core::cmp::min(PAGE_SIZE as usize - pageOffset, buf.len() - count);
+            let page = self.Page(addr)?;
+            let dst = unsafe {
+                core::slice::from_raw_parts_mut((page + pageOffset as u64) as *mut u8, len)
+            };
+            dst.copy_from_slice(&buf[count..count + len]);
+            count += len;
+        }
+
+        return Ok(());
+    }
+
+    // Zero zeroes the cached pages of the range, the missing ones are zeros
+    // already.
+    fn Zero(&mut self, start: u64, end: u64) {
+        let mut addr = start;
+        while addr < end {
+            let pageOffset = addr & PAGE_MASK;
+            let len = core::cmp::min(PAGE_SIZE - pageOffset, end - addr);
+            if let Some(page) = self.pages.get(&(addr & !PAGE_MASK)) {
+                let dst = unsafe {
+                    core::slice::from_raw_parts_mut((*page + pageOffset) as *mut u8, len as usize)
+                };
+                dst.fill(0);
+            }
+            addr += len;
+        }
+    }
+
+    fn ReadFull(fd: i32, buf: &mut DataBuff, offset: u64) -> Result<usize> {
+        let mut count = 0;
+        while count < buf.Len() {
+            let iov = IoVec::NewFromAddr(buf.Ptr() + count as u64, buf.Len() - count);
+            let ret = IOReadAt(fd, &[iov], offset + count as u64)?;
+            if ret == 0 {
+                break;
+            }
+            count += ret as usize;
+        }
+
+        return Ok(count);
+    }
+
+    fn WriteFull(fd: i32, buf: &DataBuff, len: usize, offset: u64) -> Result<()> {
+        let mut count = 0;
+        while count < len {
+            let iov = IoVec::NewFromAddr(buf.Ptr() + count as u64, len - count);
+            let ret = IOWriteAt(fd, &[iov], offset + count as u64)?;
+            if ret == 0 {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+            count += ret as usize;
+        }
+
+        return Ok(());
+    }
+
+    fn FillChunk(&mut self, backingFd: i32, chunk: u64) -> Result<()> {
+        let mut buf = DataBuff::New(CHUNK_SIZE as usize);
+        let len = Self::ReadFull(backingFd, &mut buf, chunk)?;
+
+        let firstUnit = chunk / CRYPT_UNIT_SIZE as u64;
+        for (i, unit) in buf.buf[..len].chunks_mut(CRYPT_UNIT_SIZE).enumerate() {
+            self.cipher.Decrypt(firstUnit + i as u64, unit);
+        }
+
+        return self.CopyIn(chunk, &buf.buf[..len]);
+    }
+
+    // Fill decrypts the chunks of the range which are not in the cache yet.
+    pub fn Fill(&mut self, backingFd: i32, start: u64, end: u64) -> Result<()> {
+        let mut chunk = start & !CHUNK_MASK;
+        while chunk < end {
+            if !self.filled.contains(&chunk) {
+                self.FillChunk(backingFd, chunk)?;
+                self.filled.insert(chunk);
+            }
+            chunk += CHUNK_SIZE;
+        }
+
+        return Ok(());
+    }
+
+    pub fn MarkDirty(&mut self, start: u64, end: u64) {
+        let mut chunk = start & !CHUNK_MASK;
+        while chunk < end {
+            self.dirty.insert(chunk);
+            chunk += CHUNK_SIZE;
+        }
+    }
+
+    // Resize gets the cache ready for the file size to change. The last unit
+    // of the file is encrypted with its length, so the units at both sizes
+    // are written back. The cache past the lower size is zeros, which the
+    // backing file must not be read into.
+    pub fn Resize(&mut self, backingFd: i32, size: i64) -> Result<()> {
+        let oldSize = self.size;
+        if oldSize == size {
+            return Ok(());
+        }
+
+        let (low, high) = if oldSize < size {
+            (oldSize as u64, size as u64)
+        } else {
+            (size as u64, oldSize as u64)
+        };
+
+        self.Fill(backingFd, low, low + 1)?;
+
+        let mut chunk = low & !CHUNK_MASK;
+        while chunk < high {
+            self.filled.insert(chunk);
+            self.dirty.insert(chunk);
+            chunk += CHUNK_SIZE;
+        }
+
+        if size < oldSize {
+            let end = (low + PAGE_MASK) & !PAGE_MASK;
+            self.Zero(low, end);
+            let released: Vec<u64> = self.pages.range(end..).map(|(offset, _)| *offset).collect();
+            for offset in released {
+                let page = self.pages.remove(&offset).unwrap();
+                Self::FreePage(page);
+            }
+        }
+
+        self.size = size;
+        return Ok(());
+    }
+
+    // Read reads the cache at the offset, up to the file size.
+    pub fn Read(&mut self, backingFd: i32, offset: i64, buf: &mut [u8]) -> Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+
+        let len = core::cmp::min(buf.len() as i64, self.size - offset) as usize;
+        let (start, end) = (offset as u64, (offset + len as i64) as u64);
+        self.Fill(backingFd, start, end)?;
+        self.CopyOut(start, &mut buf[..len]);
+        return Ok(len);
+    }
+
+    // Write writes the buffer to the cache at the offset, which might grow
+    // the file. A negative offset is an append. It returns the file size.
+    pub fn Write(&mut self, backingFd: i32, offset: i64, buf: &[u8]) -> Result<i64> {
+        let offset = if offset < 0 { self.size } else { offset };
+        let end = offset + buf.len() as i64;
+        if end > self.size {
+            self.Resize(backingFd, end)?;
+        }
+
+        self.Fill(backingFd, offset as u64, end as u64)?;
+        self.CopyIn(offset as u64, buf)?;
+        self.MarkDirty(offset as u64, end as u64);
+        return Ok(self.size);
+    }
+
+    // Allocate grows the file to end, a punched hole or a zeroed range is
+    // zeroed in the cache.
+    pub fn Allocate(&mut self, backingFd: i32, offset: i64, end: i64, zero: bool) -> Result<()> {
+        if end > self.size {
+            self.Resize(backingFd, end)?;
+        }
+
+        if zero && end > offset {
+            self.Fill(backingFd, offset as u64, end as u64)?;
+            self.Zero(offset as u64, end as u64);
+            self.MarkDirty(offset as u64, end as u64);
+        }
+
+        return Ok(());
+    }
+
+    // MapPage returns the cache page of the offset for a mapping of the file.
+    pub fn MapPage(&mut self, backingFd: i32, offset: u64, writeable: bool) -> Result<u64> {
+        self.Fill(backingFd, offset, offset + PAGE_SIZE)?;
+        // the writes through the mapping are not tracked
+        if writeable {
+            self.MarkDirty(offset, offset + PAGE_SIZE);
+        }
+
+        return self.Page(offset);
+    }
+
+    // MapRange returns the cache pages of the range for a mapping of the
+    // file, one iovec per page.
+    pub fn MapRange(
+        &mut self,
+        backingFd: i32,
+        start: u64,
+        end: u64,
+        writeable: bool,
+    ) -> Result<Vec<IoVec>> {
+        self.Fill(backingFd, start, end)?;
+        if writeable {
+            self.MarkDirty(start, end);
+        }
+
+        let mut iovs = Vec::new();
+        let mut addr = start;
+        while addr < end {
+            let pageOffset = addr & PAGE_MASK;
+            let len = core::cmp::min(PAGE_SIZE - pageOffset, end - addr);
+            let page = self.Page(addr)?;
+            iovs.push(IoVec::NewFromAddr(page + pageOffset, len as usize));
+            addr += len;
+        }
+
+        return Ok(iovs);
+    }
+
+    // WriteBack encrypts the dirty chunks to the backing file and sets the
+    // backing file size to the plaintext size.
+    pub fn WriteBack(&mut self, backingFd: i32) -> Result<()> {
+        let size = self.size as u64;
+        let dirty: Vec<u64> = self.dirty.iter().cloned().collect();
+        for chunk in dirty {
+            if chunk < size {
+                let len = core::cmp::min(CHUNK_SIZE, size - chunk) as usize;
+                let mut buf = DataBuff::New(len);
+                self.CopyOut(chunk, &mut buf.buf[..len]);
+
+                let firstUnit = chunk / CRYPT_UNIT_SIZE as u64;
+                for (i, unit) in buf.buf[..len].chunks_mut(CRYPT_UNIT_SIZE).enumerate() {
+                    self.cipher.Encrypt(firstUnit + i as u64, unit);
+                }
+
+                Self::WriteFull(backingFd, &buf, len, chunk)?;
+            }
+            self.dirty.remove(&chunk);
+        }
+
+        let mut fstat = LibcStat::default();
+        let ret = Fstat(backingFd, &mut fstat) as i32;
+        if ret < 0 {
+            return Err(Error::SysError(-ret));
+        }
+
+        if fstat.st_size as u64 != size {
+            let ret = Ftruncate(backingFd, size as i64);
+            if ret < 0 {
+                return Err(Error::SysError(-ret as i32));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn FromHex(s: &str) -> Vec<u8> {
+        return (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect();
+    }
+
+    // key of the IEEE 1619 XTS-AES-256 test vector 10
+    fn VectorKey() -> [u8; CRYPT_KEY_SIZE] {
+        let mut key = [0; CRYPT_KEY_SIZE];
+        key.copy_from_slice(&FromHex(
+            "2718281828459045235360287471352662497757247093699959574966967627\
+             3141592653589793238462643383279502884197169399375105820974944592",
+        ));
+        return key;
+    }
+
+    #[test]
+    fn test_XtsVector() {
+        let cipher = XtsCipher::New(&VectorKey());
+
+        let plain: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let mut buf = plain.clone();
+        cipher.Encrypt(0xff, &mut buf);
+        assert_eq!(
+            buf[..32],
+            FromHex("1c3b3a102f770386e4836c99e370cf9bea00803f5e482357a4ae12d414a3e63b")[..]
+        );
+        cipher.Decrypt(0xff, &mut buf);
+        assert_eq!(buf, plain);
+
+        // a partial last block uses ciphertext stealing
+        let plain: Vec<u8> = (0..17).collect();
+        let mut buf = plain.clone();
+        cipher.Encrypt(0xff, &mut buf);
+        assert_eq!(buf, FromHex("990b3d5708499ecacac51584606f5d761c"));
+        cipher.Decrypt(0xff, &mut buf);
+        assert_eq!(buf, plain);
+    }
+
+    #[test]
+    fn test_XtsRoundTrip() {
+        let cipher = XtsCipher::New(&VectorKey());
+        for len in [16, 17, 31, 32, 47, 48, CRYPT_UNIT_SIZE, CRYPT_UNIT_SIZE + 4] {
+            let plain: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+            let mut buf = plain.clone();
+            cipher.Encrypt(3, &mut buf);
+            assert_ne!(buf, plain);
+
+            let mut other = plain.clone();
+            cipher.Encrypt(4, &mut other);
+            assert_ne!(buf, other);
+
+            cipher.Decrypt(3, &mut buf);
+            assert_eq!(buf, plain);
+        }
+    }
+
+    #[test]
+    fn test_EncryptName() {
+        let policy = CryptPolicy::New(&[7; CRYPT_KEY_SIZE]).unwrap();
+        let names = policy.Names(&[1; CRYPT_NONCE_SIZE]);
+
+        let enc = names.EncryptName("hello.txt").unwrap();
+        assert_ne!(enc, "hello.txt");
+        assert!(!enc.contains('/'));
+        assert_eq!(names.EncryptName("hello.txt").unwrap(), enc);
+        assert_eq!(names.DecryptName(&enc), Some("hello.txt".to_string()));
+
+        assert_eq!(names.EncryptName(".").unwrap(), ".");
+        assert_eq!(names.EncryptName("..").unwrap(), "..");
+
+        let long = "a".repeat(CRYPT_NAME_MAX);
+        let enc = names.EncryptName(&long).unwrap();
+        assert!(enc.len() <= 255);
+        assert_eq!(names.DecryptName(&enc), Some(long));
+        assert!(names.EncryptName(&"a".repeat(CRYPT_NAME_MAX + 1)).is_err());
+
+        // host names which are not ciphertexts are hidden
+        assert_eq!(names.DecryptName("plain name"), None);
+        assert_eq!(names.DecryptName("abcde"), None);
+    }
+
+    #[test]
+    fn test_EncryptNameDirs() {
+        let policy = CryptPolicy::New(&[7; CRYPT_KEY_SIZE]).unwrap();
+        let dir1 = policy.Names(&[1; CRYPT_NONCE_SIZE]);
+        let dir2 = policy.Names(&[2; CRYPT_NONCE_SIZE]);
+
+        // the same name in two directories doesn't show they are the same
+        let enc1 = dir1.EncryptName("hello.txt").unwrap();
+        let enc2 = dir2.EncryptName("hello.txt").unwrap();
+        assert_ne!(enc1, enc2);
+        assert_eq!(dir2.DecryptName(&enc2), Some("hello.txt".to_string()));
+        assert_ne!(dir2.DecryptName(&enc1), Some("hello.txt".to_string()));
+    }
+
+    #[test]
+    fn test_EncryptLink() {
+        let policy = CryptPolicy::New(&[7; CRYPT_KEY_SIZE]).unwrap();
+        let names = policy.Names(&[1; CRYPT_NONCE_SIZE]);
+        let target = "../some/dir/file";
+        let enc = policy.EncryptLink(target).unwrap();
+        assert_ne!(enc, names.EncryptName(target).unwrap());
+        assert_eq!(policy.DecryptLink(&enc).unwrap(), target);
+        assert!(policy.DecryptLink("not base64!").is_err());
+    }
+}
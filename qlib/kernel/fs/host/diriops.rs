@@ -40,7 +40,9 @@ use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
 use super::super::inode::*;
+use super::crypt::*;
 use super::hostdirfops::*;
+use super::quota::*;
 use super::util::*;
 use super::*;
 
@@ -55,6 +57,12 @@ pub struct HostDirOpIntern {
 
     pub overrides: BTreeMap<String, Inode>,
     pub readdirCache: Option<DentMap>,
+
+    // cryptPolicy is the policy of the encrypted volume of the directory, the
+    // names of the host files are the ciphertexts of the names with the
+    // cipher of the directory
+    pub cryptPolicy: Option<CryptPolicy>,
+    pub cryptNames: Option<CryptNames>,
 }
 
 impl Default for HostDirOpIntern {
@@ -66,6 +74,8 @@ impl Default for HostDirOpIntern {
             errorcode: 0,
             overrides: BTreeMap::new(),
             readdirCache: None,
+            cryptPolicy: None,
+            cryptNames: None,
         };
     }
 }
@@ -90,6 +100,8 @@ impl HostDirOpIntern {
             errorcode: 0,
             readdirCache: None,
             overrides: BTreeMap::new(),
+            cryptPolicy: None,
+            cryptNames: None,
         };
     }
 
@@ -138,7 +150,16 @@ impl HostDirOpIntern {
                 };
 
                 let pathname = CString::FromAddr(&name[0] as *const _ as u64);
-                entries.insert(pathname.Str().unwrap().to_string(), dentry);
+                let name = pathname.Str().unwrap().to_string();
+                let name = match &self.cryptNames {
+                    None => name,
+                    Some(names) => match names.DecryptName(&name) {
+                        // not created in the volume
+                        None => continue,
+                        Some(name) => name,
+                    },
+                };
+                entries.insert(name, dentry);
             }
         }
 
@@ -274,6 +295,137 @@ impl HostDirOp {
         return self.lock().sattr;
     }
 
+    // HostName returns the name of the host file of a name in the directory,
+    // which is the ciphertext of the name in an encrypted volume.
+    pub fn HostName(&self, name: &str) -> Result<String> {
+        match &self.lock().cryptNames {
+            None => return Ok(name.to_string()),
+            Some(names) => return names.EncryptName(name),
+        }
+    }
+
+    // QuotaCreated records a file created in the directory in the quota which
+    // its inode was charged to.
+    fn QuotaCreated(&self, quota: &Option<HostQuota>, hostName: &str) {
+        let quota = match quota {
+            None => return,
+            Some(quota) => quota,
+        };
+
+        let mut fstat = LibcStat::default();
+        let ret = Fstatat(
+            self.HostFd(),
+            hostName,
+            &mut fstat,
+            ATType::AT_SYMLINK_NOFOLLOW,
+        );
+        if ret < 0 {
+            // the file is already gone
+            quota.UnchargeInode();
+            return;
+        }
+
+        quota.Created((fstat.st_dev, fstat.st_ino));
+    }
+
+    // NewHostInode creates the inode of a host file in the directory. The
+    // files in an encrypted volume get the policy of the volume.
+    pub fn NewHostInode(
+        &self,
+        task: &Task,
+        msrc: &Arc<QMutex<MountSource>>,
+        fd: i32,
+        fstat: &LibcStat,
+        writeable: bool,
+        skiprw: bool,
+    ) -> Result<Inode> {
+        let inode = Inode::NewHostInode(task, msrc, fd, fstat, writeable, skiprw, false)?;
+
+        let policy = match self.lock().cryptPolicy.clone() {
+            Some(policy) => policy,
+            None => match VolumePolicy(&fstat.StableAttr()) {
+                None => return Ok(inode),
+                Some(policy) => policy,
+            },
+        };
+
+        let iops = inode.lock().InodeOp.clone();
+        if let Some(dirOp) = iops.HostDirOp() {
+            dirOp.SetCryptNames(task, &policy)?;
+        } else if let Some(hostOp) = iops.HostInodeOp() {
+            let mut h = hostOp.lock();
+            if fstat.InodeType() == InodeType::RegularFile {
+                h.crypt = Some(CryptFile::New(&policy, fd, fstat.st_size)?);
+            }
+            h.cryptPolicy = Some(policy);
+        }
+
+        return Ok(inode);
+    }
+
+    // IsEmpty tells whether the host directory has no entry but . and ..
+    fn IsEmpty(&self, task: &Task) -> Result<bool> {
+        let entries = self.lock().ReadDirAll(task)?;
+        return Ok(!entries
+            .Entries
+            .keys()
+            .any(|name| name != "." && name != ".."));
+    }
+
+    // SetCryptNames puts the directory in the encrypted volume, its names are
+    // encrypted with the key of its nonce. A directory without a nonce must
+    // be empty, it is a new one.
+    pub fn SetCryptNames(&self, task: &Task, policy: &CryptPolicy) -> Result<()> {
+        let fd = self.HostFd();
+        let names = match policy.DirNames(fd, false) {
+            Err(Error::SysError(SysErr::ENOKEY)) if self.IsEmpty(task)? => {
+                policy.DirNames(fd, true)?
+            }
+            ret => ret?,
+        };
+
+        let mut h = self.lock();
+        h.cryptPolicy = Some(policy.clone());
+        h.cryptNames = Some(names);
+        h.readdirCache = None;
+        return Ok(());
+    }
+
+    // SetCryptPolicy makes the directory the root of an encrypted volume. The
+    // key descriptor kept on the directory after its nonce rejects a wrong
+    // key, a directory without one must be empty and gets a nonce and the
+    // descriptor of the key.
+    pub fn SetCryptPolicy(&self, task: &Task, policy: &CryptPolicy) -> Result<()> {
+        let fd = self.HostFd();
+        let descriptor = policy.Descriptor();
+        match Getxattr(fd, CRYPT_XATTR) {
+            Ok(v) => {
+                if v.len() != CRYPT_NONCE_SIZE + descriptor.len()
+                    || v[CRYPT_NONCE_SIZE..] != descriptor[..]
+                {
+                    return Err(Error::SysError(SysErr::EKEYREJECTED));
+                }
+            }
+            Err(Error::SysError(SysErr::ENODATA)) => {
+                if !self.IsEmpty(task)? {
+                    return Err(Error::SysError(SysErr::ENOTEMPTY));
+                }
+
+                let mut v = NewNonce()?.to_vec();
+                v.extend_from_slice(&descriptor);
+                Setxattr(fd, CRYPT_XATTR, &v, 0)?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        let sattr = self.StableAttr();
+        CRYPT_VOLUMES
+            .lock()
+            .insert((sattr.DeviceId, sattr.InodeId), policy.clone());
+
+        return self.SetCryptNames(task, policy);
+    }
+
     pub fn GetHostFileOp(&self, _task: &Task) -> HostDirFops {
         let hostDirOp = HostDirFops {
             DirOp: self.clone(),
@@ -340,7 +492,8 @@ impl InodeOperations for HostDirOp {
 
     fn Lookup(&self, task: &Task, parent: &Inode, name: &str) -> Result<Dirent> {
         let skiprw = true;
-        let (fd, writeable, fstat) = match TryOpenAt(self.HostFd(), name, skiprw) {
+        let hostName = self.HostName(name)?;
+        let (fd, writeable, fstat) = match TryOpenAt(self.HostFd(), &hostName, skiprw) {
             Err(Error::SysError(SysErr::ENOENT)) => {
                 let inode = match self.lock().overrides.get(name) {
                     None => return Err(Error::SysError(SysErr::ENOENT)),
@@ -354,8 +507,8 @@ impl InodeOperations for HostDirOp {
         };
 
         let ms = parent.lock().MountSource.clone();
-        let inode = Inode::NewHostInode(task, &ms, fd, &fstat, writeable, skiprw, false)?;
-        
+        let inode = self.NewHostInode(task, &ms, fd, &fstat, writeable, skiprw)?;
+
         let ret = Ok(Dirent::New(&inode, name));
         return ret;
     }
@@ -378,20 +531,38 @@ impl InodeOperations for HostDirOp {
         newFlags.Read = true;
         newFlags.Write = true;
 
-        let (fd, fstat) = createAt(
+        let hostName = self.HostName(name)?;
+        let quota = MountQuota(dir);
+        if let Some(quota) = &quota {
+            quota.ChargeInode()?;
+        }
+
+        let (fd, fstat) = match createAt(
             self.HostFd(),
-            name,
+            &hostName,
             newFlags.ToLinux() | LibcConst::O_CREAT as i32,
             perm.LinuxMode(),
             owner.UID.0,
             owner.GID.0,
-        )?;
+        ) {
+            Err(e) => {
+                if let Some(quota) = &quota {
+                    quota.UnchargeInode();
+                }
+                return Err(e);
+            }
+            Ok(ret) => ret,
+        };
+
+        if let Some(quota) = &quota {
+            quota.Created((fstat.st_dev, fstat.st_ino));
+        }
 
         self.lock().readdirCache = None;
 
         let mountSource = dir.lock().MountSource.clone();
 
-        let inode = Inode::NewHostInode(task, &mountSource, fd, &fstat, true, false, false)?;
+        let inode = self.NewHostInode(task, &mountSource, fd, &fstat, true, false)?;
         let dirent = Dirent::New(&inode, name);
 
         let file = inode.GetFile(task, &dirent, flags)?;
@@ -401,23 +572,33 @@ impl InodeOperations for HostDirOp {
     fn CreateDirectory(
         &self,
         task: &Task,
-        _dir: &mut Inode,
+        dir: &mut Inode,
         name: &str,
         perm: &FilePermissions,
     ) -> Result<()> {
         let owner = task.FileOwner();
+        let hostName = self.HostName(name)?;
+
+        let quota = MountQuota(dir);
+        if let Some(quota) = &quota {
+            quota.ChargeInode()?;
+        }
 
         let ret = Mkdirat(
             self.HostFd(),
-            name,
+            &hostName,
             perm.LinuxMode(),
             owner.UID.0,
             owner.GID.0,
         );
         if ret < 0 {
+            if let Some(quota) = &quota {
+                quota.UnchargeInode();
+            }
             return Err(Error::SysError(-ret as i32));
         }
 
+        self.QuotaCreated(&quota, &hostName);
         self.lock().readdirCache = None;
 
         return Ok(());
@@ -426,16 +607,31 @@ impl InodeOperations for HostDirOp {
     fn CreateLink(
         &self,
         _task: &Task,
-        _dir: &mut Inode,
+        dir: &mut Inode,
         oldname: &str,
         newname: &str,
     ) -> Result<()> {
-        let ret = SymLinkAt(oldname, self.HostFd(), newname);
+        let target = match &self.lock().cryptPolicy {
+            None => oldname.to_string(),
+            Some(policy) => policy.EncryptLink(oldname)?,
+        };
+        let hostName = self.HostName(newname)?;
+
+        let quota = MountQuota(dir);
+        if let Some(quota) = &quota {
+            quota.ChargeInode()?;
+        }
+
+        let ret = SymLinkAt(&target, self.HostFd(), &hostName);
 
         if ret < 0 {
+            if let Some(quota) = &quota {
+                quota.UnchargeInode();
+            }
             return Err(Error::SysError(-ret as i32));
         }
 
+        self.QuotaCreated(&quota, &hostName);
         self.lock().readdirCache = None;
         return Ok(());
     }
@@ -452,11 +648,18 @@ impl InodeOperations for HostDirOp {
             None => return Err(Error::SysError(SysErr::EPERM)),
         };
 
+        // the file content can't be read with the key of another volume
+        let policy = iops.lock().cryptPolicy.clone();
+        if policy != self.lock().cryptPolicy {
+            return Err(Error::SysError(SysErr::EXDEV));
+        }
+
+        let hostName = self.HostName(name)?;
         let ret = LinkAt(
             iops.HostFd(),
             "",
             self.HostFd(),
-            name,
+            &hostName,
             ATType::AT_EMPTY_PATH,
         );
 
@@ -471,55 +674,105 @@ impl InodeOperations for HostDirOp {
     fn CreateFifo(
         &self,
         task: &Task,
-        _dir: &mut Inode,
+        dir: &mut Inode,
         name: &str,
         perm: &FilePermissions,
     ) -> Result<()> {
         let owner = task.FileOwner();
+        let hostName = self.HostName(name)?;
+
+        let quota = MountQuota(dir);
+        if let Some(quota) = &quota {
+            quota.ChargeInode()?;
+        }
 
         let ret = Mkfifoat(
             self.HostFd(),
-            name,
+            &hostName,
             perm.LinuxMode(),
             owner.UID.0,
             owner.GID.0,
         );
         if ret < 0 {
+            if let Some(quota) = &quota {
+                quota.UnchargeInode();
+            }
             return Err(Error::SysError(-ret as i32));
         }
 
+        self.QuotaCreated(&quota, &hostName);
         self.lock().readdirCache = None;
 
         return Ok(());
     }
 
-    fn Remove(&self, _task: &Task, _dir: &mut Inode, name: &str) -> Result<()> {
+    fn Remove(&self, _task: &Task, dir: &mut Inode, name: &str) -> Result<()> {
         match self.lock().overrides.remove(name) {
             None => (),
             Some(_) => return Ok(()),
         }
 
+        let hostName = self.HostName(name)?;
+
+        // the data of the file is freed with its last link
+        let quota = MountQuota(dir);
+        let mut fstat = LibcStat::default();
+        let mut stated = false;
+        if quota.is_some() {
+            stated = Fstatat(
+                self.HostFd(),
+                &hostName,
+                &mut fstat,
+                ATType::AT_SYMLINK_NOFOLLOW,
+            ) == 0;
+        }
+
         let flags = 0; //ATType::AT_REMOVEDIR
 
-        let ret = UnLinkAt(self.HostFd(), name, flags);
+        let ret = UnLinkAt(self.HostFd(), &hostName, flags);
 
         if ret < 0 {
             return Err(Error::SysError(-ret as i32));
         }
 
+        if let Some(quota) = &quota {
+            if stated && fstat.st_nlink == 1 {
+                quota.Removed((fstat.st_dev, fstat.st_ino));
+            }
+        }
+
         self.lock().readdirCache = None;
         return Ok(());
     }
 
-    fn RemoveDirectory(&self, _task: &Task, _dir: &mut Inode, name: &str) -> Result<()> {
+    fn RemoveDirectory(&self, _task: &Task, dir: &mut Inode, name: &str) -> Result<()> {
         let flags = ATType::AT_REMOVEDIR;
+        let hostName = self.HostName(name)?;
+
+        let quota = MountQuota(dir);
+        let mut fstat = LibcStat::default();
+        let mut stated = false;
+        if quota.is_some() {
+            stated = Fstatat(
+                self.HostFd(),
+                &hostName,
+                &mut fstat,
+                ATType::AT_SYMLINK_NOFOLLOW,
+            ) == 0;
+        }
 
-        let ret = UnLinkAt(self.HostFd(), name, flags);
+        let ret = UnLinkAt(self.HostFd(), &hostName, flags);
 
         if ret < 0 {
             return Err(Error::SysError(-ret as i32));
         }
 
+        if let Some(quota) = &quota {
+            if stated {
+                quota.Removed((fstat.st_dev, fstat.st_ino));
+            }
+        }
+
         self.lock().readdirCache = None;
         return Ok(());
     }
@@ -593,18 +846,35 @@ impl InodeOperations for HostDirOp {
 
     //fn StableAttr(&self) -> &StableAttr;
     fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::ENODATA));
+        }
+
         return Getxattr(self.HostFd(), name);
     }
 
     fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
         return Setxattr(self.HostFd(), name, value, flags);
     }
 
     fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
-        return Listxattr(self.HostFd());
+        let mut names = Listxattr(self.HostFd())?;
+        if self.lock().cryptPolicy.is_some() {
+            names.retain(|name| name != CRYPT_XATTR);
+        }
+
+        return Ok(names);
     }
 
     fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
         return Removexattr(self.HostFd(), name);
     }
 
@@ -634,7 +904,14 @@ impl InodeOperations for HostDirOp {
         return Err(Error::SysError(SysErr::EISDIR));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EISDIR));
     }
 
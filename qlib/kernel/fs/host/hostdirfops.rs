@@ -22,6 +22,7 @@ use super::super::super::super::common::*;
 use super::super::super::super::linux_def::*;
 use super::super::super::kernel::waiter::*;
 use super::super::super::task::*;
+use super::super::super::SHARESPACE;
 
 use super::super::attr::*;
 use super::super::dentry::*;
@@ -29,6 +30,7 @@ use super::super::dirent::*;
 use super::super::file::*;
 use super::super::fsutil::file::*;
 use super::super::host::diriops::*;
+use super::hostinotify::*;
 
 #[derive(Clone)]
 pub struct HostDirFops {
@@ -135,11 +137,17 @@ impl FileOperations for HostDirFops {
     fn IterateDir(
         &self,
         task: &Task,
-        _d: &Dirent,
+        d: &Dirent,
         dirCtx: &mut DirCtx,
         offset: i32,
     ) -> (i32, Result<i64>) {
-        return self.DirOp.lock().IterateDir(task, dirCtx, offset);
+        let ret = self.DirOp.lock().IterateDir(task, dirCtx, offset);
+        if SHARESPACE.config.read().ReaddirCache {
+            // the cached listing is invalidated by the host changes
+            HOST_INOTIFY.Watch(d);
+        }
+
+        return ret;
     }
 
     fn Mappable(&self) -> Result<MMappable> {
@@ -123,6 +123,12 @@ impl FileOperations for HostFileOp {
     }
 
     fn Seek(&self, task: &Task, f: &File, whence: i32, current: i64, offset: i64) -> Result<i64> {
+        if (whence == SeekWhence::SEEK_DATA || whence == SeekWhence::SEEK_HOLE)
+            && self.InodeOp.InodeType() == InodeType::RegularFile
+        {
+            return self.InodeOp.SeekDataHole(task, whence, offset);
+        }
+
         let mut dirCursor = self.DirCursor.lock();
         let mut cursor = "".to_string();
         let newOffset = SeekWithDirCursor(task, f, whence, current, offset, Some(&mut cursor))?;
@@ -36,6 +36,7 @@ use super::super::super::memmgr::mapping_set::*;
 use super::super::super::memmgr::mm::*;
 pub use super::super::super::memmgr::vma::MMappable;
 use super::super::super::memmgr::*;
+use super::super::super::quring::throttle::IO_THROTTLES;
 use super::super::super::socket::unix::transport::unix::*;
 use super::super::super::task::*;
 use super::super::super::Kernel::HostSpace;
@@ -47,8 +48,11 @@ use super::super::file::*;
 use super::super::filesystems::*;
 use super::super::flags::*;
 use super::super::inode::*;
+use super::super::tmpfs::fs::TmpfsUsage;
+use super::crypt::*;
 use super::fs::*;
 use super::hostfileop::*;
+use super::quota::HostQuota;
 use super::util::*;
 use super::*;
 
@@ -188,6 +192,21 @@ pub struct HostInodeOpIntern {
     pub hasMappable: bool,
 
     pub isMemfd: bool,
+
+    // tmpfsUsage is the usage of the tmpfs mount of a tmpfs file, which is
+    // charged with tmpfsBlocks pages up to the written end of the file.
+    pub tmpfsUsage: Option<TmpfsUsage>,
+    pub tmpfsBlocks: u64,
+
+    // quota is the quota of the host-backed mount of a regular file, which
+    // is charged with the growth of the file beyond quotaSize.
+    pub quota: Option<HostQuota>,
+    pub quotaSize: i64,
+
+    // cryptPolicy is the policy of the encrypted volume of the file, the
+    // content of a regular file goes through the plaintext cache of crypt
+    pub cryptPolicy: Option<CryptPolicy>,
+    pub crypt: Option<CryptFile>,
 }
 
 impl Default for HostInodeOpIntern {
@@ -206,6 +225,12 @@ impl Default for HostInodeOpIntern {
             bufWriteLock: QAsyncLock::default(),
             hasMappable: false,
             isMemfd: false,
+            tmpfsUsage: None,
+            tmpfsBlocks: 0,
+            quota: None,
+            quotaSize: 0,
+            cryptPolicy: None,
+            crypt: None,
         };
     }
 }
@@ -225,6 +250,12 @@ impl Drop for HostInodeOpIntern {
             None
         };
 
+        if let Some(mut crypt) = self.crypt.take() {
+            if let Err(e) = crypt.WriteBack(self.HostFd) {
+                error!("fail to write back encrypted file {}: {:?}", self.HostFd, e);
+            }
+        }
+
         if SHARESPACE.config.read().MmapRead {
             match self.mappable.take() {
                 None => (),
@@ -234,6 +265,10 @@ impl Drop for HostInodeOpIntern {
             }
         }
 
+        if let Some(usage) = self.tmpfsUsage.take() {
+            usage.UnchargeBlocks(self.tmpfsBlocks);
+        }
+
         HostSpace::Close(self.HostFd);
     }
 }
@@ -262,6 +297,12 @@ impl HostInodeOpIntern {
             bufWriteLock: QAsyncLock::default(),
             hasMappable: false,
             isMemfd: isMemfd,
+            tmpfsUsage: None,
+            tmpfsBlocks: 0,
+            quota: None,
+            quotaSize: fstat.st_size,
+            cryptPolicy: None,
+            crypt: None,
         };
 
         if ret.CanMap() {
@@ -325,6 +366,12 @@ impl HostInodeOpIntern {
 
     //get phyaddress ranges for the file range
     pub fn MapInternal(&mut self, task: &Task, fr: &Range) -> Result<Vec<IoVec>> {
+        // an encrypted file is mapped from the pages of its plaintext cache
+        let (backingFd, writeable) = (self.HostFd, self.Writeable);
+        if let Some(crypt) = &mut self.crypt {
+            return crypt.MapRange(backingFd, fr.Start(), fr.End(), writeable);
+        }
+
         let mut chunkStart = fr.Start() & !HUGE_PAGE_MASK;
 
         self.Fill(task, chunkStart, fr.End())?;
@@ -362,6 +409,11 @@ impl HostInodeOpIntern {
             return Err(Error::FileMapError);
         }
 
+        let (backingFd, writeable) = (self.HostFd, self.Writeable);
+        if let Some(crypt) = &mut self.crypt {
+            return crypt.MapPage(backingFd, fileOffset, writeable);
+        }
+
         let chunkStart = fileOffset & !HUGE_PAGE_MASK;
         self.Fill(task, chunkStart, fileOffset + PAGE_SIZE)?;
 
@@ -434,7 +486,8 @@ impl HostInodeOpIntern {
         return SetMaskedAttributes(self.HostFd, mask, attr);
     }
 
-    pub fn Sync(&self) -> Result<()> {
+    pub fn Sync(&mut self) -> Result<()> {
+        self.CryptWriteBack()?;
         let ret = Fsync(self.HostFd);
         if ret < 0 {
             return Err(Error::SysError(-ret));
@@ -447,6 +500,14 @@ impl HostInodeOpIntern {
         return self.HostFd;
     }
 
+    pub fn CryptWriteBack(&mut self) -> Result<()> {
+        let backingFd = self.HostFd;
+        match &mut self.crypt {
+            None => return Ok(()),
+            Some(crypt) => return crypt.WriteBack(backingFd),
+        }
+    }
+
     pub fn BufWriteEnable(&self) -> bool {
         return SHARESPACE.config.read().FileBufWrite && !self.hasMappable;
     }
@@ -619,6 +680,151 @@ impl HostInodeOp {
         return hostFileOp;
     }
 
+    // TmpfsReserve charges the pages of the tmpfs file up to the end of the
+    // range written or allocated, it fails with ENOSPC if the size limit of
+    // the tmpfs mount is hit. The pages before the end are charged whether
+    // they are allocated or not, so the holes of a sparse file are charged.
+    pub fn TmpfsReserve(&self, offset: i64, len: i64) -> Result<()> {
+        let mut h = self.lock();
+        let usage = match &h.tmpfsUsage {
+            None => return Ok(()),
+            Some(usage) => usage.clone(),
+        };
+
+        if len <= 0 {
+            return Ok(());
+        }
+
+        let end = (offset as u64 + len as u64 + MemoryDef::PAGE_SIZE - 1) / MemoryDef::PAGE_SIZE;
+        if end > h.tmpfsBlocks {
+            usage.ChargeBlocks(end - h.tmpfsBlocks)?;
+            h.tmpfsBlocks = end;
+        }
+
+        return Ok(());
+    }
+
+    // TmpfsRelease drops the charge of the pages after size, it is called
+    // after the tmpfs file is truncated or a range of it is collapsed.
+    pub fn TmpfsRelease(&self, size: i64) {
+        let mut h = self.lock();
+        let usage = match &h.tmpfsUsage {
+            None => return,
+            Some(usage) => usage.clone(),
+        };
+
+        let end = (size as u64 + MemoryDef::PAGE_SIZE - 1) / MemoryDef::PAGE_SIZE;
+        if end < h.tmpfsBlocks {
+            usage.UnchargeBlocks(h.tmpfsBlocks - end);
+            h.tmpfsBlocks = end;
+        }
+    }
+
+    // Throttle waits until the block I/O limits of the container of the task
+    // allow an I/O of len bytes on the file. Only the regular files of the
+    // host mounts are on a disk, the tmpfs files, pipes and ttys are not.
+    pub fn Throttle(&self, task: &Task, write: bool, len: u64) {
+        let disk = {
+            let h = self.lock();
+            h.quota.is_some() && !h.isMemfd
+        };
+
+        if disk {
+            IO_THROTTLES.Throttle(task, write, len);
+        }
+    }
+
+    // QuotaReserve charges the growth of the file to size to the quota of its
+    // mount, it fails with EDQUOT if the quota is hit.
+    pub fn QuotaReserve(&self, size: i64) -> Result<()> {
+        let mut h = self.lock();
+        let quota = match &h.quota {
+            None => return Ok(()),
+            Some(quota) => quota.clone(),
+        };
+
+        if size > h.quotaSize {
+            let file = (h.sattr.DeviceId, h.sattr.InodeId);
+            quota.ChargeBytes(file, (size - h.quotaSize) as u64)?;
+            h.quotaSize = size;
+        }
+
+        return Ok(());
+    }
+
+    // QuotaRelease uncharges the bytes of the file beyond size.
+    pub fn QuotaRelease(&self, size: i64) {
+        let mut h = self.lock();
+        let quota = match &h.quota {
+            None => return,
+            Some(quota) => quota.clone(),
+        };
+
+        if size < h.quotaSize {
+            let file = (h.sattr.DeviceId, h.sattr.InodeId);
+            quota.UnchargeBytes(file, (h.quotaSize - size) as u64);
+            h.quotaSize = size;
+        }
+    }
+
+    // CryptRelease writes the plaintext cache of an encrypted file back when a
+    // writable file of it is released, the inode may stay cached long after.
+    pub fn CryptRelease(&self) {
+        let ret = self.lock().CryptWriteBack();
+        if let Err(e) = ret {
+            error!("fail to write back encrypted file {}: {:?}", self.HostFd(), e);
+        }
+    }
+
+    pub fn IsCrypt(&self) -> bool {
+        return self.lock().crypt.is_some();
+    }
+
+    // CryptRead reads an encrypted file from its plaintext cache.
+    pub fn CryptRead(&self, offset: i64, buf: &mut [u8]) -> Result<usize> {
+        let mut h = self.lock();
+        let backingFd = h.HostFd;
+        return h.crypt.as_mut().unwrap().Read(backingFd, offset, buf);
+    }
+
+    // CryptWrite writes an encrypted file to its plaintext cache, a negative
+    // offset is an append. It returns the file size.
+    pub fn CryptWrite(&self, offset: i64, buf: &[u8]) -> Result<i64> {
+        let mut h = self.lock();
+        let backingFd = h.HostFd;
+        return h.crypt.as_mut().unwrap().Write(backingFd, offset, buf);
+    }
+
+    // SeekDataHole implements SEEK_DATA and SEEK_HOLE with the host file,
+    // which knows the sparse regions.
+    pub fn SeekDataHole(&self, task: &Task, whence: i32, offset: i64) -> Result<i64> {
+        if self.BufWriteEnable() {
+            // try to gain the lock once, release immediately
+            self.BufWriteLock().Lock(task);
+        }
+
+        // the plaintext cache of an encrypted file has no holes
+        if self.IsCrypt() {
+            let (size, _) = self.Size()?;
+            if offset >= size {
+                return Err(Error::SysError(SysErr::ENXIO));
+            }
+
+            if whence == SeekWhence::SEEK_DATA {
+                return Ok(offset);
+            }
+
+            return Ok(size);
+        }
+
+        let ret = Seek(self.HostFd(), offset, whence);
+        if ret < 0 {
+            return Err(Error::SysError(-ret as i32));
+        }
+
+        return Ok(ret);
+    }
+
     // return (st_size, st_blocks)
     pub fn Size(&self) -> Result<(i64, i64)> {
         let mut s: LibcStat = Default::default();
@@ -628,6 +834,11 @@ impl HostInodeOp {
             return Err(Error::SysError(-ret as i32));
         }
 
+        // the backing file size lags until the write back
+        if let Some(crypt) = &self.lock().crypt {
+            return Ok((crypt.Size(), s.st_blocks));
+        }
+
         return Ok((s.st_size, s.st_blocks));
     }
 
@@ -675,7 +886,7 @@ impl HostInodeOp {
         } else {
             size
         };
-        let buf = DataBuff::New(size);
+        let mut buf = DataBuff::New(size);
 
         let iovs = buf.Iovs(size);
         let inodeType = self.InodeType();
@@ -688,6 +899,12 @@ impl HostInodeOp {
             return Ok(ret as i64);
         } else {
             if inodeType == InodeType::RegularFile && SHARESPACE.config.read().MmapRead {
+                // the wait is done before the mappable is locked
+                let end = Self::ReadEndOffset(offset, size as i64, self.lock().size);
+                if end > offset {
+                    self.Throttle(task, false, (end - offset) as u64);
+                }
+
                 let mut intern = self.lock();
                 if offset > intern.size {
                     return Ok(0);
@@ -705,13 +922,21 @@ impl HostInodeOp {
                 return Ok(count as i64);
             }
 
+            self.Throttle(task, false, size as u64);
+
+            if inodeType == InodeType::RegularFile && self.IsCrypt() {
+                let ret = self.CryptRead(offset, &mut buf.buf[..size])?;
+                task.CopyDataOutToIovs(&buf.buf[0..ret], dsts, true)?;
+                return Ok(ret as i64);
+            }
+
             if SHARESPACE.config.read().UringIO {
                 if self.BufWriteEnable() {
                     // try to gain the lock once, release immediately
                     self.BufWriteLock().Lock(task);
                 }
 
-                let ret = IOURING.Read(
+                let ret = IOURING.FileRead(
                     task,
                     hostIops.HostFd(),
                     buf.Ptr(),
@@ -779,7 +1004,15 @@ impl HostInodeOp {
 
         let mut buf = DataBuff::New(size);
         let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+
+        self.TmpfsReserve(offset, len as i64)?;
+        return self.WriteAtReserved(task, buf, offset, len);
+    }
+
+    fn WriteAtReserved(&self, task: &Task, buf: DataBuff, offset: i64, len: usize) -> Result<i64> {
+        let hostIops: HostInodeOp = self.clone();
         let iovs = buf.Iovs(len);
+        self.QuotaReserve(offset + len as i64)?;
 
         let inodeType = self.InodeType();
 
@@ -793,13 +1026,22 @@ impl HostInodeOp {
                 offset
             };
 
+            // the wait is done before the buffered write takes the lock
+            self.Throttle(task, true, len as u64);
+
+            if inodeType == InodeType::RegularFile && self.IsCrypt() {
+                let size = self.CryptWrite(offset, &buf.buf[..len])?;
+                hostIops.UpdateMaxLen(size);
+                return Ok(len as i64);
+            }
+
             if SHARESPACE.config.read().UringIO {
                 let ret = if self.BufWriteEnable() {
                     let lock = self.BufWriteLock().Lock(task);
-                    let count = IOURING.BufFileWrite(hostIops.HostFd(), buf, offset, lock);
+                    let count = IOURING.BufFileWrite(task, hostIops.HostFd(), buf, offset, lock);
                     count
                 } else {
-                    IOURING.Write(
+                    IOURING.FileWrite(
                         task,
                         hostIops.HostFd(),
                         buf.Ptr(),
@@ -851,6 +1093,18 @@ impl HostInodeOp {
             let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
             let iovs = buf.Iovs(len);
 
+            let size = self.lock().size;
+            let end = size + len as i64;
+            self.TmpfsReserve(size, len as i64)?;
+            self.QuotaReserve(end)?;
+            self.Throttle(task, true, len as u64);
+
+            if inodeType == InodeType::RegularFile && self.IsCrypt() {
+                let size = self.CryptWrite(-1, &buf.buf[..len])?;
+                hostIops.UpdateMaxLen(size);
+                return Ok((len as i64, size));
+            }
+
             let iovsAddr = &iovs[0] as *const _ as u64;
             let iovcnt = 1;
 
@@ -896,6 +1150,7 @@ impl HostInodeOp {
                 self.BufWriteLock().Lock(task);
             }
 
+            self.lock().CryptWriteBack()?;
             IOURING.Fsync(task, fd, datasync)
         } else {
             if self.BufWriteEnable() {
@@ -903,6 +1158,7 @@ impl HostInodeOp {
                 self.BufWriteLock().Lock(task);
             }
 
+            self.lock().CryptWriteBack()?;
             if datasync {
                 HostSpace::FDataSync(fd)
             } else {
@@ -1000,7 +1256,7 @@ impl HostInodeOp {
             }
         }
 
-        return Ok(());
+        return self.lock().CryptWriteBack();
     }
 
     pub fn MAdvise(&self, start: u64, len: u64, advise: i32) -> Result<()> {
@@ -1225,23 +1481,47 @@ impl InodeOperations for HostInodeOp {
         let mops = self.lock().mops.clone();
         let fd = self.HostFd();
 
-        return UnstableAttr(fd, task, &mops);
+        let mut attr = UnstableAttr(fd, task, &mops)?;
+        if self.IsCrypt() {
+            // the backing file size lags until the write back
+            let (size, _) = self.Size()?;
+            attr.Size = size;
+        }
+
+        return Ok(attr);
     }
 
     //fn StableAttr(&self) -> &StableAttr;
     fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::ENODATA));
+        }
+
         return Getxattr(self.HostFd(), name);
     }
 
     fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
         return Setxattr(self.HostFd(), name, value, flags);
     }
 
     fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
-        return Listxattr(self.HostFd());
+        let mut names = Listxattr(self.HostFd())?;
+        if self.lock().cryptPolicy.is_some() {
+            names.retain(|name| name != CRYPT_XATTR);
+        }
+
+        return Ok(names);
     }
 
     fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
+        if self.lock().cryptPolicy.is_some() && name == CRYPT_XATTR {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
         return Removexattr(self.HostFd(), name);
     }
 
@@ -1289,32 +1569,98 @@ impl InodeOperations for HostInodeOp {
             }
         }
 
-        let ret = Ftruncate(self.HostFd(), size);
-        
-        if ret < 0 {
-            return Err(Error::SysError(-ret as i32));
+        self.QuotaReserve(size)?;
+        if self.IsCrypt() {
+            // the backing file is truncated by the write back
+            let mut h = self.lock();
+            let backingFd = h.HostFd;
+            h.crypt.as_mut().unwrap().Resize(backingFd, size)?;
+        } else {
+            let ret = Ftruncate(self.HostFd(), size);
+
+            if ret < 0 {
+                return Err(Error::SysError(-ret as i32));
+            }
         }
 
         self.lock().size = size;
+        self.QuotaRelease(size);
+        // a shrunk file releases its pages, a grown one allocates none
+        self.TmpfsRelease(size);
 
         return Ok(());
     }
 
-    fn Allocate(&self, task: &Task, _dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        let ret = Fallocate(self.HostFd(), 0, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        _dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        // UnstableAttr waits for the buffered writes, which must land before
+        // the data is zeroed or moved.
+        let oldSize = self.UnstableAttr(task)?.Size;
+
+        // the tmpfs pages and the quota for the grown file size are charged,
+        // the pages of a punched hole stay charged
+        let shrink = FallocFlags::FALLOC_FL_PUNCH_HOLE | FallocFlags::FALLOC_FL_COLLAPSE_RANGE;
+        if mode & FallocFlags::FALLOC_FL_INSERT_RANGE != 0 {
+            self.TmpfsReserve(oldSize, length)?;
+            self.QuotaReserve(oldSize + length)?;
+        } else if mode & shrink == 0 {
+            self.TmpfsReserve(offset, length)?;
+            self.QuotaReserve(offset + length)?;
+        }
+
+        // the units of an encrypted file can't move
+        if self.IsCrypt() {
+            let moves = FallocFlags::FALLOC_FL_COLLAPSE_RANGE | FallocFlags::FALLOC_FL_INSERT_RANGE;
+            if mode & moves != 0 {
+                return Err(Error::SysError(SysErr::EOPNOTSUPP));
+            }
 
-        if ret < 0 {
-            return Err(Error::SysError(-ret as i32));
+            let end = if mode & FallocFlags::FALLOC_FL_KEEP_SIZE != 0 {
+                core::cmp::min(offset + length, oldSize)
+            } else {
+                offset + length
+            };
+
+            let zero = FallocFlags::FALLOC_FL_PUNCH_HOLE | FallocFlags::FALLOC_FL_ZERO_RANGE;
+            let mut h = self.lock();
+            let backingFd = h.HostFd;
+            h.crypt
+                .as_mut()
+                .unwrap()
+                .Allocate(backingFd, offset, end, mode & zero != 0)?;
+        } else {
+            // The host file system implements the mode or fails it. The
+            // mappings are shared ones of the host file, so they see the new
+            // data.
+            let ret = Fallocate(self.HostFd(), mode, offset, length);
+
+            if ret < 0 {
+                return Err(Error::SysError(-ret as i32));
+            }
         }
 
         let uattr = self.UnstableAttr(task)?;
         self.lock().size = uattr.Size;
+        if mode & FallocFlags::FALLOC_FL_COLLAPSE_RANGE != 0 {
+            self.QuotaRelease(uattr.Size);
+            self.TmpfsRelease(uattr.Size);
+        }
 
         return Ok(());
     }
 
     fn ReadLink(&self, _task: &Task, _dir: &Inode) -> Result<String> {
-        return ReadLinkAt(self.HostFd(), &"".to_string());
+        let target = ReadLinkAt(self.HostFd(), &"".to_string())?;
+        match &self.lock().cryptPolicy {
+            None => return Ok(target),
+            Some(policy) => return policy.DecryptLink(&target),
+        }
     }
 
     fn GetLink(&self, _task: &Task, dir: &Inode) -> Result<Dirent> {
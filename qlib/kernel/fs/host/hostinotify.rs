@@ -0,0 +1,610 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// hostinotify propagates the changes of the host backed files made outside of
+// the sandbox, e.g. a kubelet ConfigMap update or a sidecar writing a shared
+// volume, into the guest.
+//
+// The host backed dirents with guest inotify watches or cached directory
+// listings are watched by a host inotify fd. The host events invalidate the
+// readdir cache and the cached children of the dirents, and are queued to the
+// guest inotify watches. The events caused by the guest itself are reported by
+// both the guest and the host, so the guest ones are recorded as echoes for a
+// short while and the matching host events are not queued again.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::Deref;
+use lazy_static::lazy_static;
+
+use super::super::super::super::linux_def::*;
+use super::super::super::super::vcpu_mgr::*;
+use super::super::super::kernel::timer::MONOTONIC_CLOCK;
+use super::super::super::task::*;
+use super::super::super::taskMgr;
+use super::super::super::Kernel::HostSpace;
+use super::super::super::IOURING;
+use super::super::super::SHARESPACE;
+use super::super::dirent::*;
+use super::super::inode::*;
+use super::super::inotify::*;
+
+// HOST_INOTIFY_MASK is the host events which change a file or a directory
+// listing, the guest only events such as IN_ACCESS are not watched.
+pub const HOST_INOTIFY_MASK: u32 = InotifyEvent::IN_MODIFY
+    | InotifyEvent::IN_ATTRIB
+    | InotifyEvent::IN_CLOSE_WRITE
+    | InotifyEvent::IN_MOVED_FROM
+    | InotifyEvent::IN_MOVED_TO
+    | InotifyEvent::IN_CREATE
+    | InotifyEvent::IN_DELETE
+    | InotifyEvent::IN_DELETE_SELF
+    | InotifyEvent::IN_MOVE_SELF;
+
+// HOST_INOTIFY_CHILD_MASK is the host events which change the children of a
+// directory.
+pub const HOST_INOTIFY_CHILD_MASK: u32 = InotifyEvent::IN_MOVED_FROM
+    | InotifyEvent::IN_MOVED_TO
+    | InotifyEvent::IN_CREATE
+    | InotifyEvent::IN_DELETE;
+
+// HOST_INOTIFY_ECHO_MASK is the guest events which the host reports too. A
+// guest close doesn't close the cached host fd, so IN_CLOSE_WRITE has no echo.
+pub const HOST_INOTIFY_ECHO_MASK: u32 = HOST_INOTIFY_MASK & !InotifyEvent::IN_CLOSE_WRITE;
+
+// HOST_INOTIFY_MAX_ECHOES is the max number of the guest events waiting for
+// their host echo, the new echoes are not recorded when it is full.
+pub const HOST_INOTIFY_MAX_ECHOES: usize = 4096;
+
+// HOST_INOTIFY_ECHO_TIMEOUT is how long in ns a guest event waits for its host
+// echo, a host event after it is taken as made outside of the sandbox.
+pub const HOST_INOTIFY_ECHO_TIMEOUT: i64 = 1_000_000_000;
+
+pub const HOST_INOTIFY_BUF_SIZE: usize = 64 * 1024;
+
+lazy_static! {
+    pub static ref HOST_INOTIFY: HostInotify = HostInotify::New();
+}
+
+pub struct HostInotifyInternal {
+    // fd is the host inotify fd, -1 before the first watch
+    pub fd: i32,
+
+    // watches is the dirents of each host watch descriptor, keyed by the
+    // dirent id. A host file may be reached by more than one dirent, e.g. a
+    // bind mount.
+    pub watches: BTreeMap<i32, BTreeMap<u64, DirentWeak>>,
+
+    // wds is the host watch descriptor of each watched dirent
+    pub wds: BTreeMap<u64, i32>,
+
+    // echoes is the times of the guest events of (wd, name, mask) whose host
+    // events are not received yet, the oldest first
+    pub echoes: BTreeMap<(i32, String, u32), VecDeque<i64>>,
+
+    // echoCnt is the number of the times in echoes
+    pub echoCnt: usize,
+}
+
+impl HostInotifyInternal {
+    // RecordEcho records a guest event at now, the expired echoes are dropped
+    // when the echoes are full.
+    pub fn RecordEcho(&mut self, key: (i32, String, u32), now: i64) {
+        if self.echoCnt >= HOST_INOTIFY_MAX_ECHOES {
+            let mut cnt = 0;
+            self.echoes.retain(|_, times| {
+                times.retain(|t| now - *t < HOST_INOTIFY_ECHO_TIMEOUT);
+                cnt += times.len();
+                times.len() > 0
+            });
+            self.echoCnt = cnt;
+
+            if self.echoCnt >= HOST_INOTIFY_MAX_ECHOES {
+                return;
+            }
+        }
+
+        self.echoes
+            .entry(key)
+            .or_insert(VecDeque::new())
+            .push_back(now);
+        self.echoCnt += 1;
+    }
+
+    // TakeEcho consumes the oldest unexpired echo of the key, it returns false
+    // if there is none.
+    pub fn TakeEcho(&mut self, key: &(i32, String, u32), now: i64) -> bool {
+        let (found, empty) = match self.echoes.get_mut(key) {
+            None => return false,
+            Some(times) => {
+                let mut found = false;
+                while let Some(t) = times.pop_front() {
+                    self.echoCnt -= 1;
+                    if now - t < HOST_INOTIFY_ECHO_TIMEOUT {
+                        found = true;
+                        break;
+                    }
+                }
+                (found, times.len() == 0)
+            }
+        };
+
+        if empty {
+            self.echoes.remove(key);
+        }
+
+        return found;
+    }
+
+    // RemoveEchoes drops the echoes of the host watch descriptor.
+    pub fn RemoveEchoes(&mut self, wd: i32) {
+        let mut cnt = 0;
+        self.echoes.retain(|k, times| {
+            if k.0 == wd {
+                return false;
+            }
+            cnt += times.len();
+            true
+        });
+        self.echoCnt = cnt;
+    }
+}
+
+pub struct HostInotify(QMutex<HostInotifyInternal>);
+
+impl Deref for HostInotify {
+    type Target = QMutex<HostInotifyInternal>;
+
+    fn deref(&self) -> &QMutex<HostInotifyInternal> {
+        &self.0
+    }
+}
+
+// HostFd returns the host fd of the host backed inode.
+pub fn HostFd(inode: &Inode) -> Option<i32> {
+    let iops = inode.lock().InodeOp.clone();
+    if let Some(dirop) = iops.HostDirOp() {
+        return Some(dirop.HostFd());
+    }
+
+    if let Some(iops) = iops.HostInodeOp() {
+        return Some(iops.HostFd());
+    }
+
+    return None;
+}
+
+impl HostInotify {
+    pub fn New() -> Self {
+        return Self(QMutex::new(HostInotifyInternal {
+            fd: -1,
+            watches: BTreeMap::new(),
+            wds: BTreeMap::new(),
+            echoes: BTreeMap::new(),
+            echoCnt: 0,
+        }));
+    }
+
+    pub fn Enabled() -> bool {
+        return SHARESPACE.config.read().HostInotify;
+    }
+
+    // Watch adds a host watch on the dirent if it is host backed.
+    pub fn Watch(&self, dirent: &Dirent) {
+        if !Self::Enabled() {
+            return;
+        }
+
+        let id = dirent.ID();
+        if self.lock().wds.contains_key(&id) {
+            return;
+        }
+
+        let target = match HostFd(&dirent.Inode()) {
+            None => return,
+            Some(fd) => fd,
+        };
+
+        let fd = match self.Fd() {
+            None => return,
+            Some(fd) => fd,
+        };
+
+        let wd = HostSpace::HostInotifyAddWatch(fd, target, HOST_INOTIFY_MASK);
+        if wd < 0 {
+            info!(
+                "HostInotify: add watch of {} fail {}",
+                dirent.MyFullName(),
+                wd
+            );
+            return;
+        }
+
+        let wd = wd as i32;
+        let mut intern = self.lock();
+        intern.wds.insert(id, wd);
+        intern
+            .watches
+            .entry(wd)
+            .or_insert(BTreeMap::new())
+            .insert(id, dirent.Downgrade());
+        dirent.Watches().write().hostWd = wd;
+    }
+
+    // Unwatch removes the dirent which is being destroyed, the host watch is
+    // removed with its last dirent.
+    pub fn Unwatch(&self, id: u64) {
+        let (fd, wd) = {
+            let mut intern = self.lock();
+            let wd = match intern.wds.remove(&id) {
+                None => return,
+                Some(wd) => wd,
+            };
+
+            let empty = match intern.watches.get_mut(&wd) {
+                None => true,
+                Some(dirents) => {
+                    dirents.remove(&id);
+                    dirents.len() == 0
+                }
+            };
+
+            if !empty {
+                return;
+            }
+
+            intern.watches.remove(&wd);
+            intern.RemoveEchoes(wd);
+            (intern.fd, wd)
+        };
+
+        HostSpace::HostInotifyRmWatch(fd, wd);
+    }
+
+    // Fd returns the host inotify fd, it is created with the event process
+    // task on the first watch.
+    pub fn Fd(&self) -> Option<i32> {
+        let mut intern = self.lock();
+        if intern.fd >= 0 {
+            return Some(intern.fd);
+        }
+
+        let fd = HostSpace::HostInotifyInit();
+        if fd < 0 {
+            error!("HostInotify: init fail {}", fd);
+            return None;
+        }
+
+        intern.fd = fd as i32;
+        taskMgr::CreateTask(HostInotifyProcess as u64, fd as *const u8, true);
+        return Some(fd as i32);
+    }
+
+    // Echo records a guest event on a host watched dirent.
+    pub fn Echo(&self, wd: i32, name: &str, events: u32) {
+        let events = events & HOST_INOTIFY_ECHO_MASK;
+        if events == 0 {
+            return;
+        }
+
+        let now = MONOTONIC_CLOCK.Now().0;
+        self.lock().RecordEcho((wd, name.to_string(), events), now);
+    }
+
+    // IsEcho returns true if the host event is caused by the guest.
+    pub fn IsEcho(&self, wd: i32, name: &str, events: u32) -> bool {
+        let events = events & HOST_INOTIFY_ECHO_MASK;
+        if events == 0 {
+            return false;
+        }
+
+        let now = MONOTONIC_CLOCK.Now().0;
+        return self.lock().TakeEcho(&(wd, name.to_string(), events), now);
+    }
+
+    pub fn Dirents(&self, wd: i32) -> Vec<Dirent> {
+        let intern = self.lock();
+        let mut ret = Vec::new();
+        match intern.watches.get(&wd) {
+            None => (),
+            Some(dirents) => {
+                for (_, d) in dirents.iter() {
+                    if let Some(d) = d.Upgrade() {
+                        ret.push(d);
+                    }
+                }
+            }
+        }
+
+        return ret;
+    }
+
+    pub fn ProcessEvent(&self, wd: i32, mask: u32, cookie: u32, name: &str) {
+        if mask & InotifyEvent::IN_Q_OVERFLOW != 0 {
+            // the host events are lost, drop all the cached listings
+            let wds: Vec<i32> = self.lock().watches.keys().cloned().collect();
+            for wd in wds {
+                for d in self.Dirents(wd) {
+                    Invalidate(&d, "", 0);
+                }
+            }
+            return;
+        }
+
+        if mask & InotifyEvent::IN_IGNORED != 0 {
+            // the host file is deleted or unmounted, the watch is gone
+            for d in self.Dirents(wd) {
+                d.Watches().write().hostWd = 0;
+            }
+
+            let mut intern = self.lock();
+            if let Some(ds) = intern.watches.remove(&wd) {
+                for (id, _) in ds {
+                    intern.wds.remove(&id);
+                }
+            }
+            intern.RemoveEchoes(wd);
+            return;
+        }
+
+        let dirents = self.Dirents(wd);
+        let name = match dirents.first() {
+            None => name.to_string(),
+            Some(d) => match PlainName(d, name) {
+                // not a file of the encrypted volume
+                None => return,
+                Some(name) => name,
+            },
+        };
+        let name = &name;
+        let echo = self.IsEcho(wd, name, mask);
+        let events = mask & (HOST_INOTIFY_MASK | InotifyEvent::IN_ISDIR);
+        let et = if name.len() > 0 {
+            EventType::PathEvent
+        } else {
+            EventType::InodeEvent
+        };
+
+        for d in &dirents {
+            Invalidate(d, name, mask);
+            if !echo && SHARESPACE.config.read().EnableInotify {
+                d.Watches().NotifyFromHost(name, events, cookie, et);
+            }
+        }
+    }
+
+    pub fn ProcessEvents(&self, buf: &[u8]) {
+        for ev in ParseHostEvents(buf) {
+            self.ProcessEvent(ev.wd, ev.mask, ev.cookie, &ev.name);
+        }
+    }
+}
+
+// HostInotifyEvent is a struct inotify_event read from the host fd.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostInotifyEvent {
+    pub wd: i32,
+    pub mask: u32,
+    pub cookie: u32,
+    pub name: String,
+}
+
+// ParseHostEvents parses the events read from the host fd, a truncated event
+// ends the buffer.
+pub fn ParseHostEvents(buf: &[u8]) -> Vec<HostInotifyEvent> {
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while pos + INOTIFY_EVENT_BASE_SIZE <= buf.len() {
+        let b = &buf[pos..];
+        let wd = i32::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+        let mask = u32::from_ne_bytes([b[4], b[5], b[6], b[7]]);
+        let cookie = u32::from_ne_bytes([b[8], b[9], b[10], b[11]]);
+        let len = u32::from_ne_bytes([b[12], b[13], b[14], b[15]]) as usize;
+        if INOTIFY_EVENT_BASE_SIZE + len > b.len() {
+            error!(
+                "HostInotify: truncated event wd {} len {} in {} bytes",
+                wd,
+                len,
+                b.len()
+            );
+            break;
+        }
+
+        let name = &b[INOTIFY_EVENT_BASE_SIZE..INOTIFY_EVENT_BASE_SIZE + len];
+        let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        events.push(HostInotifyEvent {
+            wd: wd,
+            mask: mask,
+            cookie: cookie,
+            name: String::from_utf8_lossy(&name[..end]).to_string(),
+        });
+        pos += INOTIFY_EVENT_BASE_SIZE + len;
+    }
+
+    return events;
+}
+
+// PlainName returns the name of the host file in the host directory of the
+// dirent, the ciphertext of the name is decrypted in an encrypted volume.
+pub fn PlainName(dirent: &Dirent, name: &str) -> Option<String> {
+    if name.len() == 0 {
+        return Some(String::new());
+    }
+
+    let iops = dirent.Inode().lock().InodeOp.clone();
+    if let Some(dirop) = iops.HostDirOp() {
+        if let Some(names) = &dirop.lock().cryptNames {
+            return names.DecryptName(name);
+        }
+    }
+
+    return Some(name.to_string());
+}
+
+// Invalidate drops the cached state of the dirent which is changed by the
+// host event.
+pub fn Invalidate(dirent: &Dirent, name: &str, mask: u32) {
+    let iops = dirent.Inode().lock().InodeOp.clone();
+    if let Some(dirop) = iops.HostDirOp() {
+        dirop.lock().readdirCache = None;
+    }
+
+    if name.len() > 0 && mask & HOST_INOTIFY_CHILD_MASK != 0 {
+        dirent.InvalidateChild(name);
+    }
+}
+
+pub fn HostInotifyProcess(fd: *const u8) {
+    let fd = fd as i32;
+    let task = Task::Current();
+    let buf: Vec<u8> = vec![0; HOST_INOTIFY_BUF_SIZE];
+
+    loop {
+        let ret = IOURING.Read(task, fd, &buf[0] as *const _ as u64, buf.len() as u32, -1);
+        if ret < 0 {
+            if ret as i32 == -SysErr::EINTR {
+                continue;
+            }
+
+            error!("HostInotifyProcess: read fail {}", ret);
+            break;
+        }
+
+        HOST_INOTIFY.ProcessEvents(&buf[..ret as usize]);
+    }
+
+    CPULocal::SetPendingFreeStack(task.taskId);
+    taskMgr::SwitchToNewTask();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Event(wd: i32, mask: u32, cookie: u32, name: &str, len: usize) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&wd.to_ne_bytes());
+        buf.extend_from_slice(&mask.to_ne_bytes());
+        buf.extend_from_slice(&cookie.to_ne_bytes());
+        buf.extend_from_slice(&(len as u32).to_ne_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        buf.resize(INOTIFY_EVENT_BASE_SIZE + len, 0);
+        return buf;
+    }
+
+    #[test]
+    fn test_ParseHostEvents() {
+        let mut buf = Event(1, InotifyEvent::IN_CREATE, 0, "file", 16);
+        buf.append(&mut Event(2, InotifyEvent::IN_DELETE_SELF, 0, "", 0));
+        buf.append(&mut Event(
+            1,
+            InotifyEvent::IN_MOVED_TO,
+            7,
+            "0123456789abcdef",
+            16,
+        ));
+
+        let events = ParseHostEvents(&buf);
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            HostInotifyEvent {
+                wd: 1,
+                mask: InotifyEvent::IN_CREATE,
+                cookie: 0,
+                name: "file".to_string(),
+            }
+        );
+        assert_eq!(events[1].wd, 2);
+        assert_eq!(events[1].name, "");
+        assert_eq!(events[2].cookie, 7);
+        assert_eq!(events[2].name, "0123456789abcdef");
+    }
+
+    #[test]
+    fn test_ParseHostEventsTruncated() {
+        let mut buf = Event(1, InotifyEvent::IN_CREATE, 0, "file", 16);
+        buf.append(&mut Event(2, InotifyEvent::IN_CREATE, 0, "other", 16));
+
+        // the name of the second event is cut
+        let events = ParseHostEvents(&buf[..buf.len() - 4]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "file");
+
+        // so is its header
+        let events = ParseHostEvents(&buf[..INOTIFY_EVENT_BASE_SIZE + 16 + 8]);
+        assert_eq!(events.len(), 1);
+
+        // a bogus length can't overflow the buffer
+        let mut buf = Event(1, InotifyEvent::IN_CREATE, 0, "", 0);
+        buf[12..16].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert_eq!(ParseHostEvents(&buf).len(), 0);
+    }
+
+    fn Internal() -> HostInotifyInternal {
+        return HostInotifyInternal {
+            fd: -1,
+            watches: BTreeMap::new(),
+            wds: BTreeMap::new(),
+            echoes: BTreeMap::new(),
+            echoCnt: 0,
+        };
+    }
+
+    #[test]
+    fn test_EchoExpire() {
+        let mut intern = Internal();
+        let key = (1, "file".to_string(), InotifyEvent::IN_CREATE);
+        intern.RecordEcho(key.clone(), 0);
+        intern.RecordEcho(key.clone(), HOST_INOTIFY_ECHO_TIMEOUT);
+        assert_eq!(intern.echoCnt, 2);
+
+        // the first echo has expired, the second one matches
+        assert!(intern.TakeEcho(&key, HOST_INOTIFY_ECHO_TIMEOUT + 1));
+        assert_eq!(intern.echoCnt, 0);
+        assert!(intern.echoes.is_empty());
+
+        // a genuine host event long after the guest one is not suppressed
+        intern.RecordEcho(key.clone(), 0);
+        assert!(!intern.TakeEcho(&key, 2 * HOST_INOTIFY_ECHO_TIMEOUT));
+        assert!(intern.echoes.is_empty());
+    }
+
+    #[test]
+    fn test_EchoFull() {
+        let mut intern = Internal();
+        for i in 0..HOST_INOTIFY_MAX_ECHOES {
+            intern.RecordEcho((1, format!("{}", i), InotifyEvent::IN_CREATE), 0);
+        }
+
+        // the live echoes are kept and the new one is not recorded
+        let key = (2, "file".to_string(), InotifyEvent::IN_DELETE);
+        intern.RecordEcho(key.clone(), 1);
+        assert_eq!(intern.echoCnt, HOST_INOTIFY_MAX_ECHOES);
+        assert!(!intern.TakeEcho(&key, 1));
+
+        // the expired ones are dropped to make room
+        intern.RecordEcho(key.clone(), HOST_INOTIFY_ECHO_TIMEOUT);
+        assert_eq!(intern.echoCnt, 1);
+        assert!(intern.TakeEcho(&key, HOST_INOTIFY_ECHO_TIMEOUT));
+
+        intern.RecordEcho(key.clone(), 0);
+        intern.RemoveEchoes(2);
+        assert_eq!(intern.echoCnt, 0);
+    }
+}
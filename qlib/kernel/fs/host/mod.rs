@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod crypt;
 pub mod dirent;
 pub mod diriops;
 pub mod fifoiops;
@@ -19,7 +20,9 @@ pub mod fs;
 pub mod hostdirfops;
 pub mod hostfileop;
 pub mod hostinodeop;
+pub mod hostinotify;
 pub mod ioctl;
+pub mod quota;
 pub mod socket_iovec;
 pub mod tty;
 pub mod util;
@@ -0,0 +1,417 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::quota::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::super::loader::DiskQuotaLimits;
+use super::super::inode::*;
+use super::super::mount::*;
+
+// MountQuota returns the quota of the mount of the inode.
+pub fn MountQuota(inode: &Inode) -> Option<HostQuota> {
+    let msrc = inode.lock().MountSource.clone();
+    let quota = msrc.lock().quota.clone();
+    return quota;
+}
+
+// WithinLimit returns whether the hard limit is within the operator limit,
+// 0 is unlimited for both.
+fn WithinLimit(hardLimit: u64, limit: u64) -> bool {
+    return limit == 0 || (hardLimit != 0 && hardLimit <= limit);
+}
+
+pub struct HostQuotaIntern {
+    // enabled is cleared by Q_QUOTAOFF, the usage is still counted but the
+    // limits are not enforced
+    pub enabled: bool,
+
+    // the hard limits fail the charges with EDQUOT, the soft limits are only
+    // reported. 0 is unlimited
+    pub bytesHardLimit: u64,
+    pub bytesSoftLimit: u64,
+    pub inodesHardLimit: u64,
+    pub inodesSoftLimit: u64,
+
+    // bytes and inodes are charged by the file growth and the creations done
+    // in the sandbox
+    pub bytes: u64,
+    pub inodes: u64,
+
+    // bytesGrace and inodesGrace are the grace periods in seconds
+    pub bytesGrace: u64,
+    pub inodesGrace: u64,
+
+    // limits are the limits set by the operator, quotactl can't turn the
+    // quota off or set the hard limits above them
+    pub limits: DiskQuotaLimits,
+
+    // charged is what each host file is charged with by its device and inode
+    // number, the files which were on the mount before the sandbox started
+    // are only charged with their growth
+    pub charged: BTreeMap<(u64, u64), QuotaCharge>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QuotaCharge {
+    // inode is set if the file was created in the sandbox
+    pub inode: bool,
+    pub bytes: u64,
+}
+
+// HostQuota is the byte and inode quota of a host-backed mount. The host file
+// system is shared with the other pods, so the usage only counts what the
+// sandbox adds to the mount instead of the host file system usage.
+#[derive(Clone)]
+pub struct HostQuota(Arc<QMutex<HostQuotaIntern>>);
+
+impl Deref for HostQuota {
+    type Target = Arc<QMutex<HostQuotaIntern>>;
+
+    fn deref(&self) -> &Arc<QMutex<HostQuotaIntern>> {
+        &self.0
+    }
+}
+
+impl HostQuota {
+    pub fn New(limits: &DiskQuotaLimits) -> Self {
+        return Self(Arc::new(QMutex::new(HostQuotaIntern {
+            enabled: limits.Bytes != 0 || limits.Inodes != 0,
+            bytesHardLimit: limits.Bytes,
+            bytesSoftLimit: 0,
+            inodesHardLimit: limits.Inodes,
+            inodesSoftLimit: 0,
+            bytes: 0,
+            inodes: 0,
+            bytesGrace: MAX_DQ_TIME,
+            inodesGrace: MAX_IQ_TIME,
+            limits: *limits,
+            charged: BTreeMap::new(),
+        })));
+    }
+
+    pub fn Enabled(&self) -> bool {
+        return self.lock().enabled;
+    }
+
+    pub fn SetEnabled(&self, enabled: bool) -> Result<()> {
+        let mut q = self.lock();
+        if !enabled && (q.limits.Bytes != 0 || q.limits.Inodes != 0) {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
+        if q.enabled == enabled {
+            return Err(Error::SysError(if enabled {
+                SysErr::EBUSY
+            } else {
+                SysErr::ESRCH
+            }));
+        }
+
+        q.enabled = enabled;
+        return Ok(());
+    }
+
+    // ChargeBytes charges the growth of the file.
+    pub fn ChargeBytes(&self, file: (u64, u64), bytes: u64) -> Result<()> {
+        let mut q = self.lock();
+        if q.enabled && q.bytesHardLimit != 0 && q.bytes + bytes > q.bytesHardLimit {
+            return Err(Error::SysError(SysErr::EDQUOT));
+        }
+
+        q.bytes += bytes;
+        q.charged.entry(file).or_default().bytes += bytes;
+        return Ok(());
+    }
+
+    // UnchargeBytes uncharges up to the bytes the file is charged with, the
+    // content the file had before the sandbox started is not charged.
+    pub fn UnchargeBytes(&self, file: (u64, u64), bytes: u64) {
+        let mut q = self.lock();
+        let charge = match q.charged.get_mut(&file) {
+            None => return,
+            Some(charge) => charge,
+        };
+
+        let bytes = bytes.min(charge.bytes);
+        charge.bytes -= bytes;
+        if !charge.inode && charge.bytes == 0 {
+            q.charged.remove(&file);
+        }
+
+        q.bytes = q.bytes.saturating_sub(bytes);
+    }
+
+    // ChargeInode reserves an inode for a file about to be created, Created
+    // records the file once it exists and UnchargeInode drops the reservation
+    // if the creation fails.
+    pub fn ChargeInode(&self) -> Result<()> {
+        let mut q = self.lock();
+        if q.enabled && q.inodesHardLimit != 0 && q.inodes >= q.inodesHardLimit {
+            return Err(Error::SysError(SysErr::EDQUOT));
+        }
+
+        q.inodes += 1;
+        return Ok(());
+    }
+
+    pub fn UnchargeInode(&self) {
+        let mut q = self.lock();
+        q.inodes = q.inodes.saturating_sub(1);
+    }
+
+    pub fn Created(&self, file: (u64, u64)) {
+        let mut q = self.lock();
+        q.charged.entry(file).or_default().inode = true;
+    }
+
+    // Removed uncharges what the file was charged with when its last link is
+    // removed.
+    pub fn Removed(&self, file: (u64, u64)) {
+        let mut q = self.lock();
+        let charge = match q.charged.remove(&file) {
+            None => return,
+            Some(charge) => charge,
+        };
+
+        if charge.inode {
+            q.inodes = q.inodes.saturating_sub(1);
+        }
+
+        q.bytes = q.bytes.saturating_sub(charge.bytes);
+    }
+
+    // FsInfo caps the host file system info with the hard limits, so that df
+    // shows the quota as the size of the mount.
+    pub fn FsInfo(&self, mut info: FsInfo, blockSize: i64) -> FsInfo {
+        let q = self.lock();
+        if !q.enabled {
+            return info;
+        }
+
+        if q.bytesHardLimit != 0 && blockSize > 0 {
+            let blockSize = blockSize as u64;
+            let total = q.bytesHardLimit / blockSize;
+            let used = (q.bytes + blockSize - 1) / blockSize;
+            info.TotalBlocks = info.TotalBlocks.min(total);
+            info.FreeBlocks = info.FreeBlocks.min(total.saturating_sub(used));
+        }
+
+        if q.inodesHardLimit != 0 {
+            info.TotalFiles = info.TotalFiles.min(q.inodesHardLimit);
+            info.FreeFiles = info
+                .FreeFiles
+                .min(q.inodesHardLimit.saturating_sub(q.inodes));
+        }
+
+        return info;
+    }
+
+    pub fn Dqblk(&self) -> IfDqblk {
+        let q = self.lock();
+        return IfDqblk {
+            BHardLimit: q.bytesHardLimit >> QIF_DQBLKSIZE_BITS,
+            BSoftLimit: q.bytesSoftLimit >> QIF_DQBLKSIZE_BITS,
+            CurSpace: q.bytes,
+            IHardLimit: q.inodesHardLimit,
+            ISoftLimit: q.inodesSoftLimit,
+            CurInodes: q.inodes,
+            BTime: 0,
+            ITime: 0,
+            Valid: QIF_ALL,
+        };
+    }
+
+    // SetDqblk sets the fields of dqblk marked valid. The usage is counted by
+    // the sandbox and the grace times are not tracked, so they can't be set.
+    // The hard limits can't be raised above the operator limits or cleared.
+    pub fn SetDqblk(&self, dqblk: &IfDqblk) -> Result<()> {
+        if dqblk.Valid & !QIF_ALL != 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let bytesHardLimit = dqblk.BHardLimit.saturating_mul(QIF_DQBLKSIZE);
+        let bytesSoftLimit = dqblk.BSoftLimit.saturating_mul(QIF_DQBLKSIZE);
+
+        let mut q = self.lock();
+        if dqblk.Valid & QIF_BLIMITS != 0 {
+            if !WithinLimit(bytesHardLimit, q.limits.Bytes) {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+
+        if dqblk.Valid & QIF_ILIMITS != 0 {
+            if !WithinLimit(dqblk.IHardLimit, q.limits.Inodes) {
+                return Err(Error::SysError(SysErr::EPERM));
+            }
+        }
+
+        if dqblk.Valid & QIF_BLIMITS != 0 {
+            q.bytesHardLimit = bytesHardLimit;
+            q.bytesSoftLimit = bytesSoftLimit;
+        }
+
+        if dqblk.Valid & QIF_ILIMITS != 0 {
+            q.inodesHardLimit = dqblk.IHardLimit;
+            q.inodesSoftLimit = dqblk.ISoftLimit;
+        }
+
+        return Ok(());
+    }
+
+    pub fn Dqinfo(&self) -> IfDqinfo {
+        let q = self.lock();
+        return IfDqinfo {
+            BGrace: q.bytesGrace,
+            IGrace: q.inodesGrace,
+            Flags: 0,
+            Valid: IIF_ALL,
+        };
+    }
+
+    pub fn SetDqinfo(&self, dqinfo: &IfDqinfo) -> Result<()> {
+        if dqinfo.Valid & !IIF_ALL != 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut q = self.lock();
+        if dqinfo.Valid & IIF_BGRACE != 0 {
+            q.bytesGrace = dqinfo.BGrace;
+        }
+
+        if dqinfo.Valid & IIF_IGRACE != 0 {
+            q.inodesGrace = dqinfo.IGrace;
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Limits(bytes: u64, inodes: u64) -> DiskQuotaLimits {
+        return DiskQuotaLimits {
+            Bytes: bytes,
+            Inodes: inodes,
+        };
+    }
+
+    fn IsErr<T>(res: Result<T>, errno: i32) -> bool {
+        match res {
+            Err(Error::SysError(e)) => return e == errno,
+            _ => return false,
+        }
+    }
+
+    #[test]
+    fn test_ChargeBytes() {
+        let quota = HostQuota::New(&Limits(100, 0));
+        quota.ChargeBytes((1, 1), 60).unwrap();
+        assert!(IsErr(quota.ChargeBytes((1, 2), 50), SysErr::EDQUOT));
+        quota.ChargeBytes((1, 2), 40).unwrap();
+        assert_eq!(quota.lock().bytes, 100);
+
+        quota.UnchargeBytes((1, 1), 70);
+        assert_eq!(quota.lock().bytes, 40);
+        assert!(quota.lock().charged.get(&(1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_UnchargeUncharged() {
+        let quota = HostQuota::New(&Limits(100, 0));
+        quota.ChargeBytes((1, 1), 10).unwrap();
+
+        // the file was on the mount before the sandbox started
+        quota.UnchargeBytes((1, 2), 10);
+        quota.Removed((1, 2));
+        assert_eq!(quota.lock().bytes, 10);
+    }
+
+    #[test]
+    fn test_Removed() {
+        let quota = HostQuota::New(&Limits(0, 2));
+        quota.ChargeInode().unwrap();
+        quota.Created((1, 1));
+        quota.ChargeInode().unwrap();
+        assert!(IsErr(quota.ChargeInode(), SysErr::EDQUOT));
+        quota.UnchargeInode();
+
+        quota.ChargeBytes((1, 1), 30).unwrap();
+        quota.ChargeBytes((1, 2), 20).unwrap();
+        quota.Removed((1, 1));
+        assert_eq!(quota.lock().inodes, 0);
+        assert_eq!(quota.lock().bytes, 20);
+
+        // only the growth of an old file is uncharged
+        quota.Removed((1, 2));
+        assert_eq!(quota.lock().inodes, 0);
+        assert_eq!(quota.lock().bytes, 0);
+    }
+
+    #[test]
+    fn test_OperatorLimits() {
+        let quota = HostQuota::New(&Limits(4 * QIF_DQBLKSIZE, 10));
+        assert!(IsErr(quota.SetEnabled(false), SysErr::EPERM));
+
+        let mut dqblk = IfDqblk {
+            BHardLimit: 8,
+            Valid: QIF_BLIMITS,
+            ..Default::default()
+        };
+        assert!(IsErr(quota.SetDqblk(&dqblk), SysErr::EPERM));
+        dqblk.BHardLimit = 0;
+        assert!(IsErr(quota.SetDqblk(&dqblk), SysErr::EPERM));
+        dqblk.BHardLimit = u64::MAX;
+        assert!(IsErr(quota.SetDqblk(&dqblk), SysErr::EPERM));
+
+        dqblk.BHardLimit = 2;
+        quota.SetDqblk(&dqblk).unwrap();
+        assert_eq!(quota.Dqblk().BHardLimit, 2);
+
+        let dqblk = IfDqblk {
+            IHardLimit: 11,
+            Valid: QIF_ILIMITS,
+            ..Default::default()
+        };
+        assert!(IsErr(quota.SetDqblk(&dqblk), SysErr::EPERM));
+    }
+
+    #[test]
+    fn test_Unlimited() {
+        let quota = HostQuota::New(&Limits(0, 0));
+        assert!(!quota.Enabled());
+        quota.SetEnabled(true).unwrap();
+        assert!(IsErr(quota.SetEnabled(true), SysErr::EBUSY));
+
+        let dqblk = IfDqblk {
+            BHardLimit: 1,
+            Valid: QIF_BLIMITS,
+            ..Default::default()
+        };
+        quota.SetDqblk(&dqblk).unwrap();
+        assert!(IsErr(
+            quota.ChargeBytes((1, 1), QIF_DQBLKSIZE + 1),
+            SysErr::EDQUOT
+        ));
+        quota.SetEnabled(false).unwrap();
+        quota.ChargeBytes((1, 1), QIF_DQBLKSIZE + 1).unwrap();
+    }
+}
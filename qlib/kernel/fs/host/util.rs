@@ -524,7 +524,15 @@ pub fn Rename(
         None => panic!("&InodeOp isn't a HostInodeOp!"),
     };
 
-    let ret = RenameAt(oldParent.HostFd(), oldname, newParent.HostFd(), newname);
+    // the names are encrypted with the key of the volume
+    let oldPolicy = oldParent.lock().cryptPolicy.clone();
+    if oldPolicy != newParent.lock().cryptPolicy {
+        return Err(Error::SysError(SysErr::EXDEV));
+    }
+
+    let oldname = oldParent.HostName(oldname)?;
+    let newname = newParent.HostName(newname)?;
+    let ret = RenameAt(oldParent.HostFd(), &oldname, newParent.HostFd(), &newname);
 
     if ret < 0 {
         return Err(Error::SysError(-ret as i32));
@@ -0,0 +1,179 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::super::lrc_cache::*;
+use super::super::super::fd::*;
+use super::super::super::quring::throttle::IO_THROTTLES;
+use super::super::super::task::*;
+use super::super::super::Kernel::HostSpace;
+use super::super::super::IOURING;
+use super::super::super::SHARESPACE;
+use super::super::host::hostinodeop::*;
+
+// CACHE_PAGE_SIZE is the unit the image is read from the host and cached in,
+// larger than the file system blocks to take the neighbour blocks along.
+pub const CACHE_PAGE_SIZE: u64 = 0x10000;
+const CACHE_PAGE_MASK: u64 = CACHE_PAGE_SIZE - 1;
+
+// CACHE_PAGES is the number of cache pages of an image, 64MB.
+pub const CACHE_PAGES: u64 = 1024;
+
+pub struct ImageDeviceIntern {
+    pub fd: i32,
+    pub size: u64,
+
+    // file is the file the image was opened from inside the sandbox, it owns
+    // the fd then.
+    pub file: Option<HostInodeOp>,
+    pub cache: QMutex<LruCache<Arc<Vec<u8>>>>,
+}
+
+impl Drop for ImageDeviceIntern {
+    fn drop(&mut self) {
+        if self.file.is_none() {
+            HostSpace::Close(self.fd);
+        }
+    }
+}
+
+// ImageDevice is the image file a file system image is mounted from. Every
+// read of the file system goes through the page cache of the device, one
+// host fd serves the whole mount.
+#[derive(Clone)]
+pub struct ImageDevice(pub Arc<ImageDeviceIntern>);
+
+impl Deref for ImageDevice {
+    type Target = Arc<ImageDeviceIntern>;
+
+    fn deref(&self) -> &Arc<ImageDeviceIntern> {
+        &self.0
+    }
+}
+
+impl ImageDevice {
+    pub fn New(fd: i32, size: u64, file: Option<HostInodeOp>) -> Self {
+        let intern = ImageDeviceIntern {
+            fd: fd,
+            size: size,
+            file: file,
+            cache: QMutex::new(LruCache::New(CACHE_PAGES)),
+        };
+
+        return Self(Arc::new(intern));
+    }
+
+    fn ReadHost(&self, task: &Task, buf: &mut [u8], offset: u64) -> Result<usize> {
+        IO_THROTTLES.Throttle(task, false, buf.len() as u64);
+        let mut count = 0;
+        while count < buf.len() {
+            let ptr = &mut buf[count] as *mut u8 as u64;
+            let len = buf.len() - count;
+            let ret = if SHARESPACE.config.read().UringIO {
+                let ret = IOURING.FileRead(
+                    task,
+                    self.fd,
+                    ptr,
+                    len as u32,
+                    (offset + count as u64) as i64,
+                );
+                if ret < 0 {
+                    return Err(Error::SysError(-ret as i32));
+                }
+                ret
+            } else {
+                let iov = IoVec::NewFromAddr(ptr, len);
+                IOReadAt(self.fd, &[iov], offset + count as u64)?
+            };
+
+            if ret == 0 {
+                break;
+            }
+            count += ret as usize;
+        }
+
+        return Ok(count);
+    }
+
+    fn Page(&self, task: &Task, index: u64) -> Result<Arc<Vec<u8>>> {
+        {
+            let mut cache = self.cache.lock();
+            if let Some(page) = cache.Get(index) {
+                // move the page to the front of the lru list
+                cache.Add(index, page.clone());
+                return Ok(page);
+            }
+        }
+
+        // the cache isn't locked over the host read, two readers of a page
+        // may both read it
+        let mut page = Vec::with_capacity(CACHE_PAGE_SIZE as usize);
+        page.resize(CACHE_PAGE_SIZE as usize, 0);
+        let len = self.ReadHost(task, &mut page, index * CACHE_PAGE_SIZE)?;
+        page.truncate(len);
+
+        let page = Arc::new(page);
+        self.cache.lock().Add(index, page.clone());
+        return Ok(page);
+    }
+
+    // ReadAt fills buf with the image content at offset, the image must hold
+    // the whole range.
+    pub fn ReadAt(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        let end = match offset.checked_add(buf.len() as u64) {
+            Some(end) if end <= self.size => end,
+            _ => {
+                info!("image read {:x}+{:x} beyond the image", offset, buf.len());
+                return Err(Error::SysError(SysErr::EIO));
+            }
+        };
+
+        let task = Task::Current();
+        let mut pos = offset;
+        while pos < end {
+            let page = self.Page(task, pos / CACHE_PAGE_SIZE)?;
+            let start = (pos & CACHE_PAGE_MASK) as usize;
+            let len = core::cmp::min(end - pos, CACHE_PAGE_SIZE - start as u64) as usize;
+            if start + len > page.len() {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+
+            let dst = (pos - offset) as usize;
+            buf[dst..dst + len].copy_from_slice(&page[start..start + len]);
+            pos += len as u64;
+        }
+
+        return Ok(());
+    }
+
+    pub fn Read(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(len);
+        buf.resize(len, 0);
+        self.ReadAt(&mut buf, offset)?;
+        return Ok(buf);
+    }
+
+    // ReadObj reads an on-disk structure at offset.
+    pub fn ReadObj<T: Copy>(&self, offset: u64) -> Result<T> {
+        let buf = self.Read(offset, core::mem::size_of::<T>())?;
+        let obj = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const T) };
+        return Ok(obj);
+    }
+}
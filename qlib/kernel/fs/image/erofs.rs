@@ -0,0 +1,446 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::time::*;
+use super::super::mount::*;
+use super::device::*;
+use super::fs::*;
+
+// The erofs on-disk format, see Documentation/filesystems/erofs of Linux.
+const SUPER_OFFSET: u64 = 1024;
+const SUPER_SIZE: usize = 128;
+
+const S_MAGIC: usize = 0x0;
+const S_BLKSZBITS: usize = 0xc;
+const S_ROOT_NID: usize = 0xe;
+const S_INOS: usize = 0x10;
+const S_BUILD_TIME: usize = 0x18;
+const S_BUILD_TIME_NSEC: usize = 0x20;
+const S_BLOCKS: usize = 0x24;
+const S_META_BLKADDR: usize = 0x28;
+const S_EXTRA_DEVICES: usize = 0x56;
+
+const MIN_BLKSZBITS: u8 = 9;
+const MAX_BLKSZBITS: u8 = 16;
+
+const ISLOT_BITS: u64 = 5;
+const INODE_COMPACT_SIZE: usize = 32;
+const INODE_EXTENDED_SIZE: usize = 64;
+
+const I_FORMAT: usize = 0x0;
+const I_XATTR_ICOUNT: usize = 0x2;
+const I_MODE: usize = 0x4;
+const I_U: usize = 0x10;
+
+// the fields of the compact inodes
+const IC_NLINK: usize = 0x6;
+const IC_SIZE: usize = 0x8;
+const IC_UID: usize = 0x18;
+const IC_GID: usize = 0x1a;
+
+// the fields of the extended inodes
+const IE_SIZE: usize = 0x8;
+const IE_UID: usize = 0x18;
+const IE_GID: usize = 0x1c;
+const IE_MTIME: usize = 0x20;
+const IE_MTIME_NSEC: usize = 0x28;
+const IE_NLINK: usize = 0x2c;
+
+const XATTR_IBODY_HEADER_SIZE: u64 = 12;
+const XATTR_ENTRY_SIZE: u64 = 4;
+
+const LAYOUT_FLAT_PLAIN: u16 = 0;
+const LAYOUT_COMPRESSED_FULL: u16 = 1;
+const LAYOUT_FLAT_INLINE: u16 = 2;
+const LAYOUT_COMPRESSED_COMPACT: u16 = 3;
+const LAYOUT_CHUNK_BASED: u16 = 4;
+
+const CHUNK_FORMAT_BLKBITS_MASK: u32 = 0x1f;
+const CHUNK_FORMAT_INDEXES: u32 = 0x20;
+const CHUNK_INDEX_SIZE: u64 = 8;
+const CHUNK_INDEX_BLKADDR: u64 = 4;
+const CHUNK_BLOCK_MAP_SIZE: u64 = 4;
+const NULL_ADDR: u32 = 0xffffffff;
+
+const DIRENT_SIZE: usize = 12;
+
+pub struct ErofsFs {
+    pub dev: ImageDevice,
+    pub blockSize: u64,
+    pub blkbits: u8,
+    pub metaBlk: u64,
+    pub rootNid: u64,
+    pub inos: u64,
+    pub blocks: u64,
+
+    // buildTime is the mtime of the compact inodes.
+    pub buildTime: Time,
+}
+
+// ParseDirBlock adds the entries of a directory block to entries. The
+// dirents are at the start of the block, the name offset of the first one
+// gives their count and each name ends where the next one starts.
+fn ParseDirBlock(block: &[u8], entries: &mut BTreeMap<String, ImageDirent>) -> Result<()> {
+    let count = Le16(block, 8) as usize / DIRENT_SIZE;
+    if count == 0 || count * DIRENT_SIZE > block.len() {
+        return Err(Error::SysError(SysErr::EIO));
+    }
+
+    for i in 0..count {
+        let d = i * DIRENT_SIZE;
+        let start = Le16(block, d + 8) as usize;
+        let end = if i + 1 < count {
+            Le16(block, d + DIRENT_SIZE + 8) as usize
+        } else {
+            block.len()
+        };
+
+        if start > end || end > block.len() {
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        // the last name of a block may be padded with zeros
+        let mut name = &block[start..end];
+        while let Some((&0, rest)) = name.split_last() {
+            name = rest;
+        }
+
+        let name = String::from_utf8_lossy(name).to_string();
+        if name.len() == 0 || name == "." || name == ".." {
+            continue;
+        }
+
+        entries.insert(
+            name,
+            ImageDirent {
+                ino: Le64(block, d),
+                fileType: block[d + 10],
+            },
+        );
+    }
+
+    return Ok(());
+}
+
+impl ErofsFs {
+    pub fn Probe(dev: &ImageDevice) -> bool {
+        return match dev.ReadObj::<u32>(SUPER_OFFSET + S_MAGIC as u64) {
+            Ok(magic) => magic as u64 == FSMagic::EROFS_SUPER_MAGIC_V1,
+            Err(_) => false,
+        };
+    }
+
+    pub fn New(dev: &ImageDevice) -> Result<Self> {
+        let sb = dev.Read(SUPER_OFFSET, SUPER_SIZE)?;
+        if Le32(&sb, S_MAGIC) as u64 != FSMagic::EROFS_SUPER_MAGIC_V1 {
+            info!("erofs image has no erofs super block");
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let blkbits = sb[S_BLKSZBITS];
+        if blkbits < MIN_BLKSZBITS || blkbits > MAX_BLKSZBITS {
+            info!("erofs image has unsupported block size bits {}", blkbits);
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if Le16(&sb, S_EXTRA_DEVICES) != 0 {
+            info!("erofs image with extra devices is not supported");
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let buildTime = Time::FromUnix(
+            Le64(&sb, S_BUILD_TIME) as i64,
+            Le32(&sb, S_BUILD_TIME_NSEC) as i64,
+        );
+
+        return Ok(Self {
+            dev: dev.clone(),
+            blockSize: 1 << blkbits,
+            blkbits: blkbits,
+            metaBlk: Le32(&sb, S_META_BLKADDR) as u64,
+            rootNid: Le16(&sb, S_ROOT_NID) as u64,
+            inos: Le64(&sb, S_INOS),
+            blocks: Le32(&sb, S_BLOCKS) as u64,
+            buildTime: buildTime,
+        });
+    }
+
+    // Node reads the inode of nid, the nid is the inode number as well.
+    pub fn Node(&self, nid: u64) -> Result<ImageNode> {
+        let pos = (self.metaBlk << self.blkbits) + (nid << ISLOT_BITS);
+        let format = self.dev.ReadObj::<u16>(pos + I_FORMAT as u64)?;
+        let extended = format & 0x1 != 0;
+        let layout = (format >> 1) & 0x7;
+
+        let inodeSize = if extended {
+            INODE_EXTENDED_SIZE
+        } else {
+            INODE_COMPACT_SIZE
+        };
+        let raw = self.dev.Read(pos, inodeSize)?;
+
+        let (size, uid, gid, links, mtime) = if extended {
+            let mtime = Time::FromUnix(
+                Le64(&raw, IE_MTIME) as i64,
+                Le32(&raw, IE_MTIME_NSEC) as i64,
+            );
+            (
+                Le64(&raw, IE_SIZE),
+                Le32(&raw, IE_UID),
+                Le32(&raw, IE_GID),
+                Le32(&raw, IE_NLINK) as u64,
+                mtime,
+            )
+        } else {
+            (
+                Le32(&raw, IC_SIZE) as u64,
+                Le16(&raw, IC_UID) as u32,
+                Le16(&raw, IC_GID) as u32,
+                Le16(&raw, IC_NLINK) as u64,
+                self.buildTime,
+            )
+        };
+
+        let icount = Le16(&raw, I_XATTR_ICOUNT) as u64;
+        let xattrSize = if icount == 0 {
+            0
+        } else {
+            XATTR_IBODY_HEADER_SIZE + (icount - 1) * XATTR_ENTRY_SIZE
+        };
+
+        let mode = Le16(&raw, I_MODE);
+        let iu = Le32(&raw, I_U);
+        let rdev = match mode & ModeType::S_IFMT {
+            ModeType::S_IFCHR | ModeType::S_IFBLK | ModeType::S_IFIFO | ModeType::S_IFSOCK => iu,
+            _ => 0,
+        };
+
+        let usage = (size + self.blockSize - 1) & !(self.blockSize - 1);
+        return Ok(ImageNode {
+            ino: nid,
+            mode: mode,
+            uid: uid,
+            gid: gid,
+            size: size,
+            links: links,
+            usage: usage,
+            atime: mtime,
+            mtime: mtime,
+            ctime: mtime,
+            rdev: rdev,
+            layout: ImageLayout::Erofs {
+                layout: layout,
+                raw: iu,
+                tail: pos + inodeSize as u64 + xattrSize,
+            },
+        });
+    }
+
+    // Map returns the image offset of the file content at pos, None for a
+    // hole, and the count of bytes the mapping holds for.
+    fn Map(
+        &self,
+        layout: u16,
+        raw: u32,
+        tail: u64,
+        size: u64,
+        pos: u64,
+    ) -> Result<(Option<u64>, u64)> {
+        match layout {
+            LAYOUT_FLAT_PLAIN => {
+                return Ok((Some(((raw as u64) << self.blkbits) + pos), size - pos));
+            }
+            LAYOUT_FLAT_INLINE => {
+                // the last block is packed after the inode
+                let blocks = (size + self.blockSize - 1) >> self.blkbits;
+                let inlineStart = (blocks - 1) << self.blkbits;
+                if pos < inlineStart {
+                    return Ok((
+                        Some(((raw as u64) << self.blkbits) + pos),
+                        inlineStart - pos,
+                    ));
+                }
+
+                return Ok((Some(tail + pos - inlineStart), size - pos));
+            }
+            LAYOUT_CHUNK_BASED => {
+                let chunkBits = self.blkbits as u32 + (raw & CHUNK_FORMAT_BLKBITS_MASK);
+                if chunkBits >= 64 {
+                    return Err(Error::SysError(SysErr::EIO));
+                }
+
+                let chunk = pos >> chunkBits;
+                let inChunk = pos & ((1 << chunkBits) - 1);
+                let count = (1u64 << chunkBits) - inChunk;
+                let blkaddr = if raw & CHUNK_FORMAT_INDEXES != 0 {
+                    let indexes = (tail + CHUNK_INDEX_SIZE - 1) & !(CHUNK_INDEX_SIZE - 1);
+                    self.dev
+                        .ReadObj::<u32>(indexes + chunk * CHUNK_INDEX_SIZE + CHUNK_INDEX_BLKADDR)?
+                } else {
+                    let map = (tail + CHUNK_BLOCK_MAP_SIZE - 1) & !(CHUNK_BLOCK_MAP_SIZE - 1);
+                    self.dev
+                        .ReadObj::<u32>(map + chunk * CHUNK_BLOCK_MAP_SIZE)?
+                };
+
+                if blkaddr == NULL_ADDR {
+                    return Ok((None, count));
+                }
+
+                return Ok((Some(((blkaddr as u64) << self.blkbits) + inChunk), count));
+            }
+            LAYOUT_COMPRESSED_FULL | LAYOUT_COMPRESSED_COMPACT => {
+                return Err(Error::SysError(SysErr::EOPNOTSUPP));
+            }
+            _ => {
+                info!("erofs inode has unknown data layout {}", layout);
+                return Err(Error::SysError(SysErr::EIO));
+            }
+        }
+    }
+
+    pub fn Read(&self, node: &ImageNode, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let (layout, raw, tail) = match node.layout {
+            ImageLayout::Erofs { layout, raw, tail } => (layout, raw, tail),
+            _ => return Err(Error::SysError(SysErr::EIO)),
+        };
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let (addr, count) = self.Map(layout, raw, tail, node.size, pos)?;
+            let len = core::cmp::min(count, (buf.len() - done) as u64) as usize;
+            match addr {
+                None => {
+                    for b in &mut buf[done..done + len] {
+                        *b = 0;
+                    }
+                }
+                Some(addr) => {
+                    self.dev.ReadAt(&mut buf[done..done + len], addr)?;
+                }
+            }
+            done += len;
+        }
+
+        return Ok(());
+    }
+
+    // ReadDir reads the directory blocks one at a time, each starts with the
+    // entries and ends with their names.
+    pub fn ReadDir(&self, node: &ImageNode) -> Result<BTreeMap<String, ImageDirent>> {
+        // the size of a corrupted directory can't be trusted
+        if node.size > self.dev.size {
+            info!("erofs directory {} is corrupted", node.ino);
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        let mut data = DataBuff::New(self.blockSize as usize);
+        let mut entries = BTreeMap::new();
+        let mut offset = 0;
+        while offset < node.size {
+            let len = core::cmp::min(self.blockSize, node.size - offset) as usize;
+            if len < DIRENT_SIZE {
+                break;
+            }
+
+            let block = &mut data.buf[..len];
+            self.Read(node, offset, block)?;
+            if let Err(e) = ParseDirBlock(block, &mut entries) {
+                info!("erofs directory {} is corrupted", node.ino);
+                return Err(e);
+            }
+
+            offset += self.blockSize;
+        }
+
+        return Ok(entries);
+    }
+
+    pub fn StatFS(&self) -> FsInfo {
+        return FsInfo {
+            Type: FSMagic::EROFS_SUPER_MAGIC_V1,
+            TotalBlocks: self.blocks,
+            FreeBlocks: 0,
+            TotalFiles: self.inos,
+            FreeFiles: 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Block(names: &[(&str, u64, u8)], size: usize) -> Vec<u8> {
+        let mut block = Vec::new();
+        let mut nameOff = names.len() * DIRENT_SIZE;
+        for (name, nid, ft) in names {
+            block.extend_from_slice(&nid.to_le_bytes());
+            block.extend_from_slice(&(nameOff as u16).to_le_bytes());
+            block.push(*ft);
+            block.push(0);
+            nameOff += name.len();
+        }
+
+        for (name, _, _) in names {
+            block.extend_from_slice(name.as_bytes());
+        }
+        block.resize(size, 0);
+        return block;
+    }
+
+    #[test]
+    fn test_ParseDirBlock() {
+        let names = [(".", 36, 2), ("..", 36, 2), ("a", 40, 1), ("bb", 41, 2)];
+        let mut entries = BTreeMap::new();
+        ParseDirBlock(&Block(&names, 64), &mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["a"].ino, 40);
+        assert_eq!(entries["a"].fileType, 1);
+
+        // the padding of the last name is dropped
+        assert_eq!(entries["bb"].ino, 41);
+        assert_eq!(entries["bb"].fileType, 2);
+
+        // the last name can fill the block
+        let mut entries = BTreeMap::new();
+        ParseDirBlock(&Block(&names, 54), &mut entries).unwrap();
+        assert_eq!(entries["bb"].ino, 41);
+    }
+
+    #[test]
+    fn test_ParseDirBlockCorrupted() {
+        let names = [("a", 40, 1), ("bb", 41, 2)];
+        let mut entries = BTreeMap::new();
+
+        let mut block = Block(&names, 64);
+        block[8] = 0;
+        assert!(ParseDirBlock(&block, &mut entries).is_err());
+
+        // the names overlap
+        let mut block = Block(&names, 64);
+        block[DIRENT_SIZE + 8] = 20;
+        assert!(ParseDirBlock(&block, &mut entries).is_err());
+
+        // the dirents don't fit in the block
+        let block = Block(&names, 64);
+        assert!(ParseDirBlock(&block[..20], &mut entries).is_err());
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::super::super::super::common::*;
+use super::super::super::super::device::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::time::*;
+use super::super::mount::*;
+use super::device::*;
+use super::fs::*;
+
+// The ext4 on-disk format, see Documentation/filesystems/ext4 of Linux.
+const SUPER_OFFSET: u64 = 1024;
+const SUPER_SIZE: usize = 1024;
+const SUPER_MAGIC: u16 = 0xef53;
+
+const S_INODES_COUNT: usize = 0x0;
+const S_BLOCKS_COUNT_LO: usize = 0x4;
+const S_FREE_BLOCKS_COUNT_LO: usize = 0xc;
+const S_FREE_INODES_COUNT: usize = 0x10;
+const S_FIRST_DATA_BLOCK: usize = 0x14;
+const S_LOG_BLOCK_SIZE: usize = 0x18;
+const S_BLOCKS_PER_GROUP: usize = 0x20;
+const S_INODES_PER_GROUP: usize = 0x28;
+const S_MAGIC: usize = 0x38;
+const S_REV_LEVEL: usize = 0x4c;
+const S_INODE_SIZE: usize = 0x58;
+const S_FEATURE_INCOMPAT: usize = 0x60;
+const S_FEATURE_RO_COMPAT: usize = 0x64;
+const S_DESC_SIZE: usize = 0xfe;
+const S_FIRST_META_BG: usize = 0x104;
+const S_BLOCKS_COUNT_HI: usize = 0x150;
+const S_FREE_BLOCKS_COUNT_HI: usize = 0x158;
+
+const INCOMPAT_FILETYPE: u32 = 0x2;
+const INCOMPAT_RECOVER: u32 = 0x4;
+const INCOMPAT_META_BG: u32 = 0x10;
+const INCOMPAT_EXTENTS: u32 = 0x40;
+const INCOMPAT_64BIT: u32 = 0x80;
+const INCOMPAT_MMP: u32 = 0x100;
+const INCOMPAT_FLEX_BG: u32 = 0x200;
+const INCOMPAT_EA_INODE: u32 = 0x400;
+const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+const INCOMPAT_LARGEDIR: u32 = 0x4000;
+
+// SUPPORTED_INCOMPAT are the incompatible features the reader handles, the
+// journal, inline data, encryption and casefolding are not.
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_META_BG
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_MMP
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_EA_INODE
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+
+const BG_INODE_TABLE_LO: u64 = 0x8;
+const BG_INODE_TABLE_HI: u64 = 0x28;
+
+const I_MODE: usize = 0x0;
+const I_UID: usize = 0x2;
+const I_SIZE_LO: usize = 0x4;
+const I_ATIME: usize = 0x8;
+const I_CTIME: usize = 0xc;
+const I_MTIME: usize = 0x10;
+const I_GID: usize = 0x18;
+const I_LINKS_COUNT: usize = 0x1a;
+const I_BLOCKS_LO: usize = 0x1c;
+const I_FLAGS: usize = 0x20;
+const I_BLOCK: usize = 0x28;
+const I_FILE_ACL_LO: usize = 0x68;
+const I_SIZE_HIGH: usize = 0x6c;
+const I_BLOCKS_HIGH: usize = 0x74;
+const I_FILE_ACL_HIGH: usize = 0x76;
+const I_UID_HIGH: usize = 0x78;
+const I_GID_HIGH: usize = 0x7a;
+const I_EXTRA_ISIZE: usize = 0x80;
+const I_CTIME_EXTRA: usize = 0x84;
+const I_MTIME_EXTRA: usize = 0x88;
+const I_ATIME_EXTRA: usize = 0x8c;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+const HUGE_FILE_FL: u32 = 0x40000;
+const EXTENTS_FL: u32 = 0x80000;
+const INLINE_DATA_FL: u32 = 0x10000000;
+
+pub const EXT4_ROOT_INO: u64 = 2;
+pub const EXT4_N_BLOCKS_SIZE: usize = 60;
+const N_DIRECT_BLOCKS: u64 = 12;
+
+const EXTENT_MAGIC: u16 = 0xf30a;
+const EXTENT_HEADER_SIZE: usize = 12;
+const EXTENT_ENTRY_SIZE: usize = 12;
+const EXTENT_MAX_DEPTH: usize = 5;
+const EXTENT_INIT_MAX_LEN: u16 = 32768;
+
+const DIRENT_HEADER_SIZE: usize = 8;
+
+pub struct Ext4Fs {
+    pub dev: ImageDevice,
+    pub blockSize: u64,
+    pub inodeSize: u64,
+    pub inodesPerGroup: u64,
+    pub incompat: u32,
+    pub roCompat: u32,
+    pub inodesCount: u64,
+    pub freeInodes: u64,
+    pub blocksCount: u64,
+    pub freeBlocks: u64,
+
+    // inodeTables is the first block of the inode table of each group.
+    pub inodeTables: Vec<u64>,
+}
+
+// HasSuper returns whether the group keeps a copy of the super block and of
+// the group descriptors.
+fn HasSuper(group: u64, roCompat: u32) -> bool {
+    if group <= 1 || roCompat & RO_COMPAT_SPARSE_SUPER == 0 {
+        return true;
+    }
+
+    for base in &[3, 5, 7] {
+        let mut n = *base;
+        while n < group {
+            n *= *base;
+        }
+
+        if n == group {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+// ExtTime decodes an inode time, the extra field has the epoch bits and the
+// nanoseconds.
+fn ExtTime(raw: &[u8], offset: usize, extraOffset: usize) -> Time {
+    let secs = Le32(raw, offset) as i32 as i64;
+    if extraOffset + 4 > raw.len() {
+        return Time::FromUnix(secs, 0);
+    }
+
+    let extra = Le32(raw, extraOffset);
+    let secs = secs + (((extra & 0x3) as i64) << 32);
+    return Time::FromUnix(secs, (extra >> 2) as i64);
+}
+
+// ParseDirBlock adds the entries of a directory block to entries, without
+// the file type feature the name length is 16 bits.
+fn ParseDirBlock(
+    block: &[u8],
+    fileType: bool,
+    entries: &mut BTreeMap<String, ImageDirent>,
+) -> Result<()> {
+    let mut pos = 0;
+    while pos + DIRENT_HEADER_SIZE <= block.len() {
+        let ino = Le32(block, pos) as u64;
+        let recLen = Le16(block, pos + 4) as usize;
+        let (nameLen, ft) = if fileType {
+            (block[pos + 6] as usize, block[pos + 7])
+        } else {
+            (Le16(block, pos + 6) as usize, 0)
+        };
+
+        if recLen < DIRENT_HEADER_SIZE || pos + recLen > block.len() {
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        // the unused entries and the checksum tail have no inode
+        if ino != 0 && nameLen > 0 && DIRENT_HEADER_SIZE + nameLen <= recLen {
+            let name = &block[pos + DIRENT_HEADER_SIZE..pos + DIRENT_HEADER_SIZE + nameLen];
+            let name = String::from_utf8_lossy(name).to_string();
+            if name != "." && name != ".." {
+                entries.insert(
+                    name,
+                    ImageDirent {
+                        ino: ino,
+                        fileType: ft,
+                    },
+                );
+            }
+        }
+
+        pos += recLen;
+    }
+
+    return Ok(());
+}
+
+impl Ext4Fs {
+    pub fn Probe(dev: &ImageDevice) -> bool {
+        return match dev.ReadObj::<u16>(SUPER_OFFSET + S_MAGIC as u64) {
+            Ok(magic) => magic == SUPER_MAGIC,
+            Err(_) => false,
+        };
+    }
+
+    pub fn New(dev: &ImageDevice) -> Result<Self> {
+        let sb = dev.Read(SUPER_OFFSET, SUPER_SIZE)?;
+        if Le16(&sb, S_MAGIC) != SUPER_MAGIC {
+            info!("ext4 image has no ext4 super block");
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let incompat = Le32(&sb, S_FEATURE_INCOMPAT);
+        if incompat & INCOMPAT_RECOVER != 0 {
+            info!("ext4 image needs journal recovery, fsck it first");
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if incompat & !SUPPORTED_INCOMPAT != 0 {
+            info!(
+                "ext4 image has unsupported features {:x}",
+                incompat & !SUPPORTED_INCOMPAT
+            );
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let logBlockSize = Le32(&sb, S_LOG_BLOCK_SIZE);
+        if logBlockSize > 6 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+        let blockSize = 1024u64 << logBlockSize;
+
+        let inodeSize = if Le32(&sb, S_REV_LEVEL) == 0 {
+            GOOD_OLD_INODE_SIZE as u64
+        } else {
+            Le16(&sb, S_INODE_SIZE) as u64
+        };
+
+        let descSize = if incompat & INCOMPAT_64BIT != 0 {
+            Le16(&sb, S_DESC_SIZE) as u64
+        } else {
+            32
+        };
+
+        let mut blocksCount = Le32(&sb, S_BLOCKS_COUNT_LO) as u64;
+        let mut freeBlocks = Le32(&sb, S_FREE_BLOCKS_COUNT_LO) as u64;
+        if incompat & INCOMPAT_64BIT != 0 {
+            blocksCount |= (Le32(&sb, S_BLOCKS_COUNT_HI) as u64) << 32;
+            freeBlocks |= (Le32(&sb, S_FREE_BLOCKS_COUNT_HI) as u64) << 32;
+        }
+
+        let firstDataBlock = Le32(&sb, S_FIRST_DATA_BLOCK) as u64;
+        let blocksPerGroup = Le32(&sb, S_BLOCKS_PER_GROUP) as u64;
+        let inodesPerGroup = Le32(&sb, S_INODES_PER_GROUP) as u64;
+        if inodeSize < GOOD_OLD_INODE_SIZE as u64
+            || inodeSize > blockSize
+            || descSize < 32
+            || blocksPerGroup == 0
+            || inodesPerGroup == 0
+            || blocksCount <= firstDataBlock
+        {
+            info!("ext4 image has a corrupted super block");
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let groups = (blocksCount - firstDataBlock + blocksPerGroup - 1) / blocksPerGroup;
+        let descPerBlock = blockSize / descSize;
+        let firstMetaBg = Le32(&sb, S_FIRST_META_BG) as u64;
+        let roCompat = Le32(&sb, S_FEATURE_RO_COMPAT);
+
+        let mut inodeTables = Vec::with_capacity(groups as usize);
+        for group in 0..groups {
+            let descBlock = group / descPerBlock;
+            let block = if incompat & INCOMPAT_META_BG == 0 || descBlock < firstMetaBg {
+                firstDataBlock + 1 + descBlock
+            } else {
+                // the descriptors of a meta group are in its first group
+                let first = descBlock * descPerBlock;
+                let firstBlock = firstDataBlock + first * blocksPerGroup;
+                firstBlock + HasSuper(first, roCompat) as u64
+            };
+
+            let desc = block * blockSize + (group % descPerBlock) * descSize;
+            let mut table = dev.ReadObj::<u32>(desc + BG_INODE_TABLE_LO)? as u64;
+            if descSize >= 64 {
+                table |= (dev.ReadObj::<u32>(desc + BG_INODE_TABLE_HI)? as u64) << 32;
+            }
+            inodeTables.push(table);
+        }
+
+        return Ok(Self {
+            dev: dev.clone(),
+            blockSize: blockSize,
+            inodeSize: inodeSize,
+            inodesPerGroup: inodesPerGroup,
+            incompat: incompat,
+            roCompat: roCompat,
+            inodesCount: Le32(&sb, S_INODES_COUNT) as u64,
+            freeInodes: Le32(&sb, S_FREE_INODES_COUNT) as u64,
+            blocksCount: blocksCount,
+            freeBlocks: freeBlocks,
+            inodeTables: inodeTables,
+        });
+    }
+
+    pub fn Node(&self, ino: u64) -> Result<ImageNode> {
+        if ino == 0 || ino > self.inodesCount {
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        let group = (ino - 1) / self.inodesPerGroup;
+        let index = (ino - 1) % self.inodesPerGroup;
+        let table = match self.inodeTables.get(group as usize) {
+            None => return Err(Error::SysError(SysErr::EIO)),
+            Some(t) => *t,
+        };
+
+        let raw = self.dev.Read(
+            table * self.blockSize + index * self.inodeSize,
+            self.inodeSize as usize,
+        )?;
+
+        // the extra fields are there when the inode is large enough for them
+        let extraEnd = if raw.len() > GOOD_OLD_INODE_SIZE {
+            GOOD_OLD_INODE_SIZE + Le16(&raw, I_EXTRA_ISIZE) as usize
+        } else {
+            GOOD_OLD_INODE_SIZE
+        };
+        let extra = &raw[..core::cmp::min(extraEnd, raw.len())];
+
+        let mode = Le16(&raw, I_MODE);
+        let flags = Le32(&raw, I_FLAGS);
+        let fileAcl = Le32(&raw, I_FILE_ACL_LO) as u64 | (Le16(&raw, I_FILE_ACL_HIGH) as u64) << 32;
+        let mut blocks = [0; EXT4_N_BLOCKS_SIZE];
+        blocks.copy_from_slice(&raw[I_BLOCK..I_BLOCK + EXT4_N_BLOCKS_SIZE]);
+
+        let mut usage = Le32(&raw, I_BLOCKS_LO) as u64 | (Le16(&raw, I_BLOCKS_HIGH) as u64) << 32;
+        let units = if self.roCompat & RO_COMPAT_HUGE_FILE != 0 && flags & HUGE_FILE_FL != 0 {
+            self.blockSize
+        } else {
+            512
+        };
+        usage *= units;
+
+        // a fast symlink keeps the target in i_block and has no data block,
+        // the xattr block aside
+        let aclUsage = if fileAcl != 0 { self.blockSize } else { 0 };
+        let inline = mode & ModeType::S_IFMT == ModeType::S_IFLNK
+            && flags & (EXTENTS_FL | INLINE_DATA_FL) == 0
+            && usage <= aclUsage;
+
+        if flags & INLINE_DATA_FL != 0 {
+            info!("ext4 inode {} has inline data", ino);
+            return Err(Error::SysError(SysErr::EOPNOTSUPP));
+        }
+
+        let rdev = match mode & ModeType::S_IFMT {
+            ModeType::S_IFCHR | ModeType::S_IFBLK => {
+                let old = Le32(&blocks, 0);
+                if old != 0 {
+                    MakeDeviceID(((old >> 8) & 0xff) as u16, old & 0xff)
+                } else {
+                    Le32(&blocks, 4)
+                }
+            }
+            _ => 0,
+        };
+
+        return Ok(ImageNode {
+            ino: ino,
+            mode: mode,
+            uid: Le16(&raw, I_UID) as u32 | (Le16(&raw, I_UID_HIGH) as u32) << 16,
+            gid: Le16(&raw, I_GID) as u32 | (Le16(&raw, I_GID_HIGH) as u32) << 16,
+            size: Le32(&raw, I_SIZE_LO) as u64 | (Le32(&raw, I_SIZE_HIGH) as u64) << 32,
+            links: Le16(&raw, I_LINKS_COUNT) as u64,
+            usage: usage,
+            atime: ExtTime(extra, I_ATIME, I_ATIME_EXTRA),
+            mtime: ExtTime(extra, I_MTIME, I_MTIME_EXTRA),
+            ctime: ExtTime(extra, I_CTIME, I_CTIME_EXTRA),
+            rdev: rdev,
+            layout: ImageLayout::Ext4 {
+                flags: flags,
+                blocks: blocks,
+                inline: inline,
+            },
+        });
+    }
+
+    fn ReadBlock(&self, block: u64) -> Result<Vec<u8>> {
+        return self
+            .dev
+            .Read(block * self.blockSize, self.blockSize as usize);
+    }
+
+    // MapExtent looks the logical block up in the extent tree. It returns the
+    // physical block, None for a hole or an unwritten extent, and the count of
+    // blocks the mapping holds for.
+    fn MapExtent(&self, root: &[u8], lblk: u64) -> Result<(Option<u64>, u64)> {
+        let mut node = root.to_vec();
+
+        // limit is the first logical block of the next subtree
+        let mut limit = u64::MAX;
+        for _ in 0..EXTENT_MAX_DEPTH {
+            let entries = Le16(&node, 2) as usize;
+            let depth = Le16(&node, 6);
+            if Le16(&node, 0) != EXTENT_MAGIC
+                || EXTENT_HEADER_SIZE + entries * EXTENT_ENTRY_SIZE > node.len()
+            {
+                return Err(Error::SysError(SysErr::EIO));
+            }
+
+            if depth == 0 {
+                let mut next = limit;
+                for i in 0..entries {
+                    let e = EXTENT_HEADER_SIZE + i * EXTENT_ENTRY_SIZE;
+                    let start = Le32(&node, e) as u64;
+                    let rawLen = Le16(&node, e + 4);
+                    let (len, unwritten) = if rawLen > EXTENT_INIT_MAX_LEN {
+                        ((rawLen - EXTENT_INIT_MAX_LEN) as u64, true)
+                    } else {
+                        (rawLen as u64, false)
+                    };
+
+                    if lblk >= start && lblk < start + len {
+                        if unwritten {
+                            return Ok((None, start + len - lblk));
+                        }
+
+                        let phys = (Le16(&node, e + 6) as u64) << 32 | Le32(&node, e + 8) as u64;
+                        return Ok((Some(phys + lblk - start), start + len - lblk));
+                    }
+
+                    if start > lblk && start < next {
+                        next = start;
+                    }
+                }
+
+                return Ok((None, next - lblk));
+            }
+
+            let mut child = None;
+            for i in 0..entries {
+                let e = EXTENT_HEADER_SIZE + i * EXTENT_ENTRY_SIZE;
+                let start = Le32(&node, e) as u64;
+                if start > lblk {
+                    if start < limit {
+                        limit = start;
+                    }
+                    break;
+                }
+
+                child = Some((Le16(&node, e + 8) as u64) << 32 | Le32(&node, e + 4) as u64);
+            }
+
+            match child {
+                None => return Ok((None, limit - lblk)),
+                Some(block) => node = self.ReadBlock(block)?,
+            }
+        }
+
+        return Err(Error::SysError(SysErr::EIO));
+    }
+
+    // MapIndirect looks the logical block up in the direct and indirect
+    // block maps of the files without extents.
+    fn MapIndirect(&self, blocks: &[u8], lblk: u64) -> Result<(Option<u64>, u64)> {
+        let perBlock = self.blockSize / 4;
+        let mut index = lblk;
+        let (slot, depth) = if index < N_DIRECT_BLOCKS {
+            (index, 0)
+        } else {
+            index -= N_DIRECT_BLOCKS;
+            if index < perBlock {
+                (N_DIRECT_BLOCKS, 1)
+            } else {
+                index -= perBlock;
+                if index < perBlock * perBlock {
+                    (N_DIRECT_BLOCKS + 1, 2)
+                } else {
+                    index -= perBlock * perBlock;
+                    if index >= perBlock * perBlock * perBlock {
+                        return Err(Error::SysError(SysErr::EFBIG));
+                    }
+                    (N_DIRECT_BLOCKS + 2, 3)
+                }
+            }
+        };
+
+        let mut block = Le32(blocks, slot as usize * 4) as u64;
+        for level in (0..depth).rev() {
+            if block == 0 {
+                break;
+            }
+
+            let span = perBlock.pow(level);
+            let entry = index / span;
+            index %= span;
+            block = self
+                .dev
+                .ReadObj::<u32>(block * self.blockSize + entry * 4)? as u64;
+        }
+
+        if block == 0 {
+            return Ok((None, 1));
+        }
+
+        return Ok((Some(block), 1));
+    }
+
+    pub fn Read(&self, node: &ImageNode, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let (flags, blocks) = match &node.layout {
+            ImageLayout::Ext4 { flags, blocks, .. } => (*flags, blocks),
+            _ => return Err(Error::SysError(SysErr::EIO)),
+        };
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let lblk = pos / self.blockSize;
+            let inBlock = pos % self.blockSize;
+            let (phys, count) = if flags & EXTENTS_FL != 0 {
+                self.MapExtent(blocks, lblk)?
+            } else {
+                self.MapIndirect(blocks, lblk)?
+            };
+
+            let mapped = count.saturating_mul(self.blockSize) - inBlock;
+            let len = core::cmp::min(mapped, (buf.len() - done) as u64) as usize;
+            match phys {
+                None => {
+                    for b in &mut buf[done..done + len] {
+                        *b = 0;
+                    }
+                }
+                Some(block) => {
+                    self.dev
+                        .ReadAt(&mut buf[done..done + len], block * self.blockSize + inBlock)?;
+                }
+            }
+            done += len;
+        }
+
+        return Ok(());
+    }
+
+    // ReadDir reads the linear directory entries one block at a time, the
+    // blocks of a hashed directory hold them the same way.
+    pub fn ReadDir(&self, node: &ImageNode) -> Result<BTreeMap<String, ImageDirent>> {
+        // the size of a corrupted directory can't be trusted
+        if node.size > self.dev.size {
+            info!("ext4 directory {} is corrupted", node.ino);
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        let fileType = self.incompat & INCOMPAT_FILETYPE != 0;
+        let mut data = DataBuff::New(self.blockSize as usize);
+        let mut entries = BTreeMap::new();
+        let mut offset = 0;
+        while offset < node.size {
+            let len = core::cmp::min(self.blockSize, node.size - offset) as usize;
+            let block = &mut data.buf[..len];
+            self.Read(node, offset, block)?;
+            if let Err(e) = ParseDirBlock(block, fileType, &mut entries) {
+                info!("ext4 directory {} is corrupted", node.ino);
+                return Err(e);
+            }
+
+            offset += self.blockSize;
+        }
+
+        return Ok(entries);
+    }
+
+    pub fn StatFS(&self) -> FsInfo {
+        return FsInfo {
+            Type: FSMagic::EXT_SUPER_MAGIC,
+            TotalBlocks: self.blocksCount,
+            FreeBlocks: self.freeBlocks,
+            TotalFiles: self.inodesCount,
+            FreeFiles: self.freeInodes,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Dirent(ino: u32, recLen: usize, name: &str, ft: u8, fileType: bool) -> Vec<u8> {
+        let mut d = Vec::new();
+        d.extend_from_slice(&ino.to_le_bytes());
+        d.extend_from_slice(&(recLen as u16).to_le_bytes());
+        if fileType {
+            d.push(name.len() as u8);
+            d.push(ft);
+        } else {
+            d.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        }
+        d.extend_from_slice(name.as_bytes());
+        d.resize(recLen, 0);
+        return d;
+    }
+
+    fn Block(fileType: bool) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.append(&mut Dirent(2, 12, ".", 2, fileType));
+        block.append(&mut Dirent(2, 12, "..", 2, fileType));
+        block.append(&mut Dirent(12, 16, "file", 1, fileType));
+        block.append(&mut Dirent(0, 12, "gone", 1, fileType));
+        block.append(&mut Dirent(13, 12, "dir", 2, fileType));
+        return block;
+    }
+
+    #[test]
+    fn test_ParseDirBlock() {
+        let mut entries = BTreeMap::new();
+        ParseDirBlock(&Block(true), true, &mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["file"].ino, 12);
+        assert_eq!(entries["file"].fileType, 1);
+        assert_eq!(entries["dir"].ino, 13);
+        assert_eq!(entries["dir"].fileType, 2);
+
+        let mut entries = BTreeMap::new();
+        ParseDirBlock(&Block(false), false, &mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["file"].ino, 12);
+        assert_eq!(entries["file"].fileType, 0);
+    }
+
+    #[test]
+    fn test_ParseDirBlockCorrupted() {
+        let mut entries = BTreeMap::new();
+        let mut block = Block(true);
+        block[4] = 4;
+        assert!(ParseDirBlock(&block, true, &mut entries).is_err());
+
+        let mut block = Block(true);
+        block.truncate(60);
+        assert!(ParseDirBlock(&block, true, &mut entries).is_err());
+    }
+}
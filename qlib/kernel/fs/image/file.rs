@@ -0,0 +1,230 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::any::Any;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::waiter::*;
+use super::super::super::task::*;
+use super::super::host::hostinodeop::*;
+use super::super::host::util;
+
+use super::super::attr::*;
+use super::super::dentry::*;
+use super::super::dirent::*;
+use super::super::file::*;
+use super::super::fsutil::file::*;
+use super::super::inode::*;
+use super::fs::*;
+use super::inode::*;
+
+pub struct ImageFileIntern {
+    pub iops: ImageInodeOps,
+    pub dir: bool,
+    pub dirCursor: QMutex<String>,
+}
+
+#[derive(Clone)]
+pub struct ImageFileOps(pub Arc<ImageFileIntern>);
+
+impl Deref for ImageFileOps {
+    type Target = Arc<ImageFileIntern>;
+
+    fn deref(&self) -> &Arc<ImageFileIntern> {
+        &self.0
+    }
+}
+
+impl ImageFileOps {
+    pub fn New(iops: &ImageInodeOps, dir: bool) -> Self {
+        let intern = ImageFileIntern {
+            iops: iops.clone(),
+            dir: dir,
+            dirCursor: QMutex::new("".to_string()),
+        };
+
+        return Self(Arc::new(intern));
+    }
+
+    pub fn ReadDirAll(&self) -> Result<DentMap> {
+        let children = self.iops.Children()?;
+        let mut entries = BTreeMap::new();
+        for (name, child) in children.iter() {
+            // an entry without a file type takes it from its inode
+            let mut mode = FileTypeMode(child.fileType);
+            if mode == 0 {
+                mode = self.iops.fs.Node(child.ino)?.mode;
+            }
+
+            let dentry = DentAttr {
+                Type: util::InodeType(mode as u32),
+                InodeId: child.ino,
+            };
+            entries.insert(name.to_string(), dentry);
+        }
+
+        return Ok(DentMap::New(entries));
+    }
+}
+
+impl Waitable for ImageFileOps {}
+
+impl SpliceOperations for ImageFileOps {}
+
+impl FileOperations for ImageFileOps {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::ImageFileOps;
+    }
+
+    fn Seekable(&self) -> bool {
+        return true;
+    }
+
+    fn Seek(&self, task: &Task, f: &File, whence: i32, current: i64, offset: i64) -> Result<i64> {
+        if !self.dir {
+            return SeekWithDirCursor(task, f, whence, current, offset, None);
+        }
+
+        let mut dirCursor = self.dirCursor.lock();
+        let mut cursor = "".to_string();
+        let newOffset = SeekWithDirCursor(task, f, whence, current, offset, Some(&mut cursor))?;
+        *dirCursor = cursor;
+        return Ok(newOffset);
+    }
+
+    fn ReadDir(
+        &self,
+        task: &Task,
+        file: &File,
+        offset: i64,
+        serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        if !self.dir {
+            return Err(Error::SysError(SysErr::ENOTDIR));
+        }
+
+        let root = task.Root();
+        let mut dirCursor = self.dirCursor.lock();
+
+        let mut dirCtx = DirCtx {
+            Serializer: serializer,
+            DirCursor: (*dirCursor).to_string(),
+        };
+
+        let res = DirentReadDir(task, &file.Dirent, self, &root, &mut dirCtx, offset)?;
+        *dirCursor = dirCtx.DirCursor;
+        return Ok(res);
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        if self.dir {
+            return Err(Error::SysError(SysErr::EISDIR));
+        }
+
+        if offset < 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        // a large read is returned short, the caller reads on
+        let size = core::cmp::min(IoVec::NumBytes(dsts), MemoryDef::HUGE_PAGE_SIZE as usize);
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let mut buf = DataBuff::New(size);
+        let len = self.iops.Read(offset as u64, &mut buf.buf)?;
+        let n = task.CopyDataOutToIovs(&buf.buf[..len], dsts, true)?;
+        return Ok(n as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _srcs: &[IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Append(&self, _task: &Task, _f: &File, _srcs: &[IoVec]) -> Result<(i64, i64)> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Fsync(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        _syncType: SyncType,
+    ) -> Result<()> {
+        return Ok(());
+    }
+
+    fn Flush(&self, _task: &Task, _f: &File) -> Result<()> {
+        return Ok(());
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        let inode = f.Dirent.Inode();
+        return inode.UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, _task: &Task, _f: &File, _fd: i32, _request: u64, _val: u64) -> Result<u64> {
+        return Err(Error::SysError(SysErr::ENOTTY));
+    }
+
+    fn IterateDir(
+        &self,
+        task: &Task,
+        _d: &Dirent,
+        dirCtx: &mut DirCtx,
+        offset: i32,
+    ) -> (i32, Result<i64>) {
+        let dentryMap = match self.ReadDirAll() {
+            Err(e) => return (offset, Err(e)),
+            Ok(entries) => entries,
+        };
+
+        return match dirCtx.ReadDir(task, &dentryMap) {
+            Err(e) => (offset, Err(e)),
+            Ok(count) => (offset + count as i32, Ok(0)),
+        };
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return self.iops.Mappable();
+    }
+}
+
+impl SockOperations for ImageFileOps {}
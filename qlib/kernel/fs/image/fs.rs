@@ -0,0 +1,371 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+use super::super::super::super::common::*;
+use super::super::super::super::device::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::kernel::time::*;
+use super::super::super::task::*;
+use super::super::attr::*;
+use super::super::filesystems::*;
+use super::super::host::fs::WhitelistFileSystem;
+use super::super::host::util::*;
+use super::super::inode::*;
+use super::super::mount::*;
+use super::device::*;
+use super::erofs::*;
+use super::ext4::*;
+use super::inode::*;
+
+pub const EXT4: &str = "ext4";
+pub const EROFS: &str = "erofs";
+
+// The mount options of the images, the journal is never replayed as the
+// images are mounted read only.
+const IGNORED_OPTIONS: [&str; 3] = ["ro", "noload", "norecovery"];
+
+pub fn Le16(buf: &[u8], offset: usize) -> u16 {
+    return u16::from_le_bytes([buf[offset], buf[offset + 1]]);
+}
+
+pub fn Le32(buf: &[u8], offset: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[offset..offset + 4]);
+    return u32::from_le_bytes(b);
+}
+
+pub fn Le64(buf: &[u8], offset: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&buf[offset..offset + 8]);
+    return u64::from_le_bytes(b);
+}
+
+// FileTypeMode converts the file type of a directory entry, ext4 and erofs
+// share the codes, to the file mode type. 0 is unknown.
+pub fn FileTypeMode(fileType: u8) -> u16 {
+    return match fileType {
+        1 => ModeType::S_IFREG,
+        2 => ModeType::S_IFDIR,
+        3 => ModeType::S_IFCHR,
+        4 => ModeType::S_IFBLK,
+        5 => ModeType::S_IFIFO,
+        6 => ModeType::S_IFSOCK,
+        7 => ModeType::S_IFLNK,
+        _ => 0,
+    };
+}
+
+#[derive(Clone, Copy)]
+pub enum ImageLayout {
+    // blocks is the i_block area, the extent tree root, the block map or
+    // the target of a fast symlink.
+    Ext4 {
+        flags: u32,
+        blocks: [u8; EXT4_N_BLOCKS_SIZE],
+        inline: bool,
+    },
+
+    // raw is the start block, the chunk format or the device, tail is the
+    // image offset of the inline data or of the chunk indexes.
+    Erofs {
+        layout: u16,
+        raw: u32,
+        tail: u64,
+    },
+}
+
+// ImageNode is an inode read from the image.
+#[derive(Clone, Copy)]
+pub struct ImageNode {
+    pub ino: u64,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub links: u64,
+    pub usage: u64,
+    pub atime: Time,
+    pub mtime: Time,
+    pub ctime: Time,
+    pub rdev: u32,
+    pub layout: ImageLayout,
+}
+
+#[derive(Clone, Copy)]
+pub struct ImageDirent {
+    pub ino: u64,
+    pub fileType: u8,
+}
+
+pub enum ImageFormat {
+    Ext4(Ext4Fs),
+    Erofs(ErofsFs),
+}
+
+pub struct ImageFsIntern {
+    pub format: ImageFormat,
+    pub deviceId: u64,
+}
+
+#[derive(Clone)]
+pub struct ImageFs(pub Arc<ImageFsIntern>);
+
+impl Deref for ImageFs {
+    type Target = Arc<ImageFsIntern>;
+
+    fn deref(&self) -> &Arc<ImageFsIntern> {
+        &self.0
+    }
+}
+
+impl ImageFs {
+    pub fn New(name: &str, dev: &ImageDevice) -> Result<Self> {
+        let format = match name {
+            EXT4 => ImageFormat::Ext4(Ext4Fs::New(dev)?),
+            EROFS => ImageFormat::Erofs(ErofsFs::New(dev)?),
+            _ => return Err(Error::SysError(SysErr::ENODEV)),
+        };
+
+        let device = NewAnonDevice();
+        let deviceId = device.lock().DeviceID();
+        let intern = ImageFsIntern {
+            format: format,
+            deviceId: deviceId,
+        };
+
+        return Ok(Self(Arc::new(intern)));
+    }
+
+    pub fn BlockSize(&self) -> u64 {
+        return match &self.format {
+            ImageFormat::Ext4(fs) => fs.blockSize,
+            ImageFormat::Erofs(fs) => fs.blockSize,
+        };
+    }
+
+    pub fn RootIno(&self) -> u64 {
+        return match &self.format {
+            ImageFormat::Ext4(_) => EXT4_ROOT_INO,
+            ImageFormat::Erofs(fs) => fs.rootNid,
+        };
+    }
+
+    pub fn Node(&self, ino: u64) -> Result<ImageNode> {
+        return match &self.format {
+            ImageFormat::Ext4(fs) => fs.Node(ino),
+            ImageFormat::Erofs(fs) => fs.Node(ino),
+        };
+    }
+
+    // Read reads the content of the node at offset, it returns the count of
+    // bytes before the end of the file.
+    pub fn Read(&self, node: &ImageNode, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        if offset >= node.size {
+            return Ok(0);
+        }
+
+        let len = core::cmp::min(buf.len() as u64, node.size - offset) as usize;
+        match &self.format {
+            ImageFormat::Ext4(fs) => fs.Read(node, offset, &mut buf[..len])?,
+            ImageFormat::Erofs(fs) => fs.Read(node, offset, &mut buf[..len])?,
+        }
+
+        return Ok(len);
+    }
+
+    pub fn ReadDir(&self, node: &ImageNode) -> Result<BTreeMap<String, ImageDirent>> {
+        return match &self.format {
+            ImageFormat::Ext4(fs) => fs.ReadDir(node),
+            ImageFormat::Erofs(fs) => fs.ReadDir(node),
+        };
+    }
+
+    pub fn ReadLink(&self, node: &ImageNode) -> Result<String> {
+        if let ImageLayout::Ext4 {
+            blocks,
+            inline: true,
+            ..
+        } = &node.layout
+        {
+            let len = core::cmp::min(node.size as usize, blocks.len());
+            return Ok(String::from_utf8_lossy(&blocks[..len]).to_string());
+        }
+
+        if node.size as usize > PATH_MAX {
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        let mut buf = DataBuff::New(node.size as usize);
+        let len = self.Read(node, 0, &mut buf.buf)?;
+        return Ok(String::from_utf8_lossy(&buf.buf[..len]).to_string());
+    }
+
+    pub fn StatFS(&self) -> FsInfo {
+        return match &self.format {
+            ImageFormat::Ext4(fs) => fs.StatFS(),
+            ImageFormat::Erofs(fs) => fs.StatFS(),
+        };
+    }
+}
+
+// DetectImageType returns the type of the file system in the image from the
+// magic of its super block.
+pub fn DetectImageType(dev: &ImageDevice) -> Result<&'static str> {
+    if Ext4Fs::Probe(dev) {
+        return Ok(EXT4);
+    }
+
+    if ErofsFs::Probe(dev) {
+        return Ok(EROFS);
+    }
+
+    info!("image is neither ext4 nor erofs");
+    return Err(Error::SysError(SysErr::EINVAL));
+}
+
+pub fn IsImageFilesystem(name: &str) -> bool {
+    return name == EXT4 || name == EROFS;
+}
+
+// ImageFileSystem is a file system image, ext4 or erofs, the kernel reads and
+// parses itself. The images are mounted read only.
+pub struct ImageFileSystem {
+    pub name: &'static str,
+}
+
+impl ImageFileSystem {
+    pub fn New(name: &'static str) -> Self {
+        return Self { name: name };
+    }
+
+    // MountDevice mounts the image of the device, name is the file system
+    // type or "" to detect it.
+    pub fn MountDevice(name: &str, dev: &ImageDevice, flags: &MountSourceFlags) -> Result<Inode> {
+        let name = if name == "" {
+            DetectImageType(dev)?
+        } else if name == EXT4 {
+            EXT4
+        } else if name == EROFS {
+            EROFS
+        } else {
+            return Err(Error::SysError(SysErr::ENODEV));
+        };
+
+        let fs = ImageFs::New(name, dev)?;
+        let mut flags = *flags;
+        flags.ReadOnly = true;
+        let msrc = MountSource::NewCachingMountSource(&Self::New(name), &flags);
+        let msrc = Arc::new(QMutex::new(msrc));
+
+        let root = fs.Node(fs.RootIno())?;
+        if root.mode & ModeType::S_IFMT != ModeType::S_IFDIR {
+            info!("{} image root is not a directory", name);
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        return Ok(NewImageInode(&fs, &msrc, &root));
+    }
+}
+
+// MountImageFile mounts the image file of the host at path.
+pub fn MountImageFile(name: &str, path: &str, flags: &MountSourceFlags) -> Result<Inode> {
+    let (fd, fstat) = OpenAt(-100, path, Flags::O_RDONLY | Flags::O_CLOEXEC)?;
+
+    // the device owns the fd from here, it is closed with the device
+    let dev = ImageDevice::New(fd, fstat.st_size as u64, None);
+    if fstat.st_mode & ModeType::S_IFMT as u32 != ModeType::S_IFREG as u32 {
+        info!("image {} is not a regular file", path);
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    return ImageFileSystem::MountDevice(name, &dev, flags);
+}
+
+impl Filesystem for ImageFileSystem {
+    fn Name(&self) -> String {
+        return self.name.to_string();
+    }
+
+    fn Flags(&self) -> FilesystemFlags {
+        return FILESYSTEM_REQUIRES_DEV;
+    }
+
+    // Mount mounts the image file at device, the file must be on a host-backed
+    // mount of the sandbox.
+    fn Mount(
+        &mut self,
+        task: &Task,
+        device: &str,
+        flags: &MountSourceFlags,
+        data: &str,
+    ) -> Result<Inode> {
+        let mut options = WhitelistFileSystem::GenericMountSourceOptions(data);
+        for o in &IGNORED_OPTIONS {
+            options.remove(*o);
+        }
+
+        if options.len() > 0 {
+            info!("unsupported mount options: {:?}", options);
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let root = task.Root();
+        let mut remainingTraversals = MAX_SYMLINK_TRAVERSALS;
+        let dirent = task.mountNS.FindDirent(
+            task,
+            &root,
+            Some(task.Workdir()),
+            device,
+            &mut remainingTraversals,
+            true,
+        )?;
+
+        let inode = dirent.Inode();
+        if inode.StableAttr().Type != InodeType::RegularFile {
+            return Err(Error::SysError(SysErr::ENOTBLK));
+        }
+
+        let iops = match inode.lock().InodeOp.HostInodeOp() {
+            None => {
+                info!("image {} is not a host file", device);
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            Some(iops) => iops,
+        };
+
+        // the cache of an encrypted file isn't the content the host fd reads
+        if iops.IsCrypt() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let size = inode.UnstableAttr(task)?.Size as u64;
+        let dev = ImageDevice::New(iops.HostFd(), size, Some(iops));
+        return Self::MountDevice(self.name, &dev, flags);
+    }
+
+    fn AllowUserMount(&self) -> bool {
+        return true;
+    }
+
+    fn AllowUserList(&self) -> bool {
+        return true;
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ops::Deref;
+
+use super::super::super::super::auth::id::*;
+use super::super::super::super::auth::*;
+use super::super::super::super::common::*;
+use super::super::super::super::device::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::fd::*;
+use super::super::super::kernel::time::*;
+use super::super::super::socket::unix::transport::unix::*;
+use super::super::super::task::*;
+use super::super::host::hostinodeop::*;
+use super::super::host::util;
+
+use super::super::attr::*;
+use super::super::dirent::*;
+use super::super::file::*;
+use super::super::flags::*;
+use super::super::inode::*;
+use super::super::mount::*;
+use super::file::*;
+use super::fs::*;
+
+// MAP_COPY_SIZE is the unit a file is copied into its mmap cache in.
+const MAP_COPY_SIZE: usize = 0x100000;
+
+pub struct ImageInodeIntern {
+    pub fs: ImageFs,
+    pub node: ImageNode,
+    pub inodeType: InodeType,
+
+    // children is the parsed directory, read on the first lookup.
+    pub children: QMutex<Option<Arc<BTreeMap<String, ImageDirent>>>>,
+
+    // mapCache is the memfd copy of the file the mappings share, the image
+    // content isn't page aligned to be mapped from the host file.
+    pub mapCache: QMutex<Option<HostInodeOp>>,
+}
+
+#[derive(Clone)]
+pub struct ImageInodeOps(pub Arc<ImageInodeIntern>);
+
+impl Deref for ImageInodeOps {
+    type Target = Arc<ImageInodeIntern>;
+
+    fn deref(&self) -> &Arc<ImageInodeIntern> {
+        &self.0
+    }
+}
+
+pub fn NewImageInode(fs: &ImageFs, msrc: &Arc<QMutex<MountSource>>, node: &ImageNode) -> Inode {
+    let inodeType = util::InodeType(node.mode as u32);
+    let iops = ImageInodeOps(Arc::new(ImageInodeIntern {
+        fs: fs.clone(),
+        node: *node,
+        inodeType: inodeType,
+        children: QMutex::new(None),
+        mapCache: QMutex::new(None),
+    }));
+
+    let (major, minor) = DecodeDeviceId(node.rdev);
+    let stableAttr = StableAttr {
+        Type: inodeType,
+        DeviceId: fs.deviceId,
+        InodeId: node.ino,
+        BlockSize: fs.BlockSize() as i64,
+        DeviceFileMajor: major,
+        DeviceFileMinor: minor,
+    };
+
+    return Inode::New(iops.into(), msrc, &stableAttr);
+}
+
+impl ImageInodeOps {
+    pub fn Children(&self) -> Result<Arc<BTreeMap<String, ImageDirent>>> {
+        if let Some(children) = self.children.lock().as_ref() {
+            return Ok(children.clone());
+        }
+
+        // the directory is parsed without the lock, it may be parsed twice
+        let children = Arc::new(self.fs.ReadDir(&self.node)?);
+        *self.children.lock() = Some(children.clone());
+        return Ok(children);
+    }
+
+    pub fn Read(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        return self.fs.Read(&self.node, offset, buf);
+    }
+
+    // MapCache copies the file into a memfd on the first mapping.
+    fn MapCache(&self) -> Result<HostInodeOp> {
+        if let Some(iops) = self.mapCache.lock().as_ref() {
+            return Ok(iops.clone());
+        }
+
+        let size = self.node.size;
+        let iops = HostInodeOp::NewMemfdIops(size as i64)?;
+        let fd = iops.HostFd();
+        let mut buf = DataBuff::New(MAP_COPY_SIZE);
+        let mut offset = 0;
+        while offset < size {
+            let len = self.Read(offset, &mut buf.buf)?;
+            if len == 0 {
+                break;
+            }
+
+            let mut done = 0;
+            while done < len {
+                let iov = IoVec::NewFromAddr(&buf.buf[done] as *const u8 as u64, len - done);
+                let n = IOWriteAt(fd, &[iov], offset + done as u64)?;
+                if n <= 0 {
+                    return Err(Error::SysError(SysErr::EIO));
+                }
+                done += n as usize;
+            }
+            offset += len as u64;
+        }
+
+        let mut cache = self.mapCache.lock();
+        if let Some(iops) = cache.as_ref() {
+            return Ok(iops.clone());
+        }
+
+        *cache = Some(iops.clone());
+        return Ok(iops);
+    }
+}
+
+impl InodeOperations for ImageInodeOps {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn IopsType(&self) -> IopsType {
+        return IopsType::ImageInodeOps;
+    }
+
+    fn InodeType(&self) -> InodeType {
+        return self.inodeType;
+    }
+
+    fn InodeFileType(&self) -> InodeFileType {
+        return InodeFileType::Image;
+    }
+
+    fn WouldBlock(&self) -> bool {
+        return false;
+    }
+
+    fn Lookup(&self, _task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
+        if self.inodeType != InodeType::Directory {
+            return Err(Error::SysError(SysErr::ENOTDIR));
+        }
+
+        let children = self.Children()?;
+        let entry = match children.get(name) {
+            None => return Err(Error::SysError(SysErr::ENOENT)),
+            Some(entry) => *entry,
+        };
+
+        let node = self.fs.Node(entry.ino)?;
+        let msrc = dir.lock().MountSource.clone();
+        let inode = NewImageInode(&self.fs, &msrc, &node);
+        return Ok(Dirent::New(&inode, name));
+    }
+
+    fn Create(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _name: &str,
+        _flags: &FileFlags,
+        _perm: &FilePermissions,
+    ) -> Result<File> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn CreateDirectory(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _name: &str,
+        _perm: &FilePermissions,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn CreateLink(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _oldname: &str,
+        _newname: &str,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn CreateHardLink(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _target: &Inode,
+        _name: &str,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn CreateFifo(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _name: &str,
+        _perm: &FilePermissions,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Remove(&self, _task: &Task, _dir: &mut Inode, _name: &str) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn RemoveDirectory(&self, _task: &Task, _dir: &mut Inode, _name: &str) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Rename(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _oldParent: &Inode,
+        _oldname: &str,
+        _newParent: &Inode,
+        _newname: &str,
+        _replacement: bool,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Bind(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        _name: &str,
+        _data: &BoundEndpoint,
+        _perms: &FilePermissions,
+    ) -> Result<Dirent> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn BoundEndpoint(&self, _task: &Task, _inode: &Inode, _path: &str) -> Option<BoundEndpoint> {
+        return None;
+    }
+
+    fn GetFile(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        let dir = match self.inodeType {
+            InodeType::RegularFile => false,
+            InodeType::Directory => true,
+            // there is no driver behind the device nodes, fifos and sockets
+            _ => return Err(Error::SysError(SysErr::ENXIO)),
+        };
+
+        let fops = ImageFileOps::New(self, dir);
+        let mut flags = flags;
+        if !dir {
+            flags.Pread = true;
+            flags.PWrite = true;
+        }
+
+        return Ok(File::New(dirent, &flags, fops.into()));
+    }
+
+    fn UnstableAttr(&self, _task: &Task) -> Result<UnstableAttr> {
+        let node = &self.node;
+        return Ok(UnstableAttr {
+            Size: node.size as i64,
+            Usage: node.usage as i64,
+            Perms: FileMode(node.mode).FilePerms(),
+            Owner: FileOwner {
+                UID: KUID(node.uid),
+                GID: KGID(node.gid),
+            },
+            AccessTime: node.atime,
+            ModificationTime: node.mtime,
+            StatusChangeTime: node.ctime,
+            Links: node.links,
+        });
+    }
+
+    // the extended attributes of the images aren't parsed
+    fn Getxattr(&self, _dir: &Inode, _name: &str, _size: usize) -> Result<Vec<u8>> {
+        return Err(Error::SysError(SysErr::ENODATA));
+    }
+
+    fn Setxattr(&self, _dir: &mut Inode, _name: &str, _value: &[u8], _flags: u32) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
+        return Ok(Vec::new());
+    }
+
+    fn Removexattr(&self, _dir: &Inode, _name: &str) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
+        return ContextCanAccessFile(task, inode, reqPerms);
+    }
+
+    fn SetPermissions(&self, _task: &Task, _dir: &mut Inode, _f: FilePermissions) -> bool {
+        return false;
+    }
+
+    fn SetOwner(&self, _task: &Task, _dir: &mut Inode, _owner: &FileOwner) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn SetTimestamps(&self, _task: &Task, _dir: &mut Inode, _ts: &InterTimeSpec) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Truncate(&self, _task: &Task, _dir: &mut Inode, _size: i64) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EROFS));
+    }
+
+    fn ReadLink(&self, _task: &Task, _dir: &Inode) -> Result<String> {
+        if self.inodeType != InodeType::Symlink {
+            return Err(Error::SysError(SysErr::ENOLINK));
+        }
+
+        return self.fs.ReadLink(&self.node);
+    }
+
+    fn GetLink(&self, _task: &Task, _dir: &Inode) -> Result<Dirent> {
+        if self.inodeType != InodeType::Symlink {
+            return Err(Error::SysError(SysErr::ENOLINK));
+        }
+
+        return Err(Error::ErrResolveViaReadlink);
+    }
+
+    fn AddLink(&self, _task: &Task) {}
+
+    fn DropLink(&self, _task: &Task) {}
+
+    fn IsVirtual(&self) -> bool {
+        return false;
+    }
+
+    fn Sync(&self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn StatFS(&self, _task: &Task) -> Result<FsInfo> {
+        return Ok(self.fs.StatFS());
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        if self.inodeType != InodeType::RegularFile {
+            return Err(Error::SysError(SysErr::ENODEV));
+        }
+
+        let iops = self.MapCache()?;
+        return Ok(MMappable::FromHostIops(iops));
+    }
+}
@@ -32,10 +32,12 @@ use super::super::super::kernel::fs::host::fs::*;
 use super::super::super::kernel::Kernel::HostSpace;
 use super::super::super::linux_def::*;
 use super::super::super::qmsg::qcall::TmpfsFileType;
+use super::super::super::range::*;
 use super::super::kernel::time::*;
 use super::super::socket::unix::transport::unix::*;
 use super::super::task::*;
 use super::super::uid::*;
+use super::super::SHARESPACE;
 
 use super::attr::*;
 use super::dentry::*;
@@ -56,7 +58,10 @@ use crate::qlib::kernel::fs::dev::random::RandomDevice;
 use crate::qlib::kernel::fs::dev::tty::TTYDevice;
 use crate::qlib::kernel::fs::dev::zero::ZeroDevice;
 use crate::qlib::kernel::fs::fsutil::inode::SimpleFileInode;
+use crate::qlib::kernel::fs::fuse::dev::FuseDevice;
+use crate::qlib::kernel::fs::fuse::inode::FuseInodeOps;
 use crate::qlib::kernel::fs::host::fifoiops::FifoIops;
+use crate::qlib::kernel::fs::image::inode::ImageInodeOps;
 use crate::qlib::kernel::fs::procfs::dir_proc::DirNode;
 use crate::qlib::kernel::fs::procfs::inode::StaticFileInodeOps;
 use crate::qlib::kernel::fs::procfs::inode::TaskOwnedInodeOps;
@@ -151,7 +156,10 @@ pub enum IopsType {
     SimpleFileInode,
     ProxyDevice,
     NvFrontendDevice,
-    UvmDevice
+    UvmDevice,
+    FuseDevice,
+    FuseInodeOps,
+    ImageInodeOps,
 }
 
 #[enum_dispatch]
@@ -186,7 +194,10 @@ pub enum Iops {
     PipeIops(PipeIops),
     UnixSocketInodeOps(UnixSocketInodeOps),
     NvFrontendDevice(NvFrontendDevice),
-    UvmDevice(UvmDevice)
+    UvmDevice(UvmDevice),
+    FuseDevice(FuseDevice),
+    FuseInodeOps(FuseInodeOps),
+    ImageInodeOps(ImageInodeOps),
 }
 
 impl Iops {
@@ -267,6 +278,13 @@ impl Iops {
         }
     }
 
+    pub fn FuseInodeOps(&self) -> Option<FuseInodeOps> {
+        match self {
+            Self::FuseInodeOps(inner) => Some(inner.clone()),
+            _ => None,
+        }
+    }
+
     pub fn PipeIops(&self) -> Option<PipeIops> {
         match self {
             Self::PipeIops(inner) => Some(inner.clone()),
@@ -349,7 +367,14 @@ pub trait InodeOperations: Sync + Send {
     fn SetOwner(&self, task: &Task, dir: &mut Inode, owner: &FileOwner) -> Result<()>;
     fn SetTimestamps(&self, task: &Task, dir: &mut Inode, ts: &InterTimeSpec) -> Result<()>;
     fn Truncate(&self, task: &Task, dir: &mut Inode, size: i64) -> Result<()>;
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()>;
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()>;
     fn ReadLink(&self, _task: &Task, dir: &Inode) -> Result<String>;
     fn GetLink(&self, _task: &Task, dir: &Inode) -> Result<Dirent>;
     fn AddLink(&self, _task: &Task);
@@ -375,6 +400,28 @@ pub struct LockCtx {
     pub BSD: Locks,
 }
 
+impl LockCtx {
+    // NewHost returns the lock context of a host backed regular file, whose
+    // locks are mirrored to the host fd when HostFileLock is enabled.
+    pub fn NewHost(fd: i32) -> Self {
+        if !SHARESPACE.config.read().HostFileLock {
+            return Self::default();
+        }
+
+        return Self {
+            Posix: Locks::NewHost(HostLocker::Ofd(fd)),
+            BSD: Locks::NewHost(HostLocker::Flock(fd)),
+        };
+    }
+
+    // SyncHost takes the host locks again after the host fd is reopened, the
+    // host locks are released with the old open file.
+    pub fn SyncHost(&self, task: &Task) {
+        self.Posix.SyncHost(task, &Range::Max());
+        self.BSD.SyncHost(task, &Range::Max());
+    }
+}
+
 #[derive(Clone)]
 pub struct InodeWeak(pub Weak<QMutex<InodeIntern>>);
 
@@ -533,11 +580,21 @@ impl Inode {
                     isMemfd,
                 );
 
+                if inodeType == InodeType::RegularFile {
+                    iops.lock().quota = msrc.lock().quota.clone();
+                }
+
+                let lockCtx = if inodeType == InodeType::RegularFile && !isMemfd {
+                    LockCtx::NewHost(fd)
+                } else {
+                    LockCtx::default()
+                };
+
                 return Ok(Self(Arc::new(QMutex::new(InodeIntern {
                     UniqueId: NewUID(),
                     InodeOp: iops.into(),
                     StableAttr: fstat.StableAttr(),
-                    LockCtx: LockCtx::default(),
+                    LockCtx: lockCtx,
                     MountSource: msrc.clone(),
                     Overlay: None,
                 }))));
@@ -926,15 +983,22 @@ impl Inode {
         return Ok(());
     }
 
-    pub fn Allocate(&mut self, task: &Task, d: &Dirent, offset: i64, length: i64) -> Result<()> {
+    pub fn Allocate(
+        &mut self,
+        task: &Task,
+        d: &Dirent,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
         let isOverlay = self.lock().Overlay.is_some();
         if isOverlay {
             let overlay = self.lock().Overlay.as_ref().unwrap().clone();
-            return overlayAllocate(task, &overlay, d, offset, length);
+            return overlayAllocate(task, &overlay, d, mode, offset, length);
         }
 
         let op = self.lock().InodeOp.clone();
-        op.Allocate(task, self, offset, length)?;
+        op.Allocate(task, self, mode, offset, length)?;
         return Ok(());
     }
 
@@ -1131,8 +1195,19 @@ impl Inode {
             return overlayStatFS(task, &overlay);
         }
 
+        let tmpfsUsage = self.lock().MountSource.lock().tmpfsUsage.clone();
+        if let Some(usage) = tmpfsUsage {
+            return Ok(usage.FsInfo());
+        }
+
+        let quota = self.lock().MountSource.lock().quota.clone();
         let inodeOp = self.lock().InodeOp.clone();
-        return inodeOp.StatFS(task);
+        let info = inodeOp.StatFS(task)?;
+        if let Some(quota) = quota {
+            return Ok(quota.FsInfo(info, self.StableAttr().BlockSize));
+        }
+
+        return Ok(info);
     }
 }
 
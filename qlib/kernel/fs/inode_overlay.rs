@@ -38,13 +38,16 @@ use super::overlay::*;
 
 pub fn OverlayHasWhiteout(task: &Task, parent: &Inode, name: &str) -> bool {
     match parent.Getxattr(task, &XattrOverlayWhiteout(name), 1) {
-        Ok(s) => {
-            if s.len() != 0 {
-                return false;
-            }
+        Ok(s) => return s.len() == 1 && s[0] == 'y' as u8,
+        _ => return false,
+    }
+}
 
-            return s[0] == 'y' as u8;
-        }
+// OverlayIsOpaque returns whether the upper directory hides the content of
+// the lower directory it covers.
+pub fn OverlayIsOpaque(task: &Task, dir: &Inode) -> bool {
+    match dir.Getxattr(task, XATTR_OVERLAY_OPAQUE, 1) {
+        Ok(s) => return s.len() == 1 && s[0] == 'y' as u8,
         _ => return false,
     }
 }
@@ -132,7 +135,11 @@ pub fn overlayLookup(
         upperInode.as_ref().unwrap().lock().StableAttr =
             lowerInode.as_ref().unwrap().lock().StableAttr;
 
-        if upperInode.as_ref().unwrap().StableAttr().IsDir() {
+        // The lower directory is only kept to merge its entries in Readdir,
+        // unless the upper directory is opaque.
+        if !upperInode.as_ref().unwrap().StableAttr().IsDir()
+            || OverlayIsOpaque(task, upperInode.as_ref().unwrap())
+        {
             lowerInode = None;
         }
     }
@@ -559,8 +566,9 @@ pub fn overlaySetxattr(
     value: &[u8],
     flags: u32,
 ) -> Result<()> {
-    if HasPrefix(name, &XATTR_OVERLAY_PREFIX.to_string()) {
-        return Err(Error::SysError(SysErr::ENODATA));
+    // Don't allow changes to overlay xattrs through a setxattr syscall.
+    if IsXattrOverlay(name) {
+        return Err(Error::SysError(SysErr::EPERM));
     }
 
     copyUp(task, d)?;
@@ -692,6 +700,7 @@ pub fn overlayAllocate(
     task: &Task,
     o: &Arc<RwLock<OverlayEntry>>,
     d: &Dirent,
+    mode: i32,
     offset: i64,
     length: i64,
 ) -> Result<()> {
@@ -700,7 +709,7 @@ pub fn overlayAllocate(
     let overlay = o.read();
     let mut upperInode = overlay.upper.as_ref().unwrap().clone();
     let upperInodeOps = upperInode.lock().InodeOp.clone();
-    return upperInodeOps.Allocate(task, &mut upperInode, offset, length);
+    return upperInodeOps.Allocate(task, &mut upperInode, mode, offset, length);
 }
 
 pub fn overlayReadlink(task: &Task, o: &Arc<RwLock<OverlayEntry>>) -> Result<String> {
@@ -28,6 +28,7 @@ use super::super::kernel::waiter::Queue;
 use super::super::task::*;
 use super::super::uid::*;
 use super::file::*;
+use super::host::hostinotify::*;
 use crate::qlib::kernel::fs::attr::UnstableAttr;
 use crate::qlib::kernel::fs::dentry::*;
 use crate::qlib::kernel::kernel::waiter::*;
@@ -163,6 +164,10 @@ pub struct WatchesIntern {
     // knowing if the target inode is going down due to a deletion or
     // revalidation.
     pub unlinked: bool,
+
+    // hostWd is the host inotify watch descriptor of the target if it is a
+    // host backed file watched for the host changes, 0 if not watched.
+    pub hostWd: i32,
 }
 
 #[derive(Default, Clone)]
@@ -239,6 +244,26 @@ impl Watches {
             return;
         }
 
+        // the host reports the same event for a host watched file
+        let hostWd = self.read().hostWd;
+        if hostWd != 0 {
+            HOST_INOTIFY.Echo(hostWd, name, events);
+        }
+
+        self.notify(name, events, cookie, et, unlinked);
+    }
+
+    // NotifyFromHost queues an event of the host watched file which is made
+    // outside of the sandbox.
+    pub fn NotifyFromHost(&self, name: &str, events: u32, cookie: u32, et: EventType) {
+        if self.read().ws.len() == 0 {
+            return;
+        }
+
+        self.notify(name, events, cookie, et, false);
+    }
+
+    fn notify(&self, name: &str, events: u32, cookie: u32, et: EventType, unlinked: bool) {
         let mut hasExpired = false;
         let mut watchArr = Vec::new();
         {
@@ -16,16 +16,20 @@ use crate::qlib::mutex::*;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::ops::Deref;
 
 use super::super::super::common::*;
 use super::super::super::kernel::fs::file::*;
 use super::super::super::linux::fcntl::*;
+use super::super::super::linux::time::*;
 use super::super::super::linux_def::*;
 use super::super::super::mem::areaset::*;
 use super::super::super::range::*;
+use super::super::kernel::waiter::qlock::*;
 use super::super::kernel::waiter::*;
 use super::super::task::*;
+use super::super::Kernel::HostSpace;
 
 #[derive(Clone, Copy, Debug)]
 pub enum LockType {
@@ -41,6 +45,125 @@ pub enum LockType {
     WriteLock,
 }
 
+// HOST_LOCK_MIN_BACKOFF and HOST_LOCK_MAX_BACKOFF bound the interval of
+// retrying a blocking lock which conflicts with a host lock. The host doesn't
+// notify the unlock, so the waiter polls the host fd.
+pub const HOST_LOCK_MIN_BACKOFF: Duration = 10 * MILLISECOND;
+pub const HOST_LOCK_MAX_BACKOFF: Duration = 500 * MILLISECOND;
+
+// HostLocker mirrors the guest locks of a host backed file to the host fd, so
+// that they are coherent with the other sandboxes and host processes sharing
+// the file. The host fd is shared by all the guest files of the inode, so the
+// host holds the union of the guest locks.
+#[derive(Clone, Copy, Debug)]
+pub enum HostLocker {
+    // Ofd mirrors the regional fcntl(2) locks with host OFD locks
+    Ofd(i32),
+
+    // Flock mirrors the file wide flock(2) locks with host flock(2) locks
+    Flock(i32),
+}
+
+impl HostLocker {
+    fn HostFlock(t: Option<LockType>, r: &Range) -> Flock {
+        let typ = match t {
+            None => F_UNLCK,
+            Some(LockType::ReadLock) => F_RDLCK,
+            Some(LockType::WriteLock) => F_WRLCK,
+        };
+
+        return Flock {
+            Type: typ as _,
+            Whence: SeekWhence::SEEK_SET as _,
+            Start: r.Start() as _,
+            // 0 is to the end of the file
+            Len: if r.End() == MAX_RANGE {
+                0
+            } else {
+                r.Len() as _
+            },
+            Pid: 0,
+        };
+    }
+
+    // Set sets the host lock of the range without blocking. It returns false
+    // if the lock is held by another host open file.
+    pub fn Set(&self, t: Option<LockType>, r: &Range) -> Result<bool> {
+        let ret = match self {
+            Self::Ofd(fd) => {
+                let flock = Self::HostFlock(t, r);
+                HostSpace::Fcntl(*fd, Cmd::F_OFD_SETLK, &flock as *const _ as u64)
+            }
+            Self::Flock(fd) => {
+                let operation = match t {
+                    None => LibcConst::LOCK_UN,
+                    Some(LockType::ReadLock) => LibcConst::LOCK_SH,
+                    Some(LockType::WriteLock) => LibcConst::LOCK_EX,
+                };
+                HostSpace::HostFlock(*fd, operation as i32)
+            }
+        };
+
+        if ret == 0 {
+            return Ok(true);
+        }
+
+        let errno = -ret as i32;
+        if errno == SysErr::EAGAIN || errno == SysErr::EACCES {
+            return Ok(false);
+        }
+
+        // The host file system doesn't support the lock, e.g. NFS without
+        // lockd, keep the lock in guest only as before.
+        if errno == SysErr::ENOLCK || errno == SysErr::EOPNOTSUPP {
+            info!(
+                "HostLocker: host lock {:?} on {:?} not supported {}",
+                t, self, errno
+            );
+            return Ok(true);
+        }
+
+        error!(
+            "HostLocker: set host lock {:?} on {:?} fail {}",
+            t, self, errno
+        );
+        return Err(Error::SysError(errno));
+    }
+
+    // Test returns the host lock which conflicts with a typed lock of the range.
+    pub fn Test(&self, t: LockType, r: &Range) -> Option<Flock> {
+        let fd = match self {
+            Self::Ofd(fd) => *fd,
+            Self::Flock(_) => return None,
+        };
+
+        let mut flock = Self::HostFlock(Some(t), r);
+        let ret = HostSpace::Fcntl(fd, Cmd::F_OFD_GETLK, &mut flock as *mut _ as u64);
+        if ret < 0 || flock.Type == F_UNLCK as i16 {
+            return None;
+        }
+
+        // The holder is outside of the sandbox and has no guest pid.
+        flock.Pid = -1;
+        return Some(flock);
+    }
+
+    // Sync sets the host locks to the guest ones of the ranges.
+    pub fn Sync(&self, ranges: &[(Range, Option<LockType>)]) {
+        for (r, t) in ranges {
+            match self.Set(*t, r) {
+                Ok(true) => (),
+                Ok(false) => error!(
+                    "HostLocker: sync host lock {:?} of {:?} on {:?} conflict",
+                    t, r, self
+                ),
+                // Set has logged the error
+                Err(_) => (),
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct OwnerInfo {
     pub pid: i32,
@@ -218,6 +341,13 @@ pub struct LocksInternal {
 
     // queue is the queue of waiters that are waiting on a lock.
     pub queue: Queue,
+
+    // host mirrors the locks to the host fd of a host backed file
+    pub host: Option<HostLocker>,
+
+    // hostLock serializes the changes of the locks with host, the guest locks
+    // only change with the host ones.
+    pub hostLock: QAsyncLock,
 }
 
 impl Default for LocksInternal {
@@ -225,6 +355,8 @@ impl Default for LocksInternal {
         return Self {
             locks: AreaSet::New(0, MAX_RANGE),
             queue: Queue::default(),
+            host: None,
+            hostLock: QAsyncLock::default(),
         };
     }
 }
@@ -372,6 +504,58 @@ impl LocksInternal {
         return true;
     }
 
+    // Holds returns true if uid holds a lock in the range.
+    pub fn Holds(&self, uid: UniqueId, r: &Range) -> bool {
+        return !self.Lockable(r, &|value: &Lock| !value.IsHeld(uid));
+    }
+
+    // HostRanges returns the host lock of each part of the range, which is the
+    // strongest guest lock of the part.
+    pub fn HostRanges(&self, host: &HostLocker, r: &Range) -> Vec<(Range, Option<LockType>)> {
+        let mut ret = Vec::new();
+        match host {
+            HostLocker::Flock(_) => {
+                let mut t = None;
+                let mut seg = self.locks.LowerBoundSeg(0);
+                while seg.Ok() {
+                    if seg.Value().lock().Writer.is_some() {
+                        t = Some(LockType::WriteLock);
+                        break;
+                    }
+                    t = Some(LockType::ReadLock);
+                    seg = seg.NextSeg();
+                }
+                ret.push((Range::Max(), t));
+            }
+            HostLocker::Ofd(_) => {
+                let mut cur = r.Start();
+                let mut seg = self.locks.LowerBoundSeg(r.Start());
+                while seg.Ok() && seg.Range().Start() < r.End() {
+                    let start = core::cmp::max(seg.Range().Start(), r.Start());
+                    let end = core::cmp::min(seg.Range().End(), r.End());
+                    if start > cur {
+                        ret.push((Range::New(cur, start - cur), None));
+                    }
+
+                    let t = if seg.Value().lock().Writer.is_some() {
+                        LockType::WriteLock
+                    } else {
+                        LockType::ReadLock
+                    };
+                    ret.push((SubRange(start, end), Some(t)));
+                    cur = end;
+                    seg = seg.NextSeg();
+                }
+
+                if cur < r.End() {
+                    ret.push((SubRange(cur, r.End()), None));
+                }
+            }
+        }
+
+        return ret;
+    }
+
     pub fn CanLock(&self, uid: UniqueId, t: LockType, r: &Range) -> bool {
         match t {
             LockType::ReadLock => {
@@ -420,6 +604,14 @@ impl Deref for Locks {
 }
 
 impl Locks {
+    // NewHost returns the locks of a host backed file which are mirrored to the
+    // host fd.
+    pub fn NewHost(host: HostLocker) -> Self {
+        let locks = Self::default();
+        locks.lock().host = Some(host);
+        return locks;
+    }
+
     // LockRegion attempts to acquire a typed lock for the uid on a region
     // of a file. Returns true if successful in locking the region. If false
     // is returned, the caller should normally interpret this as "try again later" if
@@ -435,6 +627,11 @@ impl Locks {
         r: &Range,
         block: bool,
     ) -> Result<bool> {
+        let host = self.lock().host;
+        if let Some(host) = host {
+            return self.LockRegionWithHost(task, &host, uid, owner, t, r, block);
+        }
+
         loop {
             let mut l = self.lock();
 
@@ -465,6 +662,95 @@ impl Locks {
         }
     }
 
+    // LockRegionWithHost takes the lock on the host fd before the guest one.
+    // The guest locks only change with hostLock held, so the guest lock checked
+    // before the host call can still be taken after it.
+    fn LockRegionWithHost(
+        &self,
+        task: &Task,
+        host: &HostLocker,
+        uid: UniqueId,
+        owner: OwnerInfo,
+        t: LockType,
+        r: &Range,
+        block: bool,
+    ) -> Result<bool> {
+        // The host takes a 0 length as to the end of the file.
+        if r.Len() == 0 {
+            return Ok(true);
+        }
+
+        let mut backoff = HOST_LOCK_MIN_BACKOFF;
+        loop {
+            let hostLock = self.lock().hostLock.clone();
+            let guard = hostLock.Lock(task);
+
+            let guestFree = self.lock().CanLock(uid, t, r);
+            if guestFree {
+                // After the guest check, the union of the guest locks in the
+                // range is the same type as the new lock.
+                if host.Set(Some(t), r)? {
+                    if self.lock().Lock(uid, owner, t, r) {
+                        return Ok(true);
+                    }
+
+                    // The guest lock can't change with hostLock held, put the
+                    // host lock back to the guest ones if it does anyway.
+                    error!("Locks: guest lock changed during host lock of {:?}", r);
+                    let ranges = self.lock().HostRanges(host, r);
+                    host.Sync(&ranges);
+                    return Err(Error::SysError(SysErr::EAGAIN));
+                }
+            }
+
+            if !block {
+                return Ok(false);
+            }
+
+            // Register before releasing hostLock so that a guest unlock can't be
+            // missed. A host holder doesn't notify the unlock, so the host lock
+            // is polled with backoff.
+            self.lock()
+                .queue
+                .EventRegister(task, &task.blocker.generalEntry, EVENTMASK_ALL);
+            core::mem::drop(guard);
+
+            defer!(self
+                .lock()
+                .queue
+                .EventUnregister(task, &task.blocker.generalEntry));
+
+            let timeout = if guestFree { Some(backoff) } else { None };
+            let (_, res) = task.blocker.BlockWithMonoTimeout(true, timeout);
+            match res {
+                Err(Error::ErrInterrupted) => return Err(Error::SysError(SysErr::ERESTARTSYS)),
+                Err(Error::SysError(SysErr::ETIMEDOUT)) => {
+                    backoff = core::cmp::min(backoff * 2, HOST_LOCK_MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+                Ok(()) => (),
+            }
+        }
+    }
+
+    // SyncHost sets the host locks of the range to the guest ones, e.g. after
+    // the host fd is reopened.
+    pub fn SyncHost(&self, task: &Task, r: &Range) {
+        let (host, hostLock) = {
+            let l = self.lock();
+            (l.host, l.hostLock.clone())
+        };
+
+        let host = match host {
+            None => return,
+            Some(host) => host,
+        };
+
+        let _guard = hostLock.Lock(task);
+        let ranges = self.lock().HostRanges(&host, r);
+        host.Sync(&ranges);
+    }
+
     pub fn Print(&self) -> String {
         return self.lock().locks.Print();
     }
@@ -472,10 +758,31 @@ impl Locks {
     // UnlockRegion attempts to release a lock for the uid on a region of a file.
     // This operation is always successful, even if there did not exist a lock on
     // the requested region held by uid in the first place.
-    pub fn UnlockRegion(&self, _task: &Task, uid: UniqueId, r: &Range) {
+    pub fn UnlockRegion(&self, task: &Task, uid: UniqueId, r: &Range) {
+        let (host, hostLock) = {
+            let l = self.lock();
+            (l.host, l.hostLock.clone())
+        };
+
+        if let Some(host) = host {
+            if !self.lock().Holds(uid, r) {
+                return;
+            }
+
+            let _guard = hostLock.Lock(task);
+            let ranges = {
+                let mut l = self.lock();
+                l.Unlock(uid, r);
+                l.HostRanges(&host, r)
+            };
+            host.Sync(&ranges);
+        }
+
         let mut l = self.lock();
 
-        l.Unlock(uid, r);
+        if host.is_none() {
+            l.Unlock(uid, r);
+        }
 
         // Now that we've released the lock, we need to wake up any waiters.
         l.queue.Notify(EVENTMASK_ALL)
@@ -529,6 +836,15 @@ impl Locks {
             }
         }
 
+        if f.Type == F_UNLCK as i16 {
+            let host = self.lock().host;
+            if let Some(host) = host {
+                if let Some(hostFlock) = host.Test(t, r) {
+                    return hostFlock;
+                }
+            }
+        }
+
         return f;
     }
 
@@ -628,3 +944,13 @@ pub fn ComputeRange(start: i64, length: i64, offset: i64) -> Result<Range> {
 
     return Ok(Range::New(offset as u64, len));
 }
+
+// SubRange returns the range of [start, end), the end of MAX_RANGE is to the
+// end of the file.
+fn SubRange(start: u64, end: u64) -> Range {
+    if end == MAX_RANGE {
+        return Range::New(start, MAX_RANGE);
+    }
+
+    return Range::New(start, end - start);
+}
@@ -21,11 +21,14 @@ pub mod attr;
 pub mod copy_up;
 pub mod dentry;
 pub mod dev;
+pub mod fanotify;
 pub mod file_overlay;
 pub mod filesystems;
 pub mod flags;
 pub mod fsutil;
+pub mod fuse;
 pub mod host;
+pub mod image;
 pub mod inode_overlay;
 pub mod inotify;
 pub mod lock;
@@ -45,4 +48,6 @@ pub fn Init() {
     self::procfs::Init();
     self::sys::Init();
     self::tmpfs::Init();
+    self::fuse::Init();
+    self::image::Init();
 }
@@ -33,9 +33,11 @@ use super::super::super::path::*;
 use super::super::task::*;
 use super::dirent::*;
 use super::filesystems::*;
+use super::host::quota::HostQuota;
 use super::host::*;
 use super::inode::*;
 use super::mount_overlay::*;
+use super::tmpfs::fs::TmpfsUsage;
 use super::tty::fs::*;
 
 pub struct LookupContext {
@@ -599,6 +601,12 @@ pub struct MountSource {
     pub MountSourceOperations: Arc<QMutex<MountSourceOperations>>,
     pub fscache: LruCache<Dirent>,
     frozen: Vec<Dirent>,
+
+    // tmpfsUsage is the size and inode limits of a tmpfs mount
+    pub tmpfsUsage: Option<TmpfsUsage>,
+
+    // quota is the byte and inode quota of a host-backed mount
+    pub quota: Option<HostQuota>,
 }
 
 impl Default for MountSource {
@@ -609,6 +617,8 @@ impl Default for MountSource {
             MountSourceOperations: Arc::new(QMutex::new(SimpleMountSourceOperations::default())),
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 }
@@ -648,6 +658,8 @@ impl MountSource {
             MountSourceOperations: mops.clone(),
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -663,6 +675,8 @@ impl MountSource {
             MountSourceOperations: mops.clone(),
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -678,6 +692,8 @@ impl MountSource {
             MountSourceOperations: mops.clone(),
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -704,6 +720,8 @@ impl MountSource {
             MountSourceOperations: mops,
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -722,6 +740,8 @@ impl MountSource {
             MountSourceOperations: mops,
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -740,6 +760,8 @@ impl MountSource {
             MountSourceOperations: mops,
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -758,6 +780,8 @@ impl MountSource {
             MountSourceOperations: mops,
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -774,6 +798,8 @@ impl MountSource {
             MountSourceOperations: mops,
             fscache: LruCache::New(DEFAULT_DIRENT_CACHE_SIZE),
             frozen: Vec::new(),
+            tmpfsUsage: None,
+            quota: None,
         };
     }
 
@@ -1260,6 +1286,7 @@ mod tests {
             &self,
             _task: &Task,
             _dir: &mut Inode,
+            _mode: i32,
             _offset: i64,
             _length: i64,
         ) -> Result<()> {
@@ -31,9 +31,10 @@ use super::mount_overlay::*;
 
 pub const XATTR_OVERLAY_PREFIX: &str = "trusted.overlay.";
 pub const XATTR_OVERLAY_WHITEOUT_PREFIX: &str = "trusted.overlay.whiteout.";
+pub const XATTR_OVERLAY_OPAQUE: &str = "trusted.overlay.opaque";
 
 pub fn XattrOverlayWhiteout(name: &str) -> String {
-    return XATTR_OVERLAY_PREFIX.to_string() + name;
+    return XATTR_OVERLAY_WHITEOUT_PREFIX.to_string() + name;
 }
 
 pub fn IsXattrOverlay(name: &str) -> bool {
@@ -19,8 +19,10 @@ use enum_dispatch::enum_dispatch;
 
 use super::super::super::super::auth::*;
 use super::super::super::super::common::*;
+use super::super::super::super::control_msg::UnsupportedKind;
 use super::super::super::super::linux_def::*;
 use super::super::super::kernel::time::*;
+use super::super::super::kernel::unsupported::Unsupported;
 use super::super::super::socket::unix::transport::unix::*;
 use super::super::super::task::*;
 use super::super::attr::*;
@@ -60,6 +62,36 @@ pub trait DirDataNodeTrait: Send + Sync {
     }
 }
 
+// LookupProcFile looks up the name in the procfs directory d whose path is
+// dirPath, a missing file is recorded as an unsupported procfs file.
+pub fn LookupProcFile(
+    d: &Dir,
+    task: &Task,
+    dir: &Inode,
+    dirPath: &str,
+    name: &str,
+) -> Result<Dirent> {
+    let err = match d.Lookup(task, dir, name) {
+        Ok(dirent) => return Ok(dirent),
+        Err(e) => e,
+    };
+
+    match err {
+        Error::SysError(SysErr::ENOENT) => {
+            Unsupported(
+                task,
+                UnsupportedKind::ProcFile,
+                &format!("{}/{}", dirPath, name),
+                "",
+                false,
+            );
+        }
+        _ => (),
+    }
+
+    return Err(err);
+}
+
 #[enum_dispatch]
 #[derive(Clone)]
 pub enum DirDataNode {
@@ -247,8 +279,15 @@ impl InodeOperations for DirNode {
         return self.dir.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.dir.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        return self.dir.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
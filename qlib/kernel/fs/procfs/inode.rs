@@ -205,8 +205,15 @@ impl InodeOperations for TaskOwnedInodeOps {
         return self.iops.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.iops.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        return self.iops.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
@@ -388,6 +395,7 @@ impl InodeOperations for StaticFileInodeOps {
                 content: self.read().content.clone(),
             }
             .into(),
+            sockStats: None,
         })));
     }
 
@@ -435,7 +443,14 @@ impl InodeOperations for StaticFileInodeOps {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 
@@ -26,6 +26,7 @@ use super::super::mount::*;
 use super::super::super::super::auth::*;
 use super::super::super::super::common::*;
 use super::super::super::super::linux_def::*;
+use super::super::super::kernel::kernel::GetKernel;
 use super::super::super::task::*;
 use super::super::super::Kernel::HostSpace;
 use super::super::fsutil::file::readonly_file::*;
@@ -55,6 +56,14 @@ impl ReadonlyFileNodeTrait for MeminfoFileNode {
             return Err(Error::SysError(-ret as i32));
         }
 
+        // the memory limit set by the container runtime caps the host memory
+        let limit = GetKernel().MemoryLimit();
+        if limit > 0 && limit < info.totalram {
+            let used = info.totalram - info.freeram;
+            info.totalram = limit;
+            info.freeram = if used < limit { limit - used } else { 0 };
+        }
+
         let mut s = "".to_string();
         // this is just fake meminfo
         // todo: fix this.
@@ -22,10 +22,12 @@ use alloc::vec::Vec;
 use super::super::super::super::auth::*;
 use super::super::super::super::common::*;
 use super::super::super::super::kernel::kernel::kernel::GetKernel;
+use super::super::super::super::kernel::kernel::socket_store::*;
 use super::super::super::super::kernel::socket::control::*;
 use super::super::super::super::kernel::socket::unix::unix::*;
 use super::super::super::super::linux::time::*;
 use super::super::super::super::linux_def::*;
+use super::super::super::super::metric::Metric;
 use super::super::super::socket::unix::transport::unix::*;
 use super::super::super::task::*;
 use super::super::super::tcpip::tcpip::*;
@@ -46,7 +48,7 @@ pub struct NetDirNode {}
 
 impl DirDataNodeTrait for NetDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/net", name);
     }
 
     fn GetFile(
@@ -81,12 +83,6 @@ pub fn NewNetDir(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
         NewStaticProcInode(task, msrc, &Arc::new(netlink.as_bytes().to_vec())),
     );
 
-    let netstat = "TcpExt: SyncookiesSent SyncookiesRecv SyncookiesFailed EmbryonicRsts PruneCalled RcvPruned OfoPruned OutOfWindowIcmps LockDroppedIcmps ArpFilter TW TWRecycled TWKilled PAWSPassive PAWSActive PAWSEstab DelayedACKs DelayedACKLocked DelayedACKLost ListenOverflows ListenDrops TCPPrequeued TCPDirectCopyFromBacklog TCPDirectCopyFromPrequeue TCPPrequeueDropped TCPHPHits TCPHPHitsToUser TCPPureAcks TCPHPAcks TCPRenoRecovery TCPSackRecovery TCPSACKReneging TCPFACKReorder TCPSACKReorder TCPRenoReorder TCPTSReorder TCPFullUndo TCPPartialUndo TCPDSACKUndo TCPLossUndo TCPLostRetransmit TCPRenoFailures TCPSackFailures TCPLossFailures TCPFastRetrans TCPForwardRetrans TCPSlowStartRetrans TCPTimeouts TCPLossProbes TCPLossProbeRecovery TCPRenoRecoveryFail TCPSackRecoveryFail TCPSchedulerFailed TCPRcvCollapsed TCPDSACKOldSent TCPDSACKOfoSent TCPDSACKRecv TCPDSACKOfoRecv TCPAbortOnData TCPAbortOnClose TCPAbortOnMemory TCPAbortOnTimeout TCPAbortOnLinger TCPAbortFailed TCPMemoryPressures TCPSACKDiscard TCPDSACKIgnoredOld TCPDSACKIgnoredNoUndo TCPSpuriousRTOs TCPMD5NotFound TCPMD5Unexpected TCPMD5Failure TCPSackShifted TCPSackMerged TCPSackShiftFallback TCPBacklogDrop TCPMinTTLDrop TCPDeferAcceptDrop IPReversePathFilter TCPTimeWaitOverflow TCPReqQFullDoCookies TCPReqQFullDrop TCPRetransFail TCPRcvCoalesce TCPOFOQueue TCPOFODrop TCPOFOMerge TCPChallengeACK TCPSYNChallenge TCPFastOpenActive TCPFastOpenActiveFail TCPFastOpenPassive TCPFastOpenPassiveFail TCPFastOpenListenOverflow TCPFastOpenCookieReqd TCPSpuriousRtxHostQueues BusyPollRxPackets TCPAutoCorking TCPFromZeroWindowAdv TCPToZeroWindowAdv TCPWantZeroWindowAdv TCPSynRetrans TCPOrigDataSent TCPHystartTrainDetect TCPHystartTrainCwnd TCPHystartDelayDetect TCPHystartDelayCwnd TCPACKSkippedSynRecv TCPACKSkippedPAWS TCPACKSkippedSeq TCPACKSkippedFinWait2 TCPACKSkippedTimeWait TCPACKSkippedChallenge TCPWinProbe TCPKeepAlive TCPMTUPFail TCPMTUPSuccess\n\n";
-    contents.insert(
-        "netstat".to_string(),
-        NewStaticProcInode(task, msrc, &Arc::new(netstat.as_bytes().to_vec())),
-    );
-
     let packet = "sk       RefCnt Type Proto  Iface R Rmem   User   Inode\n";
     contents.insert(
         "packet".to_string(),
@@ -120,9 +116,14 @@ pub fn NewNetDir(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
         NewStaticProcInode(task, msrc, &Arc::new(ptype.as_bytes().to_vec())),
     );
 
-    contents.insert("tcp".to_string(), NewNetTCP(task, msrc));
-    contents.insert("udp".to_string(), NewNetUDP(task, msrc));
+    contents.insert("tcp".to_string(), NewNetTCP(task, msrc, AFType::AF_INET));
+    contents.insert("tcp6".to_string(), NewNetTCP(task, msrc, AFType::AF_INET6));
+    contents.insert("udp".to_string(), NewNetUDP(task, msrc, AFType::AF_INET));
+    contents.insert("udp6".to_string(), NewNetUDP(task, msrc, AFType::AF_INET6));
     contents.insert("unix".to_string(), NewNetUnix(task, msrc));
+    contents.insert("snmp".to_string(), NewNetSnmp(task, msrc));
+    contents.insert("netstat".to_string(), NewNetNetstat(task, msrc));
+    contents.insert("sockstat".to_string(), NewNetSockstat(task, msrc));
 
     let taskDir = DirNode {
         dir: Dir::New(
@@ -137,6 +138,13 @@ pub fn NewNetDir(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
     return NewProcInode(taskDir.into(), msrc, InodeType::SpecialDirectory, None);
 }
 
+// TCP states as rendered in the st column of /proc/net/{tcp,udp}.
+pub const TCP_ESTABLISHED: u32 = 1;
+pub const TCP_SYN_SENT: u32 = 2;
+pub const TCP_CLOSE: u32 = 7;
+pub const TCP_CLOSE_WAIT: u32 = 8;
+pub const TCP_LISTEN: u32 = 10;
+
 pub fn NetworkToHost16(n: u16) -> u16 {
     let low = n & 0xff;
     let high = (n >> 8) & 0xff;
@@ -167,26 +175,9 @@ pub fn WriteInetAddr(addr: &SockAddr) -> String {
     }
 }
 
-pub struct NetTCP {}
-
-impl SimpleFileTrait for NetTCP {
-    fn GetFile(
-        &self,
-        _task: &Task,
-        _dir: &Inode,
-        dirent: &Dirent,
-        flags: FileFlags,
-    ) -> Result<File> {
-        let fops = ReadonlyFileOperations {
-            node: NetTCPReadonlyFileNode {}.into(),
-        };
-
-        let file = File::New(dirent, &flags, fops.into());
-        return Ok(file);
-    }
-}
-
-pub fn NewNetTCP(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+// NewNetFile creates a read-only /proc/net file backed by a SimpleFileTrait
+// which renders its content on open.
+pub fn NewNetFile(task: &Task, msrc: &Arc<QMutex<MountSource>>, data: SimpleFileImpl) -> Inode {
     let node = SimpleFileInode::New(
         task,
         &ROOT_OWNER,
@@ -210,169 +201,159 @@ pub fn NewNetTCP(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
         },
         FSMagic::ANON_INODE_FS_MAGIC,
         false,
-        NetTCP {}.into(),
+        data,
     );
 
     return NewProcInode(node.into(), msrc, InodeType::SpecialFile, None);
 }
 
-#[derive(Clone)]
-pub struct NetTCPReadonlyFileNode {}
-
-impl ReadonlyFileNodeTrait for NetTCPReadonlyFileNode {
-    fn ReadAt(
-        &self,
-        task: &Task,
-        _f: &File,
-        dsts: &mut [IoVec],
-        offset: i64,
-        _blocking: bool,
-    ) -> Result<i64> {
-        if offset < 0 {
-            return Err(Error::SysError(SysErr::EINVAL));
-        }
-
-        let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode                                                     \n";
-        let bytes = GetData(task, AFType::AF_INET, header)?;
-        if offset as usize > bytes.len() {
-            return Ok(0);
-        }
-
-        let n = task.CopyDataOutToIovs(&bytes[offset as usize..], dsts, true)?;
+pub fn CopyNetData(task: &Task, bytes: &[u8], dsts: &mut [IoVec], offset: i64) -> Result<i64> {
+    if offset < 0 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
 
-        return Ok(n as i64);
+    if offset as usize > bytes.len() {
+        return Ok(0);
     }
-}
 
-pub fn GetData(task: &Task, fa: i32, header: &str) -> Result<Vec<u8>> {
-    let kernel = GetKernel();
-    let sockets = kernel.sockets.ListSockets();
+    let n = task.CopyDataOutToIovs(&bytes[offset as usize..], dsts, true)?;
+    return Ok(n as i64);
+}
 
-    let mut buf = header.to_string();
-    for (id, file) in sockets {
+// InetSockets returns the registered sockets of the given family and type,
+// whichever provider (hostinet, uring, TSoT, RDMA or netstack) serves them.
+pub fn InetSockets(family: i32, stype: i32) -> Vec<File> {
+    let mut ret = Vec::new();
+    for (_id, file) in GetKernel().sockets.ListSockets() {
         let fopsType = file.FileOp.FopsType();
-        //&& fopsType != FileOpsType::UnixSocketOperations
-        if fopsType != FileOpsType::SocketOperations {
+        if !fopsType.IsSocket() || fopsType == FileOpsType::UnixSocketOperations {
             continue;
         }
 
-        let sockops = file.FileOp.clone();
-        /*let sockops = fileops
-            .as_any()
-            .downcast_ref::<SocketOperations>()
-            .expect("SocketOperations convert fail")
-            .clone();
-
-        let family = sockops.family;
-        let stype = sockops.stype;*/
-
-        let (family, stype, _protocol) = sockops.Type();
-
-        if fa != family || stype != SockType::SOCK_STREAM {
-            continue;
+        let (fa, st, _protocol) = file.FileOp.Type();
+        if fa == family && st == stype {
+            ret.push(file);
         }
+    }
 
-        // Linux's documentation for the fields below can be found at
-        // https://www.kernel.org/doc/Documentation/networking/proc_net_tcp.txt.
-        // For Linux's implementation, see net/ipv4/tcp_ipv4.c:get_tcp4_sock().
-        // Note that the header doesn't contain labels for all the fields.
-
-        // Field: sl; entry number.
-        buf += &format!("{:>4}: ", id);
-
-        let mut sockBuf = [0; 256];
-
-        // Field: local_adddress.
-        let _ = sockops.GetSockName(task, &mut sockBuf)?;
-        let addr = GetAddr(AFType::AF_INET as _, &sockBuf)?;
-        buf += &WriteInetAddr(&addr);
-
-        // Field: rem_address.
-        let addr = {
-            match sockops.GetPeerName(task, &mut sockBuf) {
-                Err(_) => {
-                    let inetAddr = SockAddrInet {
-                        Family: family as _,
-                        ..Default::default()
-                    };
-                    SockAddr::Inet(inetAddr)
-                }
-                Ok(_) => GetAddr(AFType::AF_INET as _, &sockBuf)?,
-            }
-        };
-
-        buf += &WriteInetAddr(&addr);
-
-        // Field: state; socket state.
-        buf += &format!("{:02X} ", sockops.State());
-
-        // Field: tx_queue, rx_queue; number of packets in the transmit and
-        // receive queue. Unimplemented.
-        buf += &format!("{:08X}:{:08X} ", 0, 0);
-
-        // Field: tr, tm->when; timer active state and number of jiffies
-        // until timer expires. Unimplemented.
-        buf += &format!("{:02X}:{:08X} ", 0, 0);
+    return ret;
+}
 
-        // Field: retrnsmt; number of unrecovered RTO timeouts.
-        // Unimplemented.
-        buf += &format!("{:08X} ", 0);
+// InetAddr returns the local or peer address of a socket, or the zero
+// address of the family if it is unbound or unconnected.
+pub fn InetAddr(task: &Task, file: &File, family: i32, peer: bool) -> (SockAddr, bool) {
+    let mut sockBuf = [0; 256];
+    let res = if peer {
+        file.FileOp.GetPeerName(task, &mut sockBuf)
+    } else {
+        file.FileOp.GetSockName(task, &mut sockBuf)
+    };
 
-        // Field: uid.
-        match file.Dirent.Inode().UnstableAttr(task) {
-            Err(e) => {
-                error!("Failed to retrieve unstable attr for socket file: {:?}", e);
-                buf += &format!("{:<5} ", 0);
-            }
-            Ok(uattr) => {
-                let creds = task.Creds();
-                let usernamespace = creds.lock().UserNamespace.clone();
-                buf += &format!("{:<5} ", uattr.Owner.UID.In(&usernamespace).OrOverflow().0);
-            }
+    if res.is_ok() {
+        if let Ok(addr) = GetAddr(family as _, &sockBuf) {
+            return (addr, true);
         }
+    }
 
-        // Field: timeout; number of unanswered 0-window probes.
-        // Unimplemented.
-        buf += &format!("{:>8} ", 0);
+    let addr = if family == AFType::AF_INET6 {
+        SockAddr::Inet6(SocketAddrInet6 {
+            Family: family as _,
+            ..Default::default()
+        })
+    } else {
+        SockAddr::Inet(SockAddrInet {
+            Family: family as _,
+            ..Default::default()
+        })
+    };
 
-        // Field: inode.
-        let inodeId = file.Dirent.Inode().StableAttr().InodeId;
-        buf += &format!("{:>8} ", inodeId);
+    return (addr, false);
+}
 
-        // Field: ref; reference count on the socket inode. Don't count the ref
-        // we obtain while deferencing the weakref to this socket.
-        buf += &format!("{ } ", file.ReadRefs() - 1);
+// WriteInetSocket writes the fields shared by /proc/net/tcp and
+// /proc/net/udp, up to and including the socket struct address.
+//
+// Linux's documentation for the fields below can be found at
+// https://www.kernel.org/doc/Documentation/networking/proc_net_tcp.txt.
+// For Linux's implementation, see net/ipv4/tcp_ipv4.c:get_tcp4_sock().
+// Note that the header doesn't contain labels for all the fields.
+pub fn WriteInetSocket(
+    task: &Task,
+    buf: &mut String,
+    sl: usize,
+    file: &File,
+    family: i32,
+    stype: i32,
+) {
+    // Field: sl; entry number.
+    *buf += &format!("{:>4}: ", sl);
+
+    // Field: local_adddress.
+    let (addr, _) = InetAddr(task, file, family, false);
+    *buf += &WriteInetAddr(&addr);
+
+    // Field: rem_address.
+    let (addr, connected) = InetAddr(task, file, family, true);
+    *buf += &WriteInetAddr(&addr);
+
+    // Field: state; socket state. Datagram sockets don't track a protocol
+    // state, Linux reports them as established once connected.
+    let state = if stype == SockType::SOCK_STREAM {
+        file.FileOp.State()
+    } else if connected {
+        TCP_ESTABLISHED
+    } else {
+        TCP_CLOSE
+    };
+    *buf += &format!("{:02X} ", state);
 
-        // Field: Socket struct address. Redacted due to the same reason as
-        // the 'Num' field in /proc/net/unix, see netUnix.ReadSeqFileData.
-        buf += &format!("{:>16} ", 0);
+    // Field: tx_queue, rx_queue; number of packets in the transmit and
+    // receive queue. Unimplemented.
+    *buf += &format!("{:08X}:{:08X} ", 0, 0);
 
-        // Field: retransmit timeout. Unimplemented.
-        buf += &format!("{} ", 0);
+    // Field: tr, tm->when; timer active state and number of jiffies
+    // until timer expires. Unimplemented.
+    *buf += &format!("{:02X}:{:08X} ", 0, 0);
 
-        // Field: predicted tick of soft clock (delayed ACK control data).
-        // Unimplemented.
-        buf += &format!("{} ", 0);
+    // Field: retrnsmt; number of unrecovered RTO timeouts.
+    // Unimplemented.
+    *buf += &format!("{:08X} ", 0);
 
-        // Field: (ack.quick<<1)|ack.pingpong, Unimplemented.
-        buf += &format!("{} ", 0);
+    // Field: uid.
+    match file.Dirent.Inode().UnstableAttr(task) {
+        Err(e) => {
+            error!("Failed to retrieve unstable attr for socket file: {:?}", e);
+            *buf += &format!("{:<5} ", 0);
+        }
+        Ok(uattr) => {
+            let creds = task.Creds();
+            let usernamespace = creds.lock().UserNamespace.clone();
+            *buf += &format!("{:<5} ", uattr.Owner.UID.In(&usernamespace).OrOverflow().0);
+        }
+    }
 
-        // Field: sending congestion window, Unimplemented.
-        buf += &format!("{} ", 0);
+    // Field: timeout; number of unanswered 0-window probes.
+    // Unimplemented.
+    *buf += &format!("{:>8} ", 0);
 
-        // Field: Slow start size threshold, -1 if threshold >= 0xFFFF.
-        // Unimplemented, report as large threshold.
-        buf += &format!("{} ", -1);
+    // Field: inode. Matches the socket:[inode] link in /proc/[pid]/fd.
+    let inodeId = file.Dirent.Inode().StableAttr().InodeId;
+    *buf += &format!("{:>8} ", inodeId);
 
-        buf += &format!("\n");
-    }
+    // Field: ref; reference count on the socket inode. Don't count the ref
+    // we obtain while deferencing the weakref to this socket.
+    *buf += &format!("{} ", file.ReadRefs() - 1);
 
-    return Ok(buf.as_bytes().to_vec());
+    // Field: Socket struct address. Redacted due to the same reason as
+    // the 'Num' field in /proc/net/unix, see NetUnixReadonlyFileNode.
+    *buf += &format!("{:>16} ", 0);
 }
 
-pub struct NetUDP {}
+pub struct NetTCP {
+    pub family: i32,
+}
 
-impl SimpleFileTrait for NetUDP {
+impl SimpleFileTrait for NetTCP {
     fn GetFile(
         &self,
         _task: &Task,
@@ -381,7 +362,10 @@ impl SimpleFileTrait for NetUDP {
         flags: FileFlags,
     ) -> Result<File> {
         let fops = ReadonlyFileOperations {
-            node: NetUDPReadonlyFileNode {}.into(),
+            node: NetTCPReadonlyFileNode {
+                family: self.family,
+            }
+            .into(),
         };
 
         let file = File::New(dirent, &flags, fops.into());
@@ -389,131 +373,109 @@ impl SimpleFileTrait for NetUDP {
     }
 }
 
-pub fn NewNetUDP(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
-    let node = SimpleFileInode::New(
-        task,
-        &ROOT_OWNER,
-        &FilePermissions {
-            User: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            Group: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            Other: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            ..Default::default()
-        },
-        FSMagic::ANON_INODE_FS_MAGIC,
-        false,
-        NetUDP {}.into(),
-    );
-
-    return NewProcInode(node.into(), msrc, InodeType::SpecialFile, None);
+pub fn NewNetTCP(task: &Task, msrc: &Arc<QMutex<MountSource>>, family: i32) -> Inode {
+    return NewNetFile(task, msrc, NetTCP { family: family }.into());
 }
 
 #[derive(Clone)]
-pub struct NetUDPReadonlyFileNode {}
-
-impl NetUDPReadonlyFileNode {
-    pub fn GetData(&self, task: &Task, header: &str) -> Result<Vec<u8>> {
-        let kernel = GetKernel();
-        let sockets = kernel.sockets.ListSockets();
-
-        let mut buf = header.to_string();
-        for (id, file) in sockets {
-            let fopsType = file.FileOp.FopsType();
-            //&& fopsType != FileOpsType::UnixSocketOperations
-            if fopsType != FileOpsType::SocketOperations {
-                continue;
-            }
-
-            let sockops = file.FileOp.clone();
-            /*let sockops = fileops
-                .as_any()
-                .downcast_ref::<SocketOperations>()
-                .expect("SocketOperations convert fail")
-                .clone();
-
-            let family = sockops.family;
-            let stype = sockops.stype;*/
+pub struct NetTCPReadonlyFileNode {
+    pub family: i32,
+}
 
-            let (family, stype, _protocol) = sockops.Type();
+impl NetTCPReadonlyFileNode {
+    pub fn GetData(&self, task: &Task) -> Vec<u8> {
+        let mut buf = if self.family == AFType::AF_INET6 {
+            "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n".to_string()
+        } else {
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode                                                     \n".to_string()
+        };
 
-            if family != AFType::AF_INET || stype != SockType::SOCK_STREAM {
-                continue;
-            }
+        let sockets = InetSockets(self.family, SockType::SOCK_STREAM);
+        for (sl, file) in sockets.iter().enumerate() {
+            WriteInetSocket(task, &mut buf, sl, file, self.family, SockType::SOCK_STREAM);
 
-            // Linux's documentation for the fields below can be found at
-            // https://www.kernel.org/doc/Documentation/networking/proc_net_tcp.txt.
-            // For Linux's implementation, see net/ipv4/tcp_ipv4.c:get_tcp4_sock().
-            // Note that the header doesn't contain labels for all the fields.
+            // Field: retransmit timeout. Unimplemented.
+            buf += &format!("{} ", 0);
 
-            // Field: sl; entry number.
-            buf += &format!("{:<5}: ", id);
+            // Field: predicted tick of soft clock (delayed ACK control data).
+            // Unimplemented.
+            buf += &format!("{} ", 0);
 
-            let mut sockBuf = [0; 256];
+            // Field: (ack.quick<<1)|ack.pingpong, Unimplemented.
+            buf += &format!("{} ", 0);
 
-            // Field: local_adddress.
-            let _ = sockops.GetSockName(task, &mut sockBuf)?;
-            let addr = GetAddr(AFType::AF_INET as _, &sockBuf)?;
-            buf += &WriteInetAddr(&addr);
+            // Field: sending congestion window, Unimplemented.
+            buf += &format!("{} ", 0);
 
-            // Field: rem_address.
-            let _ = sockops.GetPeerName(task, &mut sockBuf)?;
-            let addr = GetAddr(AFType::AF_INET as _, &sockBuf)?;
-            buf += &WriteInetAddr(&addr);
+            // Field: Slow start size threshold, -1 if threshold >= 0xFFFF.
+            // Unimplemented, report as large threshold.
+            buf += &format!("{} ", -1);
 
-            // Field: state; socket state.
-            buf += &format!("{:02X} ", sockops.State());
+            buf += &format!("\n");
+        }
 
-            // Field: tx_queue, rx_queue; number of packets in the transmit and
-            // receive queue. Unimplemented.
-            buf += &format!("{:08X}:{:08X} ", 0, 0);
+        return buf.as_bytes().to_vec();
+    }
+}
 
-            // Field: tr, tm->when; timer active state and number of jiffies
-            // until timer expires. Unimplemented.
-            buf += &format!("{:02X}:{:08X} ", 0, 0);
+impl ReadonlyFileNodeTrait for NetTCPReadonlyFileNode {
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
+    }
+}
 
-            // Field: retrnsmt; number of unrecovered RTO timeouts.
-            // Unimplemented.
-            buf += &format!("{:08X} ", 0);
+pub struct NetUDP {
+    pub family: i32,
+}
 
-            // Field: uid.
-            match file.Dirent.Inode().UnstableAttr(task) {
-                Err(e) => {
-                    error!("Failed to retrieve unstable attr for socket file: {:?}", e);
-                    buf += &format!("{:<5} ", 0);
-                }
-                Ok(uattr) => {
-                    let creds = task.Creds();
-                    let usernamespace = creds.lock().UserNamespace.clone();
-                    buf += &format!("{:<5} ", uattr.Owner.UID.In(&usernamespace).OrOverflow().0);
-                }
+impl SimpleFileTrait for NetUDP {
+    fn GetFile(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        let fops = ReadonlyFileOperations {
+            node: NetUDPReadonlyFileNode {
+                family: self.family,
             }
+            .into(),
+        };
 
-            // Field: timeout; number of unanswered 0-window probes.
-            // Unimplemented.
-            buf += &format!("{:>8} ", 0);
+        let file = File::New(dirent, &flags, fops.into());
+        return Ok(file);
+    }
+}
 
-            // Field: inode.
-            let inodeId = file.Dirent.Inode().StableAttr().InodeId;
-            buf += &format!("{:>8} ", inodeId);
+pub fn NewNetUDP(task: &Task, msrc: &Arc<QMutex<MountSource>>, family: i32) -> Inode {
+    return NewNetFile(task, msrc, NetUDP { family: family }.into());
+}
 
-            // Field: ref; reference count on the socket inode. Don't count the ref
-            // we obtain while deferencing the weakref to this socket.
-            buf += &format!("{ } ", file.ReadRefs() - 1);
+#[derive(Clone)]
+pub struct NetUDPReadonlyFileNode {
+    pub family: i32,
+}
+
+impl NetUDPReadonlyFileNode {
+    pub fn GetData(&self, task: &Task) -> Vec<u8> {
+        let mut buf = if self.family == AFType::AF_INET6 {
+            "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n".to_string()
+        } else {
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops             \n".to_string()
+        };
 
-            // Field: Socket struct address. Redacted due to the same reason as
-            // the 'Num' field in /proc/net/unix, see netUnix.ReadSeqFileData.
-            buf += &format!("{:>16} ", 0);
+        let sockets = InetSockets(self.family, SockType::SOCK_DGRAM);
+        for (sl, file) in sockets.iter().enumerate() {
+            WriteInetSocket(task, &mut buf, sl, file, self.family, SockType::SOCK_DGRAM);
 
             // Field: drops; number of dropped packets. Unimplemented.
             buf += &format!("{} ", 0);
@@ -521,7 +483,7 @@ impl NetUDPReadonlyFileNode {
             buf += &format!("\n");
         }
 
-        return Ok(buf.as_bytes().to_vec());
+        return buf.as_bytes().to_vec();
     }
 }
 
@@ -534,19 +496,8 @@ impl ReadonlyFileNodeTrait for NetUDPReadonlyFileNode {
         offset: i64,
         _blocking: bool,
     ) -> Result<i64> {
-        if offset < 0 {
-            return Err(Error::SysError(SysErr::EINVAL));
-        }
-
-        let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops             \n";
-        let bytes = self.GetData(task, header)?;
-        if offset as usize > bytes.len() {
-            return Ok(0);
-        }
-
-        let n = task.CopyDataOutToIovs(&bytes[offset as usize..], dsts, true)?;
-
-        return Ok(n as i64);
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
     }
 }
 
@@ -570,45 +521,19 @@ impl SimpleFileTrait for NetUnix {
 }
 
 pub fn NewNetUnix(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
-    let node = SimpleFileInode::New(
-        task,
-        &ROOT_OWNER,
-        &FilePermissions {
-            User: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            Group: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            Other: PermMask {
-                read: true,
-                write: false,
-                execute: false,
-            },
-            ..Default::default()
-        },
-        FSMagic::ANON_INODE_FS_MAGIC,
-        false,
-        NetUnix {}.into(),
-    );
-
-    return NewProcInode(node.into(), msrc, InodeType::SpecialFile, None);
+    return NewNetFile(task, msrc, NetUnix {}.into());
 }
 
 #[derive(Clone)]
 pub struct NetUnixReadonlyFileNode {}
 
 impl NetUnixReadonlyFileNode {
-    pub fn GetData(&self, _task: &Task, header: &str) -> Result<Vec<u8>> {
+    pub fn GetData(&self, _task: &Task) -> Vec<u8> {
         let kernel = GetKernel();
         let sockets = kernel.sockets.ListSockets();
 
-        let mut buf = header.to_string();
-        for (id, file) in sockets {
+        let mut buf = "Num       RefCount Protocol Flags    Type St Inode Path\n".to_string();
+        for (_id, file) in sockets {
             let fopsType = file.FileOp.FopsType();
             if fopsType != FileOpsType::UnixSocketOperations {
                 continue;
@@ -627,13 +552,7 @@ impl NetUnixReadonlyFileNode {
                 .expect("SocketOperations convert fail")
                 .clone();
 
-            // Linux's documentation for the fields below can be found at
-            // https://www.kernel.org/doc/Documentation/networking/proc_net_tcp.txt.
-            // For Linux's implementation, see net/ipv4/tcp_ipv4.c:get_tcp4_sock().
-            // Note that the header doesn't contain labels for all the fields.
-
-            // Field: sl; entry number.
-            buf += &format!("{:<5}: ", id);
+            // For Linux's implementation, see net/unix/af_unix.c:unix_seq_show().
 
             // Field: local_adddress.
             let addr = match sockops.ep.GetLocalAddress() {
@@ -683,7 +602,7 @@ impl NetUnixReadonlyFileNode {
             //
             // For now, we always redact this pointer.
             buf += &format!(
-                "{:010}: {:08X} {:08X} {:08X} {:04X} {:02X} {:>5}",
+                "{:016X}: {:08X} {:08X} {:08X} {:04X} {:02X} {:>5}",
                 0,
                 file.ReadRefs() - 1,
                 0,
@@ -704,7 +623,7 @@ impl NetUnixReadonlyFileNode {
             buf += &format!("\n");
         }
 
-        return Ok(buf.as_bytes().to_vec());
+        return buf.as_bytes().to_vec();
     }
 }
 
@@ -717,18 +636,363 @@ impl ReadonlyFileNodeTrait for NetUnixReadonlyFileNode {
         offset: i64,
         _blocking: bool,
     ) -> Result<i64> {
-        if offset < 0 {
-            return Err(Error::SysError(SysErr::EINVAL));
-        }
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
+    }
+}
+
+// WriteNetTable writes one section of /proc/net/snmp or /proc/net/netstat:
+// a header line with the field names followed by a line with the values.
+pub fn WriteNetTable(buf: &mut String, name: &str, fields: &[(&str, i64)]) {
+    *buf += &format!("{}:", name);
+    for (field, _) in fields {
+        *buf += &format!(" {}", field);
+    }
+    *buf += &format!("\n{}:", name);
+    for (_, val) in fields {
+        *buf += &format!(" {}", val);
+    }
+    *buf += "\n";
+}
+
+// NetSocketCounts is a snapshot of the socket registry grouped the way
+// /proc/net/snmp and /proc/net/sockstat report it.
+#[derive(Default, Debug)]
+pub struct NetSocketCounts {
+    pub total: usize,
+    pub tcp: usize,
+    pub tcpEstab: usize,
+    pub udp: usize,
+    pub raw: usize,
+}
+
+impl NetSocketCounts {
+    pub fn New() -> Self {
+        let mut counts = Self::default();
+        for (_id, file) in GetKernel().sockets.ListSockets() {
+            counts.total += 1;
+            let (family, stype, _protocol) = file.FileOp.Type();
+            if family != AFType::AF_INET && family != AFType::AF_INET6 {
+                if family == AFType::AF_PACKET {
+                    counts.raw += 1;
+                }
+                continue;
+            }
 
-        let header = "Num       RefCount Protocol Flags    Type St Inode Path\n";
-        let bytes = self.GetData(task, header)?;
-        if offset as usize > bytes.len() {
-            return Ok(0);
+            match stype {
+                SockType::SOCK_STREAM => {
+                    counts.tcp += 1;
+                    let state = file.FileOp.State();
+                    if state == TCP_ESTABLISHED || state == TCP_CLOSE_WAIT {
+                        counts.tcpEstab += 1;
+                    }
+                }
+                SockType::SOCK_DGRAM => counts.udp += 1,
+                SockType::SOCK_RAW => counts.raw += 1,
+                _ => (),
+            }
         }
 
-        let n = task.CopyDataOutToIovs(&bytes[offset as usize..], dsts, true)?;
+        return counts;
+    }
+}
+
+pub struct NetSnmp {}
 
-        return Ok(n as i64);
+impl SimpleFileTrait for NetSnmp {
+    fn GetFile(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        let fops = ReadonlyFileOperations {
+            node: NetSnmpReadonlyFileNode {}.into(),
+        };
+
+        let file = File::New(dirent, &flags, fops.into());
+        return Ok(file);
+    }
+}
+
+pub fn NewNetSnmp(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+    return NewNetFile(task, msrc, NetSnmp {}.into());
+}
+
+#[derive(Clone)]
+pub struct NetSnmpReadonlyFileNode {}
+
+impl NetSnmpReadonlyFileNode {
+    pub fn GetData(&self, _task: &Task) -> Vec<u8> {
+        let stats = &*NET_STATS;
+        let counts = NetSocketCounts::New();
+        let traffic = GetKernel().sockets.Traffic();
+
+        // the tcp traffic is only known as the receive and send calls, which
+        // are not segments, so it is left out of the ip and tcp segment counters
+        let udpIn = traffic.udpInDatagrams as i64;
+        let udpOut = traffic.udpOutDatagrams as i64;
+        let rawIn = traffic.rawInPackets as i64;
+        let rawOut = traffic.rawOutPackets as i64;
+
+        let mut buf = String::new();
+
+        // For Linux's implementation, see net/ipv4/proc.c:snmp_seq_show().
+        WriteNetTable(
+            &mut buf,
+            "Ip",
+            &[
+                ("Forwarding", 1),
+                ("DefaultTTL", 64),
+                ("InReceives", udpIn + rawIn),
+                ("InHdrErrors", 0),
+                ("InAddrErrors", 0),
+                ("ForwDatagrams", 0),
+                ("InUnknownProtos", 0),
+                ("InDiscards", 0),
+                ("InDelivers", udpIn + rawIn),
+                ("OutRequests", udpOut + rawOut),
+                ("OutDiscards", 0),
+                ("OutNoRoutes", 0),
+                ("ReasmTimeout", 0),
+                ("ReasmReqds", 0),
+                ("ReasmOKs", 0),
+                ("ReasmFails", 0),
+                ("FragOKs", 0),
+                ("FragFails", 0),
+                ("FragCreates", 0),
+            ],
+        );
+
+        let icmp: Vec<(&str, i64)> = [
+            "InMsgs",
+            "InErrors",
+            "InCsumErrors",
+            "InDestUnreachs",
+            "InTimeExcds",
+            "InParmProbs",
+            "InSrcQuenchs",
+            "InRedirects",
+            "InEchos",
+            "InEchoReps",
+            "InTimestamps",
+            "InTimestampReps",
+            "InAddrMasks",
+            "InAddrMaskReps",
+            "OutMsgs",
+            "OutErrors",
+            "OutDestUnreachs",
+            "OutTimeExcds",
+            "OutParmProbs",
+            "OutSrcQuenchs",
+            "OutRedirects",
+            "OutEchos",
+            "OutEchoReps",
+            "OutTimestamps",
+            "OutTimestampReps",
+            "OutAddrMasks",
+            "OutAddrMaskReps",
+        ]
+        .iter()
+        .map(|name| (*name, 0))
+        .collect();
+        WriteNetTable(&mut buf, "Icmp", &icmp);
+
+        WriteNetTable(
+            &mut buf,
+            "Tcp",
+            &[
+                ("RtoAlgorithm", 1),
+                ("RtoMin", 200),
+                ("RtoMax", 120000),
+                ("MaxConn", -1),
+                ("ActiveOpens", stats.tcpActiveOpens.Value() as i64),
+                ("PassiveOpens", stats.tcpPassiveOpens.Value() as i64),
+                ("AttemptFails", stats.tcpAttemptFails.Value() as i64),
+                ("EstabResets", 0),
+                ("CurrEstab", counts.tcpEstab as i64),
+                ("InSegs", 0),
+                ("OutSegs", 0),
+                ("RetransSegs", 0),
+                ("InErrs", 0),
+                ("OutRsts", 0),
+                ("InCsumErrors", 0),
+            ],
+        );
+
+        let udp = |inDatagrams: i64, outDatagrams: i64| {
+            [
+                ("InDatagrams", inDatagrams),
+                ("NoPorts", 0),
+                ("InErrors", 0),
+                ("OutDatagrams", outDatagrams),
+                ("RcvbufErrors", 0),
+                ("SndbufErrors", 0),
+                ("InCsumErrors", 0),
+                ("IgnoredMulti", 0),
+                ("MemErrors", 0),
+            ]
+        };
+        WriteNetTable(&mut buf, "Udp", &udp(udpIn, udpOut));
+        WriteNetTable(&mut buf, "UdpLite", &udp(0, 0));
+
+        return buf.as_bytes().to_vec();
+    }
+}
+
+impl ReadonlyFileNodeTrait for NetSnmpReadonlyFileNode {
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
+    }
+}
+
+pub const TCP_EXT_FIELDS: &str = "SyncookiesSent SyncookiesRecv SyncookiesFailed EmbryonicRsts PruneCalled RcvPruned OfoPruned OutOfWindowIcmps LockDroppedIcmps ArpFilter TW TWRecycled TWKilled PAWSPassive PAWSActive PAWSEstab DelayedACKs DelayedACKLocked DelayedACKLost ListenOverflows ListenDrops TCPPrequeued TCPDirectCopyFromBacklog TCPDirectCopyFromPrequeue TCPPrequeueDropped TCPHPHits TCPHPHitsToUser TCPPureAcks TCPHPAcks TCPRenoRecovery TCPSackRecovery TCPSACKReneging TCPFACKReorder TCPSACKReorder TCPRenoReorder TCPTSReorder TCPFullUndo TCPPartialUndo TCPDSACKUndo TCPLossUndo TCPLostRetransmit TCPRenoFailures TCPSackFailures TCPLossFailures TCPFastRetrans TCPForwardRetrans TCPSlowStartRetrans TCPTimeouts TCPLossProbes TCPLossProbeRecovery TCPRenoRecoveryFail TCPSackRecoveryFail TCPSchedulerFailed TCPRcvCollapsed TCPDSACKOldSent TCPDSACKOfoSent TCPDSACKRecv TCPDSACKOfoRecv TCPAbortOnData TCPAbortOnClose TCPAbortOnMemory TCPAbortOnTimeout TCPAbortOnLinger TCPAbortFailed TCPMemoryPressures TCPSACKDiscard TCPDSACKIgnoredOld TCPDSACKIgnoredNoUndo TCPSpuriousRTOs TCPMD5NotFound TCPMD5Unexpected TCPMD5Failure TCPSackShifted TCPSackMerged TCPSackShiftFallback TCPBacklogDrop TCPMinTTLDrop TCPDeferAcceptDrop IPReversePathFilter TCPTimeWaitOverflow TCPReqQFullDoCookies TCPReqQFullDrop TCPRetransFail TCPRcvCoalesce TCPOFOQueue TCPOFODrop TCPOFOMerge TCPChallengeACK TCPSYNChallenge TCPFastOpenActive TCPFastOpenActiveFail TCPFastOpenPassive TCPFastOpenPassiveFail TCPFastOpenListenOverflow TCPFastOpenCookieReqd TCPSpuriousRtxHostQueues BusyPollRxPackets TCPAutoCorking TCPFromZeroWindowAdv TCPToZeroWindowAdv TCPWantZeroWindowAdv TCPSynRetrans TCPOrigDataSent TCPHystartTrainDetect TCPHystartTrainCwnd TCPHystartDelayDetect TCPHystartDelayCwnd TCPACKSkippedSynRecv TCPACKSkippedPAWS TCPACKSkippedSeq TCPACKSkippedFinWait2 TCPACKSkippedTimeWait TCPACKSkippedChallenge TCPWinProbe TCPKeepAlive TCPMTUPFail TCPMTUPSuccess";
+
+pub struct NetNetstat {}
+
+impl SimpleFileTrait for NetNetstat {
+    fn GetFile(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        let fops = ReadonlyFileOperations {
+            node: NetNetstatReadonlyFileNode {}.into(),
+        };
+
+        let file = File::New(dirent, &flags, fops.into());
+        return Ok(file);
+    }
+}
+
+pub fn NewNetNetstat(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+    return NewNetFile(task, msrc, NetNetstat {}.into());
+}
+
+#[derive(Clone)]
+pub struct NetNetstatReadonlyFileNode {}
+
+impl NetNetstatReadonlyFileNode {
+    pub fn GetData(&self, _task: &Task) -> Vec<u8> {
+        let traffic = GetKernel().sockets.Traffic();
+        let mut buf = String::new();
+
+        // None of the TcpExt counters are tracked, they are all reported as 0.
+        let tcpExt: Vec<(&str, i64)> = TCP_EXT_FIELDS
+            .split_whitespace()
+            .map(|name| (name, 0))
+            .collect();
+        WriteNetTable(&mut buf, "TcpExt", &tcpExt);
+
+        WriteNetTable(
+            &mut buf,
+            "IpExt",
+            &[
+                ("InNoRoutes", 0),
+                ("InTruncatedPkts", 0),
+                ("InMcastPkts", 0),
+                ("OutMcastPkts", 0),
+                ("InBcastPkts", 0),
+                ("OutBcastPkts", 0),
+                ("InOctets", traffic.inBytes as i64),
+                ("OutOctets", traffic.outBytes as i64),
+                ("InMcastOctets", 0),
+                ("OutMcastOctets", 0),
+                ("InBcastOctets", 0),
+                ("OutBcastOctets", 0),
+                ("InCsumErrors", 0),
+                ("InNoECTPkts", 0),
+                ("InECT1Pkts", 0),
+                ("InECT0Pkts", 0),
+                ("InCEPkts", 0),
+                ("ReasmOverlaps", 0),
+            ],
+        );
+
+        return buf.as_bytes().to_vec();
+    }
+}
+
+impl ReadonlyFileNodeTrait for NetNetstatReadonlyFileNode {
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
+    }
+}
+
+pub struct NetSockstat {}
+
+impl SimpleFileTrait for NetSockstat {
+    fn GetFile(
+        &self,
+        _task: &Task,
+        _dir: &Inode,
+        dirent: &Dirent,
+        flags: FileFlags,
+    ) -> Result<File> {
+        let fops = ReadonlyFileOperations {
+            node: NetSockstatReadonlyFileNode {}.into(),
+        };
+
+        let file = File::New(dirent, &flags, fops.into());
+        return Ok(file);
+    }
+}
+
+pub fn NewNetSockstat(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+    return NewNetFile(task, msrc, NetSockstat {}.into());
+}
+
+#[derive(Clone)]
+pub struct NetSockstatReadonlyFileNode {}
+
+impl NetSockstatReadonlyFileNode {
+    pub fn GetData(&self, _task: &Task) -> Vec<u8> {
+        let counts = NetSocketCounts::New();
+
+        // For Linux's implementation, see net/ipv4/proc.c:sockstat_seq_show().
+        let mut buf = format!("sockets: used {}\n", counts.total);
+        buf += &format!(
+            "TCP: inuse {} orphan 0 tw 0 alloc {} mem 0\n",
+            counts.tcp, counts.tcp
+        );
+        buf += &format!("UDP: inuse {} mem 0\n", counts.udp);
+        buf += "UDPLITE: inuse 0\n";
+        buf += &format!("RAW: inuse {}\n", counts.raw);
+        buf += "FRAG: inuse 0 memory 0\n";
+
+        return buf.as_bytes().to_vec();
+    }
+}
+
+impl ReadonlyFileNodeTrait for NetSockstatReadonlyFileNode {
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let bytes = self.GetData(task);
+        return CopyNetData(task, &bytes, dsts, offset);
     }
 }
@@ -22,12 +22,14 @@ use core::ops::Deref;
 
 use super::super::super::super::auth::*;
 use super::super::super::super::common::*;
+use super::super::super::super::control_msg::UnsupportedKind;
 use super::super::super::super::device::*;
 use super::super::super::super::linux_def::*;
 use super::super::super::super::task_mgr::*;
 use super::super::super::fs::dentry::*;
 use super::super::super::fs::fsutil::file::*;
 use super::super::super::kernel::kernel::*;
+use super::super::super::kernel::unsupported::Unsupported;
 use super::super::super::kernel::waiter::*;
 use super::super::super::task::*;
 use super::super::super::threadmgr::pid_namespace::*;
@@ -80,7 +82,18 @@ impl DirDataNodeTrait for ProcNode {
 
         let tid = match name.parse::<i32>() {
             Ok(tid) => tid,
-            _ => return Err(err),
+            _ => {
+                if let Error::SysError(SysErr::ENOENT) = err {
+                    Unsupported(
+                        task,
+                        UnsupportedKind::ProcFile,
+                        &format!("/proc/{}", name),
+                        "",
+                        false,
+                    );
+                }
+                return Err(err);
+            }
         };
 
         let otherThread = match self.lock().pidns.TaskWithID(tid) {
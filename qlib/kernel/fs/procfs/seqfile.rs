@@ -297,6 +297,7 @@ impl InodeOperations for SeqFile {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: fops,
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(internal)));
@@ -360,7 +361,14 @@ impl InodeOperations for SeqFile {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
@@ -246,8 +246,15 @@ impl InodeOperations for SymlinkNode {
         return self.link.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.link.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        return self.link.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
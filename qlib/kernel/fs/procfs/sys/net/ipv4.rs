@@ -36,7 +36,7 @@ pub struct Ipv4Node {}
 
 impl DirDataNodeTrait for Ipv4Node {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys/net/ipv4", name);
     }
 
     fn GetFile(
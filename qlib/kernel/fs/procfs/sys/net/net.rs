@@ -38,7 +38,7 @@ pub struct SysNetDirNode {}
 
 impl DirDataNodeTrait for SysNetDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys/net", name);
     }
 
     fn GetFile(
@@ -39,7 +39,7 @@ pub struct ProcSysDirNode {}
 
 impl DirDataNodeTrait for ProcSysDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys", name);
     }
 
     fn GetFile(
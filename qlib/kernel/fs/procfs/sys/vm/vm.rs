@@ -40,7 +40,7 @@ pub struct ProcSysVMDirNode {}
 
 impl DirDataNodeTrait for ProcSysVMDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys/vm", name);
     }
 
     fn GetFile(
@@ -79,7 +79,7 @@ pub struct ProcSysKernelDirNode {}
 
 impl DirDataNodeTrait for ProcSysKernelDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys/kernel", name);
     }
 
     fn GetFile(
@@ -116,7 +116,7 @@ pub struct ProcSysRandomDirNode {}
 
 impl DirDataNodeTrait for ProcSysRandomDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/sys/kernel/random", name);
     }
 
     fn GetFile(
@@ -87,10 +87,7 @@ impl ReadLinkNodeTrait for FdNode {
         };
 
         let fops = file.FileOp.clone();
-        let fopsType = fops.FopsType();
-        if fopsType == FileOpsType::SocketOperations
-            || fopsType == FileOpsType::UnixSocketOperations
-        {
+        if fops.FopsType().IsSocket() {
             return Err(Error::SysError(SysErr::ENXIO));
         }
 
@@ -55,7 +55,7 @@ pub struct TaskDirNode {
 
 impl DirDataNodeTrait for TaskDirNode {
     fn Lookup(&self, d: &Dir, task: &Task, dir: &Inode, name: &str) -> Result<Dirent> {
-        return d.Lookup(task, dir, name);
+        return LookupProcFile(d, task, dir, "/proc/[pid]", name);
     }
 
     fn GetFile(
@@ -36,6 +36,7 @@ use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
 use super::super::fsutil::file::*;
+use super::super::fsutil::inode::InodeSimpleExtendedAttributesInternal;
 use super::super::host::hostinodeop::*;
 use super::super::inode::*;
 use super::super::mount::*;
@@ -59,7 +60,7 @@ pub struct DirInternal {
     pub fsType: u64,
     pub unstable: UnstableAttr,
 
-    pub xattrs: BTreeMap<String, Vec<u8>>,
+    pub xattrs: InodeSimpleExtendedAttributesInternal,
 }
 
 impl DirInternal {
@@ -182,7 +183,7 @@ impl Dir {
             fsType: FSMagic::RAMFS_MAGIC,
             unstable: unstable,
 
-            xattrs: BTreeMap::new(),
+            xattrs: InodeSimpleExtendedAttributesInternal::default(),
         };
 
         let ret = Dir(Arc::new(QRwLock::new(d)));
@@ -456,6 +457,7 @@ impl InodeOperations for Dir {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: dirOps.into(),
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(file)));
@@ -467,31 +469,19 @@ impl InodeOperations for Dir {
     }
 
     fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
-        match self.read().xattrs.get(name) {
-            None => Err(Error::SysError(SysErr::ENOATTR)),
-            Some(s) => Ok(s.clone()),
-        }
+        return self.read().xattrs.Getxattr(name);
     }
 
-    fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], _flags: u32) -> Result<()> {
-        self.write().xattrs.insert(name.to_string(), value.to_vec());
-        return Ok(());
+    fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        return self.write().xattrs.Setxattr(name, value, flags);
     }
 
     fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
-        let mut res = Vec::new();
-        for (name, _) in &self.read().xattrs {
-            res.push(name.clone());
-        }
-
-        return Ok(res);
+        return Ok(self.read().xattrs.Listxattr());
     }
 
     fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
-        match self.write().xattrs.remove(name) {
-            None => return Err(Error::SysError(SysErr::ENOATTR)),
-            Some(_) => return Ok(()),
-        }
+        return self.write().xattrs.Removexattr(name);
     }
 
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
@@ -517,7 +507,14 @@ impl InodeOperations for Dir {
         return Err(Error::SysError(SysErr::EISDIR));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EISDIR));
     }
 
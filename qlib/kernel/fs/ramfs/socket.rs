@@ -192,6 +192,7 @@ impl InodeOperations for SocketInodeOps {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: fops.into(),
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(f)));
@@ -216,6 +217,10 @@ impl InodeOperations for SocketInodeOps {
         return self.simpleExtendedAttribute.Listxattr(dir, size);
     }
 
+    fn Removexattr(&self, dir: &Inode, name: &str) -> Result<()> {
+        return self.simpleExtendedAttribute.Removexattr(dir, name);
+    }
+
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
         return ContextCanAccessFile(task, inode, reqPerms);
     }
@@ -245,7 +250,14 @@ impl InodeOperations for SocketInodeOps {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 
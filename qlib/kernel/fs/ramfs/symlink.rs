@@ -13,7 +13,6 @@
 // limitations under the License.
 
 use crate::qlib::mutex::*;
-use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::sync::Arc;
@@ -35,6 +34,7 @@ use super::super::dentry::*;
 use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
+use super::super::fsutil::inode::InodeSimpleExtendedAttributesInternal;
 use super::super::host::hostinodeop::*;
 use super::super::inode::*;
 use super::super::mount::*;
@@ -45,7 +45,7 @@ pub struct SymlinkInternal {
     pub fsType: u64,
     pub unstable: UnstableAttr,
 
-    pub xattrs: BTreeMap<String, Vec<u8>>,
+    pub xattrs: InodeSimpleExtendedAttributesInternal,
 }
 
 #[derive(Clone)]
@@ -74,7 +74,7 @@ impl Symlink {
             Target: target.to_string(),
             fsType: FSMagic::RAMFS_MAGIC,
             unstable: unstable,
-            xattrs: BTreeMap::new(),
+            xattrs: InodeSimpleExtendedAttributesInternal::default(),
         };
 
         return Self(Arc::new(QRwLock::new(internal)));
@@ -206,6 +206,7 @@ impl InodeOperations for Symlink {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: SymlinkFileOperations {}.into(),
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(file)));
@@ -220,31 +221,19 @@ impl InodeOperations for Symlink {
     }
 
     fn Getxattr(&self, _dir: &Inode, name: &str, _size: usize) -> Result<Vec<u8>> {
-        match self.read().xattrs.get(name) {
-            None => Err(Error::SysError(SysErr::ENOATTR)),
-            Some(s) => Ok(s.clone()),
-        }
+        return self.read().xattrs.Getxattr(name);
     }
 
-    fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], _flags: u32) -> Result<()> {
-        self.write().xattrs.insert(name.to_string(), value.to_vec());
-        return Ok(());
+    fn Setxattr(&self, _dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        return self.write().xattrs.Setxattr(name, value, flags);
     }
 
     fn Listxattr(&self, _dir: &Inode, _size: usize) -> Result<Vec<String>> {
-        let mut res = Vec::new();
-        for (name, _) in &self.read().xattrs {
-            res.push(name.clone());
-        }
-
-        return Ok(res);
+        return Ok(self.read().xattrs.Listxattr());
     }
 
     fn Removexattr(&self, _dir: &Inode, name: &str) -> Result<()> {
-        match self.write().xattrs.remove(name) {
-            None => return Err(Error::SysError(SysErr::ENOATTR)),
-            Some(_) => return Ok(()),
-        }
+        return self.write().xattrs.Removexattr(name);
     }
 
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
@@ -269,7 +258,14 @@ impl InodeOperations for Symlink {
         return Err(Error::SysError(SysErr::EINVAL));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
@@ -32,12 +32,13 @@ use super::super::inode::*;
 use super::super::mount::*;
 use super::sys::*;
 
-pub fn NewPossible(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
+pub fn NewPossible(task: &Task, msrc: &Arc<QMutex<MountSource>>, online: bool) -> Inode {
     let v = NewPossibleSimpleFileInode(
         task,
         &ROOT_OWNER,
         &FilePermissions::FromMode(FileMode(0o400)),
         FSMagic::PROC_SUPER_MAGIC,
+        online,
     );
     return NewFile(v.into(), msrc);
 }
@@ -47,17 +48,26 @@ pub fn NewPossibleSimpleFileInode(
     owner: &FileOwner,
     perms: &FilePermissions,
     typ: u64,
+    online: bool,
 ) -> SimpleFileInode {
-    let fs = PossibleData {};
+    let fs = PossibleData { online: online };
     return SimpleFileInode::New(task, owner, perms, typ, false, fs.into());
 }
 
-pub struct PossibleData {}
+pub struct PossibleData {
+    // online shows the cores allowed by the current resource limits instead
+    // of all the cores
+    pub online: bool,
+}
 
 impl PossibleData {
     pub fn GenSnapshot(&self, _task: &Task) -> Vec<u8> {
         let kernel = GetKernel();
-        let maxCore = kernel.applicationCores - 1;
+        let maxCore = if self.online {
+            kernel.EffectiveCores() - 1
+        } else {
+            kernel.applicationCores - 1
+        };
 
         let ret = format!("0-{}\n", maxCore);
         return ret.as_bytes().to_vec();
@@ -81,9 +91,9 @@ impl SimpleFileTrait for PossibleData {
 pub fn NewCPU(task: &Task, msrc: &Arc<QMutex<MountSource>>) -> Inode {
     let mut m = BTreeMap::new();
 
-    m.insert("online".to_string(), NewPossible(task, msrc));
-    m.insert("possible".to_string(), NewPossible(task, msrc));
-    m.insert("present".to_string(), NewPossible(task, msrc));
+    m.insert("online".to_string(), NewPossible(task, msrc, true));
+    m.insert("possible".to_string(), NewPossible(task, msrc, false));
+    m.insert("present".to_string(), NewPossible(task, msrc, false));
 
     let kernel = GetKernel();
     let cores = kernel.applicationCores;
@@ -17,11 +17,13 @@ use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::sync::Arc;
+use core::ops::Deref;
 
 use super::super::super::super::auth::id::*;
 use super::super::super::super::common::*;
 use super::super::super::super::linux_def::*;
 use super::super::super::task::*;
+use super::super::super::SHARESPACE;
 use super::super::filesystems::*;
 use super::super::host::fs::*;
 use super::super::inode::*;
@@ -43,6 +45,169 @@ pub const MODE_MASK: u16 = 0o1777;
 // Default permissions are read/write/execute.
 pub const DEFAULT_MODE: u16 = 0o777;
 
+// Max size of the file data, in bytes with k/m/g/t/p/e suffixes or in percent
+// of the memory.
+pub const SIZE_KEY: &str = "size";
+
+// Max number of inodes, with k/m/g suffixes.
+pub const NR_INODES_KEY: &str = "nr_inodes";
+
+// Default size limit is half of the memory as Linux, size=0 is unlimited.
+pub const DEFAULT_SIZE_PERCENT: u64 = 50;
+
+pub struct TmpfsUsageIntern {
+    // maxBlocks is the max number of pages of the file data, 0 is unlimited
+    pub maxBlocks: u64,
+
+    // blocks is the number of pages charged by the files
+    pub blocks: u64,
+
+    // maxInodes is the max number of inodes, 0 is unlimited
+    pub maxInodes: u64,
+
+    // inodes is the inodes of the mount by their id, the dropped ones are
+    // pruned when they are counted
+    pub inodes: BTreeMap<u64, InodeWeak>,
+}
+
+impl TmpfsUsageIntern {
+    pub fn InodeCount(&mut self) -> u64 {
+        // don't upgrade the weak references here, dropping the last one would
+        // release the file pages with the usage locked.
+        self.inodes.retain(|_, i| i.0.strong_count() > 0);
+        return self.inodes.len() as u64;
+    }
+}
+
+// TmpfsUsage is the size and inode accounting of a tmpfs mount. The files are
+// charged with their allocated pages, so the holes of a sparse file are free.
+#[derive(Clone)]
+pub struct TmpfsUsage(Arc<QMutex<TmpfsUsageIntern>>);
+
+impl Deref for TmpfsUsage {
+    type Target = Arc<QMutex<TmpfsUsageIntern>>;
+
+    fn deref(&self) -> &Arc<QMutex<TmpfsUsageIntern>> {
+        &self.0
+    }
+}
+
+impl TmpfsUsage {
+    pub fn New(maxBlocks: u64, maxInodes: u64) -> Self {
+        return Self(Arc::new(QMutex::new(TmpfsUsageIntern {
+            maxBlocks: maxBlocks,
+            blocks: 0,
+            maxInodes: maxInodes,
+            inodes: BTreeMap::new(),
+        })));
+    }
+
+    pub fn ChargeInode(&self, inode: &Inode) -> Result<()> {
+        let mut u = self.lock();
+        if u.maxInodes != 0 && u.inodes.len() as u64 >= u.maxInodes {
+            if u.InodeCount() >= u.maxInodes {
+                return Err(Error::SysError(SysErr::ENOSPC));
+            }
+        }
+
+        u.inodes.insert(inode.ID(), inode.Downgrade());
+        return Ok(());
+    }
+
+    pub fn ChargeBlocks(&self, blocks: u64) -> Result<()> {
+        let mut u = self.lock();
+        if u.maxBlocks != 0 && u.blocks + blocks > u.maxBlocks {
+            return Err(Error::SysError(SysErr::ENOSPC));
+        }
+
+        u.blocks += blocks;
+        return Ok(());
+    }
+
+    // ForceChargeBlocks charges the pages which are allocated already, e.g.
+    // through a shared mapping, the limit can't fail them.
+    pub fn ForceChargeBlocks(&self, blocks: u64) {
+        let mut u = self.lock();
+        u.blocks += blocks;
+    }
+
+    pub fn UnchargeBlocks(&self, blocks: u64) {
+        let mut u = self.lock();
+        if u.blocks < blocks {
+            error!("TmpfsUsage: uncharge {} of {} blocks", blocks, u.blocks);
+        }
+        u.blocks = u.blocks.saturating_sub(blocks);
+    }
+
+    pub fn FsInfo(&self) -> FsInfo {
+        let mut u = self.lock();
+        let mut info = TMPFS_FSINFO;
+
+        // the unlimited mount reports the memory as its size
+        let totalBlocks = if u.maxBlocks == 0 {
+            MemSize() / MemoryDef::PAGE_SIZE
+        } else {
+            u.maxBlocks
+        };
+        info.TotalBlocks = totalBlocks;
+        info.FreeBlocks = totalBlocks.saturating_sub(u.blocks);
+
+        if u.maxInodes != 0 {
+            let inodes = u.InodeCount();
+            info.TotalFiles = u.maxInodes;
+            info.FreeFiles = u.maxInodes.saturating_sub(inodes);
+        }
+
+        return info;
+    }
+}
+
+// MemSize returns the memory size of the sandbox in bytes.
+pub fn MemSize() -> u64 {
+    return SHARESPACE.config.read().KernelMemSize << 30;
+}
+
+// ParseSize parses the size option, e.g. 64m or 50%.
+pub fn ParseSize(s: &str) -> Result<u64> {
+    if let Some(percent) = s.strip_suffix('%') {
+        let percent = match percent.parse::<u64>() {
+            Ok(v) => v,
+            Err(e) => {
+                info!("size value not parsable 'size={}': {:?}", s, e);
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+        };
+
+        return Ok(MemSize() / 100 * percent);
+    }
+
+    return ParseNumber(SIZE_KEY, s, "kmgtpe");
+}
+
+// ParseNumber parses the number with the memparse style suffixes.
+pub fn ParseNumber(key: &str, s: &str, suffixes: &str) -> Result<u64> {
+    let (num, shift) = match s.chars().last() {
+        None => (s, 0),
+        Some(c) => match suffixes.find(c.to_ascii_lowercase()) {
+            None => (s, 0),
+            Some(i) => (&s[..s.len() - 1], 10 * (i as u32 + 1)),
+        },
+    };
+
+    let v = match num.parse::<u64>() {
+        Ok(v) => v,
+        Err(e) => {
+            info!("{} value not parsable '{}={}': {:?}", key, key, s, e);
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+    };
+
+    return match v.checked_mul(1 << shift) {
+        None => Err(Error::SysError(SysErr::EINVAL)),
+        Some(v) => Ok(v),
+    };
+}
+
 pub struct TmpfsFileSystem {}
 
 impl Filesystem for TmpfsFileSystem {
@@ -72,7 +237,8 @@ impl Filesystem for TmpfsFileSystem {
         match options.remove(MODE_KEY) {
             None => (),
             Some(m) => {
-                let i = match m.parse::<u16>() {
+                // mount(8): the mode of tmpfs is in octal
+                let i = match u16::from_str_radix(&m, 8) {
                     Ok(v) => v,
                     Err(e) => {
                         info!("mode value not parsable 'mode={}': {:?}", m, e);
@@ -123,6 +289,21 @@ impl Filesystem for TmpfsFileSystem {
             }
         }
 
+        let mut maxBlocks = MemSize() / 100 * DEFAULT_SIZE_PERCENT / MemoryDef::PAGE_SIZE;
+        match options.remove(SIZE_KEY) {
+            None => (),
+            Some(s) => {
+                let size = ParseSize(&s)?;
+                maxBlocks = (size + MemoryDef::PAGE_SIZE - 1) / MemoryDef::PAGE_SIZE;
+            }
+        }
+
+        let mut maxInodes = MemSize() / MemoryDef::PAGE_SIZE / 2;
+        match options.remove(NR_INODES_KEY) {
+            None => (),
+            Some(s) => maxInodes = ParseNumber(NR_INODES_KEY, &s, "kmg")?,
+        }
+
         // Fail if the caller passed us more options than we can parse. They may be
         // expecting us to set something we can't set.
         if options.len() > 0 {
@@ -130,7 +311,9 @@ impl Filesystem for TmpfsFileSystem {
             return Err(Error::SysError(SysErr::EINVAL));
         }
 
-        let msrc = MountSource::NewCachingMountSource(self, flags);
+        let mut msrc = MountSource::NewCachingMountSource(self, flags);
+        let usage = TmpfsUsage::New(maxBlocks, maxInodes);
+        msrc.tmpfsUsage = Some(usage.clone());
 
         let inode = NewTmpfsDir(
             task,
@@ -139,6 +322,7 @@ impl Filesystem for TmpfsFileSystem {
             &perms,
             Arc::new(QMutex::new(msrc)),
         );
+        usage.ChargeInode(&inode)?;
         return Ok(inode);
     }
 
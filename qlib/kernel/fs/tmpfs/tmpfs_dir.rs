@@ -100,9 +100,20 @@ pub fn NewTmpfsDir(
 #[derive(Clone)]
 pub struct TmpfsDir(pub Dir);
 
+// ChargeInode charges the new inode to the nr_inodes limit of its mount, the
+// inode is dropped if the limit is hit.
+pub fn ChargeInode(inode: Inode) -> Result<Inode> {
+    let usage = inode.lock().MountSource.lock().tmpfsUsage.clone();
+    if let Some(usage) = usage {
+        usage.ChargeInode(&inode)?;
+    }
+
+    return Ok(inode);
+}
+
 fn NewDirFn(task: &Task, dir: &Inode, perms: &FilePermissions) -> Result<Inode> {
     let msrc = dir.lock().MountSource.clone();
-    return Ok(NewTmpfsDir(
+    return ChargeInode(NewTmpfsDir(
         task,
         BTreeMap::new(),
         &task.FileOwner(),
@@ -113,7 +124,7 @@ fn NewDirFn(task: &Task, dir: &Inode, perms: &FilePermissions) -> Result<Inode>
 
 fn NewSymlinkFn(task: &Task, dir: &Inode, target: &str) -> Result<Inode> {
     let msrc = dir.lock().MountSource.clone();
-    return Ok(NewTmpfsSymlink(task, target, &task.FileOwner(), &msrc));
+    return ChargeInode(NewTmpfsSymlink(task, target, &task.FileOwner(), &msrc));
 }
 
 fn NewSocketFn(
@@ -123,7 +134,7 @@ fn NewSocketFn(
     perms: &FilePermissions,
 ) -> Result<Inode> {
     let msrc = dir.lock().MountSource.clone();
-    return Ok(NewTmpfsSocket(
+    return ChargeInode(NewTmpfsSocket(
         task,
         socket,
         &task.FileOwner(),
@@ -142,13 +153,13 @@ fn NewFileFn(task: &Task, dir: &Inode, perms: &FilePermissions) -> Result<Inode>
 
     let uattr = WithCurrentTime(task, &uattr);
 
-    return NewTmpfsFileInode(task, uattr, &msrc);
+    return ChargeInode(NewTmpfsFileInode(task, uattr, &msrc)?);
 }
 
 fn NewFifoFn(task: &Task, dir: &Inode, perms: &FilePermissions) -> Result<Inode> {
     let msrc = dir.lock().MountSource.clone();
 
-    return NewTmpfsFifoInode(task, perms, &msrc);
+    return ChargeInode(NewTmpfsFifoInode(task, perms, &msrc)?);
 }
 
 impl TmpfsDir {
@@ -292,6 +303,10 @@ impl InodeOperations for TmpfsDir {
         return self.0.Listxattr(dir, size);
     }
 
+    fn Removexattr(&self, dir: &Inode, name: &str) -> Result<()> {
+        return self.0.Removexattr(dir, name);
+    }
+
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
         return self.0.Check(task, inode, reqPerms);
     }
@@ -312,8 +327,15 @@ impl InodeOperations for TmpfsDir {
         return self.0.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.0.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        return self.0.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
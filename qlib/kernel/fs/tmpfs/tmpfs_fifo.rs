@@ -31,6 +31,7 @@ use super::super::attr::*;
 use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
+use super::super::fsutil::inode::InodeSimpleExtendedAttributes;
 use super::super::host::hostinodeop::*;
 use super::super::inode::*;
 use super::super::mount::*;
@@ -45,7 +46,7 @@ pub fn NewTmpfsFifoInode(
     let (pipe, _drient) = Pipe::New(task, true, DEFAULT_PIPE_SIZE, MemoryDef::PAGE_SIZE as usize);
 
     let iops = NewPipeInodeOps(task, perms, pipe);
-    let fifo = TmpfsFifoInodeOp(iops);
+    let fifo = TmpfsFifoInodeOp(iops, Arc::new(InodeSimpleExtendedAttributes::default()));
 
     let deviceId = TMPFS_DEVICE.lock().DeviceID();
     let inodeId = TMPFS_DEVICE.lock().NextIno();
@@ -62,7 +63,7 @@ pub fn NewTmpfsFifoInode(
 }
 
 #[derive(Clone)]
-pub struct TmpfsFifoInodeOp(PipeIops);
+pub struct TmpfsFifoInodeOp(PipeIops, Arc<InodeSimpleExtendedAttributes>);
 
 impl InodeOperations for TmpfsFifoInodeOp {
     fn as_any(&self) -> &Any {
@@ -185,15 +186,19 @@ impl InodeOperations for TmpfsFifoInodeOp {
     }
 
     fn Getxattr(&self, dir: &Inode, name: &str, size: usize) -> Result<Vec<u8>> {
-        return self.0.Getxattr(dir, name, size);
+        return self.1.Getxattr(dir, name, size);
     }
 
     fn Setxattr(&self, dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
-        return self.0.Setxattr(dir, name, value, flags);
+        return self.1.Setxattr(dir, name, value, flags);
     }
 
     fn Listxattr(&self, dir: &Inode, size: usize) -> Result<Vec<String>> {
-        return self.0.Listxattr(dir, size);
+        return self.1.Listxattr(dir, size);
+    }
+
+    fn Removexattr(&self, dir: &Inode, name: &str) -> Result<()> {
+        return self.1.Removexattr(dir, name);
     }
 
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
@@ -217,7 +222,14 @@ impl InodeOperations for TmpfsFifoInodeOp {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EPIPE));
     }
 
@@ -31,6 +31,7 @@ use super::super::attr::*;
 use super::super::dirent::*;
 use super::super::file::*;
 use super::super::flags::*;
+use super::super::fsutil::inode::InodeSimpleExtendedAttributes;
 use super::super::host::hostinodeop::*;
 use super::super::inode::*;
 use super::super::mount::*;
@@ -54,10 +55,12 @@ pub fn NewTmpfsFileInode(
         None => return Err(Error::SysError(SysErr::EBADF)),
         Some(iops) => iops.clone(),
     };
+    hostiops.lock().tmpfsUsage = msrc.lock().tmpfsUsage.clone();
 
     let ops = TmpfsFileInodeOp {
         inodeops: hostiops,
         uattr: Arc::new(QMutex::new(uattr)),
+        xattrs: Arc::new(InodeSimpleExtendedAttributes::default()),
     };
 
     let deviceId = TMPFS_DEVICE.lock().DeviceID();
@@ -78,6 +81,9 @@ pub fn NewTmpfsFileInode(
 pub struct TmpfsFileInodeOp {
     pub inodeops: HostInodeOp,
     pub uattr: Arc<QMutex<UnstableAttr>>,
+    // the extended attributes are kept in memory as the host tmpfs
+    // doesn't accept every namespace from an unprivileged process.
+    pub xattrs: Arc<InodeSimpleExtendedAttributes>,
 }
 
 impl InodeOperations for TmpfsFileInodeOp {
@@ -204,15 +210,19 @@ impl InodeOperations for TmpfsFileInodeOp {
     }
 
     fn Getxattr(&self, dir: &Inode, name: &str, size: usize) -> Result<Vec<u8>> {
-        return self.inodeops.Getxattr(dir, name, size);
+        return self.xattrs.Getxattr(dir, name, size);
     }
 
     fn Setxattr(&self, dir: &mut Inode, name: &str, value: &[u8], flags: u32) -> Result<()> {
-        return self.inodeops.Setxattr(dir, name, value, flags);
+        return self.xattrs.Setxattr(dir, name, value, flags);
     }
 
     fn Listxattr(&self, dir: &Inode, size: usize) -> Result<Vec<String>> {
-        return self.inodeops.Listxattr(dir, size);
+        return self.xattrs.Listxattr(dir, size);
+    }
+
+    fn Removexattr(&self, dir: &Inode, name: &str) -> Result<()> {
+        return self.xattrs.Removexattr(dir, name);
     }
 
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
@@ -238,8 +248,24 @@ impl InodeOperations for TmpfsFileInodeOp {
         return self.inodeops.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.inodeops.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        if mode & FallocFlags::FALLOC_FL_ZERO_RANGE != 0 {
+            // the backing host tmpfs only implements KEEP_SIZE and PUNCH_HOLE,
+            // zero the range by punching it out and allocating it again.
+            let punch = FallocFlags::FALLOC_FL_PUNCH_HOLE | FallocFlags::FALLOC_FL_KEEP_SIZE;
+            self.inodeops.Allocate(task, dir, punch, offset, length)?;
+            let keepSize = mode & FallocFlags::FALLOC_FL_KEEP_SIZE;
+            return self.inodeops.Allocate(task, dir, keepSize, offset, length);
+        }
+
+        return self.inodeops.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
@@ -189,6 +189,10 @@ impl InodeOperations for TmpfsSocket {
         return self.0.Listxattr(dir, size);
     }
 
+    fn Removexattr(&self, dir: &Inode, name: &str) -> Result<()> {
+        return self.0.Removexattr(dir, name);
+    }
+
     fn Check(&self, task: &Task, inode: &Inode, reqPerms: &PermMask) -> Result<bool> {
         return self.0.Check(task, inode, reqPerms);
     }
@@ -209,8 +213,15 @@ impl InodeOperations for TmpfsSocket {
         return self.0.Truncate(task, dir, size);
     }
 
-    fn Allocate(&self, task: &Task, dir: &mut Inode, offset: i64, length: i64) -> Result<()> {
-        return self.0.Allocate(task, dir, offset, length);
+    fn Allocate(
+        &self,
+        task: &Task,
+        dir: &mut Inode,
+        mode: i32,
+        offset: i64,
+        length: i64,
+    ) -> Result<()> {
+        return self.0.Allocate(task, dir, mode, offset, length);
     }
 
     fn ReadLink(&self, task: &Task, dir: &Inode) -> Result<String> {
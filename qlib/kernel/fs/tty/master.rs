@@ -140,7 +140,14 @@ impl InodeOperations for MasterInodeOperations {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
@@ -144,7 +144,14 @@ impl InodeOperations for SlaveInodeOperations {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Err(Error::SysError(SysErr::EOPNOTSUPP));
     }
 
@@ -254,6 +261,7 @@ impl InodeOperations for SlaveInodeOperations {
             flags: QMutex::new((flags, None)),
             offset: QLock::New(0),
             FileOp: fileOp,
+            sockStats: None,
         };
 
         return Ok(File(Arc::new(internal)));
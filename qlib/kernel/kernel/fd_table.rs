@@ -562,7 +562,7 @@ impl FDTableInternal {
             } else {
                 ev |= InotifyEvent::IN_CLOSE_NOWRITE;
             }
-            d.InotifyEvent(ev, 0, EventType::PathEvent);
+            file.InotifyEvent(ev, 0, EventType::PathEvent);
         }
     }
 
@@ -14,6 +14,7 @@
 
 use crate::qlib::mutex::*;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::sync::Arc;
@@ -27,6 +28,9 @@ use super::super::super::auth::userns::*;
 use super::super::super::auth::*;
 use super::super::super::auxv::*;
 use super::super::super::common::*;
+use super::super::super::control_msg::{
+    ContainerStats, KernelLogLevel, KernelLogType, SandboxEventType, UpdateResourcesArgs,
+};
 use super::super::super::cpuid::*;
 use super::super::super::limits::*;
 use super::super::super::linux::time::*;
@@ -50,6 +54,7 @@ use super::cpuset::*;
 use super::fd_table::*;
 use super::ipc_namespace::*;
 use super::platform::*;
+use super::sandbox_event::*;
 use super::signal_handler::*;
 use super::socket_store::*;
 use super::syslog::*;
@@ -57,12 +62,16 @@ use super::time::*;
 use super::timer::timekeeper::*;
 use super::timer::timer::*;
 use super::timer::*;
+use super::unsupported::*;
 use super::uts_namespace::*;
 
 pub static ASYNC_PROCESS_TIMER: Singleton<Timer> = Singleton::<Timer>::New();
 
 static CLOCK_TICK_MS: i64 = CLOCK_TICK / MILLISECOND;
 
+// MEMORY_CHECK_INTERVAL is the interval to check the memory limit, in ns
+const MEMORY_CHECK_INTERVAL: i64 = SECOND;
+
 #[inline]
 pub fn GetKernel() -> Kernel {
     return SHARESPACE.kernel.lock().clone().unwrap();
@@ -87,6 +96,46 @@ pub struct StaticInfo {
     pub cpu: i32,
 }
 
+// ResourceLimits is the cpu and memory limits of the sandbox set by the
+// container runtime after start, e.g. by "qvisor update".
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ResourceLimits {
+    // cpus is the size of the container cpuset, 0 means no limit.
+    pub cpus: u32,
+    // cpuQuota and cpuPeriod are the cfs bandwidth limit, a non positive
+    // cpuQuota means no limit.
+    pub cpuQuota: i64,
+    pub cpuPeriod: u64,
+    pub cpuShares: u64,
+    // memoryLimit is the memory limit in bytes, 0 means no limit.
+    pub memoryLimit: u64,
+}
+
+impl ResourceLimits {
+    // EffectiveCores returns how many of the cores the limits allow the
+    // sandbox to run application tasks on, at least 1.
+    pub fn EffectiveCores(&self, cores: usize) -> usize {
+        let mut cnt = cores;
+        if self.cpus > 0 && (self.cpus as usize) < cnt {
+            cnt = self.cpus as usize;
+        }
+
+        if self.cpuQuota > 0 && self.cpuPeriod > 0 {
+            let quota = self.cpuQuota as u64;
+            let quotaCores = ((quota + self.cpuPeriod - 1) / self.cpuPeriod) as usize;
+            if quotaCores < cnt {
+                cnt = quotaCores;
+            }
+        }
+
+        if cnt == 0 {
+            cnt = 1;
+        }
+
+        return cnt;
+    }
+}
+
 #[derive(Default)]
 pub struct KernelInternal {
     // extMu serializes external changes to the Kernel with calls to
@@ -147,6 +196,39 @@ pub struct KernelInternal {
 
     // syslog is the kernel log.
     pub syslog: SysLog,
+
+    // resourceLimits is the runtime updated cpu and memory limits.
+    pub resourceLimits: QMutex<ResourceLimits>,
+
+    // lastMemoryCheck is the monotonic time of the last memory limit check.
+    pub lastMemoryCheck: QMutex<i64>,
+
+    // memoryOverLimit is whether the last memory limit check was over the limit.
+    pub memoryOverLimit: AtomicBool,
+
+    // events is the exit, oom and pause events of the sandbox.
+    pub events: SandboxEventLog,
+
+    // unsupported is the calls of each container which fall through to a stub.
+    pub unsupported: UnsupportedLog,
+
+    // pauseState is how the tasks are stopped by the container runtime and the
+    // hibernation.
+    pub pauseState: QMutex<PauseState>,
+}
+
+// PauseState tracks the external stops of the sandbox. The runtime pause and
+// the hibernation share one external stop of the tasks, so the tasks are
+// stopped once while either of them is in effect.
+#[derive(Default)]
+pub struct PauseState {
+    // paused is whether the sandbox is paused by the container runtime.
+    pub paused: bool,
+
+    // hibernateEvent is whether the ongoing hibernation has been reported as a
+    // pause of the containers. The hibernation of a checkpoint or of a paused
+    // sandbox doesn't change whether the containers run.
+    pub hibernateEvent: bool,
 }
 
 impl KernelInternal {
@@ -200,6 +282,7 @@ impl Deref for Kernel {
 impl Kernel {
     pub fn Init(args: InitKernelArgs) -> Self {
         let cpuTicker = Arc::new(KernelCPUClockTicker::New());
+        let startTime = Task::RealTimeNow();
         let internal = KernelInternal {
             extMu: QMutex::new(()),
             featureSet: args.FeatureSet,
@@ -219,11 +302,16 @@ impl Kernel {
             }),
             //cpuClockTicker: Timer::New(&MONOTONIC_CLOCK, &cpuTicker),
             cpuClockTicker: cpuTicker,
-            startTime: Task::RealTimeNow(),
+            startTime: startTime,
             started: AtomicBool::new(false),
             platform: DefaultPlatform::default(),
             lastProcessTime: QMutex::new(0),
-            syslog: SysLog::default(),
+            syslog: SysLog::New(startTime),
+            resourceLimits: QMutex::new(ResourceLimits::default()),
+            lastMemoryCheck: QMutex::new(0),
+            memoryOverLimit: AtomicBool::new(false),
+            events: SandboxEventLog::default(),
+            unsupported: UnsupportedLog::default(),
         };
 
         //error!("hasXSAVEOPT is {}", internal.featureSet.lock().UseXsaveopt());
@@ -247,6 +335,149 @@ impl Kernel {
         return self.syslog.clone();
     }
 
+    // UpdateResources applies the cpu and memory limits updated by the
+    // container runtime. The vcpus beyond the effective core count stop
+    // picking up application tasks.
+    pub fn UpdateResources(&self, args: &UpdateResourcesArgs) {
+        let mut limits = self.resourceLimits.lock();
+        if let Some(cpus) = args.CPUs {
+            limits.cpus = cpus;
+        }
+
+        if let Some(quota) = args.CPUQuota {
+            limits.cpuQuota = quota;
+        }
+
+        if let Some(period) = args.CPUPeriod {
+            limits.cpuPeriod = period;
+        }
+
+        if let Some(shares) = args.CPUShares {
+            limits.cpuShares = shares;
+        }
+
+        if let Some(limit) = args.MemoryLimit {
+            limits.memoryLimit = if limit > 0 { limit as u64 } else { 0 };
+        }
+
+        let cores = limits.EffectiveCores(self.applicationCores);
+        // vcpu 0 is the io vcpu, the application cores are [1, cores]
+        SHARESPACE.scheduler.SetActiveVcpuCnt(cores + 1);
+        info!(
+            "UpdateResources limits {:?}, effective cores {}",
+            *limits, cores
+        );
+    }
+
+    // EffectiveCores returns the number of application cores allowed by the
+    // current resource limits.
+    pub fn EffectiveCores(&self) -> usize {
+        return self
+            .resourceLimits
+            .lock()
+            .EffectiveCores(self.applicationCores);
+    }
+
+    // MemoryLimit returns the memory limit in bytes, 0 means no limit.
+    pub fn MemoryLimit(&self) -> u64 {
+        return self.resourceLimits.lock().memoryLimit;
+    }
+
+    // CheckMemoryLimit records an OOM event when the total rss crosses the
+    // memory limit. There is no memory cgroup in the sandbox, so nothing is
+    // killed here; the event lets the runtime act on it. It is called by the
+    // cpu clock ticker.
+    pub fn CheckMemoryLimit(&self) {
+        let limit = self.MemoryLimit();
+        if limit == 0 {
+            self.memoryOverLimit.store(false, Ordering::Relaxed);
+            return;
+        }
+
+        let now = Task::MonoTimeNow().0;
+        {
+            let mut last = self.lastMemoryCheck.lock();
+            if now - *last < MEMORY_CHECK_INTERVAL {
+                return;
+            }
+            *last = now;
+        }
+
+        let root = self.tasks.Root();
+        let mut usage = 0;
+        // thread groups created with CLONE_VM share the memory manager
+        let mut mms = BTreeSet::new();
+        for tg in root.ThreadGroups() {
+            let leader = match tg.lock().leader.Upgrade() {
+                None => continue,
+                Some(leader) => leader,
+            };
+
+            let mm = leader.MemoryManager();
+            if !mms.insert(mm.ID()) {
+                continue;
+            }
+
+            usage += mm.ResidentSetSizeLocked();
+        }
+
+        if usage <= limit {
+            self.memoryOverLimit.store(false, Ordering::Relaxed);
+            return;
+        }
+
+        // report once each time the usage goes over the limit
+        if self.memoryOverLimit.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        info!("CheckMemoryLimit: usage {} exceeds limit {}", usage, limit);
+        self.events.Push(SandboxEventType::OOM, "", "", 0, 0);
+        self.syslog.Push(
+            KernelLogType::OOM,
+            KernelLogLevel::Error,
+            "",
+            0,
+            format!("out of memory: usage {} exceeds limit {}", usage, limit),
+        );
+    }
+
+    // ContainerStats returns the resource usage of the container, an empty
+    // cid returns the usage of the whole sandbox.
+    pub fn ContainerStats(&self, cid: &str) -> ContainerStats {
+        let mut stats = ContainerStats {
+            cid: cid.to_string(),
+            MemoryLimit: self.MemoryLimit(),
+            OOMKills: self.events.OOMKills(cid),
+            Paused: self.tasks.read().stopCount > 0,
+            Hibernated: SHARESPACE.hibernatePause.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+
+        let root = self.tasks.Root();
+        for tg in root.ThreadGroups() {
+            let leader = match tg.lock().leader.Upgrade() {
+                None => continue,
+                Some(leader) => leader,
+            };
+
+            if cid.len() > 0 && leader.ContainerID() != cid {
+                continue;
+            }
+
+            stats.Pids += 1;
+            let cpu = tg.CPUStats();
+            stats.UserTime += cpu.UserTime;
+            stats.SysTime += cpu.SysTime;
+
+            let mm = leader.MemoryManager();
+            stats.MemoryUsage += mm.ResidentSetSizeLocked();
+            stats.MemoryMaxUsage += mm.MaxResidentSetSizeLocked();
+        }
+
+        return stats;
+    }
+
     pub fn Atomically(&self, mut f: impl FnMut()) {
         let _t = self.lastProcessTime.lock();
         f();
@@ -381,6 +612,13 @@ impl Kernel {
 
         let root = ts.Root();
         let tgid = root.IDOfThreadGroup(&tg);
+        self.syslog.Push(
+            KernelLogType::Task,
+            KernelLogLevel::Info,
+            &args.ContainerID,
+            tgid,
+            format!("started process {}", args.Filename),
+        );
 
         let isNone = self.globalInit.lock().is_none();
         if isNone {
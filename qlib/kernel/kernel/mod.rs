@@ -34,10 +34,13 @@ pub mod kernel;
 pub mod msgqueue;
 pub mod pipe;
 pub mod platform;
+pub mod sandbox_event;
 pub mod semaphore;
 pub mod shm;
 pub mod signal_handler;
 pub mod signalfd;
 pub mod socket_store;
+pub mod strace;
 pub mod syslog;
+pub mod unsupported;
 pub mod uts_namespace;
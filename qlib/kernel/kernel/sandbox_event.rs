@@ -0,0 +1,111 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::control_msg::*;
+use super::super::task::*;
+
+// EVENT_LOG_SIZE is the max number of events kept in the event log, the
+// oldest events are dropped when it is full.
+pub const EVENT_LOG_SIZE: usize = 1024;
+
+#[derive(Default)]
+pub struct SandboxEventLogInternal {
+    pub nextSeq: u64,
+    pub events: VecDeque<SandboxEvent>,
+
+    // oomKills is the number of oom events of each container, "" is the
+    // sandbox wide memory limit
+    pub oomKills: BTreeMap<String, u64>,
+}
+
+// SandboxEventLog records the process exit, oom and pause events of the
+// sandbox for "qvisor events" and the shim.
+#[derive(Default)]
+pub struct SandboxEventLog(QMutex<SandboxEventLogInternal>);
+
+impl Deref for SandboxEventLog {
+    type Target = QMutex<SandboxEventLogInternal>;
+
+    fn deref(&self) -> &QMutex<SandboxEventLogInternal> {
+        &self.0
+    }
+}
+
+impl SandboxEventLog {
+    pub fn Push(&self, typ: SandboxEventType, cid: &str, execId: &str, pid: i32, status: i32) {
+        let mut log = self.lock();
+        let seq = log.nextSeq;
+        log.nextSeq += 1;
+
+        if typ == SandboxEventType::OOM {
+            *log.oomKills.entry(cid.to_string()).or_insert(0) += 1;
+        }
+
+        if log.events.len() == EVENT_LOG_SIZE {
+            log.events.pop_front();
+        }
+
+        log.events.push_back(SandboxEvent {
+            Seq: seq,
+            Type: typ,
+            cid: cid.to_string(),
+            execId: execId.to_string(),
+            Pid: pid,
+            Status: status,
+            Time: Task::RealTimeNow().0,
+        });
+    }
+
+    // Since returns the events of the container with sequence number no
+    // less than since. The sandbox wide events are returned for all containers.
+    pub fn Since(&self, cid: &str, since: u64) -> Vec<SandboxEvent> {
+        let log = self.lock();
+        let mut ret = Vec::new();
+        for event in log.events.iter() {
+            if event.Seq < since {
+                continue;
+            }
+
+            if cid.len() > 0 && event.cid.len() > 0 && event.cid != cid {
+                continue;
+            }
+
+            ret.push(event.clone());
+        }
+
+        return ret;
+    }
+
+    pub fn OOMKills(&self, cid: &str) -> u64 {
+        let log = self.lock();
+        if cid.len() == 0 {
+            return log.oomKills.values().sum();
+        }
+
+        // the sandbox wide events count for all containers
+        let count = |cid: &str| match log.oomKills.get(cid) {
+            None => 0,
+            Some(cnt) => *cnt,
+        };
+        return count(cid) + count("");
+    }
+}
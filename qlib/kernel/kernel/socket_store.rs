@@ -14,22 +14,168 @@
 
 use crate::qlib::mutex::*;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::ops::Deref;
 
+use super::super::super::linux_def::*;
+use super::super::super::metric::*;
+use super::super::super::singleton::*;
 use super::super::fs::file::*;
+use super::super::socket::unix::transport::unix::SockType;
+
+pub static NET_STATS: Singleton<NetStats> = Singleton::<NetStats>::New();
+
+pub unsafe fn InitSingleton() {
+    NET_STATS.Init(NetStats::New());
+}
+
+// NetStats holds the connection counters rendered in /proc/net/snmp. The
+// traffic counters are kept per socket in SocketStats and summed up by
+// SocketStore::Traffic when /proc is read.
+pub struct NetStats {
+    pub tcpActiveOpens: Arc<U64Metric>,
+    pub tcpPassiveOpens: Arc<U64Metric>,
+    pub tcpAttemptFails: Arc<U64Metric>,
+}
+
+impl NetStats {
+    pub fn New() -> Self {
+        return Self {
+            tcpActiveOpens: NewU64Metric(
+                "/net/tcp/active_opens",
+                false,
+                "Number of TCP connections initiated by connect(2).",
+            ),
+            tcpPassiveOpens: NewU64Metric(
+                "/net/tcp/passive_opens",
+                false,
+                "Number of TCP connections returned by accept(2).",
+            ),
+            tcpAttemptFails: NewU64Metric(
+                "/net/tcp/attempt_fails",
+                false,
+                "Number of failed TCP connect(2) attempts.",
+            ),
+        };
+    }
+}
+
+// NetTraffic is the traffic of the inet and packet sockets. Whatever
+// transport backs a socket (hostinet, uring, TSoT, RDMA, netstack), it is
+// counted from the syscall layer, so the tcp counters are the receive and
+// send calls which moved data, not the segments on the wire.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct NetTraffic {
+    pub tcpRecvCalls: u64,
+    pub tcpSendCalls: u64,
+    pub udpInDatagrams: u64,
+    pub udpOutDatagrams: u64,
+    pub rawInPackets: u64,
+    pub rawOutPackets: u64,
+    pub inBytes: u64,
+    pub outBytes: u64,
+}
+
+impl NetTraffic {
+    pub fn Add(&mut self, stats: &SocketStats) {
+        let family = stats.family;
+        if family != AFType::AF_INET && family != AFType::AF_INET6 && family != AFType::AF_PACKET {
+            return;
+        }
+
+        let rxPackets = stats.rxPackets.Value();
+        let txPackets = stats.txPackets.Value();
+        if family == AFType::AF_PACKET || stats.stype == SockType::SOCK_RAW {
+            self.rawInPackets += rxPackets;
+            self.rawOutPackets += txPackets;
+        } else if stats.stype == SockType::SOCK_STREAM {
+            self.tcpRecvCalls += rxPackets;
+            self.tcpSendCalls += txPackets;
+        } else if stats.stype == SockType::SOCK_DGRAM {
+            self.udpInDatagrams += rxPackets;
+            self.udpOutDatagrams += txPackets;
+        } else {
+            return;
+        }
+
+        self.inBytes += stats.rxBytes.Value();
+        self.outBytes += stats.txBytes.Value();
+    }
+}
+
+// SocketStats are the per-socket counters. They are registered in the metric
+// set under /net/socket/<id>/ for the lifetime of the socket and updated
+// through the socket's file without going through the SocketStore.
+pub struct SocketStats {
+    pub prefix: String,
+    pub family: i32,
+    pub stype: i32,
+    pub rxBytes: Arc<U64Metric>,
+    pub rxPackets: Arc<U64Metric>,
+    pub txBytes: Arc<U64Metric>,
+    pub txPackets: Arc<U64Metric>,
+}
+
+impl SocketStats {
+    pub fn New(id: u64, inodeId: u64, family: i32, stype: i32) -> Self {
+        let prefix = format!("/net/socket/{}", id);
+        let metric = |name: &str, desc: &str| {
+            NewU64Metric(
+                &format!("{}/{}", prefix, name),
+                false,
+                &format!("{} for socket:[{}].", desc, inodeId),
+            )
+        };
+
+        return Self {
+            family: family,
+            stype: stype,
+            rxBytes: metric("rx_bytes", "Bytes received"),
+            rxPackets: metric("rx_packets", "Messages received"),
+            txBytes: metric("tx_bytes", "Bytes sent"),
+            txPackets: metric("tx_packets", "Messages sent"),
+            prefix: prefix,
+        };
+    }
+
+    pub fn Unregister(&self) {
+        for name in ["rx_bytes", "rx_packets", "tx_bytes", "tx_packets"].iter() {
+            UnregisterMetric(&format!("{}/{}", self.prefix, name));
+        }
+    }
+
+    pub fn Account(&self, bytes: i64, rx: bool) {
+        // a zero length stream transfer is EOF, not a segment.
+        if bytes <= 0 && self.stype == SockType::SOCK_STREAM {
+            return;
+        }
+
+        let bytes = if bytes > 0 { bytes as u64 } else { 0 };
+        if rx {
+            self.rxBytes.IncrBy(bytes);
+            self.rxPackets.Incr();
+        } else {
+            self.txBytes.IncrBy(bytes);
+            self.txPackets.Incr();
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct SocketRecord {
     pub id: u64,
     pub socket: FileWeak,
+    pub stats: Arc<SocketStats>,
 }
 
 #[derive(Default)]
 pub struct SocketStoreIntern {
     pub nextRecord: u64,
     pub sockets: BTreeMap<u64, SocketRecord>,
+    // the traffic of the deleted sockets
+    pub closed: NetTraffic,
 }
 
 #[derive(Default)]
@@ -44,7 +190,13 @@ impl Deref for SocketStore {
 }
 
 impl SocketStore {
+    // AddSocket registers a socket created by File::NewSocket
     pub fn AddSocket(&self, sock: &File) {
+        let stats = match &sock.sockStats {
+            None => panic!("SocketStore::AddSocket Socket {} has no stats", sock.UniqueId()),
+            Some(stats) => stats.clone(),
+        };
+
         let mut store = self.lock();
         let rid = store.nextRecord;
 
@@ -59,6 +211,7 @@ impl SocketStore {
             SocketRecord {
                 id: rid,
                 socket: sock.Downgrade(),
+                stats: stats,
             },
         );
     }
@@ -66,7 +219,66 @@ impl SocketStore {
     pub fn DeleteSocket(&self, sock: &File) {
         let mut store = self.lock();
         let sockId = sock.UniqueId();
-        store.sockets.remove(&sockId);
+        if let Some(record) = store.sockets.remove(&sockId) {
+            store.closed.Add(&record.stats);
+        }
+
+        if let Some(stats) = &sock.sockStats {
+            stats.Unregister();
+        }
+    }
+
+    pub fn Stats(&self, sock: &File) -> Option<Arc<SocketStats>> {
+        return sock.sockStats.clone();
+    }
+
+    // Traffic sums up the counters of the live and the deleted sockets
+    pub fn Traffic(&self) -> NetTraffic {
+        let store = self.lock();
+        let mut traffic = store.closed;
+        for record in store.sockets.values() {
+            traffic.Add(&record.stats);
+        }
+
+        return traffic;
+    }
+
+    // AccountRecv records a successful receive of `bytes` bytes on `sock`.
+    pub fn AccountRecv(&self, sock: &File, bytes: i64) {
+        if let Some(stats) = &sock.sockStats {
+            stats.Account(bytes, true);
+        }
+    }
+
+    // AccountSend records a successful send of `bytes` bytes on `sock`.
+    pub fn AccountSend(&self, sock: &File, bytes: i64) {
+        if let Some(stats) = &sock.sockStats {
+            stats.Account(bytes, false);
+        }
+    }
+
+    // AccountConnect records the outcome of connect(2) on a stream socket.
+    pub fn AccountConnect(&self, sock: &File, ok: bool) {
+        if let Some(stats) = &sock.sockStats {
+            if !IsInetStream(stats.family, stats.stype) {
+                return;
+            }
+
+            if ok {
+                NET_STATS.tcpActiveOpens.Incr();
+            } else {
+                NET_STATS.tcpAttemptFails.Incr();
+            }
+        }
+    }
+
+    // AccountAccept records a connection returned by accept(2).
+    pub fn AccountAccept(&self, sock: &File) {
+        if let Some(stats) = &sock.sockStats {
+            if IsInetStream(stats.family, stats.stype) {
+                NET_STATS.tcpPassiveOpens.Incr();
+            }
+        }
     }
 
     pub fn ListSockets(&self) -> Vec<(u64, File)> {
@@ -83,3 +295,8 @@ impl SocketStore {
         return socks;
     }
 }
+
+pub fn IsInetStream(family: i32, stype: i32) -> bool {
+    return (family == AFType::AF_INET || family == AFType::AF_INET6)
+        && stype == SockType::SOCK_STREAM;
+}
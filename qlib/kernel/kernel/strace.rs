@@ -0,0 +1,553 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// strace implements the strace style syscall tracing of "qvisor trace".
+//
+// The trace of a container is enabled at runtime over the control socket. The
+// traced syscalls are formatted as strace lines and written to the trace ring
+// in the share space, which is drained by the host.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use lazy_static::lazy_static;
+
+use super::super::super::bytestream::*;
+use super::super::super::common::*;
+use super::super::super::control_msg::*;
+use super::super::super::linux_def::*;
+use super::super::super::SysCallID;
+use super::super::task::*;
+use super::super::SHARESPACE;
+
+// STRACE_BUF_PAGES is the page count of the trace ring, 1MB.
+pub const STRACE_BUF_PAGES: u64 = 256;
+
+// STRACE_MAX_STRLEN is the max number of bytes printed for a buffer argument,
+// the same as the default of "strace -s".
+pub const STRACE_MAX_STRLEN: usize = 32;
+
+// STRACE_MAX_SOCKADDR is the max number of bytes read for a sockaddr argument.
+pub const STRACE_MAX_SOCKADDR: usize = 128;
+
+lazy_static! {
+    pub static ref STRACE: Strace = Strace::default();
+}
+
+// ArgFmt is how a syscall argument is printed.
+#[derive(Clone, Copy, Debug)]
+pub enum ArgFmt {
+    Hex,
+    Int,
+    Fd,
+    // Dirfd is a fd which may be AT_FDCWD
+    Dirfd,
+    Path,
+    OpenFlags,
+    Mode,
+    // Buf is a buffer whose length is the argument of the index
+    Buf(usize),
+    // SockAddr is a sockaddr whose length is the argument of the index
+    SockAddr(usize),
+    Timespec,
+}
+
+use self::ArgFmt::*;
+
+const DEFAULT_ARGS: &'static [ArgFmt] = &[Hex, Hex, Hex, Hex, Hex, Hex];
+
+// SyscallArgFmts returns the argument formats of the syscall, the syscalls
+// which are not in the table are printed with 6 hex arguments.
+pub fn SyscallArgFmts(name: &str) -> &'static [ArgFmt] {
+    return match name {
+        "read" => &[Fd, Hex, Int],
+        "write" => &[Fd, Buf(2), Int],
+        "pread64" => &[Fd, Hex, Int, Int],
+        "pwrite64" => &[Fd, Buf(2), Int, Int],
+        "open" => &[Path, OpenFlags, Mode],
+        "openat" => &[Dirfd, Path, OpenFlags, Mode],
+        "creat" => &[Path, Mode],
+        "close" => &[Fd],
+        "stat" | "lstat" => &[Path, Hex],
+        "fstat" => &[Fd, Hex],
+        "newfstatat" => &[Dirfd, Path, Hex, Hex],
+        "statx" => &[Dirfd, Path, Hex, Hex, Hex],
+        "access" => &[Path, Mode],
+        "faccessat" | "faccessat2" => &[Dirfd, Path, Mode, Hex],
+        "lseek" => &[Fd, Int, Int],
+        "mmap" => &[Hex, Int, Hex, Hex, Fd, Hex],
+        "munmap" => &[Hex, Int],
+        "dup" => &[Fd],
+        "dup2" => &[Fd, Fd],
+        "dup3" => &[Fd, Fd, Hex],
+        "execve" => &[Path, Hex, Hex],
+        "execveat" => &[Dirfd, Path, Hex, Hex, Hex],
+        "socket" => &[Int, Int, Int],
+        "connect" | "bind" => &[Fd, SockAddr(2), Int],
+        "accept" => &[Fd, Hex, Hex],
+        "accept4" => &[Fd, Hex, Hex, Hex],
+        "listen" => &[Fd, Int],
+        "sendto" => &[Fd, Buf(2), Int, Hex, SockAddr(5), Int],
+        "recvfrom" => &[Fd, Hex, Int, Hex, Hex, Hex],
+        "shutdown" => &[Fd, Int],
+        "nanosleep" => &[Timespec, Hex],
+        "clock_nanosleep" => &[Int, Hex, Timespec, Hex],
+        "chdir" | "rmdir" | "unlink" | "chroot" => &[Path],
+        "fchdir" | "fsync" | "fdatasync" => &[Fd],
+        "mkdir" | "chmod" => &[Path, Mode],
+        "mkdirat" | "fchmodat" => &[Dirfd, Path, Mode],
+        "unlinkat" => &[Dirfd, Path, Hex],
+        "rename" | "link" | "symlink" => &[Path, Path],
+        "renameat" | "linkat" => &[Dirfd, Path, Dirfd, Path, Hex],
+        "readlink" => &[Path, Hex, Int],
+        "readlinkat" => &[Dirfd, Path, Hex, Int],
+        "chown" | "lchown" => &[Path, Int, Int],
+        "truncate" => &[Path, Int],
+        "ftruncate" => &[Fd, Int],
+        "getdents64" | "getdents" => &[Fd, Hex, Int],
+        "ioctl" | "fcntl" => &[Fd, Hex, Hex],
+        "kill" | "tkill" => &[Int, Int],
+        "exit" | "exit_group" => &[Int],
+        "wait4" => &[Int, Hex, Hex, Hex],
+        _ => DEFAULT_ARGS,
+    };
+}
+
+// SyscallName returns the name of the syscall as strace prints it.
+pub fn SyscallName(nr: u64) -> String {
+    if nr < SysCallID::UnknowSyscall as u64 {
+        let callId: SysCallID = unsafe { core::mem::transmute(nr as u64) };
+        let name = format!("{:?}", callId);
+        match name.strip_prefix("sys_") {
+            Some(n) => return n.to_string(),
+            None => return name,
+        }
+    }
+
+    return format!("syscall_{}", nr);
+}
+
+// ErrnoName returns the name of the errno, e.g. "ENOENT".
+pub fn ErrnoName(errno: i32) -> String {
+    let name = match errno {
+        SysErr::EPERM => "EPERM",
+        SysErr::ENOENT => "ENOENT",
+        SysErr::ESRCH => "ESRCH",
+        SysErr::EINTR => "EINTR",
+        SysErr::EIO => "EIO",
+        SysErr::ENXIO => "ENXIO",
+        SysErr::E2BIG => "E2BIG",
+        SysErr::ENOEXEC => "ENOEXEC",
+        SysErr::EBADF => "EBADF",
+        SysErr::ECHILD => "ECHILD",
+        SysErr::EAGAIN => "EAGAIN",
+        SysErr::ENOMEM => "ENOMEM",
+        SysErr::EACCES => "EACCES",
+        SysErr::EFAULT => "EFAULT",
+        SysErr::EBUSY => "EBUSY",
+        SysErr::EEXIST => "EEXIST",
+        SysErr::EXDEV => "EXDEV",
+        SysErr::ENODEV => "ENODEV",
+        SysErr::ENOTDIR => "ENOTDIR",
+        SysErr::EISDIR => "EISDIR",
+        SysErr::EINVAL => "EINVAL",
+        SysErr::ENFILE => "ENFILE",
+        SysErr::EMFILE => "EMFILE",
+        SysErr::ENOTTY => "ENOTTY",
+        SysErr::ETXTBSY => "ETXTBSY",
+        SysErr::EFBIG => "EFBIG",
+        SysErr::ENOSPC => "ENOSPC",
+        SysErr::ESPIPE => "ESPIPE",
+        SysErr::EROFS => "EROFS",
+        SysErr::EMLINK => "EMLINK",
+        SysErr::EPIPE => "EPIPE",
+        SysErr::ERANGE => "ERANGE",
+        SysErr::EDEADLK => "EDEADLK",
+        SysErr::ENAMETOOLONG => "ENAMETOOLONG",
+        SysErr::ENOLCK => "ENOLCK",
+        SysErr::ENOSYS => "ENOSYS",
+        SysErr::ENOTEMPTY => "ENOTEMPTY",
+        SysErr::ELOOP => "ELOOP",
+        SysErr::ENODATA => "ENODATA",
+        SysErr::ETIME => "ETIME",
+        SysErr::EOVERFLOW => "EOVERFLOW",
+        SysErr::ENOTSOCK => "ENOTSOCK",
+        SysErr::EMSGSIZE => "EMSGSIZE",
+        SysErr::ENOPROTOOPT => "ENOPROTOOPT",
+        SysErr::EOPNOTSUPP => "EOPNOTSUPP",
+        SysErr::EAFNOSUPPORT => "EAFNOSUPPORT",
+        SysErr::EADDRINUSE => "EADDRINUSE",
+        SysErr::EADDRNOTAVAIL => "EADDRNOTAVAIL",
+        SysErr::ENETUNREACH => "ENETUNREACH",
+        SysErr::ECONNABORTED => "ECONNABORTED",
+        SysErr::ECONNRESET => "ECONNRESET",
+        SysErr::ENOBUFS => "ENOBUFS",
+        SysErr::EISCONN => "EISCONN",
+        SysErr::ENOTCONN => "ENOTCONN",
+        SysErr::ETIMEDOUT => "ETIMEDOUT",
+        SysErr::ECONNREFUSED => "ECONNREFUSED",
+        SysErr::EHOSTUNREACH => "EHOSTUNREACH",
+        SysErr::EALREADY => "EALREADY",
+        SysErr::EINPROGRESS => "EINPROGRESS",
+        SysErr::EDQUOT => "EDQUOT",
+        SysErr::ECANCELED => "ECANCELED",
+        SysErr::ERESTARTSYS => "ERESTARTSYS",
+        SysErr::ERESTARTNOINTR => "ERESTARTNOINTR",
+        SysErr::ERESTARTNOHAND => "ERESTARTNOHAND",
+        SysErr::ERESTART_RESTARTBLOCK => "ERESTART_RESTARTBLOCK",
+        _ => return format!("E{}", errno),
+    };
+
+    return name.to_string();
+}
+
+// EscapeBytes prints the bytes as a C string literal, the same as strace.
+pub fn EscapeBytes(buf: &[u8], truncated: bool) -> String {
+    let mut str = String::from("\"");
+    for &c in buf {
+        match c {
+            b'"' => str.push_str("\\\""),
+            b'\\' => str.push_str("\\\\"),
+            b'\n' => str.push_str("\\n"),
+            b'\t' => str.push_str("\\t"),
+            b'\r' => str.push_str("\\r"),
+            0x20..=0x7e => str.push(c as char),
+            _ => str.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+    str.push('"');
+    if truncated {
+        str.push_str("...");
+    }
+
+    return str;
+}
+
+pub fn OpenFlagsString(flags: i32) -> String {
+    let mut str = match flags & 0x3 {
+        Flags::O_WRONLY => "O_WRONLY".to_string(),
+        Flags::O_RDWR => "O_RDWR".to_string(),
+        _ => "O_RDONLY".to_string(),
+    };
+
+    let names = [
+        (Flags::O_CREAT, "O_CREAT"),
+        (Flags::O_EXCL, "O_EXCL"),
+        (Flags::O_NOCTTY, "O_NOCTTY"),
+        (Flags::O_TRUNC, "O_TRUNC"),
+        (Flags::O_APPEND, "O_APPEND"),
+        (Flags::O_NONBLOCK, "O_NONBLOCK"),
+        (Flags::O_DSYNC, "O_DSYNC"),
+        (Flags::O_ASYNC, "O_ASYNC"),
+        (Flags::O_DIRECT, "O_DIRECT"),
+        (Flags::O_LARGEFILE, "O_LARGEFILE"),
+        (Flags::O_DIRECTORY, "O_DIRECTORY"),
+        (Flags::O_NOFOLLOW, "O_NOFOLLOW"),
+        (Flags::O_NOATIME, "O_NOATIME"),
+        (Flags::O_CLOEXEC, "O_CLOEXEC"),
+        (Flags::O_PATH, "O_PATH"),
+    ];
+
+    let mut left = flags & !0x3;
+    // O_SYNC includes O_DSYNC and O_TMPFILE includes O_DIRECTORY
+    if left & Flags::O_SYNC == Flags::O_SYNC {
+        str.push_str("|O_SYNC");
+        left &= !Flags::O_SYNC;
+    }
+    if left & Flags::O_TMPFILE == Flags::O_TMPFILE {
+        str.push_str("|O_TMPFILE");
+        left &= !Flags::O_TMPFILE;
+    }
+
+    for (flag, name) in names.iter() {
+        if left & flag != 0 {
+            str.push('|');
+            str.push_str(name);
+            left &= !flag;
+        }
+    }
+
+    if left != 0 {
+        str.push_str(&format!("|{:#x}", left));
+    }
+
+    return str;
+}
+
+pub fn SockAddrString(task: &Task, addr: u64, len: usize) -> String {
+    if addr == 0 {
+        return "NULL".to_string();
+    }
+
+    let len = core::cmp::min(len, STRACE_MAX_SOCKADDR);
+    if len < 2 {
+        return format!("{:#x}", addr);
+    }
+
+    let buf: Vec<u8> = match task.CopyInVec(addr, len) {
+        Err(_) => return format!("{:#x}", addr),
+        Ok(b) => b,
+    };
+
+    let family = u16::from_le_bytes([buf[0], buf[1]]) as i32;
+    match family {
+        AFType::AF_UNIX => {
+            let path = &buf[2..];
+            let end = if path.len() > 0 && path[0] == 0 {
+                // abstract socket, the name is not NUL terminated
+                path.len()
+            } else {
+                path.iter().position(|&c| c == 0).unwrap_or(path.len())
+            };
+            return format!(
+                "{{sa_family=AF_UNIX, sun_path={}}}",
+                EscapeBytes(&path[..end], false)
+            );
+        }
+        AFType::AF_INET if buf.len() >= 8 => {
+            return format!(
+                "{{sa_family=AF_INET, sin_port=htons({}), sin_addr=inet_addr(\"{}.{}.{}.{}\")}}",
+                u16::from_be_bytes([buf[2], buf[3]]),
+                buf[4],
+                buf[5],
+                buf[6],
+                buf[7]
+            );
+        }
+        AFType::AF_INET6 if buf.len() >= 24 => {
+            let mut ip = String::new();
+            for i in 0..8 {
+                if i > 0 {
+                    ip.push(':');
+                }
+                let seg = u16::from_be_bytes([buf[8 + i * 2], buf[9 + i * 2]]);
+                ip.push_str(&format!("{:x}", seg));
+            }
+            return format!(
+                "{{sa_family=AF_INET6, sin6_port=htons({}), sin6_addr=\"{}\"}}",
+                u16::from_be_bytes([buf[2], buf[3]]),
+                ip
+            );
+        }
+        _ => return format!("{{sa_family={}}}", family),
+    }
+}
+
+pub fn ArgString(task: &Task, fmt: ArgFmt, args: &[u64; 6], idx: usize) -> String {
+    let arg = args[idx];
+    match fmt {
+        Hex => return format!("{:#x}", arg),
+        Int | Fd => return format!("{}", arg as i64),
+        Dirfd => {
+            if arg as i32 == AT_FDCWD {
+                return "AT_FDCWD".to_string();
+            }
+            return format!("{}", arg as i32);
+        }
+        Path => {
+            if arg == 0 {
+                return "NULL".to_string();
+            }
+            let (str, err) = task.CopyInString(arg, PATH_MAX);
+            match err {
+                Err(Error::SysError(SysErr::ENAMETOOLONG)) | Ok(()) => {
+                    return EscapeBytes(str.as_bytes(), false)
+                }
+                Err(_) => return format!("{:#x}", arg),
+            }
+        }
+        OpenFlags => return OpenFlagsString(arg as i32),
+        Mode => return format!("{:#o}", arg),
+        Buf(lenIdx) => {
+            let len = args[lenIdx] as usize;
+            let cnt = core::cmp::min(len, STRACE_MAX_STRLEN);
+            if cnt == 0 {
+                return "\"\"".to_string();
+            }
+            match task.CopyInVec::<u8>(arg, cnt) {
+                Err(_) => return format!("{:#x}", arg),
+                Ok(buf) => return EscapeBytes(&buf, len > cnt),
+            }
+        }
+        SockAddr(lenIdx) => return SockAddrString(task, arg, args[lenIdx] as usize),
+        Timespec => {
+            if arg == 0 {
+                return "NULL".to_string();
+            }
+            match task.CopyInObj::<super::super::super::linux::time::Timespec>(arg) {
+                Err(_) => return format!("{:#x}", arg),
+                Ok(ts) => return format!("{{tv_sec={}, tv_nsec={}}}", ts.tv_sec, ts.tv_nsec),
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct TraceFilter {
+    // pid is the thread group to trace, 0 means all
+    pub pid: i32,
+    // syscalls is the syscall names to trace, empty means all
+    pub syscalls: BTreeSet<String>,
+}
+
+impl TraceFilter {
+    pub fn Match(&self, pid: i32, name: &str) -> bool {
+        if self.pid != 0 && self.pid != pid {
+            return false;
+        }
+
+        return self.syscalls.len() == 0 || self.syscalls.contains(name);
+    }
+}
+
+#[derive(Default)]
+pub struct Strace {
+    // enabled is true when any container is traced, it is checked before
+    // taking the lock in the syscall path.
+    pub enabled: AtomicBool,
+    // filters is the trace filter of each traced container
+    pub filters: QMutex<BTreeMap<String, TraceFilter>>,
+}
+
+impl Strace {
+    pub fn Start(&self, args: &TraceArgs) {
+        {
+            let mut buf = SHARESPACE.traceBuf.lock();
+            if buf.is_none() {
+                *buf = Some(ByteStream::Init(STRACE_BUF_PAGES));
+            }
+        }
+
+        let filter = TraceFilter {
+            pid: args.Pid,
+            syscalls: args.Syscalls.iter().cloned().collect(),
+        };
+
+        let mut filters = self.filters.lock();
+        filters.insert(args.cid.clone(), filter);
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    pub fn Stop(&self, cid: &str) {
+        let mut filters = self.filters.lock();
+        filters.remove(cid);
+        if filters.len() == 0 {
+            self.enabled.store(false, Ordering::Release);
+        }
+    }
+
+    // Enter formats the syscall and its arguments if the syscall is traced.
+    // The syscall arguments are decoded before the syscall runs, the same as
+    // strace, as e.g. the path of execve is not available after it returns.
+    #[inline]
+    pub fn Enter(&self, task: &Task, nr: u64, args: &[u64; 6]) -> Option<SyscallTrace> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        return self.EnterSlow(task, nr, args);
+    }
+
+    pub fn EnterSlow(&self, task: &Task, nr: u64, args: &[u64; 6]) -> Option<SyscallTrace> {
+        let thread = task.Thread();
+        let cid = thread.ContainerID();
+        let pid = thread.ThreadGroup().ID();
+        let name = SyscallName(nr);
+
+        match self.filters.lock().get(&cid) {
+            None => return None,
+            Some(filter) => {
+                if !filter.Match(pid, &name) {
+                    return None;
+                }
+            }
+        }
+
+        let mut line = format!("[pid {:>5}] {}(", thread.lock().id, name);
+        for (i, fmt) in SyscallArgFmts(&name).iter().enumerate() {
+            if i > 0 {
+                line.push_str(", ");
+            }
+            line.push_str(&ArgString(task, *fmt, args, i));
+        }
+        line.push(')');
+
+        return Some(SyscallTrace {
+            cid: cid,
+            name: name,
+            line: line,
+        });
+    }
+}
+
+// FlushSyscallTrace writes the trace line of the syscall in progress of the
+// task, it is called when the syscall returns and before the task is torn down
+// by exit_group or its stack is replaced by execve.
+pub fn FlushSyscallTrace(task: &mut Task, ret: &Result<i64>) {
+    if let Some(trace) = task.syscallTrace.take() {
+        trace.Exit(ret);
+    }
+}
+
+// SyscallTrace is the trace line of a syscall which is in progress.
+pub struct SyscallTrace {
+    pub cid: String,
+    pub name: String,
+    pub line: String,
+}
+
+impl SyscallTrace {
+    // Exit appends the return value of the syscall and writes the line to
+    // the trace ring, the line is dropped if the ring is full.
+    pub fn Exit(mut self, ret: &Result<i64>) {
+        let retStr = match ret {
+            Ok(res) => match self.name.as_str() {
+                "mmap" | "brk" | "mremap" => format!("{:#x}", res),
+                _ => format!("{}", res),
+            },
+            Err(Error::SysCallRetCtrlWithRet(_, res)) => format!("{}", *res as i64),
+            Err(Error::SysError(e)) => format!("-1 {}", ErrnoName(*e)),
+            Err(Error::ErrExceedsFileSizeLimit) => format!("-1 {}", ErrnoName(SysErr::EFBIG)),
+            // the task exits or execs, or the syscall is restarted
+            Err(_) => "?".to_string(),
+        };
+
+        self.line.push_str(" = ");
+        self.line.push_str(&retStr);
+
+        // the host splits the ring by the container id before the tab
+        let record = format!("{}\t{}\n", self.cid, self.line);
+        let mut buf = SHARESPACE.traceBuf.lock();
+        let written = match buf.as_mut() {
+            None => false,
+            Some(bs) => {
+                let mut bs = bs.lock();
+                if bs.AvailableSpace() < record.len() {
+                    false
+                } else {
+                    bs.write(record.as_bytes()).is_ok()
+                }
+            }
+        };
+
+        if !written {
+            SHARESPACE.traceDropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
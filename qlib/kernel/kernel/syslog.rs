@@ -14,19 +14,180 @@
 
 // syslog represents a glboal qkernel log.
 //
-// Currently, it contains only dummy messages for a dmesg
+// It keeps the recent task lifecycle, oom, signal, unsupported syscall, mount
+// and network events of the sandbox for dmesg and "qvisor logs".
 
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::control_msg::*;
+use super::super::super::linux::time::*;
+use super::super::task::*;
+use super::kernel::*;
+use super::time::*;
+
+// SYSLOG_SIZE is the max number of entries kept in the kernel log, the
+// oldest entries are dropped when it is full.
+pub const SYSLOG_SIZE: usize = 2048;
+
+#[derive(Default)]
+pub struct SysLogInternal {
+    pub nextSeq: u64,
+    pub entries: VecDeque<KernelLogEntry>,
+
+    // startTime is the realtime of the kernel start, the uptime of the
+    // entries is relative to it.
+    pub startTime: i64,
+
+    // cleared is the first sequence number each container reads after it
+    // cleared its log, the entries stay for the other containers.
+    pub cleared: BTreeMap<String, u64>,
+}
 
 #[derive(Clone, Default)]
-pub struct SysLog {}
+pub struct SysLog(Arc<QMutex<SysLogInternal>>);
+
+impl Deref for SysLog {
+    type Target = Arc<QMutex<SysLogInternal>>;
+
+    fn deref(&self) -> &Arc<QMutex<SysLogInternal>> {
+        &self.0
+    }
+}
 
 impl SysLog {
-    pub fn Log(&self) -> Vec<u8> {
-        let str = format!(
-            "{} \n {}\n",
-            "qukernel starting ...", "quark syslog pending ..."
+    pub fn New(startTime: Time) -> Self {
+        let log = Self(Arc::new(QMutex::new(SysLogInternal {
+            startTime: startTime.0,
+            ..Default::default()
+        })));
+
+        log.Push(
+            KernelLogType::Kernel,
+            KernelLogLevel::Info,
+            "",
+            0,
+            "qkernel starting ...".to_string(),
         );
+        return log;
+    }
+
+    pub fn Push(
+        &self,
+        typ: KernelLogType,
+        level: KernelLogLevel,
+        cid: &str,
+        pid: i32,
+        message: String,
+    ) {
+        let now = Task::RealTimeNow().0;
+        let mut log = self.lock();
+        let seq = log.nextSeq;
+        log.nextSeq += 1;
+
+        if log.entries.len() == SYSLOG_SIZE {
+            log.entries.pop_front();
+        }
+
+        let uptime = now - log.startTime;
+        log.entries.push_back(KernelLogEntry {
+            Seq: seq,
+            Type: typ,
+            Level: level,
+            cid: cid.to_string(),
+            Pid: pid,
+            Message: message,
+            Time: now,
+            Uptime: uptime,
+        });
+    }
+
+    // Since returns the entries of the container with sequence number no
+    // less than since. The sandbox wide entries are returned for all containers.
+    pub fn Since(&self, cid: &str, since: u64) -> Vec<KernelLogEntry> {
+        let log = self.lock();
+        let mut ret = Vec::new();
+        for entry in log.entries.iter() {
+            if entry.Seq < since {
+                continue;
+            }
+
+            if !entry.VisibleTo(cid) {
+                continue;
+            }
+
+            ret.push(entry.clone());
+        }
+
+        return ret;
+    }
+
+    // Clear clears the log of the container, an empty cid clears the log of
+    // all containers.
+    pub fn Clear(&self, cid: &str) {
+        let mut log = self.lock();
+        if cid.len() == 0 {
+            log.entries.clear();
+            log.cleared.clear();
+            return;
+        }
+
+        let seq = log.nextSeq;
+        log.cleared.insert(cid.to_string(), seq);
+    }
+
+    // Log returns the kernel log of the container in the format of the Linux
+    // syslog(2) buffer, i.e. "<level>[seconds.microseconds] message" per line.
+    // The sandbox wide entries are returned for all containers.
+    pub fn Log(&self, cid: &str) -> Vec<u8> {
+        let log = self.lock();
+        let since = match log.cleared.get(cid) {
+            None => 0,
+            Some(seq) => *seq,
+        };
+        let mut str = String::new();
+        for entry in log.entries.iter() {
+            if entry.Seq < since || !entry.VisibleTo(cid) {
+                continue;
+            }
+
+            let uptime = entry.Uptime / MICROSECOND;
+            let line = if entry.Pid == 0 {
+                format!(
+                    "<{}>[{:5}.{:06}] {}\n",
+                    entry.Level as i32,
+                    uptime / 1000_000,
+                    uptime % 1000_000,
+                    entry.Message
+                )
+            } else {
+                format!(
+                    "<{}>[{:5}.{:06}] pid {}: {}\n",
+                    entry.Level as i32,
+                    uptime / 1000_000,
+                    uptime % 1000_000,
+                    entry.Pid,
+                    entry.Message
+                )
+            };
+            str.push_str(&line);
+        }
+
         return str.into_bytes();
     }
 }
+
+// KernelLog adds an entry to the kernel log, it is dropped if the kernel is
+// not initialized yet.
+pub fn KernelLog(typ: KernelLogType, level: KernelLogLevel, cid: &str, pid: i32, message: String) {
+    match GetKernelOption() {
+        None => (),
+        Some(kernel) => kernel.syslog.Push(typ, level, cid, pid, message),
+    }
+}
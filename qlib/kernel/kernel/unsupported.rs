@@ -0,0 +1,146 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::control_msg::*;
+use super::super::super::linux_def::*;
+use super::super::task::*;
+use super::super::SignalDef::*;
+use super::super::SHARESPACE;
+use super::kernel::*;
+
+// UNSUPPORTED_MAX_BINARIES is the max number of binaries kept in a record
+pub const UNSUPPORTED_MAX_BINARIES: usize = 8;
+
+#[derive(Default)]
+pub struct UnsupportedLogInternal {
+    // records is the unsupported calls of each container
+    pub records: BTreeMap<String, BTreeMap<(UnsupportedKind, String), UnsupportedRecord>>,
+}
+
+// UnsupportedLog counts the calls of each container which fall through to a
+// stub, so the unsupported features a workload depends on can be found
+// before it is migrated.
+#[derive(Default)]
+pub struct UnsupportedLog(QMutex<UnsupportedLogInternal>);
+
+impl Deref for UnsupportedLog {
+    type Target = QMutex<UnsupportedLogInternal>;
+
+    fn deref(&self) -> &QMutex<UnsupportedLogInternal> {
+        &self.0
+    }
+}
+
+impl UnsupportedLog {
+    // Record counts the call and returns true if it is the first call of the
+    // kind and name in the container.
+    pub fn Record(
+        &self,
+        cid: &str,
+        kind: UnsupportedKind,
+        name: &str,
+        args: &str,
+        binary: &str,
+    ) -> bool {
+        let mut log = self.lock();
+        let records = log
+            .records
+            .entry(cid.to_string())
+            .or_insert_with(BTreeMap::new);
+
+        match records.get_mut(&(kind, name.to_string())) {
+            Some(record) => {
+                record.Count += 1;
+                if record.Binaries.len() < UNSUPPORTED_MAX_BINARIES
+                    && !record.Binaries.iter().any(|b| b == binary)
+                {
+                    record.Binaries.push(binary.to_string());
+                }
+                return false;
+            }
+            None => (),
+        }
+
+        records.insert(
+            (kind, name.to_string()),
+            UnsupportedRecord {
+                Kind: kind,
+                Name: name.to_string(),
+                Count: 1,
+                Args: args.to_string(),
+                Binaries: vec![binary.to_string()],
+            },
+        );
+        return true;
+    }
+
+    // Report returns the unsupported calls of the container, an empty cid
+    // returns the calls of all containers.
+    pub fn Report(&self, cid: &str) -> Vec<UnsupportedRecord> {
+        let log = self.lock();
+        let mut ret = Vec::new();
+        for (id, records) in log.records.iter() {
+            if cid.len() > 0 && id != cid {
+                continue;
+            }
+
+            for record in records.values() {
+                ret.push(record.clone());
+            }
+        }
+
+        return ret;
+    }
+}
+
+// Unsupported records a call of the task which falls through to a stub. In
+// the strict mode, the thread group of the task is killed if the call is
+// fatal, i.e. a syscall which is not implemented or returns ENOSYS. The other
+// stubs return an error the workload can handle, so they are only recorded.
+pub fn Unsupported(task: &Task, kind: UnsupportedKind, name: &str, args: &str, fatal: bool) {
+    let binary = match task.mm.metadata.lock().executable.clone() {
+        None => "".to_string(),
+        Some(exe) => exe.FullName(&task.Root()).0,
+    };
+
+    let thread = task.Thread();
+    let cid = thread.ContainerID();
+    let kernel = GetKernel();
+    if kernel.unsupported.Record(&cid, kind, name, args, &binary) {
+        kernel.syslog.Push(
+            KernelLogType::Syscall,
+            KernelLogLevel::Warning,
+            &cid,
+            thread.ThreadGroup().ID(),
+            format!("unsupported {:?} {} by {}", kind, name, binary),
+        );
+    }
+
+    if fatal && SHARESPACE.config.read().UnsupportedStrict {
+        info!(
+            "strict mode: kill {} for unsupported {:?} {}",
+            binary, kind, name
+        );
+        thread
+            .SendGroupSignal(&SignalInfo::SignalInfoPriv(Signal(Signal::SIGKILL)))
+            .ok();
+    }
+}
@@ -19,10 +19,12 @@ use alloc::vec::Vec;
 use super::super::super::addr::*;
 use super::super::super::auxv::*;
 use super::super::super::common::*;
+use super::super::super::linux::fanotify::*;
 use super::super::super::linux_def::*;
 use super::super::super::path::*;
 use super::super::super::range::*;
 use super::super::fs::dirent::*;
+use super::super::fs::fanotify::*;
 use super::super::fs::file::*;
 use super::super::fs::flags::*;
 use super::super::fs::inotify::*;
@@ -150,8 +152,10 @@ pub fn OpenPath(task: &mut Task, filename: &str, maxTraversals: u32) -> Result<(
         },
     )?;
 
-    file.Dirent
-        .InotifyEvent(InotifyEvent::IN_OPEN, 0, EventType::InodeEvent);
+    FanotifyPermission(task, &file, FAN_OPEN_PERM | FAN_OPEN_EXEC_PERM)?;
+
+    file.InotifyEvent(InotifyEvent::IN_OPEN, 0, EventType::InodeEvent);
+    FanotifyEvent(&d, FAN_OPEN_EXEC);
 
     return Ok((file, d));
 }
@@ -170,9 +174,7 @@ pub fn LoadExecutable(
 
     for _i in 0..MAX_LOADER_ATTEMPTS {
         let (file, executable) = OpenPath(task, &filename, 40)?;
-        defer!(file
-            .Dirent
-            .InotifyEvent(InotifyEvent::IN_CLOSE_NOWRITE, 0, EventType::InodeEvent));
+        defer!(file.InotifyEvent(InotifyEvent::IN_CLOSE_NOWRITE, 0, EventType::InodeEvent));
         let mut hdr: [u8; 4] = [0; 4];
 
         match ReadAll(task, &file, &mut hdr, 0) {
@@ -181,8 +183,7 @@ pub fn LoadExecutable(
                 return Err(Error::SysError(SysErr::ENOEXEC));
             }
             Ok(n) => {
-                file.Dirent
-                    .InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
+                file.InotifyEvent(InotifyEvent::IN_ACCESS, 0, EventType::InodeEvent);
                 if n < 4 {
                     print!(
                         "Error loading ELF, there is less than 4 bytes data, cnt is {}",
@@ -0,0 +1,164 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+use super::super::super::common::*;
+use super::super::super::linux::time::*;
+use super::super::super::linux_def::*;
+use super::super::super::mem::buddy_allocator::*;
+use super::super::super::metric::*;
+use super::super::super::mutex::*;
+use super::super::super::singleton::*;
+use super::super::task::*;
+use super::super::Kernel::HostSpace;
+use super::super::SHARESPACE;
+use crate::GLOBAL_ALLOCATOR;
+
+pub static FREE_PAGE_REPORTER: Singleton<FreePageReporter> = Singleton::<FreePageReporter>::New();
+
+pub unsafe fn InitSingleton() {
+    FREE_PAGE_REPORTER.Init(FreePageReporter::New());
+}
+
+// PAGE_REPORT_ORDER is the min order of the free heap blocks reported to the
+// host, a freed page block (4MB) is always large enough
+pub const PAGE_REPORT_ORDER: usize = 21;
+
+// PAGE_REPORT_INTERVAL is how often the free heap blocks are reported
+pub const PAGE_REPORT_INTERVAL: i64 = SECOND;
+
+// PAGE_REPORT_RATE is the max bytes reported to the host in one interval, the
+// rest is reported in the next intervals
+pub const PAGE_REPORT_RATE: u64 = 1 << 30;
+
+// FreePageReporter returns the free memory of the guest heap to the host, so
+// the host memory of a sandbox shrinks after its memory usage drops. Both the
+// page blocks freed by the page allocator and the large buffers freed by the
+// heap users end up as free buddy blocks. They are taken out of the heap in
+// batches and reported without the heap lock, so the host can't drop a block
+// reused by the guest and the other vcpus can use the heap meanwhile.
+pub struct FreePageReporter {
+    pub lastReport: QMutex<i64>,
+    pub failed: AtomicU64,
+
+    pub reportedBytes: Arc<U64Metric>,
+    pub reports: Arc<U64Metric>,
+}
+
+impl FreePageReporter {
+    pub fn New() -> Self {
+        return Self {
+            lastReport: QMutex::new(0),
+            failed: AtomicU64::new(0),
+            reportedBytes: NewU64Metric(
+                "/memory/free_page_reporting/reported_bytes",
+                false,
+                "Bytes of free guest memory returned to the host.",
+            ),
+            reports: NewU64Metric(
+                "/memory/free_page_reporting/reports",
+                false,
+                "Number of free page reports sent to the host.",
+            ),
+        };
+    }
+
+    pub fn Enabled() -> bool {
+        return SHARESPACE.config.read().FreePageReporting;
+    }
+
+    // Flush reports the free heap blocks which are not reported yet, it is
+    // called by the cpu clock ticker.
+    pub fn Flush(&self) -> Result<()> {
+        if !Self::Enabled() {
+            return Ok(());
+        }
+
+        let now = Task::MonoTimeNow().0;
+        {
+            let mut last = self.lastReport.lock();
+            if now - *last < PAGE_REPORT_INTERVAL {
+                return Ok(());
+            }
+            *last = now;
+        }
+
+        let mut ret = 0;
+        let mut budget = PAGE_REPORT_RATE as usize;
+        loop {
+            let mut blocks = [(0, 0); REPORT_BATCH];
+            let count = GLOBAL_ALLOCATOR.Allocator().heap.lock().TakeUnreported(
+                PAGE_REPORT_ORDER,
+                budget,
+                &mut blocks,
+            );
+            if count == 0 {
+                break;
+            }
+
+            let mut ranges = [(0, 0); REPORT_BATCH];
+            for i in 0..count {
+                let (start, class) = blocks[i];
+                ranges[i] = ReportRange(start, class);
+                budget -= 1 << class;
+            }
+
+            ret = self.Report(&ranges[..count]);
+            GLOBAL_ALLOCATOR
+                .Allocator()
+                .heap
+                .lock()
+                .ReturnReported(&blocks[..count], ret >= 0);
+            if ret < 0 {
+                break;
+            }
+        }
+
+        if ret < 0 {
+            // log only once for a streak of failures
+            if self.failed.fetch_add(1, Ordering::AcqRel) == 0 {
+                error!("ReportFreePages fail with error {}", ret);
+            }
+            return Err(Error::SysError(-ret as i32));
+        }
+
+        self.failed.store(0, Ordering::Release);
+        return Ok(());
+    }
+
+    // Report sends one batch of free ranges to the host, the ranges are taken
+    // out of the heap so it runs without the heap lock.
+    fn Report(&self, ranges: &[(usize, usize)]) -> i64 {
+        let mut iovs = [IoVec::default(); REPORT_BATCH];
+        let mut bytes = 0;
+        for (i, (start, len)) in ranges.iter().enumerate() {
+            iovs[i] = IoVec {
+                start: *start as u64,
+                len: *len,
+            };
+            bytes += *len as u64;
+        }
+
+        let ret = HostSpace::ReportFreePages(&iovs[..ranges.len()]);
+        if ret >= 0 {
+            self.reports.Incr();
+            self.reportedBytes.IncrBy(bytes);
+        }
+
+        return ret;
+    }
+}
@@ -73,6 +73,39 @@ impl Allocator for PageMgr {
     }
 }
 
+// PinnedPages holds a reference of the user pages whose physical addresses are
+// handed to the host, the reclaimer only swaps out the pages referenced by one
+// page table entry.
+pub struct PinnedPages(Vec<u64>);
+
+impl PinnedPages {
+    pub fn New(iovs: &[IoVec]) -> Self {
+        let mut pages = Vec::new();
+        for iov in iovs {
+            let end = iov.start + iov.len as u64;
+            let mut page = iov.start & !(MemoryDef::PAGE_SIZE_4K - 1);
+            while page < end {
+                PAGE_MGR.RefPage(page);
+                pages.push(page);
+                page += MemoryDef::PAGE_SIZE_4K;
+            }
+        }
+
+        return Self(pages);
+    }
+}
+
+impl Drop for PinnedPages {
+    fn drop(&mut self) {
+        for page in &self.0 {
+            // the page has been unmapped when it is pinned
+            if PAGE_MGR.Deref(*page).unwrap() == 0 {
+                PAGE_MGR.FreePage(*page).unwrap();
+            }
+        }
+    }
+}
+
 extern "C" {
     pub fn __vsyscall_page();
 }
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod throttle;
 pub mod uring_async;
 pub mod uring_mgr;
 pub mod uring_op;
@@ -0,0 +1,179 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+use super::super::super::loader::BlockIOLimits;
+use super::super::kernel::timer::MONOTONIC_CLOCK;
+use super::super::task::*;
+
+lazy_static! {
+    pub static ref IO_THROTTLES: IOThrottles = IOThrottles::default();
+}
+
+pub const SECOND: i64 = 1_000_000_000;
+
+// TokenBucket limits the rate of a resource, the unused tokens fill the
+// bucket up to one second of the rate, which is the allowed burst.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct TokenBucket {
+    // rate is the number of tokens per second, 0 is unlimited
+    pub rate: u64,
+
+    // next is the monotonic time when the consumed tokens are refilled
+    pub next: i64,
+}
+
+impl TokenBucket {
+    pub fn New(rate: u64) -> Self {
+        return Self {
+            rate: rate,
+            next: 0,
+        };
+    }
+
+    // Consume takes count tokens and returns the ns to wait before the
+    // resource can be used.
+    pub fn Consume(&mut self, now: i64, count: u64) -> i64 {
+        if self.rate == 0 {
+            return 0;
+        }
+
+        let cost = (count as u128 * SECOND as u128 / self.rate as u128) as i64;
+        let start = if self.next < now - SECOND {
+            now - SECOND
+        } else {
+            self.next
+        };
+
+        self.next = start + cost;
+        if self.next <= now {
+            return 0;
+        }
+
+        return self.next - now;
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct IOThrottleIntern {
+    pub readBps: TokenBucket,
+    pub writeBps: TokenBucket,
+    pub readIops: TokenBucket,
+    pub writeIops: TokenBucket,
+}
+
+// IOThrottle is the block I/O bandwidth and iops limits of a container. The
+// cgroup of the sandbox can't tell the containers apart and doesn't see the
+// host file I/O done through io_uring, so they are applied before the I/O is
+// submitted.
+#[derive(Default, Clone)]
+pub struct IOThrottle(Arc<QMutex<IOThrottleIntern>>);
+
+impl Deref for IOThrottle {
+    type Target = Arc<QMutex<IOThrottleIntern>>;
+
+    fn deref(&self) -> &Arc<QMutex<IOThrottleIntern>> {
+        &self.0
+    }
+}
+
+impl IOThrottle {
+    pub fn New(limits: &BlockIOLimits) -> Self {
+        return Self(Arc::new(QMutex::new(IOThrottleIntern {
+            readBps: TokenBucket::New(limits.ReadBps),
+            writeBps: TokenBucket::New(limits.WriteBps),
+            readIops: TokenBucket::New(limits.ReadIops),
+            writeIops: TokenBucket::New(limits.WriteIops),
+        })));
+    }
+
+    // Delay charges an I/O of len bytes and returns the ns it has to wait.
+    pub fn Delay(&self, write: bool, len: u64) -> i64 {
+        let now = MONOTONIC_CLOCK.Now().0;
+        let mut t = self.lock();
+        let (bps, iops) = if write {
+            (t.writeBps.Consume(now, len), t.writeIops.Consume(now, 1))
+        } else {
+            (t.readBps.Consume(now, len), t.readIops.Consume(now, 1))
+        };
+
+        return bps.max(iops);
+    }
+}
+
+// IOThrottles is the throttle of the containers by their id.
+#[derive(Default)]
+pub struct IOThrottles {
+    pub throttles: QMutex<BTreeMap<String, IOThrottle>>,
+
+    // count is the number of throttles, it saves the lookup of the unthrottled
+    // sandboxes
+    pub count: AtomicUsize,
+}
+
+impl IOThrottles {
+    pub fn Set(&self, cid: &str, limits: &BlockIOLimits) {
+        let mut throttles = self.throttles.lock();
+        if limits.IsUnlimited() {
+            throttles.remove(cid);
+        } else {
+            throttles.insert(cid.to_string(), IOThrottle::New(limits));
+        }
+
+        self.count.store(throttles.len(), Ordering::SeqCst);
+    }
+
+    pub fn Get(&self, cid: &str) -> Option<IOThrottle> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        return self.throttles.lock().get(cid).cloned();
+    }
+
+    // Throttle waits until the I/O of len bytes of the task is allowed by the
+    // limits of its container. A signal ends the wait early, the consumed
+    // tokens stay charged so the next I/O waits for them.
+    pub fn Throttle(&self, task: &Task, write: bool, len: u64) {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let cid = match &task.thread {
+            None => return,
+            Some(thread) => thread.ContainerID(),
+        };
+
+        let throttle = match self.Get(&cid) {
+            None => return,
+            Some(t) => t,
+        };
+
+        let delay = throttle.Delay(write, len);
+        if delay <= 0 {
+            return;
+        }
+
+        task.ioUsage.AccountThrottle(write, delay);
+        let _ = task.blocker.BlockWithMonoTimeout(false, Some(delay));
+    }
+}
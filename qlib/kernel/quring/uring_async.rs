@@ -42,6 +42,7 @@ use super::super::task::*;
 use super::super::IOURING;
 use super::super::SHARESPACE;
 use super::uring_op::UringCall;
+use crate::qlib::kernel::boot::controller::HibernateWakeupEvent;
 use crate::qlib::kernel::kernel::kernel::GetKernel;
 use crate::qlib::kernel::tcpip::tcpip::SockAddrInet;
 
@@ -735,8 +736,16 @@ impl AsyncOpsTrait for AsyncAccept {
         }
 
         if SHARESPACE.hibernatePause.load(Ordering::Relaxed) {
-            GetKernel().Unpause();
-            SHARESPACE.hibernatePause.store(false, Ordering::SeqCst);
+            let kernel = GetKernel();
+            let mut state = kernel.pauseState.lock();
+            // a sandbox paused by the container runtime stays paused
+            if SHARESPACE.hibernatePause.load(Ordering::Relaxed) {
+                if !state.paused {
+                    kernel.Unpause();
+                }
+                SHARESPACE.hibernatePause.store(false, Ordering::SeqCst);
+                HibernateWakeupEvent(&kernel, &mut state);
+            }
         }
 
         /**************************hibernate wakeu end **************************/
@@ -129,6 +129,14 @@ impl QUring {
         return self.UCall(task, msg);
     }
 
+    // FileRead reads the host file and accounts the I/O to the task, the
+    // caller applies the block I/O limits.
+    pub fn FileRead(&self, task: &Task, fd: i32, addr: u64, len: u32, offset: i64) -> i64 {
+        let ret = self.Read(task, fd, addr, len, offset);
+        task.ioUsage.AccountReadIO(ret);
+        return ret;
+    }
+
     pub fn SyncAccept(&self, task: &Task, fd: i32) -> i64 {
         let msg = UringOp::Accept(AcceptOp { fd: fd });
 
@@ -146,6 +154,14 @@ impl QUring {
         return self.UCall(task, msg);
     }
 
+    // FileWrite writes the host file and accounts the I/O to the task, the
+    // caller applies the block I/O limits.
+    pub fn FileWrite(&self, task: &Task, fd: i32, addr: u64, len: u32, offset: i64) -> i64 {
+        let ret = self.Write(task, fd, addr, len, offset);
+        task.ioUsage.AccountWriteIO(ret);
+        return ret;
+    }
+
     pub fn Splice(
         &self,
         task: &Task,
@@ -419,12 +435,14 @@ impl QUring {
 
     pub fn BufFileWrite(
         &self,
+        task: &Task,
         fd: i32,
         buf: DataBuff,
         offset: i64,
         lockGuard: QAsyncLockGuard,
     ) -> i64 {
         let len = buf.Len() as i64;
+        task.ioUsage.AccountWriteIO(len);
         let writeop = AsyncBufWrite::New(fd, buf, offset, lockGuard);
 
         IOURING.AUCall(AsyncOps::AsyncBufWrite(writeop));
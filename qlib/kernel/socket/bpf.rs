@@ -0,0 +1,377 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The classic bpf socket filter of SO_ATTACH_FILTER, see
+// linux/Documentation/networking/filter.rst.
+
+use alloc::vec::Vec;
+
+use super::super::super::common::*;
+use super::super::super::linux_def::*;
+use super::super::task::*;
+
+// instruction classes
+pub const BPF_LD: u16 = 0x00;
+pub const BPF_LDX: u16 = 0x01;
+pub const BPF_ST: u16 = 0x02;
+pub const BPF_STX: u16 = 0x03;
+pub const BPF_ALU: u16 = 0x04;
+pub const BPF_JMP: u16 = 0x05;
+pub const BPF_RET: u16 = 0x06;
+pub const BPF_MISC: u16 = 0x07;
+
+// ld/ldx sizes
+pub const BPF_W: u16 = 0x00;
+pub const BPF_H: u16 = 0x08;
+pub const BPF_B: u16 = 0x10;
+
+// ld/ldx modes
+pub const BPF_IMM: u16 = 0x00;
+pub const BPF_ABS: u16 = 0x20;
+pub const BPF_IND: u16 = 0x40;
+pub const BPF_MEM: u16 = 0x60;
+pub const BPF_LEN: u16 = 0x80;
+pub const BPF_MSH: u16 = 0xa0;
+
+// alu operations
+pub const BPF_ADD: u16 = 0x00;
+pub const BPF_SUB: u16 = 0x10;
+pub const BPF_MUL: u16 = 0x20;
+pub const BPF_DIV: u16 = 0x30;
+pub const BPF_OR: u16 = 0x40;
+pub const BPF_AND: u16 = 0x50;
+pub const BPF_LSH: u16 = 0x60;
+pub const BPF_RSH: u16 = 0x70;
+pub const BPF_NEG: u16 = 0x80;
+pub const BPF_MOD: u16 = 0x90;
+pub const BPF_XOR: u16 = 0xa0;
+
+// jump operations
+pub const BPF_JA: u16 = 0x00;
+pub const BPF_JEQ: u16 = 0x10;
+pub const BPF_JGT: u16 = 0x20;
+pub const BPF_JGE: u16 = 0x30;
+pub const BPF_JSET: u16 = 0x40;
+
+// operand sources
+pub const BPF_K: u16 = 0x00;
+pub const BPF_X: u16 = 0x08;
+// the return value source of BPF_RET
+pub const BPF_A: u16 = 0x10;
+
+// misc operations
+pub const BPF_TAX: u16 = 0x00;
+pub const BPF_TXA: u16 = 0x80;
+
+pub const BPF_MAXINSNS: usize = 4096;
+pub const BPF_MEMWORDS: usize = 16;
+
+// the negative offsets of the linux extensions, from uapi/linux/filter.h
+pub const SKF_AD_OFF: i32 = -0x1000;
+pub const SKF_AD_PROTOCOL: i32 = 0;
+pub const SKF_AD_PKTTYPE: i32 = 4;
+pub const SKF_AD_IFINDEX: i32 = 8;
+pub const SKF_AD_VLAN_TAG: i32 = 44;
+pub const SKF_AD_VLAN_TAG_PRESENT: i32 = 48;
+pub const SKF_AD_MAX: i32 = 64;
+pub const SKF_NET_OFF: i32 = -0x100000;
+pub const SKF_LL_OFF: i32 = -0x200000;
+
+// SockFilter is struct sock_filter
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+// SockFprog is struct sock_fprog
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: u64,
+}
+
+pub const SIZEOF_SOCK_FPROG: usize = 16;
+
+// BpfPacket is the packet and the metadata a socket filter runs over.
+pub struct BpfPacket<'a> {
+    // the data starts at the link header for an AF_PACKET SOCK_RAW socket and
+    // at the network header otherwise
+    pub data: &'a [u8],
+    // the offset of the network header in data, for SKF_NET_OFF
+    pub netOffset: usize,
+    // the ethernet protocol in host byte order
+    pub protocol: u16,
+    pub pktType: u8,
+    pub ifindex: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BpfProgram {
+    pub insns: Vec<SockFilter>,
+}
+
+impl BpfProgram {
+    // New validates the program like linux bpf_check_classic
+    pub fn New(insns: Vec<SockFilter>) -> Result<Self> {
+        if insns.len() == 0 || insns.len() > BPF_MAXINSNS {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        for (pc, insn) in insns.iter().enumerate() {
+            let class = insn.code & 0x07;
+            let valid = match class {
+                BPF_LD | BPF_LDX => {
+                    let size = insn.code & 0x18;
+                    let mode = insn.code & 0xe0;
+                    match mode {
+                        BPF_IMM | BPF_LEN => true,
+                        BPF_MEM => (insn.k as usize) < BPF_MEMWORDS,
+                        BPF_ABS | BPF_IND => class == BPF_LD && size != 0x18,
+                        BPF_MSH => class == BPF_LDX && size == BPF_B,
+                        _ => false,
+                    }
+                }
+                BPF_ST | BPF_STX => insn.code & !0x07 == 0 && (insn.k as usize) < BPF_MEMWORDS,
+                BPF_ALU => {
+                    let op = insn.code & 0xf0;
+                    let src = insn.code & 0x08;
+                    match op {
+                        BPF_DIV | BPF_MOD => !(src == BPF_K && insn.k == 0),
+                        BPF_LSH | BPF_RSH => !(src == BPF_K && insn.k >= 32),
+                        BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_NEG | BPF_XOR => true,
+                        _ => false,
+                    }
+                }
+                BPF_JMP => {
+                    let op = insn.code & 0xf0;
+                    let rest = insns.len() - pc - 1;
+                    match op {
+                        BPF_JA => (insn.k as usize) < rest,
+                        BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                            (insn.jt as usize) < rest && (insn.jf as usize) < rest
+                        }
+                        _ => false,
+                    }
+                }
+                BPF_RET => {
+                    let src = insn.code & 0x18;
+                    src == BPF_K || src == BPF_A
+                }
+                _ => {
+                    let op = insn.code & 0xf8;
+                    op == BPF_TAX || op == BPF_TXA
+                }
+            };
+
+            if !valid {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+        }
+
+        // the program must not run off its end
+        if insns[insns.len() - 1].code & 0x07 != BPF_RET {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        return Ok(Self { insns: insns });
+    }
+
+    // CopyIn reads the struct sock_fprog of SO_ATTACH_FILTER and the filter
+    // it points to from the task's memory
+    pub fn CopyIn(task: &Task, opt: &[u8]) -> Result<Self> {
+        if opt.len() < SIZEOF_SOCK_FPROG {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let len = u16::from_ne_bytes([opt[0], opt[1]]) as usize;
+        let mut ptr = [0u8; 8];
+        ptr.copy_from_slice(&opt[8..16]);
+        let addr = u64::from_ne_bytes(ptr);
+
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let insns: Vec<SockFilter> = task.CopyInVec(addr, len)?;
+        return Self::New(insns);
+    }
+
+    fn Load(pkt: &BpfPacket, offset: i64, size: usize) -> Option<u32> {
+        if offset < 0 {
+            let offset = offset as i32;
+            let (base, off) = if offset >= SKF_AD_OFF {
+                match offset - SKF_AD_OFF {
+                    SKF_AD_PROTOCOL => return Some(pkt.protocol as u32),
+                    SKF_AD_PKTTYPE => return Some(pkt.pktType as u32),
+                    SKF_AD_IFINDEX => return Some(pkt.ifindex as u32),
+                    SKF_AD_VLAN_TAG | SKF_AD_VLAN_TAG_PRESENT => return Some(0),
+                    _ => return None,
+                }
+            } else if offset >= SKF_NET_OFF {
+                (pkt.netOffset, offset - SKF_NET_OFF)
+            } else if offset >= SKF_LL_OFF {
+                (0, offset - SKF_LL_OFF)
+            } else {
+                return None;
+            };
+
+            return Self::Load(pkt, (base + off as usize) as i64, size);
+        }
+
+        let start = offset as usize;
+        if start + size > pkt.data.len() {
+            return None;
+        }
+
+        let bytes = &pkt.data[start..start + size];
+        let v = match size {
+            4 => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            2 => u16::from_be_bytes([bytes[0], bytes[1]]) as u32,
+            _ => bytes[0] as u32,
+        };
+
+        return Some(v);
+    }
+
+    // Run returns the number of bytes of the packet to accept, 0 drops it
+    pub fn Run(&self, pkt: &BpfPacket) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+
+        while pc < self.insns.len() {
+            let insn = self.insns[pc];
+            pc += 1;
+
+            let class = insn.code & 0x07;
+            match class {
+                BPF_LD | BPF_LDX => {
+                    let size = match insn.code & 0x18 {
+                        BPF_W => 4,
+                        BPF_H => 2,
+                        _ => 1,
+                    };
+
+                    let v = match insn.code & 0xe0 {
+                        BPF_IMM => insn.k,
+                        BPF_LEN => pkt.data.len() as u32,
+                        BPF_MEM => mem[insn.k as usize],
+                        BPF_ABS => match Self::Load(pkt, insn.k as i32 as i64, size) {
+                            None => return 0,
+                            Some(v) => v,
+                        },
+                        BPF_IND => {
+                            let offset = x as i64 + insn.k as i32 as i64;
+                            match Self::Load(pkt, offset, size) {
+                                None => return 0,
+                                Some(v) => v,
+                            }
+                        }
+                        _ => {
+                            // BPF_MSH: the ipv4 header length
+                            match Self::Load(pkt, insn.k as i32 as i64, 1) {
+                                None => return 0,
+                                Some(v) => (v & 0xf) << 2,
+                            }
+                        }
+                    };
+
+                    if class == BPF_LD {
+                        a = v;
+                    } else {
+                        x = v;
+                    }
+                }
+                BPF_ST => mem[insn.k as usize] = a,
+                BPF_STX => mem[insn.k as usize] = x,
+                BPF_ALU => {
+                    let operand = if insn.code & 0x08 == BPF_X { x } else { insn.k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV => {
+                            if operand == 0 {
+                                return 0;
+                            }
+                            a / operand
+                        }
+                        BPF_MOD => {
+                            if operand == 0 {
+                                return 0;
+                            }
+                            a % operand
+                        }
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_LSH => {
+                            if operand >= 32 {
+                                0
+                            } else {
+                                a << operand
+                            }
+                        }
+                        BPF_RSH => {
+                            if operand >= 32 {
+                                0
+                            } else {
+                                a >> operand
+                            }
+                        }
+                        BPF_NEG => (a as i32).wrapping_neg() as u32,
+                        _ => a ^ operand,
+                    };
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & 0x08 == BPF_X { x } else { insn.k };
+                    let cond = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += insn.k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        _ => a & operand != 0,
+                    };
+
+                    let offset = if cond { insn.jt } else { insn.jf };
+                    pc += offset as usize;
+                }
+                BPF_RET => {
+                    if insn.code & 0x18 == BPF_A {
+                        return a;
+                    }
+                    return insn.k;
+                }
+                _ => {
+                    if insn.code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+            }
+        }
+
+        // New makes sure the program ends with a return
+        return 0;
+    }
+}
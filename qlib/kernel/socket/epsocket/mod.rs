@@ -13,3 +13,7 @@
 // limitations under the License.
 
 pub mod epsocket;
+pub mod netfilter;
+pub mod netstack_raw;
+pub mod netstack_socket;
+pub mod netstack_tcp;
@@ -0,0 +1,116 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The ip_tables and ip6_tables socket options of the netstack raw sockets,
+// iptables and ip6tables manage the netstack nat tables with them.
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::netfilter::*;
+use super::super::super::super::linux::socket::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::task::*;
+use super::super::super::tcpip::nat::*;
+use super::super::super::tcpip::stack::*;
+
+// CheckNetfilterAccess checks the option level matches the family of the
+// socket and the caller has CAP_NET_ADMIN like linux
+fn CheckNetfilterAccess(task: &Task, family: i32, level: i32) -> Result<()> {
+    let want = if family == AFType::AF_INET6 {
+        SOL_IPV6
+    } else {
+        SOL_IP
+    };
+    if level != want {
+        return Err(Error::SysError(SysErr::ENOPROTOOPT));
+    }
+
+    if !task.Creds().HasCapability(Capability::CAP_NET_ADMIN) {
+        return Err(Error::SysError(SysErr::EPERM));
+    }
+
+    return Ok(());
+}
+
+// NetfilterGetSockOpt serves the IPT_SO_GET options, the request is read
+// from opt and the answer is written back to it
+pub fn NetfilterGetSockOpt(
+    task: &Task,
+    family: i32,
+    level: i32,
+    name: i32,
+    opt: &mut [u8],
+) -> Result<i64> {
+    CheckNetfilterAccess(task, family, level)?;
+
+    match name {
+        IPT_SO_GET_INFO => {
+            let mut inner = NETSTACK.lock();
+            inner.netfilter.TableByName(family, opt)?.GetInfo(opt)?;
+        }
+        IPT_SO_GET_ENTRIES => {
+            let mut inner = NETSTACK.lock();
+            inner.netfilter.TableByName(family, opt)?.GetEntries(opt)?;
+        }
+        IPT_SO_GET_REVISION_MATCH | IPT_SO_GET_REVISION_TARGET => {
+            // struct xt_get_revision
+            if opt.len() != XT_GET_REVISION_SIZE {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            let ext = XtName(&opt[..XT_EXTENSION_MAXNAMELEN]);
+            let revision = opt[XT_EXTENSION_MAXNAMELEN];
+            XtRevision(family, name == IPT_SO_GET_REVISION_TARGET, ext, revision)?;
+        }
+        _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+    }
+
+    return Ok(opt.len() as i64);
+}
+
+// NetfilterSetSockOpt handles IPT_SO_SET_REPLACE and IPT_SO_SET_ADD_COUNTERS
+pub fn NetfilterSetSockOpt(
+    task: &Task,
+    family: i32,
+    level: i32,
+    name: i32,
+    opt: &[u8],
+) -> Result<i64> {
+    CheckNetfilterAccess(task, family, level)?;
+
+    match name {
+        IPT_SO_SET_REPLACE => {
+            let counters = {
+                let mut inner = NETSTACK.lock();
+                inner.netfilter.TableByName(family, opt)?.Replace(opt)?
+            };
+
+            // like linux, the counters of the old table are copied to the
+            // caller after the new one is in place
+            let mut addr = [0; 8];
+            addr.copy_from_slice(&opt[XT_REPLACE_COUNTERS..XT_REPLACE_COUNTERS + 8]);
+            let addr = u64::from_ne_bytes(addr);
+            if addr != 0 {
+                task.CopyOutSlice(&counters, addr, counters.len())?;
+            }
+        }
+        IPT_SO_SET_ADD_COUNTERS => {
+            // struct xt_counters_info starts with the table name
+            let mut inner = NETSTACK.lock();
+            inner.netfilter.TableByName(family, opt)?.AddCounters(opt)?;
+        }
+        _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+    }
+
+    return Ok(0);
+}
@@ -0,0 +1,842 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ops::Deref;
+use core::slice;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicI64;
+use core::sync::atomic::Ordering;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::netfilter::IsNetfilterSockOpt;
+use super::super::super::super::linux::socket::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::fs::attr::*;
+use super::super::super::fs::dentry::*;
+use super::super::super::fs::dirent::*;
+use super::super::super::fs::file::*;
+use super::super::super::fs::flags::*;
+use super::super::super::kernel::kernel::GetKernel;
+use super::super::super::kernel::time::*;
+use super::super::super::kernel::waiter::*;
+use super::super::super::memmgr::vma::MMappable;
+use super::super::super::task::*;
+use super::super::super::tcpip::header::*;
+use super::super::super::tcpip::raw::*;
+use super::super::super::tcpip::stack::*;
+use super::super::super::tcpip::tcpip::*;
+use super::super::bpf::*;
+use super::super::socket::*;
+use super::super::socketopts::*;
+use super::super::unix::transport::unix::SockType;
+use super::super::unix::unix::NewUnixSocketDummyDirent;
+use super::epsocket::{SockOptResult, SIZEOF_I32};
+use super::netfilter::*;
+use super::netstack_socket::*;
+
+// the option names of SOL_IP, SOL_IPV6, SOL_RAW, SOL_ICMPV6 and SOL_PACKET
+pub const IP_HDRINCL: i32 = 3;
+pub const IPV6_HDRINCL: i32 = 36;
+pub const ICMP_FILTER: i32 = 1;
+pub const ICMP6_FILTER: i32 = 1;
+// the size of struct icmp6_filter
+pub const ICMP6_FILTER_SIZE: usize = 32;
+pub const PACKET_ADD_MEMBERSHIP: i32 = 1;
+pub const PACKET_DROP_MEMBERSHIP: i32 = 2;
+pub const PACKET_STATISTICS: i32 = 6;
+
+pub fn NewNetstackRawSocket(
+    task: &Task,
+    ep: RawEndpoint,
+    family: i32,
+    stype: i32,
+    protocol: i32,
+    nonblocking: bool,
+) -> Result<File> {
+    let dirent = NewUnixSocketDummyDirent(task, SOCKET_DEVICE.clone())?;
+    let fileFlags = FileFlags {
+        Read: true,
+        Write: true,
+        NonBlocking: nonblocking,
+        ..Default::default()
+    };
+
+    let file = File::NewSocket(
+        &dirent,
+        &fileFlags,
+        NetstackRawSocketOperations::New(ep, family, stype, protocol).into(),
+    );
+
+    GetKernel().sockets.AddSocket(&file);
+
+    return Ok(file);
+}
+
+// ExtractLinkAddr returns the struct sockaddr_ll of an AF_PACKET address
+pub fn ExtractLinkAddr(sockaddr: &[u8]) -> Result<SockAddrLink> {
+    match GetAddr(AFType::AF_PACKET as i16, sockaddr)? {
+        SockAddr::Link(addr) => return Ok(addr),
+        _ => return Err(Error::SysError(SysErr::EINVAL)),
+    }
+}
+
+// NetstackRawSocketOperations is the AF_INET or AF_INET6 SOCK_RAW or the
+// AF_PACKET socket served by the user space netstack.
+pub struct NetstackRawSocketOperationsIntern {
+    pub ep: RawEndpoint,
+    pub family: i32,
+    pub stype: i32,
+    // the protocol argument of socket(2)
+    pub protocol: i32,
+    pub broadcast: AtomicBool,
+    pub recv: AtomicI64,
+    pub send: AtomicI64,
+}
+
+impl Drop for NetstackRawSocketOperationsIntern {
+    fn drop(&mut self) {
+        NETSTACK.RawUnregister(&self.ep);
+    }
+}
+
+#[derive(Clone)]
+pub struct NetstackRawSocketOperations(Arc<NetstackRawSocketOperationsIntern>);
+
+impl Deref for NetstackRawSocketOperations {
+    type Target = Arc<NetstackRawSocketOperationsIntern>;
+
+    fn deref(&self) -> &Arc<NetstackRawSocketOperationsIntern> {
+        &self.0
+    }
+}
+
+impl NetstackRawSocketOperations {
+    pub fn New(ep: RawEndpoint, family: i32, stype: i32, protocol: i32) -> Self {
+        NETSTACK.RawRegister(&ep);
+        return Self(Arc::new(NetstackRawSocketOperationsIntern {
+            ep: ep,
+            family: family,
+            stype: stype,
+            protocol: protocol,
+            broadcast: AtomicBool::new(false),
+            recv: AtomicI64::new(0),
+            send: AtomicI64::new(0),
+        }));
+    }
+
+    pub fn RecvQueueSize(&self) -> i32 {
+        let state = self.ep.state.lock();
+        match state.rcvQueue.front() {
+            None => return 0,
+            Some(pkt) => return pkt.data.len() as i32,
+        }
+    }
+
+    pub fn BlockingRecv(
+        &self,
+        task: &Task,
+        peek: bool,
+        dontWait: bool,
+        deadline: Option<Time>,
+    ) -> Result<RawPacket> {
+        match self.ep.Dequeue(peek) {
+            Some(pkt) => return Ok(pkt),
+            None => {
+                if dontWait {
+                    return Err(Error::SysError(SysErr::EAGAIN));
+                }
+            }
+        }
+
+        let general = task.blocker.generalEntry.clone();
+        self.EventRegister(task, &general, EVENT_READ);
+        defer!(self.EventUnregister(task, &general));
+
+        loop {
+            match self.ep.Dequeue(peek) {
+                Some(pkt) => return Ok(pkt),
+                None => {
+                    if self.ep.state.lock().rclosed {
+                        return Err(Error::SysError(SysErr::EAGAIN));
+                    }
+                }
+            }
+
+            match task.blocker.BlockWithMonoTimer(true, deadline) {
+                Err(Error::SysError(SysErr::ETIMEDOUT)) => {
+                    return Err(Error::SysError(SysErr::EAGAIN));
+                }
+                Err(Error::ErrInterrupted) => {
+                    return Err(Error::SysError(SysErr::ERESTARTSYS));
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Send sends data to the address of sendto(2), or to the connected peer
+    // when it is None
+    pub fn Send(&self, sockaddr: Option<&[u8]>, data: &[u8]) -> Result<i64> {
+        if self.ep.state.lock().wclosed {
+            return Err(Error::SysError(SysErr::EPIPE));
+        }
+
+        if self.ep.IsIp() {
+            let dst = match sockaddr {
+                Some(sockaddr) => match ExtractAddr(self.family, sockaddr)? {
+                    None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+                    Some((addr, _)) => addr,
+                },
+                None => match self.ep.state.lock().peer {
+                    None => return Err(Error::SysError(SysErr::EDESTADDRREQ)),
+                    Some(peer) => peer,
+                },
+            };
+
+            if NETSTACK.lock().nic.IsBroadcast(dst) {
+                if !self.broadcast.load(Ordering::Relaxed) {
+                    return Err(Error::SysError(SysErr::EACCES));
+                }
+            }
+
+            let n = match self.ep.kind {
+                RawEndpointKind::Ipv4(_) => NETSTACK.SendRawIpv4(&self.ep, dst.Ipv4(), data)?,
+                _ => {
+                    // the raw ipv6 sockets don't send ipv4 packets
+                    if dst.IsIpv4() {
+                        return Err(Error::SysError(SysErr::EINVAL));
+                    }
+                    NETSTACK.SendRawIpv6(&self.ep, dst, data)?
+                }
+            };
+            return Ok(n as i64);
+        }
+
+        let (mut protocol, ifindex) = {
+            let state = self.ep.state.lock();
+            (state.protocol, state.ifindex)
+        };
+
+        let mut dstMac = [0; 6];
+        match sockaddr {
+            None => {
+                // a cooked packet needs the destination hardware address
+                if self.ep.kind == RawEndpointKind::CookedPacket {
+                    return Err(Error::SysError(SysErr::EDESTADDRREQ));
+                }
+            }
+            Some(sockaddr) => {
+                let addr = ExtractLinkAddr(sockaddr)?;
+                if addr.InterfaceIndex != 0 && addr.InterfaceIndex != NETSTACK.IfIndex() {
+                    return Err(Error::SysError(SysErr::ENXIO));
+                }
+
+                if addr.Protocol != 0 {
+                    protocol = u16::from_be(addr.Protocol);
+                }
+
+                if self.ep.kind == RawEndpointKind::CookedPacket {
+                    if (addr.HardwareAddrLen as usize) < dstMac.len() {
+                        return Err(Error::SysError(SysErr::EINVAL));
+                    }
+                    dstMac.copy_from_slice(&addr.HardwareAddr[0..6]);
+                }
+            }
+        }
+
+        if ifindex != 0 && ifindex != NETSTACK.IfIndex() {
+            return Err(Error::SysError(SysErr::ENXIO));
+        }
+
+        let n = NETSTACK.SendPacket(&self.ep, protocol, dstMac, data)?;
+        return Ok(n as i64);
+    }
+
+    pub fn LinkAddr(&self, protocol: u16, pktType: u8, mac: [u8; 6]) -> SockAddr {
+        let mut hardwareAddr = [0; 8];
+        hardwareAddr[0..6].copy_from_slice(&mac);
+        return SockAddr::Link(SockAddrLink {
+            Family: AFType::AF_PACKET as u16,
+            Protocol: protocol.to_be(),
+            InterfaceIndex: NETSTACK.IfIndex(),
+            HardwareType: ARPHRD_ETHER,
+            PacketType: pktType,
+            HardwareAddrLen: 6,
+            HardwareAddr: hardwareAddr,
+        });
+    }
+
+    pub fn SockOptI32(opt: &[u8]) -> Result<i32> {
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        return Ok(i32::from_ne_bytes([opt[0], opt[1], opt[2], opt[3]]));
+    }
+}
+
+impl Waitable for NetstackRawSocketOperations {
+    fn Readiness(&self, _task: &Task, mask: EventMask) -> EventMask {
+        return self.ep.Readiness(mask);
+    }
+
+    fn EventRegister(&self, task: &Task, e: &WaitEntry, mask: EventMask) {
+        self.ep.queue.EventRegister(task, e, mask)
+    }
+
+    fn EventUnregister(&self, task: &Task, e: &WaitEntry) {
+        self.ep.queue.EventUnregister(task, e)
+    }
+}
+
+impl SpliceOperations for NetstackRawSocketOperations {}
+
+impl FileOperations for NetstackRawSocketOperations {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::NetstackRawSocketOperations;
+    }
+
+    fn Seekable(&self) -> bool {
+        return false;
+    }
+
+    fn Seek(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _whence: i32,
+        _current: i64,
+        _offset: i64,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ESPIPE));
+    }
+
+    fn ReadDir(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _offset: i64,
+        _serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ENOTDIR));
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        // the caller blocks on EAGAIN
+        let pkt = match self.ep.Dequeue(false) {
+            None => return Err(Error::SysError(SysErr::EAGAIN)),
+            Some(pkt) => pkt,
+        };
+
+        let len = task.CopyDataOutToIovs(&pkt.data, dsts, false)?;
+        return Ok(len as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        srcs: &[IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let size = IoVec::NumBytes(srcs);
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        return self.Send(None, &buf.buf[0..len]);
+    }
+
+    fn Append(&self, task: &Task, f: &File, srcs: &[IoVec]) -> Result<(i64, i64)> {
+        let n = self.WriteAt(task, f, srcs, 0, false)?;
+        return Ok((n, 0));
+    }
+
+    fn Fsync(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        _syncType: SyncType,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    fn Flush(&self, _task: &Task, _f: &File) -> Result<()> {
+        return Ok(());
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        let inode = f.Dirent.Inode();
+        return inode.UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, task: &Task, _f: &File, _fd: i32, request: u64, val: u64) -> Result<u64> {
+        match request {
+            LibcConst::TIOCINQ => {
+                let v = self.RecvQueueSize();
+                task.CopyOutObj(&v, val)?;
+                return Ok(0);
+            }
+            LibcConst::TIOCOUTQ => {
+                // the packets are written to the link synchronously
+                let v: i32 = 0;
+                task.CopyOutObj(&v, val)?;
+                return Ok(0);
+            }
+            _ => return Err(Error::SysError(SysErr::ENOTTY)),
+        }
+    }
+
+    fn IterateDir(
+        &self,
+        _task: &Task,
+        _d: &Dirent,
+        _dirCtx: &mut DirCtx,
+        _offset: i32,
+    ) -> (i32, Result<i64>) {
+        return (0, Err(Error::SysError(SysErr::ENOTDIR)));
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
+
+impl SockOperations for NetstackRawSocketOperations {
+    fn Connect(&self, _task: &Task, socketaddr: &[u8], _blocking: bool) -> Result<i64> {
+        if !self.ep.IsIp() {
+            return Err(Error::SysError(SysErr::EOPNOTSUPP));
+        }
+
+        match ExtractAddr(self.family, socketaddr)? {
+            None => self.ep.state.lock().peer = None,
+            Some((addr, _)) => self.ep.state.lock().peer = Some(addr),
+        }
+
+        return Ok(0);
+    }
+
+    fn Accept(
+        &self,
+        _task: &Task,
+        _addr: &mut [u8],
+        _addrlen: &mut u32,
+        _flags: i32,
+        _blocking: bool,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    fn Bind(&self, _task: &Task, socketaddr: &[u8]) -> Result<i64> {
+        if self.ep.IsIp() {
+            let addr = match ExtractAddr(self.family, socketaddr)? {
+                None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+                Some((addr, _)) => addr,
+            };
+
+            if !addr.IsAny() && !NETSTACK.IsLocalAddr(addr) {
+                return Err(Error::SysError(SysErr::EADDRNOTAVAIL));
+            }
+
+            self.ep.state.lock().localAddr = addr;
+            return Ok(0);
+        }
+
+        let addr = ExtractLinkAddr(socketaddr)?;
+        if addr.InterfaceIndex != 0 && addr.InterfaceIndex != NETSTACK.IfIndex() {
+            return Err(Error::SysError(SysErr::ENODEV));
+        }
+
+        let mut state = self.ep.state.lock();
+        state.ifindex = addr.InterfaceIndex;
+        if addr.Protocol != 0 {
+            state.protocol = u16::from_be(addr.Protocol);
+        }
+
+        return Ok(0);
+    }
+
+    fn Listen(&self, _task: &Task, _backlog: i32) -> Result<i64> {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    fn Shutdown(&self, _task: &Task, how: i32) -> Result<i64> {
+        let mut state = self.ep.state.lock();
+        match how {
+            SHUT_RD => state.rclosed = true,
+            SHUT_WR => state.wclosed = true,
+            SHUT_RDWR => {
+                state.rclosed = true;
+                state.wclosed = true;
+            }
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        }
+        core::mem::drop(state);
+
+        self.ep.queue.Notify(READABLE_EVENT | WRITEABLE_EVENT);
+        return Ok(0);
+    }
+
+    fn GetSockOpt(&self, task: &Task, level: i32, name: i32, opt: &mut [u8]) -> Result<i64> {
+        if IsNetfilterSockOpt(level, name) && self.ep.IsIp() {
+            return NetfilterGetSockOpt(task, self.family, level, name, opt);
+        }
+
+        let ret = match (level, name) {
+            (SOL_SOCKET, SO_TYPE) => SockOptResult::I32(self.stype),
+            (SOL_SOCKET, SO_DOMAIN) => SockOptResult::I32(self.family),
+            (SOL_SOCKET, SO_PROTOCOL) => SockOptResult::I32(self.protocol),
+            (SOL_SOCKET, SO_ERROR) => SockOptResult::I32(0),
+            (SOL_SOCKET, SO_BROADCAST) => {
+                SockOptResult::I32(self.broadcast.load(Ordering::Relaxed) as i32)
+            }
+            (SOL_SOCKET, SO_RCVBUF) => SockOptResult::I32(self.ep.state.lock().rcvBufSize as i32),
+            (SOL_SOCKET, SO_SNDBUF) => SockOptResult::I32(self.ep.state.lock().sndBufSize as i32),
+            (SOL_SOCKET, SO_LOCK_FILTER) => {
+                SockOptResult::I32(self.ep.state.lock().filterLocked as i32)
+            }
+            (SOL_IP, IP_HDRINCL) if self.family == AFType::AF_INET => {
+                SockOptResult::I32(self.ep.state.lock().hdrIncl as i32)
+            }
+            (SOL_IPV6, IPV6_HDRINCL) if self.family == AFType::AF_INET6 => {
+                SockOptResult::I32(self.ep.state.lock().hdrIncl as i32)
+            }
+            (SOL_RAW, ICMP_FILTER) if self.ep.kind == RawEndpointKind::Ipv4(IPPROTO_ICMP) => {
+                SockOptResult::I32(self.ep.state.lock().icmpFilter as i32)
+            }
+            (SOL_ICMPV6, ICMP6_FILTER) if self.ep.kind == RawEndpointKind::Ipv6(IPPROTO_ICMPV6) => {
+                // like linux, the filter is truncated to the buffer
+                let filter = self.ep.state.lock().icmp6Filter;
+                let len = core::cmp::min(opt.len(), ICMP6_FILTER_SIZE);
+                for i in 0..len {
+                    opt[i] = filter[i / 4].to_ne_bytes()[i % 4];
+                }
+                return Ok(len as i64);
+            }
+            (SOL_PACKET, PACKET_STATISTICS) if self.ep.IsPacket() => {
+                // struct tpacket_stats, the counters are reset on read
+                if opt.len() < 2 * SIZEOF_I32 {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                let mut state = self.ep.state.lock();
+                let drops = state.drops as u32;
+                let packets = state.rcvQueue.len() as u32 + drops;
+                state.drops = 0;
+                opt[0..4].copy_from_slice(&packets.to_ne_bytes());
+                opt[4..8].copy_from_slice(&drops.to_ne_bytes());
+                return Ok(8);
+            }
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        };
+
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let len = ret.Marsh(opt)?;
+        return Ok(len as i64);
+    }
+
+    fn SetSockOpt(&self, task: &Task, level: i32, name: i32, opt: &[u8]) -> Result<i64> {
+        if IsNetfilterSockOpt(level, name) && self.ep.IsIp() {
+            return NetfilterSetSockOpt(task, self.family, level, name, opt);
+        }
+
+        match (level, name) {
+            (SOL_SOCKET, SO_ATTACH_FILTER) => {
+                let prog = BpfProgram::CopyIn(task, opt)?;
+                self.ep.SetFilter(Some(prog))?;
+            }
+            (SOL_SOCKET, SO_DETACH_FILTER) => self.ep.SetFilter(None)?,
+            (SOL_SOCKET, SO_LOCK_FILTER) => {
+                let v = Self::SockOptI32(opt)?;
+                let mut state = self.ep.state.lock();
+                if state.filterLocked && v == 0 {
+                    return Err(Error::SysError(SysErr::EPERM));
+                }
+                state.filterLocked = v != 0;
+            }
+            (SOL_SOCKET, SO_BROADCAST) => {
+                let v = Self::SockOptI32(opt)?;
+                self.broadcast.store(v != 0, Ordering::Relaxed);
+            }
+            (SOL_SOCKET, SO_RCVBUF) | (SOL_SOCKET, SO_SNDBUF) => {
+                let v = Self::SockOptI32(opt)?;
+                let size = if v < MINIMUM_BUFFER_SIZE as i32 {
+                    MINIMUM_BUFFER_SIZE
+                } else if v as usize > MAX_BUFFER_SIZE {
+                    MAX_BUFFER_SIZE
+                } else {
+                    v as usize
+                };
+
+                let mut state = self.ep.state.lock();
+                if name == SO_RCVBUF {
+                    state.rcvBufSize = size;
+                } else {
+                    state.sndBufSize = size;
+                }
+            }
+            (SOL_IP, IP_HDRINCL) if self.family == AFType::AF_INET => {
+                let v = Self::SockOptI32(opt)?;
+                self.ep.state.lock().hdrIncl = v != 0;
+            }
+            (SOL_IPV6, IPV6_HDRINCL) if self.family == AFType::AF_INET6 => {
+                let v = Self::SockOptI32(opt)?;
+                self.ep.state.lock().hdrIncl = v != 0;
+            }
+            (SOL_RAW, ICMP_FILTER) if self.ep.kind == RawEndpointKind::Ipv4(IPPROTO_ICMP) => {
+                let v = Self::SockOptI32(opt)?;
+                self.ep.state.lock().icmpFilter = v as u32;
+            }
+            (SOL_ICMPV6, ICMP6_FILTER) if self.ep.kind == RawEndpointKind::Ipv6(IPPROTO_ICMPV6) => {
+                // like linux, a short option sets the leading bytes of the filter
+                let len = core::cmp::min(opt.len(), ICMP6_FILTER_SIZE);
+                let mut state = self.ep.state.lock();
+                for i in 0..len {
+                    let mut word = state.icmp6Filter[i / 4].to_ne_bytes();
+                    word[i % 4] = opt[i];
+                    state.icmp6Filter[i / 4] = u32::from_ne_bytes(word);
+                }
+            }
+            // the netstack interface isn't filtered by the mac address, the
+            // memberships make no difference
+            (SOL_PACKET, PACKET_ADD_MEMBERSHIP) | (SOL_PACKET, PACKET_DROP_MEMBERSHIP)
+                if self.ep.IsPacket() => {}
+            // todo: support PACKET_RX_RING, PACKET_VERSION and PACKET_AUXDATA
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        }
+
+        return Ok(0);
+    }
+
+    fn GetSockName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let addr = if self.ep.IsIp() {
+            let localAddr = self.ep.state.lock().localAddr;
+            ToSockAddr(self.family, localAddr, 0)
+        } else {
+            let protocol = self.ep.state.lock().protocol;
+            let mac = NETSTACK.lock().nic.mac;
+            self.LinkAddr(protocol, PACKET_HOST, mac)
+        };
+
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn GetPeerName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let peer = match self.ep.state.lock().peer {
+            None => return Err(Error::SysError(SysErr::ENOTCONN)),
+            Some(peer) => peer,
+        };
+
+        let addr = ToSockAddr(self.family, peer, 0);
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn RecvMsg(
+        &self,
+        task: &Task,
+        dsts: &mut [IoVec],
+        flags: i32,
+        deadline: Option<Time>,
+        senderRequested: bool,
+        _controlDataLen: usize,
+    ) -> Result<(i64, i32, Option<(SockAddr, usize)>, Vec<u8>)> {
+        let trunc = flags & MsgType::MSG_TRUNC != 0;
+        let peek = flags & MsgType::MSG_PEEK != 0;
+        let dontWait = flags & MsgType::MSG_DONTWAIT != 0;
+
+        let pkt = self.BlockingRecv(task, peek, dontWait, deadline)?;
+
+        let size = IoVec::NumBytes(dsts);
+        let mut msgFlags = 0;
+        if pkt.data.len() > size {
+            msgFlags |= MsgType::MSG_TRUNC;
+        }
+
+        let mut len = task.CopyDataOutToIovs(&pkt.data, dsts, false)? as i64;
+        if trunc {
+            len = pkt.data.len() as i64;
+        }
+
+        let sender = if senderRequested {
+            let addr = if self.ep.IsIp() {
+                ToSockAddr(self.family, pkt.srcAddr, 0)
+            } else {
+                self.LinkAddr(pkt.protocol, pkt.pktType, pkt.srcMac)
+            };
+            let addrLen = addr.Len();
+            Some((addr, addrLen))
+        } else {
+            None
+        };
+
+        return Ok((len, msgFlags, sender, Vec::new()));
+    }
+
+    fn SendMsg(
+        &self,
+        task: &Task,
+        srcs: &[IoVec],
+        _flags: i32,
+        msgHdr: &mut MsgHdr,
+        _deadline: Option<Time>,
+    ) -> Result<i64> {
+        let sockaddr = if msgHdr.msgName != 0 && msgHdr.nameLen > 0 {
+            // sendSingleMsg has copied the name in
+            Some(unsafe {
+                slice::from_raw_parts(msgHdr.msgName as *const u8, msgHdr.nameLen as usize)
+            })
+        } else {
+            None
+        };
+
+        let size = IoVec::NumBytes(srcs);
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        return self.Send(sockaddr, &buf.buf[0..len]);
+    }
+
+    fn SetRecvTimeout(&self, ns: i64) {
+        self.recv.store(ns, Ordering::Relaxed)
+    }
+
+    fn SetSendTimeout(&self, ns: i64) {
+        self.send.store(ns, Ordering::Relaxed)
+    }
+
+    fn RecvTimeout(&self) -> i64 {
+        return self.recv.load(Ordering::Relaxed);
+    }
+
+    fn SendTimeout(&self) -> i64 {
+        return self.send.load(Ordering::Relaxed);
+    }
+
+    fn State(&self) -> u32 {
+        // TCP_CLOSE, like the udp socket
+        return 7;
+    }
+
+    fn Type(&self) -> (i32, i32, i32) {
+        return (self.family, self.stype, self.protocol);
+    }
+}
+
+// RawProvider checks CAP_NET_RAW for the AF_INET/AF_INET6 SOCK_RAW and the
+// AF_PACKET sockets. The netstack serves them when it's enabled, otherwise
+// they fall through to the host sockets in the sandbox's network namespace.
+pub struct RawProvider {
+    pub family: i32,
+}
+
+impl Provider for RawProvider {
+    fn Socket(&self, task: &Task, stype: i32, protocol: i32) -> Result<Option<Arc<File>>> {
+        let nonblocking = stype & SocketFlags::SOCK_NONBLOCK != 0;
+        let stype = stype & SocketType::SOCK_TYPE_MASK;
+
+        if self.family == AFType::AF_PACKET {
+            if stype != SockType::SOCK_RAW && stype != SockType::SOCK_DGRAM {
+                return Err(Error::SysError(SysErr::ESOCKTNOSUPPORT));
+            }
+        } else if stype != SockType::SOCK_RAW {
+            return Ok(None);
+        }
+
+        if !task.Creds().HasCapability(Capability::CAP_NET_RAW) {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
+        if !NetStack::Enabled() {
+            return Ok(None);
+        }
+
+        let ep = if self.family == AFType::AF_INET || self.family == AFType::AF_INET6 {
+            if protocol < 0 || protocol > 0xff {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            // linux doesn't allow the IPPROTO_IP raw socket
+            if protocol == 0 {
+                return Err(Error::SysError(SysErr::EPROTONOSUPPORT));
+            }
+
+            let kind = if self.family == AFType::AF_INET {
+                RawEndpointKind::Ipv4(protocol as u8)
+            } else {
+                RawEndpointKind::Ipv6(protocol as u8)
+            };
+
+            RawEndpoint::New(kind, 0, DEFAULT_BUFFER_SIZE, DEFAULT_BUFFER_SIZE)
+        } else {
+            let kind = if stype == SockType::SOCK_RAW {
+                RawEndpointKind::Packet
+            } else {
+                RawEndpointKind::CookedPacket
+            };
+
+            // the protocol is the ethernet type in network byte order
+            RawEndpoint::New(
+                kind,
+                u16::from_be(protocol as u16),
+                DEFAULT_BUFFER_SIZE,
+                DEFAULT_BUFFER_SIZE,
+            )
+        };
+
+        let file = NewNetstackRawSocket(task, ep, self.family, stype, protocol, nonblocking)?;
+        return Ok(Some(Arc::new(file)));
+    }
+
+    fn Pair(
+        &self,
+        _task: &Task,
+        _stype: i32,
+        _protocol: i32,
+    ) -> Result<Option<(Arc<File>, Arc<File>)>> {
+        return Ok(None);
+    }
+}
+
+pub fn Init() {
+    for family in [AFType::AF_INET, AFType::AF_INET6, AFType::AF_PACKET].iter() {
+        FAMILIAES
+            .write()
+            .RegisterProvider(*family, Box::new(RawProvider { family: *family }))
+    }
+}
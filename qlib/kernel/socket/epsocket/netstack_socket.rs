@@ -0,0 +1,725 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ops::Deref;
+use core::slice;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicI64;
+use core::sync::atomic::Ordering;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::socket::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::fs::attr::*;
+use super::super::super::fs::dentry::*;
+use super::super::super::fs::dirent::*;
+use super::super::super::fs::file::*;
+use super::super::super::fs::flags::*;
+use super::super::super::kernel::kernel::GetKernel;
+use super::super::super::kernel::time::*;
+use super::super::super::kernel::waiter::*;
+use super::super::super::memmgr::vma::MMappable;
+use super::super::super::task::*;
+use super::super::super::tcpip::header::IpAddr;
+use super::super::super::tcpip::header::IPPROTO_ICMP;
+use super::super::super::tcpip::header::IPPROTO_ICMPV6;
+use super::super::super::tcpip::header::IPPROTO_UDP;
+use super::super::super::tcpip::stack::*;
+use super::super::super::tcpip::tcp::TcpEndpoint;
+use super::super::super::tcpip::tcpip::*;
+use super::super::socket::*;
+use super::super::socketopts::*;
+use super::super::unix::transport::unix::SockType;
+use super::super::unix::unix::NewUnixSocketDummyDirent;
+use super::epsocket::{SockOptResult, SIZEOF_I32};
+use super::netstack_tcp::*;
+
+pub fn NewNetstackSocket(
+    task: &Task,
+    family: i32,
+    protocol: u8,
+    nonblocking: bool,
+) -> Result<File> {
+    let dirent = NewUnixSocketDummyDirent(task, SOCKET_DEVICE.clone())?;
+    let fileFlags = FileFlags {
+        Read: true,
+        Write: true,
+        NonBlocking: nonblocking,
+        ..Default::default()
+    };
+
+    let file = File::NewSocket(
+        &dirent,
+        &fileFlags,
+        NetstackSocketOperations::New(family, protocol).into(),
+    );
+
+    GetKernel().sockets.AddSocket(&file);
+
+    return Ok(file);
+}
+
+// ToSockAddr returns the sockaddr of the socket family, the ipv4 addresses
+// of an AF_INET6 socket are the ipv4 mapped ones
+pub fn ToSockAddr(family: i32, addr: IpAddr, port: u16) -> SockAddr {
+    if family == AFType::AF_INET6 {
+        return SockAddr::Inet6(SocketAddrInet6 {
+            Family: AFType::AF_INET6 as u16,
+            Port: port.to_be(),
+            Flowinfo: 0,
+            Addr: addr.0,
+            Scope_id: 0,
+        });
+    }
+
+    return SockAddr::Inet(SockAddrInet::New(port, &addr.Ipv4().to_be_bytes()));
+}
+
+// ExtractAddr returns the address and the port in host byte order of the
+// sockaddr given to a socket of the family. The address is None for AF_UNSPEC.
+pub fn ExtractAddr(family: i32, sockaddr: &[u8]) -> Result<Option<(IpAddr, u16)>> {
+    if sockaddr.len() < 2 {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    let addrFamily = u16::from_ne_bytes([sockaddr[0], sockaddr[1]]) as i32;
+    if addrFamily == AFType::AF_UNSPEC {
+        return Ok(None);
+    }
+
+    if addrFamily != family {
+        return Err(Error::SysError(SysErr::EAFNOSUPPORT));
+    }
+
+    match GetAddr(family as i16, sockaddr)? {
+        SockAddr::Inet(addr) => {
+            let ip = IpAddr::FromIpv4(u32::from_be_bytes(addr.Addr));
+            return Ok(Some((ip, addr.Ipv4Port())));
+        }
+        SockAddr::Inet6(addr) => {
+            return Ok(Some((IpAddr(addr.Addr), u16::from_be(addr.Port))));
+        }
+        _ => return Err(Error::SysError(SysErr::EINVAL)),
+    }
+}
+
+// NetstackSocketOperations is the udp or icmp echo (ping) socket of AF_INET
+// or AF_INET6 served by the user space netstack.
+pub struct NetstackSocketOperationsIntern {
+    pub ep: DatagramEndpoint,
+    pub broadcast: AtomicBool,
+    pub recv: AtomicI64,
+    pub send: AtomicI64,
+}
+
+impl Drop for NetstackSocketOperationsIntern {
+    fn drop(&mut self) {
+        NETSTACK.Unbind(&self.ep);
+    }
+}
+
+#[derive(Clone)]
+pub struct NetstackSocketOperations(Arc<NetstackSocketOperationsIntern>);
+
+impl Deref for NetstackSocketOperations {
+    type Target = Arc<NetstackSocketOperationsIntern>;
+
+    fn deref(&self) -> &Arc<NetstackSocketOperationsIntern> {
+        &self.0
+    }
+}
+
+impl NetstackSocketOperations {
+    pub fn New(family: i32, protocol: u8) -> Self {
+        let ep = DatagramEndpoint::New(family, protocol, DEFAULT_BUFFER_SIZE, DEFAULT_BUFFER_SIZE);
+        return Self(Arc::new(NetstackSocketOperationsIntern {
+            ep: ep,
+            broadcast: AtomicBool::new(false),
+            recv: AtomicI64::new(0),
+            send: AtomicI64::new(0),
+        }));
+    }
+
+    pub fn Family(&self) -> i32 {
+        return self.ep.family;
+    }
+
+    pub fn Protocol(&self) -> u8 {
+        return self.ep.protocol;
+    }
+
+    pub fn RecvQueueSize(&self) -> i32 {
+        let state = self.ep.state.lock();
+        match state.rcvQueue.front() {
+            None => return 0,
+            Some(pkt) => return pkt.data.len() as i32,
+        }
+    }
+
+    pub fn BlockingRecv(
+        &self,
+        task: &Task,
+        peek: bool,
+        dontWait: bool,
+        deadline: Option<Time>,
+    ) -> Result<DatagramPacket> {
+        match self.ep.Dequeue(peek) {
+            Some(pkt) => return Ok(pkt),
+            None => {
+                if dontWait {
+                    return Err(Error::SysError(SysErr::EAGAIN));
+                }
+            }
+        }
+
+        let general = task.blocker.generalEntry.clone();
+        self.EventRegister(task, &general, EVENT_READ);
+        defer!(self.EventUnregister(task, &general));
+
+        loop {
+            match self.ep.Dequeue(peek) {
+                Some(pkt) => return Ok(pkt),
+                None => {
+                    if self.ep.state.lock().rclosed {
+                        return Err(Error::SysError(SysErr::EAGAIN));
+                    }
+                }
+            }
+
+            match task.blocker.BlockWithMonoTimer(true, deadline) {
+                Err(Error::SysError(SysErr::ETIMEDOUT)) => {
+                    return Err(Error::SysError(SysErr::EAGAIN));
+                }
+                Err(Error::ErrInterrupted) => {
+                    return Err(Error::SysError(SysErr::ERESTARTSYS));
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub fn Send(&self, dst: (IpAddr, u16), data: &[u8]) -> Result<i64> {
+        let (addr, port) = dst;
+        {
+            let state = self.ep.state.lock();
+            if state.wclosed {
+                return Err(Error::SysError(SysErr::EPIPE));
+            }
+
+            if state.v6only && addr.IsIpv4() {
+                return Err(Error::SysError(SysErr::ENETUNREACH));
+            }
+        }
+
+        if NETSTACK.lock().nic.IsBroadcast(addr) {
+            if !self.broadcast.load(Ordering::Relaxed) {
+                return Err(Error::SysError(SysErr::EACCES));
+            }
+        }
+
+        let n = match self.Protocol() {
+            IPPROTO_UDP => {
+                if port == 0 {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+                NETSTACK.SendUdp(&self.ep, addr, port, data)?
+            }
+            _ => NETSTACK.SendIcmpEcho(&self.ep, addr, data)?,
+        };
+
+        return Ok(n as i64);
+    }
+}
+
+impl Waitable for NetstackSocketOperations {
+    fn Readiness(&self, _task: &Task, mask: EventMask) -> EventMask {
+        return self.ep.Readiness(mask);
+    }
+
+    fn EventRegister(&self, task: &Task, e: &WaitEntry, mask: EventMask) {
+        self.ep.queue.EventRegister(task, e, mask)
+    }
+
+    fn EventUnregister(&self, task: &Task, e: &WaitEntry) {
+        self.ep.queue.EventUnregister(task, e)
+    }
+}
+
+impl SpliceOperations for NetstackSocketOperations {}
+
+impl FileOperations for NetstackSocketOperations {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::NetstackSocketOperations;
+    }
+
+    fn Seekable(&self) -> bool {
+        return false;
+    }
+
+    fn Seek(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _whence: i32,
+        _current: i64,
+        _offset: i64,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ESPIPE));
+    }
+
+    fn ReadDir(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _offset: i64,
+        _serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ENOTDIR));
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        // the caller blocks on EAGAIN
+        let pkt = match self.ep.Dequeue(false) {
+            None => return Err(Error::SysError(SysErr::EAGAIN)),
+            Some(pkt) => pkt,
+        };
+
+        let len = task.CopyDataOutToIovs(&pkt.data, dsts, false)?;
+        return Ok(len as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        srcs: &[IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let peer = match self.ep.state.lock().peer {
+            None => return Err(Error::SysError(SysErr::EDESTADDRREQ)),
+            Some(peer) => peer,
+        };
+
+        let size = IoVec::NumBytes(srcs);
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        return self.Send(peer, &buf.buf[0..len]);
+    }
+
+    fn Append(&self, task: &Task, f: &File, srcs: &[IoVec]) -> Result<(i64, i64)> {
+        let n = self.WriteAt(task, f, srcs, 0, false)?;
+        return Ok((n, 0));
+    }
+
+    fn Fsync(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        _syncType: SyncType,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    fn Flush(&self, _task: &Task, _f: &File) -> Result<()> {
+        return Ok(());
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        let inode = f.Dirent.Inode();
+        return inode.UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, task: &Task, _f: &File, _fd: i32, request: u64, val: u64) -> Result<u64> {
+        match request {
+            LibcConst::TIOCINQ => {
+                let v = self.RecvQueueSize();
+                task.CopyOutObj(&v, val)?;
+                return Ok(0);
+            }
+            LibcConst::TIOCOUTQ => {
+                // the packets are written to the link synchronously
+                let v: i32 = 0;
+                task.CopyOutObj(&v, val)?;
+                return Ok(0);
+            }
+            _ => return Err(Error::SysError(SysErr::ENOTTY)),
+        }
+    }
+
+    fn IterateDir(
+        &self,
+        _task: &Task,
+        _d: &Dirent,
+        _dirCtx: &mut DirCtx,
+        _offset: i32,
+    ) -> (i32, Result<i64>) {
+        return (0, Err(Error::SysError(SysErr::ENOTDIR)));
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
+
+impl SockOperations for NetstackSocketOperations {
+    fn Connect(&self, _task: &Task, socketaddr: &[u8], _blocking: bool) -> Result<i64> {
+        match ExtractAddr(self.Family(), socketaddr)? {
+            None => {
+                // AF_UNSPEC dissolves the association
+                self.ep.state.lock().peer = None;
+            }
+            Some((addr, port)) => {
+                if self.ep.state.lock().v6only && addr.IsIpv4() {
+                    return Err(Error::SysError(SysErr::ENETUNREACH));
+                }
+
+                NETSTACK.AutoBind(&self.ep)?;
+                let port = if self.Protocol() == IPPROTO_UDP {
+                    port
+                } else {
+                    0
+                };
+                self.ep.state.lock().peer = Some((addr, port));
+            }
+        }
+
+        return Ok(0);
+    }
+
+    fn Accept(
+        &self,
+        _task: &Task,
+        _addr: &mut [u8],
+        _addrlen: &mut u32,
+        _flags: i32,
+        _blocking: bool,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    fn Bind(&self, _task: &Task, socketaddr: &[u8]) -> Result<i64> {
+        let (addr, port) = match ExtractAddr(self.Family(), socketaddr)? {
+            None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+            Some(addr) => addr,
+        };
+
+        if self.ep.state.lock().v6only && addr.IsIpv4() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        NETSTACK.Bind(&self.ep, addr, port)?;
+        return Ok(0);
+    }
+
+    fn Listen(&self, _task: &Task, _backlog: i32) -> Result<i64> {
+        return Err(Error::SysError(SysErr::EOPNOTSUPP));
+    }
+
+    fn Shutdown(&self, _task: &Task, how: i32) -> Result<i64> {
+        let mut state = self.ep.state.lock();
+        if state.peer.is_none() {
+            return Err(Error::SysError(SysErr::ENOTCONN));
+        }
+
+        match how {
+            SHUT_RD => state.rclosed = true,
+            SHUT_WR => state.wclosed = true,
+            SHUT_RDWR => {
+                state.rclosed = true;
+                state.wclosed = true;
+            }
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        }
+        core::mem::drop(state);
+
+        self.ep.queue.Notify(READABLE_EVENT | WRITEABLE_EVENT);
+        return Ok(0);
+    }
+
+    fn GetSockOpt(&self, _task: &Task, level: i32, name: i32, opt: &mut [u8]) -> Result<i64> {
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if level == SOL_IPV6 && name as u64 == LibcConst::IPV6_V6ONLY {
+            if self.Family() != AFType::AF_INET6 {
+                return Err(Error::SysError(SysErr::ENOPROTOOPT));
+            }
+
+            let ret = SockOptResult::I32(self.ep.state.lock().v6only as i32);
+            let len = ret.Marsh(opt)?;
+            return Ok(len as i64);
+        }
+
+        if level != SOL_SOCKET {
+            return Err(Error::SysError(SysErr::ENOPROTOOPT));
+        }
+
+        let ret = match name as u64 {
+            LibcConst::SO_TYPE => SockOptResult::I32(SockType::SOCK_DGRAM),
+            LibcConst::SO_DOMAIN => SockOptResult::I32(self.Family()),
+            LibcConst::SO_PROTOCOL => SockOptResult::I32(self.Protocol() as i32),
+            LibcConst::SO_ERROR => SockOptResult::I32(0),
+            LibcConst::SO_REUSEADDR => SockOptResult::I32(0),
+            LibcConst::SO_BROADCAST => {
+                SockOptResult::I32(self.broadcast.load(Ordering::Relaxed) as i32)
+            }
+            LibcConst::SO_RCVBUF => SockOptResult::I32(self.ep.state.lock().rcvBufSize as i32),
+            LibcConst::SO_SNDBUF => SockOptResult::I32(self.ep.state.lock().sndBufSize as i32),
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        };
+
+        let len = ret.Marsh(opt)?;
+        return Ok(len as i64);
+    }
+
+    fn SetSockOpt(&self, _task: &Task, level: i32, name: i32, opt: &[u8]) -> Result<i64> {
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let v = i32::from_ne_bytes([opt[0], opt[1], opt[2], opt[3]]);
+        if level == SOL_IPV6 && name as u64 == LibcConst::IPV6_V6ONLY {
+            if self.Family() != AFType::AF_INET6 || self.Protocol() == IPPROTO_ICMPV6 {
+                return Err(Error::SysError(SysErr::ENOPROTOOPT));
+            }
+
+            // like linux, the option can't change once the socket is bound
+            let mut state = self.ep.state.lock();
+            if state.localPort != 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            state.v6only = v != 0;
+            return Ok(0);
+        }
+
+        if level != SOL_SOCKET {
+            return Err(Error::SysError(SysErr::ENOPROTOOPT));
+        }
+
+        let size = if v < MINIMUM_BUFFER_SIZE as i32 {
+            MINIMUM_BUFFER_SIZE
+        } else if v as usize > MAX_BUFFER_SIZE {
+            MAX_BUFFER_SIZE
+        } else {
+            v as usize
+        };
+
+        match name as u64 {
+            LibcConst::SO_BROADCAST => self.broadcast.store(v != 0, Ordering::Relaxed),
+            // the netstack doesn't share the udp ports between sockets
+            LibcConst::SO_REUSEADDR => (),
+            LibcConst::SO_RCVBUF => self.ep.state.lock().rcvBufSize = size,
+            LibcConst::SO_SNDBUF => self.ep.state.lock().sndBufSize = size,
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        }
+
+        return Ok(0);
+    }
+
+    fn GetSockName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let (addr, port) = {
+            let state = self.ep.state.lock();
+            (state.localAddr, state.localPort)
+        };
+
+        let addr = ToSockAddr(self.Family(), addr, port);
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn GetPeerName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let (addr, port) = match self.ep.state.lock().peer {
+            None => return Err(Error::SysError(SysErr::ENOTCONN)),
+            Some(peer) => peer,
+        };
+
+        let addr = ToSockAddr(self.Family(), addr, port);
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn RecvMsg(
+        &self,
+        task: &Task,
+        dsts: &mut [IoVec],
+        flags: i32,
+        deadline: Option<Time>,
+        senderRequested: bool,
+        _controlDataLen: usize,
+    ) -> Result<(i64, i32, Option<(SockAddr, usize)>, Vec<u8>)> {
+        let trunc = flags & MsgType::MSG_TRUNC != 0;
+        let peek = flags & MsgType::MSG_PEEK != 0;
+        let dontWait = flags & MsgType::MSG_DONTWAIT != 0;
+
+        let pkt = self.BlockingRecv(task, peek, dontWait, deadline)?;
+
+        let size = IoVec::NumBytes(dsts);
+        let mut msgFlags = 0;
+        if pkt.data.len() > size {
+            msgFlags |= MsgType::MSG_TRUNC;
+        }
+
+        let mut len = task.CopyDataOutToIovs(&pkt.data, dsts, false)? as i64;
+        if trunc {
+            len = pkt.data.len() as i64;
+        }
+
+        let sender = if senderRequested {
+            let addr = ToSockAddr(self.Family(), pkt.srcAddr, pkt.srcPort);
+            let addrLen = addr.Len();
+            Some((addr, addrLen))
+        } else {
+            None
+        };
+
+        return Ok((len, msgFlags, sender, Vec::new()));
+    }
+
+    fn SendMsg(
+        &self,
+        task: &Task,
+        srcs: &[IoVec],
+        _flags: i32,
+        msgHdr: &mut MsgHdr,
+        _deadline: Option<Time>,
+    ) -> Result<i64> {
+        let dst = if msgHdr.msgName != 0 && msgHdr.nameLen > 0 {
+            // sendSingleMsg has copied the name in
+            let sockaddr = unsafe {
+                slice::from_raw_parts(msgHdr.msgName as *const u8, msgHdr.nameLen as usize)
+            };
+            match ExtractAddr(self.Family(), sockaddr)? {
+                None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+                Some(dst) => dst,
+            }
+        } else {
+            match self.ep.state.lock().peer {
+                None => return Err(Error::SysError(SysErr::EDESTADDRREQ)),
+                Some(peer) => peer,
+            }
+        };
+
+        let size = IoVec::NumBytes(srcs);
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        return self.Send(dst, &buf.buf[0..len]);
+    }
+
+    fn SetRecvTimeout(&self, ns: i64) {
+        self.recv.store(ns, Ordering::Relaxed)
+    }
+
+    fn SetSendTimeout(&self, ns: i64) {
+        self.send.store(ns, Ordering::Relaxed)
+    }
+
+    fn RecvTimeout(&self) -> i64 {
+        return self.recv.load(Ordering::Relaxed);
+    }
+
+    fn SendTimeout(&self) -> i64 {
+        return self.send.load(Ordering::Relaxed);
+    }
+
+    fn State(&self) -> u32 {
+        // TCP_CLOSE, linux reports the udp socket state with the tcp states
+        return 7;
+    }
+
+    fn Type(&self) -> (i32, i32, i32) {
+        return (self.Family(), SockType::SOCK_DGRAM, self.Protocol() as i32);
+    }
+}
+
+// NetstackProvider serves the AF_INET and AF_INET6 tcp, udp and icmp echo
+// sockets when the user space netstack is enabled, the other sockets fall
+// through to hostinet.
+pub struct NetstackProvider {
+    pub family: i32,
+}
+
+impl Provider for NetstackProvider {
+    fn Socket(&self, task: &Task, stype: i32, protocol: i32) -> Result<Option<Arc<File>>> {
+        if !NetStack::Enabled() {
+            return Ok(None);
+        }
+
+        let nonblocking = stype & SocketFlags::SOCK_NONBLOCK != 0;
+        let stype = stype & SocketType::SOCK_TYPE_MASK;
+        if stype == SockType::SOCK_STREAM {
+            if protocol != 0 && protocol as u64 != LibcConst::IPPROTO_TCP {
+                return Err(Error::SysError(SysErr::EPROTONOSUPPORT));
+            }
+
+            let ep = TcpEndpoint::New(self.family, DEFAULT_BUFFER_SIZE, DEFAULT_BUFFER_SIZE);
+            let file = NewNetstackTcpSocket(task, ep, nonblocking)?;
+            return Ok(Some(Arc::new(file)));
+        }
+
+        if stype != SockType::SOCK_DGRAM {
+            return Ok(None);
+        }
+
+        let protocol = match protocol as u64 {
+            0 | LibcConst::IPPROTO_UDP => IPPROTO_UDP,
+            LibcConst::IPPROTO_ICMP if self.family == AFType::AF_INET => IPPROTO_ICMP,
+            LibcConst::IPPROTO_ICMPV6 if self.family == AFType::AF_INET6 => IPPROTO_ICMPV6,
+            _ => return Err(Error::SysError(SysErr::EPROTONOSUPPORT)),
+        };
+
+        let file = NewNetstackSocket(task, self.family, protocol, nonblocking)?;
+        return Ok(Some(Arc::new(file)));
+    }
+
+    fn Pair(
+        &self,
+        _task: &Task,
+        _stype: i32,
+        _protocol: i32,
+    ) -> Result<Option<(Arc<File>, Arc<File>)>> {
+        return Ok(None);
+    }
+}
+
+pub fn Init() {
+    for family in [AFType::AF_INET, AFType::AF_INET6].iter() {
+        FAMILIAES
+            .write()
+            .RegisterProvider(*family, Box::new(NetstackProvider { family: *family }))
+    }
+}
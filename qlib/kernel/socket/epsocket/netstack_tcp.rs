@@ -0,0 +1,688 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ops::Deref;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicI64;
+use core::sync::atomic::Ordering;
+
+use super::super::super::super::common::*;
+use super::super::super::super::linux::netfilter::SO_ORIGINAL_DST;
+use super::super::super::super::linux::socket::*;
+use super::super::super::super::linux_def::*;
+use super::super::super::fs::attr::*;
+use super::super::super::fs::dentry::*;
+use super::super::super::fs::dirent::*;
+use super::super::super::fs::file::*;
+use super::super::super::fs::flags::*;
+use super::super::super::kernel::fd_table::*;
+use super::super::super::kernel::kernel::GetKernel;
+use super::super::super::kernel::time::*;
+use super::super::super::kernel::timer::MonotonicNow;
+use super::super::super::kernel::waiter::*;
+use super::super::super::memmgr::vma::MMappable;
+use super::super::super::task::*;
+use super::super::super::tcpip::header::IPPROTO_TCP;
+use super::super::super::tcpip::nat::ConnTuple;
+use super::super::super::tcpip::stack::*;
+use super::super::super::tcpip::tcp::*;
+use super::super::super::tcpip::tcpip::*;
+use super::super::socket::*;
+use super::super::socketopts::*;
+use super::super::unix::transport::unix::SockType;
+use super::super::unix::unix::NewUnixSocketDummyDirent;
+use super::epsocket::{SockOptResult, SIZEOF_I32};
+use super::netstack_socket::*;
+
+pub fn NewNetstackTcpSocket(task: &Task, ep: TcpEndpoint, nonblocking: bool) -> Result<File> {
+    let dirent = NewUnixSocketDummyDirent(task, SOCKET_DEVICE.clone())?;
+    let fileFlags = FileFlags {
+        Read: true,
+        Write: true,
+        NonBlocking: nonblocking,
+        ..Default::default()
+    };
+
+    let file = File::NewSocket(
+        &dirent,
+        &fileFlags,
+        NetstackTcpSocketOperations::New(ep).into(),
+    );
+
+    GetKernel().sockets.AddSocket(&file);
+
+    return Ok(file);
+}
+
+// NetstackTcpSocketOperations is the tcp socket served by the user space netstack
+pub struct NetstackTcpSocketOperationsIntern {
+    pub ep: TcpEndpoint,
+    pub noDelay: AtomicBool,
+    pub keepAlive: AtomicBool,
+    pub recv: AtomicI64,
+    pub send: AtomicI64,
+}
+
+impl Drop for NetstackTcpSocketOperationsIntern {
+    fn drop(&mut self) {
+        NETSTACK.TcpClose(&self.ep);
+    }
+}
+
+#[derive(Clone)]
+pub struct NetstackTcpSocketOperations(Arc<NetstackTcpSocketOperationsIntern>);
+
+impl Deref for NetstackTcpSocketOperations {
+    type Target = Arc<NetstackTcpSocketOperationsIntern>;
+
+    fn deref(&self) -> &Arc<NetstackTcpSocketOperationsIntern> {
+        &self.0
+    }
+}
+
+impl NetstackTcpSocketOperations {
+    pub fn New(ep: TcpEndpoint) -> Self {
+        return Self(Arc::new(NetstackTcpSocketOperationsIntern {
+            ep: ep,
+            noDelay: AtomicBool::new(false),
+            keepAlive: AtomicBool::new(false),
+            recv: AtomicI64::new(0),
+            send: AtomicI64::new(0),
+        }));
+    }
+
+    // OriginalDst answers SO_ORIGINAL_DST, the destination the connection
+    // had before the nat redirected it to the socket. SOL_IP serves the ipv4
+    // connections and SOL_IPV6 the ipv6 ones.
+    pub fn OriginalDst(&self, level: i32, opt: &mut [u8]) -> Result<i64> {
+        let tuple = {
+            let state = self.ep.state.lock();
+            ConnTuple {
+                protocol: IPPROTO_TCP,
+                src: state.localAddr,
+                srcPort: state.localPort,
+                dst: state.remoteAddr,
+                dstPort: state.remotePort,
+            }
+        };
+
+        let family = if level == SOL_IP {
+            AFType::AF_INET
+        } else {
+            AFType::AF_INET6
+        };
+        if tuple.src.IsIpv4() != (family == AFType::AF_INET) {
+            return Err(Error::SysError(SysErr::ENOENT));
+        }
+
+        let orig = NETSTACK
+            .lock()
+            .netfilter
+            .OriginalDst(&tuple, MonotonicNow());
+        let (addr, port) = match orig {
+            None => return Err(Error::SysError(SysErr::ENOENT)),
+            Some(orig) => orig,
+        };
+
+        let addr = ToSockAddr(family, addr, port);
+        addr.Marsh(opt, addr.Len())?;
+        return Ok(addr.Len() as i64);
+    }
+
+    pub fn Block(&self, task: &Task, mask: EventMask, deadline: Option<Time>) -> Result<()> {
+        let general = task.blocker.generalEntry.clone();
+        self.EventRegister(task, &general, mask);
+        defer!(self.EventUnregister(task, &general));
+
+        if self.ep.Readiness(mask) != 0 {
+            return Ok(());
+        }
+
+        match task.blocker.BlockWithMonoTimer(true, deadline) {
+            Err(Error::SysError(SysErr::ETIMEDOUT)) => {
+                return Err(Error::SysError(SysErr::EAGAIN));
+            }
+            Err(Error::ErrInterrupted) => {
+                return Err(Error::SysError(SysErr::ERESTARTSYS));
+            }
+            Err(e) => {
+                return Err(e);
+            }
+            _ => return Ok(()),
+        }
+    }
+
+    pub fn Recv(
+        &self,
+        task: &Task,
+        len: usize,
+        peek: bool,
+        dontWait: bool,
+        deadline: Option<Time>,
+    ) -> Result<Vec<u8>> {
+        loop {
+            match NETSTACK.TcpRecv(&self.ep, len, peek) {
+                Err(Error::SysError(SysErr::EAGAIN)) => {
+                    if dontWait {
+                        return Err(Error::SysError(SysErr::EAGAIN));
+                    }
+                }
+                res => return res,
+            }
+
+            self.Block(task, EVENT_READ, deadline)?;
+        }
+    }
+
+    pub fn Send(
+        &self,
+        task: &Task,
+        data: &[u8],
+        dontWait: bool,
+        deadline: Option<Time>,
+    ) -> Result<usize> {
+        let mut total = 0;
+        while total < data.len() {
+            match NETSTACK.TcpSend(&self.ep, &data[total..]) {
+                Err(Error::SysError(SysErr::EAGAIN)) => {
+                    if dontWait {
+                        if total > 0 {
+                            return Ok(total);
+                        }
+                        return Err(Error::SysError(SysErr::EAGAIN));
+                    }
+                }
+                Err(e) => {
+                    if total > 0 {
+                        return Ok(total);
+                    }
+                    return Err(e);
+                }
+                Ok(n) => {
+                    total += n;
+                    continue;
+                }
+            }
+
+            match self.Block(task, EVENT_OUT | EVENT_ERR | EVENT_HUP, deadline) {
+                Err(e) => {
+                    if total > 0 {
+                        return Ok(total);
+                    }
+                    return Err(e);
+                }
+                Ok(()) => (),
+            }
+        }
+
+        return Ok(total);
+    }
+}
+
+impl Waitable for NetstackTcpSocketOperations {
+    fn Readiness(&self, _task: &Task, mask: EventMask) -> EventMask {
+        return self.ep.Readiness(mask);
+    }
+
+    fn EventRegister(&self, task: &Task, e: &WaitEntry, mask: EventMask) {
+        self.ep.queue.EventRegister(task, e, mask)
+    }
+
+    fn EventUnregister(&self, task: &Task, e: &WaitEntry) {
+        self.ep.queue.EventUnregister(task, e)
+    }
+}
+
+impl SpliceOperations for NetstackTcpSocketOperations {}
+
+impl FileOperations for NetstackTcpSocketOperations {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+
+    fn FopsType(&self) -> FileOpsType {
+        return FileOpsType::NetstackTcpSocketOperations;
+    }
+
+    fn Seekable(&self) -> bool {
+        return false;
+    }
+
+    fn Seek(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _whence: i32,
+        _current: i64,
+        _offset: i64,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ESPIPE));
+    }
+
+    fn ReadDir(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _offset: i64,
+        _serializer: &mut DentrySerializer,
+    ) -> Result<i64> {
+        return Err(Error::SysError(SysErr::ENOTDIR));
+    }
+
+    fn ReadAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        dsts: &mut [IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let size = IoVec::NumBytes(dsts);
+        if size == 0 {
+            return Ok(0);
+        }
+
+        // the caller blocks on EAGAIN
+        let data = NETSTACK.TcpRecv(&self.ep, size, false)?;
+        let len = task.CopyDataOutToIovs(&data, dsts, false)?;
+        return Ok(len as i64);
+    }
+
+    fn WriteAt(
+        &self,
+        task: &Task,
+        _f: &File,
+        srcs: &[IoVec],
+        _offset: i64,
+        _blocking: bool,
+    ) -> Result<i64> {
+        let size = IoVec::NumBytes(srcs);
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        let n = NETSTACK.TcpSend(&self.ep, &buf.buf[0..len])?;
+        return Ok(n as i64);
+    }
+
+    fn Append(&self, task: &Task, f: &File, srcs: &[IoVec]) -> Result<(i64, i64)> {
+        let n = self.WriteAt(task, f, srcs, 0, false)?;
+        return Ok((n, 0));
+    }
+
+    fn Fsync(
+        &self,
+        _task: &Task,
+        _f: &File,
+        _start: i64,
+        _end: i64,
+        _syncType: SyncType,
+    ) -> Result<()> {
+        return Err(Error::SysError(SysErr::EINVAL));
+    }
+
+    fn Flush(&self, _task: &Task, _f: &File) -> Result<()> {
+        return Ok(());
+    }
+
+    fn UnstableAttr(&self, task: &Task, f: &File) -> Result<UnstableAttr> {
+        let inode = f.Dirent.Inode();
+        return inode.UnstableAttr(task);
+    }
+
+    fn Ioctl(&self, task: &Task, _f: &File, _fd: i32, request: u64, val: u64) -> Result<u64> {
+        let v = {
+            let state = self.ep.state.lock();
+            match request {
+                LibcConst::TIOCINQ => state.rcvBuf.len() as i32,
+                LibcConst::TIOCOUTQ => state.sndBuf.len() as i32,
+                _ => return Err(Error::SysError(SysErr::ENOTTY)),
+            }
+        };
+
+        task.CopyOutObj(&v, val)?;
+        return Ok(0);
+    }
+
+    fn IterateDir(
+        &self,
+        _task: &Task,
+        _d: &Dirent,
+        _dirCtx: &mut DirCtx,
+        _offset: i32,
+    ) -> (i32, Result<i64>) {
+        return (0, Err(Error::SysError(SysErr::ENOTDIR)));
+    }
+
+    fn Mappable(&self) -> Result<MMappable> {
+        return Err(Error::SysError(SysErr::ENODEV));
+    }
+}
+
+impl SockOperations for NetstackTcpSocketOperations {
+    fn Connect(&self, task: &Task, socketaddr: &[u8], blocking: bool) -> Result<i64> {
+        let (addr, port) = match ExtractAddr(self.ep.family, socketaddr)? {
+            None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+            Some(addr) => addr,
+        };
+
+        if self.ep.state.lock().v6only && addr.IsIpv4() {
+            return Err(Error::SysError(SysErr::ENETUNREACH));
+        }
+
+        NETSTACK.TcpConnect(&self.ep, addr, port)?;
+        if !blocking {
+            return Err(Error::SysError(SysErr::EINPROGRESS));
+        }
+
+        loop {
+            {
+                let mut state = self.ep.state.lock();
+                if state.state != TcpState::SynSent {
+                    if let Some(err) = state.error.take() {
+                        return Err(Error::SysError(err));
+                    }
+                    return Ok(0);
+                }
+            }
+
+            self.Block(task, EVENT_OUT | EVENT_ERR | EVENT_HUP, None)?;
+        }
+    }
+
+    fn Accept(
+        &self,
+        task: &Task,
+        addr: &mut [u8],
+        addrlen: &mut u32,
+        flags: i32,
+        blocking: bool,
+    ) -> Result<i64> {
+        let ep = loop {
+            match NETSTACK.TcpAccept(&self.ep) {
+                Err(Error::SysError(SysErr::EAGAIN)) => {
+                    if !blocking {
+                        return Err(Error::SysError(SysErr::EWOULDBLOCK));
+                    }
+                }
+                Err(e) => return Err(e),
+                Ok(ep) => break ep,
+            }
+
+            self.Block(task, EVENT_IN, None)?;
+        };
+
+        let nonblocking = flags & SocketFlags::SOCK_NONBLOCK != 0;
+        let ns = NewNetstackTcpSocket(task, ep, nonblocking)?;
+        ns.flags.lock().0.NonSeekable = true;
+
+        if *addrlen != 0 {
+            *addrlen = ns.FileOp.GetPeerName(task, addr)? as u32;
+        }
+
+        let fdFlags = FDFlags {
+            CloseOnExec: flags & SocketFlags::SOCK_CLOEXEC != 0,
+        };
+
+        let fd = task.NewFDFrom(0, &ns, &fdFlags)?;
+        return Ok(fd as i64);
+    }
+
+    fn Bind(&self, _task: &Task, socketaddr: &[u8]) -> Result<i64> {
+        let (addr, port) = match ExtractAddr(self.ep.family, socketaddr)? {
+            None => return Err(Error::SysError(SysErr::EAFNOSUPPORT)),
+            Some(addr) => addr,
+        };
+
+        if self.ep.state.lock().v6only && addr.IsIpv4() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        NETSTACK.TcpBind(&self.ep, addr, port)?;
+        return Ok(0);
+    }
+
+    fn Listen(&self, _task: &Task, backlog: i32) -> Result<i64> {
+        let backlog = if backlog <= 0 { 1 } else { backlog as usize };
+        NETSTACK.TcpListen(&self.ep, backlog)?;
+        return Ok(0);
+    }
+
+    fn Shutdown(&self, _task: &Task, how: i32) -> Result<i64> {
+        let (read, write) = match how {
+            SHUT_RD => (true, false),
+            SHUT_WR => (false, true),
+            SHUT_RDWR => (true, true),
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        };
+
+        NETSTACK.TcpShutdown(&self.ep, read, write)?;
+        return Ok(0);
+    }
+
+    fn GetSockOpt(&self, _task: &Task, level: i32, name: i32, opt: &mut [u8]) -> Result<i64> {
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if name == SO_ORIGINAL_DST && (level == SOL_IP || level == SOL_IPV6) {
+            return self.OriginalDst(level, opt);
+        }
+
+        let ret = match level {
+            SOL_SOCKET => match name as u64 {
+                LibcConst::SO_TYPE => SockOptResult::I32(SockType::SOCK_STREAM),
+                LibcConst::SO_DOMAIN => SockOptResult::I32(self.ep.family),
+                LibcConst::SO_PROTOCOL => SockOptResult::I32(IPPROTO_TCP as i32),
+                LibcConst::SO_ERROR => SockOptResult::I32(self.ep.TakeError().unwrap_or(0)),
+                LibcConst::SO_REUSEADDR => {
+                    SockOptResult::I32(self.ep.state.lock().reuseAddr as i32)
+                }
+                LibcConst::SO_KEEPALIVE => {
+                    SockOptResult::I32(self.keepAlive.load(Ordering::Relaxed) as i32)
+                }
+                LibcConst::SO_RCVBUF => SockOptResult::I32(self.ep.state.lock().rcvBufSize as i32),
+                LibcConst::SO_SNDBUF => SockOptResult::I32(self.ep.state.lock().sndBufSize as i32),
+                LibcConst::SO_ACCEPTCONN => {
+                    SockOptResult::I32((self.ep.state.lock().state == TcpState::Listen) as i32)
+                }
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            SOL_TCP => match name as u64 {
+                LibcConst::TCP_NODELAY => {
+                    SockOptResult::I32(self.noDelay.load(Ordering::Relaxed) as i32)
+                }
+                LibcConst::TCP_MAXSEG => SockOptResult::I32(self.ep.state.lock().mss as i32),
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            SOL_IPV6 if self.ep.family == AFType::AF_INET6 => match name as u64 {
+                LibcConst::IPV6_V6ONLY => SockOptResult::I32(self.ep.state.lock().v6only as i32),
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        };
+
+        let len = ret.Marsh(opt)?;
+        return Ok(len as i64);
+    }
+
+    fn SetSockOpt(&self, _task: &Task, level: i32, name: i32, opt: &[u8]) -> Result<i64> {
+        if opt.len() < SIZEOF_I32 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let v = i32::from_ne_bytes([opt[0], opt[1], opt[2], opt[3]]);
+        let size = if v < MINIMUM_BUFFER_SIZE as i32 {
+            MINIMUM_BUFFER_SIZE
+        } else if v as usize > MAX_BUFFER_SIZE {
+            MAX_BUFFER_SIZE
+        } else {
+            v as usize
+        };
+
+        match level {
+            SOL_SOCKET => match name as u64 {
+                LibcConst::SO_REUSEADDR => self.ep.state.lock().reuseAddr = v != 0,
+                // todo: send the keepalive probes
+                LibcConst::SO_KEEPALIVE => self.keepAlive.store(v != 0, Ordering::Relaxed),
+                LibcConst::SO_RCVBUF => self.ep.state.lock().rcvBufSize = size,
+                LibcConst::SO_SNDBUF => self.ep.state.lock().sndBufSize = size,
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            SOL_TCP => match name as u64 {
+                // the netstack doesn't do nagle, the segments are always sent at once
+                LibcConst::TCP_NODELAY => self.noDelay.store(v != 0, Ordering::Relaxed),
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            SOL_IPV6 if self.ep.family == AFType::AF_INET6 => match name as u64 {
+                LibcConst::IPV6_V6ONLY => {
+                    // like linux, the option can't change once the socket is bound
+                    let mut state = self.ep.state.lock();
+                    if state.bound {
+                        return Err(Error::SysError(SysErr::EINVAL));
+                    }
+                    state.v6only = v != 0;
+                }
+                _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+            },
+            _ => return Err(Error::SysError(SysErr::ENOPROTOOPT)),
+        }
+
+        return Ok(0);
+    }
+
+    fn GetSockName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let (addr, port) = {
+            let state = self.ep.state.lock();
+            (state.localAddr, state.localPort)
+        };
+
+        let addr = ToSockAddr(self.ep.family, addr, port);
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn GetPeerName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        let (addr, port) = {
+            let state = self.ep.state.lock();
+            if !state.hasConnected || state.state == TcpState::Closed {
+                return Err(Error::SysError(SysErr::ENOTCONN));
+            }
+            (state.remoteAddr, state.remotePort)
+        };
+
+        let addr = ToSockAddr(self.ep.family, addr, port);
+        let len = core::cmp::min(addr.Len(), socketaddr.len());
+        addr.Marsh(socketaddr, len)?;
+        return Ok(addr.Len() as i64);
+    }
+
+    fn RecvMsg(
+        &self,
+        task: &Task,
+        dsts: &mut [IoVec],
+        flags: i32,
+        deadline: Option<Time>,
+        senderRequested: bool,
+        _controlDataLen: usize,
+    ) -> Result<(i64, i32, Option<(SockAddr, usize)>, Vec<u8>)> {
+        let peek = flags & MsgType::MSG_PEEK != 0;
+        let dontWait = flags & MsgType::MSG_DONTWAIT != 0;
+        let waitAll = flags & MsgType::MSG_WAITALL != 0;
+
+        let size = IoVec::NumBytes(dsts);
+        let mut buf = Vec::with_capacity(size);
+        loop {
+            let data = match self.Recv(task, size - buf.len(), peek, dontWait, deadline) {
+                Err(e) => {
+                    if buf.len() > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+                Ok(data) => data,
+            };
+
+            if data.len() == 0 {
+                break;
+            }
+
+            buf.extend_from_slice(&data);
+            if !waitAll || peek || buf.len() == size {
+                break;
+            }
+        }
+
+        let len = task.CopyDataOutToIovs(&buf, dsts, false)?;
+        let sender = if senderRequested {
+            let (addr, port) = {
+                let state = self.ep.state.lock();
+                (state.remoteAddr, state.remotePort)
+            };
+            let addr = ToSockAddr(self.ep.family, addr, port);
+            let addrLen = addr.Len();
+            Some((addr, addrLen))
+        } else {
+            None
+        };
+
+        return Ok((len as i64, 0, sender, Vec::new()));
+    }
+
+    fn SendMsg(
+        &self,
+        task: &Task,
+        srcs: &[IoVec],
+        flags: i32,
+        _msgHdr: &mut MsgHdr,
+        deadline: Option<Time>,
+    ) -> Result<i64> {
+        let dontWait = flags & MsgType::MSG_DONTWAIT != 0;
+        let size = IoVec::NumBytes(srcs);
+        let mut buf = DataBuff::New(size);
+        let len = task.CopyDataInFromIovs(&mut buf.buf, srcs, true)?;
+        let n = self.Send(task, &buf.buf[0..len], dontWait, deadline)?;
+        return Ok(n as i64);
+    }
+
+    fn SetRecvTimeout(&self, ns: i64) {
+        self.recv.store(ns, Ordering::Relaxed)
+    }
+
+    fn SetSendTimeout(&self, ns: i64) {
+        self.send.store(ns, Ordering::Relaxed)
+    }
+
+    fn RecvTimeout(&self) -> i64 {
+        return self.recv.load(Ordering::Relaxed);
+    }
+
+    fn SendTimeout(&self) -> i64 {
+        return self.send.load(Ordering::Relaxed);
+    }
+
+    fn State(&self) -> u32 {
+        return self.ep.state.lock().state as u32;
+    }
+
+    fn Type(&self) -> (i32, i32, i32) {
+        return (self.ep.family, SockType::SOCK_STREAM, IPPROTO_TCP as i32);
+    }
+}
@@ -127,7 +127,7 @@ pub fn newAsyncSocketFile(
         addr,
     )?;
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &FileFlags {
             NonBlocking: nonblock,
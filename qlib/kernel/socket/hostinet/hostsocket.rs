@@ -44,6 +44,7 @@ use super::super::super::tcpip::tcpip::*;
 use super::super::super::Kernel;
 use super::super::super::Kernel::HostSpace;
 use super::super::super::IOURING;
+use super::super::bpf::*;
 use super::super::control::*;
 use super::super::socket::*;
 use super::socket::*;
@@ -62,7 +63,7 @@ pub fn newHostSocketFile(
     let hostiops = iops.HostInodeOp().unwrap();
     let s = HostSocketOperations::New(family, fd, stype, hostiops.Queue(), hostiops.clone(), addr)?;
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &FileFlags {
             NonBlocking: nonblock,
@@ -700,6 +701,30 @@ impl SockOperations for HostSocketOperations {
             }
         }
 
+        // the sock_fprog points to the filter in the application's memory, the
+        // host gets a validated copy of it instead
+        if (level as u64) == LibcConst::SOL_SOCKET && (name as u64) == LibcConst::SO_ATTACH_FILTER {
+            let prog = BpfProgram::CopyIn(task, opt)?;
+            let fprog = SockFprog {
+                len: prog.insns.len() as u16,
+                filter: &prog.insns[0] as *const _ as u64,
+            };
+
+            let res = Kernel::HostSpace::SetSockOpt(
+                self.fd,
+                level,
+                name,
+                &fprog as *const _ as u64,
+                SIZEOF_SOCK_FPROG as u32,
+            );
+
+            if res < 0 {
+                return Err(Error::SysError(-res as i32));
+            }
+
+            return Ok(res);
+        }
+
         let optLen = opt.len();
         let res = if optLen == 0 {
             Kernel::HostSpace::SetSockOpt(
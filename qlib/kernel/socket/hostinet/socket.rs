@@ -97,7 +97,7 @@ fn newSocketFile(
         addr,
     )?;
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &FileFlags {
             NonBlocking: nonblock,
@@ -2563,7 +2563,16 @@ impl Provider for SocketProvider {
 }
 
 pub fn Init() {
-    for family in [AFType::AF_INET, AFType::AF_INET6, AFType::AF_NETLINK].iter() {
+    // the raw and packet sockets come here after epsocket::netstack_raw checks
+    // CAP_NET_RAW, the host sockets are in the sandbox's network namespace
+    for family in [
+        AFType::AF_INET,
+        AFType::AF_INET6,
+        AFType::AF_NETLINK,
+        AFType::AF_PACKET,
+    ]
+    .iter()
+    {
         FAMILIAES
             .write()
             .RegisterProvider(*family, Box::new(SocketProvider { family: *family }))
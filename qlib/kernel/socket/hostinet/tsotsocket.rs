@@ -16,6 +16,7 @@ use crate::qlib::kernel::socket::epsocket::epsocket::SIZEOF_I32;
 use crate::qlib::kernel::socket::hostinet::tsot_mgr::QIPv4Addr;
 use crate::qlib::kernel::socket::unix::transport::unix::SockType;
 use crate::qlib::mutex::*;
+use alloc::collections::btree_map::BTreeMap;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
 use alloc::vec::Vec;
@@ -41,6 +42,10 @@ use super::super::super::fs::dirent::*;
 use super::super::super::fs::file::*;
 use super::super::super::fs::flags::*;
 use super::super::super::fs::host::hostinodeop::*;
+use super::super::super::fs::procfs::net::TCP_CLOSE;
+use super::super::super::fs::procfs::net::TCP_ESTABLISHED;
+use super::super::super::fs::procfs::net::TCP_LISTEN;
+use super::super::super::fs::procfs::net::TCP_SYN_SENT;
 use super::super::super::kernel::fd_table::*;
 use super::super::super::kernel::kernel::GetKernel;
 use super::super::super::kernel::time::*;
@@ -62,6 +67,22 @@ use crate::qlib::kernel::socket::hostinet::loopbacksocket::*;
 use crate::qlib::kernel::socket::hostinet::socket::HostIoctlIFConf;
 use crate::qlib::kernel::socket::hostinet::socket::HostIoctlIFReq;
 
+// IsTlsSocket returns whether the host socket is a unix socket, the pod end of
+// a socketpair the qlet relays through tls, instead of a tcp socket.
+fn IsTlsSocket(fd: i32) -> bool {
+    let mut domain: i32 = 0;
+    let mut len: u32 = SocketSize::SIZEOF_INT32 as u32;
+    let ret = HostSpace::GetSockOpt(
+        fd,
+        LibcConst::SOL_SOCKET as _,
+        LibcConst::SO_DOMAIN as _,
+        &mut domain as *mut _ as u64,
+        &mut len as *mut _ as u64,
+    );
+
+    return ret >= 0 && domain == AFType::AF_UNIX;
+}
+
 pub fn NewTsotSocketFile(
     task: &Task,
     family: i32,
@@ -91,7 +112,7 @@ pub fn NewTsotSocketFile(
         remoteAddr,
     )?;
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &FileFlags {
             NonBlocking: nonblock,
@@ -187,6 +208,12 @@ pub struct TsotSocketOperationsIntern {
     pub hostops: HostInodeOp,
     pub reusePort: AtomicBool,
     passInq: AtomicBool,
+
+    // tls is set when the host socket is the pod end of the unix socketpair
+    // the qlet relays through tls. It has no tcp and ip options, they are
+    // kept in tlsOpts.
+    pub tls: bool,
+    pub tlsOpts: QMutex<BTreeMap<(i32, i32), Vec<u8>>>,
 }
 
 #[derive(Clone)]
@@ -309,12 +336,63 @@ impl TsotSocketOperations {
             hostops: hostops,
             passInq: AtomicBool::new(false),
             reusePort: AtomicBool::new(false),
+            tls: IsTlsSocket(fd),
+            tlsOpts: QMutex::new(BTreeMap::new()),
         };
 
         let ret = Self(Arc::new(ret));
         return Ok(ret);
     }
 
+    // TlsState is the tcp state of a tls socket.
+    pub fn TlsState(&self) -> u32 {
+        if self.listening.load(Ordering::Relaxed) {
+            return TCP_LISTEN;
+        }
+
+        match self.SocketType() {
+            TsotSocketType::Uring(_) => return TCP_ESTABLISHED,
+            TsotSocketType::Connecting => return TCP_SYN_SENT,
+            _ => return TCP_CLOSE,
+        }
+    }
+
+    fn TlsGetSockOpt(&self, level: i32, name: i32, opt: &mut [u8]) -> Result<i64> {
+        let val = if level as u64 == LibcConst::SOL_TCP && name as u64 == LibcConst::TCP_INFO {
+            let mut info = TCPInfo::default();
+            info.State = self.TlsState() as u8;
+            let addr = &info as *const _ as *const u8;
+            unsafe { core::slice::from_raw_parts(addr, SocketSize::SIZEOF_TCPINFO) }.to_vec()
+        } else if level as u64 == LibcConst::SOL_TCP && name as u64 == LibcConst::TCP_INQ {
+            let passInq = self.passInq.load(Ordering::SeqCst) as i32;
+            passInq.to_ne_bytes().to_vec()
+        } else {
+            match self.tlsOpts.lock().get(&(level, name)) {
+                None => 0i32.to_ne_bytes().to_vec(),
+                Some(v) => v.clone(),
+            }
+        };
+
+        let len = val.len().min(opt.len());
+        opt[..len].copy_from_slice(&val[..len]);
+        return Ok(len as i64);
+    }
+
+    // TlsSockName is the address of a tls socket, the one it is bound to or
+    // the pod address.
+    fn TlsSockName(&self, socketaddr: &mut [u8]) -> Result<i64> {
+        let mut ip: QIPv4Addr = self.bindIp.load(Ordering::Relaxed).into();
+        if ip.IsAny() {
+            ip = SHARESPACE.tsotSocketMgr.LocalIpAddr();
+        }
+
+        let addr = QIPv4Endpoint::New(ip, self.bindPort.load(Ordering::Relaxed)).ToSockAddr();
+        let v = addr.ToVec()?;
+        let len = addr.Len().min(socketaddr.len());
+        socketaddr[..len].copy_from_slice(&v[..len]);
+        return Ok(len as i64);
+    }
+
     pub fn Produce(&self, task: &Task, count: usize, iovs: &mut SocketBufIovs) -> Result<()> {
         let sockBufType = self.socketType.lock().clone();
         match sockBufType {
@@ -1168,6 +1246,12 @@ impl SockOperations for TsotSocketOperations {
             _ => (),
         };
 
+        if self.tls
+            && (level as u64 == LibcConst::SOL_TCP || level as u64 == LibcConst::SOL_IP)
+        {
+            return self.TlsGetSockOpt(level, name, opt);
+        }
+
         if (level as u64) == LibcConst::SOL_TCP {
             match name as u64 {                
                 LibcConst::TCP_INQ => {
@@ -1308,6 +1392,13 @@ impl SockOperations for TsotSocketOperations {
             }
         }
 
+        if self.tls
+            && (level as u64 == LibcConst::SOL_TCP || level as u64 == LibcConst::SOL_IP)
+        {
+            self.tlsOpts.lock().insert((level, name), opt.to_vec());
+            return Ok(0);
+        }
+
         let optLen = opt.len();
         let res = if optLen == 0 {
             Kernel::HostSpace::SetSockOpt(
@@ -1335,6 +1426,10 @@ impl SockOperations for TsotSocketOperations {
     }
 
     fn GetSockName(&self, _task: &Task, socketaddr: &mut [u8]) -> Result<i64> {
+        if self.tls {
+            return self.TlsSockName(socketaddr);
+        }
+
         let len = socketaddr.len() as i32;
 
         let res = Kernel::HostSpace::GetSockName(
@@ -1619,6 +1714,10 @@ impl SockOperations for TsotSocketOperations {
     }
 
     fn State(&self) -> u32 {
+        if self.tls {
+            return self.TlsState();
+        }
+
         let mut info = TCPInfo::default();
         let mut len = SocketSize::SIZEOF_TCPINFO;
 
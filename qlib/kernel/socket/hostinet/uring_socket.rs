@@ -83,7 +83,7 @@ pub fn newUringSocketFile(
         addr,
     )?;
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &FileFlags {
             NonBlocking: nonblock,
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod bpf;
 pub mod buffer;
 pub mod control;
 pub mod epsocket;
@@ -22,6 +23,11 @@ pub mod socketopts;
 pub mod unix;
 
 pub fn Init() {
+    // the raw provider checks CAP_NET_RAW before any other provider sees the
+    // raw sockets. the netstack providers go first, they fall through to
+    // hostinet when the netstack is disabled
+    self::epsocket::netstack_raw::Init();
+    self::epsocket::netstack_socket::Init();
     self::hostinet::Init();
     self::unix::Init();
 }
@@ -165,7 +165,9 @@ pub fn NewSocketDirent(task: &Task, _d: Arc<QMutex<Device>>, fd: i32) -> Result<
     }
     let inode = Inode::NewHostInode(task, &Arc::new(QMutex::new(msrc)), fd, &fstat, true, false, false)?;
 
-    let name = format!("socket:[{}]", fd);
+    // Name the socket after its inode number so that /proc/[pid]/fd links
+    // match the inode column of /proc/net/*.
+    let name = format!("socket:[{}]", inode.StableAttr().InodeId);
     return Ok(Dirent::New(&inode, &name.to_string()));
 }
 
@@ -69,7 +69,7 @@ pub fn NewUnixSocket(task: &Task, ep: BoundEndpoint, stype: i32) -> Result<File>
         ..Default::default()
     };
 
-    let file = File::New(
+    let file = File::NewSocket(
         &dirent,
         &fileFlags,
         UnixSocketOperations::New(ep, stype).into(),
@@ -81,8 +81,6 @@ pub fn NewUnixSocket(task: &Task, ep: BoundEndpoint, stype: i32) -> Result<File>
 }
 
 pub fn NewUnixSocketDummyDirent(task: &Task, d: Arc<QMutex<Device>>) -> Result<Dirent> {
-    let ino = d.lock().NextIno();
-
     let iops = SimpleFileInode::New(
         task,
         &task.FileOwner(),
@@ -109,7 +107,7 @@ pub fn NewUnixSocketDummyDirent(task: &Task, d: Arc<QMutex<Device>>) -> Result<D
     let msrc = MountSource::NewPseudoMountSource();
     let inode = Inode::New(iops.into(), &Arc::new(QMutex::new(msrc)), &attr);
 
-    let name = format!("socket:[{}]", ino);
+    let name = format!("socket:[{}]", inodeId);
     return Ok(Dirent::New(&inode, &name.to_string()));
 }
 
@@ -1817,7 +1815,14 @@ impl InodeOperations for UnixSocketInodeOps {
         return Ok(());
     }
 
-    fn Allocate(&self, _task: &Task, _dir: &mut Inode, _offset: i64, _length: i64) -> Result<()> {
+    fn Allocate(
+        &self,
+        _task: &Task,
+        _dir: &mut Inode,
+        _mode: i32,
+        _offset: i64,
+        _length: i64,
+    ) -> Result<()> {
         return Ok(());
     }
 
@@ -34,6 +34,7 @@ use super::fs::dirent::*;
 use super::kernel::fd_table::*;
 use super::kernel::futex::*;
 use super::kernel::ipc_namespace::*;
+use super::kernel::strace::*;
 use super::kernel::time::*;
 use super::kernel::timer::*;
 use super::kernel::uts_namespace::*;
@@ -187,6 +188,8 @@ pub struct Task {
     pub thread: Option<Thread>,
     pub haveSyscallReturn: bool,
     pub syscallRestartBlock: Option<Box<SyscallRestartBlock>>,
+    // the strace line of the syscall in progress
+    pub syscallTrace: Option<SyscallTrace>,
     pub futexMgr: FutexMgr,
     pub ioUsage: IO,
     pub sched: TaskSchedInfo,
@@ -308,6 +311,7 @@ impl Task {
             thread: None,
             haveSyscallReturn: false,
             syscallRestartBlock: None,
+            syscallTrace: None,
             futexMgr: futexMgr,
             ioUsage: IO::default(),
             sched: TaskSchedInfo::default(),
@@ -664,6 +668,7 @@ impl Task {
                     thread: None,
                     haveSyscallReturn: false,
                     syscallRestartBlock: None,
+                    syscallTrace: None,
                     futexMgr: futexMgr,
                     ioUsage: ioUsage,
                     sched: TaskSchedInfo::default(),
@@ -767,6 +772,7 @@ impl Task {
                     thread: None,
                     haveSyscallReturn: false,
                     syscallRestartBlock: None,
+                    syscallTrace: None,
                     futexMgr: FUTEX_MGR.clone(),
                     ioUsage: dummyTask.ioUsage.clone(),
                     sched: TaskSchedInfo::default(),
@@ -290,6 +290,10 @@ impl Scheduler {
             }
         }
 
+        if !self.VcpuActive(vcpuId) {
+            return None;
+        }
+
         match self.Steal(vcpuId) {
             None => return None,
             Some(t) => {
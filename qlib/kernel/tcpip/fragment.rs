@@ -0,0 +1,607 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The ip fragment reassembly and fragmentation of the netstack. Like linux, a
+// datagram whose fragments overlap is dropped as a whole (rfc 5722), and the
+// incomplete datagrams are dropped after REASSEMBLY_TIMEOUT or when the
+// fragments held exceed REASSEMBLY_MEM_LIMIT.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+
+use super::header::*;
+
+// linux ipfrag_time
+pub const REASSEMBLY_TIMEOUT: i64 = 30_000_000_000;
+// linux ipfrag_high_thresh
+pub const REASSEMBLY_MEM_LIMIT: usize = 4 * 1024 * 1024;
+pub const MAX_IP_PAYLOAD_SIZE: usize = 0xffff;
+
+// FragmentKey identifies the fragments of one datagram. The protocol of an
+// ipv6 datagram is left 0 as it isn't part of the identification (rfc 8200).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FragmentKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub id: u32,
+    pub protocol: u8,
+}
+
+impl FragmentKey {
+    pub fn Ipv4(hdr: &Ipv4Header) -> Self {
+        return Self {
+            src: IpAddr::FromIpv4(hdr.src),
+            dst: IpAddr::FromIpv4(hdr.dst),
+            id: hdr.id as u32,
+            protocol: hdr.protocol,
+        };
+    }
+
+    pub fn Ipv6(hdr: &Ipv6Header, id: u32) -> Self {
+        return Self {
+            src: hdr.src,
+            dst: hdr.dst,
+            id: id,
+            protocol: 0,
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FragmentBuffer {
+    // the header of the fragment at offset 0, empty until it arrives
+    pub header: Vec<u8>,
+    pub data: Vec<u8>,
+    // the received payload ranges, sorted and not overlapping
+    pub ranges: Vec<(usize, usize)>,
+    // the payload length, known when the last fragment arrives
+    pub totalLen: Option<usize>,
+    pub deadline: i64,
+}
+
+impl FragmentBuffer {
+    pub fn Size(&self) -> usize {
+        return self.header.len() + self.data.len();
+    }
+
+    // Insert adds the payload at offset. It returns false when the fragment
+    // overlaps a received one and the datagram has to be dropped, an exact
+    // duplicate is ignored.
+    pub fn Insert(&mut self, offset: usize, payload: &[u8]) -> bool {
+        let end = offset + payload.len();
+        let mut idx = 0;
+        for &(start, stop) in &self.ranges {
+            if start == offset && stop == end {
+                return true;
+            }
+
+            if offset < stop && start < end {
+                return false;
+            }
+
+            if stop <= offset {
+                idx += 1;
+            }
+        }
+
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[offset..end].copy_from_slice(payload);
+        self.ranges.insert(idx, (offset, end));
+        return true;
+    }
+
+    pub fn Complete(&self) -> bool {
+        let totalLen = match self.totalLen {
+            None => return false,
+            Some(len) => len,
+        };
+
+        if self.header.len() == 0 {
+            return false;
+        }
+
+        let mut next = 0;
+        for &(start, stop) in &self.ranges {
+            if start != next {
+                return false;
+            }
+            next = stop;
+        }
+
+        return next == totalLen;
+    }
+}
+
+// Reassembled is a complete datagram, header is the header of the first
+// fragment
+#[derive(Debug)]
+pub struct Reassembled {
+    pub header: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    pub buffers: BTreeMap<FragmentKey, FragmentBuffer>,
+    pub memUsed: usize,
+}
+
+impl Reassembler {
+    // Process adds a fragment of the datagram key. offset is the offset of the
+    // payload in the datagram, more is the more fragments flag and header is
+    // the part of the packet in front of the fragmentable payload. It returns
+    // the datagram when it is complete.
+    pub fn Process(
+        &mut self,
+        key: FragmentKey,
+        offset: usize,
+        more: bool,
+        header: &[u8],
+        payload: &[u8],
+        now: i64,
+    ) -> Option<Reassembled> {
+        let end = offset + payload.len();
+        // the fragments except the last one carry a multiple of 8 bytes
+        if end > MAX_IP_PAYLOAD_SIZE
+            || (more && payload.len() % 8 != 0)
+            || (more && payload.len() == 0)
+        {
+            self.Drop(&key);
+            return None;
+        }
+
+        self.Reserve(payload.len() + header.len());
+        if self.memUsed + payload.len() + header.len() > REASSEMBLY_MEM_LIMIT {
+            return None;
+        }
+
+        let buf = self.buffers.entry(key).or_insert_with(|| FragmentBuffer {
+            deadline: now + REASSEMBLY_TIMEOUT,
+            ..Default::default()
+        });
+        let oldSize = buf.Size();
+
+        let mut valid = match buf.totalLen {
+            Some(totalLen) => end <= totalLen && (more || end == totalLen),
+            None => true,
+        };
+
+        if valid && !more {
+            if buf.ranges.iter().any(|&(_, stop)| stop > end) {
+                valid = false;
+            }
+            buf.totalLen = Some(end);
+        }
+
+        if valid {
+            valid = buf.Insert(offset, payload);
+        }
+
+        if !valid {
+            self.memUsed -= oldSize;
+            self.buffers.remove(&key);
+            return None;
+        }
+
+        if offset == 0 && buf.header.len() == 0 {
+            buf.header = header.to_vec();
+        }
+
+        let newSize = buf.Size();
+        self.memUsed = self.memUsed + newSize - oldSize;
+        if !buf.Complete() {
+            return None;
+        }
+
+        self.memUsed -= newSize;
+        let buf = self.buffers.remove(&key).unwrap();
+        return Some(Reassembled {
+            header: buf.header,
+            payload: buf.data,
+        });
+    }
+
+    pub fn Drop(&mut self, key: &FragmentKey) {
+        if let Some(buf) = self.buffers.remove(key) {
+            self.memUsed -= buf.Size();
+        }
+    }
+
+    // Reserve drops the oldest datagrams when size more bytes don't fit in
+    // the memory limit
+    pub fn Reserve(&mut self, size: usize) {
+        while self.memUsed + size > REASSEMBLY_MEM_LIMIT {
+            let oldest = self
+                .buffers
+                .iter()
+                .min_by_key(|(_, buf)| buf.deadline)
+                .map(|(key, _)| *key);
+
+            match oldest {
+                None => return,
+                Some(key) => self.Drop(&key),
+            }
+        }
+    }
+
+    // Expire drops the datagrams not completed in time. It returns the
+    // header and the first payload bytes of the dropped datagrams which have
+    // received the first fragment, for the time exceeded errors.
+    pub fn Expire(&mut self, now: i64) -> Vec<Vec<u8>> {
+        let expired: Vec<FragmentKey> = self
+            .buffers
+            .iter()
+            .filter(|(_, buf)| buf.deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+
+        let mut firsts = Vec::new();
+        for key in expired {
+            let buf = match self.buffers.remove(&key) {
+                None => continue,
+                Some(buf) => buf,
+            };
+            self.memUsed -= buf.Size();
+
+            if buf.header.len() > 0 {
+                let n = match buf.ranges.first() {
+                    Some(&(0, stop)) => core::cmp::min(stop, 8),
+                    _ => 0,
+                };
+                let mut first = buf.header;
+                first.extend_from_slice(&buf.data[..n]);
+                firsts.push(first);
+            }
+        }
+
+        return firsts;
+    }
+
+    pub fn Pending(&self) -> bool {
+        return self.buffers.len() > 0;
+    }
+}
+
+// Ipv4Reassembled builds the ipv4 packet of a reassembled datagram from the
+// header of its first fragment
+pub fn Ipv4Reassembled(r: &Reassembled) -> Vec<u8> {
+    let headerLen = r.header.len();
+    let mut packet = Vec::with_capacity(headerLen + r.payload.len());
+    packet.extend_from_slice(&r.header);
+    packet.extend_from_slice(&r.payload);
+
+    PutU16(&mut packet, 2, (headerLen + r.payload.len()) as u16);
+    let flags = GetU16(&packet, 6) & IPV4_FLAG_DONT_FRAGMENT;
+    PutU16(&mut packet, 6, flags);
+    PutU16(&mut packet, 10, 0);
+    let csum = Checksum(&packet[0..headerLen], 0);
+    PutU16(&mut packet, 10, csum);
+    return packet;
+}
+
+// FragmentIpv4 splits the ipv4 packet at ETHERNET_HEADER_SIZE of frame into
+// frames whose packets fit in mtu. The fragments keep the room of the
+// ethernet header and have the don't fragment flag cleared.
+pub fn FragmentIpv4(frame: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    let packet = &frame[ETHERNET_HEADER_SIZE..];
+    let headerLen = ((packet[0] & 0xf) as usize) * 4;
+    let totalLen = GetU16(packet, 2) as usize;
+    let payload = &packet[headerLen..totalLen];
+
+    let flagsFragment = GetU16(packet, 6);
+    let baseOffset = ((flagsFragment & IPV4_FRAGMENT_OFFSET_MASK) as usize) * 8;
+    let moreFragments = flagsFragment & IPV4_FLAG_MORE_FRAGMENTS != 0;
+
+    let chunk = ((mtu - headerLen) / 8) * 8;
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < payload.len() {
+        let n = core::cmp::min(chunk, payload.len() - offset);
+        let last = offset + n == payload.len();
+
+        let mut f = Vec::with_capacity(ETHERNET_HEADER_SIZE + headerLen + n);
+        f.extend_from_slice(&frame[..ETHERNET_HEADER_SIZE + headerLen]);
+        f.extend_from_slice(&payload[offset..offset + n]);
+
+        let hdr = &mut f[ETHERNET_HEADER_SIZE..];
+        PutU16(hdr, 2, (headerLen + n) as u16);
+        let mut flags = (((baseOffset + offset) / 8) as u16) & IPV4_FRAGMENT_OFFSET_MASK;
+        if !last || moreFragments {
+            flags |= IPV4_FLAG_MORE_FRAGMENTS;
+        }
+        PutU16(hdr, 6, flags);
+        PutU16(hdr, 10, 0);
+        let csum = Checksum(&hdr[0..headerLen], 0);
+        PutU16(hdr, 10, csum);
+
+        frames.push(f);
+        offset += n;
+    }
+
+    return frames;
+}
+
+// Ipv6Reassembled builds the ipv6 packet of a reassembled datagram, the header
+// of the first fragment is the ipv6 header with the next header of its
+// fragment header
+pub fn Ipv6Reassembled(r: &Reassembled) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(r.header.len() + r.payload.len());
+    packet.extend_from_slice(&r.header);
+    packet.extend_from_slice(&r.payload);
+    PutU16(
+        &mut packet,
+        4,
+        (r.header.len() - IPV6_HEADER_SIZE + r.payload.len()) as u16,
+    );
+    return packet;
+}
+
+// FragmentIpv6 splits the ipv6 packet at ETHERNET_HEADER_SIZE of frame into
+// frames whose packets fit in mtu. The packet has no extension headers, each
+// fragment gets a fragment header with id.
+pub fn FragmentIpv6(frame: &[u8], mtu: usize, id: u32) -> Vec<Vec<u8>> {
+    let packet = &frame[ETHERNET_HEADER_SIZE..];
+    let nextHeader = packet[6];
+    let payload = &packet[IPV6_HEADER_SIZE..IPV6_HEADER_SIZE + GetU16(packet, 4) as usize];
+    let headerLen = IPV6_HEADER_SIZE + IPV6_FRAGMENT_HEADER_SIZE;
+
+    let chunk = ((mtu - headerLen) / 8) * 8;
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < payload.len() {
+        let n = core::cmp::min(chunk, payload.len() - offset);
+        let last = offset + n == payload.len();
+
+        let mut f = Vec::with_capacity(ETHERNET_HEADER_SIZE + headerLen + n);
+        f.extend_from_slice(&frame[..ETHERNET_HEADER_SIZE + IPV6_HEADER_SIZE]);
+        f.resize(ETHERNET_HEADER_SIZE + headerLen, 0);
+        f.extend_from_slice(&payload[offset..offset + n]);
+
+        let hdr = &mut f[ETHERNET_HEADER_SIZE..];
+        PutU16(hdr, 4, (IPV6_FRAGMENT_HEADER_SIZE + n) as u16);
+        hdr[6] = IPPROTO_FRAGMENT;
+        let frag = &mut hdr[IPV6_HEADER_SIZE..];
+        frag[0] = nextHeader;
+        let mut offsetFlags = offset as u16 & IPV6_FRAGMENT_OFFSET_MASK;
+        if !last {
+            offsetFlags |= IPV6_FLAG_MORE_FRAGMENTS;
+        }
+        PutU16(frag, 2, offsetFlags);
+        PutU32(frag, 4, id);
+
+        frames.push(f);
+        offset += n;
+    }
+
+    return frames;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Key(id: u32) -> FragmentKey {
+        return FragmentKey {
+            src: IpAddr([1; 16]),
+            dst: IpAddr([2; 16]),
+            id: id,
+            protocol: IPPROTO_UDP,
+        };
+    }
+
+    fn Payload(len: usize) -> Vec<u8> {
+        return (0..len).map(|i| i as u8).collect();
+    }
+
+    #[test]
+    fn test_ReassembleInOrder() {
+        let mut r = Reassembler::default();
+        let data = Payload(24);
+        assert!(r
+            .Process(Key(1), 0, true, &[0x45; 20], &data[0..8], 0)
+            .is_none());
+        assert!(r
+            .Process(Key(1), 8, true, &[0x45; 20], &data[8..16], 0)
+            .is_none());
+        let d = r
+            .Process(Key(1), 16, false, &[0x45; 20], &data[16..24], 0)
+            .unwrap();
+        assert_eq!(d.header, [0x45; 20].to_vec());
+        assert_eq!(d.payload, data);
+        assert_eq!(r.memUsed, 0);
+        assert!(!r.Pending());
+    }
+
+    #[test]
+    fn test_ReassembleOutOfOrder() {
+        let mut r = Reassembler::default();
+        let data = Payload(20);
+        assert!(r
+            .Process(Key(1), 16, false, &[], &data[16..20], 0)
+            .is_none());
+        assert!(r.Process(Key(1), 8, true, &[], &data[8..16], 0).is_none());
+        // a duplicate is ignored
+        assert!(r.Process(Key(1), 8, true, &[], &data[8..16], 0).is_none());
+        let d = r
+            .Process(Key(1), 0, true, &[0x45; 20], &data[0..8], 0)
+            .unwrap();
+        assert_eq!(d.payload, data);
+        assert_eq!(r.memUsed, 0);
+    }
+
+    #[test]
+    fn test_ReassembleOverlap() {
+        let mut r = Reassembler::default();
+        let data = Payload(24);
+        assert!(r
+            .Process(Key(1), 0, true, &[0x45; 20], &data[0..16], 0)
+            .is_none());
+        // overlaps the first fragment, the datagram is dropped
+        assert!(r.Process(Key(1), 8, false, &[], &data[8..24], 0).is_none());
+        assert!(!r.Pending());
+        assert_eq!(r.memUsed, 0);
+
+        // the rest of the dropped datagram starts a new one
+        assert!(r
+            .Process(Key(1), 16, false, &[], &data[16..24], 0)
+            .is_none());
+        assert!(r.Pending());
+    }
+
+    #[test]
+    fn test_ReassembleBadLength() {
+        let mut r = Reassembler::default();
+        // a middle fragment which is not a multiple of 8 bytes
+        assert!(r
+            .Process(Key(1), 0, true, &[0x45; 20], &Payload(7), 0)
+            .is_none());
+        assert!(!r.Pending());
+
+        // data beyond the end given by the last fragment
+        assert!(r.Process(Key(2), 8, false, &[], &Payload(8), 0).is_none());
+        assert!(r.Process(Key(2), 16, true, &[], &Payload(8), 0).is_none());
+        assert!(!r.Pending());
+
+        // the datagram exceeds the maximum ip payload
+        assert!(r
+            .Process(Key(3), 0xfff8, false, &[], &Payload(16), 0)
+            .is_none());
+        assert!(!r.Pending());
+    }
+
+    #[test]
+    fn test_ReassembleExpire() {
+        let mut r = Reassembler::default();
+        let data = Payload(16);
+        assert!(r
+            .Process(Key(1), 0, true, &[0x45; 20], &data[0..8], 0)
+            .is_none());
+        assert!(r.Process(Key(2), 8, false, &[], &data[8..16], 0).is_none());
+        assert!(r.Expire(REASSEMBLY_TIMEOUT - 1).len() == 0);
+
+        let firsts = r.Expire(REASSEMBLY_TIMEOUT);
+        // only the datagram with the first fragment is reported
+        assert_eq!(firsts.len(), 1);
+        assert_eq!(firsts[0].len(), 28);
+        assert!(!r.Pending());
+        assert_eq!(r.memUsed, 0);
+    }
+
+    #[test]
+    fn test_ReassembleMemLimit() {
+        let mut r = Reassembler::default();
+        let data = Payload(64 * 1024 - 8);
+        let mut id = 0;
+        while r.memUsed + data.len() <= REASSEMBLY_MEM_LIMIT {
+            r.Process(Key(id), 0, true, &[], &data, id as i64);
+            id += 1;
+        }
+
+        // the oldest incomplete datagram makes room for the new one
+        r.Process(Key(id), 0, true, &[], &data, id as i64);
+        assert!(r.memUsed <= REASSEMBLY_MEM_LIMIT);
+        assert!(!r.buffers.contains_key(&Key(0)));
+        assert!(r.buffers.contains_key(&Key(id)));
+    }
+
+    #[test]
+    fn test_FragmentIpv4() {
+        let payload = Payload(3000);
+        let (mut frame, offset) =
+            NewIpv4Packet(IPPROTO_UDP, 0x0a000001, 0x0a000002, 7, payload.len());
+        frame[offset..].copy_from_slice(&payload);
+
+        let frames = FragmentIpv4(&frame, 1500);
+        assert_eq!(frames.len(), 3);
+
+        let mut r = Reassembler::default();
+        let mut packet = None;
+        for f in &frames {
+            assert!(f.len() - ETHERNET_HEADER_SIZE <= 1500);
+            let hdr = Ipv4Header::Decode(&f[ETHERNET_HEADER_SIZE..]).unwrap();
+            assert!(hdr.IsFragment());
+            assert_eq!(hdr.flagsFragment & IPV4_FLAG_DONT_FRAGMENT, 0);
+
+            let ip = &f[ETHERNET_HEADER_SIZE..];
+            let off = ((hdr.flagsFragment & IPV4_FRAGMENT_OFFSET_MASK) as usize) * 8;
+            let more = hdr.flagsFragment & IPV4_FLAG_MORE_FRAGMENTS != 0;
+            let d = r.Process(
+                FragmentKey::Ipv4(&hdr),
+                off,
+                more,
+                &ip[..hdr.headerLen],
+                &ip[hdr.headerLen..hdr.totalLen],
+                0,
+            );
+            if let Some(d) = d {
+                packet = Some(Ipv4Reassembled(&d));
+            }
+        }
+
+        let packet = packet.unwrap();
+        let hdr = Ipv4Header::Decode(&packet).unwrap();
+        assert!(!hdr.IsFragment());
+        assert_eq!(hdr.totalLen, IPV4_MIN_HEADER_SIZE + payload.len());
+        assert_eq!(&packet[hdr.headerLen..], &payload[..]);
+    }
+
+    #[test]
+    fn test_FragmentIpv6() {
+        let src = IpAddr([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let dst = IpAddr([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        let payload = Payload(3000);
+        let (mut frame, offset) =
+            NewIpv6Packet(IPPROTO_UDP, src, dst, IPV6_DEFAULT_HOP_LIMIT, payload.len());
+        frame[offset..].copy_from_slice(&payload);
+
+        let frames = FragmentIpv6(&frame, 1500, 9);
+        assert_eq!(frames.len(), 3);
+
+        let mut r = Reassembler::default();
+        let mut packet = None;
+        for f in &frames {
+            assert!(f.len() - ETHERNET_HEADER_SIZE <= 1500);
+            let ip = &f[ETHERNET_HEADER_SIZE..];
+            let hdr = Ipv6Header::Decode(ip).unwrap();
+            assert_eq!(hdr.nextHeader, IPPROTO_FRAGMENT);
+
+            let frag = &ip[IPV6_HEADER_SIZE..];
+            assert_eq!(frag[0], IPPROTO_UDP);
+            assert_eq!(GetU32(frag, 4), 9);
+            let offsetFlags = GetU16(frag, 2);
+            let mut header = ip[..IPV6_HEADER_SIZE].to_vec();
+            header[6] = frag[0];
+            let d = r.Process(
+                FragmentKey::Ipv6(&hdr, GetU32(frag, 4)),
+                (offsetFlags & IPV6_FRAGMENT_OFFSET_MASK) as usize,
+                offsetFlags & IPV6_FLAG_MORE_FRAGMENTS != 0,
+                &header,
+                &ip[IPV6_HEADER_SIZE + IPV6_FRAGMENT_HEADER_SIZE
+                    ..IPV6_HEADER_SIZE + hdr.payloadLen],
+                0,
+            );
+            if let Some(d) = d {
+                packet = Some(Ipv6Reassembled(&d));
+            }
+        }
+
+        let packet = packet.unwrap();
+        let hdr = Ipv6Header::Decode(&packet).unwrap();
+        assert_eq!(hdr.nextHeader, IPPROTO_UDP);
+        assert_eq!(hdr.payloadLen, payload.len());
+        assert_eq!(&packet[IPV6_HEADER_SIZE..], &payload[..]);
+    }
+}
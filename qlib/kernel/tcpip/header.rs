@@ -0,0 +1,1012 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Wire formats of the protocols handled by the netstack. All the multi-byte
+// fields are kept in host byte order in the parsed structs and converted when
+// encoding/decoding the packet buffers.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const ETHERNET_HEADER_SIZE: usize = 14;
+pub const ETHERNET_TYPE_IPV4: u16 = 0x0800;
+pub const ETHERNET_TYPE_ARP: u16 = 0x0806;
+pub const ETHERNET_TYPE_IPV6: u16 = 0x86dd;
+pub const ETHERNET_BROADCAST: [u8; 6] = [0xff; 6];
+
+pub const ARP_PACKET_SIZE: usize = 28;
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+
+pub const IPV4_MIN_HEADER_SIZE: usize = 20;
+pub const IPV4_DEFAULT_TTL: u8 = 64;
+pub const IPV4_FLAG_DONT_FRAGMENT: u16 = 0x4000;
+pub const IPV4_FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+pub const IPV4_FRAGMENT_OFFSET_MASK: u16 = 0x1fff;
+
+pub const IPV6_HEADER_SIZE: usize = 40;
+pub const IPV6_DEFAULT_HOP_LIMIT: u8 = 64;
+pub const IPV6_FRAGMENT_HEADER_SIZE: usize = 8;
+pub const IPV6_FRAGMENT_OFFSET_MASK: u16 = 0xfff8;
+pub const IPV6_FLAG_MORE_FRAGMENTS: u16 = 0x1;
+// the payload of an icmpv6 error is cut so that the error fits in this mtu
+pub const IPV6_MIN_MTU: usize = 1280;
+
+pub const IPPROTO_HOPOPTS: u8 = 0;
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPPROTO_ROUTING: u8 = 43;
+pub const IPPROTO_FRAGMENT: u8 = 44;
+pub const IPPROTO_ICMPV6: u8 = 58;
+pub const IPPROTO_DSTOPTS: u8 = 60;
+
+pub const ICMP_HEADER_SIZE: usize = 8;
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PORT_UNREACHABLE_CODE: u8 = 3;
+pub const ICMP_REASSEMBLY_TIME_EXCEEDED_CODE: u8 = 1;
+
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+pub const ICMPV6_ROUTER_ADVERT: u8 = 134;
+pub const ICMPV6_NEIGHBOR_SOLICIT: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERT: u8 = 136;
+pub const ICMPV6_PORT_UNREACHABLE_CODE: u8 = 4;
+pub const ICMPV6_REASSEMBLY_TIME_EXCEEDED_CODE: u8 = 1;
+
+// the neighbor discovery messages are only accepted from the link (rfc 4861)
+pub const NDP_HOP_LIMIT: u8 = 255;
+pub const NDP_NEIGHBOR_SIZE: usize = 24;
+pub const NDP_ROUTER_ADVERT_SIZE: usize = 16;
+pub const NDP_OPTION_SOURCE_LINK_ADDR: u8 = 1;
+pub const NDP_OPTION_TARGET_LINK_ADDR: u8 = 2;
+pub const NDP_FLAG_SOLICITED: u8 = 0x40;
+pub const NDP_FLAG_OVERRIDE: u8 = 0x20;
+
+pub const UDP_HEADER_SIZE: usize = 8;
+
+pub const TCP_MIN_HEADER_SIZE: usize = 20;
+// the syn segments carry the mss and the window scale options
+pub const TCP_SYN_HEADER_SIZE: usize = 28;
+pub const TCP_FLAG_FIN: u8 = 0x01;
+pub const TCP_FLAG_SYN: u8 = 0x02;
+pub const TCP_FLAG_RST: u8 = 0x04;
+pub const TCP_FLAG_PSH: u8 = 0x08;
+pub const TCP_FLAG_ACK: u8 = 0x10;
+pub const TCP_OPTION_END: u8 = 0;
+pub const TCP_OPTION_NOP: u8 = 1;
+pub const TCP_OPTION_MSS: u8 = 2;
+pub const TCP_OPTION_WS: u8 = 3;
+
+#[inline]
+pub fn GetU16(buf: &[u8], offset: usize) -> u16 {
+    return (buf[offset] as u16) << 8 | buf[offset + 1] as u16;
+}
+
+#[inline]
+pub fn PutU16(buf: &mut [u8], offset: usize, v: u16) {
+    buf[offset] = (v >> 8) as u8;
+    buf[offset + 1] = v as u8;
+}
+
+#[inline]
+pub fn GetU32(buf: &[u8], offset: usize) -> u32 {
+    return (buf[offset] as u32) << 24
+        | (buf[offset + 1] as u32) << 16
+        | (buf[offset + 2] as u32) << 8
+        | buf[offset + 3] as u32;
+}
+
+#[inline]
+pub fn PutU32(buf: &mut [u8], offset: usize, v: u32) {
+    buf[offset] = (v >> 24) as u8;
+    buf[offset + 1] = (v >> 16) as u8;
+    buf[offset + 2] = (v >> 8) as u8;
+    buf[offset + 3] = v as u8;
+}
+
+// Checksum returns the 16-bit one's complement of the one's complement sum of
+// buf, starting from the partial sum initial.
+pub fn Checksum(buf: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    let mut i = 0;
+    while i + 1 < buf.len() {
+        sum += GetU16(buf, i) as u32;
+        i += 2;
+    }
+
+    if i < buf.len() {
+        sum += (buf[i] as u32) << 8;
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    return !(sum as u16);
+}
+
+// ChecksumAdjust updates the checksum csum of a message whose bytes old are
+// replaced by new as in rfc 1624, both have the same even length.
+pub fn ChecksumAdjust(csum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let mut sum = !csum as u32;
+    let mut i = 0;
+    while i + 1 < old.len() {
+        sum += !GetU16(old, i) as u32;
+        sum += GetU16(new, i) as u32;
+        i += 2;
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    return !(sum as u16);
+}
+
+// IpAddr is an ipv6 address. The ipv4 addresses are kept as the ipv4 mapped
+// addresses ::ffff:a.b.c.d so that both families share the endpoint tables.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct IpAddr(pub [u8; 16]);
+
+impl IpAddr {
+    // the ipv6 any address, the ipv4 one is FromIpv4(0)
+    pub const ANY: IpAddr = IpAddr([0; 16]);
+    pub const LOOPBACK: IpAddr = IpAddr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    pub const ALL_NODES: IpAddr = IpAddr([0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    pub fn FromIpv4(addr: u32) -> Self {
+        let mut bytes = [0; 16];
+        bytes[10] = 0xff;
+        bytes[11] = 0xff;
+        bytes[12..].copy_from_slice(&addr.to_be_bytes());
+        return Self(bytes);
+    }
+
+    pub fn FromSlice(buf: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&buf[0..16]);
+        return Self(bytes);
+    }
+
+    pub fn IsIpv4(&self) -> bool {
+        return self.0[0..10] == [0; 10] && self.0[10] == 0xff && self.0[11] == 0xff;
+    }
+
+    // Ipv4 returns the ipv4 address in host byte order
+    pub fn Ipv4(&self) -> u32 {
+        return GetU32(&self.0, 12);
+    }
+
+    // IsAny returns whether the address is the ipv4 or ipv6 any address
+    pub fn IsAny(&self) -> bool {
+        return *self == Self::ANY || (self.IsIpv4() && self.Ipv4() == 0);
+    }
+
+    pub fn IsLoopback(&self) -> bool {
+        if self.IsIpv4() {
+            return self.Ipv4() >> 24 == 127;
+        }
+
+        return *self == Self::LOOPBACK;
+    }
+
+    pub fn IsMulticast(&self) -> bool {
+        if self.IsIpv4() {
+            return self.Ipv4() >> 28 == 0xe;
+        }
+
+        return self.0[0] == 0xff;
+    }
+
+    pub fn IsLinkLocal(&self) -> bool {
+        return self.0[0] == 0xfe && self.0[1] & 0xc0 == 0x80;
+    }
+
+    // SolicitedNode returns the solicited node multicast address the
+    // neighbor solicitations of the address are sent to
+    pub fn SolicitedNode(&self) -> Self {
+        let mut bytes = [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0, 0];
+        bytes[13..].copy_from_slice(&self.0[13..]);
+        return Self(bytes);
+    }
+
+    // MulticastMac returns the ethernet address of the multicast address
+    pub fn MulticastMac(&self) -> [u8; 6] {
+        if self.IsIpv4() {
+            return [0x01, 0x00, 0x5e, self.0[13] & 0x7f, self.0[14], self.0[15]];
+        }
+
+        return [0x33, 0x33, self.0[12], self.0[13], self.0[14], self.0[15]];
+    }
+
+    // PrefixMatch returns whether the first prefixLen bits of the addresses
+    // are the same
+    pub fn PrefixMatch(&self, other: &IpAddr, prefixLen: usize) -> bool {
+        let bytes = prefixLen / 8;
+        if self.0[..bytes] != other.0[..bytes] {
+            return false;
+        }
+
+        let bits = prefixLen % 8;
+        if bits == 0 {
+            return true;
+        }
+
+        let mask = !0u8 << (8 - bits);
+        return self.0[bytes] & mask == other.0[bytes] & mask;
+    }
+
+    // Sum is the partial checksum of the address in the pseudo header
+    pub fn Sum(&self) -> u32 {
+        let start = if self.IsIpv4() { 12 } else { 0 };
+        let mut sum = 0;
+        let mut i = start;
+        while i < 16 {
+            sum += GetU16(&self.0, i) as u32;
+            i += 2;
+        }
+
+        return sum;
+    }
+}
+
+impl fmt::Debug for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.IsIpv4() {
+            let b = &self.0;
+            return write!(f, "{}.{}.{}.{}", b[12], b[13], b[14], b[15]);
+        }
+
+        for i in 0..8 {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{:x}", GetU16(&self.0, i * 2))?;
+        }
+
+        return Ok(());
+    }
+}
+
+// PseudoHeaderSum is the partial checksum of the ipv4 or ipv6 pseudo header
+// used by udp, tcp and icmpv6.
+pub fn PseudoHeaderSum(src: IpAddr, dst: IpAddr, protocol: u8, len: usize) -> u32 {
+    return src.Sum() + dst.Sum() + protocol as u32 + (len >> 16) as u32 + (len & 0xffff) as u32;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EthernetHeader {
+    pub dst: [u8; 6],
+    pub src: [u8; 6],
+    pub etherType: u16,
+}
+
+impl EthernetHeader {
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < ETHERNET_HEADER_SIZE {
+            return None;
+        }
+
+        let mut hdr = Self::default();
+        hdr.dst.copy_from_slice(&buf[0..6]);
+        hdr.src.copy_from_slice(&buf[6..12]);
+        hdr.etherType = GetU16(buf, 12);
+        return Some(hdr);
+    }
+
+    pub fn Encode(&self, buf: &mut [u8]) {
+        buf[0..6].copy_from_slice(&self.dst);
+        buf[6..12].copy_from_slice(&self.src);
+        PutU16(buf, 12, self.etherType);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArpPacket {
+    pub op: u16,
+    pub senderMac: [u8; 6],
+    pub senderIp: u32,
+    pub targetMac: [u8; 6],
+    pub targetIp: u32,
+}
+
+impl ArpPacket {
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < ARP_PACKET_SIZE {
+            return None;
+        }
+
+        // only ethernet/ipv4 arp is supported
+        if GetU16(buf, 0) != 1 || GetU16(buf, 2) != ETHERNET_TYPE_IPV4 || buf[4] != 6 || buf[5] != 4
+        {
+            return None;
+        }
+
+        let mut pkt = Self::default();
+        pkt.op = GetU16(buf, 6);
+        pkt.senderMac.copy_from_slice(&buf[8..14]);
+        pkt.senderIp = GetU32(buf, 14);
+        pkt.targetMac.copy_from_slice(&buf[18..24]);
+        pkt.targetIp = GetU32(buf, 24);
+        return Some(pkt);
+    }
+
+    pub fn Encode(&self, buf: &mut [u8]) {
+        PutU16(buf, 0, 1);
+        PutU16(buf, 2, ETHERNET_TYPE_IPV4);
+        buf[4] = 6;
+        buf[5] = 4;
+        PutU16(buf, 6, self.op);
+        buf[8..14].copy_from_slice(&self.senderMac);
+        PutU32(buf, 14, self.senderIp);
+        buf[18..24].copy_from_slice(&self.targetMac);
+        PutU32(buf, 24, self.targetIp);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ipv4Header {
+    pub headerLen: usize,
+    pub tos: u8,
+    pub totalLen: usize,
+    pub id: u16,
+    pub flagsFragment: u16,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub src: u32,
+    pub dst: u32,
+}
+
+impl Ipv4Header {
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < IPV4_MIN_HEADER_SIZE || buf[0] >> 4 != 4 {
+            return None;
+        }
+
+        let headerLen = ((buf[0] & 0xf) as usize) * 4;
+        let totalLen = GetU16(buf, 2) as usize;
+        if headerLen < IPV4_MIN_HEADER_SIZE || totalLen < headerLen || totalLen > buf.len() {
+            return None;
+        }
+
+        if Checksum(&buf[0..headerLen], 0) != 0 {
+            return None;
+        }
+
+        return Some(Self {
+            headerLen: headerLen,
+            tos: buf[1],
+            totalLen: totalLen,
+            id: GetU16(buf, 4),
+            flagsFragment: GetU16(buf, 6),
+            ttl: buf[8],
+            protocol: buf[9],
+            src: GetU32(buf, 12),
+            dst: GetU32(buf, 16),
+        });
+    }
+
+    // Encode writes a header without options, the checksum is calculated here
+    pub fn Encode(&self, buf: &mut [u8]) {
+        buf[0] = 0x45;
+        buf[1] = self.tos;
+        PutU16(buf, 2, self.totalLen as u16);
+        PutU16(buf, 4, self.id);
+        PutU16(buf, 6, self.flagsFragment);
+        buf[8] = self.ttl;
+        buf[9] = self.protocol;
+        PutU16(buf, 10, 0);
+        PutU32(buf, 12, self.src);
+        PutU32(buf, 16, self.dst);
+        let csum = Checksum(&buf[0..IPV4_MIN_HEADER_SIZE], 0);
+        PutU16(buf, 10, csum);
+    }
+
+    pub fn IsFragment(&self) -> bool {
+        return self.flagsFragment & (IPV4_FLAG_MORE_FRAGMENTS | IPV4_FRAGMENT_OFFSET_MASK) != 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ipv6Header {
+    pub payloadLen: usize,
+    pub nextHeader: u8,
+    pub hopLimit: u8,
+    pub src: IpAddr,
+    pub dst: IpAddr,
+}
+
+impl Ipv6Header {
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < IPV6_HEADER_SIZE || buf[0] >> 4 != 6 {
+            return None;
+        }
+
+        let payloadLen = GetU16(buf, 4) as usize;
+        if IPV6_HEADER_SIZE + payloadLen > buf.len() {
+            return None;
+        }
+
+        return Some(Self {
+            payloadLen: payloadLen,
+            nextHeader: buf[6],
+            hopLimit: buf[7],
+            src: IpAddr::FromSlice(&buf[8..24]),
+            dst: IpAddr::FromSlice(&buf[24..40]),
+        });
+    }
+
+    // Encode writes the header with zero traffic class and flow label
+    pub fn Encode(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&[0x60, 0, 0, 0]);
+        PutU16(buf, 4, self.payloadLen as u16);
+        buf[6] = self.nextHeader;
+        buf[7] = self.hopLimit;
+        buf[8..24].copy_from_slice(&self.src.0);
+        buf[24..40].copy_from_slice(&self.dst.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IcmpHeader {
+    pub typ: u8,
+    pub code: u8,
+    // identifier and sequence number for echo messages
+    pub ident: u16,
+    pub seq: u16,
+}
+
+impl IcmpHeader {
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        return Self::DecodeWithSum(buf, 0);
+    }
+
+    // DecodeWithSum checks the checksum starting from the partial sum
+    // initial, the icmpv6 checksum covers the pseudo header
+    pub fn DecodeWithSum(buf: &[u8], initial: u32) -> Option<Self> {
+        if buf.len() < ICMP_HEADER_SIZE || Checksum(buf, initial) != 0 {
+            return None;
+        }
+
+        return Some(Self {
+            typ: buf[0],
+            code: buf[1],
+            ident: GetU16(buf, 4),
+            seq: GetU16(buf, 6),
+        });
+    }
+
+    // Encode writes the header in front of the payload already in buf and
+    // calculates the checksum over the whole message
+    pub fn Encode(&self, buf: &mut [u8]) {
+        self.EncodeWithSum(buf, 0);
+    }
+
+    pub fn EncodeWithSum(&self, buf: &mut [u8], initial: u32) {
+        buf[0] = self.typ;
+        buf[1] = self.code;
+        PutU16(buf, 4, self.ident);
+        PutU16(buf, 6, self.seq);
+        IcmpChecksum(buf, initial);
+    }
+}
+
+// IcmpChecksum fills the checksum of the icmp message in buf, initial is
+// the partial sum of the pseudo header or 0 for icmpv4
+pub fn IcmpChecksum(buf: &mut [u8], initial: u32) {
+    PutU16(buf, 2, 0);
+    let csum = Checksum(buf, initial);
+    PutU16(buf, 2, csum);
+}
+
+// NdpPacket is a neighbor solicitation or advertisement, linkAddr is the
+// source or target link layer address option
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NdpPacket {
+    pub typ: u8,
+    pub flags: u8,
+    pub target: IpAddr,
+    pub linkAddr: Option<[u8; 6]>,
+}
+
+impl NdpPacket {
+    // Decode parses the message whose checksum has been checked
+    pub fn Decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < NDP_NEIGHBOR_SIZE || buf[1] != 0 {
+            return None;
+        }
+
+        return Some(Self {
+            typ: buf[0],
+            flags: buf[4],
+            target: IpAddr::FromSlice(&buf[8..24]),
+            linkAddr: NdpLinkAddr(buf, NDP_NEIGHBOR_SIZE),
+        });
+    }
+
+    pub fn Size(&self) -> usize {
+        match self.linkAddr {
+            None => return NDP_NEIGHBOR_SIZE,
+            Some(_) => return NDP_NEIGHBOR_SIZE + 8,
+        }
+    }
+
+    // Encode writes the message of Size() bytes, initial is the partial sum
+    // of the pseudo header
+    pub fn Encode(&self, buf: &mut [u8], initial: u32) {
+        buf[0] = self.typ;
+        buf[1] = 0;
+        buf[4..8].copy_from_slice(&[self.flags, 0, 0, 0]);
+        buf[8..24].copy_from_slice(&self.target.0);
+        if let Some(mac) = self.linkAddr {
+            buf[24] = if self.typ == ICMPV6_NEIGHBOR_SOLICIT {
+                NDP_OPTION_SOURCE_LINK_ADDR
+            } else {
+                NDP_OPTION_TARGET_LINK_ADDR
+            };
+            buf[25] = 1;
+            buf[26..32].copy_from_slice(&mac);
+        }
+        IcmpChecksum(buf, initial);
+    }
+}
+
+// NdpLinkAddr returns the ethernet address in the source or target link
+// layer address option of the ndp message, the options start at offset
+pub fn NdpLinkAddr(buf: &[u8], offset: usize) -> Option<[u8; 6]> {
+    let mut i = offset;
+    while i + 2 <= buf.len() {
+        let len = buf[i + 1] as usize * 8;
+        if len == 0 || i + len > buf.len() {
+            return None;
+        }
+
+        let typ = buf[i];
+        if (typ == NDP_OPTION_SOURCE_LINK_ADDR || typ == NDP_OPTION_TARGET_LINK_ADDR) && len == 8 {
+            let mut mac = [0; 6];
+            mac.copy_from_slice(&buf[i + 2..i + 8]);
+            return Some(mac);
+        }
+        i += len;
+    }
+
+    return None;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpHeader {
+    pub srcPort: u16,
+    pub dstPort: u16,
+    pub len: usize,
+}
+
+impl UdpHeader {
+    pub fn Decode(buf: &[u8], src: IpAddr, dst: IpAddr) -> Option<Self> {
+        if buf.len() < UDP_HEADER_SIZE {
+            return None;
+        }
+
+        let len = GetU16(buf, 4) as usize;
+        if len < UDP_HEADER_SIZE || len > buf.len() {
+            return None;
+        }
+
+        // zero checksum means the sender doesn't calculate it, which is only
+        // allowed over ipv4
+        if GetU16(buf, 6) == 0 && !src.IsIpv4() {
+            return None;
+        }
+
+        if GetU16(buf, 6) != 0 {
+            let initial = PseudoHeaderSum(src, dst, IPPROTO_UDP, len);
+            if Checksum(&buf[0..len], initial) != 0 {
+                return None;
+            }
+        }
+
+        return Some(Self {
+            srcPort: GetU16(buf, 0),
+            dstPort: GetU16(buf, 2),
+            len: len,
+        });
+    }
+
+    pub fn Encode(&self, buf: &mut [u8], src: IpAddr, dst: IpAddr) {
+        PutU16(buf, 0, self.srcPort);
+        PutU16(buf, 2, self.dstPort);
+        PutU16(buf, 4, self.len as u16);
+        PutU16(buf, 6, 0);
+        let initial = PseudoHeaderSum(src, dst, IPPROTO_UDP, self.len);
+        let mut csum = Checksum(&buf[0..self.len], initial);
+        if csum == 0 {
+            csum = 0xffff;
+        }
+        PutU16(buf, 6, csum);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpHeader {
+    pub srcPort: u16,
+    pub dstPort: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub headerLen: usize,
+    pub flags: u8,
+    pub window: u16,
+    // the mss option, 0 if the segment doesn't carry it
+    pub mss: u16,
+    // the window scale option of rfc 7323
+    pub wndScale: Option<u8>,
+}
+
+impl TcpHeader {
+    pub fn Decode(buf: &[u8], src: IpAddr, dst: IpAddr) -> Option<Self> {
+        if buf.len() < TCP_MIN_HEADER_SIZE {
+            return None;
+        }
+
+        let headerLen = ((buf[12] >> 4) as usize) * 4;
+        if headerLen < TCP_MIN_HEADER_SIZE || headerLen > buf.len() {
+            return None;
+        }
+
+        let initial = PseudoHeaderSum(src, dst, IPPROTO_TCP, buf.len());
+        if Checksum(buf, initial) != 0 {
+            return None;
+        }
+
+        let mut hdr = Self {
+            srcPort: GetU16(buf, 0),
+            dstPort: GetU16(buf, 2),
+            seq: GetU32(buf, 4),
+            ack: GetU32(buf, 8),
+            headerLen: headerLen,
+            flags: buf[13],
+            window: GetU16(buf, 14),
+            mss: 0,
+            wndScale: None,
+        };
+
+        let mut i = TCP_MIN_HEADER_SIZE;
+        while i < headerLen {
+            match buf[i] {
+                TCP_OPTION_END => break,
+                TCP_OPTION_NOP => i += 1,
+                kind => {
+                    if i + 1 >= headerLen {
+                        break;
+                    }
+
+                    let len = buf[i + 1] as usize;
+                    if len < 2 || i + len > headerLen {
+                        break;
+                    }
+
+                    if kind == TCP_OPTION_MSS && len == 4 {
+                        hdr.mss = GetU16(buf, i + 2);
+                    } else if kind == TCP_OPTION_WS && len == 3 {
+                        hdr.wndScale = Some(buf[i + 2]);
+                    }
+                    i += len;
+                }
+            }
+        }
+
+        return Some(hdr);
+    }
+
+    pub fn HasFlag(&self, flag: u8) -> bool {
+        return self.flags & flag != 0;
+    }
+
+    // Encode writes the header in front of the payload already in buf and
+    // calculates the checksum over the whole segment. buf must have
+    // headerLen bytes reserved for the header.
+    pub fn Encode(&self, buf: &mut [u8], src: IpAddr, dst: IpAddr) {
+        PutU16(buf, 0, self.srcPort);
+        PutU16(buf, 2, self.dstPort);
+        PutU32(buf, 4, self.seq);
+        PutU32(buf, 8, self.ack);
+        buf[12] = ((self.headerLen / 4) as u8) << 4;
+        buf[13] = self.flags;
+        PutU16(buf, 14, self.window);
+        PutU16(buf, 16, 0);
+        PutU16(buf, 18, 0);
+        if self.mss != 0 && self.headerLen >= TCP_SYN_HEADER_SIZE {
+            buf[20] = TCP_OPTION_MSS;
+            buf[21] = 4;
+            PutU16(buf, 22, self.mss);
+        }
+
+        if self.headerLen >= TCP_SYN_HEADER_SIZE {
+            match self.wndScale {
+                None => buf[24..28].copy_from_slice(&[TCP_OPTION_NOP; 4]),
+                Some(shift) => {
+                    buf[24..28].copy_from_slice(&[TCP_OPTION_NOP, TCP_OPTION_WS, 3, shift])
+                }
+            }
+        }
+
+        let initial = PseudoHeaderSum(src, dst, IPPROTO_TCP, buf.len());
+        let csum = Checksum(buf, initial);
+        PutU16(buf, 16, csum);
+    }
+}
+
+// RecalcTransportChecksum fixes up the transport checksum at csumOffset of an udp
+// or tcp segment after nat rewrites its address/port. It recalculates from
+// scratch as the segments handled by the netstack are small.
+pub fn RecalcTransportChecksum(payload: &mut [u8], protocol: u8, src: IpAddr, dst: IpAddr) {
+    let csumOffset = match protocol {
+        IPPROTO_UDP => 6,
+        IPPROTO_TCP => 16,
+        _ => return,
+    };
+
+    if payload.len() < csumOffset + 2 {
+        return;
+    }
+
+    if protocol == IPPROTO_UDP && GetU16(payload, csumOffset) == 0 {
+        return;
+    }
+
+    PutU16(payload, csumOffset, 0);
+    let initial = PseudoHeaderSum(src, dst, protocol, payload.len());
+    let mut csum = Checksum(payload, initial);
+    if protocol == IPPROTO_UDP && csum == 0 {
+        csum = 0xffff;
+    }
+    PutU16(payload, csumOffset, csum);
+}
+
+// NewIpv4Packet builds an ipv4 packet with room for an ethernet header in
+// front of it. It returns the buffer and the offset of the transport payload.
+pub fn NewIpv4Packet(
+    protocol: u8,
+    src: u32,
+    dst: u32,
+    id: u16,
+    payloadLen: usize,
+) -> (Vec<u8>, usize) {
+    let totalLen = IPV4_MIN_HEADER_SIZE + payloadLen;
+    let mut buf = Vec::with_capacity(ETHERNET_HEADER_SIZE + totalLen);
+    buf.resize(ETHERNET_HEADER_SIZE + totalLen, 0);
+
+    let hdr = Ipv4Header {
+        headerLen: IPV4_MIN_HEADER_SIZE,
+        tos: 0,
+        totalLen: totalLen,
+        id: id,
+        flagsFragment: IPV4_FLAG_DONT_FRAGMENT,
+        ttl: IPV4_DEFAULT_TTL,
+        protocol: protocol,
+        src: src,
+        dst: dst,
+    };
+    hdr.Encode(&mut buf[ETHERNET_HEADER_SIZE..]);
+
+    return (buf, ETHERNET_HEADER_SIZE + IPV4_MIN_HEADER_SIZE);
+}
+
+// NewIpv6Packet builds an ipv6 packet without extension headers and with room
+// for an ethernet header in front of it. It returns the buffer and the offset
+// of the transport payload.
+pub fn NewIpv6Packet(
+    protocol: u8,
+    src: IpAddr,
+    dst: IpAddr,
+    hopLimit: u8,
+    payloadLen: usize,
+) -> (Vec<u8>, usize) {
+    let totalLen = IPV6_HEADER_SIZE + payloadLen;
+    let mut buf = Vec::with_capacity(ETHERNET_HEADER_SIZE + totalLen);
+    buf.resize(ETHERNET_HEADER_SIZE + totalLen, 0);
+
+    let hdr = Ipv6Header {
+        payloadLen: payloadLen,
+        nextHeader: protocol,
+        hopLimit: hopLimit,
+        src: src,
+        dst: dst,
+    };
+    hdr.Encode(&mut buf[ETHERNET_HEADER_SIZE..]);
+
+    return (buf, ETHERNET_HEADER_SIZE + IPV6_HEADER_SIZE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Addr6(last: u8) -> IpAddr {
+        return IpAddr([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, last]);
+    }
+
+    #[test]
+    fn test_IpAddr() {
+        let v4 = IpAddr::FromIpv4(0x7f000001);
+        assert!(v4.IsIpv4());
+        assert!(v4.IsLoopback());
+        assert_eq!(v4.Ipv4(), 0x7f000001);
+        assert!(IpAddr::FromIpv4(0).IsAny());
+        assert!(IpAddr::ANY.IsAny());
+        assert!(!IpAddr::ANY.IsIpv4());
+        assert!(IpAddr::LOOPBACK.IsLoopback());
+
+        let ll = IpAddr([
+            0xfe, 0x80, 0, 0, 0, 0, 0, 0, 2, 0x11, 0x22, 0xff, 0xfe, 0x33, 0x44, 0x55,
+        ]);
+        assert!(ll.IsLinkLocal());
+        assert_eq!(
+            ll.SolicitedNode(),
+            IpAddr([0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0x33, 0x44, 0x55])
+        );
+        assert_eq!(
+            ll.SolicitedNode().MulticastMac(),
+            [0x33, 0x33, 0xff, 0x33, 0x44, 0x55]
+        );
+        assert_eq!(
+            IpAddr::FromIpv4(0xe0000001).MulticastMac(),
+            [1, 0, 0x5e, 0, 0, 1]
+        );
+
+        assert!(Addr6(1).PrefixMatch(&Addr6(2), 64));
+        assert!(!Addr6(1).PrefixMatch(&Addr6(2), 127));
+        assert!(Addr6(2).PrefixMatch(&Addr6(3), 127));
+        assert_eq!(format!("{:?}", Addr6(1)), "fd00:0:0:0:0:0:0:1");
+        assert_eq!(format!("{:?}", IpAddr::FromIpv4(0x0a000002)), "10.0.0.2");
+    }
+
+    #[test]
+    fn test_PseudoHeaderSum() {
+        // the ipv4 pseudo header doesn't include the mapped prefix
+        let src = 0x0a000001u32;
+        let dst = 0x0a000002u32;
+        let sum = PseudoHeaderSum(
+            IpAddr::FromIpv4(src),
+            IpAddr::FromIpv4(dst),
+            IPPROTO_UDP,
+            12,
+        );
+        assert_eq!(sum, 0x0a00 + 0x0001 + 0x0a00 + 0x0002 + 17 + 12);
+
+        let sum = PseudoHeaderSum(Addr6(1), Addr6(2), IPPROTO_TCP, 0x10002);
+        assert_eq!(sum, 0xfd00 * 2 + 1 + 2 + 6 + 1 + 2);
+    }
+
+    #[test]
+    fn test_UdpChecksum() {
+        for (src, dst) in [
+            (IpAddr::FromIpv4(0x0a000001), IpAddr::FromIpv4(0x0a000002)),
+            (Addr6(1), Addr6(2)),
+        ]
+        .iter()
+        {
+            let mut buf = [0u8; 13];
+            buf[UDP_HEADER_SIZE..].copy_from_slice(b"hello");
+            let hdr = UdpHeader {
+                srcPort: 1000,
+                dstPort: 53,
+                len: buf.len(),
+            };
+            hdr.Encode(&mut buf, *src, *dst);
+
+            let d = UdpHeader::Decode(&buf, *src, *dst).unwrap();
+            assert_eq!(d.srcPort, 1000);
+            assert_eq!(d.dstPort, 53);
+            assert!(UdpHeader::Decode(&buf, *dst, IpAddr::FromIpv4(1)).is_none());
+
+            // a zero checksum is only allowed over ipv4
+            PutU16(&mut buf, 6, 0);
+            assert_eq!(UdpHeader::Decode(&buf, *src, *dst).is_some(), src.IsIpv4());
+        }
+    }
+
+    #[test]
+    fn test_TcpChecksum() {
+        let mut buf = [0u8; TCP_SYN_HEADER_SIZE + 3];
+        buf[TCP_SYN_HEADER_SIZE..].copy_from_slice(b"abc");
+        let hdr = TcpHeader {
+            srcPort: 80,
+            dstPort: 40000,
+            seq: 1,
+            ack: 2,
+            headerLen: TCP_SYN_HEADER_SIZE,
+            flags: TCP_FLAG_SYN | TCP_FLAG_ACK,
+            window: 1024,
+            mss: 1440,
+            wndScale: Some(7),
+        };
+        hdr.Encode(&mut buf, Addr6(1), Addr6(2));
+
+        let d = TcpHeader::Decode(&buf, Addr6(1), Addr6(2)).unwrap();
+        assert_eq!(d.mss, 1440);
+        assert_eq!(d.wndScale, Some(7));
+        assert_eq!(d.seq, 1);
+        assert_eq!(d.ack, 2);
+        assert!(d.HasFlag(TCP_FLAG_SYN));
+        assert!(TcpHeader::Decode(&buf, Addr6(1), Addr6(3)).is_none());
+
+        // nat rewrites the destination and fixes the checksum up
+        RecalcTransportChecksum(&mut buf, IPPROTO_TCP, Addr6(1), Addr6(3));
+        assert!(TcpHeader::Decode(&buf, Addr6(1), Addr6(3)).is_some());
+    }
+
+    #[test]
+    fn test_ChecksumAdjust() {
+        let mut buf = [0u8; 24];
+        for i in 0..buf.len() {
+            buf[i] = (i * 37 + 5) as u8;
+        }
+        let csum = Checksum(&buf, 0);
+
+        let old = [buf[8], buf[9], buf[10], buf[11]];
+        let new = [10, 0, 0, 9];
+        buf[8..12].copy_from_slice(&new);
+        assert_eq!(ChecksumAdjust(csum, &old, &new), Checksum(&buf, 0));
+        assert_eq!(ChecksumAdjust(csum, &old, &old), csum);
+    }
+
+    #[test]
+    fn test_Ndp() {
+        let src = IpAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let target = Addr6(2);
+        let dst = target.SolicitedNode();
+        let ns = NdpPacket {
+            typ: ICMPV6_NEIGHBOR_SOLICIT,
+            flags: 0,
+            target: target,
+            linkAddr: Some([2, 0, 0, 0, 0, 1]),
+        };
+
+        let mut buf = [0u8; 32];
+        assert_eq!(ns.Size(), buf.len());
+        let initial = PseudoHeaderSum(src, dst, IPPROTO_ICMPV6, buf.len());
+        ns.Encode(&mut buf, initial);
+        assert!(IcmpHeader::DecodeWithSum(&buf, initial).is_some());
+        assert!(IcmpHeader::Decode(&buf).is_none());
+
+        let d = NdpPacket::Decode(&buf).unwrap();
+        assert_eq!(d.typ, ICMPV6_NEIGHBOR_SOLICIT);
+        assert_eq!(d.target, target);
+        assert_eq!(d.linkAddr, Some([2, 0, 0, 0, 0, 1]));
+
+        // an option of zero length is invalid
+        buf[25] = 0;
+        assert_eq!(NdpPacket::Decode(&buf).unwrap().linkAddr, None);
+    }
+
+    #[test]
+    fn test_Ipv6Header() {
+        let (frame, offset) = NewIpv6Packet(IPPROTO_UDP, Addr6(1), Addr6(2), 64, 8);
+        assert_eq!(offset, ETHERNET_HEADER_SIZE + IPV6_HEADER_SIZE);
+        let hdr = Ipv6Header::Decode(&frame[ETHERNET_HEADER_SIZE..]).unwrap();
+        assert_eq!(hdr.payloadLen, 8);
+        assert_eq!(hdr.nextHeader, IPPROTO_UDP);
+        assert_eq!(hdr.src, Addr6(1));
+        assert_eq!(hdr.dst, Addr6(2));
+
+        // the payload length runs past the buffer
+        assert!(Ipv6Header::Decode(&frame[ETHERNET_HEADER_SIZE..frame.len() - 1]).is_none());
+    }
+}
@@ -13,4 +13,10 @@
 // limitations under the License.
 
 pub mod buffer;
+pub mod fragment;
 pub mod tcpip;
+pub mod header;
+pub mod nat;
+pub mod raw;
+pub mod stack;
+pub mod tcp;
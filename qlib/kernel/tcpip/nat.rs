@@ -0,0 +1,2475 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The iptables nat table of each address family and the connection tracking
+// behind it. The guest installs the rulesets with the ip_tables and
+// ip6_tables socket options like on linux. The first packet of a connection
+// walks the chains of its hooks and binds the translation, the later packets
+// of both directions are translated by the conntrack entry.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+
+use super::super::super::common::*;
+use super::super::super::linux::netfilter::*;
+use super::super::super::linux_def::*;
+use super::header::*;
+
+pub const NAT_TABLE_NAME: &[u8] = b"nat";
+pub const NAT_VALID_HOOKS: u32 = 1 << NF_INET_PRE_ROUTING
+    | 1 << NF_INET_LOCAL_IN
+    | 1 << NF_INET_LOCAL_OUT
+    | 1 << NF_INET_POST_ROUTING;
+
+// the hooks where the targets rewriting the destination or the source can be
+// reached from
+pub const DNAT_HOOKS: u32 = 1 << NF_INET_PRE_ROUTING | 1 << NF_INET_LOCAL_OUT;
+pub const SNAT_HOOKS: u32 = 1 << NF_INET_LOCAL_IN | 1 << NF_INET_POST_ROUTING;
+pub const MASQUERADE_HOOKS: u32 = 1 << NF_INET_POST_ROUTING;
+
+// the max number of tracked connections, the new connections are dropped
+// when the table is full like linux does
+pub const CONNTRACK_MAX: usize = 65536;
+
+// the conntrack timeouts, the linux defaults
+const SECOND: i64 = 1_000_000_000;
+pub const CONNTRACK_TCP_TIMEOUT_SYN: i64 = 120 * SECOND;
+pub const CONNTRACK_TCP_TIMEOUT_ESTABLISHED: i64 = 432000 * SECOND;
+pub const CONNTRACK_TCP_TIMEOUT_FIN: i64 = 120 * SECOND;
+pub const CONNTRACK_TCP_TIMEOUT_CLOSE: i64 = 10 * SECOND;
+pub const CONNTRACK_UDP_TIMEOUT: i64 = 30 * SECOND;
+pub const CONNTRACK_UDP_TIMEOUT_STREAM: i64 = 120 * SECOND;
+pub const CONNTRACK_ICMP_TIMEOUT: i64 = 30 * SECOND;
+
+pub const LOOPBACK_IFACE: [u8; XT_IFNAMSIZ] =
+    [b'l', b'o', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+#[inline]
+fn NeU16(buf: &[u8], offset: usize) -> u16 {
+    return u16::from_ne_bytes([buf[offset], buf[offset + 1]]);
+}
+
+#[inline]
+fn NeU32(buf: &[u8], offset: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[offset..offset + 4]);
+    return u32::from_ne_bytes(b);
+}
+
+#[inline]
+fn NeU64(buf: &[u8], offset: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&buf[offset..offset + 8]);
+    return u64::from_ne_bytes(b);
+}
+
+#[inline]
+fn PutNeU16(buf: &mut [u8], offset: usize, v: u16) {
+    buf[offset..offset + 2].copy_from_slice(&v.to_ne_bytes());
+}
+
+#[inline]
+fn PutNeU32(buf: &mut [u8], offset: usize, v: u32) {
+    buf[offset..offset + 4].copy_from_slice(&v.to_ne_bytes());
+}
+
+#[inline]
+fn PutNeU64(buf: &mut [u8], offset: usize, v: u64) {
+    buf[offset..offset + 8].copy_from_slice(&v.to_ne_bytes());
+}
+
+// XtName returns the nul terminated name at the start of buf
+pub fn XtName(buf: &[u8]) -> &[u8] {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    return &buf[..len];
+}
+
+fn IsIpv4Family(family: i32) -> bool {
+    return family == AFType::AF_INET;
+}
+
+// XtLayout is the layout of struct ipt_entry or struct ip6t_entry
+#[derive(Debug, Clone, Copy)]
+pub struct XtLayout {
+    pub entrySize: usize,
+    pub targetOffset: usize,
+    pub nextOffset: usize,
+    pub counters: usize,
+}
+
+impl XtLayout {
+    pub fn New(family: i32) -> Self {
+        if IsIpv4Family(family) {
+            return Self {
+                entrySize: IPT_ENTRY_SIZE,
+                targetOffset: IPT_ENTRY_TARGET_OFFSET,
+                nextOffset: IPT_ENTRY_NEXT_OFFSET,
+                counters: IPT_ENTRY_COUNTERS,
+            };
+        }
+
+        return Self {
+            entrySize: IP6T_ENTRY_SIZE,
+            targetOffset: IP6T_ENTRY_TARGET_OFFSET,
+            nextOffset: IP6T_ENTRY_NEXT_OFFSET,
+            counters: IP6T_ENTRY_COUNTERS,
+        };
+    }
+}
+
+// NewXtEntry builds an entry matching all the packets with the target
+pub fn NewXtEntry(family: i32, target: &[u8]) -> Vec<u8> {
+    let layout = XtLayout::New(family);
+    let mut entry = Vec::new();
+    entry.resize(layout.entrySize, 0);
+    entry.extend_from_slice(target);
+    PutNeU16(&mut entry, layout.targetOffset, layout.entrySize as u16);
+    let next = entry.len() as u16;
+    PutNeU16(&mut entry, layout.nextOffset, next);
+    return entry;
+}
+
+// NewXtTarget builds a struct xt_entry_target with the data
+pub fn NewXtTarget(name: &str, revision: u8, data: &[u8]) -> Vec<u8> {
+    let size = XtAlign(XT_ENTRY_TARGET_SIZE + data.len());
+    let mut target = Vec::new();
+    target.resize(size, 0);
+    PutNeU16(&mut target, 0, size as u16);
+    target[XT_ENTRY_NAME..XT_ENTRY_NAME + name.len()].copy_from_slice(name.as_bytes());
+    target[XT_ENTRY_REVISION] = revision;
+    target[XT_ENTRY_TARGET_SIZE..XT_ENTRY_TARGET_SIZE + data.len()].copy_from_slice(data);
+    return target;
+}
+
+pub fn NewStandardTarget(verdict: i32) -> Vec<u8> {
+    return NewXtTarget(XT_STANDARD_TARGET, 0, &verdict.to_ne_bytes());
+}
+
+pub fn NewErrorTarget(name: &str) -> Vec<u8> {
+    let mut data = [0; XT_ERROR_TARGET_SIZE - XT_ENTRY_TARGET_SIZE];
+    data[..name.len()].copy_from_slice(name.as_bytes());
+    return NewXtTarget(XT_ERROR_TARGET, 0, &data);
+}
+
+// the verdict of the standard target for the netfilter verdict v
+pub fn StandardVerdict(v: i32) -> i32 {
+    return -v - 1;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NatRange {
+    pub flags: u32,
+    pub minAddr: IpAddr,
+    pub maxAddr: IpAddr,
+    // host byte order
+    pub minPort: u16,
+    pub maxPort: u16,
+}
+
+impl NatRange {
+    // Parse decodes struct nf_nat_ipv4_multi_range_compat for the ipv4
+    // revision 0 targets and struct nf_nat_range or nf_nat_range2 for the
+    // others
+    pub fn Parse(family: i32, compat: bool, rangeV2: bool, data: &[u8]) -> Result<Self> {
+        let (flags, minAddr, maxAddr, minPort, maxPort) = if compat {
+            if data.len() < NF_NAT_IPV4_MULTI_RANGE_SIZE || NeU32(data, 0) != 1 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            (
+                NeU32(data, 4),
+                IpAddr::FromIpv4(GetU32(data, 8)),
+                IpAddr::FromIpv4(GetU32(data, 12)),
+                GetU16(data, 16),
+                GetU16(data, 18),
+            )
+        } else {
+            let size = if rangeV2 {
+                NF_NAT_RANGE2_SIZE
+            } else {
+                NF_NAT_RANGE_SIZE
+            };
+            if data.len() < size {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            let (minAddr, maxAddr) = if IsIpv4Family(family) {
+                (
+                    IpAddr::FromIpv4(GetU32(data, 4)),
+                    IpAddr::FromIpv4(GetU32(data, 20)),
+                )
+            } else {
+                (
+                    IpAddr::FromSlice(&data[4..20]),
+                    IpAddr::FromSlice(&data[20..36]),
+                )
+            };
+            (
+                NeU32(data, 0),
+                minAddr,
+                maxAddr,
+                GetU16(data, 36),
+                GetU16(data, 38),
+            )
+        };
+
+        // the port offset of nf_nat_range2 isn't supported
+        if flags & NF_NAT_RANGE_PROTO_OFFSET != 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if flags & NF_NAT_RANGE_PROTO_SPECIFIED != 0 && minPort > maxPort {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        return Ok(Self {
+            flags: flags,
+            minAddr: minAddr,
+            maxAddr: maxAddr,
+            minPort: minPort,
+            maxPort: maxPort,
+        });
+    }
+
+    pub fn MapIps(&self) -> bool {
+        return self.flags & NF_NAT_RANGE_MAP_IPS != 0;
+    }
+
+    pub fn ProtoSpecified(&self) -> bool {
+        return self.flags & NF_NAT_RANGE_PROTO_SPECIFIED != 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NatKind {
+    Dnat,
+    Redirect,
+    Snat,
+    Masquerade,
+}
+
+impl NatKind {
+    pub fn SrcManip(&self) -> bool {
+        return *self == Self::Snat || *self == Self::Masquerade;
+    }
+
+    pub fn Hooks(&self) -> u32 {
+        match self {
+            Self::Dnat | Self::Redirect => return DNAT_HOOKS,
+            Self::Snat => return SNAT_HOOKS,
+            Self::Masquerade => return MASQUERADE_HOOKS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XtTarget {
+    // NF_ACCEPT, NF_DROP or XT_RETURN
+    Verdict(i32),
+    // the index of the first rule of the chain
+    Jump(usize),
+    Error,
+    Nat(NatKind, NatRange),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XtMatch {
+    Tcp {
+        srcPorts: (u16, u16),
+        dstPorts: (u16, u16),
+        flagMask: u8,
+        flagCmp: u8,
+        invFlags: u8,
+    },
+    Udp {
+        srcPorts: (u16, u16),
+        dstPorts: (u16, u16),
+        invFlags: u8,
+    },
+    // the comment match matches all the packets
+    Comment,
+}
+
+fn PortsMatch(ports: (u16, u16), port: u16, invert: bool) -> bool {
+    return (ports.0 <= port && port <= ports.1) != invert;
+}
+
+impl XtMatch {
+    // Parse decodes a struct xt_entry_match, the matches the netstack
+    // doesn't know are ENOENT like a kernel without the module
+    pub fn Parse(protocol: u16, invProto: bool, m: &[u8]) -> Result<Self> {
+        let name = XtName(&m[XT_ENTRY_NAME..XT_ENTRY_REVISION]);
+        let revision = m[XT_ENTRY_REVISION];
+        let data = &m[XT_ENTRY_MATCH_SIZE..];
+        match name {
+            b"tcp" | b"udp" => {
+                if revision != 0 {
+                    return Err(Error::SysError(SysErr::ENOENT));
+                }
+
+                let (want, size) = if name == b"tcp" {
+                    (IPPROTO_TCP, XT_TCP_SIZE)
+                } else {
+                    (IPPROTO_UDP, XT_UDP_SIZE)
+                };
+                if m.len() != XtAlign(XT_ENTRY_MATCH_SIZE + size)
+                    || protocol != want as u16
+                    || invProto
+                {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                let srcPorts = (NeU16(data, 0), NeU16(data, 2));
+                let dstPorts = (NeU16(data, 4), NeU16(data, 6));
+                if want == IPPROTO_UDP {
+                    let invFlags = data[8];
+                    if invFlags & !XT_UDP_INV_MASK != 0 {
+                        return Err(Error::SysError(SysErr::EINVAL));
+                    }
+
+                    return Ok(Self::Udp {
+                        srcPorts: srcPorts,
+                        dstPorts: dstPorts,
+                        invFlags: invFlags,
+                    });
+                }
+
+                // the tcp option match isn't supported
+                let invFlags = data[11];
+                if data[8] != 0 || invFlags & !XT_TCP_INV_MASK != 0 {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                return Ok(Self::Tcp {
+                    srcPorts: srcPorts,
+                    dstPorts: dstPorts,
+                    flagMask: data[9],
+                    flagCmp: data[10],
+                    invFlags: invFlags,
+                });
+            }
+            b"comment" => {
+                if revision != 0 {
+                    return Err(Error::SysError(SysErr::ENOENT));
+                }
+                return Ok(Self::Comment);
+            }
+            _ => return Err(Error::SysError(SysErr::ENOENT)),
+        }
+    }
+
+    pub fn Matches(&self, pkt: &NatPacket) -> bool {
+        match *self {
+            Self::Tcp {
+                srcPorts,
+                dstPorts,
+                flagMask,
+                flagCmp,
+                invFlags,
+            } => {
+                return pkt.protocol == IPPROTO_TCP
+                    && PortsMatch(srcPorts, pkt.srcPort, invFlags & XT_TCP_INV_SRCPT != 0)
+                    && PortsMatch(dstPorts, pkt.dstPort, invFlags & XT_TCP_INV_DSTPT != 0)
+                    && ((pkt.tcpFlags & flagMask == flagCmp)
+                        != (invFlags & XT_TCP_INV_FLAGS != 0));
+            }
+            Self::Udp {
+                srcPorts,
+                dstPorts,
+                invFlags,
+            } => {
+                return pkt.protocol == IPPROTO_UDP
+                    && PortsMatch(srcPorts, pkt.srcPort, invFlags & XT_UDP_INV_SRCPT != 0)
+                    && PortsMatch(dstPorts, pkt.dstPort, invFlags & XT_UDP_INV_DSTPT != 0);
+            }
+            Self::Comment => return true,
+        }
+    }
+}
+
+fn MaskedEqual(a: &IpAddr, b: &IpAddr, mask: &IpAddr) -> bool {
+    for i in 0..16 {
+        if (a.0[i] ^ b.0[i]) & mask.0[i] != 0 {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+fn IfaceEqual(
+    iface: &[u8; XT_IFNAMSIZ],
+    rule: &[u8; XT_IFNAMSIZ],
+    mask: &[u8; XT_IFNAMSIZ],
+) -> bool {
+    for i in 0..XT_IFNAMSIZ {
+        if (iface[i] ^ rule[i]) & mask[i] != 0 {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+fn Iface(buf: &[u8]) -> [u8; XT_IFNAMSIZ] {
+    let mut iface = [0; XT_IFNAMSIZ];
+    iface.copy_from_slice(&buf[..XT_IFNAMSIZ]);
+    return iface;
+}
+
+// XtRule is a parsed struct ipt_entry or struct ip6t_entry
+#[derive(Debug, Clone)]
+pub struct XtRule {
+    // the offset of the entry in the table
+    pub offset: usize,
+    pub src: IpAddr,
+    pub srcMask: IpAddr,
+    pub dst: IpAddr,
+    pub dstMask: IpAddr,
+    pub inIface: [u8; XT_IFNAMSIZ],
+    pub inIfaceMask: [u8; XT_IFNAMSIZ],
+    pub outIface: [u8; XT_IFNAMSIZ],
+    pub outIfaceMask: [u8; XT_IFNAMSIZ],
+    // 0 for any protocol
+    pub protocol: u16,
+    // the ipv6 traffic class
+    pub tos: Option<u8>,
+    pub fragment: bool,
+    pub goto: bool,
+    pub invFlags: u8,
+    pub matches: Vec<XtMatch>,
+    pub target: XtTarget,
+}
+
+impl XtRule {
+    // Parse decodes the entry at offset of the table. A jump is returned with
+    // the offset of its target, the caller resolves it to the rule index.
+    pub fn Parse(family: i32, table: &[u8], offset: usize) -> Result<(Self, Option<usize>)> {
+        let layout = XtLayout::New(family);
+        if offset % 8 != 0 || offset + layout.entrySize > table.len() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let e = &table[offset..];
+        let targetOffset = NeU16(e, layout.targetOffset) as usize;
+        let nextOffset = NeU16(e, layout.nextOffset) as usize;
+        if targetOffset < layout.entrySize
+            || targetOffset + XT_ENTRY_TARGET_SIZE > nextOffset
+            || nextOffset % 8 != 0
+            || offset + nextOffset > table.len()
+        {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut rule = if IsIpv4Family(family) {
+            let flags = e[IPT_IP_FLAGS];
+            if flags & !IPT_F_MASK != 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            Self {
+                offset: offset,
+                src: IpAddr::FromIpv4(GetU32(e, IPT_IP_SRC)),
+                srcMask: IpAddr::FromIpv4(GetU32(e, IPT_IP_SMSK)),
+                dst: IpAddr::FromIpv4(GetU32(e, IPT_IP_DST)),
+                dstMask: IpAddr::FromIpv4(GetU32(e, IPT_IP_DMSK)),
+                inIface: Iface(&e[IPT_IP_INIFACE..]),
+                inIfaceMask: Iface(&e[IPT_IP_INIFACE_MASK..]),
+                outIface: Iface(&e[IPT_IP_OUTIFACE..]),
+                outIfaceMask: Iface(&e[IPT_IP_OUTIFACE_MASK..]),
+                protocol: NeU16(e, IPT_IP_PROTO),
+                tos: None,
+                fragment: flags & IPT_F_FRAG != 0,
+                goto: flags & IPT_F_GOTO != 0,
+                invFlags: e[IPT_IP_INVFLAGS],
+                matches: Vec::new(),
+                target: XtTarget::Error,
+            }
+        } else {
+            let flags = e[IP6T_IP6_FLAGS];
+            if flags & !IP6T_F_MASK != 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            Self {
+                offset: offset,
+                src: IpAddr::FromSlice(&e[IP6T_IP6_SRC..]),
+                srcMask: IpAddr::FromSlice(&e[IP6T_IP6_SMSK..]),
+                dst: IpAddr::FromSlice(&e[IP6T_IP6_DST..]),
+                dstMask: IpAddr::FromSlice(&e[IP6T_IP6_DMSK..]),
+                inIface: Iface(&e[IP6T_IP6_INIFACE..]),
+                inIfaceMask: Iface(&e[IP6T_IP6_INIFACE_MASK..]),
+                outIface: Iface(&e[IP6T_IP6_OUTIFACE..]),
+                outIfaceMask: Iface(&e[IP6T_IP6_OUTIFACE_MASK..]),
+                protocol: if flags & IP6T_F_PROTO != 0 {
+                    NeU16(e, IP6T_IP6_PROTO)
+                } else {
+                    0
+                },
+                tos: if flags & IP6T_F_TOS != 0 {
+                    Some(e[IP6T_IP6_TOS])
+                } else {
+                    None
+                },
+                fragment: false,
+                goto: flags & IP6T_F_GOTO != 0,
+                invFlags: e[IP6T_IP6_INVFLAGS],
+                matches: Vec::new(),
+                target: XtTarget::Error,
+            }
+        };
+
+        if rule.invFlags & !XT_INV_MASK != 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let invProto = rule.invFlags & XT_INV_PROTO != 0;
+        let mut m = layout.entrySize;
+        while m < targetOffset {
+            if m + XT_ENTRY_MATCH_SIZE > targetOffset {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            let size = NeU16(e, m) as usize;
+            if size < XT_ENTRY_MATCH_SIZE || size % 8 != 0 || m + size > targetOffset {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            let xtMatch = XtMatch::Parse(rule.protocol, invProto, &e[m..m + size])?;
+            rule.matches.push(xtMatch);
+            m += size;
+        }
+
+        let t = &e[targetOffset..nextOffset];
+        let size = NeU16(t, 0) as usize;
+        if size < XT_ENTRY_TARGET_SIZE || size > t.len() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let t = &t[..size];
+        let name = XtName(&t[XT_ENTRY_NAME..XT_ENTRY_REVISION]);
+        let revision = t[XT_ENTRY_REVISION];
+        let data = &t[XT_ENTRY_TARGET_SIZE..];
+        let mut jump = None;
+        rule.target = match name {
+            b"" => {
+                if size != XT_STANDARD_TARGET_SIZE {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                let verdict = NeU32(data, 0) as i32;
+                if verdict >= 0 {
+                    jump = Some(verdict as usize);
+                    XtTarget::Jump(0)
+                } else if verdict == StandardVerdict(NF_ACCEPT)
+                    || verdict == StandardVerdict(NF_DROP)
+                {
+                    XtTarget::Verdict(-verdict - 1)
+                } else if verdict == XT_RETURN {
+                    XtTarget::Verdict(XT_RETURN)
+                } else {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+            }
+            b"ERROR" => {
+                if size != XT_ERROR_TARGET_SIZE {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+                XtTarget::Error
+            }
+            b"DNAT" | b"SNAT" | b"MASQUERADE" | b"REDIRECT" => {
+                let kind = match name {
+                    b"DNAT" => NatKind::Dnat,
+                    b"SNAT" => NatKind::Snat,
+                    b"MASQUERADE" => NatKind::Masquerade,
+                    _ => NatKind::Redirect,
+                };
+
+                let maxRevision = NatTargetRevision(family, name).unwrap_or(0);
+                if revision > maxRevision
+                    || (!IsIpv4Family(family) && (kind == NatKind::Dnat || kind == NatKind::Snat))
+                        && revision == 0
+                {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                // the ipv4 revision 0 targets take the compat range
+                let compat = IsIpv4Family(family) && revision == 0;
+                let range = NatRange::Parse(family, compat, revision == 2, data)?;
+                XtTarget::Nat(kind, range)
+            }
+            _ => return Err(Error::SysError(SysErr::ENOENT)),
+        };
+
+        return Ok((rule, jump));
+    }
+
+    // IsUnconditional returns whether the rule matches all the packets
+    pub fn IsUnconditional(&self) -> bool {
+        return self.srcMask.IsAny()
+            && self.dstMask.IsAny()
+            && self.inIfaceMask == [0; XT_IFNAMSIZ]
+            && self.outIfaceMask == [0; XT_IFNAMSIZ]
+            && self.protocol == 0
+            && self.tos.is_none()
+            && !self.fragment
+            && self.invFlags == 0
+            && self.matches.len() == 0;
+    }
+
+    pub fn Matches(
+        &self,
+        pkt: &NatPacket,
+        inIface: &[u8; XT_IFNAMSIZ],
+        outIface: &[u8; XT_IFNAMSIZ],
+    ) -> bool {
+        let inv = |flag: u8| self.invFlags & flag != 0;
+        if MaskedEqual(&pkt.src, &self.src, &self.srcMask) == inv(XT_INV_SRCIP) {
+            return false;
+        }
+
+        if MaskedEqual(&pkt.dst, &self.dst, &self.dstMask) == inv(XT_INV_DSTIP) {
+            return false;
+        }
+
+        if IfaceEqual(inIface, &self.inIface, &self.inIfaceMask) == inv(XT_INV_VIA_IN) {
+            return false;
+        }
+
+        if IfaceEqual(outIface, &self.outIface, &self.outIfaceMask) == inv(XT_INV_VIA_OUT) {
+            return false;
+        }
+
+        if self.protocol != 0 && (pkt.protocol as u16 == self.protocol) == inv(XT_INV_PROTO) {
+            return false;
+        }
+
+        if let Some(tos) = self.tos {
+            if (pkt.tos == tos) == inv(XT_INV_TOS) {
+                return false;
+            }
+        }
+
+        // the packets are reassembled before the nat
+        if self.fragment && !inv(XT_INV_FRAG) {
+            return false;
+        }
+
+        for m in &self.matches {
+            if !m.Matches(pkt) {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
+
+// NatTargetRevision returns the max revision of the nat target supported
+// for the family
+pub fn NatTargetRevision(family: i32, name: &[u8]) -> Option<u8> {
+    match name {
+        b"DNAT" | b"SNAT" => return Some(2),
+        b"MASQUERADE" | b"REDIRECT" => return Some(0),
+        b"" | b"ERROR" => return Some(0),
+        _ => {
+            let _ = family;
+            return None;
+        }
+    }
+}
+
+// XtRevision answers IPT_SO_GET_REVISION_MATCH and IPT_SO_GET_REVISION_TARGET
+pub fn XtRevision(family: i32, target: bool, name: &[u8], revision: u8) -> Result<()> {
+    let max = if target {
+        NatTargetRevision(family, name)
+    } else {
+        match name {
+            b"tcp" | b"udp" | b"comment" => Some(0),
+            _ => None,
+        }
+    };
+
+    match max {
+        None => return Err(Error::SysError(SysErr::ENOENT)),
+        Some(max) => {
+            // the ipv6 dnat and snat start from the revision 1
+            let min = if target && !IsIpv4Family(family) && (name == b"DNAT" || name == b"SNAT") {
+                1
+            } else {
+                0
+            };
+            if revision < min || revision > max {
+                return Err(Error::SysError(SysErr::EPROTONOSUPPORT));
+            }
+            return Ok(());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XtVerdict {
+    Accept,
+    Drop,
+    Nat(NatKind, NatRange),
+}
+
+// NatTable is the nat table of an address family
+#[derive(Debug, Clone)]
+pub struct NatTable {
+    pub family: i32,
+    // the offsets of the hook entries and the underflows
+    pub hookEntry: [u32; NF_INET_NUMHOOKS],
+    pub underflow: [u32; NF_INET_NUMHOOKS],
+    pub rules: Vec<XtRule>,
+    // the packet and byte counters of the rules
+    pub counters: Vec<(u64, u64)>,
+    // the entries as installed, the counters are filled in on read
+    pub entries: Vec<u8>,
+    pub hookIndex: [usize; NF_INET_NUMHOOKS],
+    pub underflowIndex: [usize; NF_INET_NUMHOOKS],
+    // whether the table has other rules than the accept policies
+    pub active: bool,
+}
+
+impl NatTable {
+    // New returns the table linux starts with, the chains are empty and
+    // their policy is ACCEPT
+    pub fn New(family: i32) -> Self {
+        let mut entries = Vec::new();
+        let mut hookEntry = [0; NF_INET_NUMHOOKS];
+        for hook in 0..NF_INET_NUMHOOKS {
+            if NAT_VALID_HOOKS & (1 << hook) == 0 {
+                continue;
+            }
+
+            hookEntry[hook] = entries.len() as u32;
+            let target = NewStandardTarget(StandardVerdict(NF_ACCEPT));
+            entries.extend_from_slice(&NewXtEntry(family, &target));
+        }
+        entries.extend_from_slice(&NewXtEntry(family, &NewErrorTarget(XT_ERROR_TARGET)));
+
+        return Self::Parse(family, NAT_VALID_HOOKS, hookEntry, hookEntry, 5, &entries)
+            .expect("NatTable::New: invalid initial table");
+    }
+
+    // Parse checks and decodes the entries like translate_table on linux
+    pub fn Parse(
+        family: i32,
+        validHooks: u32,
+        hookEntry: [u32; NF_INET_NUMHOOKS],
+        underflow: [u32; NF_INET_NUMHOOKS],
+        numEntries: usize,
+        entries: &[u8],
+    ) -> Result<Self> {
+        if validHooks != NAT_VALID_HOOKS {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let layout = XtLayout::New(family);
+        let mut rules = Vec::new();
+        let mut jumps = Vec::new();
+        let mut index = BTreeMap::new();
+        let mut offset = 0;
+        while offset < entries.len() {
+            let (rule, jump) = XtRule::Parse(family, entries, offset)?;
+            index.insert(offset, rules.len());
+            jumps.push(jump);
+            rules.push(rule);
+            offset += NeU16(entries, offset + layout.nextOffset) as usize;
+        }
+
+        if rules.len() != numEntries || rules.len() == 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        // the table ends with an error entry
+        if rules[rules.len() - 1].target != XtTarget::Error {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut hookIndex = [0; NF_INET_NUMHOOKS];
+        let mut underflowIndex = [0; NF_INET_NUMHOOKS];
+        for hook in 0..NF_INET_NUMHOOKS {
+            if validHooks & (1 << hook) == 0 {
+                continue;
+            }
+
+            hookIndex[hook] = match index.get(&(hookEntry[hook] as usize)) {
+                None => return Err(Error::SysError(SysErr::EINVAL)),
+                Some(i) => *i,
+            };
+            underflowIndex[hook] = match index.get(&(underflow[hook] as usize)) {
+                None => return Err(Error::SysError(SysErr::EINVAL)),
+                Some(i) => *i,
+            };
+
+            // the policy is an unconditional accept or drop
+            let policy = &rules[underflowIndex[hook]];
+            let verdictOk = policy.target == XtTarget::Verdict(NF_ACCEPT)
+                || policy.target == XtTarget::Verdict(NF_DROP);
+            if !policy.IsUnconditional() || !verdictOk || underflowIndex[hook] < hookIndex[hook] {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+        }
+
+        // the jumps go to the first rule of a user chain, the rule after its
+        // error entry
+        for i in 0..rules.len() {
+            if let Some(target) = jumps[i] {
+                let t = match index.get(&target) {
+                    None => return Err(Error::SysError(SysErr::EINVAL)),
+                    Some(t) => *t,
+                };
+                if t == 0 || rules[t - 1].target != XtTarget::Error || t == i {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+                rules[i].target = XtTarget::Jump(t);
+            }
+        }
+
+        let mut table = Self {
+            family: family,
+            hookEntry: hookEntry,
+            underflow: underflow,
+            counters: Vec::new(),
+            rules: rules,
+            entries: entries.to_vec(),
+            hookIndex: hookIndex,
+            underflowIndex: underflowIndex,
+            active: false,
+        };
+        table.counters.resize(table.rules.len(), (0, 0));
+        table.CheckChains(validHooks)?;
+        table.active = table.rules.iter().enumerate().any(|(i, r)| match r.target {
+            XtTarget::Error => false,
+            XtTarget::Verdict(NF_ACCEPT) => !(r.IsUnconditional() && underflowIndex.contains(&i)),
+            _ => true,
+        });
+        return Ok(table);
+    }
+
+    // ChainOf returns the first rule of the chain of each rule, the error
+    // entries belong to no chain
+    fn ChainOf(&self, validHooks: u32) -> Vec<Option<usize>> {
+        let mut chains = Vec::with_capacity(self.rules.len());
+        let mut cur = None;
+        for i in 0..self.rules.len() {
+            for hook in 0..NF_INET_NUMHOOKS {
+                if validHooks & (1 << hook) != 0 && self.hookIndex[hook] == i {
+                    cur = Some(i);
+                }
+            }
+
+            if self.rules[i].target == XtTarget::Error {
+                chains.push(None);
+                cur = Some(i + 1);
+            } else {
+                chains.push(cur);
+            }
+        }
+
+        return chains;
+    }
+
+    // CheckChains finds the hooks each chain is reachable from like
+    // mark_source_chains on linux. The loops are ELOOP and the nat targets
+    // reachable from the hooks they don't work in are EINVAL.
+    fn CheckChains(&self, validHooks: u32) -> Result<()> {
+        let chainOf = self.ChainOf(validHooks);
+
+        // chain --> the chains it jumps to
+        let mut edges: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let (XtTarget::Jump(t), Some(c)) = (rule.target, chainOf[i]) {
+                edges.entry(c).or_insert(Vec::new()).push(t);
+            }
+        }
+
+        let mut hooks: BTreeMap<usize, u32> = BTreeMap::new();
+        for hook in 0..NF_INET_NUMHOOKS {
+            if validHooks & (1 << hook) == 0 {
+                continue;
+            }
+
+            // depth first walk, the chains on the stack are in path
+            let mut path: Vec<usize> = Vec::new();
+            let mut stack: Vec<(usize, usize)> = Vec::new();
+            let start = self.hookIndex[hook];
+            stack.push((start, 0));
+            path.push(start);
+            *hooks.entry(start).or_insert(0) |= 1 << hook;
+            while let Some(&mut (chain, ref mut next)) = stack.last_mut() {
+                let targets = match edges.get(&chain) {
+                    None => &[][..],
+                    Some(t) => &t[..],
+                };
+
+                if *next >= targets.len() {
+                    stack.pop();
+                    path.pop();
+                    continue;
+                }
+
+                let t = targets[*next];
+                *next += 1;
+                if path.contains(&t) {
+                    return Err(Error::SysError(SysErr::ELOOP));
+                }
+
+                let seen = hooks.get(&t).cloned().unwrap_or(0) & (1 << hook) != 0;
+                *hooks.entry(t).or_insert(0) |= 1 << hook;
+                if !seen {
+                    stack.push((t, 0));
+                    path.push(t);
+                }
+            }
+        }
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let XtTarget::Nat(kind, _) = rule.target {
+                let reached = match chainOf[i] {
+                    None => 0,
+                    Some(c) => hooks.get(&c).cloned().unwrap_or(0),
+                };
+                if reached & !kind.Hooks() != 0 {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Run walks the chains of the hook like ipt_do_table on linux
+    pub fn Run(
+        &mut self,
+        hook: usize,
+        pkt: &NatPacket,
+        inIface: &[u8; XT_IFNAMSIZ],
+        outIface: &[u8; XT_IFNAMSIZ],
+    ) -> XtVerdict {
+        let mut i = self.hookIndex[hook];
+        let mut stack: Vec<usize> = Vec::new();
+        loop {
+            let rule = match self.rules.get(i) {
+                None => return XtVerdict::Drop,
+                Some(rule) => rule,
+            };
+
+            if !rule.Matches(pkt, inIface, outIface) {
+                i += 1;
+                continue;
+            }
+
+            self.counters[i].0 += 1;
+            self.counters[i].1 += pkt.len as u64;
+            match rule.target {
+                XtTarget::Verdict(XT_RETURN) => {
+                    i = match stack.pop() {
+                        None => self.underflowIndex[hook],
+                        Some(r) => r,
+                    };
+                }
+                XtTarget::Verdict(NF_ACCEPT) => return XtVerdict::Accept,
+                XtTarget::Verdict(_) => return XtVerdict::Drop,
+                XtTarget::Jump(t) => {
+                    if !rule.goto {
+                        stack.push(i + 1);
+                    }
+                    i = t;
+                }
+                // linux drops the packet reaching an error entry
+                XtTarget::Error => return XtVerdict::Drop,
+                XtTarget::Nat(kind, range) => return XtVerdict::Nat(kind, range),
+            }
+        }
+    }
+
+    pub fn Size(&self) -> usize {
+        return self.entries.len();
+    }
+
+    // Entries returns the entries with the current counters
+    pub fn Entries(&self) -> Vec<u8> {
+        let layout = XtLayout::New(self.family);
+        let mut entries = self.entries.clone();
+        for (rule, counters) in self.rules.iter().zip(self.counters.iter()) {
+            PutNeU64(&mut entries, rule.offset + layout.counters, counters.0);
+            PutNeU64(&mut entries, rule.offset + layout.counters + 8, counters.1);
+        }
+
+        return entries;
+    }
+
+    // Counters returns the counters as an array of struct xt_counters
+    pub fn Counters(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.resize(self.counters.len() * XT_COUNTERS_SIZE, 0);
+        for (i, c) in self.counters.iter().enumerate() {
+            PutNeU64(&mut buf, i * XT_COUNTERS_SIZE, c.0);
+            PutNeU64(&mut buf, i * XT_COUNTERS_SIZE + 8, c.1);
+        }
+
+        return buf;
+    }
+
+    // GetInfo answers IPT_SO_GET_INFO, opt holds the table name
+    pub fn GetInfo(&self, opt: &mut [u8]) -> Result<()> {
+        if opt.len() != XT_GETINFO_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        PutNeU32(opt, XT_GETINFO_VALID_HOOKS, NAT_VALID_HOOKS);
+        for hook in 0..NF_INET_NUMHOOKS {
+            PutNeU32(opt, XT_GETINFO_HOOK_ENTRY + hook * 4, self.hookEntry[hook]);
+            PutNeU32(opt, XT_GETINFO_UNDERFLOW + hook * 4, self.underflow[hook]);
+        }
+        PutNeU32(opt, XT_GETINFO_NUM_ENTRIES, self.rules.len() as u32);
+        PutNeU32(opt, XT_GETINFO_SIZE_OFFSET, self.Size() as u32);
+        return Ok(());
+    }
+
+    // GetEntries answers IPT_SO_GET_ENTRIES, the size in opt is the size
+    // returned by GetInfo
+    pub fn GetEntries(&self, opt: &mut [u8]) -> Result<()> {
+        if opt.len() < XT_GET_ENTRIES_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let size = NeU32(opt, XT_GET_ENTRIES_SIZE_OFFSET) as usize;
+        if opt.len() != XT_GET_ENTRIES_SIZE + size {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        // the table has been replaced since the size was read
+        if size != self.Size() {
+            return Err(Error::SysError(SysErr::EAGAIN));
+        }
+
+        opt[XT_GET_ENTRIES_SIZE..].copy_from_slice(&self.Entries());
+        return Ok(());
+    }
+
+    // Replace installs the table of IPT_SO_SET_REPLACE and returns the
+    // counters of the old one
+    pub fn Replace(&mut self, opt: &[u8]) -> Result<Vec<u8>> {
+        if opt.len() < XT_REPLACE_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let size = NeU32(opt, XT_REPLACE_SIZE_OFFSET) as usize;
+        if opt.len() != XT_REPLACE_SIZE + size {
+            return Err(Error::SysError(SysErr::ENOPROTOOPT));
+        }
+
+        let validHooks = NeU32(opt, XT_REPLACE_VALID_HOOKS);
+        let numEntries = NeU32(opt, XT_REPLACE_NUM_ENTRIES) as usize;
+        let numCounters = NeU32(opt, XT_REPLACE_NUM_COUNTERS) as usize;
+        let mut hookEntry = [0; NF_INET_NUMHOOKS];
+        let mut underflow = [0; NF_INET_NUMHOOKS];
+        for hook in 0..NF_INET_NUMHOOKS {
+            hookEntry[hook] = NeU32(opt, XT_REPLACE_HOOK_ENTRY + hook * 4);
+            underflow[hook] = NeU32(opt, XT_REPLACE_UNDERFLOW + hook * 4);
+        }
+
+        let table = Self::Parse(
+            self.family,
+            validHooks,
+            hookEntry,
+            underflow,
+            numEntries,
+            &opt[XT_REPLACE_SIZE..],
+        )?;
+
+        // the caller has read another table than the one it replaces
+        if numCounters != self.rules.len() {
+            return Err(Error::SysError(SysErr::EAGAIN));
+        }
+
+        let counters = self.Counters();
+        *self = table;
+        return Ok(counters);
+    }
+
+    // AddCounters handles IPT_SO_SET_ADD_COUNTERS
+    pub fn AddCounters(&mut self, opt: &[u8]) -> Result<()> {
+        if opt.len() < XT_COUNTERS_INFO_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let num = NeU32(opt, XT_COUNTERS_INFO_NUM_COUNTERS) as usize;
+        if opt.len() != XT_COUNTERS_INFO_SIZE + num * XT_COUNTERS_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if num != self.counters.len() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        for i in 0..num {
+            let off = XT_COUNTERS_INFO_SIZE + i * XT_COUNTERS_SIZE;
+            self.counters[i].0 += NeU64(opt, off);
+            self.counters[i].1 += NeU64(opt, off + 8);
+        }
+
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NatPacketKind {
+    // tcp, udp and the icmp echo messages
+    Tracked,
+    // an icmp error, the offending packet starts at the offset
+    IcmpError(usize),
+    Untracked,
+}
+
+// NatPacket is the view of an ip packet the nat works on
+#[derive(Debug, Clone, Copy)]
+pub struct NatPacket {
+    pub ipv4: bool,
+    pub protocol: u8,
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub tos: u8,
+    // the offset of the transport header and the end of the packet
+    pub l4Offset: usize,
+    pub len: usize,
+    // the ports, or the identifier of the icmp echo messages on the side
+    // of the requester
+    pub srcPort: u16,
+    pub dstPort: u16,
+    pub tcpFlags: u8,
+    pub kind: NatPacketKind,
+}
+
+fn IsIcmpEchoRequest(ipv4: bool, typ: u8) -> bool {
+    return if ipv4 {
+        typ == ICMP_ECHO_REQUEST
+    } else {
+        typ == ICMPV6_ECHO_REQUEST
+    };
+}
+
+fn IsIcmpEchoReply(ipv4: bool, typ: u8) -> bool {
+    return if ipv4 {
+        typ == ICMP_ECHO_REPLY
+    } else {
+        typ == ICMPV6_ECHO_REPLY
+    };
+}
+
+fn IsIcmpError(ipv4: bool, typ: u8) -> bool {
+    if ipv4 {
+        // destination unreachable, source quench, redirect, time exceeded
+        // and parameter problem
+        return typ == 3 || typ == 4 || typ == 5 || typ == 11 || typ == 12;
+    }
+
+    return typ >= 1 && typ <= 4;
+}
+
+impl NatPacket {
+    // Parse decodes the ip packet, inner is set for the packet quoted in an
+    // icmp error which is truncated and carries no valid checksums
+    pub fn Parse(packet: &[u8], inner: bool) -> Option<Self> {
+        if packet.len() == 0 {
+            return None;
+        }
+
+        let mut pkt = if packet[0] >> 4 == 4 {
+            if packet.len() < IPV4_MIN_HEADER_SIZE {
+                return None;
+            }
+
+            let headerLen = ((packet[0] & 0xf) as usize) * 4;
+            let totalLen = if inner {
+                packet.len()
+            } else {
+                GetU16(packet, 2) as usize
+            };
+            if headerLen < IPV4_MIN_HEADER_SIZE || totalLen < headerLen || totalLen > packet.len() {
+                return None;
+            }
+
+            let flagsFragment = GetU16(packet, 6);
+            let fragment =
+                flagsFragment & (IPV4_FLAG_MORE_FRAGMENTS | IPV4_FRAGMENT_OFFSET_MASK) != 0;
+            Self {
+                ipv4: true,
+                protocol: packet[9],
+                src: IpAddr::FromIpv4(GetU32(packet, 12)),
+                dst: IpAddr::FromIpv4(GetU32(packet, 16)),
+                tos: packet[1],
+                l4Offset: headerLen,
+                len: totalLen,
+                srcPort: 0,
+                dstPort: 0,
+                tcpFlags: 0,
+                kind: if fragment {
+                    NatPacketKind::Untracked
+                } else {
+                    NatPacketKind::Tracked
+                },
+            }
+        } else if packet[0] >> 4 == 6 {
+            if packet.len() < IPV6_HEADER_SIZE {
+                return None;
+            }
+
+            let end = if inner {
+                packet.len()
+            } else {
+                IPV6_HEADER_SIZE + GetU16(packet, 4) as usize
+            };
+            if end > packet.len() {
+                return None;
+            }
+
+            let mut next = packet[6];
+            let mut offset = IPV6_HEADER_SIZE;
+            let mut kind = NatPacketKind::Tracked;
+            loop {
+                match next {
+                    IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                        if offset + 8 > end {
+                            return None;
+                        }
+                        next = packet[offset];
+                        offset += (packet[offset + 1] as usize + 1) * 8;
+                    }
+                    IPPROTO_FRAGMENT => {
+                        kind = NatPacketKind::Untracked;
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+
+            if offset > end {
+                return None;
+            }
+
+            Self {
+                ipv4: false,
+                protocol: next,
+                src: IpAddr::FromSlice(&packet[8..24]),
+                dst: IpAddr::FromSlice(&packet[24..40]),
+                tos: ((GetU16(packet, 0) >> 4) & 0xff) as u8,
+                l4Offset: offset,
+                len: end,
+                srcPort: 0,
+                dstPort: 0,
+                tcpFlags: 0,
+                kind: kind,
+            }
+        } else {
+            return None;
+        };
+
+        if pkt.kind != NatPacketKind::Tracked {
+            return Some(pkt);
+        }
+
+        let l4 = &packet[pkt.l4Offset..pkt.len];
+        let icmp = if pkt.ipv4 {
+            IPPROTO_ICMP
+        } else {
+            IPPROTO_ICMPV6
+        };
+        match pkt.protocol {
+            IPPROTO_TCP | IPPROTO_UDP => {
+                let min = if pkt.protocol == IPPROTO_TCP && !inner {
+                    TCP_MIN_HEADER_SIZE
+                } else {
+                    4
+                };
+                if l4.len() < min {
+                    return None;
+                }
+
+                pkt.srcPort = GetU16(l4, 0);
+                pkt.dstPort = GetU16(l4, 2);
+                if pkt.protocol == IPPROTO_TCP && l4.len() > 13 {
+                    pkt.tcpFlags = l4[13];
+                }
+            }
+            p if p == icmp => {
+                if l4.len() < ICMP_HEADER_SIZE {
+                    return None;
+                }
+
+                let typ = l4[0];
+                if IsIcmpEchoRequest(pkt.ipv4, typ) {
+                    pkt.srcPort = GetU16(l4, 4);
+                } else if IsIcmpEchoReply(pkt.ipv4, typ) {
+                    pkt.dstPort = GetU16(l4, 4);
+                } else if IsIcmpError(pkt.ipv4, typ) && !inner {
+                    pkt.kind = NatPacketKind::IcmpError(pkt.l4Offset + ICMP_HEADER_SIZE);
+                } else {
+                    pkt.kind = NatPacketKind::Untracked;
+                }
+            }
+            _ => pkt.kind = NatPacketKind::Untracked,
+        }
+
+        return Some(pkt);
+    }
+
+    pub fn Tuple(&self) -> ConnTuple {
+        return ConnTuple {
+            protocol: self.protocol,
+            src: self.src,
+            srcPort: self.srcPort,
+            dst: self.dst,
+            dstPort: self.dstPort,
+        };
+    }
+
+    // Rewrite sets the source or the destination address and port of the
+    // packet, the checksums are fixed up by FixChecksums
+    pub fn Rewrite(&mut self, packet: &mut [u8], src: bool, addr: IpAddr, port: u16) {
+        if self.ipv4 {
+            PutU32(packet, if src { 12 } else { 16 }, addr.Ipv4());
+        } else {
+            let offset = if src { 8 } else { 24 };
+            packet[offset..offset + 16].copy_from_slice(&addr.0);
+        }
+
+        let l4 = self.l4Offset;
+        match self.protocol {
+            IPPROTO_TCP | IPPROTO_UDP => {
+                PutU16(packet, l4 + if src { 0 } else { 2 }, port);
+            }
+            _ => {
+                // the echo identifier is on the side of the requester
+                let typ = packet[l4];
+                let request = IsIcmpEchoRequest(self.ipv4, typ);
+                let reply = IsIcmpEchoReply(self.ipv4, typ);
+                if (src && request) || (!src && reply) {
+                    PutU16(packet, l4 + 4, port);
+                }
+            }
+        }
+
+        if src {
+            self.src = addr;
+            self.srcPort = port;
+        } else {
+            self.dst = addr;
+            self.dstPort = port;
+        }
+    }
+
+    pub fn FixChecksums(&self, packet: &mut [u8]) {
+        if self.ipv4 {
+            PutU16(packet, 10, 0);
+            let csum = Checksum(&packet[..self.l4Offset], 0);
+            PutU16(packet, 10, csum);
+        }
+
+        let l4 = &mut packet[self.l4Offset..self.len];
+        match self.protocol {
+            IPPROTO_TCP | IPPROTO_UDP => {
+                RecalcTransportChecksum(l4, self.protocol, self.src, self.dst);
+            }
+            IPPROTO_ICMP if self.ipv4 => IcmpChecksum(l4, 0),
+            IPPROTO_ICMPV6 if !self.ipv4 => {
+                let initial = PseudoHeaderSum(self.src, self.dst, IPPROTO_ICMPV6, l4.len());
+                IcmpChecksum(l4, initial);
+            }
+            _ => (),
+        }
+    }
+}
+
+// ConnTuple identifies a connection in one direction, the icmp echo
+// identifier is the port on the side of the requester
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConnTuple {
+    pub protocol: u8,
+    pub src: IpAddr,
+    pub srcPort: u16,
+    pub dst: IpAddr,
+    pub dstPort: u16,
+}
+
+impl ConnTuple {
+    pub fn Invert(&self) -> Self {
+        return Self {
+            protocol: self.protocol,
+            src: self.dst,
+            srcPort: self.dstPort,
+            dst: self.src,
+            dstPort: self.srcPort,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnDir {
+    Original,
+    Reply,
+}
+
+impl ConnDir {
+    pub fn Other(&self) -> Self {
+        match self {
+            Self::Original => return Self::Reply,
+            Self::Reply => return Self::Original,
+        }
+    }
+}
+
+// Conn is a tracked connection. The reply tuple is the inverse of the
+// original one rewritten by the nat bindings.
+#[derive(Debug, Clone, Copy)]
+pub struct Conn {
+    pub orig: ConnTuple,
+    pub reply: ConnTuple,
+    // whether the nat rules of the source and the destination have been
+    // looked up
+    pub srcBound: bool,
+    pub dstBound: bool,
+    // whether the first packet has passed its hooks
+    pub confirmed: bool,
+    pub replied: bool,
+    pub closing: bool,
+    pub deadline: i64,
+}
+
+impl Conn {
+    pub fn Tuple(&self, dir: ConnDir) -> ConnTuple {
+        match dir {
+            ConnDir::Original => return self.orig,
+            ConnDir::Reply => return self.reply,
+        }
+    }
+
+    // SrcNat returns whether the source of the original direction is
+    // translated
+    pub fn SrcNat(&self) -> bool {
+        return self.reply.dst != self.orig.src || self.reply.dstPort != self.orig.srcPort;
+    }
+
+    pub fn DstNat(&self) -> bool {
+        return self.reply.src != self.orig.dst || self.reply.srcPort != self.orig.dstPort;
+    }
+
+    // Refresh updates the timeout with the packet of the direction
+    pub fn Refresh(&mut self, pkt: &NatPacket, dir: ConnDir, now: i64) {
+        if dir == ConnDir::Reply {
+            self.replied = true;
+        }
+
+        let timeout = match pkt.protocol {
+            IPPROTO_TCP => {
+                if pkt.tcpFlags & TCP_FLAG_RST != 0 {
+                    self.closing = true;
+                    CONNTRACK_TCP_TIMEOUT_CLOSE
+                } else {
+                    if pkt.tcpFlags & TCP_FLAG_FIN != 0 {
+                        self.closing = true;
+                    }
+
+                    if self.closing {
+                        CONNTRACK_TCP_TIMEOUT_FIN
+                    } else if self.replied {
+                        CONNTRACK_TCP_TIMEOUT_ESTABLISHED
+                    } else {
+                        CONNTRACK_TCP_TIMEOUT_SYN
+                    }
+                }
+            }
+            IPPROTO_UDP => {
+                if self.replied {
+                    CONNTRACK_UDP_TIMEOUT_STREAM
+                } else {
+                    CONNTRACK_UDP_TIMEOUT
+                }
+            }
+            _ => CONNTRACK_ICMP_TIMEOUT,
+        };
+
+        self.deadline = now + timeout;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Conntrack {
+    pub conns: BTreeMap<u64, Conn>,
+    // the tuples of both directions of the connections
+    pub tuples: BTreeMap<ConnTuple, (u64, ConnDir)>,
+    pub nextId: u64,
+}
+
+impl Conntrack {
+    pub fn Len(&self) -> usize {
+        return self.conns.len();
+    }
+
+    pub fn Find(&mut self, tuple: &ConnTuple, now: i64) -> Option<(u64, ConnDir)> {
+        let (id, dir) = *self.tuples.get(tuple)?;
+        if self.conns[&id].deadline <= now {
+            self.Remove(id);
+            return None;
+        }
+
+        return Some((id, dir));
+    }
+
+    // InUse returns whether the tuple belongs to a live connection other than id
+    pub fn InUse(&mut self, tuple: &ConnTuple, id: u64, now: i64) -> bool {
+        match self.Find(tuple, now) {
+            None => return false,
+            Some((other, _)) => return other != id,
+        }
+    }
+
+    pub fn New(&mut self, tuple: ConnTuple, now: i64) -> Result<u64> {
+        if self.conns.len() >= CONNTRACK_MAX {
+            self.Expire(now);
+            if self.conns.len() >= CONNTRACK_MAX {
+                return Err(Error::SysError(SysErr::ENOBUFS));
+            }
+        }
+
+        self.nextId += 1;
+        let id = self.nextId;
+        let reply = tuple.Invert();
+        self.conns.insert(
+            id,
+            Conn {
+                orig: tuple,
+                reply: reply,
+                srcBound: false,
+                dstBound: false,
+                confirmed: false,
+                replied: false,
+                closing: false,
+                deadline: now + CONNTRACK_TCP_TIMEOUT_SYN,
+            },
+        );
+        self.tuples.insert(tuple, (id, ConnDir::Original));
+        self.tuples.insert(reply, (id, ConnDir::Reply));
+        return Ok(id);
+    }
+
+    pub fn SetReply(&mut self, id: u64, reply: ConnTuple) {
+        let conn = self.conns.get_mut(&id).unwrap();
+        if self.tuples.get(&conn.reply) == Some(&(id, ConnDir::Reply)) {
+            self.tuples.remove(&conn.reply);
+        }
+        conn.reply = reply;
+        self.tuples.insert(reply, (id, ConnDir::Reply));
+    }
+
+    pub fn Remove(&mut self, id: u64) {
+        if let Some(conn) = self.conns.remove(&id) {
+            if self.tuples.get(&conn.orig) == Some(&(id, ConnDir::Original)) {
+                self.tuples.remove(&conn.orig);
+            }
+            if self.tuples.get(&conn.reply) == Some(&(id, ConnDir::Reply)) {
+                self.tuples.remove(&conn.reply);
+            }
+        }
+    }
+
+    pub fn Expire(&mut self, now: i64) {
+        let expired: Vec<u64> = self
+            .conns
+            .iter()
+            .filter(|(_, c)| c.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.Remove(id);
+        }
+    }
+}
+
+// NatContext is what the nat needs to know about the netstack's interfaces
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NatContext {
+    // the interface the packet comes in from, zero for the outgoing packets
+    pub inIface: [u8; XT_IFNAMSIZ],
+    pub nicIface: [u8; XT_IFNAMSIZ],
+    // the addresses of the interface
+    pub addr: IpAddr,
+    pub addr6: IpAddr,
+    pub linkLocal6: IpAddr,
+}
+
+impl NatContext {
+    pub fn IsLocal(&self, ip: &IpAddr) -> bool {
+        if ip.IsLoopback() {
+            return true;
+        }
+
+        return !ip.IsAny() && (*ip == self.addr || *ip == self.addr6 || *ip == self.linkLocal6);
+    }
+
+    pub fn OutIface(&self, dst: &IpAddr) -> [u8; XT_IFNAMSIZ] {
+        if self.IsLocal(dst) {
+            return LOOPBACK_IFACE;
+        }
+
+        return self.nicIface;
+    }
+
+    // LocalAddr returns the address masquerade and redirect use toward dst
+    pub fn LocalAddr(&self, dst: &IpAddr) -> IpAddr {
+        if dst.IsLoopback() {
+            return Loopback(dst.IsIpv4());
+        }
+
+        if dst.IsIpv4() {
+            return self.addr;
+        }
+
+        if dst.IsLinkLocal() || self.addr6.IsAny() {
+            return self.linkLocal6;
+        }
+
+        return self.addr6;
+    }
+}
+
+pub fn Loopback(ipv4: bool) -> IpAddr {
+    if ipv4 {
+        return IpAddr::FromIpv4(0x7f000001);
+    }
+
+    return IpAddr::LOOPBACK;
+}
+
+// Netfilter is the nat state of the netstack
+#[derive(Debug)]
+pub struct Netfilter {
+    pub nat: NatTable,
+    pub nat6: NatTable,
+    pub conntrack: Conntrack,
+}
+
+impl Default for Netfilter {
+    fn default() -> Self {
+        return Self {
+            nat: NatTable::New(AFType::AF_INET),
+            nat6: NatTable::New(AFType::AF_INET6),
+            conntrack: Conntrack::default(),
+        };
+    }
+}
+
+impl Netfilter {
+    // Active returns whether the packets have to go through the nat
+    pub fn Active(&self) -> bool {
+        return self.nat.active || self.nat6.active || self.conntrack.Len() > 0;
+    }
+
+    pub fn Table(&mut self, family: i32) -> Result<&mut NatTable> {
+        if IsIpv4Family(family) {
+            return Ok(&mut self.nat);
+        }
+
+        if family == AFType::AF_INET6 {
+            return Ok(&mut self.nat6);
+        }
+
+        return Err(Error::SysError(SysErr::ENOPROTOOPT));
+    }
+
+    // TableByName returns the table named at the start of opt, the tables
+    // other than nat don't exist like on a kernel without their modules
+    pub fn TableByName(&mut self, family: i32, opt: &[u8]) -> Result<&mut NatTable> {
+        if opt.len() < XT_TABLE_MAXNAMELEN {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        if XtName(&opt[..XT_TABLE_MAXNAMELEN]) != NAT_TABLE_NAME {
+            return Err(Error::SysError(SysErr::ENOENT));
+        }
+
+        return self.Table(family);
+    }
+
+    // OriginalDst returns the original destination of the connection the
+    // tuple belongs to, for SO_ORIGINAL_DST
+    pub fn OriginalDst(&mut self, tuple: &ConnTuple, now: i64) -> Option<(IpAddr, u16)> {
+        let (id, _) = self.conntrack.Find(tuple, now)?;
+        let orig = self.conntrack.conns[&id].orig;
+        return Some((orig.dst, orig.dstPort));
+    }
+
+    // Process runs the nat hooks of the packet, the destination one and then
+    // the source one: PREROUTING and INPUT for the incoming packets, OUTPUT
+    // and POSTROUTING for the outgoing ones. It returns false when the packet
+    // is dropped.
+    pub fn Process(
+        &mut self,
+        packet: &mut [u8],
+        hooks: [usize; 2],
+        ctx: &NatContext,
+        now: i64,
+    ) -> bool {
+        let mut pkt = match NatPacket::Parse(packet, false) {
+            None => return true,
+            Some(pkt) => pkt,
+        };
+
+        match pkt.kind {
+            NatPacketKind::Untracked => return true,
+            NatPacketKind::IcmpError(offset) => {
+                self.TranslateIcmpError(packet, &pkt, offset, hooks, now);
+                return true;
+            }
+            NatPacketKind::Tracked => (),
+        }
+
+        let (id, dir) = match self.conntrack.Find(&pkt.Tuple(), now) {
+            Some(c) => c,
+            None => match self.conntrack.New(pkt.Tuple(), now) {
+                Err(_) => return false,
+                Ok(id) => (id, ConnDir::Original),
+            },
+        };
+
+        let outgoing = hooks[0] == NF_INET_LOCAL_OUT;
+        let mut changed = false;
+        for &hook in &hooks {
+            let srcManip = hook == NF_INET_LOCAL_IN || hook == NF_INET_POST_ROUTING;
+            let conn = self.conntrack.conns[&id];
+            let bound = if srcManip {
+                conn.srcBound
+            } else {
+                conn.dstBound
+            };
+
+            if dir == ConnDir::Original && !bound {
+                let (inIface, outIface) = if outgoing {
+                    ([0; XT_IFNAMSIZ], ctx.OutIface(&pkt.dst))
+                } else {
+                    (ctx.inIface, [0; XT_IFNAMSIZ])
+                };
+
+                let table = if pkt.ipv4 {
+                    &mut self.nat
+                } else {
+                    &mut self.nat6
+                };
+                match table.Run(hook, &pkt, &inIface, &outIface) {
+                    XtVerdict::Drop => {
+                        if !conn.confirmed {
+                            self.conntrack.Remove(id);
+                        }
+                        return false;
+                    }
+                    XtVerdict::Accept => (),
+                    XtVerdict::Nat(kind, range) => {
+                        if !self.Bind(id, kind, &range, &pkt, hook, ctx, now) {
+                            if !conn.confirmed {
+                                self.conntrack.Remove(id);
+                            }
+                            return false;
+                        }
+                    }
+                }
+
+                let conn = self.conntrack.conns.get_mut(&id).unwrap();
+                if srcManip {
+                    conn.srcBound = true;
+                } else {
+                    conn.dstBound = true;
+                }
+            }
+
+            // the packet is made to look like the inverse of the tuple of the
+            // other direction
+            let conn = self.conntrack.conns[&id];
+            let target = conn.Tuple(dir.Other()).Invert();
+            if srcManip {
+                if pkt.src != target.src || pkt.srcPort != target.srcPort {
+                    pkt.Rewrite(packet, true, target.src, target.srcPort);
+                    changed = true;
+                }
+            } else if pkt.dst != target.dst || pkt.dstPort != target.dstPort {
+                pkt.Rewrite(packet, false, target.dst, target.dstPort);
+                changed = true;
+            }
+        }
+
+        let conn = self.conntrack.conns.get_mut(&id).unwrap();
+        conn.confirmed = true;
+        conn.Refresh(&pkt, dir, now);
+
+        if changed {
+            pkt.FixChecksums(packet);
+        }
+
+        return true;
+    }
+
+    // Bind sets up the translation of a new connection per the nat target
+    // like nf_nat_setup_info, the reply tuple gets the new source or
+    // destination. The ports of the source translation are kept when they
+    // are free and searched in the range otherwise. It returns false when
+    // no free tuple is found.
+    pub fn Bind(
+        &mut self,
+        id: u64,
+        kind: NatKind,
+        range: &NatRange,
+        pkt: &NatPacket,
+        hook: usize,
+        ctx: &NatContext,
+        now: i64,
+    ) -> bool {
+        let reply = self.conntrack.conns[&id].reply;
+        let srcManip = kind.SrcManip();
+        let (cur, curPort) = if srcManip {
+            (pkt.src, pkt.srcPort)
+        } else {
+            (pkt.dst, pkt.dstPort)
+        };
+
+        let addr = match kind {
+            NatKind::Snat | NatKind::Dnat if range.MapIps() => range.minAddr,
+            NatKind::Snat | NatKind::Dnat => cur,
+            NatKind::Masquerade => ctx.LocalAddr(&pkt.dst),
+            NatKind::Redirect => {
+                if hook == NF_INET_LOCAL_OUT {
+                    Loopback(pkt.ipv4)
+                } else {
+                    ctx.LocalAddr(&pkt.src)
+                }
+            }
+        };
+
+        if addr.IsAny() {
+            return false;
+        }
+
+        // the icmp echo identifier is only on the side of the requester
+        let icmp = pkt.protocol != IPPROTO_TCP && pkt.protocol != IPPROTO_UDP;
+        let (min, max) = if icmp && !srcManip {
+            (0, 0)
+        } else if range.ProtoSpecified() {
+            (range.minPort, range.maxPort)
+        } else if !srcManip {
+            (curPort, curPort)
+        } else if icmp {
+            (0, 0xffff)
+        } else if curPort < 512 {
+            (1, 511)
+        } else if curPort < 1024 {
+            (600, 1023)
+        } else {
+            (1024, 0xffff)
+        };
+
+        let candidate = |port: u16| {
+            let mut tuple = reply;
+            if srcManip {
+                tuple.dst = addr;
+                tuple.dstPort = port;
+            } else {
+                tuple.src = addr;
+                tuple.srcPort = port;
+            }
+            tuple
+        };
+
+        // keep the port when it is in the range and free
+        let first = if min <= curPort && curPort <= max {
+            curPort
+        } else {
+            min
+        };
+
+        let count = (max - min) as usize + 1;
+        let mut port = first;
+        for _ in 0..count {
+            let tuple = candidate(port);
+            if !self.conntrack.InUse(&tuple, id, now) {
+                self.conntrack.SetReply(id, tuple);
+                return true;
+            }
+
+            port = if port == max { min } else { port + 1 };
+        }
+
+        return false;
+    }
+
+    // TranslateIcmpError translates the icmp error about a tracked
+    // connection like nf_nat_icmp_reply_translation: the quoted packet gets
+    // the addresses it had when it was sent and the error is made to look
+    // like it comes from the peer the sender knows
+    pub fn TranslateIcmpError(
+        &mut self,
+        packet: &mut [u8],
+        pkt: &NatPacket,
+        innerOffset: usize,
+        hooks: [usize; 2],
+        now: i64,
+    ) {
+        let end = pkt.len;
+        let mut inner = match NatPacket::Parse(&packet[innerOffset..end], true) {
+            None => return,
+            Some(inner) => inner,
+        };
+
+        if inner.kind != NatPacketKind::Tracked || inner.ipv4 != pkt.ipv4 {
+            return;
+        }
+
+        // the quoted packet was sent the other way, its inverse is the tuple
+        // of the direction of the error
+        let (id, dir) = match self.conntrack.Find(&inner.Tuple().Invert(), now) {
+            None => return,
+            Some(c) => c,
+        };
+
+        let mut outer = *pkt;
+        let mut changed = false;
+        for &hook in &hooks {
+            let srcManip = hook == NF_INET_LOCAL_IN || hook == NF_INET_POST_ROUTING;
+            let conn = self.conntrack.conns[&id];
+            // the translation of the manip in the direction of the error
+            let translated = match (srcManip, dir) {
+                (true, ConnDir::Original) | (false, ConnDir::Reply) => conn.SrcNat(),
+                _ => conn.DstNat(),
+            };
+            if !translated {
+                continue;
+            }
+
+            let t = conn.Tuple(dir.Other());
+            let innerPacket = &mut packet[innerOffset..end];
+            let (oldAddr, oldPort) = if srcManip {
+                (inner.dst, inner.dstPort)
+            } else {
+                (inner.src, inner.srcPort)
+            };
+            if srcManip {
+                inner.Rewrite(innerPacket, false, t.dst, t.dstPort);
+            } else {
+                inner.Rewrite(innerPacket, true, t.src, t.srcPort);
+            }
+            FixInnerChecksums(innerPacket, &inner, oldAddr, oldPort, !srcManip);
+
+            if srcManip {
+                outer.src = t.dst;
+                SetIpAddr(packet, outer.ipv4, true, t.dst);
+            } else {
+                outer.dst = t.src;
+                SetIpAddr(packet, outer.ipv4, false, t.src);
+            }
+            changed = true;
+        }
+
+        if changed {
+            outer.FixChecksums(packet);
+        }
+    }
+}
+
+// IpDst returns the destination address of the ip packet
+pub fn IpDst(packet: &[u8]) -> Option<IpAddr> {
+    if packet.len() >= IPV4_MIN_HEADER_SIZE && packet[0] >> 4 == 4 {
+        return Some(IpAddr::FromIpv4(GetU32(packet, 16)));
+    }
+
+    if packet.len() >= IPV6_HEADER_SIZE && packet[0] >> 4 == 6 {
+        return Some(IpAddr::FromSlice(&packet[24..40]));
+    }
+
+    return None;
+}
+
+fn SetIpAddr(packet: &mut [u8], ipv4: bool, src: bool, addr: IpAddr) {
+    if ipv4 {
+        PutU32(packet, if src { 12 } else { 16 }, addr.Ipv4());
+    } else {
+        let offset = if src { 8 } else { 24 };
+        packet[offset..offset + 16].copy_from_slice(&addr.0);
+    }
+}
+
+// FixInnerChecksums fixes up the checksums of the truncated packet quoted in
+// an icmp error after the address and the port on one side changed. The
+// transport checksum can't be recalculated, it is adjusted when the quote
+// has it.
+fn FixInnerChecksums(
+    packet: &mut [u8],
+    inner: &NatPacket,
+    oldAddr: IpAddr,
+    oldPort: u16,
+    src: bool,
+) {
+    let (newAddr, newPort) = if src {
+        (inner.src, inner.srcPort)
+    } else {
+        (inner.dst, inner.dstPort)
+    };
+
+    if inner.ipv4 {
+        PutU16(packet, 10, 0);
+        let csum = Checksum(&packet[..inner.l4Offset], 0);
+        PutU16(packet, 10, csum);
+    }
+
+    let csumOffset = match inner.protocol {
+        IPPROTO_TCP => 16,
+        IPPROTO_UDP => 6,
+        _ => 2,
+    };
+    let offset = inner.l4Offset + csumOffset;
+    if offset + 2 > packet.len() {
+        return;
+    }
+
+    let csum = GetU16(packet, offset);
+    if inner.protocol == IPPROTO_UDP && csum == 0 {
+        return;
+    }
+
+    // the icmpv4 checksum doesn't cover the addresses
+    let icmp4 = inner.ipv4 && inner.protocol == IPPROTO_ICMP;
+    let (old, new) = if icmp4 {
+        (&[][..], &[][..])
+    } else if inner.ipv4 {
+        (&oldAddr.0[12..], &newAddr.0[12..])
+    } else {
+        (&oldAddr.0[..], &newAddr.0[..])
+    };
+    let mut csum = ChecksumAdjust(csum, old, new);
+    csum = ChecksumAdjust(csum, &oldPort.to_be_bytes(), &newPort.to_be_bytes());
+    PutU16(packet, offset, csum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn Ipv4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        return IpAddr::FromIpv4(u32::from_be_bytes([a, b, c, d]));
+    }
+
+    // Replace builds the ipt_replace of the entries, the hook entries are
+    // the offsets of the entries at the indexes
+    fn ReplaceOpt(
+        family: i32,
+        entries: &[Vec<u8>],
+        hooks: [usize; 5],
+        underflows: [usize; 5],
+        numCounters: usize,
+    ) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut blob = Vec::new();
+        for e in entries {
+            offsets.push(blob.len());
+            blob.extend_from_slice(e);
+        }
+
+        let mut opt = Vec::new();
+        opt.resize(XT_REPLACE_SIZE, 0);
+        opt[..3].copy_from_slice(b"nat");
+        PutNeU32(&mut opt, XT_REPLACE_VALID_HOOKS, NAT_VALID_HOOKS);
+        PutNeU32(&mut opt, XT_REPLACE_NUM_ENTRIES, entries.len() as u32);
+        PutNeU32(&mut opt, XT_REPLACE_SIZE_OFFSET, blob.len() as u32);
+        for hook in 0..NF_INET_NUMHOOKS {
+            PutNeU32(
+                &mut opt,
+                XT_REPLACE_HOOK_ENTRY + hook * 4,
+                offsets[hooks[hook]] as u32,
+            );
+            PutNeU32(
+                &mut opt,
+                XT_REPLACE_UNDERFLOW + hook * 4,
+                offsets[underflows[hook]] as u32,
+            );
+        }
+        PutNeU32(&mut opt, XT_REPLACE_NUM_COUNTERS, numCounters as u32);
+        opt.extend_from_slice(&blob);
+        let _ = family;
+        return opt;
+    }
+
+    fn Accept(family: i32) -> Vec<u8> {
+        return NewXtEntry(family, &NewStandardTarget(StandardVerdict(NF_ACCEPT)));
+    }
+
+    fn CompatRange(flags: u32, addr: u32, port: u16) -> Vec<u8> {
+        let mut data = [0u8; NF_NAT_IPV4_MULTI_RANGE_SIZE];
+        PutNeU32(&mut data, 0, 1);
+        PutNeU32(&mut data, 4, flags);
+        PutU32(&mut data, 8, addr);
+        PutU32(&mut data, 12, addr);
+        PutU16(&mut data, 16, port);
+        PutU16(&mut data, 18, port);
+        return data.to_vec();
+    }
+
+    // TcpEntry builds an ipv4 tcp rule to the destination port with the target
+    fn TcpEntry(dport: u16, target: &[u8]) -> Vec<u8> {
+        let mut m = Vec::new();
+        m.resize(XtAlign(XT_ENTRY_MATCH_SIZE + XT_TCP_SIZE), 0);
+        let size = m.len() as u16;
+        PutNeU16(&mut m, 0, size);
+        m[XT_ENTRY_NAME..XT_ENTRY_NAME + 3].copy_from_slice(b"tcp");
+        PutNeU16(&mut m, XT_ENTRY_MATCH_SIZE, 0);
+        PutNeU16(&mut m, XT_ENTRY_MATCH_SIZE + 2, 0xffff);
+        PutNeU16(&mut m, XT_ENTRY_MATCH_SIZE + 4, dport);
+        PutNeU16(&mut m, XT_ENTRY_MATCH_SIZE + 6, dport);
+
+        let mut e = Vec::new();
+        e.resize(IPT_ENTRY_SIZE, 0);
+        PutNeU16(&mut e, IPT_IP_PROTO, IPPROTO_TCP as u16);
+        e.extend_from_slice(&m);
+        let targetOffset = e.len() as u16;
+        e.extend_from_slice(target);
+        let next = e.len() as u16;
+        PutNeU16(&mut e, IPT_ENTRY_TARGET_OFFSET, targetOffset);
+        PutNeU16(&mut e, IPT_ENTRY_NEXT_OFFSET, next);
+        return e;
+    }
+
+    fn TcpPacket(src: IpAddr, sport: u16, dst: IpAddr, dport: u16, flags: u8) -> Vec<u8> {
+        let (mut buf, offset) =
+            NewIpv4Packet(IPPROTO_TCP, src.Ipv4(), dst.Ipv4(), 1, TCP_MIN_HEADER_SIZE);
+        let hdr = TcpHeader {
+            srcPort: sport,
+            dstPort: dport,
+            seq: 1,
+            ack: 0,
+            headerLen: TCP_MIN_HEADER_SIZE,
+            flags: flags,
+            window: 1000,
+            mss: 0,
+            wndScale: None,
+        };
+        hdr.Encode(&mut buf[offset..], src, dst);
+        return buf[ETHERNET_HEADER_SIZE..].to_vec();
+    }
+
+    fn Context() -> NatContext {
+        let mut nicIface = [0; XT_IFNAMSIZ];
+        nicIface[..4].copy_from_slice(b"eth0");
+        return NatContext {
+            inIface: nicIface,
+            nicIface: nicIface,
+            addr: Ipv4(10, 0, 0, 2),
+            addr6: IpAddr::ANY,
+            linkLocal6: IpAddr::ANY,
+        };
+    }
+
+    const OUT: [usize; 2] = [NF_INET_LOCAL_OUT, NF_INET_POST_ROUTING];
+    const IN: [usize; 2] = [NF_INET_PRE_ROUTING, NF_INET_LOCAL_IN];
+
+    // the ruleset of "-t nat -A OUTPUT -p tcp --dport 80 -j DNAT --to
+    // 10.0.0.9:8080" and "-A POSTROUTING -j MASQUERADE"
+    fn DnatMasqueradeOpt(numCounters: usize) -> Vec<u8> {
+        let dnat = NewXtTarget(
+            "DNAT",
+            0,
+            &CompatRange(
+                NF_NAT_RANGE_MAP_IPS | NF_NAT_RANGE_PROTO_SPECIFIED,
+                0x0a000009,
+                8080,
+            ),
+        );
+        let masq = NewXtTarget("MASQUERADE", 0, &CompatRange(0, 0, 0));
+        let entries = vec![
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            TcpEntry(80, &dnat),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &masq),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &NewErrorTarget(XT_ERROR_TARGET)),
+        ];
+        return ReplaceOpt(
+            AFType::AF_INET,
+            &entries,
+            [0, 1, 0, 2, 4],
+            [0, 1, 0, 3, 5],
+            numCounters,
+        );
+    }
+
+    #[test]
+    fn test_NatTableDefault() {
+        for family in [AFType::AF_INET, AFType::AF_INET6] {
+            let table = NatTable::New(family);
+            let layout = XtLayout::New(family);
+            let policy = layout.entrySize + XT_STANDARD_TARGET_SIZE;
+            assert_eq!(table.rules.len(), 5);
+            assert_eq!(
+                table.Size(),
+                4 * policy + layout.entrySize + XT_ERROR_TARGET_SIZE
+            );
+            assert_eq!(table.hookEntry[NF_INET_LOCAL_OUT], 2 * policy as u32);
+            assert_eq!(table.hookEntry[NF_INET_POST_ROUTING], 3 * policy as u32);
+            assert!(!table.active);
+
+            let mut info = [0u8; XT_GETINFO_SIZE];
+            table.GetInfo(&mut info).unwrap();
+            assert_eq!(NeU32(&info, XT_GETINFO_VALID_HOOKS), NAT_VALID_HOOKS);
+            assert_eq!(NeU32(&info, XT_GETINFO_NUM_ENTRIES), 5);
+            assert_eq!(NeU32(&info, XT_GETINFO_SIZE_OFFSET) as usize, table.Size());
+        }
+    }
+
+    #[test]
+    fn test_NatTableReplace() {
+        let mut table = NatTable::New(AFType::AF_INET);
+        // the number of the old counters is checked
+        assert!(table.Replace(&DnatMasqueradeOpt(4)).is_err());
+        let old = table.Replace(&DnatMasqueradeOpt(5)).unwrap();
+        assert_eq!(old.len(), 5 * XT_COUNTERS_SIZE);
+        assert!(table.active);
+        assert_eq!(table.rules.len(), 7);
+
+        let size = table.Size();
+        let mut opt = Vec::new();
+        opt.resize(XT_GET_ENTRIES_SIZE + size, 0);
+        PutNeU32(&mut opt, XT_GET_ENTRIES_SIZE_OFFSET, size as u32);
+        table.GetEntries(&mut opt).unwrap();
+        assert_eq!(
+            &opt[XT_GET_ENTRIES_SIZE..],
+            &DnatMasqueradeOpt(5)[XT_REPLACE_SIZE..]
+        );
+
+        // dnat isn't valid in POSTROUTING
+        let dnat = NewXtTarget("DNAT", 0, &CompatRange(NF_NAT_RANGE_MAP_IPS, 0x0a000009, 0));
+        let entries = vec![
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &dnat),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &NewErrorTarget(XT_ERROR_TARGET)),
+        ];
+        let opt = ReplaceOpt(
+            AFType::AF_INET,
+            &entries,
+            [0, 1, 0, 2, 3],
+            [0, 1, 0, 2, 4],
+            7,
+        );
+        assert!(table.Replace(&opt).is_err());
+
+        // unknown targets are ENOENT
+        let entries = vec![
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &NewXtTarget("TPROXY", 0, &[0; 8])),
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &NewErrorTarget(XT_ERROR_TARGET)),
+        ];
+        let opt = ReplaceOpt(
+            AFType::AF_INET,
+            &entries,
+            [0, 1, 0, 2, 4],
+            [0, 1, 0, 3, 4],
+            7,
+        );
+        match table.Replace(&opt) {
+            Err(Error::SysError(SysErr::ENOENT)) => (),
+            _ => panic!("unknown target accepted"),
+        }
+    }
+
+    #[test]
+    fn test_NatTableLoop() {
+        // OUTPUT jumps to the user chain A which jumps to itself through B
+        let family = AFType::AF_INET;
+        let policy = Accept(family).len();
+        let error = NewXtEntry(family, &NewErrorTarget("A")).len();
+        let jumpSize = policy;
+        // the offsets: 4 policies and the jump, then A: error, jump to B,
+        // return, then B: error, jump to A, return, then the end
+        let a = 5 * policy;
+        let b = a + error + 2 * jumpSize;
+        let jump = |t: usize| NewXtEntry(family, &NewStandardTarget(t as i32));
+        let ret = NewXtEntry(family, &NewStandardTarget(XT_RETURN));
+        let entries = vec![
+            Accept(family),
+            Accept(family),
+            jump(a + error),
+            Accept(family),
+            Accept(family),
+            NewXtEntry(family, &NewErrorTarget("A")),
+            jump(b + error),
+            ret.clone(),
+            NewXtEntry(family, &NewErrorTarget("B")),
+            jump(a + error),
+            ret,
+            NewXtEntry(family, &NewErrorTarget(XT_ERROR_TARGET)),
+        ];
+        let opt = ReplaceOpt(family, &entries, [0, 1, 0, 2, 4], [0, 1, 0, 3, 4], 5);
+        let mut table = NatTable::New(family);
+        match table.Replace(&opt) {
+            Err(Error::SysError(SysErr::ELOOP)) => (),
+            r => panic!("loop accepted {:?}", r.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_NatDnatMasquerade() {
+        let mut nf = Netfilter::default();
+        nf.nat.Replace(&DnatMasqueradeOpt(5)).unwrap();
+        let ctx = Context();
+        let local = Ipv4(10, 0, 0, 2);
+        let vip = Ipv4(1, 2, 3, 4);
+        let backend = Ipv4(10, 0, 0, 9);
+
+        let mut syn = TcpPacket(local, 40000, vip, 80, TCP_FLAG_SYN);
+        assert!(nf.Process(&mut syn, OUT, &ctx, 0));
+        let hdr = Ipv4Header::Decode(&syn).unwrap();
+        assert_eq!(IpAddr::FromIpv4(hdr.dst), backend);
+        let tcp = TcpHeader::Decode(&syn[hdr.headerLen..], local, backend).unwrap();
+        assert_eq!(tcp.dstPort, 8080);
+        assert_eq!(tcp.srcPort, 40000);
+
+        // the reply is translated back to the address the socket connected to
+        let mut synAck = TcpPacket(backend, 8080, local, 40000, TCP_FLAG_SYN | TCP_FLAG_ACK);
+        let mut inCtx = ctx;
+        inCtx.inIface = ctx.nicIface;
+        assert!(nf.Process(&mut synAck, IN, &inCtx, 1));
+        let hdr = Ipv4Header::Decode(&synAck).unwrap();
+        assert_eq!(IpAddr::FromIpv4(hdr.src), vip);
+        let tcp = TcpHeader::Decode(&synAck[hdr.headerLen..], vip, local).unwrap();
+        assert_eq!(tcp.srcPort, 80);
+
+        let tuple = ConnTuple {
+            protocol: IPPROTO_TCP,
+            src: local,
+            srcPort: 40000,
+            dst: vip,
+            dstPort: 80,
+        };
+        assert_eq!(nf.OriginalDst(&tuple, 2), Some((vip, 80)));
+        assert_eq!(nf.nat.counters[2].0, 1);
+
+        // the connection expires
+        assert_eq!(
+            nf.OriginalDst(&tuple, CONNTRACK_TCP_TIMEOUT_ESTABLISHED + 2),
+            None
+        );
+    }
+
+    #[test]
+    fn test_NatSnatPortClash() {
+        // POSTROUTING -j SNAT --to 10.0.0.100
+        let snat = NewXtTarget("SNAT", 0, &CompatRange(NF_NAT_RANGE_MAP_IPS, 0x0a000064, 0));
+        let entries = vec![
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &snat),
+            Accept(AFType::AF_INET),
+            NewXtEntry(AFType::AF_INET, &NewErrorTarget(XT_ERROR_TARGET)),
+        ];
+        let opt = ReplaceOpt(
+            AFType::AF_INET,
+            &entries,
+            [0, 1, 0, 2, 3],
+            [0, 1, 0, 2, 4],
+            5,
+        );
+        let mut nf = Netfilter::default();
+        nf.nat.Replace(&opt).unwrap();
+
+        let ctx = Context();
+        let peer = Ipv4(8, 8, 8, 8);
+        let mut a = TcpPacket(Ipv4(10, 0, 0, 2), 40000, peer, 443, TCP_FLAG_SYN);
+        let mut b = TcpPacket(Ipv4(10, 0, 0, 3), 40000, peer, 443, TCP_FLAG_SYN);
+        assert!(nf.Process(&mut a, OUT, &ctx, 0));
+        assert!(nf.Process(&mut b, OUT, &ctx, 0));
+
+        let ha = Ipv4Header::Decode(&a).unwrap();
+        let hb = Ipv4Header::Decode(&b).unwrap();
+        assert_eq!(ha.src, 0x0a000064);
+        assert_eq!(hb.src, 0x0a000064);
+        let src = IpAddr::FromIpv4(0x0a000064);
+        let ta = TcpHeader::Decode(&a[ha.headerLen..], src, peer).unwrap();
+        let tb = TcpHeader::Decode(&b[hb.headerLen..], src, peer).unwrap();
+        assert_eq!(ta.srcPort, 40000);
+        assert_ne!(tb.srcPort, 40000);
+        assert!(tb.srcPort >= 1024);
+    }
+
+    #[test]
+    fn test_NatIcmpError() {
+        let mut nf = Netfilter::default();
+        nf.nat.Replace(&DnatMasqueradeOpt(5)).unwrap();
+        let ctx = Context();
+        let local = Ipv4(10, 0, 0, 2);
+        let vip = Ipv4(1, 2, 3, 4);
+        let router = Ipv4(10, 0, 0, 1);
+
+        let mut syn = TcpPacket(local, 40000, vip, 80, TCP_FLAG_SYN);
+        assert!(nf.Process(&mut syn, OUT, &ctx, 0));
+
+        // the router reports the translated syn unreachable
+        let quote = &syn[..IPV4_MIN_HEADER_SIZE + 8];
+        let (mut buf, offset) = NewIpv4Packet(
+            IPPROTO_ICMP,
+            router.Ipv4(),
+            local.Ipv4(),
+            2,
+            ICMP_HEADER_SIZE + quote.len(),
+        );
+        buf[offset + ICMP_HEADER_SIZE..].copy_from_slice(quote);
+        let hdr = IcmpHeader {
+            typ: ICMP_DEST_UNREACHABLE,
+            code: 1,
+            ident: 0,
+            seq: 0,
+        };
+        hdr.Encode(&mut buf[offset..]);
+        let mut err = buf[ETHERNET_HEADER_SIZE..].to_vec();
+        assert!(nf.Process(&mut err, IN, &ctx, 1));
+
+        // like on linux the error comes from the address the socket knows
+        let outer = Ipv4Header::Decode(&err).unwrap();
+        assert_eq!(IpAddr::FromIpv4(outer.src), vip);
+        assert_eq!(IpAddr::FromIpv4(outer.dst), local);
+        assert!(IcmpHeader::Decode(&err[outer.headerLen..]).is_some());
+        let inner = &err[outer.headerLen + ICMP_HEADER_SIZE..];
+        let innerHdr = NatPacket::Parse(inner, true).unwrap();
+        assert_eq!(innerHdr.src, local);
+        assert_eq!(innerHdr.dst, vip);
+        assert_eq!(innerHdr.dstPort, 80);
+        assert_eq!(Checksum(&inner[..IPV4_MIN_HEADER_SIZE], 0), 0);
+    }
+
+    #[test]
+    fn test_XtRevision() {
+        assert!(XtRevision(AFType::AF_INET, true, b"DNAT", 2).is_ok());
+        assert!(XtRevision(AFType::AF_INET, true, b"DNAT", 0).is_ok());
+        assert!(XtRevision(AFType::AF_INET6, true, b"DNAT", 0).is_err());
+        assert!(XtRevision(AFType::AF_INET, false, b"tcp", 0).is_ok());
+        match XtRevision(AFType::AF_INET, false, b"conntrack", 0) {
+            Err(Error::SysError(SysErr::ENOENT)) => (),
+            _ => panic!("unknown match accepted"),
+        }
+    }
+}
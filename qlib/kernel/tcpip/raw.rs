@@ -0,0 +1,584 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The raw ip (AF_INET and AF_INET6 SOCK_RAW) and packet (AF_PACKET)
+// endpoints of the netstack. They get a copy of the packets the netstack handles, so they only
+// see the traffic of the sandbox's own interface.
+
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::common::*;
+use super::super::super::linux_def::*;
+use super::super::super::mutex::*;
+use super::super::kernel::waiter::*;
+use super::super::socket::bpf::*;
+use super::header::*;
+use super::stack::*;
+
+pub const IPPROTO_RAW: u8 = 255;
+
+pub const ETH_P_ALL: u16 = 0x0003;
+
+// the sll_pkttype values, from uapi/linux/if_packet.h
+pub const PACKET_HOST: u8 = 0;
+pub const PACKET_BROADCAST: u8 = 1;
+pub const PACKET_MULTICAST: u8 = 2;
+pub const PACKET_OTHERHOST: u8 = 3;
+pub const PACKET_OUTGOING: u8 = 4;
+
+pub const ARPHRD_ETHER: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RawEndpointKind {
+    // AF_INET SOCK_RAW of the ip protocol
+    Ipv4(u8),
+    // AF_INET6 SOCK_RAW of the ip protocol, the data excludes the ipv6 header
+    Ipv6(u8),
+    // AF_PACKET SOCK_RAW, the data includes the ethernet header
+    Packet,
+    // AF_PACKET SOCK_DGRAM, the ethernet header is stripped
+    CookedPacket,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RawPacket {
+    pub data: Vec<u8>,
+    // the source ip of an ip packet
+    pub srcAddr: IpAddr,
+    // the link information of a packet endpoint's packet
+    pub protocol: u16,
+    pub pktType: u8,
+    pub srcMac: [u8; 6],
+}
+
+#[derive(Debug)]
+pub struct RawEndpointState {
+    // the ethernet protocol in host byte order a packet endpoint receives,
+    // 0 receives nothing
+    pub protocol: u16,
+    // the ifindex a packet endpoint is bound to, 0 is any
+    pub ifindex: i32,
+    // the local and peer address of a raw ip endpoint
+    pub localAddr: IpAddr,
+    pub peer: Option<IpAddr>,
+    pub hdrIncl: bool,
+    // the ICMP_FILTER of a raw icmp endpoint, bit n drops the icmp type n
+    pub icmpFilter: u32,
+    // the ICMP6_FILTER of a raw icmpv6 endpoint, bit n drops the icmpv6 type n
+    pub icmp6Filter: [u32; 8],
+    pub filter: Option<BpfProgram>,
+    pub filterLocked: bool,
+    pub rcvQueue: VecDeque<RawPacket>,
+    pub rcvBufUsed: usize,
+    pub rcvBufSize: usize,
+    pub sndBufSize: usize,
+    pub drops: u64,
+    pub rclosed: bool,
+    pub wclosed: bool,
+}
+
+pub struct RawEndpointIntern {
+    pub kind: RawEndpointKind,
+    pub queue: Queue,
+    pub state: QMutex<RawEndpointState>,
+}
+
+// RawEndpoint is the endpoint of a raw ip or packet socket.
+#[derive(Clone)]
+pub struct RawEndpoint(Arc<RawEndpointIntern>);
+
+impl Deref for RawEndpoint {
+    type Target = Arc<RawEndpointIntern>;
+
+    fn deref(&self) -> &Arc<RawEndpointIntern> {
+        &self.0
+    }
+}
+
+impl PartialEq for RawEndpoint {
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl RawEndpoint {
+    pub fn New(kind: RawEndpointKind, protocol: u16, rcvBufSize: usize, sndBufSize: usize) -> Self {
+        let state = RawEndpointState {
+            protocol: protocol,
+            ifindex: 0,
+            localAddr: match kind {
+                RawEndpointKind::Ipv6(_) => IpAddr::ANY,
+                _ => IpAddr::FromIpv4(0),
+            },
+            peer: None,
+            // linux sets IP_HDRINCL and IPV6_HDRINCL for IPPROTO_RAW
+            hdrIncl: kind == RawEndpointKind::Ipv4(IPPROTO_RAW)
+                || kind == RawEndpointKind::Ipv6(IPPROTO_RAW),
+            icmpFilter: 0,
+            icmp6Filter: [0; 8],
+            filter: None,
+            filterLocked: false,
+            rcvQueue: VecDeque::new(),
+            rcvBufUsed: 0,
+            rcvBufSize: rcvBufSize,
+            sndBufSize: sndBufSize,
+            drops: 0,
+            rclosed: false,
+            wclosed: false,
+        };
+
+        return Self(Arc::new(RawEndpointIntern {
+            kind: kind,
+            queue: Queue::default(),
+            state: QMutex::new(state),
+        }));
+    }
+
+    pub fn IsPacket(&self) -> bool {
+        return !self.IsIp();
+    }
+
+    pub fn IsIp(&self) -> bool {
+        match self.kind {
+            RawEndpointKind::Ipv4(_) | RawEndpointKind::Ipv6(_) => return true,
+            _ => return false,
+        }
+    }
+
+    // Deliver runs the socket filter over the packet and queues what it accepts.
+    // netOffset is the offset of the network header in data.
+    pub fn Deliver(&self, mut pkt: RawPacket, netOffset: usize, ifindex: i32) {
+        {
+            let mut state = self.state.lock();
+            if state.rclosed {
+                return;
+            }
+
+            if let Some(filter) = &state.filter {
+                let accept = filter.Run(&BpfPacket {
+                    data: &pkt.data,
+                    netOffset: netOffset,
+                    protocol: pkt.protocol,
+                    pktType: pkt.pktType,
+                    ifindex: ifindex,
+                }) as usize;
+
+                if accept == 0 {
+                    return;
+                }
+
+                if accept < pkt.data.len() {
+                    pkt.data.truncate(accept);
+                }
+            }
+
+            if state.rcvBufUsed + pkt.data.len() > state.rcvBufSize {
+                state.drops += 1;
+                return;
+            }
+
+            state.rcvBufUsed += pkt.data.len();
+            state.rcvQueue.push_back(pkt);
+        }
+
+        self.queue.Notify(READABLE_EVENT);
+    }
+
+    pub fn Dequeue(&self, peek: bool) -> Option<RawPacket> {
+        let mut state = self.state.lock();
+        if peek {
+            return state.rcvQueue.front().cloned();
+        }
+
+        let pkt = state.rcvQueue.pop_front()?;
+        state.rcvBufUsed -= pkt.data.len();
+        return Some(pkt);
+    }
+
+    pub fn Readiness(&self, mask: EventMask) -> EventMask {
+        let state = self.state.lock();
+        let mut events = 0;
+        if state.rcvQueue.len() > 0 || state.rclosed {
+            events |= READABLE_EVENT;
+        }
+
+        if !state.wclosed {
+            events |= WRITEABLE_EVENT;
+        }
+
+        return events & mask;
+    }
+
+    pub fn SetFilter(&self, filter: Option<BpfProgram>) -> Result<()> {
+        let mut state = self.state.lock();
+        if state.filterLocked {
+            return Err(Error::SysError(SysErr::EPERM));
+        }
+
+        if filter.is_none() && state.filter.is_none() {
+            return Err(Error::SysError(SysErr::ENOENT));
+        }
+
+        state.filter = filter;
+        return Ok(());
+    }
+}
+
+impl NetStack {
+    pub fn RawRegister(&self, ep: &RawEndpoint) {
+        self.lock().rawEndpoints.push(ep.clone());
+    }
+
+    pub fn RawUnregister(&self, ep: &RawEndpoint) {
+        self.lock().rawEndpoints.retain(|e| e != ep);
+    }
+
+    pub fn IfIndex(&self) -> i32 {
+        return self.lock().nic.ifindex;
+    }
+
+    // DeliverRawIpv4 hands a copy of an incoming ipv4 packet, header included,
+    // to the raw endpoints of its protocol
+    pub fn DeliverRawIpv4(&self, hdr: &Ipv4Header, packet: &[u8]) {
+        let (eps, ifindex) = {
+            let inner = self.lock();
+            let eps: Vec<RawEndpoint> = inner
+                .rawEndpoints
+                .iter()
+                .filter(|ep| ep.kind == RawEndpointKind::Ipv4(hdr.protocol))
+                .cloned()
+                .collect();
+            (eps, inner.nic.ifindex)
+        };
+
+        for ep in eps {
+            {
+                let state = ep.state.lock();
+                if !state.localAddr.IsAny() && state.localAddr != IpAddr::FromIpv4(hdr.dst) {
+                    continue;
+                }
+
+                if let Some(peer) = state.peer {
+                    if peer != IpAddr::FromIpv4(hdr.src) {
+                        continue;
+                    }
+                }
+
+                if hdr.protocol == IPPROTO_ICMP && packet.len() > hdr.headerLen {
+                    let typ = packet[hdr.headerLen] as u32;
+                    if typ < 32 && state.icmpFilter & (1 << typ) != 0 {
+                        continue;
+                    }
+                }
+            }
+
+            let pkt = RawPacket {
+                data: packet[..hdr.totalLen].to_vec(),
+                srcAddr: IpAddr::FromIpv4(hdr.src),
+                protocol: ETHERNET_TYPE_IPV4,
+                pktType: PACKET_HOST,
+                ..Default::default()
+            };
+            ep.Deliver(pkt, 0, ifindex);
+        }
+    }
+
+    // DeliverRawIpv6 hands a copy of the payload of an incoming ipv6 packet
+    // to the raw endpoints of its protocol. Like linux, the ipv6 raw sockets
+    // don't get the ip header.
+    pub fn DeliverRawIpv6(&self, pkt: &IpPacket) {
+        let (eps, ifindex) = {
+            let inner = self.lock();
+            let eps: Vec<RawEndpoint> = inner
+                .rawEndpoints
+                .iter()
+                .filter(|ep| ep.kind == RawEndpointKind::Ipv6(pkt.protocol))
+                .cloned()
+                .collect();
+            (eps, inner.nic.ifindex)
+        };
+
+        let payload = pkt.Payload();
+        for ep in eps {
+            {
+                let state = ep.state.lock();
+                if !state.localAddr.IsAny() && state.localAddr != pkt.dst {
+                    continue;
+                }
+
+                if let Some(peer) = state.peer {
+                    if peer != pkt.src {
+                        continue;
+                    }
+                }
+
+                if pkt.protocol == IPPROTO_ICMPV6 && payload.len() > 0 {
+                    let typ = payload[0] as usize;
+                    if state.icmp6Filter[typ >> 5] & (1 << (typ & 31)) != 0 {
+                        continue;
+                    }
+                }
+            }
+
+            let raw = RawPacket {
+                data: payload.to_vec(),
+                srcAddr: pkt.src,
+                protocol: ETHERNET_TYPE_IPV6,
+                pktType: PACKET_HOST,
+                ..Default::default()
+            };
+            ep.Deliver(raw, 0, ifindex);
+        }
+    }
+
+    // DeliverPacket hands a copy of a frame going through the link to the
+    // packet endpoints, origin is the endpoint which sends the frame
+    pub fn DeliverPacket(&self, frame: &[u8], outgoing: bool, origin: Option<&RawEndpoint>) {
+        let hdr = match EthernetHeader::Decode(frame) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        let (eps, nic) = {
+            let inner = self.lock();
+            let eps: Vec<RawEndpoint> = inner
+                .rawEndpoints
+                .iter()
+                .filter(|ep| ep.IsPacket())
+                .cloned()
+                .collect();
+            (eps, inner.nic)
+        };
+
+        if eps.len() == 0 {
+            return;
+        }
+
+        let pktType = if outgoing {
+            PACKET_OUTGOING
+        } else if hdr.dst == nic.mac {
+            PACKET_HOST
+        } else if hdr.dst == ETHERNET_BROADCAST {
+            PACKET_BROADCAST
+        } else if hdr.dst[0] & 1 != 0 {
+            PACKET_MULTICAST
+        } else {
+            PACKET_OTHERHOST
+        };
+
+        for ep in eps {
+            if let Some(origin) = origin {
+                if &ep == origin {
+                    continue;
+                }
+            }
+
+            {
+                let state = ep.state.lock();
+                if state.protocol != ETH_P_ALL && state.protocol != hdr.etherType {
+                    continue;
+                }
+
+                if state.ifindex != 0 && state.ifindex != nic.ifindex {
+                    continue;
+                }
+            }
+
+            let (data, netOffset) = match ep.kind {
+                RawEndpointKind::CookedPacket => (frame[ETHERNET_HEADER_SIZE..].to_vec(), 0),
+                _ => (frame.to_vec(), ETHERNET_HEADER_SIZE),
+            };
+
+            let pkt = RawPacket {
+                data: data,
+                srcAddr: IpAddr::default(),
+                protocol: hdr.etherType,
+                pktType: pktType,
+                srcMac: hdr.src,
+            };
+            ep.Deliver(pkt, netOffset, nic.ifindex);
+        }
+    }
+
+    // SendRawIpv4 sends the data of a raw ipv4 endpoint. The ip header is
+    // built here unless the endpoint has IP_HDRINCL.
+    pub fn SendRawIpv4(&self, ep: &RawEndpoint, dst: u32, data: &[u8]) -> Result<usize> {
+        let protocol = match ep.kind {
+            RawEndpointKind::Ipv4(protocol) => protocol,
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        };
+
+        let (hdrIncl, localAddr) = {
+            let state = ep.state.lock();
+            (state.hdrIncl, state.localAddr)
+        };
+
+        let id = self.lock().NextIpId();
+        if !hdrIncl {
+            if data.len() > 0xffff - IPV4_MIN_HEADER_SIZE {
+                return Err(Error::SysError(SysErr::EMSGSIZE));
+            }
+
+            let src = self.SourceAddr(localAddr, IpAddr::FromIpv4(dst))?.Ipv4();
+            let (mut buf, offset) = NewIpv4Packet(protocol, src, dst, id, data.len());
+            buf[offset..].copy_from_slice(data);
+            self.SendIpPacket(buf, IpAddr::FromIpv4(dst))?;
+            return Ok(data.len());
+        }
+
+        // the application supplies the header, fill in the fields linux fills
+        // in raw_send_hdrinc
+        if data.len() < IPV4_MIN_HEADER_SIZE || data.len() > 0xffff {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let headerLen = ((data[0] & 0xf) as usize) * 4;
+        if data[0] >> 4 != 4 || headerLen < IPV4_MIN_HEADER_SIZE || headerLen > data.len() {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let mut buf = Vec::with_capacity(ETHERNET_HEADER_SIZE + data.len());
+        buf.resize(ETHERNET_HEADER_SIZE, 0);
+        buf.extend_from_slice(data);
+
+        let packet = &mut buf[ETHERNET_HEADER_SIZE..];
+        PutU16(packet, 2, data.len() as u16);
+        if GetU16(packet, 4) == 0 {
+            PutU16(packet, 4, id);
+        }
+        if GetU32(packet, 12) == 0 {
+            let src = self.SourceAddr(localAddr, IpAddr::FromIpv4(dst))?.Ipv4();
+            PutU32(packet, 12, src);
+        }
+        PutU16(packet, 10, 0);
+        let csum = Checksum(&packet[0..headerLen], 0);
+        PutU16(packet, 10, csum);
+
+        // like linux, the packet with the don't fragment flag isn't fragmented
+        let mtu = self.lock().nic.mtu as usize;
+        let dontFragment = GetU16(packet, 6) & IPV4_FLAG_DONT_FRAGMENT != 0;
+        if dontFragment && mtu != 0 && data.len() > mtu && !self.IsLocalAddr(IpAddr::FromIpv4(dst))
+        {
+            return Err(Error::SysError(SysErr::EMSGSIZE));
+        }
+
+        self.SendIpPacket(buf, IpAddr::FromIpv4(dst))?;
+        return Ok(data.len());
+    }
+
+    // SendRawIpv6 sends the data of a raw ipv6 endpoint. The ipv6 header is
+    // built here unless the endpoint has IPV6_HDRINCL, the kernel fills the
+    // checksum of the icmpv6 messages like linux.
+    pub fn SendRawIpv6(&self, ep: &RawEndpoint, dst: IpAddr, data: &[u8]) -> Result<usize> {
+        let protocol = match ep.kind {
+            RawEndpointKind::Ipv6(protocol) => protocol,
+            _ => return Err(Error::SysError(SysErr::EINVAL)),
+        };
+
+        let (hdrIncl, localAddr) = {
+            let state = ep.state.lock();
+            (state.hdrIncl, state.localAddr)
+        };
+
+        if hdrIncl {
+            if data.len() < IPV6_HEADER_SIZE || data[0] >> 4 != 6 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+
+            // the packet may carry extension headers, it isn't fragmented
+            let mtu = self.lock().nic.mtu as usize;
+            if mtu != 0 && data.len() > mtu && !self.IsLocalAddr(dst) {
+                return Err(Error::SysError(SysErr::EMSGSIZE));
+            }
+
+            let mut buf = Vec::with_capacity(ETHERNET_HEADER_SIZE + data.len());
+            buf.resize(ETHERNET_HEADER_SIZE, 0);
+            buf.extend_from_slice(data);
+            self.SendIpPacket(buf, dst)?;
+            return Ok(data.len());
+        }
+
+        if data.len() > 0xffff {
+            return Err(Error::SysError(SysErr::EMSGSIZE));
+        }
+
+        let src = self.SourceAddr(localAddr, dst)?;
+        let (mut buf, offset) =
+            NewIpv6Packet(protocol, src, dst, IPV6_DEFAULT_HOP_LIMIT, data.len());
+        buf[offset..].copy_from_slice(data);
+        if protocol == IPPROTO_ICMPV6 {
+            if data.len() < ICMP_HEADER_SIZE {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            IcmpChecksum(&mut buf[offset..], IcmpPseudoSum(src, dst, data.len()));
+        }
+
+        self.SendIpPacket(buf, dst)?;
+        return Ok(data.len());
+    }
+
+    // SendPacket sends the data of a packet endpoint. The data of a cooked
+    // endpoint gets an ethernet header to dstMac with the protocol.
+    pub fn SendPacket(
+        &self,
+        ep: &RawEndpoint,
+        protocol: u16,
+        dstMac: [u8; 6],
+        data: &[u8],
+    ) -> Result<usize> {
+        let nic = {
+            let inner = self.lock();
+            if !inner.started {
+                return Err(Error::SysError(SysErr::ENETDOWN));
+            }
+            inner.nic
+        };
+
+        let maxLen = nic.mtu as usize + ETHERNET_HEADER_SIZE;
+        let frame = match ep.kind {
+            RawEndpointKind::CookedPacket => {
+                if data.len() + ETHERNET_HEADER_SIZE > maxLen {
+                    return Err(Error::SysError(SysErr::EMSGSIZE));
+                }
+
+                let mut frame = Vec::with_capacity(ETHERNET_HEADER_SIZE + data.len());
+                frame.resize(ETHERNET_HEADER_SIZE, 0);
+                frame.extend_from_slice(data);
+                let hdr = EthernetHeader {
+                    dst: dstMac,
+                    src: nic.mac,
+                    etherType: protocol,
+                };
+                hdr.Encode(&mut frame);
+                frame
+            }
+            _ => {
+                if data.len() < ETHERNET_HEADER_SIZE {
+                    return Err(Error::SysError(SysErr::EINVAL));
+                }
+
+                if data.len() > maxLen {
+                    return Err(Error::SysError(SysErr::EMSGSIZE));
+                }
+                data.to_vec()
+            }
+        };
+
+        self.WriteFrameFrom(&frame, Some(ep))?;
+        return Ok(data.len());
+    }
+}
@@ -0,0 +1,1575 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The user space network stack used when Config::EnableNetstack is set. qvisor
+// hands over an AF_PACKET socket bound to the sandbox's network interface and
+// the netstack owns the interface's ipv4 and ipv6 addresses: it answers arp
+// and neighbor solicitations, routes the outgoing packets to the gateway and
+// dispatches the incoming ones to the guest sockets, raw and packet sockets
+// get a copy of the traffic. The ip fragments are reassembled before the
+// dispatch and the outgoing packets larger than the mtu are fragmented. The
+// guest's iptables nat rules and the connection tracking apply to the packets
+// of both directions, see nat.rs. The ipv4 addresses are kept as ipv4 mapped
+// ipv6 addresses, so that a dual stack AF_INET6 socket gets the ipv4 traffic
+// like on linux.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::common::*;
+use super::super::super::linux::netfilter::*;
+use super::super::super::linux_def::*;
+use super::super::super::mutex::*;
+use super::super::super::singleton::*;
+use super::super::fd::*;
+use super::super::guestfdnotifier::*;
+use super::super::kernel::time::*;
+use super::super::kernel::timer::MonotonicNow;
+use super::super::kernel::waiter::*;
+use super::super::task::*;
+use super::super::SHARESPACE;
+use super::fragment::*;
+use super::header::*;
+use super::nat::*;
+use super::raw::*;
+use super::tcp::*;
+
+pub static NETSTACK: Singleton<NetStack> = Singleton::<NetStack>::New();
+
+pub unsafe fn InitSingleton() {
+    NETSTACK.Init(NetStack::default());
+}
+
+// the buffer has to hold a full frame even when the interface does gro
+pub const LINK_READ_BUF_SIZE: usize = 64 * 1024;
+
+pub const EPHEMERAL_PORT_START: u16 = 32768;
+pub const EPHEMERAL_PORT_END: u16 = 60999;
+
+// the max number of packets waiting for one neighbor resolution, a 64KiB
+// datagram is 45 fragments with the 1500 bytes mtu
+pub const MAX_PENDING_NEIGHBOR_PACKETS: usize = 64;
+
+pub const ICMPV6_ROUTER_SOLICIT: u8 = 133;
+pub const NDP_ROUTER_SOLICIT_SIZE: usize = 8;
+
+// NetstackNicInfo is the network interface handed over from qvisor.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetstackNicInfo {
+    // the AF_PACKET socket bound to the interface
+    pub fd: i32,
+    pub mac: [u8; 6],
+    // ipv4 address, prefix and default gateway in host byte order
+    pub addr: u32,
+    pub prefixLen: u8,
+    pub gateway: u32,
+    // the global ipv6 address and its prefix, the link local address and the
+    // default router. They are IpAddr::ANY when the interface has no ipv6.
+    pub addr6: IpAddr,
+    pub prefixLen6: u8,
+    pub linkLocal6: IpAddr,
+    pub gateway6: IpAddr,
+    pub mtu: u32,
+    pub ifindex: i32,
+    // the nul terminated name of the host interface, the nat rules match it
+    pub ifname: [u8; 16],
+}
+
+impl NetstackNicInfo {
+    pub fn Mask(&self) -> u32 {
+        if self.prefixLen == 0 {
+            return 0;
+        }
+
+        return !0u32 << (32 - self.prefixLen as u32);
+    }
+
+    pub fn OnLink(&self, ip: u32) -> bool {
+        return ip & self.Mask() == self.addr & self.Mask();
+    }
+
+    pub fn Broadcast(&self) -> u32 {
+        return self.addr | !self.Mask();
+    }
+
+    pub fn HasIpv6(&self) -> bool {
+        return !self.linkLocal6.IsAny();
+    }
+
+    // IsLocal returns whether ip is one of the netstack's own addresses
+    pub fn IsLocal(&self, ip: IpAddr) -> bool {
+        if ip.IsLoopback() {
+            return true;
+        }
+
+        if ip.IsIpv4() {
+            return self.addr != 0 && ip.Ipv4() == self.addr;
+        }
+
+        return !ip.IsAny() && (ip == self.addr6 || ip == self.linkLocal6);
+    }
+
+    pub fn IsBroadcast(&self, ip: IpAddr) -> bool {
+        return ip.IsIpv4() && (ip.Ipv4() == 0xffff_ffff || ip.Ipv4() == self.Broadcast());
+    }
+
+    // InMulticastGroup returns whether the netstack listens on the ipv6
+    // multicast address: all nodes and the solicited node addresses of its
+    // own addresses
+    pub fn InMulticastGroup(&self, ip: IpAddr) -> bool {
+        if !self.HasIpv6() {
+            return false;
+        }
+
+        if ip == IpAddr::ALL_NODES || ip == self.linkLocal6.SolicitedNode() {
+            return true;
+        }
+
+        return !self.addr6.IsAny() && ip == self.addr6.SolicitedNode();
+    }
+
+    pub fn NextHop(&self, dst: IpAddr) -> IpAddr {
+        if dst.IsIpv4() {
+            if self.OnLink(dst.Ipv4()) || self.gateway == 0 {
+                return dst;
+            }
+
+            return IpAddr::FromIpv4(self.gateway);
+        }
+
+        let onLink = !self.addr6.IsAny() && dst.PrefixMatch(&self.addr6, self.prefixLen6 as usize);
+        if dst.IsLinkLocal() || onLink || self.gateway6.IsAny() {
+            return dst;
+        }
+
+        return self.gateway6;
+    }
+}
+
+// AnyAddr returns the any address of the socket family
+pub fn AnyAddr(family: i32) -> IpAddr {
+    if family == AFType::AF_INET6 {
+        return IpAddr::ANY;
+    }
+
+    return IpAddr::FromIpv4(0);
+}
+
+// LocalAddrMatch returns whether an endpoint bound to local receives the
+// packets to dst. The ipv6 any address takes the ipv4 packets too unless
+// the endpoint is IPV6_V6ONLY.
+pub fn LocalAddrMatch(local: IpAddr, v6only: bool, dst: IpAddr) -> bool {
+    if local == IpAddr::ANY {
+        return !dst.IsIpv4() || !v6only;
+    }
+
+    if local.IsIpv4() && local.Ipv4() == 0 {
+        return dst.IsIpv4();
+    }
+
+    return local == dst;
+}
+
+// LocalAddrOverlap returns whether two endpoints bound to the same port
+// would receive the same packets
+pub fn LocalAddrOverlap(a: IpAddr, av6only: bool, b: IpAddr, bv6only: bool) -> bool {
+    if a.IsAny() {
+        if b.IsAny() {
+            // the ipv4 any address and a v6only any address are disjoint
+            return a == b || (a == IpAddr::ANY && !av6only) || (b == IpAddr::ANY && !bv6only);
+        }
+        return LocalAddrMatch(a, av6only, b);
+    }
+
+    if b.IsAny() {
+        return LocalAddrMatch(b, bv6only, a);
+    }
+
+    return a == b;
+}
+
+// IpPacket is an incoming ipv4 or ipv6 packet handed to the transport layer
+pub struct IpPacket<'a> {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: u8,
+    // the ttl or the hop limit
+    pub ttl: u8,
+    // the packet from the ip header, the transport message starts at payloadOffset
+    pub data: &'a [u8],
+    pub payloadOffset: usize,
+    // whether the packet is sent to a broadcast or multicast address
+    pub broadcast: bool,
+}
+
+impl<'a> IpPacket<'a> {
+    pub fn Payload(&self) -> &'a [u8] {
+        return &self.data[self.payloadOffset..];
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatagramPacket {
+    pub srcAddr: IpAddr,
+    pub srcPort: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct DatagramEndpointState {
+    pub localAddr: IpAddr,
+    pub localPort: u16,
+    pub peer: Option<(IpAddr, u16)>,
+    // IPV6_V6ONLY of an AF_INET6 endpoint
+    pub v6only: bool,
+    pub rcvQueue: VecDeque<DatagramPacket>,
+    pub rcvBufUsed: usize,
+    pub rcvBufSize: usize,
+    pub sndBufSize: usize,
+    pub rclosed: bool,
+    pub wclosed: bool,
+}
+
+pub struct DatagramEndpointIntern {
+    // AF_INET or AF_INET6
+    pub family: i32,
+    // IPPROTO_UDP, IPPROTO_ICMP or IPPROTO_ICMPV6
+    pub protocol: u8,
+    pub queue: Queue,
+    pub state: QMutex<DatagramEndpointState>,
+}
+
+// DatagramEndpoint is the transport endpoint of an udp or icmp echo socket.
+#[derive(Clone)]
+pub struct DatagramEndpoint(Arc<DatagramEndpointIntern>);
+
+impl Deref for DatagramEndpoint {
+    type Target = Arc<DatagramEndpointIntern>;
+
+    fn deref(&self) -> &Arc<DatagramEndpointIntern> {
+        &self.0
+    }
+}
+
+impl PartialEq for DatagramEndpoint {
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl DatagramEndpoint {
+    pub fn New(family: i32, protocol: u8, rcvBufSize: usize, sndBufSize: usize) -> Self {
+        let state = DatagramEndpointState {
+            localAddr: AnyAddr(family),
+            localPort: 0,
+            peer: None,
+            // the ping6 sockets only see icmpv6
+            v6only: protocol == IPPROTO_ICMPV6,
+            rcvQueue: VecDeque::new(),
+            rcvBufUsed: 0,
+            rcvBufSize: rcvBufSize,
+            sndBufSize: sndBufSize,
+            rclosed: false,
+            wclosed: false,
+        };
+
+        return Self(Arc::new(DatagramEndpointIntern {
+            family: family,
+            protocol: protocol,
+            queue: Queue::default(),
+            state: QMutex::new(state),
+        }));
+    }
+
+    // Enqueue returns false when the packet is dropped as the receive buffer is full
+    pub fn Enqueue(&self, pkt: DatagramPacket) -> bool {
+        {
+            let mut state = self.state.lock();
+            if state.rclosed || state.rcvBufUsed + pkt.data.len() > state.rcvBufSize {
+                return false;
+            }
+
+            if let Some((addr, port)) = state.peer {
+                if addr != pkt.srcAddr || (port != 0 && port != pkt.srcPort) {
+                    return false;
+                }
+            }
+
+            state.rcvBufUsed += pkt.data.len();
+            state.rcvQueue.push_back(pkt);
+        }
+
+        self.queue.Notify(READABLE_EVENT);
+        return true;
+    }
+
+    pub fn Dequeue(&self, peek: bool) -> Option<DatagramPacket> {
+        let mut state = self.state.lock();
+        if peek {
+            return state.rcvQueue.front().cloned();
+        }
+
+        let pkt = state.rcvQueue.pop_front()?;
+        state.rcvBufUsed -= pkt.data.len();
+        return Some(pkt);
+    }
+
+    pub fn Readiness(&self, mask: EventMask) -> EventMask {
+        let state = self.state.lock();
+        let mut events = 0;
+        if state.rcvQueue.len() > 0 || state.rclosed {
+            events |= READABLE_EVENT;
+        }
+
+        if !state.wclosed {
+            events |= WRITEABLE_EVENT;
+        }
+
+        return events & mask;
+    }
+}
+
+#[derive(Default)]
+pub struct NetStackInner {
+    pub nic: NetstackNicInfo,
+    pub started: bool,
+
+    // the arp and ndp neighbor cache, ip --> ethernet address
+    pub neighbors: BTreeMap<IpAddr, [u8; 6]>,
+    // next hop ip --> ethernet frames waiting for the neighbor resolution
+    pub pendingNeighbors: BTreeMap<IpAddr, VecDeque<Vec<u8>>>,
+
+    // bound port --> endpoints, the local addresses of an entry don't overlap
+    pub udpEndpoints: BTreeMap<u16, Vec<DatagramEndpoint>>,
+    // echo identifier --> endpoints of the ipv4 and ipv6 ping sockets
+    pub icmpEndpoints: BTreeMap<u16, Vec<DatagramEndpoint>>,
+    pub icmp6Endpoints: BTreeMap<u16, Vec<DatagramEndpoint>>,
+
+    // port --> the tcp endpoints bound to it
+    pub tcpPorts: BTreeMap<u16, Vec<TcpEndpoint>>,
+    pub tcpListeners: BTreeMap<u16, Vec<TcpEndpoint>>,
+    pub tcpConnections: BTreeMap<TcpConnId, TcpEndpoint>,
+
+    // the raw ip and packet endpoints
+    pub rawEndpoints: Vec<RawEndpoint>,
+
+    // the fragments of the incoming datagrams
+    pub reassembler: Reassembler,
+
+    // the iptables nat tables and the connection tracking
+    pub netfilter: Netfilter,
+
+    // the packets sent to the netstack's own address
+    pub loopbackQueue: VecDeque<Vec<u8>>,
+    pub loopbackDraining: bool,
+
+    // the netstack process waits on it for the link fd
+    pub linkQueue: Queue,
+
+    pub nextEphemeralPort: u16,
+    pub ipId: u16,
+    pub ipv6FragmentId: u32,
+}
+
+impl NetStackInner {
+    pub fn AllocPort(&mut self, protocol: u8) -> Result<u16> {
+        if self.nextEphemeralPort < EPHEMERAL_PORT_START {
+            self.nextEphemeralPort = EPHEMERAL_PORT_START;
+        }
+
+        let count = (EPHEMERAL_PORT_END - EPHEMERAL_PORT_START) as usize + 1;
+        for _ in 0..count {
+            let port = self.nextEphemeralPort;
+            self.nextEphemeralPort = if port == EPHEMERAL_PORT_END {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+
+            let used = match protocol {
+                IPPROTO_TCP => self.tcpPorts.contains_key(&port),
+                _ => self.Endpoints(protocol).contains_key(&port),
+            };
+
+            if !used {
+                return Ok(port);
+            }
+        }
+
+        return Err(Error::SysError(SysErr::EADDRINUSE));
+    }
+
+    pub fn Endpoints(&mut self, protocol: u8) -> &mut BTreeMap<u16, Vec<DatagramEndpoint>> {
+        match protocol {
+            IPPROTO_UDP => return &mut self.udpEndpoints,
+            IPPROTO_ICMPV6 => return &mut self.icmp6Endpoints,
+            _ => return &mut self.icmpEndpoints,
+        }
+    }
+
+    // FindDatagramEndpoint returns the endpoint bound to port which receives
+    // the packets to dst
+    pub fn FindDatagramEndpoint(
+        &mut self,
+        protocol: u8,
+        port: u16,
+        dst: IpAddr,
+    ) -> Option<DatagramEndpoint> {
+        let eps = self.Endpoints(protocol).get(&port)?;
+        for ep in eps {
+            let state = ep.state.lock();
+            if LocalAddrMatch(state.localAddr, state.v6only, dst) {
+                return Some(ep.clone());
+            }
+        }
+
+        return None;
+    }
+
+    pub fn NextIpId(&mut self) -> u16 {
+        self.ipId = self.ipId.wrapping_add(1);
+        return self.ipId;
+    }
+
+    // NatContext returns the interfaces the nat rules see, the incoming
+    // packets come from the nic or from the loopback
+    pub fn NatContext(&self, incoming: bool, loopback: bool) -> NatContext {
+        let inIface = if !incoming {
+            [0; XT_IFNAMSIZ]
+        } else if loopback {
+            LOOPBACK_IFACE
+        } else {
+            self.nic.ifname
+        };
+
+        return NatContext {
+            inIface: inIface,
+            nicIface: self.nic.ifname,
+            addr: IpAddr::FromIpv4(self.nic.addr),
+            addr6: self.nic.addr6,
+            linkLocal6: self.nic.linkLocal6,
+        };
+    }
+
+    // NatIncoming runs the nat PREROUTING and INPUT hooks of the packet, it
+    // returns false when the packet is dropped
+    pub fn NatIncoming(&mut self, packet: &mut [u8], loopback: bool) -> bool {
+        let ctx = self.NatContext(true, loopback);
+        let hooks = [NF_INET_PRE_ROUTING, NF_INET_LOCAL_IN];
+        return self.netfilter.Process(packet, hooks, &ctx, MonotonicNow());
+    }
+
+    pub fn NextIpv6FragmentId(&mut self) -> u32 {
+        self.ipv6FragmentId = self.ipv6FragmentId.wrapping_add(1);
+        return self.ipv6FragmentId;
+    }
+}
+
+#[derive(Default)]
+pub struct NetStack(QMutex<NetStackInner>);
+
+impl Deref for NetStack {
+    type Target = QMutex<NetStackInner>;
+
+    fn deref(&self) -> &QMutex<NetStackInner> {
+        &self.0
+    }
+}
+
+impl NetStack {
+    pub fn Enabled() -> bool {
+        return SHARESPACE.config.read().EnableNetstack;
+    }
+
+    pub fn IsLocalAddr(&self, ip: IpAddr) -> bool {
+        return self.lock().nic.IsLocal(ip);
+    }
+
+    // Bind binds the endpoint to addr:port, port 0 means an ephemeral port
+    pub fn Bind(&self, ep: &DatagramEndpoint, addr: IpAddr, port: u16) -> Result<u16> {
+        let mut inner = self.lock();
+        if !addr.IsAny() && !inner.nic.IsLocal(addr) && !inner.nic.IsBroadcast(addr) {
+            return Err(Error::SysError(SysErr::EADDRNOTAVAIL));
+        }
+
+        let v6only = {
+            let state = ep.state.lock();
+            if state.localPort != 0 {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            state.v6only
+        };
+
+        let port = if port == 0 {
+            inner.AllocPort(ep.protocol)?
+        } else {
+            if let Some(eps) = inner.Endpoints(ep.protocol).get(&port) {
+                for other in eps {
+                    let state = other.state.lock();
+                    if LocalAddrOverlap(addr, v6only, state.localAddr, state.v6only) {
+                        return Err(Error::SysError(SysErr::EADDRINUSE));
+                    }
+                }
+            }
+            port
+        };
+
+        inner
+            .Endpoints(ep.protocol)
+            .entry(port)
+            .or_insert(Vec::new())
+            .push(ep.clone());
+        let mut state = ep.state.lock();
+        state.localAddr = addr;
+        state.localPort = port;
+        return Ok(port);
+    }
+
+    pub fn Unbind(&self, ep: &DatagramEndpoint) {
+        let port = ep.state.lock().localPort;
+        if port == 0 {
+            return;
+        }
+
+        let mut inner = self.lock();
+        let endpoints = inner.Endpoints(ep.protocol);
+        let remove = match endpoints.get_mut(&port) {
+            None => false,
+            Some(eps) => {
+                eps.retain(|e| e != ep);
+                eps.len() == 0
+            }
+        };
+
+        if remove {
+            endpoints.remove(&port);
+        }
+    }
+
+    // SendUdp sends data from the endpoint's bound port, the endpoint is bound
+    // to an ephemeral port first if needed
+    pub fn SendUdp(
+        &self,
+        ep: &DatagramEndpoint,
+        dst: IpAddr,
+        dstPort: u16,
+        data: &[u8],
+    ) -> Result<usize> {
+        let (localAddr, localPort) = self.AutoBind(ep)?;
+        let src = self.SourceAddr(localAddr, dst)?;
+        let len = UDP_HEADER_SIZE + data.len();
+        let maxLen = if dst.IsIpv4() {
+            0xffff - IPV4_MIN_HEADER_SIZE
+        } else {
+            0xffff
+        };
+        if len > maxLen {
+            return Err(Error::SysError(SysErr::EMSGSIZE));
+        }
+
+        let (mut buf, offset) = self.NewIpPacket(IPPROTO_UDP, src, dst, len);
+        buf[offset + UDP_HEADER_SIZE..].copy_from_slice(data);
+        let hdr = UdpHeader {
+            srcPort: localPort,
+            dstPort: dstPort,
+            len: len,
+        };
+        hdr.Encode(&mut buf[offset..], src, dst);
+
+        self.SendIpPacket(buf, dst)?;
+        return Ok(data.len());
+    }
+
+    // SendIcmpEcho sends an echo request of a ping socket. data starts with the
+    // icmp header filled by the application, the identifier is replaced with
+    // the endpoint's bound id like linux does.
+    pub fn SendIcmpEcho(&self, ep: &DatagramEndpoint, dst: IpAddr, data: &[u8]) -> Result<usize> {
+        if data.len() < ICMP_HEADER_SIZE {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let (protocol, request) = if dst.IsIpv4() {
+            (IPPROTO_ICMP, ICMP_ECHO_REQUEST)
+        } else {
+            (IPPROTO_ICMPV6, ICMPV6_ECHO_REQUEST)
+        };
+
+        if data[0] != request || data[1] != 0 {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        let (localAddr, ident) = self.AutoBind(ep)?;
+        let src = self.SourceAddr(localAddr, dst)?;
+
+        let (mut buf, offset) = self.NewIpPacket(protocol, src, dst, data.len());
+        buf[offset..].copy_from_slice(data);
+        let hdr = IcmpHeader {
+            typ: request,
+            code: 0,
+            ident: ident,
+            seq: GetU16(data, 6),
+        };
+        hdr.EncodeWithSum(&mut buf[offset..], IcmpPseudoSum(src, dst, data.len()));
+
+        self.SendIpPacket(buf, dst)?;
+        return Ok(data.len());
+    }
+
+    pub fn AutoBind(&self, ep: &DatagramEndpoint) -> Result<(IpAddr, u16)> {
+        let localAddr = {
+            let state = ep.state.lock();
+            if state.localPort != 0 {
+                return Ok((state.localAddr, state.localPort));
+            }
+            state.localAddr
+        };
+
+        let port = self.Bind(ep, localAddr, 0)?;
+        return Ok((localAddr, port));
+    }
+
+    // SourceAddr returns the source address of the packets to dst from an
+    // endpoint bound to localAddr
+    pub fn SourceAddr(&self, localAddr: IpAddr, dst: IpAddr) -> Result<IpAddr> {
+        let nic = self.lock().nic;
+        if !localAddr.IsAny() && !nic.IsBroadcast(localAddr) {
+            if localAddr.IsIpv4() != dst.IsIpv4() {
+                return Err(Error::SysError(SysErr::ENETUNREACH));
+            }
+            return Ok(localAddr);
+        }
+
+        if dst.IsLoopback() {
+            if dst.IsIpv4() {
+                return Ok(dst);
+            }
+            return Ok(IpAddr::LOOPBACK);
+        }
+
+        if dst.IsIpv4() {
+            return Ok(IpAddr::FromIpv4(nic.addr));
+        }
+
+        if !nic.HasIpv6() {
+            return Err(Error::SysError(SysErr::ENETUNREACH));
+        }
+
+        // the link local and the link scope multicast destinations
+        let linkScope = dst.IsLinkLocal() || (dst.IsMulticast() && dst.0[1] & 0xf == 2);
+        if linkScope {
+            return Ok(nic.linkLocal6);
+        }
+
+        if nic.addr6.IsAny() {
+            return Err(Error::SysError(SysErr::ENETUNREACH));
+        }
+
+        return Ok(nic.addr6);
+    }
+
+    // NewIpPacket builds an ipv4 or ipv6 packet per the family of dst, see
+    // NewIpv4Packet and NewIpv6Packet
+    pub fn NewIpPacket(
+        &self,
+        protocol: u8,
+        src: IpAddr,
+        dst: IpAddr,
+        payloadLen: usize,
+    ) -> (Vec<u8>, usize) {
+        if dst.IsIpv4() {
+            let id = self.lock().NextIpId();
+            return NewIpv4Packet(protocol, src.Ipv4(), dst.Ipv4(), id, payloadLen);
+        }
+
+        return NewIpv6Packet(protocol, src, dst, IPV6_DEFAULT_HOP_LIMIT, payloadLen);
+    }
+
+    // SendIpPacket sends an ip packet built by NewIpPacket, the ethernet
+    // header room in front of it is filled here. The packet goes through the
+    // nat OUTPUT and POSTROUTING hooks and is fragmented when it doesn't fit
+    // in the mtu.
+    pub fn SendIpPacket(&self, mut frame: Vec<u8>, dst: IpAddr) -> Result<()> {
+        let mut dst = dst;
+        let nic = {
+            let mut inner = self.lock();
+            if inner.netfilter.Active() {
+                let ctx = inner.NatContext(false, false);
+                let hooks = [NF_INET_LOCAL_OUT, NF_INET_POST_ROUTING];
+                let packet = &mut frame[ETHERNET_HEADER_SIZE..];
+                if !inner.netfilter.Process(packet, hooks, &ctx, MonotonicNow()) {
+                    return Err(Error::SysError(SysErr::EPERM));
+                }
+                dst = IpDst(packet).unwrap_or(dst);
+            }
+            inner.nic
+        };
+
+        if nic.IsLocal(dst) {
+            frame.drain(..ETHERNET_HEADER_SIZE);
+            self.DeliverLoopback(frame);
+            return Ok(());
+        }
+
+        let mtu = nic.mtu as usize;
+        if mtu != 0 && frame.len() - ETHERNET_HEADER_SIZE > mtu {
+            let frames = if dst.IsIpv4() {
+                FragmentIpv4(&frame, mtu)
+            } else {
+                let id = self.lock().NextIpv6FragmentId();
+                FragmentIpv6(&frame, mtu, id)
+            };
+
+            for f in frames {
+                self.SendIpFrame(f, dst)?;
+            }
+            return Ok(());
+        }
+
+        return self.SendIpFrame(frame, dst);
+    }
+
+    pub fn SendIpFrame(&self, mut frame: Vec<u8>, dst: IpAddr) -> Result<()> {
+        let mut inner = self.lock();
+        if !inner.started {
+            return Err(Error::SysError(SysErr::ENETUNREACH));
+        }
+
+        let nic = inner.nic;
+        let nextHop = nic.NextHop(dst);
+        let dstMac = if nic.IsBroadcast(dst) {
+            Some(ETHERNET_BROADCAST)
+        } else if dst.IsMulticast() {
+            Some(dst.MulticastMac())
+        } else {
+            inner.neighbors.get(&nextHop).cloned()
+        };
+
+        let hdr = EthernetHeader {
+            dst: dstMac.unwrap_or([0; 6]),
+            src: nic.mac,
+            etherType: if dst.IsIpv4() {
+                ETHERNET_TYPE_IPV4
+            } else {
+                ETHERNET_TYPE_IPV6
+            },
+        };
+        hdr.Encode(&mut frame);
+
+        match dstMac {
+            Some(_) => {
+                core::mem::drop(inner);
+                return self.WriteFrame(&frame);
+            }
+            None => {
+                let first = !inner.pendingNeighbors.contains_key(&nextHop);
+                let pending = inner
+                    .pendingNeighbors
+                    .entry(nextHop)
+                    .or_insert(VecDeque::new());
+                if pending.len() >= MAX_PENDING_NEIGHBOR_PACKETS {
+                    pending.pop_front();
+                }
+                pending.push_back(frame);
+                core::mem::drop(inner);
+
+                if first {
+                    self.SolicitNeighbor(nextHop)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // DeliverLoopback handles the packets sent to the netstack itself. They are
+    // queued and handled one by one so that a tcp exchange over loopback doesn't
+    // recurse.
+    pub fn DeliverLoopback(&self, packet: Vec<u8>) {
+        {
+            let mut inner = self.lock();
+            inner.loopbackQueue.push_back(packet);
+            if inner.loopbackDraining {
+                return;
+            }
+            inner.loopbackDraining = true;
+        }
+
+        loop {
+            let packet = {
+                let mut inner = self.lock();
+                match inner.loopbackQueue.pop_front() {
+                    None => {
+                        inner.loopbackDraining = false;
+                        return;
+                    }
+                    Some(p) => p,
+                }
+            };
+
+            if packet.len() > 0 && packet[0] >> 4 == 6 {
+                self.HandleIpv6(&packet, true);
+            } else {
+                self.HandleIpv4(&packet, true);
+            }
+        }
+    }
+
+    // Wakeup makes the netstack process recheck its timer
+    pub fn Wakeup(&self) {
+        let queue = self.lock().linkQueue.clone();
+        queue.Notify(READABLE_EVENT);
+    }
+
+    // SolicitNeighbor sends the arp request or the neighbor solicitation of ip
+    pub fn SolicitNeighbor(&self, ip: IpAddr) -> Result<()> {
+        if ip.IsIpv4() {
+            return self.SendArp(ARP_REQUEST, ETHERNET_BROADCAST, [0; 6], ip.Ipv4());
+        }
+
+        return self.SendNdp(ICMPV6_NEIGHBOR_SOLICIT, 0, ip, ip.SolicitedNode());
+    }
+
+    // ResolveNeighbor records the ethernet address of ip and sends the frames
+    // waiting for it
+    pub fn ResolveNeighbor(&self, ip: IpAddr, mac: [u8; 6]) {
+        let pending = {
+            let mut inner = self.lock();
+            inner.neighbors.insert(ip, mac);
+            inner.pendingNeighbors.remove(&ip)
+        };
+
+        if let Some(frames) = pending {
+            for mut frame in frames {
+                frame[0..6].copy_from_slice(&mac);
+                self.WriteFrame(&frame).ok();
+            }
+        }
+    }
+
+    pub fn SendArp(
+        &self,
+        op: u16,
+        dstMac: [u8; 6],
+        targetMac: [u8; 6],
+        targetIp: u32,
+    ) -> Result<()> {
+        let nic = self.lock().nic;
+        let mut frame = Vec::with_capacity(ETHERNET_HEADER_SIZE + ARP_PACKET_SIZE);
+        frame.resize(ETHERNET_HEADER_SIZE + ARP_PACKET_SIZE, 0);
+
+        let hdr = EthernetHeader {
+            dst: dstMac,
+            src: nic.mac,
+            etherType: ETHERNET_TYPE_ARP,
+        };
+        hdr.Encode(&mut frame);
+
+        let pkt = ArpPacket {
+            op: op,
+            senderMac: nic.mac,
+            senderIp: nic.addr,
+            targetMac: targetMac,
+            targetIp: targetIp,
+        };
+        pkt.Encode(&mut frame[ETHERNET_HEADER_SIZE..]);
+
+        return self.WriteFrame(&frame);
+    }
+
+    // SendNdp sends a neighbor solicitation or advertisement of target to dst
+    // with the netstack's ethernet address
+    pub fn SendNdp(&self, typ: u8, flags: u8, target: IpAddr, dst: IpAddr) -> Result<()> {
+        let nic = self.lock().nic;
+        if !nic.HasIpv6() {
+            return Err(Error::SysError(SysErr::ENETUNREACH));
+        }
+
+        // the advertisement comes from the target, the solicitation from our
+        // address on the target's link
+        let src = if typ == ICMPV6_NEIGHBOR_ADVERT {
+            target
+        } else if target.IsLinkLocal() || nic.addr6.IsAny() {
+            nic.linkLocal6
+        } else {
+            nic.addr6
+        };
+
+        let pkt = NdpPacket {
+            typ: typ,
+            flags: flags,
+            target: target,
+            linkAddr: Some(nic.mac),
+        };
+        let (mut buf, offset) = NewIpv6Packet(IPPROTO_ICMPV6, src, dst, NDP_HOP_LIMIT, pkt.Size());
+        pkt.Encode(&mut buf[offset..], IcmpPseudoSum(src, dst, pkt.Size()));
+        return self.SendIpFrame(buf, dst);
+    }
+
+    // SendRouterSolicit asks the routers on the link for an advertisement
+    // when qvisor hasn't found the ipv6 default router
+    pub fn SendRouterSolicit(&self) -> Result<()> {
+        let nic = self.lock().nic;
+        let len = NDP_ROUTER_SOLICIT_SIZE + 8;
+        let dst = IpAddr([0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        let (mut buf, offset) =
+            NewIpv6Packet(IPPROTO_ICMPV6, nic.linkLocal6, dst, NDP_HOP_LIMIT, len);
+        let msg = &mut buf[offset..];
+        msg[0] = ICMPV6_ROUTER_SOLICIT;
+        msg[NDP_ROUTER_SOLICIT_SIZE] = NDP_OPTION_SOURCE_LINK_ADDR;
+        msg[NDP_ROUTER_SOLICIT_SIZE + 1] = 1;
+        msg[NDP_ROUTER_SOLICIT_SIZE + 2..].copy_from_slice(&nic.mac);
+        IcmpChecksum(msg, IcmpPseudoSum(nic.linkLocal6, dst, len));
+        return self.SendIpFrame(buf, dst);
+    }
+
+    pub fn WriteFrame(&self, frame: &[u8]) -> Result<()> {
+        return self.WriteFrameFrom(frame, None);
+    }
+
+    // WriteFrameFrom writes a frame to the link, origin is the packet
+    // endpoint sending it which doesn't get a copy of its own frame
+    pub fn WriteFrameFrom(&self, frame: &[u8], origin: Option<&RawEndpoint>) -> Result<()> {
+        self.DeliverPacket(frame, true, origin);
+
+        let fd = self.lock().nic.fd;
+        let iovs = [IoVec::New(frame)];
+        match IOWrite(fd, &iovs) {
+            Err(Error::SysError(SysErr::EAGAIN)) => {
+                // the link is congested, drop the frame like a nic does
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+            Ok(_) => return Ok(()),
+        }
+    }
+
+    pub fn HandleFrame(&self, frame: &[u8]) {
+        let hdr = match EthernetHeader::Decode(frame) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        self.DeliverPacket(frame, false, None);
+
+        // the ipv6 multicast frames are filtered by their ip address
+        let mac = self.lock().nic.mac;
+        let multicast6 = hdr.dst[0] == 0x33 && hdr.dst[1] == 0x33;
+        if hdr.dst != mac && hdr.dst != ETHERNET_BROADCAST && !multicast6 {
+            return;
+        }
+
+        let payload = &frame[ETHERNET_HEADER_SIZE..];
+        match hdr.etherType {
+            ETHERNET_TYPE_ARP => self.HandleArp(payload),
+            ETHERNET_TYPE_IPV4 => self.HandleIpv4(payload, false),
+            ETHERNET_TYPE_IPV6 => self.HandleIpv6(payload, false),
+            _ => (),
+        }
+    }
+
+    pub fn HandleArp(&self, payload: &[u8]) {
+        let pkt = match ArpPacket::Decode(payload) {
+            None => return,
+            Some(pkt) => pkt,
+        };
+
+        if pkt.senderIp != 0 {
+            self.ResolveNeighbor(IpAddr::FromIpv4(pkt.senderIp), pkt.senderMac);
+        }
+
+        let nic = self.lock().nic;
+        if pkt.op == ARP_REQUEST && pkt.targetIp == nic.addr {
+            self.SendArp(ARP_REPLY, pkt.senderMac, pkt.senderMac, pkt.senderIp)
+                .ok();
+        }
+    }
+
+    // HandleIpv4 handles an incoming ipv4 packet, loopback is set for the
+    // packets the netstack sent to itself
+    pub fn HandleIpv4(&self, packet: &[u8], loopback: bool) {
+        let hdr = match Ipv4Header::Decode(packet) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        // the fragments go through the nat once reassembled
+        let translated;
+        let (packet, hdr) = if !hdr.IsFragment() && self.lock().netfilter.Active() {
+            let mut copy = packet[..hdr.totalLen].to_vec();
+            if !self.lock().NatIncoming(&mut copy, loopback) {
+                return;
+            }
+
+            translated = copy;
+            match Ipv4Header::Decode(&translated) {
+                None => return,
+                Some(hdr) => (&translated[..], hdr),
+            }
+        } else {
+            (packet, hdr)
+        };
+
+        let nic = self.lock().nic;
+        let dst = IpAddr::FromIpv4(hdr.dst);
+        let broadcast = nic.IsBroadcast(dst);
+        if !nic.IsLocal(dst) && !broadcast {
+            // the netstack doesn't forward packets
+            return;
+        }
+
+        if hdr.IsFragment() {
+            if let Some(packet) = self.ReassembleIpv4(&hdr, packet) {
+                self.HandleIpv4(&packet, loopback);
+            }
+            return;
+        }
+
+        self.DeliverRawIpv4(&hdr, packet);
+
+        let pkt = IpPacket {
+            src: IpAddr::FromIpv4(hdr.src),
+            dst: dst,
+            protocol: hdr.protocol,
+            ttl: hdr.ttl,
+            data: &packet[..hdr.totalLen],
+            payloadOffset: hdr.headerLen,
+            broadcast: broadcast,
+        };
+
+        match hdr.protocol {
+            IPPROTO_ICMP => self.HandleIcmp(&pkt),
+            IPPROTO_UDP => self.HandleUdp(&pkt),
+            IPPROTO_TCP => self.HandleTcp(&pkt),
+            _ => (),
+        }
+    }
+
+    // ReassembleIpv4 queues the fragment and returns the datagram when all
+    // its fragments have arrived
+    pub fn ReassembleIpv4(&self, hdr: &Ipv4Header, packet: &[u8]) -> Option<Vec<u8>> {
+        let offset = ((hdr.flagsFragment & IPV4_FRAGMENT_OFFSET_MASK) as usize) * 8;
+        let more = hdr.flagsFragment & IPV4_FLAG_MORE_FRAGMENTS != 0;
+        let datagram = self.lock().reassembler.Process(
+            FragmentKey::Ipv4(hdr),
+            offset,
+            more,
+            &packet[..hdr.headerLen],
+            &packet[hdr.headerLen..hdr.totalLen],
+            MonotonicNow(),
+        )?;
+
+        return Some(Ipv4Reassembled(&datagram));
+    }
+
+    // HandleIpv6 handles an incoming ipv6 packet, loopback is set for the
+    // packets the netstack sent to itself
+    pub fn HandleIpv6(&self, packet: &[u8], loopback: bool) {
+        let hdr = match Ipv6Header::Decode(packet) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        // the nat leaves the fragments alone, they go through it once
+        // reassembled
+        let translated;
+        let (packet, hdr) = if self.lock().netfilter.Active() {
+            let mut copy = packet[..IPV6_HEADER_SIZE + hdr.payloadLen].to_vec();
+            if !self.lock().NatIncoming(&mut copy, loopback) {
+                return;
+            }
+
+            translated = copy;
+            match Ipv6Header::Decode(&translated) {
+                None => return,
+                Some(hdr) => (&translated[..], hdr),
+            }
+        } else {
+            (packet, hdr)
+        };
+
+        let nic = self.lock().nic;
+        let multicast = hdr.dst.IsMulticast();
+        if hdr.dst.IsIpv4() || !(nic.IsLocal(hdr.dst) || multicast && nic.InMulticastGroup(hdr.dst))
+        {
+            return;
+        }
+
+        // walk the extension headers to the upper layer protocol
+        let end = IPV6_HEADER_SIZE + hdr.payloadLen;
+        let mut next = hdr.nextHeader;
+        let mut offset = IPV6_HEADER_SIZE;
+        loop {
+            match next {
+                IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                    if offset + 8 > end {
+                        return;
+                    }
+
+                    // the netstack is the final destination, it doesn't
+                    // take part in source routing
+                    if next == IPPROTO_ROUTING && packet[offset + 3] != 0 {
+                        return;
+                    }
+
+                    next = packet[offset];
+                    offset += (packet[offset + 1] as usize + 1) * 8;
+                }
+                IPPROTO_FRAGMENT => {
+                    if offset + IPV6_FRAGMENT_HEADER_SIZE > end {
+                        return;
+                    }
+
+                    let fragNext = packet[offset];
+                    let flags = GetU16(packet, offset + 2);
+                    let id = GetU32(packet, offset + 4);
+                    let fragOffset = (flags & IPV6_FRAGMENT_OFFSET_MASK) as usize;
+                    let more = flags & IPV6_FLAG_MORE_FRAGMENTS != 0;
+                    let payload = &packet[offset + IPV6_FRAGMENT_HEADER_SIZE..end];
+                    if fragOffset == 0 && !more {
+                        // an atomic fragment, rfc 6946
+                        next = fragNext;
+                        offset += IPV6_FRAGMENT_HEADER_SIZE;
+                        continue;
+                    }
+
+                    if let Some(packet) =
+                        self.ReassembleIpv6(&hdr, fragNext, id, fragOffset, more, payload)
+                    {
+                        self.HandleIpv6(&packet, loopback);
+                    }
+                    return;
+                }
+                _ => break,
+            }
+        }
+
+        if offset > end {
+            return;
+        }
+
+        let pkt = IpPacket {
+            src: hdr.src,
+            dst: hdr.dst,
+            protocol: next,
+            ttl: hdr.hopLimit,
+            data: &packet[..end],
+            payloadOffset: offset,
+            broadcast: multicast,
+        };
+
+        self.DeliverRawIpv6(&pkt);
+
+        match next {
+            IPPROTO_ICMPV6 => self.HandleIcmpv6(&pkt),
+            IPPROTO_UDP => self.HandleUdp(&pkt),
+            IPPROTO_TCP => self.HandleTcp(&pkt),
+            _ => (),
+        }
+    }
+
+    // ReassembleIpv6 queues the fragment and returns the datagram when all its
+    // fragments have arrived. The datagram has no extension headers, its ipv6
+    // header carries the protocol of the fragment header.
+    pub fn ReassembleIpv6(
+        &self,
+        hdr: &Ipv6Header,
+        fragNext: u8,
+        id: u32,
+        offset: usize,
+        more: bool,
+        payload: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut header = [0; IPV6_HEADER_SIZE];
+        let first = Ipv6Header {
+            payloadLen: 0,
+            nextHeader: fragNext,
+            hopLimit: hdr.hopLimit,
+            src: hdr.src,
+            dst: hdr.dst,
+        };
+        first.Encode(&mut header);
+
+        let datagram = self.lock().reassembler.Process(
+            FragmentKey::Ipv6(hdr, id),
+            offset,
+            more,
+            &header,
+            payload,
+            MonotonicNow(),
+        )?;
+
+        return Some(Ipv6Reassembled(&datagram));
+    }
+
+    // FragmentTick drops the datagrams not reassembled in time, the sender
+    // gets a time exceeded error when the first fragment has arrived
+    pub fn FragmentTick(&self, now: i64) {
+        let firsts = self.lock().reassembler.Expire(now);
+        for first in firsts {
+            if first.len() >= IPV4_MIN_HEADER_SIZE && first[0] >> 4 == 4 {
+                let local = IpAddr::FromIpv4(GetU32(&first, 16));
+                let remote = IpAddr::FromIpv4(GetU32(&first, 12));
+                self.SendIcmpError(
+                    &first,
+                    local,
+                    remote,
+                    ICMP_TIME_EXCEEDED,
+                    ICMP_REASSEMBLY_TIME_EXCEEDED_CODE,
+                );
+            } else if first.len() >= IPV6_HEADER_SIZE && first[0] >> 4 == 6 {
+                let local = IpAddr::FromSlice(&first[24..40]);
+                let remote = IpAddr::FromSlice(&first[8..24]);
+                self.SendIcmpError(
+                    &first,
+                    local,
+                    remote,
+                    ICMPV6_TIME_EXCEEDED,
+                    ICMPV6_REASSEMBLY_TIME_EXCEEDED_CODE,
+                );
+            }
+        }
+    }
+
+    pub fn TimerPending(&self) -> bool {
+        return self.TcpTimerPending() || self.lock().reassembler.Pending();
+    }
+
+    pub fn HandleIcmp(&self, pkt: &IpPacket) {
+        let payload = pkt.Payload();
+        let hdr = match IcmpHeader::Decode(payload) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        match hdr.typ {
+            ICMP_ECHO_REQUEST => {
+                if pkt.broadcast {
+                    // like linux icmp_echo_ignore_broadcasts
+                    return;
+                }
+
+                self.SendEchoReply(pkt, pkt.dst, ICMP_ECHO_REPLY, &hdr);
+            }
+            ICMP_ECHO_REPLY => self.DeliverEchoReply(pkt, IPPROTO_ICMP, &hdr),
+            _ => (),
+        }
+    }
+
+    pub fn HandleIcmpv6(&self, pkt: &IpPacket) {
+        let payload = pkt.Payload();
+        let hdr = match IcmpHeader::DecodeWithSum(
+            payload,
+            IcmpPseudoSum(pkt.src, pkt.dst, payload.len()),
+        ) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        match hdr.typ {
+            ICMPV6_ECHO_REQUEST => {
+                // linux answers the multicast echo requests from a unicast address
+                let src = if pkt.broadcast {
+                    match self.SourceAddr(IpAddr::ANY, pkt.src) {
+                        Err(_) => return,
+                        Ok(src) => src,
+                    }
+                } else {
+                    pkt.dst
+                };
+
+                self.SendEchoReply(pkt, src, ICMPV6_ECHO_REPLY, &hdr);
+            }
+            ICMPV6_ECHO_REPLY => self.DeliverEchoReply(pkt, IPPROTO_ICMPV6, &hdr),
+            ICMPV6_NEIGHBOR_SOLICIT | ICMPV6_NEIGHBOR_ADVERT => self.HandleNdp(pkt),
+            ICMPV6_ROUTER_ADVERT => self.HandleRouterAdvert(pkt),
+            _ => (),
+        }
+    }
+
+    pub fn SendEchoReply(&self, pkt: &IpPacket, src: IpAddr, typ: u8, hdr: &IcmpHeader) {
+        let payload = pkt.Payload();
+        let (mut buf, offset) = self.NewIpPacket(pkt.protocol, src, pkt.src, payload.len());
+        buf[offset..].copy_from_slice(payload);
+        let reply = IcmpHeader {
+            typ: typ,
+            code: 0,
+            ident: hdr.ident,
+            seq: hdr.seq,
+        };
+        reply.EncodeWithSum(
+            &mut buf[offset..],
+            IcmpPseudoSum(src, pkt.src, payload.len()),
+        );
+        self.SendIpPacket(buf, pkt.src).ok();
+    }
+
+    pub fn DeliverEchoReply(&self, pkt: &IpPacket, protocol: u8, hdr: &IcmpHeader) {
+        let ep = self
+            .lock()
+            .FindDatagramEndpoint(protocol, hdr.ident, pkt.dst);
+        if let Some(ep) = ep {
+            ep.Enqueue(DatagramPacket {
+                srcAddr: pkt.src,
+                srcPort: 0,
+                data: pkt.Payload().to_vec(),
+            });
+        }
+    }
+
+    pub fn HandleNdp(&self, pkt: &IpPacket) {
+        // rfc 4861 7.1.1, the messages from off link are dropped
+        if pkt.ttl != NDP_HOP_LIMIT {
+            return;
+        }
+
+        let ndp = match NdpPacket::Decode(pkt.Payload()) {
+            None => return,
+            Some(ndp) => ndp,
+        };
+
+        if ndp.typ == ICMPV6_NEIGHBOR_ADVERT {
+            if let Some(mac) = ndp.linkAddr {
+                self.ResolveNeighbor(ndp.target, mac);
+            }
+            return;
+        }
+
+        let nic = self.lock().nic;
+        if ndp.target.IsIpv4() || ndp.target.IsLoopback() || !nic.IsLocal(ndp.target) {
+            return;
+        }
+
+        if pkt.src.IsAny() {
+            // the duplicate address detection of another node, defend the address
+            self.SendNdp(
+                ICMPV6_NEIGHBOR_ADVERT,
+                NDP_FLAG_OVERRIDE,
+                ndp.target,
+                IpAddr::ALL_NODES,
+            )
+            .ok();
+            return;
+        }
+
+        if let Some(mac) = ndp.linkAddr {
+            self.ResolveNeighbor(pkt.src, mac);
+        }
+
+        self.SendNdp(
+            ICMPV6_NEIGHBOR_ADVERT,
+            NDP_FLAG_SOLICITED | NDP_FLAG_OVERRIDE,
+            ndp.target,
+            pkt.src,
+        )
+        .ok();
+    }
+
+    // HandleRouterAdvert takes the advertising router as the default router
+    // when qvisor hasn't found one
+    pub fn HandleRouterAdvert(&self, pkt: &IpPacket) {
+        let payload = pkt.Payload();
+        if pkt.ttl != NDP_HOP_LIMIT
+            || !pkt.src.IsLinkLocal()
+            || payload.len() < NDP_ROUTER_ADVERT_SIZE
+        {
+            return;
+        }
+
+        if let Some(mac) = NdpLinkAddr(payload, NDP_ROUTER_ADVERT_SIZE) {
+            self.ResolveNeighbor(pkt.src, mac);
+        }
+
+        let lifetime = GetU16(payload, 6);
+        let mut inner = self.lock();
+        if lifetime != 0 && inner.nic.gateway6.IsAny() {
+            info!("netstack takes ipv6 router {:?}", pkt.src);
+            inner.nic.gateway6 = pkt.src;
+        }
+    }
+
+    pub fn HandleUdp(&self, pkt: &IpPacket) {
+        let payload = pkt.Payload();
+        let hdr = match UdpHeader::Decode(payload, pkt.src, pkt.dst) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        let ep = self
+            .lock()
+            .FindDatagramEndpoint(IPPROTO_UDP, hdr.dstPort, pkt.dst);
+        let ep = match ep {
+            Some(ep) => ep,
+            None => {
+                if !pkt.broadcast {
+                    self.SendPortUnreachable(pkt);
+                }
+                return;
+            }
+        };
+
+        ep.Enqueue(DatagramPacket {
+            srcAddr: pkt.src,
+            srcPort: hdr.srcPort,
+            data: payload[UDP_HEADER_SIZE..hdr.len].to_vec(),
+        });
+    }
+
+    pub fn SendPortUnreachable(&self, pkt: &IpPacket) {
+        if pkt.src.IsIpv4() {
+            self.SendIcmpError(
+                pkt.data,
+                pkt.dst,
+                pkt.src,
+                ICMP_DEST_UNREACHABLE,
+                ICMP_PORT_UNREACHABLE_CODE,
+            );
+        } else {
+            self.SendIcmpError(
+                pkt.data,
+                pkt.dst,
+                pkt.src,
+                ICMPV6_DEST_UNREACHABLE,
+                ICMPV6_PORT_UNREACHABLE_CODE,
+            );
+        }
+    }
+
+    // SendIcmpError sends an icmp or icmpv6 error about the packet orig from
+    // remote to local. It quotes the ip header and the first 8 payload bytes
+    // of an ipv4 packet, and as much of an ipv6 packet as fits in the minimum
+    // ipv6 mtu.
+    pub fn SendIcmpError(&self, orig: &[u8], local: IpAddr, remote: IpAddr, typ: u8, code: u8) {
+        if remote.IsAny() || remote.IsMulticast() || self.lock().nic.IsBroadcast(remote) {
+            return;
+        }
+
+        let (protocol, quoted) = if remote.IsIpv4() {
+            let headerLen = ((orig[0] & 0xf) as usize) * 4;
+            (IPPROTO_ICMP, core::cmp::min(orig.len(), headerLen + 8))
+        } else {
+            let max = IPV6_MIN_MTU - IPV6_HEADER_SIZE - ICMP_HEADER_SIZE;
+            (IPPROTO_ICMPV6, core::cmp::min(orig.len(), max))
+        };
+
+        let len = ICMP_HEADER_SIZE + quoted;
+        let (mut buf, offset) = self.NewIpPacket(protocol, local, remote, len);
+        buf[offset + ICMP_HEADER_SIZE..].copy_from_slice(&orig[..quoted]);
+        let hdr = IcmpHeader {
+            typ: typ,
+            code: code,
+            ident: 0,
+            seq: 0,
+        };
+        hdr.EncodeWithSum(&mut buf[offset..], IcmpPseudoSum(local, remote, len));
+        self.SendIpPacket(buf, remote).ok();
+    }
+
+    pub fn Process(&self, task: &Task) -> Result<()> {
+        let nic = *SHARESPACE.netstackNic.lock();
+        {
+            let mut inner = self.lock();
+            inner.nic = nic;
+            inner.started = true;
+        }
+
+        info!("netstack start with nic {:x?}", &nic);
+
+        let queue = self.lock().linkQueue.clone();
+        SetWaitInfo(nic.fd, queue.clone());
+        let general = task.blocker.generalEntry.clone();
+        queue.EventRegister(task, &general, READABLE_EVENT);
+        defer!(queue.EventUnregister(task, &general));
+        UpdateFD(nic.fd)?;
+
+        // announce the addresses so that the peers don't keep the stale
+        // neighbor entries of the host interface
+        self.SendArp(ARP_REQUEST, ETHERNET_BROADCAST, [0; 6], nic.addr)
+            .ok();
+        if nic.HasIpv6() {
+            for addr in [nic.linkLocal6, nic.addr6] {
+                if !addr.IsAny() {
+                    self.SendNdp(
+                        ICMPV6_NEIGHBOR_ADVERT,
+                        NDP_FLAG_OVERRIDE,
+                        addr,
+                        IpAddr::ALL_NODES,
+                    )
+                    .ok();
+                }
+            }
+
+            if nic.gateway6.IsAny() {
+                self.SendRouterSolicit().ok();
+            }
+        }
+
+        let buf = DataBuff::New(LINK_READ_BUF_SIZE);
+        let iovs = buf.Iovs(LINK_READ_BUF_SIZE);
+        let mut lastTick = MonotonicNow();
+        loop {
+            let now = MonotonicNow();
+            if now - lastTick >= TCP_TICK {
+                self.TcpTick(now);
+                self.FragmentTick(now);
+                self.lock().netfilter.conntrack.Expire(now);
+                lastTick = now;
+            }
+
+            match IORead(nic.fd, &iovs) {
+                Err(Error::SysError(SysErr::EAGAIN)) => {
+                    let deadline = if self.TimerPending() {
+                        Some(Time(lastTick + TCP_TICK))
+                    } else {
+                        None
+                    };
+
+                    match task.blocker.BlockWithMonoTimer(true, deadline) {
+                        Err(Error::SysError(SysErr::ETIMEDOUT)) => (),
+                        Err(Error::ErrInterrupted) => (),
+                        Err(e) => return Err(e),
+                        _ => (),
+                    }
+                }
+                Err(e) => {
+                    error!("netstack read link fail with error {:?}", e);
+                    return Err(e);
+                }
+                Ok(n) => {
+                    self.HandleFrame(&buf.buf[0..n as usize]);
+                }
+            }
+        }
+    }
+}
+
+// IcmpPseudoSum is the partial checksum an icmp message starts from, the
+// icmpv6 checksum covers the pseudo header while the icmpv4 one doesn't
+pub fn IcmpPseudoSum(src: IpAddr, dst: IpAddr, len: usize) -> u32 {
+    if dst.IsIpv4() {
+        return 0;
+    }
+
+    return PseudoHeaderSum(src, dst, IPPROTO_ICMPV6, len);
+}
+
+pub fn NetstackProcess(_para: *const u8) {
+    let task = Task::Current();
+    match NETSTACK.Process(task) {
+        Err(e) => error!("netstack exit with error {:?}", e),
+        Ok(()) => (),
+    }
+}
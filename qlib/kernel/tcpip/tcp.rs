@@ -0,0 +1,1685 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The tcp of the netstack. It keeps to the rfc 793 state machine with the
+// window scale option of rfc 7323 and the newreno congestion control of rfc
+// 5681 and rfc 6582, as nat.rs forwards the connections beyond the sandbox's
+// own link. The out of order segments are queued until the gap is filled and
+// the retransmission timeout goes back to sndUna with an exponential backoff
+// rto. There is no sack, timestamps or rtt estimation.
+
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use super::super::super::common::*;
+use super::super::super::linux_def::*;
+use super::super::super::mutex::*;
+use super::super::kernel::timer::MonotonicNow;
+use super::super::kernel::waiter::*;
+use super::header::*;
+use super::stack::*;
+
+pub const TCP_DEFAULT_MSS: usize = 536;
+pub const TCP_MAX_WINDOW: usize = 65535;
+pub const TCP_MAX_WND_SCALE: u8 = 14;
+// rfc 6928 initial window in segments
+pub const TCP_INITIAL_CWND: usize = 10;
+// the duplicate acks which start the fast retransmit
+pub const TCP_DUP_ACK_THRESHOLD: u32 = 3;
+
+pub const TCP_INITIAL_RTO: i64 = 1_000_000_000;
+pub const TCP_MAX_RTO: i64 = 60_000_000_000;
+pub const TCP_MAX_SYN_RETRIES: u32 = 6;
+pub const TCP_MAX_RETRIES: u32 = 15;
+pub const TCP_TIME_WAIT: i64 = 10_000_000_000;
+
+// the interval of the netstack timer when there is a tcp connection
+pub const TCP_TICK: i64 = 100_000_000;
+
+#[inline]
+pub fn SeqLT(a: u32, b: u32) -> bool {
+    return (a.wrapping_sub(b) as i32) < 0;
+}
+
+#[inline]
+pub fn SeqLE(a: u32, b: u32) -> bool {
+    return (a.wrapping_sub(b) as i32) <= 0;
+}
+
+// TcpState values are the linux tcp states reported in /proc/net/tcp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TcpState {
+    Established = 1,
+    SynSent = 2,
+    SynRcvd = 3,
+    FinWait1 = 4,
+    FinWait2 = 5,
+    TimeWait = 6,
+    Closed = 7,
+    CloseWait = 8,
+    LastAck = 9,
+    Listen = 10,
+    Closing = 11,
+}
+
+// local addr, local port, remote addr, remote port
+pub type TcpConnId = (IpAddr, u16, IpAddr, u16);
+
+#[derive(Debug)]
+pub struct TcpSegment {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub hdr: TcpHeader,
+    pub data: Vec<u8>,
+}
+
+// a segment received after a gap in the sequence
+#[derive(Debug)]
+pub struct TcpOooSegment {
+    pub seq: u32,
+    pub data: Vec<u8>,
+    pub fin: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpAction {
+    None,
+    // the active open finished
+    Connected,
+    // the passive open finished, the endpoint goes to the listener's accept queue
+    Established,
+    // the connection is closed and has to be removed from the netstack
+    Remove,
+}
+
+pub struct TcpEndpointState {
+    pub state: TcpState,
+    pub localAddr: IpAddr,
+    pub localPort: u16,
+    pub remoteAddr: IpAddr,
+    pub remotePort: u16,
+    // whether the endpoint is in the netstack's tcpPorts
+    pub bound: bool,
+    pub reuseAddr: bool,
+    // IPV6_V6ONLY of an AF_INET6 endpoint
+    pub v6only: bool,
+    pub hasConnected: bool,
+    pub error: Option<i32>,
+
+    pub iss: u32,
+    pub sndUna: u32,
+    pub sndNxt: u32,
+    // the highest sequence sent, sndNxt goes back to sndUna on a retransmission timeout
+    pub sndMax: u32,
+    pub sndWnd: u32,
+    // the data from sndUna, both the unacked and the unsent
+    pub sndBuf: VecDeque<u8>,
+    pub sndBufSize: usize,
+    pub finQueued: bool,
+    pub finSent: bool,
+    pub mss: usize,
+
+    // whether the syns carry the window scale option, the shifts are 0 otherwise
+    pub wndScaled: bool,
+    pub sndWndShift: u8,
+    pub rcvWndShift: u8,
+
+    // the congestion window of rfc 5681
+    pub cwnd: usize,
+    pub ssthresh: usize,
+    pub dupAcks: u32,
+    // rfc 6582 fast recovery ends when sndUna reaches recover
+    pub inRecovery: bool,
+    pub recover: u32,
+
+    pub rcvNxt: u32,
+    pub rcvBuf: VecDeque<u8>,
+    pub rcvBufSize: usize,
+    pub rcvClosed: bool,
+    pub shutRead: bool,
+    pub lastAdvWnd: usize,
+    // the out of order segments sorted by seq and the data bytes they hold
+    pub oooQueue: VecDeque<TcpOooSegment>,
+    pub oooSize: usize,
+
+    pub rto: i64,
+    pub retries: u32,
+    pub retransmitDeadline: Option<i64>,
+    pub timeWaitDeadline: Option<i64>,
+
+    pub backlog: usize,
+    pub acceptQueue: VecDeque<TcpEndpoint>,
+    // the listener of a passive open endpoint before it is established
+    pub listener: Option<TcpEndpoint>,
+}
+
+impl TcpEndpointState {
+    pub fn Id(&self) -> TcpConnId {
+        return (
+            self.localAddr,
+            self.localPort,
+            self.remoteAddr,
+            self.remotePort,
+        );
+    }
+
+    pub fn Synchronized(&self) -> bool {
+        match self.state {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynRcvd => {
+                return false
+            }
+            _ => return true,
+        }
+    }
+
+    pub fn CanSend(&self) -> bool {
+        return self.state == TcpState::Established || self.state == TcpState::CloseWait;
+    }
+
+    pub fn CanReceive(&self) -> bool {
+        match self.state {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => return true,
+            _ => return false,
+        }
+    }
+
+    pub fn RcvWnd(&self) -> usize {
+        let free = self.rcvBufSize.saturating_sub(self.rcvBuf.len());
+        return core::cmp::min(free, TCP_MAX_WINDOW << self.rcvWndShift);
+    }
+
+    // WndShift is the shift which lets the advertised window cover the receive buffer
+    pub fn WndShift(&self) -> u8 {
+        let mut shift = 0;
+        while shift < TCP_MAX_WND_SCALE && (TCP_MAX_WINDOW << shift) < self.rcvBufSize {
+            shift += 1;
+        }
+        return shift;
+    }
+
+    // SetWndScale takes the window scale option of the peer's syn, the
+    // windows are only scaled when both syns carry it
+    pub fn SetWndScale(&mut self, peer: Option<u8>) {
+        match peer {
+            None => {
+                self.wndScaled = false;
+                self.sndWndShift = 0;
+                self.rcvWndShift = 0;
+            }
+            Some(shift) => {
+                self.wndScaled = true;
+                self.sndWndShift = core::cmp::min(shift, TCP_MAX_WND_SCALE);
+                if self.state == TcpState::SynRcvd {
+                    self.rcvWndShift = self.WndShift();
+                }
+            }
+        }
+    }
+
+    pub fn SndSpace(&self) -> usize {
+        return self.sndBufSize.saturating_sub(self.sndBuf.len());
+    }
+
+    // the data bytes sent but not acked yet
+    pub fn DataInflight(&self) -> usize {
+        let mut inflight = self.sndNxt.wrapping_sub(self.sndUna) as usize;
+        if self.finSent && inflight > 0 {
+            inflight -= 1;
+        }
+
+        return core::cmp::min(inflight, self.sndBuf.len());
+    }
+
+    pub fn SetMss(&mut self, mss: u16, mtuMss: usize) {
+        let peer = if mss == 0 {
+            TCP_DEFAULT_MSS
+        } else {
+            mss as usize
+        };
+        self.mss = core::cmp::min(peer, mtuMss);
+        self.cwnd = TCP_INITIAL_CWND * self.mss;
+    }
+
+    pub fn MakeSegment(&mut self, flags: u8, seq: u32, data: Vec<u8>) -> TcpSegment {
+        // the window of a syn is never scaled
+        let shift = if flags & TCP_FLAG_SYN != 0 {
+            0
+        } else {
+            self.rcvWndShift
+        };
+        let wnd = core::cmp::min(self.RcvWnd() >> shift, TCP_MAX_WINDOW);
+        self.lastAdvWnd = wnd << shift;
+        let ack = if flags & TCP_FLAG_ACK != 0 {
+            self.rcvNxt
+        } else {
+            0
+        };
+        return TcpSegment {
+            src: self.localAddr,
+            dst: self.remoteAddr,
+            hdr: TcpHeader {
+                srcPort: self.localPort,
+                dstPort: self.remotePort,
+                seq: seq,
+                ack: ack,
+                headerLen: TCP_MIN_HEADER_SIZE,
+                flags: flags,
+                window: wnd as u16,
+                mss: 0,
+                wndScale: None,
+            },
+            data: data,
+        };
+    }
+
+    pub fn SynSegment(&mut self, mtuMss: usize) -> TcpSegment {
+        let flags = if self.state == TcpState::SynRcvd {
+            TCP_FLAG_SYN | TCP_FLAG_ACK
+        } else {
+            TCP_FLAG_SYN
+        };
+
+        let wndScale = if self.state == TcpState::SynSent {
+            self.rcvWndShift = self.WndShift();
+            Some(self.rcvWndShift)
+        } else if self.wndScaled {
+            Some(self.rcvWndShift)
+        } else {
+            None
+        };
+
+        let mut seg = self.MakeSegment(flags, self.iss, Vec::new());
+        seg.hdr.headerLen = TCP_SYN_HEADER_SIZE;
+        seg.hdr.mss = mtuMss as u16;
+        seg.hdr.wndScale = wndScale;
+        return seg;
+    }
+
+    pub fn AckSegment(&mut self) -> TcpSegment {
+        return self.MakeSegment(TCP_FLAG_ACK, self.sndNxt, Vec::new());
+    }
+
+    pub fn RstSegment(&mut self) -> TcpSegment {
+        return self.MakeSegment(TCP_FLAG_RST | TCP_FLAG_ACK, self.sndNxt, Vec::new());
+    }
+
+    pub fn SetTimeWait(&mut self, now: i64) {
+        self.state = TcpState::TimeWait;
+        self.retransmitDeadline = None;
+        self.timeWaitDeadline = Some(now + TCP_TIME_WAIT);
+    }
+
+    // Output sends the queued data the peer's window allows and the fin
+    pub fn Output(&mut self, now: i64) -> Vec<TcpSegment> {
+        let mut segs = Vec::new();
+        if !self.CanSend() {
+            return segs;
+        }
+
+        loop {
+            let inflight = self.DataInflight();
+            let unsent = self.sndBuf.len() - inflight;
+            let wnd = core::cmp::min(self.sndWnd as usize, self.cwnd);
+            if unsent == 0 || inflight >= wnd {
+                break;
+            }
+
+            let n = core::cmp::min(core::cmp::min(self.mss, unsent), wnd - inflight);
+            let data: Vec<u8> = self.sndBuf.range(inflight..inflight + n).cloned().collect();
+            let seq = self.sndNxt;
+            segs.push(self.MakeSegment(TCP_FLAG_ACK | TCP_FLAG_PSH, seq, data));
+            self.sndNxt = self.sndNxt.wrapping_add(n as u32);
+        }
+
+        if self.finQueued && !self.finSent && self.DataInflight() == self.sndBuf.len() {
+            let seq = self.sndNxt;
+            segs.push(self.MakeSegment(TCP_FLAG_FIN | TCP_FLAG_ACK, seq, Vec::new()));
+            self.sndNxt = self.sndNxt.wrapping_add(1);
+            self.finSent = true;
+            self.state = if self.state == TcpState::Established {
+                TcpState::FinWait1
+            } else {
+                TcpState::LastAck
+            };
+        }
+
+        if SeqLT(self.sndMax, self.sndNxt) {
+            self.sndMax = self.sndNxt;
+        }
+
+        let zeroWindow = self.sndWnd == 0 && self.sndBuf.len() > 0;
+        if (self.sndMax != self.sndUna || zeroWindow) && self.retransmitDeadline.is_none() {
+            self.retransmitDeadline = Some(now + self.rto);
+        }
+
+        return segs;
+    }
+
+    pub fn Retransmit(&mut self, now: i64, mtuMss: usize) -> (Vec<TcpSegment>, TcpAction) {
+        let mut segs = Vec::new();
+        self.retries += 1;
+        let maxRetries = if self.Synchronized() {
+            TCP_MAX_RETRIES
+        } else {
+            TCP_MAX_SYN_RETRIES
+        };
+
+        if self.retries > maxRetries {
+            self.error = Some(SysErr::ETIMEDOUT);
+            if self.state != TcpState::SynSent {
+                segs.push(self.RstSegment());
+            }
+            return (segs, TcpAction::Remove);
+        }
+
+        self.rto = core::cmp::min(self.rto * 2, TCP_MAX_RTO);
+        self.retransmitDeadline = Some(now + self.rto);
+
+        match self.state {
+            TcpState::SynSent | TcpState::SynRcvd => {
+                segs.push(self.SynSegment(mtuMss));
+            }
+            _ => {
+                let inflight = self.DataInflight();
+                if inflight > 0 {
+                    // rfc 5681 the timeout restarts the slow start
+                    self.ssthresh = core::cmp::max(inflight / 2, 2 * self.mss);
+                    self.cwnd = self.mss;
+                    self.dupAcks = 0;
+                    self.inRecovery = false;
+
+                    let seg = self.RetransmitFirst();
+                    if !self.finSent {
+                        // go back to sndUna, the acks send the rest again
+                        self.sndNxt = self.sndUna.wrapping_add(seg.data.len() as u32);
+                    }
+                    segs.push(seg);
+                } else if self.finSent && self.sndNxt != self.sndUna {
+                    let seq = self.sndUna;
+                    segs.push(self.MakeSegment(TCP_FLAG_FIN | TCP_FLAG_ACK, seq, Vec::new()));
+                } else if self.sndWnd == 0 && self.sndBuf.len() > 0 {
+                    // zero window probe
+                    let data = [self.sndBuf[0]].to_vec();
+                    let seq = self.sndUna;
+                    segs.push(self.MakeSegment(TCP_FLAG_ACK, seq, data));
+                    self.sndNxt = self.sndUna.wrapping_add(1);
+                    if SeqLT(self.sndMax, self.sndNxt) {
+                        self.sndMax = self.sndNxt;
+                    }
+                    // the probes don't time out the connection
+                    self.retries = 0;
+                } else {
+                    self.retries = 0;
+                    self.retransmitDeadline = None;
+                }
+            }
+        }
+
+        return (segs, TcpAction::None);
+    }
+
+    // RetransmitFirst resends the first unacked segment
+    pub fn RetransmitFirst(&mut self) -> TcpSegment {
+        let n = core::cmp::min(self.mss, self.DataInflight());
+        let data: Vec<u8> = self.sndBuf.range(0..n).cloned().collect();
+        let seq = self.sndUna;
+        return self.MakeSegment(TCP_FLAG_ACK | TCP_FLAG_PSH, seq, data);
+    }
+
+    // CongestionAck grows the congestion window for the newly acked bytes, a
+    // partial ack in the fast recovery resends the next hole
+    pub fn CongestionAck(&mut self, acked: usize, segs: &mut Vec<TcpSegment>) {
+        if self.inRecovery {
+            if SeqLE(self.recover, self.sndUna) {
+                self.inRecovery = false;
+                self.dupAcks = 0;
+                let inflight = core::cmp::max(self.DataInflight(), self.mss);
+                self.cwnd = core::cmp::min(self.ssthresh, inflight + self.mss);
+            } else {
+                segs.push(self.RetransmitFirst());
+                self.cwnd = core::cmp::max(self.cwnd.saturating_sub(acked) + self.mss, self.mss);
+            }
+            return;
+        }
+
+        self.dupAcks = 0;
+        if self.cwnd < self.ssthresh {
+            self.cwnd += core::cmp::min(acked, self.mss);
+        } else {
+            self.cwnd += core::cmp::max(self.mss * self.mss / self.cwnd, 1);
+        }
+    }
+
+    // CongestionDupAck starts the fast retransmit on the third duplicate ack
+    // and inflates the window for the segments which left the network
+    pub fn CongestionDupAck(&mut self, segs: &mut Vec<TcpSegment>) {
+        self.dupAcks += 1;
+        if self.inRecovery {
+            self.cwnd += self.mss;
+            return;
+        }
+
+        // rfc 6582 the duplicate acks of the data sent before the last
+        // recovery don't start a new one
+        if self.dupAcks == TCP_DUP_ACK_THRESHOLD && SeqLT(self.recover, self.sndUna) {
+            let inflight = self.DataInflight();
+            self.ssthresh = core::cmp::max(inflight / 2, 2 * self.mss);
+            self.cwnd = self.ssthresh + TCP_DUP_ACK_THRESHOLD as usize * self.mss;
+            self.inRecovery = true;
+            self.recover = self.sndMax;
+            segs.push(self.RetransmitFirst());
+        }
+    }
+
+    // QueueOutOfOrder keeps the segment after a gap until the gap is filled,
+    // the queue is limited to the receive window
+    pub fn QueueOutOfOrder(&mut self, seq: u32, data: &[u8], fin: bool) {
+        if !self.CanReceive() || self.shutRead {
+            return;
+        }
+
+        let wnd = self.RcvWnd();
+        let offset = seq.wrapping_sub(self.rcvNxt) as usize;
+        if offset >= wnd {
+            return;
+        }
+
+        let len = core::cmp::min(data.len(), wnd - offset);
+        let fin = fin && len == data.len();
+        if self.oooSize + len > wnd {
+            return;
+        }
+
+        let mut i = 0;
+        while i < self.oooQueue.len() && SeqLT(self.oooQueue[i].seq, seq) {
+            i += 1;
+        }
+
+        if i < self.oooQueue.len() {
+            let queued = &self.oooQueue[i];
+            if queued.seq == seq && queued.data.len() >= len && (queued.fin || !fin) {
+                // retransmission of a queued segment
+                return;
+            }
+        }
+
+        self.oooSize += len;
+        self.oooQueue.insert(
+            i,
+            TcpOooSegment {
+                seq: seq,
+                data: data[..len].to_vec(),
+                fin: fin,
+            },
+        );
+    }
+
+    // NextOutOfOrder takes the queued segment which continues at rcvNxt
+    pub fn NextOutOfOrder(&mut self) -> Option<TcpOooSegment> {
+        loop {
+            match self.oooQueue.front() {
+                None => return None,
+                Some(seg) if SeqLT(self.rcvNxt, seg.seq) => return None,
+                _ => (),
+            }
+
+            let mut seg = self.oooQueue.pop_front().unwrap();
+            self.oooSize -= seg.data.len();
+            let offset = self.rcvNxt.wrapping_sub(seg.seq) as usize;
+            if offset > seg.data.len() || (offset == seg.data.len() && !seg.fin) {
+                // the data is already received
+                continue;
+            }
+
+            seg.data.drain(..offset);
+            seg.seq = self.rcvNxt;
+            return Some(seg);
+        }
+    }
+
+    // Receive takes the data and fin at rcvNxt
+    pub fn Receive(&mut self, data: &[u8], fin: bool, now: i64) {
+        let mut fin = fin;
+        if data.len() > 0 {
+            if self.CanReceive() {
+                if self.shutRead {
+                    self.rcvNxt = self.rcvNxt.wrapping_add(data.len() as u32);
+                } else {
+                    let n = core::cmp::min(data.len(), self.RcvWnd());
+                    self.rcvBuf.extend(data[..n].iter());
+                    self.rcvNxt = self.rcvNxt.wrapping_add(n as u32);
+                    if n < data.len() {
+                        fin = false;
+                    }
+                }
+            } else {
+                fin = false;
+            }
+        }
+
+        if fin {
+            self.rcvNxt = self.rcvNxt.wrapping_add(1);
+            self.rcvClosed = true;
+            self.oooQueue.clear();
+            self.oooSize = 0;
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 => self.state = TcpState::Closing,
+                TcpState::FinWait2 => self.SetTimeWait(now),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn HandleSegment(
+        &mut self,
+        hdr: &TcpHeader,
+        data: &[u8],
+        now: i64,
+        mtuMss: usize,
+    ) -> (Vec<TcpSegment>, TcpAction) {
+        let mut segs = Vec::new();
+        match self.state {
+            TcpState::SynSent => {
+                if hdr.HasFlag(TCP_FLAG_ACK) && hdr.ack != self.sndNxt {
+                    if !hdr.HasFlag(TCP_FLAG_RST) {
+                        let mut rst = self.MakeSegment(TCP_FLAG_RST, hdr.ack, Vec::new());
+                        rst.hdr.ack = 0;
+                        segs.push(rst);
+                    }
+                    return (segs, TcpAction::None);
+                }
+
+                if hdr.HasFlag(TCP_FLAG_RST) {
+                    if hdr.HasFlag(TCP_FLAG_ACK) {
+                        self.error = Some(SysErr::ECONNREFUSED);
+                        return (segs, TcpAction::Remove);
+                    }
+                    return (segs, TcpAction::None);
+                }
+
+                // todo: support simultaneous open
+                if !hdr.HasFlag(TCP_FLAG_SYN) || !hdr.HasFlag(TCP_FLAG_ACK) {
+                    return (segs, TcpAction::None);
+                }
+
+                self.rcvNxt = hdr.seq.wrapping_add(1);
+                self.sndUna = hdr.ack;
+                self.sndWnd = hdr.window as u32;
+                self.SetMss(hdr.mss, mtuMss);
+                self.SetWndScale(hdr.wndScale);
+                self.state = TcpState::Established;
+                self.hasConnected = true;
+                self.retransmitDeadline = None;
+                self.retries = 0;
+                self.rto = TCP_INITIAL_RTO;
+                segs.push(self.AckSegment());
+                segs.append(&mut self.Output(now));
+                return (segs, TcpAction::Connected);
+            }
+            TcpState::Listen | TcpState::Closed => return (segs, TcpAction::None),
+            _ => (),
+        }
+
+        if hdr.HasFlag(TCP_FLAG_RST) {
+            let wnd = core::cmp::max(self.RcvWnd(), 1) as u32;
+            if SeqLE(self.rcvNxt, hdr.seq) && SeqLT(hdr.seq, self.rcvNxt.wrapping_add(wnd)) {
+                if self.state != TcpState::SynRcvd && self.state != TcpState::TimeWait {
+                    self.error = Some(SysErr::ECONNRESET);
+                }
+                return (segs, TcpAction::Remove);
+            }
+            return (segs, TcpAction::None);
+        }
+
+        if hdr.HasFlag(TCP_FLAG_SYN) {
+            if self.state == TcpState::SynRcvd && hdr.seq.wrapping_add(1) == self.rcvNxt {
+                // the peer doesn't get the syn-ack
+                segs.push(self.SynSegment(mtuMss));
+            } else {
+                segs.push(self.AckSegment());
+            }
+            return (segs, TcpAction::None);
+        }
+
+        if !hdr.HasFlag(TCP_FLAG_ACK) {
+            return (segs, TcpAction::None);
+        }
+
+        let mut action = TcpAction::None;
+        if self.state == TcpState::SynRcvd {
+            if hdr.ack != self.sndNxt {
+                let mut rst = self.MakeSegment(TCP_FLAG_RST, hdr.ack, Vec::new());
+                rst.hdr.ack = 0;
+                segs.push(rst);
+                return (segs, TcpAction::None);
+            }
+
+            self.state = TcpState::Established;
+            self.hasConnected = true;
+            self.sndUna = hdr.ack;
+            self.retransmitDeadline = None;
+            self.retries = 0;
+            self.rto = TCP_INITIAL_RTO;
+            action = TcpAction::Established;
+        }
+
+        let wnd = (hdr.window as u32) << self.sndWndShift;
+        if SeqLT(self.sndUna, hdr.ack) && SeqLE(hdr.ack, self.sndMax) {
+            let mut acked = hdr.ack.wrapping_sub(self.sndUna) as usize;
+            if self.finSent && hdr.ack == self.sndMax {
+                acked -= 1;
+            }
+
+            let n = core::cmp::min(acked, self.sndBuf.len());
+            self.sndBuf.drain(..n);
+            self.sndUna = hdr.ack;
+            if SeqLT(self.sndNxt, self.sndUna) {
+                self.sndNxt = self.sndUna;
+            }
+            self.CongestionAck(n, &mut segs);
+            self.retries = 0;
+            self.rto = TCP_INITIAL_RTO;
+            self.retransmitDeadline = if self.sndUna == self.sndMax {
+                None
+            } else {
+                Some(now + self.rto)
+            };
+        } else if hdr.ack == self.sndUna
+            && self.sndUna != self.sndMax
+            && data.len() == 0
+            && !hdr.HasFlag(TCP_FLAG_FIN)
+            && wnd == self.sndWnd
+        {
+            self.CongestionDupAck(&mut segs);
+        }
+
+        if SeqLE(self.sndUna, hdr.ack) {
+            self.sndWnd = wnd;
+        }
+
+        let finAcked = self.finSent && self.sndUna == self.sndNxt;
+        match self.state {
+            TcpState::FinWait1 if finAcked => self.state = TcpState::FinWait2,
+            TcpState::Closing if finAcked => self.SetTimeWait(now),
+            TcpState::LastAck if finAcked => return (segs, TcpAction::Remove),
+            _ => (),
+        }
+
+        let mut seq = hdr.seq;
+        let mut data = data;
+        let mut fin = hdr.HasFlag(TCP_FLAG_FIN);
+        let mut needAck = false;
+
+        if SeqLT(seq, self.rcvNxt) {
+            let offset = self.rcvNxt.wrapping_sub(seq) as usize;
+            if offset > data.len() {
+                // retransmission of the received data or fin
+                needAck = data.len() > 0 || fin;
+                data = &[];
+                fin = false;
+            } else {
+                data = &data[offset..];
+                seq = self.rcvNxt;
+            }
+        }
+
+        if (data.len() > 0 || fin) && seq != self.rcvNxt {
+            // out of order, queue it and ack the expected sequence so that
+            // the peer fast retransmits the gap
+            needAck = true;
+            self.QueueOutOfOrder(seq, data, fin);
+            data = &[];
+            fin = false;
+        }
+
+        if data.len() > 0 || fin {
+            needAck = true;
+            self.Receive(data, fin, now);
+            while let Some(seg) = self.NextOutOfOrder() {
+                self.Receive(&seg.data, seg.fin, now);
+            }
+        }
+
+        if needAck {
+            segs.push(self.AckSegment());
+        }
+
+        segs.append(&mut self.Output(now));
+        return (segs, action);
+    }
+}
+
+pub struct TcpEndpointIntern {
+    // AF_INET or AF_INET6
+    pub family: i32,
+    pub queue: Queue,
+    pub state: QMutex<TcpEndpointState>,
+}
+
+#[derive(Clone)]
+pub struct TcpEndpoint(Arc<TcpEndpointIntern>);
+
+impl Deref for TcpEndpoint {
+    type Target = Arc<TcpEndpointIntern>;
+
+    fn deref(&self) -> &Arc<TcpEndpointIntern> {
+        &self.0
+    }
+}
+
+impl PartialEq for TcpEndpoint {
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl TcpEndpoint {
+    pub fn New(family: i32, rcvBufSize: usize, sndBufSize: usize) -> Self {
+        let state = TcpEndpointState {
+            state: TcpState::Closed,
+            localAddr: AnyAddr(family),
+            localPort: 0,
+            remoteAddr: AnyAddr(family),
+            remotePort: 0,
+            bound: false,
+            reuseAddr: false,
+            v6only: false,
+            hasConnected: false,
+            error: None,
+            iss: 0,
+            sndUna: 0,
+            sndNxt: 0,
+            sndMax: 0,
+            sndWnd: 0,
+            sndBuf: VecDeque::new(),
+            sndBufSize: sndBufSize,
+            finQueued: false,
+            finSent: false,
+            mss: TCP_DEFAULT_MSS,
+            wndScaled: false,
+            sndWndShift: 0,
+            rcvWndShift: 0,
+            cwnd: TCP_INITIAL_CWND * TCP_DEFAULT_MSS,
+            ssthresh: usize::MAX,
+            dupAcks: 0,
+            inRecovery: false,
+            recover: 0,
+            rcvNxt: 0,
+            rcvBuf: VecDeque::new(),
+            rcvBufSize: rcvBufSize,
+            rcvClosed: false,
+            shutRead: false,
+            lastAdvWnd: 0,
+            oooQueue: VecDeque::new(),
+            oooSize: 0,
+            rto: TCP_INITIAL_RTO,
+            retries: 0,
+            retransmitDeadline: None,
+            timeWaitDeadline: None,
+            backlog: 0,
+            acceptQueue: VecDeque::new(),
+            listener: None,
+        };
+
+        return Self(Arc::new(TcpEndpointIntern {
+            family: family,
+            queue: Queue::default(),
+            state: QMutex::new(state),
+        }));
+    }
+
+    pub fn Notify(&self) {
+        self.queue
+            .Notify(READABLE_EVENT | WRITEABLE_EVENT | EVENT_HUP | EVENT_ERR);
+    }
+
+    pub fn Readiness(&self, mask: EventMask) -> EventMask {
+        let state = self.state.lock();
+        let mut events = 0;
+        if state.state == TcpState::Listen {
+            if state.acceptQueue.len() > 0 {
+                events |= READABLE_EVENT;
+            }
+            return events & mask;
+        }
+
+        if state.rcvBuf.len() > 0 || state.rcvClosed || state.shutRead {
+            events |= READABLE_EVENT;
+        }
+
+        if state.CanSend() && !state.finQueued && state.SndSpace() > 0 {
+            events |= WRITEABLE_EVENT;
+        }
+
+        if state.error.is_some() {
+            events |= EVENT_ERR | READABLE_EVENT | WRITEABLE_EVENT;
+        }
+
+        if state.hasConnected && state.state == TcpState::Closed {
+            events |= EVENT_HUP | READABLE_EVENT;
+        }
+
+        if state.rcvClosed && state.finQueued {
+            events |= EVENT_HUP;
+        }
+
+        return events & mask;
+    }
+
+    pub fn TakeError(&self) -> Option<i32> {
+        return self.state.lock().error.take();
+    }
+}
+
+impl NetStack {
+    // MtuMss is the mss the mtu allows for the connections to remote
+    pub fn MtuMss(&self, remote: IpAddr) -> usize {
+        let mtu = self.lock().nic.mtu as usize;
+        if mtu == 0 {
+            return TCP_DEFAULT_MSS;
+        }
+
+        let ipHeaderLen = if remote.IsIpv4() {
+            IPV4_MIN_HEADER_SIZE
+        } else {
+            IPV6_HEADER_SIZE
+        };
+        return mtu - ipHeaderLen - TCP_MIN_HEADER_SIZE;
+    }
+
+    pub fn NewIss(&self) -> u32 {
+        // rfc 793 4 microseconds clock
+        let id = self.lock().NextIpId() as u32;
+        return ((MonotonicNow() / 4000) as u32).wrapping_add(id << 16);
+    }
+
+    pub fn TcpBind(&self, ep: &TcpEndpoint, addr: IpAddr, port: u16) -> Result<u16> {
+        let mut inner = self.lock();
+        if !addr.IsAny() && !inner.nic.IsLocal(addr) {
+            return Err(Error::SysError(SysErr::EADDRNOTAVAIL));
+        }
+
+        let (reuseAddr, v6only) = {
+            let state = ep.state.lock();
+            if state.bound || state.state != TcpState::Closed {
+                return Err(Error::SysError(SysErr::EINVAL));
+            }
+            (state.reuseAddr, state.v6only)
+        };
+
+        let port = if port == 0 {
+            inner.AllocPort(IPPROTO_TCP)?
+        } else {
+            if let Some(eps) = inner.tcpPorts.get(&port) {
+                for other in eps {
+                    let state = other.state.lock();
+                    if !LocalAddrOverlap(addr, v6only, state.localAddr, state.v6only) {
+                        continue;
+                    }
+
+                    // like linux, the address is shared when both endpoints
+                    // have SO_REUSEADDR and none of them listens
+                    if !reuseAddr || !state.reuseAddr || state.state == TcpState::Listen {
+                        return Err(Error::SysError(SysErr::EADDRINUSE));
+                    }
+                }
+            }
+            port
+        };
+
+        inner
+            .tcpPorts
+            .entry(port)
+            .or_insert(Vec::new())
+            .push(ep.clone());
+        let mut state = ep.state.lock();
+        state.bound = true;
+        state.localAddr = addr;
+        state.localPort = port;
+        return Ok(port);
+    }
+
+    pub fn TcpReleasePort(&self, ep: &TcpEndpoint) {
+        let port = {
+            let mut state = ep.state.lock();
+            if !state.bound {
+                return;
+            }
+            state.bound = false;
+            state.localPort
+        };
+
+        let mut inner = self.lock();
+        let remove = match inner.tcpPorts.get_mut(&port) {
+            None => false,
+            Some(eps) => {
+                eps.retain(|e| e != ep);
+                eps.len() == 0
+            }
+        };
+
+        if remove {
+            inner.tcpPorts.remove(&port);
+        }
+    }
+
+    pub fn TcpListen(&self, ep: &TcpEndpoint, backlog: usize) -> Result<()> {
+        let (bound, localAddr) = {
+            let state = ep.state.lock();
+            (state.bound, state.localAddr)
+        };
+        if !bound {
+            self.TcpBind(ep, localAddr, 0)?;
+        }
+
+        let mut inner = self.lock();
+        let (localAddr, localPort, v6only) = {
+            let mut state = ep.state.lock();
+            match state.state {
+                TcpState::Listen => {
+                    state.backlog = backlog;
+                    return Ok(());
+                }
+                TcpState::Closed => (),
+                _ => return Err(Error::SysError(SysErr::EINVAL)),
+            }
+            (state.localAddr, state.localPort, state.v6only)
+        };
+
+        if let Some(listeners) = inner.tcpListeners.get(&localPort) {
+            for other in listeners {
+                let state = other.state.lock();
+                if LocalAddrOverlap(localAddr, v6only, state.localAddr, state.v6only) {
+                    return Err(Error::SysError(SysErr::EADDRINUSE));
+                }
+            }
+        }
+
+        {
+            let mut state = ep.state.lock();
+            state.state = TcpState::Listen;
+            state.backlog = backlog;
+        }
+        inner
+            .tcpListeners
+            .entry(localPort)
+            .or_insert(Vec::new())
+            .push(ep.clone());
+        return Ok(());
+    }
+
+    // FindTcpListener returns the endpoint listening on port which accepts
+    // the connections to dst
+    pub fn FindTcpListener(&self, port: u16, dst: IpAddr) -> Option<TcpEndpoint> {
+        let inner = self.lock();
+        let listeners = inner.tcpListeners.get(&port)?;
+        for listener in listeners {
+            let state = listener.state.lock();
+            if LocalAddrMatch(state.localAddr, state.v6only, dst) {
+                return Some(listener.clone());
+            }
+        }
+
+        return None;
+    }
+
+    // TcpConnect starts the active open, the caller waits for the endpoint
+    // to leave SynSent
+    pub fn TcpConnect(&self, ep: &TcpEndpoint, dst: IpAddr, dstPort: u16) -> Result<()> {
+        {
+            let state = ep.state.lock();
+            match state.state {
+                TcpState::Closed => (),
+                TcpState::SynSent => return Err(Error::SysError(SysErr::EALREADY)),
+                TcpState::Listen => return Err(Error::SysError(SysErr::EINVAL)),
+                _ => return Err(Error::SysError(SysErr::EISCONN)),
+            }
+
+            if state.hasConnected {
+                return Err(Error::SysError(SysErr::EISCONN));
+            }
+        }
+
+        if dstPort == 0 {
+            return Err(Error::SysError(SysErr::ECONNREFUSED));
+        }
+
+        let (bound, localAddr) = {
+            let state = ep.state.lock();
+            (state.bound, state.localAddr)
+        };
+        if !bound {
+            self.TcpBind(ep, localAddr, 0)?;
+        }
+
+        let iss = self.NewIss();
+        let mtuMss = self.MtuMss(dst);
+        let src = self.SourceAddr(localAddr, dst)?;
+
+        let seg = {
+            let mut inner = self.lock();
+            let mut state = ep.state.lock();
+            let id = (src, state.localPort, dst, dstPort);
+            if inner.tcpConnections.contains_key(&id) {
+                return Err(Error::SysError(SysErr::EADDRNOTAVAIL));
+            }
+
+            state.localAddr = src;
+            state.remoteAddr = dst;
+            state.remotePort = dstPort;
+            state.iss = iss;
+            state.sndUna = iss;
+            state.sndNxt = iss.wrapping_add(1);
+            state.sndMax = state.sndNxt;
+            state.recover = iss;
+            state.mss = mtuMss;
+            state.state = TcpState::SynSent;
+            state.error = None;
+            state.retransmitDeadline = Some(MonotonicNow() + state.rto);
+            inner.tcpConnections.insert(id, ep.clone());
+            state.SynSegment(mtuMss)
+        };
+
+        // the netstack process starts the timer
+        self.Wakeup();
+        self.SendTcpSegment(seg);
+        return Ok(());
+    }
+
+    pub fn TcpAccept(&self, ep: &TcpEndpoint) -> Result<TcpEndpoint> {
+        let mut state = ep.state.lock();
+        if state.state != TcpState::Listen {
+            return Err(Error::SysError(SysErr::EINVAL));
+        }
+
+        match state.acceptQueue.pop_front() {
+            None => return Err(Error::SysError(SysErr::EAGAIN)),
+            Some(child) => return Ok(child),
+        }
+    }
+
+    pub fn TcpSend(&self, ep: &TcpEndpoint, data: &[u8]) -> Result<usize> {
+        let (n, segs) = {
+            let mut state = ep.state.lock();
+            if let Some(err) = state.error.take() {
+                return Err(Error::SysError(err));
+            }
+
+            if state.finQueued {
+                return Err(Error::SysError(SysErr::EPIPE));
+            }
+
+            match state.state {
+                TcpState::Established | TcpState::CloseWait => (),
+                TcpState::SynSent | TcpState::SynRcvd => {
+                    return Err(Error::SysError(SysErr::EAGAIN))
+                }
+                TcpState::Closed | TcpState::Listen if !state.hasConnected => {
+                    return Err(Error::SysError(SysErr::ENOTCONN))
+                }
+                _ => return Err(Error::SysError(SysErr::EPIPE)),
+            }
+
+            let n = core::cmp::min(state.SndSpace(), data.len());
+            if n == 0 {
+                return Err(Error::SysError(SysErr::EAGAIN));
+            }
+
+            state.sndBuf.extend(data[..n].iter());
+            let segs = state.Output(MonotonicNow());
+            (n, segs)
+        };
+
+        self.SendTcpSegments(segs);
+        return Ok(n);
+    }
+
+    // TcpRecv returns the received data up to len bytes. It returns an empty
+    // buffer on eof.
+    pub fn TcpRecv(&self, ep: &TcpEndpoint, len: usize, peek: bool) -> Result<Vec<u8>> {
+        let (data, update) = {
+            let mut state = ep.state.lock();
+            if state.rcvBuf.len() == 0 {
+                if let Some(err) = state.error.take() {
+                    return Err(Error::SysError(err));
+                }
+
+                if state.rcvClosed || state.shutRead {
+                    return Ok(Vec::new());
+                }
+
+                match state.state {
+                    TcpState::Closed if state.hasConnected => return Ok(Vec::new()),
+                    TcpState::Closed | TcpState::Listen => {
+                        return Err(Error::SysError(SysErr::ENOTCONN))
+                    }
+                    _ => return Err(Error::SysError(SysErr::EAGAIN)),
+                }
+            }
+
+            let n = core::cmp::min(len, state.rcvBuf.len());
+            let data: Vec<u8> = if peek {
+                state.rcvBuf.range(0..n).cloned().collect()
+            } else {
+                state.rcvBuf.drain(..n).collect()
+            };
+
+            // window update when the window opens from less than one mss
+            let update = if !peek && state.lastAdvWnd < state.mss && state.RcvWnd() >= state.mss {
+                Some(state.AckSegment())
+            } else {
+                None
+            };
+            (data, update)
+        };
+
+        if let Some(seg) = update {
+            self.SendTcpSegment(seg);
+        }
+
+        return Ok(data);
+    }
+
+    pub fn TcpShutdown(&self, ep: &TcpEndpoint, read: bool, write: bool) -> Result<()> {
+        let segs = {
+            let mut state = ep.state.lock();
+            match state.state {
+                TcpState::Closed | TcpState::Listen | TcpState::SynSent => {
+                    return Err(Error::SysError(SysErr::ENOTCONN))
+                }
+                _ => (),
+            }
+
+            if read {
+                state.shutRead = true;
+                state.rcvBuf.clear();
+            }
+
+            if write {
+                state.finQueued = true;
+            }
+
+            state.Output(MonotonicNow())
+        };
+
+        self.SendTcpSegments(segs);
+        ep.Notify();
+        return Ok(());
+    }
+
+    // TcpClose is called when the socket is released. The connection stays in
+    // the netstack until the fin handshake finishes.
+    pub fn TcpClose(&self, ep: &TcpEndpoint) {
+        let (st, pending) = {
+            let mut state = ep.state.lock();
+            let pending: Vec<TcpEndpoint> = state.acceptQueue.drain(..).collect();
+            (state.state, pending)
+        };
+
+        match st {
+            TcpState::Listen => {
+                {
+                    let mut inner = self.lock();
+                    let port = ep.state.lock().localPort;
+                    let remove = match inner.tcpListeners.get_mut(&port) {
+                        None => false,
+                        Some(listeners) => {
+                            listeners.retain(|l| l != ep);
+                            listeners.len() == 0
+                        }
+                    };
+                    if remove {
+                        inner.tcpListeners.remove(&port);
+                    }
+                }
+
+                ep.state.lock().state = TcpState::Closed;
+                self.TcpReleasePort(ep);
+                for child in pending {
+                    self.TcpAbort(&child);
+                }
+            }
+            TcpState::Closed => {
+                self.TcpReleasePort(ep);
+            }
+            TcpState::SynSent | TcpState::SynRcvd => {
+                self.TcpRemove(ep);
+            }
+            _ => {
+                let unread = ep.state.lock().rcvBuf.len() > 0;
+                if unread {
+                    // like linux, reset the connection when there is unread data
+                    self.TcpAbort(ep);
+                } else {
+                    self.TcpShutdown(ep, true, true).ok();
+                }
+            }
+        }
+    }
+
+    pub fn TcpAbort(&self, ep: &TcpEndpoint) {
+        let seg = {
+            let mut state = ep.state.lock();
+            if state.Synchronized() || state.state == TcpState::SynRcvd {
+                Some(state.RstSegment())
+            } else {
+                None
+            }
+        };
+
+        if let Some(seg) = seg {
+            self.SendTcpSegment(seg);
+        }
+
+        self.TcpRemove(ep);
+    }
+
+    pub fn TcpRemove(&self, ep: &TcpEndpoint) {
+        let id = {
+            let mut state = ep.state.lock();
+            state.state = TcpState::Closed;
+            state.retransmitDeadline = None;
+            state.timeWaitDeadline = None;
+            state.listener = None;
+            state.Id()
+        };
+
+        {
+            let mut inner = self.lock();
+            let remove = match inner.tcpConnections.get(&id) {
+                Some(curr) => curr == ep,
+                None => false,
+            };
+
+            if remove {
+                inner.tcpConnections.remove(&id);
+            }
+        }
+
+        self.TcpReleasePort(ep);
+        ep.Notify();
+    }
+
+    pub fn TcpTimerPending(&self) -> bool {
+        return self.lock().tcpConnections.len() > 0;
+    }
+
+    pub fn TcpTick(&self, now: i64) {
+        let eps: Vec<TcpEndpoint> = self.lock().tcpConnections.values().cloned().collect();
+        for ep in eps {
+            let remoteAddr = ep.state.lock().remoteAddr;
+            let mtuMss = self.MtuMss(remoteAddr);
+            let (segs, action) = {
+                let mut state = ep.state.lock();
+                let timeWait = state.timeWaitDeadline.unwrap_or(i64::MAX);
+                let retransmit = state.retransmitDeadline.unwrap_or(i64::MAX);
+                if timeWait <= now {
+                    (Vec::new(), TcpAction::Remove)
+                } else if retransmit <= now {
+                    state.Retransmit(now, mtuMss)
+                } else {
+                    continue;
+                }
+            };
+
+            self.SendTcpSegments(segs);
+            if action == TcpAction::Remove {
+                self.TcpRemove(&ep);
+            }
+        }
+    }
+
+    pub fn HandleTcp(&self, pkt: &IpPacket) {
+        if pkt.broadcast {
+            return;
+        }
+
+        let payload = pkt.Payload();
+        let hdr = match TcpHeader::Decode(payload, pkt.src, pkt.dst) {
+            None => return,
+            Some(hdr) => hdr,
+        };
+
+        let data = &payload[hdr.headerLen..];
+        let id = (pkt.dst, hdr.dstPort, pkt.src, hdr.srcPort);
+        let ep = self.lock().tcpConnections.get(&id).cloned();
+
+        let now = MonotonicNow();
+        let mtuMss = self.MtuMss(pkt.src);
+        match ep {
+            Some(ep) => {
+                let (segs, action) = ep.state.lock().HandleSegment(&hdr, data, now, mtuMss);
+                self.SendTcpSegments(segs);
+                match action {
+                    TcpAction::Remove => self.TcpRemove(&ep),
+                    TcpAction::Established => self.TcpEstablished(&ep),
+                    _ => ep.Notify(),
+                }
+            }
+            None => match self.FindTcpListener(hdr.dstPort, pkt.dst) {
+                Some(listener)
+                    if hdr.flags & (TCP_FLAG_SYN | TCP_FLAG_ACK | TCP_FLAG_RST) == TCP_FLAG_SYN =>
+                {
+                    self.TcpPassiveOpen(&listener, pkt, &hdr);
+                }
+                _ => self.SendTcpReset(pkt, &hdr, data.len()),
+            },
+        }
+    }
+
+    pub fn TcpPassiveOpen(&self, listener: &TcpEndpoint, pkt: &IpPacket, hdr: &TcpHeader) {
+        let (rcvBufSize, sndBufSize, reuseAddr) = {
+            let state = listener.state.lock();
+            if state.state != TcpState::Listen || state.acceptQueue.len() >= state.backlog {
+                // drop the syn, the peer retries
+                return;
+            }
+            (state.rcvBufSize, state.sndBufSize, state.reuseAddr)
+        };
+
+        let iss = self.NewIss();
+        let mtuMss = self.MtuMss(pkt.src);
+        let child = TcpEndpoint::New(listener.family, rcvBufSize, sndBufSize);
+        let seg = {
+            let mut inner = self.lock();
+            let mut state = child.state.lock();
+            state.state = TcpState::SynRcvd;
+            state.localAddr = pkt.dst;
+            state.localPort = hdr.dstPort;
+            state.remoteAddr = pkt.src;
+            state.remotePort = hdr.srcPort;
+            state.bound = true;
+            // the accepted socket inherits SO_REUSEADDR like on linux
+            state.reuseAddr = reuseAddr;
+            state.iss = iss;
+            state.sndUna = iss;
+            state.sndNxt = iss.wrapping_add(1);
+            state.sndMax = state.sndNxt;
+            state.recover = iss;
+            state.sndWnd = hdr.window as u32;
+            state.rcvNxt = hdr.seq.wrapping_add(1);
+            state.SetMss(hdr.mss, mtuMss);
+            state.SetWndScale(hdr.wndScale);
+            state.listener = Some(listener.clone());
+            state.retransmitDeadline = Some(MonotonicNow() + state.rto);
+
+            inner
+                .tcpPorts
+                .entry(hdr.dstPort)
+                .or_insert(Vec::new())
+                .push(child.clone());
+            inner.tcpConnections.insert(state.Id(), child.clone());
+            state.SynSegment(mtuMss)
+        };
+
+        self.SendTcpSegment(seg);
+    }
+
+    pub fn TcpEstablished(&self, child: &TcpEndpoint) {
+        let listener = child.state.lock().listener.take();
+        let listener = match listener {
+            None => return,
+            Some(l) => l,
+        };
+
+        let queued = {
+            let mut state = listener.state.lock();
+            if state.state == TcpState::Listen {
+                state.acceptQueue.push_back(child.clone());
+                true
+            } else {
+                false
+            }
+        };
+
+        if queued {
+            listener.Notify();
+        } else {
+            self.TcpAbort(child);
+        }
+    }
+
+    // SendTcpReset answers the segment which doesn't belong to any connection
+    pub fn SendTcpReset(&self, pkt: &IpPacket, hdr: &TcpHeader, dataLen: usize) {
+        if hdr.HasFlag(TCP_FLAG_RST) {
+            return;
+        }
+
+        let mut rst = TcpHeader {
+            srcPort: hdr.dstPort,
+            dstPort: hdr.srcPort,
+            headerLen: TCP_MIN_HEADER_SIZE,
+            ..Default::default()
+        };
+
+        if hdr.HasFlag(TCP_FLAG_ACK) {
+            rst.seq = hdr.ack;
+            rst.flags = TCP_FLAG_RST;
+        } else {
+            let mut len = dataLen as u32;
+            if hdr.HasFlag(TCP_FLAG_SYN) {
+                len += 1;
+            }
+            if hdr.HasFlag(TCP_FLAG_FIN) {
+                len += 1;
+            }
+            rst.ack = hdr.seq.wrapping_add(len);
+            rst.flags = TCP_FLAG_RST | TCP_FLAG_ACK;
+        }
+
+        self.SendTcpSegment(TcpSegment {
+            src: pkt.dst,
+            dst: pkt.src,
+            hdr: rst,
+            data: Vec::new(),
+        });
+    }
+
+    pub fn SendTcpSegments(&self, segs: Vec<TcpSegment>) {
+        for seg in segs {
+            self.SendTcpSegment(seg);
+        }
+    }
+
+    pub fn SendTcpSegment(&self, seg: TcpSegment) {
+        let len = seg.hdr.headerLen + seg.data.len();
+        let (mut buf, offset) = self.NewIpPacket(IPPROTO_TCP, seg.src, seg.dst, len);
+        buf[offset + seg.hdr.headerLen..].copy_from_slice(&seg.data);
+        seg.hdr.Encode(&mut buf[offset..], seg.src, seg.dst);
+        match self.SendIpPacket(buf, seg.dst) {
+            Err(e) => debug!("netstack send tcp segment fail with error {:?}", e),
+            Ok(()) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: usize = 1000;
+
+    fn Established() -> TcpEndpoint {
+        let ep = TcpEndpoint::New(AFType::AF_INET, 1 << 20, 1 << 20);
+        {
+            let mut state = ep.state.lock();
+            state.state = TcpState::Established;
+            state.iss = 100;
+            state.sndUna = 101;
+            state.sndNxt = 101;
+            state.sndMax = 101;
+            state.recover = 100;
+            state.sndWnd = 1 << 20;
+            state.rcvNxt = 1000;
+            state.SetMss(MSS as u16, MSS);
+        }
+        return ep;
+    }
+
+    fn Header(seq: u32, ack: u32, flags: u8, window: u16) -> TcpHeader {
+        return TcpHeader {
+            seq: seq,
+            ack: ack,
+            headerLen: TCP_MIN_HEADER_SIZE,
+            flags: flags,
+            window: window,
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_TcpOutOfOrder() {
+        let ep = Established();
+        let mut state = ep.state.lock();
+
+        let (segs, _) =
+            state.HandleSegment(&Header(1010, 101, TCP_FLAG_ACK, 65535), &[2; 10], 0, MSS);
+        assert_eq!(segs.len(), 1);
+        assert_eq!(segs[0].hdr.ack, 1000);
+        assert_eq!(state.rcvBuf.len(), 0);
+        assert_eq!(state.oooQueue.len(), 1);
+
+        // the retransmission of the queued segment is not queued again
+        state.HandleSegment(&Header(1010, 101, TCP_FLAG_ACK, 65535), &[2; 10], 0, MSS);
+        assert_eq!(state.oooQueue.len(), 1);
+
+        // the fin after the second gap
+        state.HandleSegment(
+            &Header(1025, 101, TCP_FLAG_ACK | TCP_FLAG_FIN, 65535),
+            &[3; 5],
+            0,
+            MSS,
+        );
+        assert_eq!(state.oooQueue.len(), 2);
+
+        // the gap is filled, the queued data follows in order
+        let (segs, _) =
+            state.HandleSegment(&Header(1000, 101, TCP_FLAG_ACK, 65535), &[1; 10], 0, MSS);
+        assert_eq!(segs[0].hdr.ack, 1020);
+        assert_eq!(state.rcvBuf.len(), 20);
+        assert_eq!(state.rcvBuf[10], 2);
+        assert_eq!(state.oooQueue.len(), 1);
+
+        // an overlapping segment fills the second gap and the fin is taken
+        let (segs, _) =
+            state.HandleSegment(&Header(1015, 101, TCP_FLAG_ACK, 65535), &[4; 12], 0, MSS);
+        assert_eq!(segs[0].hdr.ack, 1031);
+        assert_eq!(state.rcvBuf.len(), 30);
+        assert_eq!(state.rcvBuf[29], 3);
+        assert!(state.rcvClosed);
+        assert_eq!(state.state, TcpState::CloseWait);
+        assert_eq!(state.oooQueue.len(), 0);
+        assert_eq!(state.oooSize, 0);
+    }
+
+    #[test]
+    fn test_TcpWndScale() {
+        let ep = TcpEndpoint::New(AFType::AF_INET, 1 << 20, 1 << 20);
+        let mut state = ep.state.lock();
+        state.state = TcpState::SynSent;
+        state.iss = 100;
+        state.sndUna = 100;
+        state.sndNxt = 101;
+        state.sndMax = 101;
+
+        let syn = state.SynSegment(MSS);
+        assert_eq!(syn.hdr.wndScale, Some(5));
+        assert_eq!(syn.hdr.window as usize, TCP_MAX_WINDOW);
+
+        let mut synAck = Header(1000, 101, TCP_FLAG_SYN | TCP_FLAG_ACK, 1000);
+        synAck.wndScale = Some(7);
+        let (segs, action) = state.HandleSegment(&synAck, &[], 0, MSS);
+        assert_eq!(action, TcpAction::Connected);
+        assert_eq!(state.sndWnd, 1000);
+        assert_eq!(segs[0].hdr.window as usize, (1 << 20) >> 5);
+
+        state.HandleSegment(&Header(1001, 101, TCP_FLAG_ACK, 1000), &[], 0, MSS);
+        assert_eq!(state.sndWnd, 1000 << 7);
+
+        // a peer without the option turns the scaling off on both sides
+        state.SetWndScale(None);
+        assert_eq!(state.RcvWnd(), TCP_MAX_WINDOW);
+    }
+
+    #[test]
+    fn test_TcpCongestion() {
+        let ep = Established();
+        let mut state = ep.state.lock();
+
+        // the initial window limits the first flight
+        state.sndBuf.extend([0u8; 20 * MSS].iter());
+        let segs = state.Output(0);
+        assert_eq!(segs.len(), TCP_INITIAL_CWND);
+
+        // the slow start grows the window by the acked bytes
+        let (segs, _) = state.HandleSegment(
+            &Header(1000, 101 + MSS as u32, TCP_FLAG_ACK, 65535),
+            &[],
+            0,
+            MSS,
+        );
+        assert_eq!(state.cwnd, (TCP_INITIAL_CWND + 1) * MSS);
+        assert_eq!(segs.len(), 2);
+
+        // the third duplicate ack resends the lost segment
+        let ack = 101 + MSS as u32;
+        for i in 0..TCP_DUP_ACK_THRESHOLD {
+            let (segs, _) =
+                state.HandleSegment(&Header(1000, ack, TCP_FLAG_ACK, 65535), &[], 0, MSS);
+            if i + 1 < TCP_DUP_ACK_THRESHOLD {
+                assert_eq!(segs.len(), 0);
+            } else {
+                assert_eq!(segs[0].hdr.seq, ack);
+                assert_eq!(segs[0].data.len(), MSS);
+            }
+        }
+        assert!(state.inRecovery);
+        assert_eq!(state.ssthresh, 11 * MSS / 2);
+
+        // the partial ack resends the next hole
+        let (segs, _) = state.HandleSegment(
+            &Header(1000, ack + MSS as u32, TCP_FLAG_ACK, 65535),
+            &[],
+            0,
+            MSS,
+        );
+        assert_eq!(segs[0].hdr.seq, ack + MSS as u32);
+        assert!(state.inRecovery);
+
+        // the ack of all the data sent ends the recovery
+        let sndMax = state.sndMax;
+        state.HandleSegment(&Header(1000, sndMax, TCP_FLAG_ACK, 65535), &[], 0, MSS);
+        assert!(!state.inRecovery);
+        assert!(state.cwnd <= state.ssthresh);
+
+        // the timeout restarts the slow start from sndUna
+        state.Output(0);
+        let sndUna = state.sndUna;
+        let (segs, _) = state.Retransmit(0, MSS);
+        assert_eq!(segs[0].hdr.seq, sndUna);
+        assert_eq!(state.cwnd, MSS);
+        assert_eq!(state.sndNxt, sndUna + MSS as u32);
+        assert!(SeqLT(state.sndNxt, state.sndMax));
+
+        // the late ack of the data sent before the timeout is taken
+        let sndMax = state.sndMax;
+        state.HandleSegment(&Header(1000, sndMax, TCP_FLAG_ACK, 65535), &[], 0, MSS);
+        assert_eq!(state.sndUna, sndMax);
+        assert!(SeqLE(sndMax, state.sndNxt));
+    }
+}
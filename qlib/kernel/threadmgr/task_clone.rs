@@ -522,6 +522,7 @@ impl Task {
                     thread: Some(nt.clone()),
                     haveSyscallReturn: false,
                     syscallRestartBlock: None,
+                    syscallTrace: None,
                     futexMgr: futexMgr,
                     ioUsage: ioUsage,
                     sched: sched,
@@ -21,8 +21,10 @@ use crate::qlib::kernel::PAGE_MGR;
 
 use super::super::super::auth::id::*;
 use super::super::super::common::*;
+use super::super::super::control_msg::{KernelLogLevel, KernelLogType};
 use super::super::super::linux_def::*;
 use super::super::boot::controller::WriteWaitAllResponse;
+use super::super::kernel::syslog::KernelLog;
 use super::super::threadmgr::pid_namespace::*;
 use super::super::threadmgr::thread::*;
 use super::super::threadmgr::thread_group::*;
@@ -1021,7 +1023,18 @@ impl Thread {
                 " sending exit notification for CID:{}, execID:{}",
                 &cid, &execId
             );
-            WriteWaitAllResponse(cid.clone(), execId.clone(), tg.ExitStatus().Status() as i32);
+            let status = tg.ExitStatus();
+            KernelLog(
+                KernelLogType::Task,
+                KernelLogLevel::Info,
+                &cid,
+                tid,
+                format!(
+                    "process exited with code {} signal {}",
+                    status.Code, status.Signo
+                ),
+            );
+            WriteWaitAllResponse(cid.clone(), execId.clone(), status.Status() as i32);
             let curr = Task::Current();
             LOADER
                 .Lock(curr)
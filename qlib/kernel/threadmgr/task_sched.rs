@@ -28,6 +28,7 @@ use super::super::kernel::kernel::*;
 use super::super::kernel::time::*;
 use super::super::kernel::timer::timer::*;
 use super::super::kernel::waiter::*;
+use super::super::memmgr::page_reporting::*;
 use super::super::task::*;
 use super::super::threadmgr::thread::*;
 use super::super::threadmgr::thread_group::*;
@@ -708,6 +709,11 @@ impl KernelCPUClockTicker {
                 }
             }
         }
+
+        kernel.CheckMemoryLimit();
+        if let Err(e) = FREE_PAGE_REPORTER.Flush() {
+            error!("free page reporting fail {:?}", e);
+        }
     }
 
     pub fn Destroy(&self) {}
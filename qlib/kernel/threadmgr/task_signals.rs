@@ -16,11 +16,13 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 
 use super::super::super::common::*;
+use super::super::super::control_msg::{KernelLogLevel, KernelLogType};
 use super::super::super::cpuid::*;
 use super::super::super::linux::time::*;
 use super::super::super::linux_def::*;
 use super::super::arch::x86_64::arch_x86::*;
 use super::super::kernel::posixtimer::*;
+use super::super::kernel::syslog::KernelLog;
 use super::super::kernel::waiter::*;
 use super::super::stack::*;
 use super::super::task::*;
@@ -532,6 +534,13 @@ impl Thread {
         return SignalSet(pendingset | tg.lock().pendingSignals.pendingSet.0);
     }
 
+    // Killed returns whether a SIGKILL is pending, it aborts the waits which
+    // ignore the other signals.
+    pub fn Killed(&self) -> bool {
+        let kill = SignalSet::New(Signal(Signal::SIGKILL));
+        return self.PendingSignals().0 & kill.0 != 0;
+    }
+
     // PendingSignals returns the set of pending signals without lock. Just for signalfd readiness check.
     pub fn PendingSignalsNolock(&self) -> SignalSet {
         let tg = self.lock().tg.clone();
@@ -1058,6 +1067,14 @@ impl Task {
         match sigact {
             SignalAction::TERM | SignalAction::CORE => {
                 info!("Signal {}: terminating thread group", info.Signo);
+                let thread = self.Thread();
+                KernelLog(
+                    KernelLogType::Signal,
+                    KernelLogLevel::Notice,
+                    &thread.ContainerID(),
+                    thread.ThreadGroup().ID(),
+                    format!("terminated by signal {}", info.Signo),
+                );
                 //todo: fix this
                 //let tid = t.k.TaskSet().root.IDOfTask(self)
                 //let tid = 0xabcd;
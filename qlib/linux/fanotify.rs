@@ -0,0 +1,193 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Definitions from include/uapi/linux/fanotify.h.
+
+// Events which can be watched with fanotify_mark(2). The notification bits
+// have the same value as the inotify ones.
+pub const FAN_ACCESS: u64 = 0x00000001;
+pub const FAN_MODIFY: u64 = 0x00000002;
+pub const FAN_ATTRIB: u64 = 0x00000004;
+pub const FAN_CLOSE_WRITE: u64 = 0x00000008;
+pub const FAN_CLOSE_NOWRITE: u64 = 0x00000010;
+pub const FAN_OPEN: u64 = 0x00000020;
+pub const FAN_MOVED_FROM: u64 = 0x00000040;
+pub const FAN_MOVED_TO: u64 = 0x00000080;
+pub const FAN_CREATE: u64 = 0x00000100;
+pub const FAN_DELETE: u64 = 0x00000200;
+pub const FAN_DELETE_SELF: u64 = 0x00000400;
+pub const FAN_MOVE_SELF: u64 = 0x00000800;
+pub const FAN_OPEN_EXEC: u64 = 0x00001000;
+
+// FAN_Q_OVERFLOW is reported when the event queue overflowed.
+pub const FAN_Q_OVERFLOW: u64 = 0x00004000;
+
+// Permission events, the listener has to allow or deny the operation.
+pub const FAN_OPEN_PERM: u64 = 0x00010000;
+pub const FAN_ACCESS_PERM: u64 = 0x00020000;
+pub const FAN_OPEN_EXEC_PERM: u64 = 0x00040000;
+
+// FAN_EVENT_ON_CHILD reports the events of the children of a marked
+// directory.
+pub const FAN_EVENT_ON_CHILD: u64 = 0x08000000;
+
+// FAN_ONDIR reports the events on directories.
+pub const FAN_ONDIR: u64 = 0x40000000;
+
+pub const FAN_CLOSE: u64 = FAN_CLOSE_WRITE | FAN_CLOSE_NOWRITE;
+pub const FAN_MOVE: u64 = FAN_MOVED_FROM | FAN_MOVED_TO;
+
+// FANOTIFY_PERM_EVENTS are the events waiting for a listener response.
+pub const FANOTIFY_PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM | FAN_OPEN_EXEC_PERM;
+
+// FANOTIFY_PATH_EVENTS are reported with an fd of the object.
+pub const FANOTIFY_PATH_EVENTS: u64 =
+    FAN_ACCESS | FAN_MODIFY | FAN_CLOSE | FAN_OPEN | FAN_OPEN_EXEC;
+
+// FANOTIFY_DIRENT_EVENTS are reported on the parent directory of the
+// changed entry.
+pub const FANOTIFY_DIRENT_EVENTS: u64 = FAN_MOVE | FAN_CREATE | FAN_DELETE;
+
+// FANOTIFY_INODE_EVENTS need a listener reporting file ids.
+pub const FANOTIFY_INODE_EVENTS: u64 =
+    FANOTIFY_DIRENT_EVENTS | FAN_ATTRIB | FAN_MOVE_SELF | FAN_DELETE_SELF;
+
+pub const FANOTIFY_EVENTS: u64 = FANOTIFY_PATH_EVENTS | FANOTIFY_INODE_EVENTS;
+
+pub const FANOTIFY_EVENT_FLAGS: u64 = FAN_EVENT_ON_CHILD | FAN_ONDIR;
+
+pub const FANOTIFY_MARK_MASK: u64 = FANOTIFY_EVENTS | FANOTIFY_PERM_EVENTS | FANOTIFY_EVENT_FLAGS;
+
+// Flags of fanotify_init(2).
+pub const FAN_CLOEXEC: u32 = 0x00000001;
+pub const FAN_NONBLOCK: u32 = 0x00000002;
+
+pub const FAN_CLASS_NOTIF: u32 = 0x00000000;
+pub const FAN_CLASS_CONTENT: u32 = 0x00000004;
+pub const FAN_CLASS_PRE_CONTENT: u32 = 0x00000008;
+pub const FAN_ALL_CLASS_BITS: u32 = FAN_CLASS_CONTENT | FAN_CLASS_PRE_CONTENT;
+
+pub const FAN_UNLIMITED_QUEUE: u32 = 0x00000010;
+pub const FAN_UNLIMITED_MARKS: u32 = 0x00000020;
+pub const FAN_ENABLE_AUDIT: u32 = 0x00000040;
+
+pub const FAN_REPORT_PIDFD: u32 = 0x00000080;
+pub const FAN_REPORT_TID: u32 = 0x00000100;
+pub const FAN_REPORT_FID: u32 = 0x00000200;
+pub const FAN_REPORT_DIR_FID: u32 = 0x00000400;
+pub const FAN_REPORT_NAME: u32 = 0x00000800;
+
+// FANOTIFY_INIT_FLAGS are the fanotify_init(2) flags supported by the
+// sentry.
+pub const FANOTIFY_INIT_FLAGS: u32 = FAN_CLOEXEC
+    | FAN_NONBLOCK
+    | FAN_ALL_CLASS_BITS
+    | FAN_UNLIMITED_QUEUE
+    | FAN_UNLIMITED_MARKS
+    | FAN_ENABLE_AUDIT
+    | FAN_REPORT_TID
+    | FAN_REPORT_FID;
+
+// Flags of fanotify_mark(2).
+pub const FAN_MARK_ADD: u32 = 0x00000001;
+pub const FAN_MARK_REMOVE: u32 = 0x00000002;
+pub const FAN_MARK_DONT_FOLLOW: u32 = 0x00000004;
+pub const FAN_MARK_ONLYDIR: u32 = 0x00000008;
+pub const FAN_MARK_IGNORED_MASK: u32 = 0x00000020;
+pub const FAN_MARK_IGNORED_SURV_MODIFY: u32 = 0x00000040;
+pub const FAN_MARK_FLUSH: u32 = 0x00000080;
+
+pub const FAN_MARK_INODE: u32 = 0x00000000;
+pub const FAN_MARK_MOUNT: u32 = 0x00000010;
+pub const FAN_MARK_FILESYSTEM: u32 = 0x00000100;
+pub const FAN_MARK_TYPE_MASK: u32 = FAN_MARK_INODE | FAN_MARK_MOUNT | FAN_MARK_FILESYSTEM;
+
+pub const FANOTIFY_MARK_FLAGS: u32 = FAN_MARK_TYPE_MASK
+    | FAN_MARK_ADD
+    | FAN_MARK_REMOVE
+    | FAN_MARK_DONT_FOLLOW
+    | FAN_MARK_ONLYDIR
+    | FAN_MARK_IGNORED_MASK
+    | FAN_MARK_IGNORED_SURV_MODIFY
+    | FAN_MARK_FLUSH;
+
+// FANOTIFY_DEFAULT_MAX_EVENTS is the queue size of a listener without
+// FAN_UNLIMITED_QUEUE.
+pub const FANOTIFY_DEFAULT_MAX_EVENTS: usize = 16384;
+
+// FANOTIFY_DEFAULT_MAX_MARKS is the number of marks of a listener without
+// FAN_UNLIMITED_MARKS.
+pub const FANOTIFY_DEFAULT_MAX_MARKS: usize = 8192;
+
+pub const FANOTIFY_METADATA_VERSION: u8 = 3;
+
+// FAN_NOFD is the fd of an event without an object fd.
+pub const FAN_NOFD: i32 = -1;
+
+// Responses to the permission events.
+pub const FAN_ALLOW: u32 = 0x01;
+pub const FAN_DENY: u32 = 0x02;
+pub const FAN_AUDIT: u32 = 0x10;
+
+// Types of the information records following an event.
+pub const FAN_EVENT_INFO_TYPE_FID: u8 = 1;
+
+// FILEID_INO64_GEN is the file handle type of a 64 bits inode number and a
+// 32 bits generation.
+pub const FILEID_INO64_GEN: i32 = 0x81;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FanotifyEventMetadata {
+    pub EventLen: u32,
+    pub Vers: u8,
+    pub Reserved: u8,
+    pub MetadataLen: u16,
+    pub Mask: u64,
+    pub Fd: i32,
+    pub Pid: i32,
+}
+
+pub const FAN_EVENT_METADATA_LEN: usize = core::mem::size_of::<FanotifyEventMetadata>();
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FanotifyEventInfoHeader {
+    pub InfoType: u8,
+    pub Pad: u8,
+    pub Len: u16,
+}
+
+// FanotifyEventInfoFid is struct fanotify_event_info_fid followed by the
+// struct file_handle of a FILEID_INO64_GEN handle.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FanotifyEventInfoFid {
+    pub Hdr: FanotifyEventInfoHeader,
+    pub Fsid: [i32; 2],
+    pub HandleBytes: u32,
+    pub HandleType: i32,
+    pub Ino: u32,
+    pub InoHigh: u32,
+    pub Gen: u32,
+}
+
+pub const FAN_EVENT_INFO_FID_LEN: usize = core::mem::size_of::<FanotifyEventInfoFid>();
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FanotifyResponse {
+    pub Fd: i32,
+    pub Response: u32,
+}
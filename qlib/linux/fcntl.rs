@@ -24,6 +24,9 @@ pub const F_SETOWN: i32 = 8;
 pub const F_GETOWN: i32 = 9;
 pub const F_SETOWN_EX: i32 = 15;
 pub const F_GETOWN_EX: i32 = 16;
+pub const F_OFD_GETLK: i32 = 36;
+pub const F_OFD_SETLK: i32 = 37;
+pub const F_OFD_SETLKW: i32 = 38;
 pub const F_DUPFD_CLOEXEC: i32 = 1024 + 6;
 pub const F_SETPIPE_SZ: i32 = 1024 + 7;
 pub const F_GETPIPE_SZ: i32 = 1024 + 8;
@@ -0,0 +1,470 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Definitions from include/uapi/linux/fuse.h.
+
+// FUSE_KERNEL_VERSION is the major version of the FUSE protocol.
+pub const FUSE_KERNEL_VERSION: u32 = 7;
+// FUSE_KERNEL_MINOR_VERSION is the minor version of the FUSE protocol
+// supported by the sentry.
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+
+// FUSE_ROOT_ID is the node id of the root of a FUSE file system.
+pub const FUSE_ROOT_ID: u64 = 1;
+
+// FUSE_MIN_READ_BUFFER is the minimum size of the buffer the daemon reads
+// requests into.
+pub const FUSE_MIN_READ_BUFFER: usize = 8192;
+
+// FUSE_DEFAULT_MAX_PAGES is the default max_pages of a connection, it bounds
+// the size of a single READ or WRITE.
+pub const FUSE_DEFAULT_MAX_PAGES: u32 = 32;
+
+// FUSE_DEV_MAJOR and FUSE_DEV_MINOR are the device numbers of /dev/fuse.
+pub const FUSE_DEV_MAJOR: u16 = 10;
+pub const FUSE_DEV_MINOR: u32 = 229;
+
+// FUSE_SUPER_MAGIC is the statfs magic of a FUSE file system.
+pub const FUSE_SUPER_MAGIC: u64 = 0x65735546;
+
+// Opcodes of the FUSE requests.
+pub const FUSE_LOOKUP: u32 = 1;
+pub const FUSE_FORGET: u32 = 2;
+pub const FUSE_GETATTR: u32 = 3;
+pub const FUSE_SETATTR: u32 = 4;
+pub const FUSE_READLINK: u32 = 5;
+pub const FUSE_SYMLINK: u32 = 6;
+pub const FUSE_MKNOD: u32 = 8;
+pub const FUSE_MKDIR: u32 = 9;
+pub const FUSE_UNLINK: u32 = 10;
+pub const FUSE_RMDIR: u32 = 11;
+pub const FUSE_RENAME: u32 = 12;
+pub const FUSE_LINK: u32 = 13;
+pub const FUSE_OPEN: u32 = 14;
+pub const FUSE_READ: u32 = 15;
+pub const FUSE_WRITE: u32 = 16;
+pub const FUSE_STATFS: u32 = 17;
+pub const FUSE_RELEASE: u32 = 18;
+pub const FUSE_FSYNC: u32 = 20;
+pub const FUSE_SETXATTR: u32 = 21;
+pub const FUSE_GETXATTR: u32 = 22;
+pub const FUSE_LISTXATTR: u32 = 23;
+pub const FUSE_REMOVEXATTR: u32 = 24;
+pub const FUSE_FLUSH: u32 = 25;
+pub const FUSE_INIT: u32 = 26;
+pub const FUSE_OPENDIR: u32 = 27;
+pub const FUSE_READDIR: u32 = 28;
+pub const FUSE_RELEASEDIR: u32 = 29;
+pub const FUSE_FSYNCDIR: u32 = 30;
+pub const FUSE_GETLK: u32 = 31;
+pub const FUSE_SETLK: u32 = 32;
+pub const FUSE_SETLKW: u32 = 33;
+pub const FUSE_ACCESS: u32 = 34;
+pub const FUSE_CREATE: u32 = 35;
+pub const FUSE_INTERRUPT: u32 = 36;
+pub const FUSE_BMAP: u32 = 37;
+pub const FUSE_DESTROY: u32 = 38;
+pub const FUSE_IOCTL: u32 = 39;
+pub const FUSE_POLL: u32 = 40;
+pub const FUSE_NOTIFY_REPLY: u32 = 41;
+pub const FUSE_BATCH_FORGET: u32 = 42;
+pub const FUSE_FALLOCATE: u32 = 43;
+pub const FUSE_READDIRPLUS: u32 = 44;
+pub const FUSE_RENAME2: u32 = 45;
+pub const FUSE_LSEEK: u32 = 46;
+
+// Flags of FUSE_INIT.
+pub const FUSE_ASYNC_READ: u32 = 1 << 0;
+pub const FUSE_POSIX_LOCKS: u32 = 1 << 1;
+pub const FUSE_FILE_OPS: u32 = 1 << 2;
+pub const FUSE_ATOMIC_O_TRUNC: u32 = 1 << 3;
+pub const FUSE_EXPORT_SUPPORT: u32 = 1 << 4;
+pub const FUSE_BIG_WRITES: u32 = 1 << 5;
+pub const FUSE_DONT_MASK: u32 = 1 << 6;
+pub const FUSE_SPLICE_WRITE: u32 = 1 << 7;
+pub const FUSE_SPLICE_MOVE: u32 = 1 << 8;
+pub const FUSE_SPLICE_READ: u32 = 1 << 9;
+pub const FUSE_FLOCK_LOCKS: u32 = 1 << 10;
+pub const FUSE_HAS_IOCTL_DIR: u32 = 1 << 11;
+pub const FUSE_AUTO_INVAL_DATA: u32 = 1 << 12;
+pub const FUSE_DO_READDIRPLUS: u32 = 1 << 13;
+pub const FUSE_READDIRPLUS_AUTO: u32 = 1 << 14;
+pub const FUSE_ASYNC_DIO: u32 = 1 << 15;
+pub const FUSE_WRITEBACK_CACHE: u32 = 1 << 16;
+pub const FUSE_NO_OPEN_SUPPORT: u32 = 1 << 17;
+pub const FUSE_PARALLEL_DIROPS: u32 = 1 << 18;
+pub const FUSE_HANDLE_KILLPRIV: u32 = 1 << 19;
+pub const FUSE_POSIX_ACL: u32 = 1 << 20;
+pub const FUSE_ABORT_ERROR: u32 = 1 << 21;
+pub const FUSE_MAX_PAGES: u32 = 1 << 22;
+pub const FUSE_CACHE_SYMLINKS: u32 = 1 << 23;
+pub const FUSE_NO_OPENDIR_SUPPORT: u32 = 1 << 24;
+pub const FUSE_EXPLICIT_INVAL_DATA: u32 = 1 << 25;
+
+// Valid bits of FUSE_SETATTR.
+pub const FATTR_MODE: u32 = 1 << 0;
+pub const FATTR_UID: u32 = 1 << 1;
+pub const FATTR_GID: u32 = 1 << 2;
+pub const FATTR_SIZE: u32 = 1 << 3;
+pub const FATTR_ATIME: u32 = 1 << 4;
+pub const FATTR_MTIME: u32 = 1 << 5;
+pub const FATTR_FH: u32 = 1 << 6;
+pub const FATTR_ATIME_NOW: u32 = 1 << 7;
+pub const FATTR_MTIME_NOW: u32 = 1 << 8;
+pub const FATTR_LOCKOWNER: u32 = 1 << 9;
+pub const FATTR_CTIME: u32 = 1 << 10;
+
+// Flags returned by FUSE_OPEN and FUSE_OPENDIR.
+pub const FOPEN_DIRECT_IO: u32 = 1 << 0;
+pub const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+pub const FOPEN_NONSEEKABLE: u32 = 1 << 2;
+
+// Flags of FUSE_FSYNC and FUSE_FSYNCDIR.
+pub const FUSE_FSYNC_FDATASYNC: u32 = 1 << 0;
+
+// FuseHeaderIn is the header of every request read from /dev/fuse.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseHeaderIn {
+    pub Len: u32,
+    pub Opcode: u32,
+    pub Unique: u64,
+    pub NodeID: u64,
+    pub UID: u32,
+    pub GID: u32,
+    pub PID: u32,
+    pub Padding: u32,
+}
+
+// FuseHeaderOut is the header of every reply written to /dev/fuse.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseHeaderOut {
+    pub Len: u32,
+    pub Error: i32,
+    pub Unique: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInitIn {
+    pub Major: u32,
+    pub Minor: u32,
+    pub MaxReadahead: u32,
+    pub Flags: u32,
+}
+
+// FuseInitOut is the reply of FUSE_INIT, daemons speaking an older minor
+// version send a shorter reply.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInitOut {
+    pub Major: u32,
+    pub Minor: u32,
+    pub MaxReadahead: u32,
+    pub Flags: u32,
+    pub MaxBackground: u16,
+    pub CongestionThreshold: u16,
+    pub MaxWrite: u32,
+    pub TimeGran: u32,
+    pub MaxPages: u16,
+    pub MapAlignment: u16,
+    pub Unused: [u32; 8],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseAttr {
+    pub Ino: u64,
+    pub Size: u64,
+    pub Blocks: u64,
+    pub Atime: u64,
+    pub Mtime: u64,
+    pub Ctime: u64,
+    pub AtimeNsec: u32,
+    pub MtimeNsec: u32,
+    pub CtimeNsec: u32,
+    pub Mode: u32,
+    pub Nlink: u32,
+    pub UID: u32,
+    pub GID: u32,
+    pub Rdev: u32,
+    pub BlkSize: u32,
+    pub Padding: u32,
+}
+
+// FuseEntryOut is the reply of FUSE_LOOKUP, FUSE_MKNOD, FUSE_MKDIR,
+// FUSE_SYMLINK, FUSE_LINK and the first part of the FUSE_CREATE reply.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseEntryOut {
+    pub NodeID: u64,
+    pub Generation: u64,
+    pub EntryValid: u64,
+    pub AttrValid: u64,
+    pub EntryValidNsec: u32,
+    pub AttrValidNsec: u32,
+    pub Attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseForgetIn {
+    pub Nlookup: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInterruptIn {
+    pub Unique: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseGetAttrIn {
+    pub GetAttrFlags: u32,
+    pub Dummy: u32,
+    pub Fh: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseAttrOut {
+    pub AttrValid: u64,
+    pub AttrValidNsec: u32,
+    pub Dummy: u32,
+    pub Attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseSetAttrIn {
+    pub Valid: u32,
+    pub Padding: u32,
+    pub Fh: u64,
+    pub Size: u64,
+    pub LockOwner: u64,
+    pub Atime: u64,
+    pub Mtime: u64,
+    pub Ctime: u64,
+    pub AtimeNsec: u32,
+    pub MtimeNsec: u32,
+    pub CtimeNsec: u32,
+    pub Mode: u32,
+    pub Unused4: u32,
+    pub UID: u32,
+    pub GID: u32,
+    pub Unused5: u32,
+}
+
+// FuseMknodIn is followed by the name of the new node.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseMknodIn {
+    pub Mode: u32,
+    pub Rdev: u32,
+    pub Umask: u32,
+    pub Padding: u32,
+}
+
+// FuseMkdirIn is followed by the name of the new directory.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseMkdirIn {
+    pub Mode: u32,
+    pub Umask: u32,
+}
+
+// FuseRenameIn is followed by the old and the new name.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseRenameIn {
+    pub Newdir: u64,
+}
+
+// FuseLinkIn is followed by the name of the new link.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseLinkIn {
+    pub Oldnodeid: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseOpenIn {
+    pub Flags: u32,
+    pub Unused: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseOpenOut {
+    pub Fh: u64,
+    pub OpenFlags: u32,
+    pub Padding: u32,
+}
+
+// FuseCreateIn is followed by the name of the new file.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseCreateIn {
+    pub Flags: u32,
+    pub Mode: u32,
+    pub Umask: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseCreateOut {
+    pub Entry: FuseEntryOut,
+    pub Open: FuseOpenOut,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseReleaseIn {
+    pub Fh: u64,
+    pub Flags: u32,
+    pub ReleaseFlags: u32,
+    pub LockOwner: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseFlushIn {
+    pub Fh: u64,
+    pub Unused: u32,
+    pub Padding: u32,
+    pub LockOwner: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseReadIn {
+    pub Fh: u64,
+    pub Offset: u64,
+    pub Size: u32,
+    pub ReadFlags: u32,
+    pub LockOwner: u64,
+    pub Flags: u32,
+    pub Padding: u32,
+}
+
+// FuseWriteIn is followed by the data to write.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseWriteIn {
+    pub Fh: u64,
+    pub Offset: u64,
+    pub Size: u32,
+    pub WriteFlags: u32,
+    pub LockOwner: u64,
+    pub Flags: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseWriteOut {
+    pub Size: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseFsyncIn {
+    pub Fh: u64,
+    pub FsyncFlags: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseKstatfs {
+    pub Blocks: u64,
+    pub Bfree: u64,
+    pub Bavail: u64,
+    pub Files: u64,
+    pub Ffree: u64,
+    pub Bsize: u32,
+    pub NameLen: u32,
+    pub Frsize: u32,
+    pub Padding: u32,
+    pub Spare: [u32; 6],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseStatfsOut {
+    pub St: FuseKstatfs,
+}
+
+// FuseSetxattrIn is followed by the attribute name and the value.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseSetxattrIn {
+    pub Size: u32,
+    pub Flags: u32,
+}
+
+// FuseGetxattrIn is followed by the attribute name for FUSE_GETXATTR, a zero
+// size asks for the size of the value or of the list.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseGetxattrIn {
+    pub Size: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseGetxattrOut {
+    pub Size: u32,
+    pub Padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseFallocateIn {
+    pub Fh: u64,
+    pub Offset: u64,
+    pub Length: u64,
+    pub Mode: u32,
+    pub Padding: u32,
+}
+
+// FuseDirent is an entry of the FUSE_READDIR reply, it is followed by the
+// name and padded to 8 bytes.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseDirent {
+    pub Ino: u64,
+    pub Off: u64,
+    pub NameLen: u32,
+    pub Type: u32,
+}
+
+// FuseDirentplus is an entry of the FUSE_READDIRPLUS reply.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseDirentplus {
+    pub EntryOut: FuseEntryOut,
+    pub Dirent: FuseDirent,
+}
+
+// FuseDirentAlign rounds up the size of a directory entry to 8 bytes.
+pub fn FuseDirentAlign(x: usize) -> usize {
+    return (x + 7) & !7;
+}
@@ -12,14 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod fanotify;
 pub mod fcntl;
+pub mod fuse;
 pub mod futex;
 pub mod inotify;
 pub mod ipc;
 pub mod limits;
 pub mod membarrier;
 pub mod msgqueue;
+pub mod netfilter;
 pub mod netdevice;
+pub mod quota;
 pub mod rusage;
 pub mod sem;
 pub mod shm;
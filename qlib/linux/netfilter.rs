@@ -0,0 +1,197 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Definitions from include/uapi/linux/netfilter.h, netfilter/x_tables.h,
+// netfilter_ipv4/ip_tables.h, netfilter_ipv6/ip6_tables.h and
+// netfilter/nf_nat.h. The structures are variable length blobs, they are
+// described by their sizes and field offsets.
+
+use super::socket::{SOL_IP, SOL_IPV6};
+
+// The hooks.
+pub const NF_INET_PRE_ROUTING: usize = 0;
+pub const NF_INET_LOCAL_IN: usize = 1;
+pub const NF_INET_FORWARD: usize = 2;
+pub const NF_INET_LOCAL_OUT: usize = 3;
+pub const NF_INET_POST_ROUTING: usize = 4;
+pub const NF_INET_NUMHOOKS: usize = 5;
+
+// The verdicts.
+pub const NF_DROP: i32 = 0;
+pub const NF_ACCEPT: i32 = 1;
+pub const NF_REPEAT: i32 = 4;
+pub const XT_RETURN: i32 = -NF_REPEAT - 1;
+
+// The socket options of SOL_IP, the ip6tables ones of SOL_IPV6 have the
+// same values.
+pub const IPT_SO_SET_REPLACE: i32 = 64;
+pub const IPT_SO_SET_ADD_COUNTERS: i32 = 65;
+pub const IPT_SO_GET_INFO: i32 = 64;
+pub const IPT_SO_GET_ENTRIES: i32 = 65;
+pub const IPT_SO_GET_REVISION_MATCH: i32 = 66;
+pub const IPT_SO_GET_REVISION_TARGET: i32 = 67;
+pub const SO_ORIGINAL_DST: i32 = 80;
+
+// The max size of the option buffer of the netfilter socket options, the
+// ruleset is passed in one setsockopt.
+pub const XT_MAX_OPT_LEN: usize = 4 << 20;
+
+pub const XT_TABLE_MAXNAMELEN: usize = 32;
+pub const XT_EXTENSION_MAXNAMELEN: usize = 29;
+pub const XT_IFNAMSIZ: usize = 16;
+
+pub const XT_STANDARD_TARGET: &str = "";
+pub const XT_ERROR_TARGET: &str = "ERROR";
+
+// struct ipt_getinfo and struct ip6t_getinfo
+pub const XT_GETINFO_SIZE: usize = 84;
+pub const XT_GETINFO_VALID_HOOKS: usize = 32;
+pub const XT_GETINFO_HOOK_ENTRY: usize = 36;
+pub const XT_GETINFO_UNDERFLOW: usize = 56;
+pub const XT_GETINFO_NUM_ENTRIES: usize = 76;
+pub const XT_GETINFO_SIZE_OFFSET: usize = 80;
+
+// struct ipt_get_entries and struct ip6t_get_entries, the entries follow
+// the header
+pub const XT_GET_ENTRIES_SIZE: usize = 40;
+pub const XT_GET_ENTRIES_SIZE_OFFSET: usize = 32;
+
+// struct ipt_replace and struct ip6t_replace, the entries follow the header
+pub const XT_REPLACE_SIZE: usize = 96;
+pub const XT_REPLACE_VALID_HOOKS: usize = 32;
+pub const XT_REPLACE_NUM_ENTRIES: usize = 36;
+pub const XT_REPLACE_SIZE_OFFSET: usize = 40;
+pub const XT_REPLACE_HOOK_ENTRY: usize = 44;
+pub const XT_REPLACE_UNDERFLOW: usize = 64;
+pub const XT_REPLACE_NUM_COUNTERS: usize = 84;
+pub const XT_REPLACE_COUNTERS: usize = 88;
+
+// struct xt_counters_info, the counters follow the header
+pub const XT_COUNTERS_INFO_SIZE: usize = 40;
+pub const XT_COUNTERS_INFO_NUM_COUNTERS: usize = 32;
+// struct xt_counters, the packet and byte counters
+pub const XT_COUNTERS_SIZE: usize = 16;
+
+// struct xt_get_revision
+pub const XT_GET_REVISION_SIZE: usize = 30;
+
+// struct ipt_entry, the ipt_ip match is at its start
+pub const IPT_ENTRY_SIZE: usize = 112;
+pub const IPT_IP_SRC: usize = 0;
+pub const IPT_IP_DST: usize = 4;
+pub const IPT_IP_SMSK: usize = 8;
+pub const IPT_IP_DMSK: usize = 12;
+pub const IPT_IP_INIFACE: usize = 16;
+pub const IPT_IP_OUTIFACE: usize = 32;
+pub const IPT_IP_INIFACE_MASK: usize = 48;
+pub const IPT_IP_OUTIFACE_MASK: usize = 64;
+pub const IPT_IP_PROTO: usize = 80;
+pub const IPT_IP_FLAGS: usize = 82;
+pub const IPT_IP_INVFLAGS: usize = 83;
+pub const IPT_ENTRY_TARGET_OFFSET: usize = 88;
+pub const IPT_ENTRY_NEXT_OFFSET: usize = 90;
+pub const IPT_ENTRY_COUNTERS: usize = 96;
+
+// struct ip6t_entry, the ip6t_ip6 match is at its start
+pub const IP6T_ENTRY_SIZE: usize = 168;
+pub const IP6T_IP6_SRC: usize = 0;
+pub const IP6T_IP6_DST: usize = 16;
+pub const IP6T_IP6_SMSK: usize = 32;
+pub const IP6T_IP6_DMSK: usize = 48;
+pub const IP6T_IP6_INIFACE: usize = 64;
+pub const IP6T_IP6_OUTIFACE: usize = 80;
+pub const IP6T_IP6_INIFACE_MASK: usize = 96;
+pub const IP6T_IP6_OUTIFACE_MASK: usize = 112;
+pub const IP6T_IP6_PROTO: usize = 128;
+pub const IP6T_IP6_TOS: usize = 130;
+pub const IP6T_IP6_FLAGS: usize = 131;
+pub const IP6T_IP6_INVFLAGS: usize = 132;
+pub const IP6T_ENTRY_TARGET_OFFSET: usize = 140;
+pub const IP6T_ENTRY_NEXT_OFFSET: usize = 142;
+pub const IP6T_ENTRY_COUNTERS: usize = 152;
+
+// The flags of ipt_ip.
+pub const IPT_F_FRAG: u8 = 0x01;
+pub const IPT_F_GOTO: u8 = 0x02;
+pub const IPT_F_MASK: u8 = 0x03;
+
+// The flags of ip6t_ip6.
+pub const IP6T_F_PROTO: u8 = 0x01;
+pub const IP6T_F_TOS: u8 = 0x02;
+pub const IP6T_F_GOTO: u8 = 0x04;
+pub const IP6T_F_MASK: u8 = 0x07;
+
+// The inverse flags of ipt_ip and ip6t_ip6.
+pub const XT_INV_VIA_IN: u8 = 0x01;
+pub const XT_INV_VIA_OUT: u8 = 0x02;
+pub const XT_INV_TOS: u8 = 0x04;
+pub const XT_INV_SRCIP: u8 = 0x08;
+pub const XT_INV_DSTIP: u8 = 0x10;
+pub const XT_INV_FRAG: u8 = 0x20;
+pub const XT_INV_PROTO: u8 = 0x40;
+pub const XT_INV_MASK: u8 = 0x7f;
+
+// struct xt_entry_match and struct xt_entry_target, the data follows the
+// header
+pub const XT_ENTRY_MATCH_SIZE: usize = 32;
+pub const XT_ENTRY_TARGET_SIZE: usize = 32;
+pub const XT_ENTRY_NAME: usize = 2;
+pub const XT_ENTRY_REVISION: usize = 31;
+
+// struct xt_standard_target and struct xt_error_target
+pub const XT_STANDARD_TARGET_SIZE: usize = 40;
+pub const XT_ERROR_TARGET_SIZE: usize = 64;
+
+// struct xt_tcp
+pub const XT_TCP_SIZE: usize = 12;
+pub const XT_TCP_INV_SRCPT: u8 = 0x01;
+pub const XT_TCP_INV_DSTPT: u8 = 0x02;
+pub const XT_TCP_INV_FLAGS: u8 = 0x04;
+pub const XT_TCP_INV_OPTION: u8 = 0x08;
+pub const XT_TCP_INV_MASK: u8 = 0x0f;
+
+// struct xt_udp
+pub const XT_UDP_SIZE: usize = 10;
+pub const XT_UDP_INV_SRCPT: u8 = 0x01;
+pub const XT_UDP_INV_DSTPT: u8 = 0x02;
+pub const XT_UDP_INV_MASK: u8 = 0x03;
+
+// struct nf_nat_ipv4_multi_range_compat with one range, struct nf_nat_range
+// and struct nf_nat_range2
+pub const NF_NAT_IPV4_MULTI_RANGE_SIZE: usize = 20;
+pub const NF_NAT_RANGE_SIZE: usize = 40;
+pub const NF_NAT_RANGE2_SIZE: usize = 44;
+
+pub const NF_NAT_RANGE_MAP_IPS: u32 = 1 << 0;
+pub const NF_NAT_RANGE_PROTO_SPECIFIED: u32 = 1 << 1;
+pub const NF_NAT_RANGE_PROTO_RANDOM: u32 = 1 << 2;
+pub const NF_NAT_RANGE_PERSISTENT: u32 = 1 << 3;
+pub const NF_NAT_RANGE_PROTO_RANDOM_FULLY: u32 = 1 << 4;
+pub const NF_NAT_RANGE_PROTO_OFFSET: u32 = 1 << 5;
+
+// XT_ALIGN rounds the size up to the alignment of the entries
+pub fn XtAlign(size: usize) -> usize {
+    return (size + 7) & !7;
+}
+
+// IsNetfilterSockOpt returns whether the option is one of the ruleset
+// options whose buffer isn't bounded by the usual option size and whose
+// getsockopt reads its input from the option buffer
+pub fn IsNetfilterSockOpt(level: i32, name: i32) -> bool {
+    if level != SOL_IP && level != SOL_IPV6 {
+        return false;
+    }
+
+    return name >= IPT_SO_SET_REPLACE && name <= IPT_SO_GET_REVISION_TARGET;
+}
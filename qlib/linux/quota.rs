@@ -0,0 +1,107 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Definitions from include/uapi/linux/quota.h.
+
+// The quotactl(2) command is the subcommand shifted by SUBCMDSHIFT and the
+// quota type in the low bits.
+pub const SUBCMDMASK: u32 = 0x00ff;
+pub const SUBCMDSHIFT: u32 = 8;
+
+// Quota types.
+pub const USRQUOTA: u32 = 0;
+pub const GRPQUOTA: u32 = 1;
+pub const PRJQUOTA: u32 = 2;
+pub const MAXQUOTAS: u32 = 3;
+
+// Subcommands.
+pub const Q_SYNC: u32 = 0x800001;
+pub const Q_QUOTAON: u32 = 0x800002;
+pub const Q_QUOTAOFF: u32 = 0x800003;
+pub const Q_GETFMT: u32 = 0x800004;
+pub const Q_GETINFO: u32 = 0x800005;
+pub const Q_SETINFO: u32 = 0x800006;
+pub const Q_GETQUOTA: u32 = 0x800007;
+pub const Q_SETQUOTA: u32 = 0x800008;
+pub const Q_GETNEXTQUOTA: u32 = 0x800009;
+
+// QFMT_VFS_V1 is the quota format reported by Q_GETFMT.
+pub const QFMT_VFS_V1: u32 = 4;
+
+// The block limits are in QIF_DQBLKSIZE units.
+pub const QIF_DQBLKSIZE_BITS: u64 = 10;
+pub const QIF_DQBLKSIZE: u64 = 1 << QIF_DQBLKSIZE_BITS;
+
+// Valid fields of IfDqblk.
+pub const QIF_BLIMITS: u32 = 1;
+pub const QIF_SPACE: u32 = 2;
+pub const QIF_ILIMITS: u32 = 4;
+pub const QIF_INODES: u32 = 8;
+pub const QIF_BTIME: u32 = 16;
+pub const QIF_ITIME: u32 = 32;
+pub const QIF_LIMITS: u32 = QIF_BLIMITS | QIF_ILIMITS;
+pub const QIF_USAGE: u32 = QIF_SPACE | QIF_INODES;
+pub const QIF_TIMES: u32 = QIF_BTIME | QIF_ITIME;
+pub const QIF_ALL: u32 = QIF_LIMITS | QIF_USAGE | QIF_TIMES;
+
+// Valid fields of IfDqinfo.
+pub const IIF_BGRACE: u32 = 1;
+pub const IIF_IGRACE: u32 = 2;
+pub const IIF_FLAGS: u32 = 4;
+pub const IIF_ALL: u32 = IIF_BGRACE | IIF_IGRACE | IIF_FLAGS;
+
+// Default grace periods, in seconds.
+pub const MAX_DQ_TIME: u64 = 604800;
+pub const MAX_IQ_TIME: u64 = 604800;
+
+// IfDqblk is struct if_dqblk, the limits and usage of a quota id.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct IfDqblk {
+    pub BHardLimit: u64,
+    pub BSoftLimit: u64,
+    pub CurSpace: u64,
+    pub IHardLimit: u64,
+    pub ISoftLimit: u64,
+    pub CurInodes: u64,
+    pub BTime: u64,
+    pub ITime: u64,
+    pub Valid: u32,
+}
+
+// IfNextDqblk is struct if_nextdqblk returned by Q_GETNEXTQUOTA.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct IfNextDqblk {
+    pub BHardLimit: u64,
+    pub BSoftLimit: u64,
+    pub CurSpace: u64,
+    pub IHardLimit: u64,
+    pub ISoftLimit: u64,
+    pub CurInodes: u64,
+    pub BTime: u64,
+    pub ITime: u64,
+    pub Valid: u32,
+    pub Id: u32,
+}
+
+// IfDqinfo is struct if_dqinfo, the grace periods of a quota type.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct IfDqinfo {
+    pub BGrace: u64,
+    pub IGrace: u64,
+    pub Flags: u32,
+    pub Valid: u32,
+}
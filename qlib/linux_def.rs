@@ -33,6 +33,11 @@ impl Xattr {
 
     pub const XATTR_USER_PREFIX: &'static str = "user.";
     pub const XATTR_USER_PREFIX_LEN: usize = Self::XATTR_USER_PREFIX.len();
+
+    pub const XATTR_SECURITY_PREFIX: &'static str = "security.";
+    pub const XATTR_SECURITY_PREFIX_LEN: usize = Self::XATTR_SECURITY_PREFIX.len();
+
+    pub const XATTR_NAME_CAPS: &'static str = "security.capability";
 }
 
 pub struct InotifyEvent {}
@@ -559,6 +564,7 @@ pub struct FSMagic {}
 impl FSMagic {
     pub const ANON_INODE_FS_MAGIC: u64 = 0x09041934;
     pub const DEVPTS_SUPER_MAGIC: u64 = 0x00001cd1;
+    pub const EROFS_SUPER_MAGIC_V1: u64 = 0xe0f5e1e2;
     pub const EXT_SUPER_MAGIC: u64 = 0xef53;
     pub const OVERLAYFS_SUPER_MAGIC: u64 = 0x794c7630;
     pub const PIPEFS_MAGIC: u64 = 0x50495045;
@@ -2103,6 +2109,7 @@ impl LibcConst {
     pub const TUNSETSNDBUF: u64 = 0x400454d4;
     pub const TUNSETTXFILTER: u64 = 0x400454d1;
     pub const TUNSETVNETHDRSZ: u64 = 0x400454d8;
+    pub const UMOUNT_NOFOLLOW: u64 = 0x8;
     pub const WALL: u64 = 0x40000000;
     pub const WCLONE: u64 = 0x80000000;
     pub const WCONTINUED: u64 = 0x8;
@@ -2132,6 +2139,9 @@ impl Cmd {
     pub const F_GETSIG: i32 = 11;
     pub const F_SETOWN_EX: i32 = 15;
     pub const F_GETOWN_EX: i32 = 16;
+    pub const F_OFD_GETLK: i32 = 36;
+    pub const F_OFD_SETLK: i32 = 37;
+    pub const F_OFD_SETLKW: i32 = 38;
     pub const F_DUPFD_CLOEXEC: i32 = 1024 + 6;
     pub const F_SETPIPE_SZ: i32 = 1024 + 7;
     pub const F_GETPIPE_SZ: i32 = 1024 + 8;
@@ -2908,6 +2918,27 @@ impl SeekWhence {
     pub const SEEK_SET: i32 = 0;
     pub const SEEK_CUR: i32 = 1;
     pub const SEEK_END: i32 = 2;
+    pub const SEEK_DATA: i32 = 3;
+    pub const SEEK_HOLE: i32 = 4;
+}
+
+pub struct FallocFlags {}
+
+impl FallocFlags {
+    pub const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+    pub const FALLOC_FL_PUNCH_HOLE: i32 = 0x02;
+    pub const FALLOC_FL_NO_HIDE_STALE: i32 = 0x04;
+    pub const FALLOC_FL_COLLAPSE_RANGE: i32 = 0x08;
+    pub const FALLOC_FL_ZERO_RANGE: i32 = 0x10;
+    pub const FALLOC_FL_INSERT_RANGE: i32 = 0x20;
+    pub const FALLOC_FL_UNSHARE_RANGE: i32 = 0x40;
+
+    pub const FALLOC_FL_SUPPORTED_MASK: i32 = Self::FALLOC_FL_KEEP_SIZE
+        | Self::FALLOC_FL_PUNCH_HOLE
+        | Self::FALLOC_FL_COLLAPSE_RANGE
+        | Self::FALLOC_FL_ZERO_RANGE
+        | Self::FALLOC_FL_INSERT_RANGE
+        | Self::FALLOC_FL_UNSHARE_RANGE;
 }
 
 pub struct OpenFlags {}
@@ -14,6 +14,7 @@
 
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt;
 
 use super::auth::cap_set::*;
 use super::limits::*;
@@ -44,6 +45,79 @@ pub struct Process {
     pub ID: String,
 
     pub Root: String,
+    pub RootfsOverlay: bool,
     pub Stdiofds: [i32; 3],
     pub ExecId: Option<String>,
+
+    pub DiskQuota: DiskQuotaLimits,
+    pub BlockIO: BlockIOLimits,
+    pub EncryptedVolumes: Vec<EncryptedVolume>,
+    pub ImageMounts: Vec<ImageMount>,
+    pub HostMounts: Vec<String>,
+    pub EmptyDirs: Vec<EmptyDirMount>,
+}
+
+// DiskQuotaLimits is the byte and inode quota of the host-backed mount of a
+// container, 0 is unlimited.
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct DiskQuotaLimits {
+    pub Bytes: u64,
+    pub Inodes: u64,
+}
+
+// BlockIOLimits is the bandwidth in bytes per second and the iops of the I/O
+// on the regular files of the host mounts of a container, 0 is unlimited. The
+// limits are not per device, the lowest rate of the devices applies to all.
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct BlockIOLimits {
+    pub ReadBps: u64,
+    pub WriteBps: u64,
+    pub ReadIops: u64,
+    pub WriteIops: u64,
+}
+
+impl BlockIOLimits {
+    pub fn IsUnlimited(&self) -> bool {
+        return self.ReadBps == 0
+            && self.WriteBps == 0
+            && self.ReadIops == 0
+            && self.WriteIops == 0;
+    }
+}
+
+// ImageMount is an ext4 or erofs image file the kernel mounts read only at
+// Destination, "/" is the rootfs. Source is the path of the file in the
+// sandbox root and an empty Type is detected from the image.
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
+pub struct ImageMount {
+    pub Source: String,
+    pub Destination: String,
+    pub Type: String,
+}
+
+// EmptyDirMount is a memory backed emptyDir volume of the pod, the kernel
+// mounts the bind mounts of Source and its subdirectories on a tmpfs of Size
+// bytes shared by the containers of the pod, 0 is the default size.
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
+pub struct EmptyDirMount {
+    pub Name: String,
+    pub Source: String,
+    pub Size: u64,
+}
+
+// EncryptedVolume is a host-backed volume whose file content and names are
+// encrypted by the kernel with Key, the 64 bytes AES-256-XTS master key.
+#[derive(Serialize, Deserialize, Default, Eq, PartialEq, Clone)]
+pub struct EncryptedVolume {
+    pub Path: String,
+    pub Key: Vec<u8>,
+}
+
+// the key is kept out of the logs of the process
+impl fmt::Debug for EncryptedVolume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncryptedVolume")
+            .field("Path", &self.Path)
+            .finish()
+    }
 }
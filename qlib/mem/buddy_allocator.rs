@@ -6,6 +6,24 @@ use core::mem::size_of;
 use core::ptr::NonNull;
 use core::{fmt, ptr};
 
+// REPORT_MIN_CLASS is the min class of the free blocks which can be reported to
+// the host, the first page of a block holds the free list node and is kept
+pub const REPORT_MIN_CLASS: usize = 13;
+const PAGE_SIZE: usize = 1 << (REPORT_MIN_CLASS - 1);
+
+// REPORTED_MAGIC is stored in the second word of a free block after it is
+// reported, the word is cleared when the block is freed or merged again
+const REPORTED_MAGIC: usize = 0x7265_706f_7274_6564;
+
+// REPORT_BATCH is the max number of blocks taken out for one report
+pub const REPORT_BATCH: usize = 16;
+
+// ReportRange is the range of a free block which can be reported, the first
+// page holds the free list node
+pub fn ReportRange(start: usize, class: usize) -> (usize, usize) {
+    return (start + PAGE_SIZE, (1 << class) - PAGE_SIZE);
+}
+
 /// A heap that uses buddy system with configurable order.
 ///
 /// # Usage
@@ -33,6 +51,8 @@ pub struct Heap<const ORDER: usize> {
     pub user: usize,
     pub allocated: usize,
     pub total: usize,
+    // bytes of the free blocks taken out of the free lists while they are reported
+    pub reporting: usize,
 }
 
 impl<const ORDER: usize> Heap<ORDER> {
@@ -43,6 +63,7 @@ impl<const ORDER: usize> Heap<ORDER> {
             user: 0,
             allocated: 0,
             total: 0,
+            reporting: 0,
         }
     }
 
@@ -67,6 +88,7 @@ impl<const ORDER: usize> Heap<ORDER> {
             total += size;
 
             self.free_list[size.trailing_zeros() as usize].push(current_start as *mut usize);
+            Self::ClearReported(current_start, size.trailing_zeros() as usize);
             current_start += size;
         }
 
@@ -137,39 +159,112 @@ impl<const ORDER: usize> Heap<ORDER> {
         //raw!(0x506, class as u64, ptr.as_ptr() as u64);
 
         unsafe {
-            // Put back into free list
-            self.free_list[class].push(ptr.as_ptr() as *mut usize);
-
-            // Merge free buddy lists
-            let mut current_ptr = ptr.as_ptr() as usize;
-            let mut current_class = class;
-            while current_class < self.free_list.len() {
-                let buddy = current_ptr ^ (1 << current_class);
-                let mut flag = false;
-                for block in self.free_list[current_class].iter_mut() {
-                    if block.value() as usize == buddy {
-                        block.pop();
-                        flag = true;
-                        break;
-                    }
+            let (current_ptr, current_class) = self.free_block(ptr.as_ptr() as usize, class);
+            Self::ClearReported(current_ptr, current_class);
+        }
+
+        self.user -= layout.size();
+        self.allocated -= size;
+
+        //raw!(0x507, self.free_list[0x11].top(), self.free_list[0x11].second());
+    }
+
+    /// Put the block back into the free list of `class` and merge it with its
+    /// free buddies, return the merged block.
+    unsafe fn free_block(&mut self, ptr: usize, class: usize) -> (usize, usize) {
+        // Put back into free list
+        self.free_list[class].push(ptr as *mut usize);
+
+        // Merge free buddy lists
+        let mut current_ptr = ptr;
+        let mut current_class = class;
+        while current_class < self.free_list.len() {
+            let buddy = current_ptr ^ (1 << current_class);
+            let mut flag = false;
+            for block in self.free_list[current_class].iter_mut() {
+                if block.value() as usize == buddy {
+                    block.pop();
+                    flag = true;
+                    break;
                 }
+            }
 
-                // Free buddy found
-                if flag {
-                    self.free_list[current_class].pop();
-                    current_ptr = min(current_ptr, buddy);
-                    current_class += 1;
-                    self.free_list[current_class].push(current_ptr as *mut usize);
-                } else {
+            // Free buddy found
+            if flag {
+                self.free_list[current_class].pop();
+                current_ptr = min(current_ptr, buddy);
+                current_class += 1;
+                self.free_list[current_class].push(current_ptr as *mut usize);
+            } else {
+                break;
+            }
+        }
+
+        return (current_ptr, current_class);
+    }
+
+    fn ClearReported(addr: usize, class: usize) {
+        if class >= REPORT_MIN_CLASS {
+            unsafe { *(addr as *mut usize).add(1) = 0 };
+        }
+    }
+
+    /// Take up to REPORT_BATCH free blocks of class >= `minClass` which are not
+    /// reported yet out of the free lists, within `budget` bytes, and return
+    /// them as (start, class). The blocks can't be allocated until they are put
+    /// back with ReturnReported, so they can be reported without the heap lock.
+    pub fn TakeUnreported(
+        &mut self,
+        minClass: usize,
+        budget: usize,
+        blocks: &mut [(usize, usize); REPORT_BATCH],
+    ) -> usize {
+        assert!(minClass >= REPORT_MIN_CLASS);
+        let mut count = 0;
+        let mut used = 0;
+        for class in (minClass..self.free_list.len()).rev() {
+            let size = 1 << class;
+            while count < REPORT_BATCH && used + size <= budget {
+                // the iterator can't go on after a pop, rescan the list
+                let mut taken = None;
+                for block in self.free_list[class].iter_mut() {
+                    let marker = unsafe { (block.value() as *mut usize).add(1) };
+                    if unsafe { *marker } == REPORTED_MAGIC {
+                        continue;
+                    }
+
+                    taken = Some(block.pop() as usize);
                     break;
                 }
+
+                match taken {
+                    None => break,
+                    Some(start) => {
+                        blocks[count] = (start, class);
+                        count += 1;
+                        used += size;
+                    }
+                }
             }
         }
 
-        self.user -= layout.size();
-        self.allocated -= size;
+        self.reporting += used;
+        return count;
+    }
 
-        //raw!(0x507, self.free_list[0x11].top(), self.free_list[0x11].second());
+    /// Put the blocks taken by TakeUnreported back to the free lists. A block
+    /// is marked as reported when `reported`, unless it is merged with a buddy
+    /// freed meanwhile.
+    pub fn ReturnReported(&mut self, blocks: &[(usize, usize)], reported: bool) {
+        for (start, class) in blocks {
+            self.reporting -= 1 << *class;
+            let (ptr, merged) = unsafe { self.free_block(*start, *class) };
+            if reported && merged == *class {
+                unsafe { *(ptr as *mut usize).add(1) = REPORTED_MAGIC };
+            } else {
+                Self::ClearReported(ptr, merged);
+            }
+        }
     }
 
     /// Return the number of bytes that user requests
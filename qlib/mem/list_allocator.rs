@@ -17,6 +17,7 @@ use alloc::string::String;
 use cache_padded::CachePadded;
 use core::alloc::{GlobalAlloc, Layout};
 use core::cmp::max;
+use core::hint::spin_loop;
 use core::mem::size_of;
 use core::ptr::NonNull;
 use core::sync::atomic::Ordering;
@@ -584,12 +585,18 @@ unsafe impl GlobalAlloc for ListAllocator {
             panic!("alloc size is {}", layout.size());
         }*/
 
-        let ret = self
-            .heap
-            .lock()
-            .alloc(layout)
-            .ok()
-            .map_or(0 as *mut u8, |allocation| allocation.as_ptr()) as u64;
+        let ret = loop {
+            let mut heap = self.heap.lock();
+            match heap.alloc(layout) {
+                Ok(allocation) => break allocation.as_ptr() as u64,
+                // the free blocks being reported to the host come back soon
+                Err(()) if heap.reporting > 0 => {
+                    core::mem::drop(heap);
+                    spin_loop();
+                }
+                Err(()) => break 0,
+            }
+        };
 
         if ret == 0 {
             self.handleError(size as u64, layout.align() as u64);
@@ -34,6 +34,12 @@ pub fn NewU64Metric(name: &str, sync: bool, description: &str) -> Arc<U64Metric>
         .RegisterU64Metric(name.to_string(), sync, description.to_string());
 }
 
+// UnregisterMetric removes a metric whose lifetime is bounded by a kernel
+// object, e.g. the per-socket counters.
+pub fn UnregisterMetric(name: &str) {
+    ALL_METRICS.lock().Unregister(name);
+}
+
 pub trait Metric: Send + Sync {
     fn Value(&self) -> u64;
 }
@@ -99,4 +105,8 @@ impl MetricSet {
         self.m.insert(name, data);
         return metric;
     }
+
+    pub fn Unregister(&mut self, name: &str) {
+        self.m.remove(name);
+    }
 }
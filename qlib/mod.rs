@@ -122,6 +122,7 @@ use self::rdma_svc_cli::*;
 use self::ringbuf::*;
 use self::task_mgr::*;
 use self::kernel::socket::hostinet::tsot_mgr::TsotSocketMgr;
+use self::kernel::tcpip::stack::NetstackNicInfo;
 use self::kernel::quring::uring_async::UringEntry;
 
 pub fn InitSingleton() {
@@ -1196,6 +1197,10 @@ pub struct ShareSpace {
     pub logBuf: CachePadded<QMutex<Option<ByteStream>>>,
     pub logLock: CachePadded<QMutex<()>>,
     pub logfd: CachePadded<AtomicI32>,
+    // traceBuf is the ring of the syscall trace lines, it is written by the
+    // qkernel and drained by the host for "qvisor trace".
+    pub traceBuf: CachePadded<QMutex<Option<ByteStream>>>,
+    pub traceDropped: CachePadded<AtomicU64>,
     pub signalHandlerAddr: CachePadded<AtomicU64>,
     pub virtualizationHandlerAddr: CachePadded<AtomicU64>,
     pub kernel: CachePadded<QMutex<Option<Kernel>>>,
@@ -1213,6 +1218,7 @@ pub struct ShareSpace {
     pub hostEpollfd: AtomicI32,
 
     pub tsotSocketMgr: TsotSocketMgr,
+    pub netstackNic: QMutex<NetstackNicInfo>,
     pub dnsSvc: DnsSvc,
     pub uringQueue: UringQueue,
 
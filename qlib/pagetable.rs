@@ -1192,6 +1192,87 @@ impl PageTables {
         Ok(())
     }
 
+    // ReclaimColdPages clears the accessed bit of the user pages in [start, start + len).
+    // The pages which were not accessed since the last call and are swapped out
+    // by swapOut are marked as swapped out and inserted in pages, the page is
+    // left mapped if swapOut fails or skips it.
+    // It must be called when no vcpu is running the user code.
+    #[cfg(target_arch = "x86_64")]
+    pub fn ReclaimColdPages(
+        &self,
+        start: u64,
+        len: u64,
+        pages: &mut BTreeSet<u64>,
+        mut swapOut: impl FnMut(u64) -> bool,
+    ) -> Result<()> {
+        let end = start + len;
+
+        let mut updated = false;
+        let mut visit = |entry: &mut PageTableEntry, _virtualAddr: u64| {
+            let phyAddr = entry.addr().as_u64();
+            if phyAddr < start || end <= phyAddr {
+                return;
+            }
+
+            let mut flags = entry.flags();
+            if flags & PageTableFlags::BIT_9 == PageTableFlags::BIT_9
+                || !flags.contains(PageTableFlags::PRESENT)
+            {
+                return;
+            }
+
+            if flags.contains(PageTableFlags::ACCESSED) {
+                flags &= !PageTableFlags::ACCESSED;
+                entry.set_flags(flags);
+                updated = true;
+                return;
+            }
+
+            if !swapOut(phyAddr) {
+                return;
+            }
+
+            flags &= !PageTableFlags::PRESENT;
+            // flags bit9 which indicate the page is swapped out
+            flags |= PageTableFlags::BIT_9;
+            entry.set_flags(flags);
+            updated = true;
+            pages.insert(phyAddr);
+        };
+
+        self.Traverse(
+            Addr(MemoryDef::PAGE_SIZE),
+            Addr(MemoryDef::PHY_LOWER_ADDR),
+            &mut visit,
+            false,
+        )?;
+
+        self.Traverse(
+            Addr(MemoryDef::PHY_UPPER_ADDR),
+            Addr(MemoryDef::LOWER_TOP),
+            &mut visit,
+            false,
+        )?;
+
+        // the vcpus have to flush the stale tlb entries before return to user
+        if updated {
+            self.EnableTlbShootdown();
+        }
+
+        return Ok(());
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn ReclaimColdPages(
+        &self,
+        _start: u64,
+        _len: u64,
+        _pages: &mut BTreeSet<u64>,
+        _swapOut: impl FnMut(u64) -> bool,
+    ) -> Result<()> {
+        Ok(())
+    }
+
     // ret: >0: the swapped out page addr, 0: the page is missing
     pub fn SwapInPage(&self, vaddr: Addr) -> Result<u64> {
         let vaddr = Addr(vaddr.0 & !(PAGE_SIZE - 1));
@@ -124,6 +124,7 @@ pub enum Msg {
     SwapInPage(SwapInPage),
     SwapOut(SwapOut),
     SwapIn(SwapIn),
+    ReportFreePages(ReportFreePages),
     Proxy(Proxy),
     RemapGuestMemRanges(RemapGuestMemRanges),
     UnmapGuestMemRange(UnmapGuestMemRange),
@@ -131,6 +132,10 @@ pub enum Msg {
     NvidiaMMap(NvidiaMMap),
     HostUnixConnect(HostUnixConnect),
     HostUnixRecvMsg(HostUnixRecvMsg),
+    HostInotifyInit(HostInotifyInit),
+    HostInotifyAddWatch(HostInotifyAddWatch),
+    HostInotifyRmWatch(HostInotifyRmWatch),
+    HostFlock(HostFlock),
 
     // TsotListen(TsotListen),
     // TsotAccept(TsotAccept),
@@ -204,6 +209,28 @@ pub struct HostUnixRecvMsg {
     pub flags: i32,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct HostInotifyInit {}
+
+#[derive(Clone, Debug, Default)]
+pub struct HostInotifyAddWatch {
+    pub fd: i32,     // the host inotify fd
+    pub target: i32, // the host fd of the watched file
+    pub mask: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HostInotifyRmWatch {
+    pub fd: i32,
+    pub wd: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HostFlock {
+    pub fd: i32,
+    pub operation: i32,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct RemapGuestMemRanges {
     pub len: u64,
@@ -234,6 +261,12 @@ pub struct SwapInPage {
     pub addr: u64,
 }
 
+#[derive(Clone, Default, Debug)]
+pub struct ReportFreePages {
+    pub iovs: u64, // the IoVec array of the free page ranges
+    pub count: usize,
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct HostMemoryBarrier {}
 
@@ -127,6 +127,11 @@ pub struct Scheduler {
 
     pub vcpuWaitMask: AtomicU64,
     pub VcpuArr: Vec<CPULocal>,
+
+    // activeVcpuCnt is the number of vcpus allowed to run tasks, the vcpus
+    // [activeVcpuCnt, vcpuCnt) only drain their own queue after the sandbox
+    // cpu limit is lowered.
+    pub activeVcpuCnt: AtomicUsize,
 }
 
 impl Scheduler {
@@ -142,6 +147,7 @@ impl Scheduler {
             VcpuArr: vcpuArr,
             queue: queue,
             vcpuCnt: vcpuCount,
+            activeVcpuCnt: AtomicUsize::new(vcpuCount),
             ..Default::default()
         };
     }
@@ -172,6 +178,38 @@ impl Scheduler {
         return ret;
     }
 
+    pub fn ActiveVcpuCnt(&self) -> usize {
+        return self.activeVcpuCnt.load(Ordering::Acquire);
+    }
+
+    pub fn SetActiveVcpuCnt(&self, cnt: usize) {
+        // keep the io vcpu and at least one application vcpu
+        let cnt = if cnt < 2 {
+            2
+        } else if cnt > self.vcpuCnt {
+            self.vcpuCnt
+        } else {
+            cnt
+        };
+
+        self.activeVcpuCnt.store(cnt, Ordering::Release);
+        self.WakeAll();
+    }
+
+    #[inline(always)]
+    pub fn VcpuActive(&self, vcpuId: usize) -> bool {
+        return vcpuId < self.ActiveVcpuCnt();
+    }
+
+    pub fn ActiveVcpuMask(&self) -> u64 {
+        let cnt = self.ActiveVcpuCnt();
+        if cnt >= 64 {
+            return !0;
+        }
+
+        return (1u64 << cnt) - 1;
+    }
+
     pub fn ReadyTaskCnt(&self, vcpuId: usize) -> u64 {
         //return self.readyTaskCnt.load(Ordering::SeqCst) as u64
         return self.queue[vcpuId].Len();
@@ -199,6 +237,13 @@ impl Scheduler {
     }
 
     pub fn ScheduleQ(&self, task: TaskId, vcpuId: u64, cpuAff: bool) {
+        // the inactive vcpus don't pick up new tasks, use the global queue
+        let (vcpuId, cpuAff) = if self.VcpuActive(vcpuId as usize) {
+            (vcpuId, cpuAff)
+        } else {
+            (0, false)
+        };
+
         if self.queue[vcpuId as usize].Enqueue(task, cpuAff) {
             self.IncReadyTaskCount();
         }
@@ -220,7 +265,7 @@ impl Scheduler {
 
     pub fn WakeOne(&self) -> i64 {
         loop {
-            let mask = self.vcpuWaitMask.load(Ordering::Acquire);
+            let mask = self.vcpuWaitMask.load(Ordering::Acquire) & self.ActiveVcpuMask();
 
             let vcpuId = mask.trailing_zeros() as usize;
             if vcpuId >= 64 {
@@ -40,6 +40,14 @@ pub struct IOInternal {
     // BytesWriteCancelled is the number of bytes not written out due to
     // truncation.
     pub BytesWriteCancelled: AtomicU64,
+
+    // ThrottledReads and ThrottledWrites are the number of host file I/Os
+    // delayed by the block I/O limits of the container.
+    pub ThrottledReads: AtomicU64,
+    pub ThrottledWrites: AtomicU64,
+
+    // ThrottledTime is the time in ns the I/Os were delayed.
+    pub ThrottledTime: AtomicU64,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -84,6 +92,18 @@ impl IO {
         }
     }
 
+    pub fn AccountThrottle(&self, write: bool, delay: i64) {
+        if write {
+            self.ThrottledWrites.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.ThrottledReads.fetch_add(1, Ordering::SeqCst);
+        }
+
+        if delay > 0 {
+            self.ThrottledTime.fetch_add(delay as u64, Ordering::SeqCst);
+        }
+    }
+
     pub fn Accumulate(&self, io: &IO) {
         self.CharsRead
             .fetch_add(io.CharsRead.load(Ordering::SeqCst), Ordering::SeqCst);
@@ -101,5 +121,11 @@ impl IO {
             io.BytesWriteCancelled.load(Ordering::SeqCst),
             Ordering::SeqCst,
         );
+        self.ThrottledReads
+            .fetch_add(io.ThrottledReads.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.ThrottledWrites
+            .fetch_add(io.ThrottledWrites.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.ThrottledTime
+            .fetch_add(io.ThrottledTime.load(Ordering::SeqCst), Ordering::SeqCst);
     }
 }
@@ -67,7 +67,8 @@ lazy_static::lazy_static! {
                 stateSvcAddr: vec![
                     "127.0.0.1:8890".to_string()
                 ],
-                singleNodeModel: true
+                singleNodeModel: true,
+                tsotTls: None,
             }
         } else {
             let configFilePath = &args[1];
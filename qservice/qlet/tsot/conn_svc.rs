@@ -20,6 +20,10 @@ use std::os::fd::IntoRawFd;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use tokio::net::TcpSocket;
 use tokio::net::TcpStream;
@@ -31,6 +35,7 @@ use super::peer_mgr::PEER_MGR;
 use super::pod_broker::PodBroker;
 use super::pod_broker_mgr::POD_BRORKER_MGRS;
 use super::tsot_msg::ErrCode;
+use super::tsot_tls::*;
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -125,6 +130,14 @@ impl ConnectionSvc {
                 Err(_) => continue,
                 Ok((stream, _peerAddr)) => {
                     tokio::spawn(async move {
+                        if let Some(tls) = TSOT_TLS.get() {
+                            match TlsSvcConnection::Process(tls, stream).await {
+                                Err(e) => error!("ConnectionSvc::ProcessTlsConnection fail with error {:?}", e),
+                                Ok(_) => (),
+                            }
+                            return;
+                        }
+
                         let conn = TcpSvcConnection::New(stream);
                         match conn.Process().await {
                             Err(e) => error!("ConnectionSvc::ProcessConnection fail with error {:?}", e),
@@ -219,6 +232,79 @@ impl TcpSvcConnection {
     }
 }
 
+// read a fixed size tsot message from a tls stream
+pub async fn ReadStruct<T: Copy, S: AsyncRead + Unpin>(stream: &mut S) -> Result<T> {
+    let size = std::mem::size_of::<T>();
+    let mut readBuf = vec![0; size];
+    stream.read_exact(&mut readBuf).await?;
+
+    // the Vec<u8> buffer has no alignment guarantee for T
+    let msg = unsafe {
+        std::ptr::read_unaligned(readBuf.as_ptr() as * const T)
+    };
+
+    return Ok(msg)
+}
+
+pub async fn WriteStruct<T: Copy, S: AsyncWrite + Unpin>(stream: &mut S, msg: T) -> Result<()> {
+    let size = std::mem::size_of::<T>();
+    let addr = &msg as * const _ as u64 as * const u8;
+    let buf = unsafe {
+        std::slice::from_raw_parts(addr, size)
+    };
+
+    stream.write_all(buf).await?;
+    stream.flush().await?;
+    return Ok(())
+}
+
+pub struct TlsSvcConnection {}
+
+impl TlsSvcConnection {
+    pub async fn Process(tls: &TsotTls, stream: TcpStream) -> Result<()> {
+        let mut stream = tls.Accept(stream).await?;
+        let connReq : TsotConnReq = ReadStruct(&mut stream).await?;
+
+        match Self::ProcessConnReq(&stream, &connReq) {
+            Err(e) => {
+                error!("TlsSvcConnection reject connection {:x?} with error {:?}", &connReq, e);
+                let resp = TsotConnResp {
+                    errcode: TsotErrCode::Reject as _,
+                };
+
+                WriteStruct(&mut stream, resp).await?;
+                return Ok(())
+            }
+            Ok(localSocket) => {
+                let resp = TsotConnResp {
+                    errcode: TsotErrCode::Ok as _,
+                };
+
+                WriteStruct(&mut stream, resp).await?;
+                Relay(stream, localSocket).await;
+                return Ok(())
+            }
+        }
+    }
+
+    pub fn ProcessConnReq(
+        stream: &tokio_rustls::server::TlsStream<TcpStream>, 
+        connReq: &TsotConnReq
+    ) -> Result<std::os::unix::net::UnixStream> {
+        TsotTls::VerifyPeer(stream, connReq.srcIp)?;
+
+        let namespace = connReq.GetNamespace()?;
+        let pair = TlsSocketPair::New()?;
+        let socket = pair.podSocket.as_raw_fd();
+
+        POD_BRORKER_MGRS.HandlePeerConnect(&namespace, connReq.dstIp, connReq.dstPort, connReq.srcIp, connReq.srcPort, socket)?;
+
+        // the enqueued TsotMessage takes the ownership of the pod end
+        let _fd = pair.podSocket.into_raw_fd();
+        return Ok(pair.localSocket)
+    }
+}
+
 pub struct TcpClientConnection {
     pub podBroker: PodBroker,
 
@@ -234,14 +320,39 @@ pub struct TcpClientConnection {
 
 impl TcpClientConnection {
     pub async fn Process(self) {
-        let podBroker = self.podBroker.clone();
+        if let Some(tls) = TSOT_TLS.get() {
+            match self.ProcessTlsConnection(tls).await {
+                Ok((stream, localSocket)) => {
+                    if self.ConnectResp(ErrCode::None) {
+                        Relay(stream, localSocket).await;
+                    }
+                }
+                Err(e) => {
+                    error!("TcpClientConnection tls connect fail with error {:?}", e);
+                    self.ConnectResp(ErrCode::ECONNREFUSED);
+                }
+            }
+            return;
+        }
+
         match self.ProcessConnection().await {
             Ok(_stream) => {
                 // drop the TcpStream and close the socket 
-                podBroker.HandleConnectResp(self.reqId, ErrCode::None as i32).unwrap();
+                self.ConnectResp(ErrCode::None);
             }
             Err(_e) => {
-                podBroker.HandleConnectResp(self.reqId, ErrCode::ECONNREFUSED as i32).unwrap();
+                self.ConnectResp(ErrCode::ECONNREFUSED);
+            }
+        }
+    }
+
+    // ConnectResp returns false when the pod broker has gone away
+    pub fn ConnectResp(&self, errcode: ErrCode) -> bool {
+        match self.podBroker.HandleConnectResp(self.reqId, errcode as i32) {
+            Ok(()) => return true,
+            Err(e) => {
+                error!("TcpClientConnection connect resp for req {} fail with error {:?}", self.reqId, e);
+                return false;
             }
         }
     }
@@ -271,6 +382,40 @@ impl TcpClientConnection {
         return Ok(stream)
     }
 
+    pub async fn ProcessTlsConnection(
+        &self, 
+        tls: &TsotTls
+    ) -> Result<(tokio_rustls::client::TlsStream<TcpStream>, std::os::unix::net::UnixStream)> {
+        // self.socket is the pod end of the socketpair created in ProcessCreateSocketReq
+        let localSocket = self.podBroker.TakeTlsSocket(self.socket)?;
+
+        let peer = PEER_MGR.LookforPeer(self.dstIp)?;
+        let socketv4Addr = SocketAddrV4::new(Ipv4Addr::from(peer.hostIp), peer.port);
+        let stream = TcpStream::connect(socketv4Addr).await?;
+        let mut stream = tls.Connect(stream, self.dstIp).await?;
+
+        let mut req = TsotConnReq {
+            namespace: [0; 64],
+            dstIp: self.dstIp,
+            dstPort: self.dstPort,
+            srcIp: self.srcIp,
+            srcPort: self.srcPort
+        };
+
+        for i in 0..self.namespace.as_bytes().len() {
+            req.namespace[i] = self.namespace.as_bytes()[i];
+        }
+
+        WriteStruct(&mut stream, req).await?;
+        let resp : TsotConnResp = ReadStruct(&mut stream).await?;
+
+        if resp.errcode != TsotErrCode::Ok as u32 {
+            return Err(Error::CommonError(format!("TcpClientConnection tls connect fail with error {:?}", resp.errcode)));
+        }
+
+        return Ok((stream, localSocket))
+    }
+
     pub async fn Connect(&self) -> Result<TcpStream> {
         let peer = PEER_MGR.LookforPeer(self.dstIp)?;
         let ip = Ipv4Addr::from(peer.hostIp);
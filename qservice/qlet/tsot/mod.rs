@@ -19,4 +19,5 @@ pub mod pod_broker;
 pub mod conn_svc;
 pub mod pod_broker_mgr;
 pub mod dns_proxy;
+pub mod tsot_tls;
 mod tsot_agent;
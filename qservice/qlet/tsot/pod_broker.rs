@@ -20,6 +20,7 @@ use std::sync::Arc;
 use std::sync::atomic::*;
 use std::sync::Mutex;
 use tokio::sync::Notify;
+use tokio::time;
 use std::os::unix::net::SocketAncillary;
 use std::os::fd::{FromRawFd, IntoRawFd, AsRawFd, RawFd};
 use std::os::unix::net::UnixStream as StdStream;
@@ -39,9 +40,13 @@ use super::conn_svc::TcpClientConnection;
 use super::dns_proxy::DnsProxyReq;
 use super::dns_proxy::DNS_PROXY;
 use super::pod_broker_mgr::POD_BRORKER_MGRS;
+use super::tsot_tls::*;
 
 pub const BUFF_SIZE: usize = std::mem::size_of::<TsotMsg>();
 
+// how often the qlet ends of the tls sockets the pod closed are dropped
+pub const TLS_SOCKET_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 #[derive(Debug, Default)]
 pub struct PodIdentity {
     pub podUid: String,
@@ -83,6 +88,10 @@ pub struct PodBrokerInner {
 
     // reqId to ConnectReq
     pub connecting: Mutex<HashMap<u32, ConnectReq>>,
+
+    // tls mode: inode of the pod end socket to the qlet end of the socketpair,
+    // the entries of the sockets the pod closes are dropped by RemoveClosedTlsSockets
+    pub tlsSockets: Mutex<HashMap<u64, StdStream>>,
 }
 
 #[derive(Debug, Clone)]
@@ -115,6 +124,7 @@ impl PodBroker {
 
             listeningPorts: Mutex::new(HashMap::new()),  
             connecting: Mutex::new(HashMap::new()),       
+            tlsSockets: Mutex::new(HashMap::new()),
         };
 
         return Self(Arc::new(inner));
@@ -210,6 +220,7 @@ impl PodBroker {
         let mut rx = self.outputRx.lock().unwrap().take().unwrap();
 
         let mut msg : Option<TsotMessage> = None;
+        let mut tlsSweep = time::interval(TLS_SOCKET_SWEEP_INTERVAL);
         
         loop {
             match msg.take() {
@@ -222,6 +233,9 @@ impl PodBroker {
                         _res = self.stream.readable() => {
                             self.ProcessRead()?;
                         }
+                        _ = tlsSweep.tick() => {
+                            self.RemoveClosedTlsSockets();
+                        }
                         m = rx.recv() => {
                             match m {
                                 None => (),
@@ -247,6 +261,12 @@ impl PodBroker {
                         _ = self.stream.writable() => {
                             self.SendMsg(m)?;
                         }
+                        _ = tlsSweep.tick() => {
+                            self.RemoveClosedTlsSockets();
+
+                            // return the msg
+                            msg = Some(m);
+                        }
                     }
                 }
             }
@@ -387,6 +407,20 @@ impl PodBroker {
     }
 
     pub fn ProcessCreateSocketReq(&self, _req: CreateSocketReq) -> Result<()> {
+        if TlsEnabled() {
+            let pair = TlsSocketPair::New()?;
+            let fd = pair.podSocket.into_raw_fd();
+            let inode = TlsSocketPair::SocketInode(fd)?;
+            self.tlsSockets.lock().unwrap().insert(inode, pair.localSocket);
+
+            let message = TsotMessage {
+                socket: fd,
+                msg: TsotMsg::CreateSocketResp(CreateSocketResp {}),
+            };
+
+            return self.EnqMsg(message);
+        }
+
         let stream = TcpSocket::new_v4()?;
         let fd = stream.into_raw_fd();
         let resp = CreateSocketResp {};
@@ -399,6 +433,26 @@ impl PodBroker {
         return self.EnqMsg(message);
     }
 
+    // get the qlet end of the socketpair whose pod end is socket, the socket
+    // is received from the pod and will be closed
+    pub fn TakeTlsSocket(&self, socket: RawFd) -> Result<StdStream> {
+        let inode = TlsSocketPair::SocketInode(socket);
+        unsafe {
+            libc::close(socket);
+        }
+
+        let inode = inode?;
+        match self.tlsSockets.lock().unwrap().remove(&inode) {
+            None => return Err(Error::NotExist(format!("PodBroker::TakeTlsSocket socket inode {} doesn't exist", inode))),
+            Some(s) => return Ok(s),
+        }
+    }
+
+    // drop the qlet end of the tls sockets the pod closed without connecting them
+    pub fn RemoveClosedTlsSockets(&self) {
+        self.tlsSockets.lock().unwrap().retain(|_, s| !TlsSocketPair::PeerClosed(s));
+    }
+
     pub fn ProcessListenReq(&self, req: ListenReq) -> Result<()> {
         match self.listeningPorts.lock().unwrap().insert(req.port, req.backlog) {
             None => return Ok(()),
@@ -31,6 +31,7 @@ use super::tsot_msg::TsotMessage;
 use crate::pod_mgr::NAMESPACE_MGR;
 use crate::tsot::conn_svc::ConnectionSvc;
 use crate::tsot::dns_proxy::DNS_PROXY;
+use crate::tsot::tsot_tls::InitTsotTls;
 use crate::tsot::tsot_msg::TSOT_SOCKET_PATH;
 use crate::QLET_CONFIG;
 
@@ -166,6 +167,7 @@ impl tsot_cni::tsot_cni_service_server::TsotCniService for TostCniSvc {
 
 pub async fn TsotSvc() -> Result<()>{
     info!("Tsot service start ...");
+    InitTsotTls()?;
     let tsotSvc = TsotSvc::New()?;
     let tsotSvcFuture = tsotSvc.Process();
 
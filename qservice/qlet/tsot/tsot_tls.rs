@@ -0,0 +1,255 @@
+// Copyright (c) 2023 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::IntoRawFd;
+use std::os::fd::RawFd;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpStream;
+use tokio::net::UnixStream;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::Certificate;
+use tokio_rustls::rustls::PrivateKey;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::TlsConnector;
+use x509_parser::extensions::GeneralName;
+
+use qshare::common::*;
+use qshare::qlet_config::TsotTlsConfig;
+
+use super::peer_mgr::PEER_MGR;
+use crate::QLET_CONFIG;
+
+// not set when the qlet config has no tsotTls section, the inter-node
+// connections are plain tcp in that case
+pub static TSOT_TLS: OnceCell<TsotTls> = OnceCell::new();
+
+// InitTsotTls loads the tls config at startup, a bad cert or key path fails
+// the tsot service instead of the first connection
+pub fn InitTsotTls() -> Result<()> {
+    let config = match &QLET_CONFIG.tsotTls {
+        None => return Ok(()),
+        Some(config) => config,
+    };
+
+    let tls = TsotTls::New(config).map_err(|e| {
+        Error::CommonError(format!("can't load tsot tls config {:?} with error {:?}", config, e))
+    })?;
+
+    if TSOT_TLS.set(tls).is_err() {
+        return Err(Error::CommonError("tsot tls config is loaded twice".to_string()));
+    }
+
+    return Ok(())
+}
+
+pub fn TlsEnabled() -> bool {
+    return TSOT_TLS.get().is_some();
+}
+
+pub struct TsotTls {
+    pub acceptor: TlsAcceptor,
+    pub connector: TlsConnector,
+}
+
+impl TsotTls {
+    pub fn New(config: &TsotTlsConfig) -> Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in Self::LoadCerts(&config.caCert)? {
+            roots.add(&cert).map_err(|e| {
+                Error::CommonError(format!("TsotTls add ca cert {} fail with error {:?}", &config.caCert, e))
+            })?;
+        }
+
+        let certs = Self::LoadCerts(&config.nodeCert)?;
+        let key = Self::LoadKey(&config.nodeKey)?;
+
+        let verifier = AllowAnyAuthenticatedClient::new(roots.clone()).boxed();
+        let serverConfig = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs.clone(), key.clone())
+            .map_err(|e| Error::CommonError(format!("TsotTls server config fail with error {:?}", e)))?;
+
+        let clientConfig = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)
+            .map_err(|e| Error::CommonError(format!("TsotTls client config fail with error {:?}", e)))?;
+
+        return Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(serverConfig)),
+            connector: TlsConnector::from(Arc::new(clientConfig)),
+        })
+    }
+
+    pub fn LoadCerts(path: &str) -> Result<Vec<Certificate>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        if certs.len() == 0 {
+            return Err(Error::NotExist(format!("TsotTls no certificate in {}", path)));
+        }
+
+        return Ok(certs.into_iter().map(Certificate).collect());
+    }
+
+    pub fn LoadKey(path: &str) -> Result<PrivateKey> {
+        let mut reader = BufReader::new(File::open(path)?);
+        loop {
+            match rustls_pemfile::read_one(&mut reader)? {
+                None => break,
+                Some(rustls_pemfile::Item::PKCS8Key(key)) => return Ok(PrivateKey(key)),
+                Some(rustls_pemfile::Item::RSAKey(key)) => return Ok(PrivateKey(key)),
+                Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+                Some(_) => continue,
+            }
+        }
+
+        return Err(Error::NotExist(format!("TsotTls no private key in {}", path)));
+    }
+
+    // connect to the qlet which owns the cidr of dstIp, the server certificate
+    // must be issued for the peer's host ip
+    pub async fn Connect(&self, stream: TcpStream, dstIp: u32) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        let peer = PEER_MGR.LookforPeer(dstIp)?;
+        let serverName = ServerName::IpAddress(IpAddr::V4(Ipv4Addr::from(peer.hostIp)));
+        let stream = self.connector.connect(serverName, stream).await?;
+        return Ok(stream)
+    }
+
+    pub async fn Accept(&self, stream: TcpStream) -> Result<tokio_rustls::server::TlsStream<TcpStream>> {
+        let stream = self.acceptor.accept(stream).await?;
+        return Ok(stream)
+    }
+
+    // the client certificate of an accepted connection must be issued for the
+    // host ip of the peer which owns the cidr of the connection's source pod ip
+    pub fn VerifyPeer(stream: &tokio_rustls::server::TlsStream<TcpStream>, srcIp: u32) -> Result<()> {
+        let peer = PEER_MGR.LookforPeer(srcIp)?;
+        let certs = match stream.get_ref().1.peer_certificates() {
+            None => return Err(Error::CommonError(format!("TsotTls peer has no certificate"))),
+            Some(certs) => certs,
+        };
+
+        let ips = Self::CertIps(&certs[0])?;
+        let expect = Ipv4Addr::from(peer.hostIp);
+        if !ips.contains(&IpAddr::V4(expect)) {
+            return Err(Error::CommonError(format!(
+                "TsotTls peer certificate {:?} doesn't match peer host {:?} of ip {:x}",
+                ips, expect, srcIp
+            )));
+        }
+
+        return Ok(())
+    }
+
+    pub fn CertIps(cert: &Certificate) -> Result<Vec<IpAddr>> {
+        let (_, cert) = x509_parser::parse_x509_certificate(&cert.0)
+            .map_err(|e| Error::CommonError(format!("TsotTls parse peer certificate fail with error {:?}", e)))?;
+        let san = cert.subject_alternative_name()
+            .map_err(|e| Error::CommonError(format!("TsotTls parse peer certificate san fail with error {:?}", e)))?;
+
+        let mut ips = Vec::new();
+        if let Some(san) = san {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::IPAddress(addr) if addr.len() == 4 => {
+                        ips.push(IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3])));
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        return Ok(ips)
+    }
+}
+
+// When tls is enabled the pod can't use the inter-node tcp socket directly. It
+// gets one end of a unix socketpair instead and the qlet relays the data between
+// the other end and the tls stream.
+pub struct TlsSocketPair {
+    // the end handed to the pod
+    pub podSocket: StdUnixStream,
+    // the end kept by the qlet
+    pub localSocket: StdUnixStream,
+}
+
+impl TlsSocketPair {
+    pub fn New() -> Result<Self> {
+        let (podSocket, localSocket) = StdUnixStream::pair()?;
+        localSocket.set_nonblocking(true)?;
+        return Ok(Self {
+            podSocket: podSocket,
+            localSocket: localSocket,
+        })
+    }
+
+    // the pod sends its end back with the ConnectReq, both fds share the same inode
+    pub fn SocketInode(socket: RawFd) -> Result<u64> {
+        let file = unsafe {
+            File::from_raw_fd(socket)
+        };
+        let res = file.metadata();
+        // take ownership of the file to avoid fd close
+        let _ = file.into_raw_fd();
+        return Ok(res?.ino())
+    }
+
+    // the pod end is closed when the qlet end hangs up
+    pub fn PeerClosed(localSocket: &StdUnixStream) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: localSocket.as_raw_fd(),
+            events: libc::POLLRDHUP,
+            revents: 0,
+        };
+
+        let ret = unsafe {
+            libc::poll(&mut pollfd, 1, 0)
+        };
+
+        return ret > 0 && pollfd.revents & (libc::POLLRDHUP | libc::POLLHUP | libc::POLLERR) != 0;
+    }
+}
+
+pub async fn Relay<T: AsyncRead + AsyncWrite + Unpin>(mut tlsStream: T, localSocket: StdUnixStream) {
+    let mut local = match UnixStream::from_std(localSocket) {
+        Err(e) => {
+            error!("TsotTls Relay fail with error {:?}", e);
+            return
+        }
+        Ok(s) => s,
+    };
+
+    match tokio::io::copy_bidirectional(&mut tlsStream, &mut local).await {
+        Err(e) => {
+            info!("TsotTls Relay finish with error {:?}", e);
+        }
+        Ok(_) => (),
+    }
+}
@@ -31,6 +31,20 @@ pub struct QletConfig {
     pub cidr: String,
     pub stateSvcAddr: Vec<String>,
     pub singleNodeModel: bool,
+
+    // mutual tls for the qlet to qlet tsot connections, plain tcp when absent
+    #[serde(default)]
+    pub tsotTls: Option<TsotTlsConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TsotTlsConfig {
+    // pem file of the ca which issues all the node certificates
+    pub caCert: String,
+    // pem files of this node's certificate and private key, the certificate's
+    // subject alternative name must contain the node's host ip
+    pub nodeCert: String,
+    pub nodeKey: String,
 }
 
 impl QletConfig {
@@ -16,6 +16,7 @@ use crate::qlib::linux_def::SysErr;
 use crate::qlib::{backtracer, VcpuFeq, GetTimeCall};
 use crate::{qlib, URING_MGR};
 use crate::KERNEL_IO_THREAD;
+use crate::vmspace::netstack::RestoreNetstackNic;
 use crate::SHARE_SPACE;
 use crate::syncmgr::SyncMgr;
 use crate::qlib::perf_tunning::PerfPrint;
@@ -355,6 +356,7 @@ impl KVMVcpu {
                 let msg = unsafe { &*(addr as *const Print) };
 
                 eprintln!("Application error: {}", msg.str);
+                RestoreNetstackNic();
                 ::std::process::exit(1);
             }
 
@@ -395,11 +397,13 @@ impl KVMVcpu {
                     "OOM!!! cpu [{}], size is {:x}, alignment is {:x}",
                     self.id, data1, data2
                 );
+                RestoreNetstackNic();
                 ::std::process::exit(1);
             }
 
             qlib::HYPERCALL_EXIT => {
                 info!("call in HYPERCALL_EXIT");
+                RestoreNetstackNic();
                 unsafe { libc::_exit(0) }
             }
 
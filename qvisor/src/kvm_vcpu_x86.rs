@@ -49,6 +49,7 @@ use super::qlib::perf_tunning::*;
 //use super::qlib::vcpu_mgr::*;
 use super::runc::runtime::vm::*;
 use super::syncmgr::*;
+use super::vmspace::netstack::RestoreNetstackNic;
 
 #[repr(C)]
 pub struct SignalMaskStruct {
@@ -426,6 +427,7 @@ impl KVMVcpu {
                             let msg = unsafe { &*(addr as *const Print) };
 
                             eprintln!("Application error: {}", msg.str);
+                            RestoreNetstackNic();
                             ::std::process::exit(1);
                         }
 
@@ -466,11 +468,13 @@ impl KVMVcpu {
                                 "OOM!!! cpu [{}], size is {:x}, alignment is {:x}",
                                 self.id, data1, data2
                             );
+                            RestoreNetstackNic();
                             ::std::process::exit(1);
                         }
 
                         qlib::HYPERCALL_EXIT => {
                             info!("call in HYPERCALL_EXIT");
+                            RestoreNetstackNic();
                             unsafe { libc::_exit(0) }
                         }
 
@@ -731,6 +735,7 @@ impl KVMVcpu {
 
     pub fn VcpuWait(&self) -> i64 {
         let sharespace = &SHARE_SPACE;
+        let _wait = RECLAIMER.EnterWait();
         loop {
             if !super::runc::runtime::vm::IsRunning() {
                 return -1;
@@ -98,6 +98,7 @@ use self::runc::cmd::command::*;
 use self::runc::sandbox::sandbox::*;
 use self::runc::shim::service::*;
 use self::vmspace::hibernate::*;
+use self::vmspace::reclaim::*;
 use self::vmspace::host_pma_keeper::*;
 use self::vmspace::hostfdnotifier::*;
 use self::vmspace::kernel_io_thread::*;
@@ -141,6 +142,8 @@ lazy_static! {
     pub static ref SHARE_SPACE_STRUCT: Arc<Mutex<ShareSpace>> =
         Arc::new(Mutex::new(ShareSpace::New()));
     pub static ref SWAP_FILE: Mutex<SwapFile> = Mutex::new(SwapFile::Init().unwrap());
+    pub static ref RECLAIM_STORE: Mutex<ReclaimStore> = Mutex::new(ReclaimStore::default());
+    pub static ref RECLAIMER: Reclaimer = Reclaimer::default();
     pub static ref VMS: Mutex<VMSpace> = Mutex::new(VMSpace::Init());
     pub static ref ROOT_CONTAINER_ID: Mutex<String> = Mutex::new(String::new());
     pub static ref PAGE_ALLOCATOR: MemAllocator = MemAllocator::New();
@@ -207,6 +210,7 @@ fn main() {
         match Run(&mut args) {
             Err(e) => {
                 error!("the error is {:?}", e);
+                vmspace::netstack::RestoreNetstackNic();
                 ::std::process::exit(-1);
             }
             Ok(()) => {
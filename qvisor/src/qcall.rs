@@ -138,6 +138,18 @@ impl KVMVcpu {
             Msg::HostUnixRecvMsg(msg) => {
                 ret = super::VMSpace::HostUnixRecvMsg(msg.fd, msg.msghdr, msg.flags) as u64;
             }
+            Msg::HostInotifyInit(_msg) => {
+                ret = super::VMSpace::HostInotifyInit() as u64;
+            }
+            Msg::HostInotifyAddWatch(msg) => {
+                ret = super::VMSpace::HostInotifyAddWatch(msg.fd, msg.target, msg.mask) as u64;
+            }
+            Msg::HostInotifyRmWatch(msg) => {
+                ret = super::VMSpace::HostInotifyRmWatch(msg.fd, msg.wd) as u64;
+            }
+            Msg::HostFlock(msg) => {
+                ret = super::VMSpace::HostFlock(msg.fd, msg.operation) as u64;
+            }
             Msg::TsotRecvMsg(msg) => {
                 ret = super::VMSpace::TsotRecvMsg(msg.msgAddr) as u64;
             }
@@ -287,6 +299,9 @@ impl KVMVcpu {
                 SHARE_SPACE.hiberMgr.ReapSwapIn().unwrap();
                 ret = 0;
             }
+            Msg::ReportFreePages(msg) => {
+                ret = super::VMSpace::ReportFreePages(msg.iovs, msg.count) as u64;
+            }
             Msg::Proxy(msg) => {
                 ret = super::VMSpace::Proxy(msg.cmd, &msg.parameters) as u64;
             }
@@ -28,6 +28,7 @@ use super::super::super::qlib::linux_def::*;
 use super::super::super::qlib::path::*;
 use super::super::oci::*;
 use super::super::specutils::specutils::MkdirAll;
+use super::cgroup_v2::*;
 
 pub const CONTROLLERS: [(&str, fn(spec: &LinuxResources, path: &str) -> Result<()>); 11] = [
     ("blkio", BlockIO),
@@ -47,6 +48,11 @@ pub const CONTROLLERS: [(&str, fn(spec: &LinuxResources, path: &str) -> Result<(
 
 pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
 
+// IsCgroupV2 returns whether the host uses the cgroup v2 unified hierarchy.
+pub fn IsCgroupV2() -> bool {
+    return Path::new(&Join(CGROUP_ROOT, CONTROLLERS_FILE)).exists();
+}
+
 pub fn SetOptionalValueInt(path: &str, name: &str, val: Option<i64>) -> Result<()> {
     let val = match val {
         None => return Ok(()),
@@ -220,7 +226,7 @@ pub fn CountCpuset(cpuset: &str) -> Result<usize> {
                     }
                 };
 
-                let end = match interval[0].parse::<usize>() {
+                let end = match interval[1].parse::<usize>() {
                     Ok(i) => i,
                     Err(_e) => {
                         return Err(Error::Common(format!("invalid cpuset: {}", p)));
@@ -320,6 +326,43 @@ impl Cgroup {
         return Ok(());
     }
 
+    // Update applies 'res' to the existing cgroup, e.g. when the container is
+    // resized in place. Controllers the cgroup doesn't have are skipped.
+    pub fn Update(&self, res: &Option<LinuxResources>) -> Result<()> {
+        let spec = match res {
+            None => return Ok(()),
+            Some(ref spec) => spec,
+        };
+
+        info!("Updating cgroup {}", &self.Name);
+        if IsCgroupV2() {
+            // cgroup v2 has a single hierarchy, /proc/self/cgroup reports it
+            // with an empty controller list.
+            let path = self.MakePath("");
+            Cpu2 {}.Set(res, &path)?;
+            CpuSet2 {}.Set(res, &path)?;
+            Memory2 {}.Set(res, &path)?;
+            Pids(spec, &path)?;
+            return Ok(());
+        }
+
+        for controller in &CONTROLLERS {
+            let path = self.MakePath(&controller.0);
+            if !Path::new(&path).exists() {
+                continue;
+            }
+
+            controller.1(spec, &path)?;
+        }
+
+        let path = self.MakePath("pids");
+        if Path::new(&path).exists() {
+            Pids(spec, &path)?;
+        }
+
+        return Ok(());
+    }
+
     pub fn Uninstall(&self) {
         if !self.Own {
             return;
@@ -548,6 +591,23 @@ pub fn SetThrottle(path: &str, name: &str, devs: &[LinuxThrottleDevice]) -> Resu
     return Ok(());
 }
 
+// Pids is only used when updating a cgroup, the pids controller is not
+// configured when the sandbox cgroup is installed.
+fn Pids(spec: &LinuxResources, path: &str) -> Result<()> {
+    match spec.pids {
+        None => return Ok(()),
+        Some(ref p) => {
+            if p.limit > 0 {
+                SetValue(path, "pids.max", &format!("{}", p.limit))?;
+            } else {
+                SetValue(path, "pids.max", "max")?;
+            }
+
+            return Ok(());
+        }
+    }
+}
+
 fn NetworkClass(spec: &LinuxResources, path: &str) -> Result<()> {
     match spec.network {
         None => return Ok(()),
@@ -0,0 +1,99 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::io::Write;
+use tabwriter::TabWriter;
+
+use super::super::super::qlib::common::*;
+use super::super::super::qlib::control_msg::*;
+use super::super::cmd::config::*;
+use super::super::container::container::*;
+use super::command::*;
+use super::ps::Format;
+
+#[derive(Debug)]
+pub struct AuditCmd {
+    pub id: String,
+    pub format: Format,
+}
+
+impl AuditCmd {
+    pub fn Init(cmd_matches: &ArgMatches) -> Result<Self> {
+        let ret = Self {
+            id: cmd_matches.value_of("id").unwrap().to_string(),
+            format: match cmd_matches.value_of("format").unwrap() {
+                "table" => Format::Table,
+                "json" => Format::Json,
+                _ => return Err(Error::Common("invalid format option".to_string())),
+            },
+        };
+
+        return Ok(ret);
+    }
+
+    pub fn SubCommand<'a, 'b>(common: &CommonArgs<'a, 'b>) -> App<'a, 'b> {
+        return SubCommand::with_name("audit")
+            .setting(AppSettings::ColoredHelp)
+            .arg(&common.id_arg)
+            .arg(
+                Arg::with_name("format")
+                    .help("output format, table or json")
+                    .default_value("table")
+                    .takes_value(true)
+                    .long("format")
+                    .short("f"),
+            )
+            .about("audit displays the unsupported syscalls, ioctls, socket options, fcntl commands and procfs files used by a container");
+    }
+
+    pub fn Run(&mut self, gCfg: &GlobalConfig) -> Result<()> {
+        let container = Container::Load(&gCfg.RootDir, &self.id)?;
+
+        let records = container.Unsupported()?;
+
+        if self.format == Format::Table {
+            PrintUnsupportedToTable(&records);
+        } else {
+            let s = serde_json::to_string(&records)
+                .map_err(|e| Error::Common(format!("serialize fail {:?}", e)))?;
+            println!("{}", s);
+        }
+
+        return Ok(());
+    }
+}
+
+pub fn PrintUnsupportedToTable(records: &[UnsupportedRecord]) {
+    let mut tw = TabWriter::new(vec![]).minwidth(10).padding(3);
+
+    write!(&mut tw, "KIND\tNAME\tCOUNT\tARGS\tBINARIES\n").unwrap();
+    for r in records {
+        write!(
+            &mut tw,
+            "{:?}\t{}\t{}\t{}\t{}\n",
+            r.Kind,
+            r.Name,
+            r.Count,
+            r.Args,
+            r.Binaries.join(",")
+        )
+        .unwrap();
+    }
+    tw.flush().unwrap();
+
+    let written = String::from_utf8(tw.into_inner().unwrap()).unwrap();
+    println!("{}", written);
+}
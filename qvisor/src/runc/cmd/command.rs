@@ -17,15 +17,18 @@ use std::env;
 use clap::{App, AppSettings, Arg};
 
 use super::super::super::qlib::common::*;
+use super::audit::*;
 use super::boot::*;
 use super::cmd::*;
 use super::config;
 use super::config::*;
 use super::create::*;
 use super::delete::*;
+use super::events::*;
 use super::exec::*;
 use super::kill::*;
 use super::list::*;
+use super::logs::*;
 use super::pause::*;
 use super::ps::*;
 use super::resume::*;
@@ -33,6 +36,8 @@ use super::run::*;
 use super::sandbox::*;
 use super::start::*;
 use super::state::*;
+use super::trace::*;
+use super::update::*;
 use super::wait::*;
 
 fn id_validator(val: String) -> core::result::Result<(), String> {
@@ -184,6 +189,11 @@ pub fn Parse() -> Result<Arguments> {
         .subcommand(DeleteCmd::SubCommand(&common))
         .subcommand(StateCmd::SubCommand(&common))
         .subcommand(SandboxCmd::SubCommand(&common))
+        .subcommand(UpdateCmd::SubCommand(&common))
+        .subcommand(EventsCmd::SubCommand(&common))
+        .subcommand(LogsCmd::SubCommand(&common))
+        .subcommand(AuditCmd::SubCommand(&common))
+        .subcommand(TraceCmd::SubCommand(&common))
         .get_matches_from(get_args());
 
     let level = match matches.occurrences_of("v") {
@@ -275,6 +285,26 @@ pub fn Parse() -> Result<Arguments> {
             config: gConfig,
             cmd: Command::SandboxCmd(SandboxCmd::Init(&cmd_matches)?),
         },
+        ("update", Some(cmd_matches)) => Arguments {
+            config: gConfig,
+            cmd: Command::UpdateCmd(UpdateCmd::Init(&cmd_matches)?),
+        },
+        ("events", Some(cmd_matches)) => Arguments {
+            config: gConfig,
+            cmd: Command::EventsCmd(EventsCmd::Init(&cmd_matches)?),
+        },
+        ("logs", Some(cmd_matches)) => Arguments {
+            config: gConfig,
+            cmd: Command::LogsCmd(LogsCmd::Init(&cmd_matches)?),
+        },
+        ("audit", Some(cmd_matches)) => Arguments {
+            config: gConfig,
+            cmd: Command::AuditCmd(AuditCmd::Init(&cmd_matches)?),
+        },
+        ("trace", Some(cmd_matches)) => Arguments {
+            config: gConfig,
+            cmd: Command::TraceCmd(TraceCmd::Init(&cmd_matches)?),
+        },
         // We should never reach here because clap already enforces this
         _ => panic!("command not recognized"),
     };
@@ -305,6 +335,11 @@ pub enum Command {
     DeleteCmd(DeleteCmd),
     StateCmd(StateCmd),
     SandboxCmd(SandboxCmd),
+    UpdateCmd(UpdateCmd),
+    EventsCmd(EventsCmd),
+    LogsCmd(LogsCmd),
+    AuditCmd(AuditCmd),
+    TraceCmd(TraceCmd),
 }
 
 pub fn Run(args: &mut Arguments) -> Result<()> {
@@ -324,5 +359,10 @@ pub fn Run(args: &mut Arguments) -> Result<()> {
         Command::DeleteCmd(cmd) => return cmd.Run(&mut args.config),
         Command::StateCmd(cmd) => return cmd.Run(&mut args.config),
         Command::SandboxCmd(cmd) => return cmd.Run(&mut args.config),
+        Command::UpdateCmd(cmd) => return cmd.Run(&mut args.config),
+        Command::EventsCmd(cmd) => return cmd.Run(&mut args.config),
+        Command::LogsCmd(cmd) => return cmd.Run(&mut args.config),
+        Command::AuditCmd(cmd) => return cmd.Run(&mut args.config),
+        Command::TraceCmd(cmd) => return cmd.Run(&mut args.config),
     }
 }
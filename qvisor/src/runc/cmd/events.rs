@@ -0,0 +1,153 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::super::super::qlib::common::*;
+use super::super::super::qlib::control_msg::*;
+use super::super::cmd::config::*;
+use super::super::container::container::*;
+use super::super::container::status::*;
+use super::command::*;
+
+// EVENTS_POLL_INTERVAL is how often the sandbox event log is polled
+const EVENTS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum EventData {
+    Event(SandboxEvent),
+    Stats(ContainerStats),
+}
+
+// Event is the json object printed for each event, the same layout as runc
+#[derive(Serialize, Debug)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub Type: String,
+    pub id: String,
+    pub data: EventData,
+}
+
+#[derive(Debug)]
+pub struct EventsCmd {
+    pub id: String,
+    pub interval: Duration,
+    pub stats: bool,
+}
+
+impl EventsCmd {
+    pub fn Init(cmd_matches: &ArgMatches) -> Result<Self> {
+        let interval = cmd_matches.value_of("interval").unwrap();
+        let interval = interval
+            .trim_end_matches('s')
+            .parse::<u64>()
+            .map_err(|e| Error::Common(format!("invalid interval {}: {:?}", interval, e)))?;
+        if interval == 0 {
+            return Err(Error::Common(
+                "duration interval must be greater than 0".to_string(),
+            ));
+        }
+
+        return Ok(Self {
+            id: cmd_matches.value_of("id").unwrap().to_string(),
+            interval: Duration::from_secs(interval),
+            stats: cmd_matches.is_present("stats"),
+        });
+    }
+
+    pub fn SubCommand<'a, 'b>(common: &CommonArgs<'a, 'b>) -> App<'a, 'b> {
+        return SubCommand::with_name("events")
+            .setting(AppSettings::ColoredHelp)
+            .arg(&common.id_arg)
+            .arg(
+                Arg::with_name("interval")
+                    .help("set the stats collection interval in seconds")
+                    .default_value("5")
+                    .takes_value(true)
+                    .long("interval"),
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .help("display the container's stats then exit")
+                    .long("stats"),
+            )
+            .about("display container events such as OOM notifications, exits and resource stats");
+    }
+
+    pub fn Print(&self, typ: &str, data: EventData) {
+        let event = Event {
+            Type: typ.to_string(),
+            id: self.id.to_string(),
+            data: data,
+        };
+
+        match serde_json::to_string(&event) {
+            Ok(s) => println!("{}", s),
+            Err(e) => error!("events: serialize {:?} fail {:?}", event, e),
+        }
+    }
+
+    pub fn Run(&self, gCfg: &GlobalConfig) -> Result<()> {
+        let container = Container::Load(&gCfg.RootDir, &self.id)?;
+
+        if self.stats {
+            self.Print("stats", EventData::Stats(container.Stats()?));
+            return Ok(());
+        }
+
+        // only the events after the start of the command are streamed
+        let mut since = match container.Events(0)?.last() {
+            None => 0,
+            Some(event) => event.Seq + 1,
+        };
+
+        let mut lastStats = Instant::now();
+        loop {
+            let events = match container.Events(since) {
+                Ok(events) => events,
+                Err(e) => {
+                    let container = Container::Load(&gCfg.RootDir, &self.id)?;
+                    if container.Status == Status::Stopped {
+                        return Ok(());
+                    }
+                    return Err(e);
+                }
+            };
+
+            for event in events {
+                since = event.Seq + 1;
+                let typ = match event.Type {
+                    SandboxEventType::Exit => "exit",
+                    SandboxEventType::OOM => "oom",
+                    SandboxEventType::Pause => "pause",
+                    SandboxEventType::Resume => "resume",
+                    SandboxEventType::Hibernate => "hibernate",
+                    SandboxEventType::Wakeup => "wakeup",
+                };
+                self.Print(typ, EventData::Event(event));
+            }
+
+            if lastStats.elapsed() >= self.interval {
+                lastStats = Instant::now();
+                self.Print("stats", EventData::Stats(container.Stats()?));
+            }
+
+            thread::sleep(EVENTS_POLL_INTERVAL);
+        }
+    }
+}
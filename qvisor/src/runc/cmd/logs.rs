@@ -0,0 +1,106 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::thread;
+use std::time::Duration;
+
+use super::super::super::qlib::common::*;
+use super::super::super::qlib::control_msg::*;
+use super::super::cmd::config::*;
+use super::super::container::container::*;
+use super::super::container::status::*;
+use super::command::*;
+
+// LOGS_POLL_INTERVAL is how often the kernel log is polled with --follow
+const LOGS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub struct LogsCmd {
+    pub id: String,
+    pub since: u64,
+    pub follow: bool,
+}
+
+impl LogsCmd {
+    pub fn Init(cmd_matches: &ArgMatches) -> Result<Self> {
+        let since = cmd_matches.value_of("since").unwrap();
+        let since = since
+            .parse::<u64>()
+            .map_err(|e| Error::Common(format!("invalid since {}: {:?}", since, e)))?;
+
+        return Ok(Self {
+            id: cmd_matches.value_of("id").unwrap().to_string(),
+            since: since,
+            follow: cmd_matches.is_present("follow"),
+        });
+    }
+
+    pub fn SubCommand<'a, 'b>(common: &CommonArgs<'a, 'b>) -> App<'a, 'b> {
+        return SubCommand::with_name("logs")
+            .setting(AppSettings::ColoredHelp)
+            .arg(&common.id_arg)
+            .arg(
+                Arg::with_name("since")
+                    .help("print the entries with sequence number no less than since")
+                    .default_value("0")
+                    .takes_value(true)
+                    .long("since"),
+            )
+            .arg(
+                Arg::with_name("follow")
+                    .help("keep printing the new entries until the container stops")
+                    .short("f")
+                    .long("follow"),
+            )
+            .about("display the quark kernel log of the container as json");
+    }
+
+    pub fn Print(&self, entry: &KernelLogEntry) {
+        match serde_json::to_string(entry) {
+            Ok(s) => println!("{}", s),
+            Err(e) => error!("logs: serialize {:?} fail {:?}", entry, e),
+        }
+    }
+
+    pub fn Run(&self, gCfg: &GlobalConfig) -> Result<()> {
+        let container = Container::Load(&gCfg.RootDir, &self.id)?;
+
+        let mut since = self.since;
+        loop {
+            let entries = match container.KernelLog(since) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let container = Container::Load(&gCfg.RootDir, &self.id)?;
+                    if self.follow && container.Status == Status::Stopped {
+                        return Ok(());
+                    }
+                    return Err(e);
+                }
+            };
+
+            for entry in &entries {
+                since = entry.Seq + 1;
+                self.Print(entry);
+            }
+
+            if !self.follow {
+                return Ok(());
+            }
+
+            thread::sleep(LOGS_POLL_INTERVAL);
+        }
+    }
+}
@@ -15,12 +15,15 @@
 pub mod boot;
 pub mod cmd;
 pub mod command;
+pub mod audit;
 pub mod config;
 pub mod create;
 pub mod delete;
+pub mod events;
 pub mod exec;
 pub mod kill;
 pub mod list;
+pub mod logs;
 pub mod pause;
 pub mod ps;
 pub mod resume;
@@ -28,4 +31,6 @@ pub mod run;
 pub mod sandbox;
 pub mod start;
 pub mod state;
+pub mod trace;
+pub mod update;
 pub mod wait;
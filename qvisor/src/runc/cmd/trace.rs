@@ -0,0 +1,149 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use core::sync::atomic::{AtomicBool, Ordering};
+use nix::sys::signal;
+use std::thread;
+use std::time::Duration;
+
+use super::super::super::qlib::common::*;
+use super::super::cmd::config::*;
+use super::super::container::container::*;
+use super::super::container::status::*;
+use super::command::*;
+
+// TRACE_POLL_INTERVAL is how often the trace lines are read from the sandbox
+const TRACE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// TRACE_STOP is set by SIGINT/SIGTERM, the trace is disabled in the sandbox
+// before the command exits.
+static TRACE_STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_trace_stop(_signal: i32) {
+    TRACE_STOP.store(true, Ordering::SeqCst);
+}
+
+#[derive(Debug)]
+pub struct TraceCmd {
+    pub id: String,
+    pub pid: i32,
+    pub filter: Vec<String>,
+}
+
+impl TraceCmd {
+    pub fn Init(cmd_matches: &ArgMatches) -> Result<Self> {
+        let pid = cmd_matches.value_of("pid").unwrap();
+        let pid = pid
+            .parse::<i32>()
+            .map_err(|e| Error::Common(format!("invalid pid {}: {:?}", pid, e)))?;
+
+        let filter = match cmd_matches.value_of("filter") {
+            None => Vec::new(),
+            Some(f) => f
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| s.len() > 0)
+                .collect(),
+        };
+
+        return Ok(Self {
+            id: cmd_matches.value_of("id").unwrap().to_string(),
+            pid: pid,
+            filter: filter,
+        });
+    }
+
+    pub fn SubCommand<'a, 'b>(common: &CommonArgs<'a, 'b>) -> App<'a, 'b> {
+        return SubCommand::with_name("trace")
+            .setting(AppSettings::ColoredHelp)
+            .arg(&common.id_arg)
+            .arg(
+                Arg::with_name("pid")
+                    .help("only trace the process of the pid, 0 traces all the processes")
+                    .default_value("0")
+                    .takes_value(true)
+                    .long("pid"),
+            )
+            .arg(
+                Arg::with_name("filter")
+                    .help("comma separated syscall names to trace, e.g. open,connect")
+                    .takes_value(true)
+                    .long("filter"),
+            )
+            .about("trace the syscalls of the container in strace format until interrupted");
+    }
+
+    pub fn Run(&self, gCfg: &GlobalConfig) -> Result<()> {
+        let container = Container::Load(&gCfg.RootDir, &self.id)?;
+
+        let sig_action = signal::SigAction::new(
+            signal::SigHandler::Handler(handle_trace_stop),
+            signal::SaFlags::empty(),
+            signal::SigSet::empty(),
+        );
+        unsafe {
+            signal::sigaction(signal::SIGINT, &sig_action).expect("sigaction set fail");
+            signal::sigaction(signal::SIGTERM, &sig_action).expect("sigaction set fail");
+        }
+
+        container.TraceStart(self.pid, self.filter.clone())?;
+
+        let ret = self.Poll(gCfg, &container);
+        match container.TraceStop() {
+            Err(e) => info!("trace: stop trace of {} fail {:?}", self.id, e),
+            Ok(()) => (),
+        }
+
+        return ret;
+    }
+
+    pub fn Poll(&self, gCfg: &GlobalConfig, container: &Container) -> Result<()> {
+        loop {
+            // the lines flushed by the exited container are read once more
+            let exited = match Container::Load(&gCfg.RootDir, &self.id) {
+                Ok(c) => c.Status == Status::Stopped,
+                Err(_) => true,
+            };
+            let stop = TRACE_STOP.load(Ordering::SeqCst) || exited;
+
+            let (lines, dropped) = match container.TraceRead() {
+                Ok(ret) => ret,
+                Err(e) => {
+                    let container = Container::Load(&gCfg.RootDir, &self.id)?;
+                    if container.Status == Status::Stopped {
+                        return Ok(());
+                    }
+                    return Err(e);
+                }
+            };
+
+            if dropped > 0 {
+                eprintln!("+++ {} trace lines dropped +++", dropped);
+            }
+
+            for line in &lines {
+                println!("{}", line);
+            }
+
+            if stop {
+                return Ok(());
+            }
+
+            thread::sleep(TRACE_POLL_INTERVAL);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::fs;
+use std::io::Read;
+
+use super::super::super::qlib::common::*;
+use super::super::cmd::config::*;
+use super::super::container::container::*;
+use super::super::oci::*;
+use super::command::*;
+
+#[derive(Default, Debug)]
+pub struct UpdateCmd {
+    pub id: String,
+    pub resources: String,
+    pub cpuPeriod: Option<u64>,
+    pub cpuQuota: Option<i64>,
+    pub cpuShares: Option<u64>,
+    pub cpusetCpus: String,
+    pub cpusetMems: String,
+    pub memory: Option<i64>,
+    pub memoryReservation: Option<i64>,
+    pub memorySwap: Option<i64>,
+    pub pidsLimit: Option<i64>,
+}
+
+fn ParseValue<T: core::str::FromStr>(cmd_matches: &ArgMatches, name: &str) -> Result<Option<T>> {
+    match cmd_matches.value_of(name) {
+        None => return Ok(None),
+        Some(v) => match v.parse::<T>() {
+            Ok(v) => return Ok(Some(v)),
+            Err(_) => return Err(Error::Common(format!("invalid value {} for --{}", v, name))),
+        },
+    }
+}
+
+// ParseMemory parses a memory size such as "512m". -1 means unlimited.
+fn ParseMemory(cmd_matches: &ArgMatches, name: &str) -> Result<Option<i64>> {
+    let val = match cmd_matches.value_of(name) {
+        None => return Ok(None),
+        Some(v) => v.trim().to_lowercase(),
+    };
+
+    if val == "-1" {
+        return Ok(Some(-1));
+    }
+
+    let (num, shift) = match val.trim_end_matches('b').chars().last() {
+        Some('k') => (&val[..val.find('k').unwrap()], 10),
+        Some('m') => (&val[..val.find('m').unwrap()], 20),
+        Some('g') => (&val[..val.find('g').unwrap()], 30),
+        _ => (val.trim_end_matches('b'), 0),
+    };
+
+    match num.parse::<i64>() {
+        Ok(v) if v >= 0 => match v.checked_mul(1 << shift) {
+            Some(v) => return Ok(Some(v)),
+            None => {
+                return Err(Error::Common(format!(
+                    "value {} for --{} is too large",
+                    val, name
+                )))
+            }
+        },
+        _ => {
+            return Err(Error::Common(format!(
+                "invalid value {} for --{}",
+                val, name
+            )))
+        }
+    }
+}
+
+impl UpdateCmd {
+    pub fn Init(cmd_matches: &ArgMatches) -> Result<Self> {
+        return Ok(Self {
+            id: cmd_matches.value_of("id").unwrap().to_string(),
+            resources: cmd_matches
+                .value_of("resources")
+                .unwrap_or_default()
+                .to_string(),
+            cpuPeriod: ParseValue(cmd_matches, "cpu-period")?,
+            cpuQuota: ParseValue(cmd_matches, "cpu-quota")?,
+            cpuShares: ParseValue(cmd_matches, "cpu-share")?,
+            cpusetCpus: cmd_matches
+                .value_of("cpuset-cpus")
+                .unwrap_or_default()
+                .to_string(),
+            cpusetMems: cmd_matches
+                .value_of("cpuset-mems")
+                .unwrap_or_default()
+                .to_string(),
+            memory: ParseMemory(cmd_matches, "memory")?,
+            memoryReservation: ParseMemory(cmd_matches, "memory-reservation")?,
+            memorySwap: ParseMemory(cmd_matches, "memory-swap")?,
+            pidsLimit: ParseValue(cmd_matches, "pids-limit")?,
+        });
+    }
+
+    pub fn SubCommand<'a, 'b>(common: &CommonArgs<'a, 'b>) -> App<'a, 'b> {
+        return SubCommand::with_name("update")
+            .setting(AppSettings::ColoredHelp)
+            .arg(&common.id_arg)
+            .arg(
+                Arg::with_name("resources")
+                    .long("resources")
+                    .short("r")
+                    .takes_value(true)
+                    .help("path to a file containing the resources to update, or \"-\" to read from stdin"),
+            )
+            .arg(
+                Arg::with_name("cpu-period")
+                    .long("cpu-period")
+                    .takes_value(true)
+                    .help("CPU CFS period to be used for hardcapping (in usecs)"),
+            )
+            .arg(
+                Arg::with_name("cpu-quota")
+                    .long("cpu-quota")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("CPU CFS hardcap limit (in usecs), -1 for unlimited"),
+            )
+            .arg(
+                Arg::with_name("cpu-share")
+                    .long("cpu-share")
+                    .takes_value(true)
+                    .help("CPU shares (relative weight vs. other containers)"),
+            )
+            .arg(
+                Arg::with_name("cpuset-cpus")
+                    .long("cpuset-cpus")
+                    .takes_value(true)
+                    .help("CPU(s) to use"),
+            )
+            .arg(
+                Arg::with_name("cpuset-mems")
+                    .long("cpuset-mems")
+                    .takes_value(true)
+                    .help("Memory node(s) to use"),
+            )
+            .arg(
+                Arg::with_name("memory")
+                    .long("memory")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("Memory limit (in bytes, or with k, m, g suffix)"),
+            )
+            .arg(
+                Arg::with_name("memory-reservation")
+                    .long("memory-reservation")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("Memory reservation or soft_limit (in bytes)"),
+            )
+            .arg(
+                Arg::with_name("memory-swap")
+                    .long("memory-swap")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("Total memory usage (memory + swap); set '-1' to enable unlimited swap"),
+            )
+            .arg(
+                Arg::with_name("pids-limit")
+                    .long("pids-limit")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .help("Maximum number of pids allowed in the container"),
+            )
+            .about("update container resource constraints");
+    }
+
+    fn LoadResources(&self) -> Result<LinuxResources> {
+        if self.resources.len() == 0 {
+            return Ok(LinuxResources::default());
+        }
+
+        let data = if self.resources == "-" {
+            let mut data = String::new();
+            std::io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| Error::IOError(format!("read resources from stdin: {:?}", e)))?;
+            data
+        } else {
+            fs::read_to_string(&self.resources).map_err(|e| {
+                Error::IOError(format!("read resources file {}: {:?}", &self.resources, e))
+            })?
+        };
+
+        let res: LinuxResources = serde_json::from_str(&data)
+            .map_err(|e| Error::Common(format!("parse resources fail: {:?}", e)))?;
+        return Ok(res);
+    }
+
+    // Resources merges the resources file with the command line flags, the
+    // flags take precedence.
+    pub fn Resources(&self) -> Result<LinuxResources> {
+        let mut res = self.LoadResources()?;
+
+        if self.cpuPeriod.is_some()
+            || self.cpuQuota.is_some()
+            || self.cpuShares.is_some()
+            || self.cpusetCpus.len() != 0
+            || self.cpusetMems.len() != 0
+        {
+            let cpu = res.cpu.get_or_insert_with(LinuxCPU::default);
+            if self.cpuPeriod.is_some() {
+                cpu.period = self.cpuPeriod;
+            }
+            if self.cpuQuota.is_some() {
+                cpu.quota = self.cpuQuota;
+            }
+            if self.cpuShares.is_some() {
+                cpu.shares = self.cpuShares;
+            }
+            if self.cpusetCpus.len() != 0 {
+                cpu.cpus = self.cpusetCpus.to_string();
+            }
+            if self.cpusetMems.len() != 0 {
+                cpu.mems = self.cpusetMems.to_string();
+            }
+        }
+
+        if self.memory.is_some() || self.memoryReservation.is_some() || self.memorySwap.is_some() {
+            let memory = res.memory.get_or_insert_with(LinuxMemory::default);
+            if self.memory.is_some() {
+                memory.limit = self.memory;
+            }
+            if self.memoryReservation.is_some() {
+                memory.reservation = self.memoryReservation;
+            }
+            if self.memorySwap.is_some() {
+                memory.swap = self.memorySwap;
+            }
+        }
+
+        match self.pidsLimit {
+            None => (),
+            Some(limit) => res.pids = Some(LinuxPids { limit: limit }),
+        }
+
+        return Ok(res);
+    }
+
+    pub fn Run(&self, gCfg: &GlobalConfig) -> Result<()> {
+        let id = &self.id;
+
+        let res = self.Resources()?;
+        let mut container = Container::Load(&gCfg.RootDir, id)?;
+        container.Update(res)?;
+
+        return Ok(());
+    }
+}
@@ -918,11 +918,139 @@ impl Container {
         return self.Save();
     }
 
+    // Checkpoint hibernates the sandbox and writes its swap files and the
+    // container spec into the image path. The container is left in its state
+    // from before the checkpoint unless exit is set, in which case its processes
+    // are killed and the container exits like with runc checkpoint.
+    pub fn Checkpoint(&mut self, imagePath: &str, exit: bool) -> Result<()> {
+        info!("Checkpoint container {} to {}", self.ID, imagePath);
+
+        let _unlock = self.Lock()?;
+
+        self.RequireStatus("checkpoint", &[Status::Running, Status::Paused])?;
+
+        let sandbox = self.Sandbox.as_ref().unwrap();
+        sandbox.Checkpoint(&self.ID, imagePath)?;
+
+        let specFile = Join(imagePath, "config.json");
+        serialize(&self.Spec, &specFile)
+            .map_err(|e| Error::Common(format!("Container::Checkpoint error is {:?}", e)))?;
+
+        if exit {
+            // the kill wakes up the hibernated sandbox, the tasks of a paused
+            // container need the unpause to handle it
+            sandbox.SignalContainer(&self.ID, Signal::SIGKILL, true)?;
+            if self.Status == Status::Paused {
+                sandbox.Unpause(&self.ID)?;
+                self.changeStatus(Status::Running);
+                return self.Save();
+            }
+            return Ok(());
+        }
+
+        if self.Status == Status::Running {
+            sandbox.Unpause(&self.ID)?;
+        }
+
+        return Ok(());
+    }
+
+    // Update applies new resource limits to the sandbox cgroup and the sandbox
+    // kernel, and records them in the container spec.
+    pub fn Update(&mut self, res: LinuxResources) -> Result<()> {
+        info!("Update container {}", self.ID);
+
+        let _unlock = self.Lock()?;
+
+        self.RequireStatus(
+            "update",
+            &[Status::Created, Status::Running, Status::Paused],
+        )?;
+
+        let res = Some(res);
+        {
+            let sandbox = self.Sandbox.as_ref().unwrap();
+            // the cgroup and the guest limits are shared by the whole sandbox
+            if !sandbox.IsRootContainer(&self.ID) {
+                return Err(Error::Common(format!(
+                    "update of container {} is not supported, only the sandbox container {} can be updated",
+                    self.ID, sandbox.ID
+                )));
+            }
+
+            match sandbox.Cgroup {
+                None => (),
+                Some(ref cg) => cg.Update(&res)?,
+            }
+
+            sandbox.UpdateResources(&self.ID, res.as_ref().unwrap())?;
+        }
+
+        let res = res.unwrap();
+        if let Some(ref mut linux) = self.Spec.linux {
+            let resources = linux.resources.get_or_insert_with(LinuxResources::default);
+            if res.cpu.is_some() {
+                resources.cpu = res.cpu;
+            }
+            if res.memory.is_some() {
+                resources.memory = res.memory;
+            }
+            if res.pids.is_some() {
+                resources.pids = res.pids;
+            }
+            if res.block_io.is_some() {
+                resources.block_io = res.block_io;
+            }
+        }
+
+        return self.Save();
+    }
+
     pub fn Processes(&self) -> Result<Vec<ProcessInfo>> {
         self.RequireStatus("get processes of", &[Status::Running, Status::Paused])?;
         return self.Sandbox.as_ref().unwrap().Processes(&self.ID);
     }
 
+    pub fn Events(&self, since: u64) -> Result<Vec<SandboxEvent>> {
+        self.RequireStatus("get events of", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().Events(&self.ID, since);
+    }
+
+    pub fn KernelLog(&self, since: u64) -> Result<Vec<KernelLogEntry>> {
+        self.RequireStatus("get kernel log of", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().KernelLog(&self.ID, since);
+    }
+
+    pub fn Unsupported(&self) -> Result<Vec<UnsupportedRecord>> {
+        self.RequireStatus("get unsupported calls of", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().Unsupported(&self.ID);
+    }
+
+    pub fn TraceStart(&self, pid: i32, syscalls: Vec<String>) -> Result<()> {
+        self.RequireStatus("trace", &[Status::Running, Status::Paused])?;
+        let args = TraceArgs {
+            cid: self.ID.clone(),
+            Pid: pid,
+            Syscalls: syscalls,
+        };
+        return self.Sandbox.as_ref().unwrap().TraceStart(&args);
+    }
+
+    pub fn TraceStop(&self) -> Result<()> {
+        self.RequireStatus("stop tracing", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().TraceStop(&self.ID);
+    }
+
+    pub fn TraceRead(&self) -> Result<(Vec<String>, u64)> {
+        self.RequireStatus("read trace of", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().TraceRead(&self.ID);
+    }
+
+    pub fn Stats(&self) -> Result<ContainerStats> {
+        self.RequireStatus("get stats of", &[Status::Running, Status::Paused])?;
+        return self.Sandbox.as_ref().unwrap().Stats(&self.ID);
+    }
+
     // Start starts running the containerized process inside the sandbox.
     pub fn Start(&mut self) -> Result<()> {
         info!("Start container {}", &self.ID);
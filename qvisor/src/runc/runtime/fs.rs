@@ -20,9 +20,10 @@ use std::{thread, time};
 
 use super::super::super::namespace::Util;
 use super::super::super::qlib::common::*;
-use super::super::super::qlib::path::{IsAbs, Join};
+use super::super::super::qlib::path::{Dir, IsAbs, Join};
 use super::super::container::mounts::*;
 use super::super::oci::Spec;
+use super::super::specutils::specutils::{ImageMounts, IsImageMount, IMAGES_DIR};
 use super::sandbox_process::*;
 
 const DEFAULT_QUARK_SANDBOX_ROOT_PATH: &str = "/var/lib/quark/";
@@ -123,6 +124,10 @@ impl FsImageMounter {
                 let msg = format!("invalid mount destination: {}", m.destination);
                 return Err(Error::Common(msg));
             }
+            // the kernel mounts the image files
+            if IsImageMount(m) {
+                continue;
+            }
             let (flags, data) = parse_mount(m);
             if m.typ == "cgroup" {
                 //mount_cgroups(m, rootfs, flags, &data, &linux.mount_label, cpath)?;
@@ -142,6 +147,7 @@ impl FsImageMounter {
             }
         }
 
+        BindImageFiles(spec, containerId, &self.sandboxRoot())?;
         return Ok(());
     }
 
@@ -152,7 +158,7 @@ impl FsImageMounter {
                 warn!("invalid mount destination: {}", m.destination);
             }
             let (_, _) = parse_mount(m);
-            if m.typ == "cgroup" {
+            if m.typ == "cgroup" || IsImageMount(m) {
                 //mount_cgroups(m, rootfs, flags, &data, &linux.mount_label, cpath)?;
                 // won't mount cgroup
                 continue;
@@ -164,6 +170,8 @@ impl FsImageMounter {
             }
         }
 
+        UnbindImageFiles(containerId, &self.sandboxRoot());
+
         // unmount dev at last
         let dest = format!("{}{}", containerFsRootTarget, "/dev");
         if Path::new(&dest).exists() {
@@ -208,3 +216,47 @@ impl FsImageMounter {
         return Ok(());
     }
 }
+
+// BindImageFiles bind mounts the image files of the container into the
+// sandbox root, where the kernel opens them after the pivot root.
+pub fn BindImageFiles(spec: &Spec, containerId: &str, sandboxRoot: &str) -> Result<()> {
+    for (source, m) in ImageMounts(spec, containerId)? {
+        let target = Join(sandboxRoot, &m.Source);
+        create_dir_all(Dir(&target))
+            .map_err(|e| Error::IOError(format!("failed to create dir for {}, {}", target, e)))?;
+        fs::File::create(&target)
+            .map_err(|e| Error::IOError(format!("failed to create {}, {}", target, e)))?;
+
+        let ret = Util::Mount(&source, &target, "", libc::MS_BIND, "");
+        if ret < 0 {
+            return Err(Error::Common(format!(
+                "bind image {} to {} fail, error is {}",
+                source, target, ret
+            )));
+        }
+    }
+
+    return Ok(());
+}
+
+// UnbindImageFiles unmounts the image files of the container from the
+// sandbox root.
+pub fn UnbindImageFiles(containerId: &str, sandboxRoot: &str) {
+    let dir = Join(sandboxRoot, &Join(IMAGES_DIR, containerId));
+    let entries = match fs::read_dir(&dir) {
+        Err(_) => return,
+        Ok(entries) => entries,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path().to_string_lossy().to_string();
+        let ret = Util::Umount2(&path, libc::MNT_DETACH);
+        if ret < 0 {
+            warn!("unmount image file {} fail, error is {}", path, ret);
+        }
+    }
+
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        warn!("remove image dir {} fail, error is {}", dir, e);
+    }
+}
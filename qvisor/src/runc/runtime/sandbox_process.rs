@@ -63,6 +63,7 @@ use super::super::oci::*;
 use super::super::shim::container_io::*;
 use super::super::specutils::specutils::*;
 use super::console::*;
+use super::fs::BindImageFiles;
 use super::loader::*;
 use super::signal_handle::*;
 use super::util::*;
@@ -301,6 +302,7 @@ impl SandboxProcess {
             panic!("InitRootfs: mount rootfs fail, error is {}", ret);
         }
 
+        BindImageFiles(&self.spec, &self.containerId, &self.SandboxRootDir)?;
         return Ok(());
     }
 
@@ -430,6 +432,10 @@ impl SandboxProcess {
                 let msg = format!("invalid mount destination: {}", m.destination);
                 return Err(Error::Common(msg));
             }
+            // the kernel mounts the image files
+            if IsImageMount(m) {
+                continue;
+            }
             let (flags, data) = parse_mount(m);
             if m.typ == "cgroup" {
                 //mount_cgroups(m, rootfs, flags, &data, &linux.mount_label, cpath)?;
@@ -26,6 +26,8 @@ use nix::sys::signal;
 
 use crate::qlib::MAX_VCPU_COUNT;
 use crate::tsot_agent::TSOT_AGENT;
+use crate::vmspace::netstack::InitNetstack;
+use crate::vmspace::netstack::RestoreNetstackNic;
 //use crate::vmspace::hibernate::HiberMgr;
 
 use super::super::super::elf_loader::*;
@@ -56,8 +58,8 @@ use super::super::super::vmspace::*;
 use super::super::super::SHARE_SPACE;
 use super::super::super::SHARE_SPACE_STRUCT;
 use super::super::super::{
-    ThreadId, KERNEL_IO_THREAD, PMA_KEEPER, QUARK_CONFIG, ROOT_CONTAINER_ID, THREAD_ID, URING_MGR,
-    VCPU, VMS,
+    ThreadId, KERNEL_IO_THREAD, PMA_KEEPER, QUARK_CONFIG, RECLAIMER, RECLAIM_STORE,
+    ROOT_CONTAINER_ID, THREAD_ID, URING_MGR, VCPU, VMS,
 };
 
 pub const SANDBOX_UID_NAME : &str = "io.kubernetes.cri.sandbox-uid";
@@ -227,6 +229,9 @@ impl VirtualMachine {
             SHARESPACE.dnsSvc.Init().unwrap();
         };
 
+        // take over the network interface before the guest starts
+        InitNetstack();
+
         *SHARESPACE.bootId.lock() = uuid::Uuid::new_v4().to_string();
         
         let syncPrint = sharespace.config.read().SyncPrint();
@@ -484,11 +489,25 @@ impl VirtualMachine {
             );
         }
 
+        if QUARK_CONFIG.lock().EnableReclaim {
+            threads.push(
+                thread::Builder::new()
+                    .name("reclaim".to_string())
+                    .spawn(move || {
+                        RECLAIMER.Process();
+                        info!("reclaimer finish");
+                    })
+                    .unwrap(),
+            );
+        }
+
         for t in threads {
             t.join().expect("the working threads has panicked");
         }
 
+        RECLAIM_STORE.lock().Close();
         URING_MGR.lock().Close();
+        RestoreNetstackNic();
         Ok(GetExitStatus())
     }
 
@@ -31,6 +31,7 @@ use super::super::super::qlib::loader;
 use super::super::super::qlib::*;
 use super::super::super::ucall::ucall::*;
 use super::super::super::ucall::ucall_client::*;
+use super::super::super::vmspace::hibernate::{REAP_SWAP_FILE_NAME, SWAP_FILE_NAME};
 use super::super::super::vmspace::limits::CreateLimitSet;
 use super::super::super::vmspace::syscall::*;
 use super::super::cgroup::cgroup::*;
@@ -326,6 +327,205 @@ impl Sandbox {
         return Ok(());
     }
 
+    // UpdateResources tells the sandbox kernel about the new cpu and memory
+    // limits of the container so that the guest sees the resized resources.
+    pub fn UpdateResources(&self, cid: &str, res: &LinuxResources) -> Result<()> {
+        info!(
+            "Update resources of container {} in sandbox {}",
+            cid, &self.ID
+        );
+
+        let mut args = UpdateResourcesArgs {
+            ContainerID: cid.to_string(),
+            ..Default::default()
+        };
+        match res.cpu {
+            None => (),
+            Some(ref cpu) => {
+                if cpu.cpus.len() != 0 {
+                    args.CPUs = Some(CountCpuset(&cpu.cpus)? as u32);
+                }
+                args.CPUQuota = cpu.quota;
+                args.CPUPeriod = cpu.period;
+                args.CPUShares = cpu.shares;
+            }
+        }
+
+        match res.memory {
+            None => (),
+            Some(ref memory) => {
+                args.MemoryLimit = memory.limit;
+            }
+        }
+
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::UpdateResources(args);
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::UpdateResourcesResp => return Ok(()),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("UpdateResources get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed updating resources".to_string()));
+            }
+        }
+    }
+
+    // Checkpoint hibernates the sandbox and copies its swap files into the
+    // image path. The sandbox stays paused until Unpause.
+    pub fn Checkpoint(&self, cid: &str, imagePath: &str) -> Result<()> {
+        info!(
+            "Checkpoint container {} in sandbox {} to {}",
+            cid, &self.ID, imagePath
+        );
+
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::Checkpoint;
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::CheckpointResp => (),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("Checkpoint get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed checkpointing sandbox".to_string()));
+            }
+        }
+
+        std::fs::create_dir_all(imagePath)
+            .map_err(|e| Error::Common(format!("Checkpoint create {} fail {:?}", imagePath, e)))?;
+
+        // the swap files are created in the working directory of the sandbox process
+        for name in &[SWAP_FILE_NAME, REAP_SWAP_FILE_NAME] {
+            let name = name.trim_start_matches("./");
+            let src = format!("/proc/{}/cwd/{}", self.Pid, name);
+            if !std::path::Path::new(&src).exists() {
+                continue;
+            }
+
+            let dst = format!("{}/{}", imagePath, name);
+            std::fs::copy(&src, &dst).map_err(|e| {
+                Error::Common(format!("Checkpoint copy {} to {} fail {:?}", &src, &dst, e))
+            })?;
+        }
+
+        return Ok(());
+    }
+
+    // Events returns the sandbox events of the container with sequence
+    // number no less than since.
+    pub fn Events(&self, cid: &str, since: u64) -> Result<Vec<SandboxEvent>> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::Events(EventsArgs {
+            cid: cid.to_string(),
+            Since: since,
+        });
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::EventsResp(events) => return Ok(events),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("Events get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed getting events".to_string()));
+            }
+        }
+    }
+
+    // KernelLog returns the kernel log entries of the container with
+    // sequence number no less than since.
+    pub fn KernelLog(&self, cid: &str, since: u64) -> Result<Vec<KernelLogEntry>> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::KernelLog(KernelLogArgs {
+            cid: cid.to_string(),
+            Since: since,
+        });
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::KernelLogResp(entries) => return Ok(entries),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("KernelLog get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed getting kernel log".to_string()));
+            }
+        }
+    }
+
+    // Unsupported returns the calls of the container which fell through to
+    // a stub in the quark kernel.
+    pub fn Unsupported(&self, cid: &str) -> Result<Vec<UnsupportedRecord>> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::Unsupported(cid.to_string());
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::UnsupportedResp(records) => return Ok(records),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("Unsupported get unknown resp {:?}", resp);
+                return Err(Error::Common(
+                    "Failed getting unsupported calls".to_string(),
+                ));
+            }
+        }
+    }
+
+    // TraceStart enables the syscall tracing of the container.
+    pub fn TraceStart(&self, args: &TraceArgs) -> Result<()> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::TraceStart(args.clone());
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::TraceStartResp => return Ok(()),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("TraceStart get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed starting trace".to_string()));
+            }
+        }
+    }
+
+    pub fn TraceStop(&self, cid: &str) -> Result<()> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::TraceStop(cid.to_string());
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::TraceStopResp => return Ok(()),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("TraceStop get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed stopping trace".to_string()));
+            }
+        }
+    }
+
+    // TraceRead returns the trace lines of the container since the last read
+    // and the number of the lines dropped.
+    pub fn TraceRead(&self, cid: &str) -> Result<(Vec<String>, u64)> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::TraceRead(cid.to_string());
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::TraceReadResp(lines, dropped) => return Ok((lines, dropped)),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("TraceRead get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed reading trace".to_string()));
+            }
+        }
+    }
+
+    pub fn Stats(&self, cid: &str) -> Result<ContainerStats> {
+        let client = self.SandboxConnect()?;
+        let req = UCallReq::Stats(cid.to_string());
+        let resp = client.Call(&req)?;
+        match resp {
+            UCallResp::StatsResp(stats) => return Ok(stats),
+            UCallResp::UCallRespErr(e) => return Err(Error::Common(e)),
+            resp => {
+                error!("Stats get unknown resp {:?}", resp);
+                return Err(Error::Common("Failed getting stats".to_string()));
+            }
+        }
+    }
+
     pub fn Processes(&self, cid: &str) -> Result<Vec<ProcessInfo>> {
         info!(
             "Getting processes for container {} in sandbox {}",
@@ -423,6 +623,16 @@ impl Sandbox {
             ID: id.to_string(),
             Caps: specutils::Capabilities(false, &spec.process.capabilities),
             Root: container_root,
+            RootfsOverlay: specutils::RootfsOverlay(&spec),
+            DiskQuota: specutils::DiskQuota(&spec),
+            BlockIO: specutils::BlockIO(&spec),
+            EncryptedVolumes: specutils::EncryptedVolumes(&spec)?,
+            ImageMounts: specutils::ImageMounts(&spec, id)?
+                .into_iter()
+                .map(|(_, m)| m)
+                .collect(),
+            HostMounts: specutils::HostMounts(&spec),
+            EmptyDirs: specutils::EmptyDirs(&spec),
             ..Default::default()
         };
 
@@ -18,6 +18,7 @@ use std::sync::mpsc::Sender;
 use std::sync::Once;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 //use std::path::Path;
 //use std::path::PathBuf;
 //use nix::unistd::{mkdir, Pid};
@@ -29,10 +30,12 @@ use containerd_shim::api::*;
 use containerd_shim::event::Event;
 use containerd_shim::protos::cgroups::metrics::Metrics;
 use containerd_shim::protos::events::task::{
-    TaskCreate, TaskDelete, TaskExecAdded, TaskExecStarted, TaskExit, TaskIO, TaskStart,
+    TaskCheckpointed, TaskCreate, TaskDelete, TaskExecAdded, TaskExecStarted, TaskExit, TaskIO,
+    TaskOOM, TaskPaused, TaskResumed, TaskStart,
 };
 use containerd_shim::protos::protobuf::well_known_types::{Any, Timestamp};
 use containerd_shim::protos::protobuf::{Message, SingularPtrField};
+use containerd_shim::protos::shim::oci::CheckpointOptions;
 use containerd_shim::protos::ttrpc::Error as TError;
 use containerd_shim::util::*;
 use containerd_shim::Error as TtrpcError;
@@ -43,6 +46,7 @@ use containerd_shim::TtrpcResult;
 
 use super::container::*;
 
+use super::super::super::qlib::control_msg::*;
 use super::super::super::runc::oci::LinuxResources;
 use super::super::super::runc::sandbox::sandbox::*;
 
@@ -50,6 +54,9 @@ use super::container_io::{ContainerIO, ContainerStdio};
 
 type EventSender = Sender<(String, Box<dyn Message>)>;
 
+// EVENTS_POLL_INTERVAL is how often the sandbox event log is polled
+const EVENTS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(Clone)]
 pub struct ShimTask {
     pub containers: Arc<Mutex<HashMap<String, CommonContainer>>>,
@@ -101,6 +108,88 @@ impl ShimTask {
         });
     }
 
+    // forward the oom and hibernate events recorded in the sandbox to containerd
+    pub fn ForwardEvents(&self, containers: Arc<Mutex<HashMap<String, CommonContainer>>>) {
+        let tx = self.tx.clone();
+        // the events logged before the shim starts forwarding are not replayed
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+        thread::spawn(move || {
+            let mut since = 0;
+            let mut failed = false;
+            loop {
+                thread::sleep(EVENTS_POLL_INTERVAL);
+                let events = match crate::SANDBOX.lock().Events("", since) {
+                    Ok(events) => events,
+                    Err(e) => {
+                        // the sandbox may be busy, e.g. hibernating, keep polling
+                        // and only log the first failure of a streak
+                        if !failed {
+                            error!("ShimTask ForwardEvents error with {:?}, retrying", e);
+                            failed = true;
+                        }
+                        continue;
+                    }
+                };
+                failed = false;
+
+                for event in events {
+                    since = event.Seq + 1;
+                    if event.Time < start {
+                        continue;
+                    }
+
+                    // the hibernate events apply to all the running containers of
+                    // the sandbox
+                    let ids: Vec<String> = if event.cid.len() > 0 {
+                        vec![event.cid.clone()]
+                    } else {
+                        containers
+                            .lock()
+                            .unwrap()
+                            .values()
+                            .filter(|c| c.init.common.status() == Status::RUNNING)
+                            .map(|c| c.id.clone())
+                            .collect()
+                    };
+
+                    for id in ids {
+                        match event.Type {
+                            SandboxEventType::OOM => Self::SendEvent(
+                                &tx,
+                                TaskOOM {
+                                    container_id: id,
+                                    ..Default::default()
+                                },
+                            ),
+                            // the sandbox only logs the hibernation which pauses
+                            // running containers and its wakeup
+                            SandboxEventType::Hibernate => Self::SendEvent(
+                                &tx,
+                                TaskPaused {
+                                    container_id: id,
+                                    ..Default::default()
+                                },
+                            ),
+                            SandboxEventType::Wakeup => Self::SendEvent(
+                                &tx,
+                                TaskResumed {
+                                    container_id: id,
+                                    ..Default::default()
+                                },
+                            ),
+                            // exits are handled by WaitAll, pause and resume
+                            // are published by the shim requests
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     // handle exit event of container
     pub fn Exit(
         tx: &Arc<Mutex<EventSender>>,
@@ -237,6 +326,16 @@ impl Task for ShimTask {
         let ns = self.namespace.as_str();
         let id = req.id.as_str();
 
+        // the checkpoint image only holds the swapped out memory of the
+        // sandbox, a task can't be restored from it yet
+        if !req.checkpoint.is_empty() {
+            return Err(TtrpcError::Other(format!(
+                "restoring container {} from checkpoint {} is not supported",
+                id, req.checkpoint
+            ))
+            .into());
+        }
+
         let container = ContainerFactory::Create(ns, &req)
             .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
         let mut resp = CreateTaskResponse::new();
@@ -252,6 +351,7 @@ impl Task for ShimTask {
         if len == 0 {
             // root container
             self.WaitAll(self.containers.clone());
+            self.ForwardEvents(self.containers.clone());
         }
 
         containers.insert(id.to_string(), container);
@@ -466,8 +566,80 @@ impl Task for ShimTask {
         let resources: LinuxResources = serde_json::from_slice(req.get_resources().get_value())
             .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
         container
-            .update(&resources)
+            .update(resources)
+            .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
+        Ok(Empty::new())
+    }
+
+    fn pause(&self, _ctx: &TtrpcContext, req: PauseRequest) -> TtrpcResult<Empty> {
+        info!("shim: Pause request for {:?}", req);
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(req.get_id()).ok_or_else(|| {
+            TtrpcError::NotFoundError(format!("can not find container by id {}", req.get_id()))
+        })?;
+        container
+            .pause()
+            .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
+
+        Self::SendEvent(
+            &self.tx,
+            TaskPaused {
+                container_id: req.get_id().to_string(),
+                ..Default::default()
+            },
+        );
+        Ok(Empty::new())
+    }
+
+    fn resume(&self, _ctx: &TtrpcContext, req: ResumeRequest) -> TtrpcResult<Empty> {
+        info!("shim: Resume request for {:?}", req);
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(req.get_id()).ok_or_else(|| {
+            TtrpcError::NotFoundError(format!("can not find container by id {}", req.get_id()))
+        })?;
+        container
+            .resume()
+            .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
+
+        Self::SendEvent(
+            &self.tx,
+            TaskResumed {
+                container_id: req.get_id().to_string(),
+                ..Default::default()
+            },
+        );
+        Ok(Empty::new())
+    }
+
+    fn checkpoint(&self, _ctx: &TtrpcContext, req: CheckpointTaskRequest) -> TtrpcResult<Empty> {
+        info!("shim: Checkpoint request for {:?}", req);
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(req.get_id()).ok_or_else(|| {
+            TtrpcError::NotFoundError(format!("can not find container by id {}", req.get_id()))
+        })?;
+
+        // the runc CheckpointOptions exit asks to stop the task after the
+        // checkpoint
+        let exit = if req.has_options() {
+            CheckpointOptions::parse_from_bytes(req.get_options().get_value())
+                .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?
+                .exit
+        } else {
+            false
+        };
+
+        container
+            .checkpoint(req.get_path(), exit)
             .map_err(|e| TtrpcError::Other(format!("{:?}", e)))?;
+
+        Self::SendEvent(
+            &self.tx,
+            TaskCheckpointed {
+                container_id: req.get_id().to_string(),
+                checkpoint: req.get_path().to_string(),
+                ..Default::default()
+            },
+        );
         Ok(Empty::new())
     }
 
@@ -124,6 +124,11 @@ pub fn ValidateMount(mnt: &Mount) -> Result<()> {
     if mnt.typ.as_str() == "bind" {
         for o in &mnt.options {
             let o: &str = o;
+            // the key of an encrypted volume is for the kernel
+            if o.starts_with(ENCRYPT_KEY_OPTION) {
+                continue;
+            }
+
             if ContainsStr(&*INVALID_OPTIONS, o) {
                 return Err(Error::Common(format!(
                     "mount option {:?} is not supported: {:?}",
@@ -7,7 +7,11 @@ use std::fs::File;
 
 use super::super::super::qlib::auth::cap_set::*;
 use super::super::super::qlib::common::*;
+use super::super::super::qlib::config;
 use super::super::super::qlib::linux_def::*;
+use super::super::super::qlib::loader::{
+    BlockIOLimits, DiskQuotaLimits, EmptyDirMount, EncryptedVolume, ImageMount,
+};
 use super::super::super::qlib::path::*;
 use super::super::oci::*;
 use super::fs::*;
@@ -39,6 +43,38 @@ const CONTAINERD_CONTAINER_TYPE_SANDBOX: &str = "sandbox";
 // is not the first container in the sandbox.
 const CONTAINERD_SANDBOX_IDANNOTATION: &str = "io.kubernetes.cri.sandbox-id";
 
+// RootfsOverlayAnnotation overrides the RootfsOverlay config for a container,
+// the value is "tmpfs" or "none".
+const ROOTFS_OVERLAY_ANNOTATION: &str = "dev.quark.rootfs-overlay";
+
+// DiskQuotaBytesAnnotation and DiskQuotaInodesAnnotation set the byte and
+// inode quota of the host-backed mount of a container.
+const DISK_QUOTA_BYTES_ANNOTATION: &str = "dev.quark.disk-quota-bytes";
+const DISK_QUOTA_INODES_ANNOTATION: &str = "dev.quark.disk-quota-inodes";
+
+// EncryptKeyOption is the bind mount option of an encrypted volume, the value
+// is the path of the file with the master key of the volume.
+pub const ENCRYPT_KEY_OPTION: &str = "quark.encrypt-key=";
+const ENCRYPT_KEY_SIZE: usize = 64;
+
+// RootfsImageAnnotation is the host path of an ext4 or erofs image file the
+// kernel mounts as the container rootfs in place of the rootfs directory.
+const ROOTFS_IMAGE_ANNOTATION: &str = "dev.quark.rootfs-image";
+
+// MountAnnotationPrefix is the prefix of the pod volume annotations set by
+// the containerd shim, "<prefix><name>.source" and "<prefix><name>.type" are
+// the host path and the type of the volume. A tmpfs volume is a memory backed
+// emptyDir.
+const MOUNT_ANNOTATION_PREFIX: &str = "dev.gvisor.spec.mount.";
+
+// ImagesDir is the directory of the sandbox root the image files of the
+// containers are bind mounted under, the kernel opens them there.
+pub const IMAGES_DIR: &str = ".quark-images";
+
+// KernelMounts are mounted by the kernel itself, the host mounts under them
+// are never seen by the container.
+const KERNEL_MOUNTS: [&str; 3] = ["/dev", "/proc", "/sys"];
+
 // ValidateSpec validates that the spec is compatible with qvisor.
 pub fn ValidateSpec(spec: &Spec) -> Result<()> {
     // Mandatory fields.
@@ -237,6 +273,302 @@ pub fn SandboxID(spec: &Spec) -> Option<String> {
     };
 }
 
+// RootfsOverlay returns true if the container rootfs should be mounted as an
+// overlay with a tmpfs upper layer. A read only rootfs has no writes to keep,
+// so it is never overlaid.
+pub fn RootfsOverlay(spec: &Spec) -> bool {
+    if spec.root.readonly {
+        return false;
+    }
+
+    // the image rootfs is read only, the writes need the overlay
+    if spec.annotations.contains_key(ROOTFS_IMAGE_ANNOTATION) {
+        return true;
+    }
+
+    let overlay = match spec.annotations.get(ROOTFS_OVERLAY_ANNOTATION) {
+        None => crate::QUARK_CONFIG.lock().RootfsOverlay,
+        Some(v) => match v.as_str() {
+            "tmpfs" => config::RootfsOverlay::Tmpfs,
+            "none" => config::RootfsOverlay::None,
+            _ => {
+                warn!(
+                    "invalid {} annotation value {}",
+                    ROOTFS_OVERLAY_ANNOTATION, v
+                );
+                crate::QUARK_CONFIG.lock().RootfsOverlay
+            }
+        },
+    };
+
+    return overlay == config::RootfsOverlay::Tmpfs;
+}
+
+fn AnnotationU64(spec: &Spec, key: &str) -> u64 {
+    return match spec.annotations.get(key) {
+        None => 0,
+        Some(v) => match v.parse::<u64>() {
+            Ok(v) => v,
+            Err(_) => {
+                warn!("invalid {} annotation value {}", key, v);
+                0
+            }
+        },
+    };
+}
+
+// DiskQuota returns the byte and inode quota of the host-backed mount of the
+// container, 0 is unlimited.
+pub fn DiskQuota(spec: &Spec) -> DiskQuotaLimits {
+    return DiskQuotaLimits {
+        Bytes: AnnotationU64(spec, DISK_QUOTA_BYTES_ANNOTATION),
+        Inodes: AnnotationU64(spec, DISK_QUOTA_INODES_ANNOTATION),
+    };
+}
+
+// ThrottleRate returns the most restrictive rate of the throttled devices.
+// The kernel can't tell the block device of a host file, the st_dev of an
+// overlay or btrfs file is not the device, so the per device limits are not
+// kept. The one limit applies to the I/O of the regular files of all the host
+// mounts of the container, whatever device they are on.
+fn ThrottleRate(devices: &Vec<LinuxThrottleDevice>) -> u64 {
+    let mut rate = 0;
+    for d in devices {
+        if d.rate != 0 && (rate == 0 || d.rate < rate) {
+            rate = d.rate;
+        }
+    }
+
+    let limited = devices.iter().filter(|d| d.rate != 0).count();
+    if limited > 1 {
+        warn!(
+            "the blkio throttle of {} devices is applied as the rate {} to all the devices",
+            limited, rate
+        );
+    }
+
+    return rate;
+}
+
+// BlockIO returns the bandwidth and iops limits of the container from the
+// blockIO throttle settings of the spec.
+pub fn BlockIO(spec: &Spec) -> BlockIOLimits {
+    let blockIO = match &spec.linux {
+        None => return BlockIOLimits::default(),
+        Some(linux) => match &linux.resources {
+            None => return BlockIOLimits::default(),
+            Some(resources) => match &resources.block_io {
+                None => return BlockIOLimits::default(),
+                Some(blockIO) => blockIO,
+            },
+        },
+    };
+
+    return BlockIOLimits {
+        ReadBps: ThrottleRate(&blockIO.throttle_read_bps_device),
+        WriteBps: ThrottleRate(&blockIO.throttle_write_bps_device),
+        ReadIops: ThrottleRate(&blockIO.throttle_read_iops_device),
+        WriteIops: ThrottleRate(&blockIO.throttle_write_iops_device),
+    };
+}
+
+// DecodeKey decodes the content of a key file, the raw key or its hex
+// encoding.
+fn DecodeKey(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() == ENCRYPT_KEY_SIZE {
+        return Ok(data.to_vec());
+    }
+
+    let hex = match std::str::from_utf8(data) {
+        Ok(s) => s.trim(),
+        Err(_) => "",
+    };
+
+    if hex.len() == ENCRYPT_KEY_SIZE * 2 && hex.is_ascii() {
+        let mut key = Vec::with_capacity(ENCRYPT_KEY_SIZE);
+        for i in (0..hex.len()).step_by(2) {
+            match u8::from_str_radix(&hex[i..i + 2], 16) {
+                Ok(b) => key.push(b),
+                Err(_) => break,
+            }
+        }
+
+        if key.len() == ENCRYPT_KEY_SIZE {
+            return Ok(key);
+        }
+    }
+
+    return Err(Error::Common(format!(
+        "encrypt key must be {} bytes, raw or hex encoded",
+        ENCRYPT_KEY_SIZE
+    )));
+}
+
+// EncryptedVolumes returns the mounts of the spec with the encrypt key
+// option. The keys are read here and handed to the kernel with the process,
+// the key files are expected on a tmpfs the key service fills at start.
+pub fn EncryptedVolumes(spec: &Spec) -> Result<Vec<EncryptedVolume>> {
+    let mut volumes = Vec::new();
+    for m in &spec.mounts {
+        for o in &m.options {
+            let keyFile = match o.strip_prefix(ENCRYPT_KEY_OPTION) {
+                None => continue,
+                Some(f) => f,
+            };
+
+            let bind =
+                m.typ.as_str() == "bind" || m.options.iter().any(|o| o == "bind" || o == "rbind");
+            if !bind {
+                return Err(Error::Common(format!(
+                    "encrypted volume {} must be a bind mount",
+                    m.destination
+                )));
+            }
+
+            let data = fs::read(keyFile)
+                .map_err(|e| Error::IOError(format!("read key file {} fail: {:?}", keyFile, e)))?;
+
+            volumes.push(EncryptedVolume {
+                Path: m.destination.to_string(),
+                Key: DecodeKey(&data)?,
+            });
+        }
+    }
+
+    return Ok(volumes);
+}
+
+// IsImageMount returns true if the mount is an ext4 or erofs image file the
+// kernel mounts, the host doesn't mount it.
+pub fn IsImageMount(m: &Mount) -> bool {
+    if m.typ != "ext4" && m.typ != "erofs" {
+        return false;
+    }
+
+    return match fs::metadata(&m.source) {
+        Ok(fi) => fi.is_file(),
+        Err(_) => false,
+    };
+}
+
+// ImageMounts returns the host paths of the image files of the container with
+// the mounts of them. The source of a mount is the path the file is bind
+// mounted at in the sandbox root.
+pub fn ImageMounts(spec: &Spec, containerId: &str) -> Result<Vec<(String, ImageMount)>> {
+    let mut images = Vec::new();
+    if let Some(path) = spec.annotations.get(ROOTFS_IMAGE_ANNOTATION) {
+        if !IsAbs(path) {
+            return Err(Error::Common(format!(
+                "{} annotation must be an absolute path: {}",
+                ROOTFS_IMAGE_ANNOTATION, path
+            )));
+        }
+
+        images.push((
+            path.to_string(),
+            ImageMount {
+                Source: ImageSandboxPath(containerId, images.len()),
+                Destination: "/".to_string(),
+                Type: "".to_string(),
+            },
+        ));
+    }
+
+    for m in &spec.mounts {
+        if !IsImageMount(m) {
+            continue;
+        }
+
+        images.push((
+            m.source.to_string(),
+            ImageMount {
+                Source: ImageSandboxPath(containerId, images.len()),
+                Destination: m.destination.to_string(),
+                Type: m.typ.to_string(),
+            },
+        ));
+    }
+
+    return Ok(images);
+}
+
+fn ImageSandboxPath(containerId: &str, index: usize) -> String {
+    return format!("/{}/{}/{}", IMAGES_DIR, containerId, index);
+}
+
+// EmptyDirs returns the memory backed emptyDir volumes of the pod from the
+// volume annotations. The kubelet mounts such a volume as a host tmpfs of the
+// emptyDir.sizeLimit size, which is the size of the kernel tmpfs.
+pub fn EmptyDirs(spec: &Spec) -> Vec<EmptyDirMount> {
+    let mut dirs = Vec::new();
+    for (key, source) in &spec.annotations {
+        let name = match key
+            .strip_prefix(MOUNT_ANNOTATION_PREFIX)
+            .and_then(|k| k.strip_suffix(".source"))
+        {
+            None => continue,
+            Some(name) => name,
+        };
+
+        let typ = format!("{}{}.type", MOUNT_ANNOTATION_PREFIX, name);
+        if spec.annotations.get(&typ).map(|t| t.as_str()) != Some("tmpfs") {
+            continue;
+        }
+
+        dirs.push(EmptyDirMount {
+            Name: name.to_string(),
+            Source: Clean(source),
+            Size: TmpfsSize(source),
+        });
+    }
+
+    return dirs;
+}
+
+// TmpfsSize returns the size of the host tmpfs at path, 0 if it is not one.
+fn TmpfsSize(path: &str) -> u64 {
+    let cpath = match std::ffi::CString::new(path) {
+        Err(_) => return 0,
+        Ok(p) => p,
+    };
+
+    let mut st: statfs = unsafe { core::mem::zeroed() };
+    let ret = unsafe { statfs(cpath.as_ptr(), &mut st) };
+    if ret < 0 || st.f_type as u64 != FSMagic::TMPFS_MAGIC {
+        return 0;
+    }
+
+    return st.f_blocks as u64 * st.f_bsize as u64;
+}
+
+// HostMounts returns the destinations of the mounts the host makes in the
+// rootfs directory when the rootfs is an image, the kernel mounts them again
+// over the image.
+pub fn HostMounts(spec: &Spec) -> Vec<String> {
+    let mut mounts = Vec::new();
+    if !spec.annotations.contains_key(ROOTFS_IMAGE_ANNOTATION) {
+        return mounts;
+    }
+
+    for m in &spec.mounts {
+        if m.typ == "cgroup" || IsImageMount(m) {
+            continue;
+        }
+
+        let dest = Clean(&m.destination);
+        let underKernelMount = KERNEL_MOUNTS
+            .iter()
+            .any(|k| dest == *k || dest.starts_with(&format!("{}/", k)));
+        if underKernelMount {
+            continue;
+        }
+
+        mounts.push(dest);
+    }
+
+    return mounts;
+}
+
 pub fn MkdirAll(dst: &str) -> Result<()> {
     return fs::create_dir_all(dst)
         .map_err(|e| Error::IOError(format!("Mkdir({:?}) failed: {:?}", dst, e)));
@@ -39,6 +39,16 @@ pub enum UCallReq {
     CreateSubContainer(CreateArgs),
     StartSubContainer(StartArgs),
     WaitAll,
+    UpdateResources(UpdateResourcesArgs),
+    Checkpoint,
+    Events(EventsArgs),
+    Stats(Cid),
+    KernelLog(KernelLogArgs),
+    Unsupported(Cid),
+    TraceStart(TraceArgs),
+    TraceStop(Cid),
+    // TraceRead drains the trace ring in the share space on the host
+    TraceRead(Cid),
 }
 
 impl FileDescriptors for UCallReq {
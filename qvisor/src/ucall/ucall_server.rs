@@ -13,6 +13,10 @@
 // limitations under the License.
 
 use crate::qlib::kernel::GlobalIOMgr;
+use alloc::collections::btree_map::BTreeMap;
+use core::sync::atomic::Ordering;
+use lazy_static::lazy_static;
+use spin::Mutex;
 
 use super::super::qlib::common::*;
 use super::super::qlib::control_msg::*;
@@ -20,10 +24,21 @@ use super::super::qlib::linux_def::*;
 use super::super::qlib::loader;
 use super::super::runc::container::container::*;
 use super::super::vmspace::*;
+use super::super::SHARE_SPACE;
 use super::super::URING_MGR;
 use super::ucall::*;
 use super::usocket::*;
 
+// TRACE_MAX_PENDING is the max number of the trace lines of a container kept
+// on the host until they are read.
+pub const TRACE_MAX_PENDING: usize = 65536;
+
+lazy_static! {
+    // TRACE_LINES is the trace lines drained from the trace ring which are
+    // not read yet, keyed by the container id.
+    static ref TRACE_LINES: Mutex<BTreeMap<String, Vec<String>>> = Mutex::new(BTreeMap::new());
+}
+
 pub fn ReadControlMsg(fd: i32) -> Result<ControlMsg> {
     let usock = USocket { socket: fd };
 
@@ -37,7 +52,7 @@ pub fn ReadControlMsg(fd: i32) -> Result<ControlMsg> {
         }
     };
 
-    let msg = ProcessReqHandler(&mut req, &fds);
+    let msg = ProcessReqHandler(&usock, &mut req, &fds);
     return msg;
 }
 
@@ -157,7 +172,95 @@ pub fn StartSubContainerHandler(args: &mut StartArgs) -> Result<ControlMsg> {
     return Ok(msg);
 }
 
-pub fn ProcessReqHandler(req: &mut UCallReq, fds: &[i32]) -> Result<ControlMsg> {
+pub fn UpdateResourcesHandler(args: &UpdateResourcesArgs) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::UpdateResources(args.clone()));
+    return Ok(msg);
+}
+
+pub fn CheckpointHandler() -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::Checkpoint);
+    return Ok(msg);
+}
+
+pub fn EventsHandler(args: &EventsArgs) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::Events(args.clone()));
+    return Ok(msg);
+}
+
+pub fn StatsHandler(cid: &str) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::Stats(cid.to_string()));
+    return Ok(msg);
+}
+
+pub fn KernelLogHandler(args: &KernelLogArgs) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::KernelLog(args.clone()));
+    return Ok(msg);
+}
+
+pub fn UnsupportedHandler(cid: &str) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::Unsupported(cid.to_string()));
+    return Ok(msg);
+}
+
+pub fn TraceStartHandler(args: &TraceArgs) -> Result<ControlMsg> {
+    let msg = ControlMsg::New(Payload::TraceStart(args.clone()));
+    return Ok(msg);
+}
+
+pub fn TraceStopHandler(cid: &str) -> Result<ControlMsg> {
+    TRACE_LINES.lock().remove(cid);
+    let msg = ControlMsg::New(Payload::TraceStop(cid.to_string()));
+    return Ok(msg);
+}
+
+// TraceReadHandler drains the trace ring and answers the request with the
+// trace lines of the container on the host, the lines of the other containers
+// are kept for their readers. The qkernel only gets Payload::Served.
+pub fn TraceReadHandler(usock: &USocket, cid: &str) -> Result<ControlMsg> {
+    let resp = {
+        let mut lines = TRACE_LINES.lock();
+        let mut traceBuf = SHARE_SPACE.traceBuf.lock();
+        if let Some(bs) = traceBuf.as_mut() {
+            let mut data = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let cnt = match bs.lock().read(&mut buf) {
+                    Ok((_, cnt)) => cnt,
+                    Err(_) => 0,
+                };
+                if cnt == 0 {
+                    break;
+                }
+                data.extend_from_slice(&buf[..cnt]);
+            }
+
+            for record in String::from_utf8_lossy(&data).lines() {
+                let (id, line) = match record.split_once('\t') {
+                    Some(r) => r,
+                    None => continue,
+                };
+                let pending = lines.entry(id.to_string()).or_insert(Vec::new());
+                if pending.len() < TRACE_MAX_PENDING {
+                    pending.push(line.to_string());
+                }
+            }
+        }
+
+        let ret = lines.remove(cid).unwrap_or_default();
+        let dropped = SHARE_SPACE.traceDropped.swap(0, Ordering::Relaxed);
+        UCallResp::TraceReadResp(ret, dropped)
+    };
+
+    match usock.SendResp(&resp) {
+        Err(e) => error!("TraceRead send resp fail with error {:?}", e),
+        Ok(()) => (),
+    }
+    usock.Drop();
+
+    return Ok(ControlMsg::New(Payload::Served));
+}
+
+pub fn ProcessReqHandler(usock: &USocket, req: &mut UCallReq, fds: &[i32]) -> Result<ControlMsg> {
     let msg = match req {
         UCallReq::RootContainerStart(start) => RootContainerStartHandler(start)?,
         UCallReq::ExecProcess(ref mut execArgs) => ExecProcessHandler(execArgs, fds)?,
@@ -171,6 +274,15 @@ pub fn ProcessReqHandler(req: &mut UCallReq, fds: &[i32]) -> Result<ControlMsg>
         UCallReq::CreateSubContainer(args) => CreateSubContainerHandler(args, fds)?,
         UCallReq::StartSubContainer(args) => StartSubContainerHandler(args)?,
         UCallReq::WaitAll => WaitAll()?,
+        UCallReq::UpdateResources(args) => UpdateResourcesHandler(args)?,
+        UCallReq::Checkpoint => CheckpointHandler()?,
+        UCallReq::Events(args) => EventsHandler(args)?,
+        UCallReq::Stats(cid) => StatsHandler(cid)?,
+        UCallReq::KernelLog(args) => KernelLogHandler(args)?,
+        UCallReq::Unsupported(cid) => UnsupportedHandler(cid)?,
+        UCallReq::TraceStart(args) => TraceStartHandler(args)?,
+        UCallReq::TraceStop(cid) => TraceStopHandler(cid)?,
+        UCallReq::TraceRead(cid) => TraceReadHandler(usock, cid)?,
     };
 
     return Ok(msg);
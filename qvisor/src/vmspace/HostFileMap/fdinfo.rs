@@ -377,7 +377,11 @@ impl FdInfo {
 
     pub fn IOFcntl(&self, cmd: i32, arg: u64) -> i64 {
         assert!(
-            cmd == Cmd::F_GETFL || cmd == Cmd::F_GET_SEALS || cmd == Cmd::F_ADD_SEALS,
+            cmd == Cmd::F_GETFL
+                || cmd == Cmd::F_GET_SEALS
+                || cmd == Cmd::F_ADD_SEALS
+                || cmd == Cmd::F_OFD_GETLK
+                || cmd == Cmd::F_OFD_SETLK,
             "we only support Cmd::F_GETFL in Fcntl"
         );
         if cmd == Cmd::F_GETFL {
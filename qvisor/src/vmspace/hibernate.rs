@@ -29,7 +29,9 @@ use crate::qlib::mem::block_allocator::*;
 use crate::qlib::mem::buddy_allocator::*;
 use crate::vmspace::kernel::Timestamp;
 use crate::vmspace::kernel::SHARESPACE;
+use crate::vmspace::reclaim::*;
 use crate::GLOBAL_ALLOCATOR;
+use crate::RECLAIM_STORE;
 use crate::SHARE_SPACE;
 use crate::SWAP_FILE;
 
@@ -195,9 +197,69 @@ impl HiberMgr {
 
         match intern.pageMap.remove(&phyAddr) {
             None => return Err(Error::SysError(SysErr::EINVAL)),
+            Some(offset) if offset & RECLAIM_SLOT_FLAG != 0 => {
+                return RECLAIM_STORE
+                    .lock()
+                    .SwapInPage(phyAddr, offset & !RECLAIM_SLOT_FLAG)
+            }
             Some(offset) => return SWAP_FILE.lock().SwapInPage(phyAddr, offset),
         }
     }
+
+    // ReclaimColdPages swaps out the private user pages which are not accessed
+    // since the last round to the reclaim swap store. A page which can't be
+    // written to the store stays mapped and is retried in the next round.
+    pub fn ReclaimColdPages(&self, start: u64, len: u64) -> Result<u64> {
+        let mut intern = self.lock();
+        let intern = &mut *intern;
+        let mut map = BTreeSet::new();
+
+        let mut store = RECLAIM_STORE.lock();
+        let pageMap = &mut intern.pageMap;
+        // only the page mapped by one page table entry is reclaimed, the page
+        // cache pages are accessed by the kernel with the physical address and
+        // the user pages handed to the host are pinned with an extra reference.
+        // The io_uring buffers are kernel buffers which the user doesn't map.
+        let mut swapOut = |addr: u64| -> bool {
+            if !matches!(SHARE_SPACE.pageMgr.pagepool.GetRef(addr), Ok(1)) {
+                return false;
+            }
+
+            let offset = match store.SwapOutPage(addr) {
+                Ok(offset) => offset,
+                Err(e) => {
+                    error!("reclaim swap out page {:x} fail {:?}, skip it", addr, e);
+                    return false;
+                }
+            };
+
+            // the stale slot of a page which has been freed when it is swapped out
+            let ret = match pageMap.insert(addr, offset | RECLAIM_SLOT_FLAG) {
+                None => Ok(()),
+                Some(offset) if offset & RECLAIM_SLOT_FLAG != 0 => {
+                    store.DropPage(offset & !RECLAIM_SLOT_FLAG)
+                }
+                Some(offset) => SWAP_FILE.lock().DropPage(offset),
+            };
+            if let Err(e) = ret {
+                error!(
+                    "reclaim drop the stale slot of page {:x} fail {:?}",
+                    addr, e
+                );
+            }
+            return true;
+        };
+
+        for (_, mm) in &intern.memmgrs {
+            let mm = mm.Upgrade();
+            mm.pagetable
+                .write()
+                .pt
+                .ReclaimColdPages(start, len, &mut map, &mut swapOut)?;
+        }
+
+        return Ok(map.len() as u64);
+    }
 }
 
 pub const REAP_SWAP_FILE_NAME: &str = "./reap_swapfile.data";
@@ -20,7 +20,9 @@ pub mod host_uring;
 pub mod hostfdnotifier;
 pub mod kernel_io_thread;
 pub mod limits;
+pub mod netstack;
 pub mod random;
+pub mod reclaim;
 pub mod syscall;
 pub mod time;
 pub mod uringMgr;
@@ -270,6 +272,25 @@ impl VMSpace {
             process.Stdiofds[i] = hostfd;
         }
         process.Root = format!("/{}", &process.ID);
+        process.RootfsOverlay = RootfsOverlay(spec);
+        process.DiskQuota = DiskQuota(spec);
+        process.BlockIO = BlockIO(spec);
+        process.EncryptedVolumes = match EncryptedVolumes(spec) {
+            Err(e) => {
+                error!("LoadProcessKernel: load encrypted volumes fail {:?}", e);
+                return -SysErr::EINVAL as i64;
+            }
+            Ok(volumes) => volumes,
+        };
+        process.ImageMounts = match ImageMounts(spec, &process.ID) {
+            Err(e) => {
+                error!("LoadProcessKernel: load image mounts fail {:?}", e);
+                return -SysErr::EINVAL as i64;
+            }
+            Ok(mounts) => mounts.into_iter().map(|(_, m)| m).collect(),
+        };
+        process.HostMounts = HostMounts(spec);
+        process.EmptyDirs = EmptyDirs(spec);
         //process.Root = "/".to_string();
 
         let rootfs = self.args.as_ref().unwrap().Rootfs.to_string();
@@ -1286,6 +1307,53 @@ impl VMSpace {
 
     ///////////start of network operation//////////////////////////////////////////////////////////////////
 
+    // HostInotifyInit creates the host inotify fd which watches the host
+    // backed files of the guest, the guest reads the events through uring.
+    pub fn HostInotifyInit() -> i64 {
+        let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+        if fd < 0 {
+            return Self::GetRet(fd as i64);
+        }
+
+        if let Err(e) = URING_MGR.lock().Addfd(fd) {
+            error!("HostInotifyInit: add fd {} to uring fail {:?}", fd, e);
+            unsafe { close(fd) };
+            return match e {
+                Error::SysError(errno) => -errno as i64,
+                _ => -SysErr::EINVAL as i64,
+            };
+        }
+
+        let hostfd = GlobalIOMgr().AddFile(fd);
+        return hostfd as i64;
+    }
+
+    pub fn HostInotifyAddWatch(fd: i32, target: i32, mask: u32) -> i64 {
+        // the watched file is only known by its fd, watch it through procfs
+        let path = format!("/proc/self/fd/{}", target);
+        let cstr = CString::New(&path);
+        let ret = unsafe { inotify_add_watch(fd, cstr.Ptr() as *const c_char, mask) };
+        return Self::GetRet(ret as i64);
+    }
+
+    pub fn HostInotifyRmWatch(fd: i32, wd: i32) -> i64 {
+        let ret = unsafe { inotify_rm_watch(fd, wd) };
+        return Self::GetRet(ret as i64);
+    }
+
+    // HostFlock mirrors the guest flock(2) locks of a host backed file, the
+    // operation is always LOCK_NB so that the qcall thread never blocks.
+    pub fn HostFlock(fd: i32, operation: i32) -> i64 {
+        let fdInfo = match Self::GetFdInfo(fd) {
+            Some(info) => info,
+            None => return -SysErr::EBADF as i64,
+        };
+
+        let fd = fdInfo.lock().fd;
+        let ret = unsafe { flock(fd, operation | LOCK_NB) };
+        return Self::GetRet(ret as i64);
+    }
+
     pub fn HostUnixRecvMsg(fd: i32, msghdr: u64, flags: i32) -> i64 {
         match Self::HostUnixRecvMsgHelper(fd, msghdr, flags) {
             Err(Error::SysError(errno)) => {
@@ -1938,6 +2006,20 @@ impl VMSpace {
         }
     }
 
+    // ReportFreePages returns the free page ranges reported by the guest to the host
+    pub fn ReportFreePages(iovs: u64, count: usize) -> i64 {
+        let iovs = unsafe { std::slice::from_raw_parts(iovs as *const IoVec, count) };
+        for iov in iovs {
+            let ret =
+                unsafe { madvise(iov.start as *mut c_void, iov.len, MAdviseOp::MADV_DONTNEED) };
+            if ret < 0 {
+                return Self::GetRet(ret as i64);
+            }
+        }
+
+        return 0;
+    }
+
     pub fn SwapInPage(addr: u64) -> i64 {
         match SHARE_SPACE.hiberMgr.SwapIn(addr) {
             Ok(_) => return 0,
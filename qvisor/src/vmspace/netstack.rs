@@ -0,0 +1,604 @@
+// Copyright (c) 2021 Quark Container Authors / 2018 The gVisor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Hands the sandbox's network interface over to the qkernel netstack. The
+// ipv4 and ipv6 configuration of the interface is moved into the netstack and
+// removed from the host interface so that the host kernel doesn't answer the
+// traffic of the sandbox any more. The removed configuration is put back when
+// qvisor exits.
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use std::fs;
+
+use crate::qlib::common::*;
+use crate::qlib::kernel::tcpip::header::IpAddr;
+use crate::qlib::kernel::tcpip::stack::NetstackNicInfo;
+use crate::qlib::linux_def::*;
+use crate::vmspace::kernel::GlobalIOMgr;
+use crate::vmspace::kernel::SHARESPACE;
+
+const ETH_P_ALL: u16 = 0x0003;
+
+const SIOCADDRT: u64 = 0x890B;
+const RTF_UP: u16 = 0x0001;
+const RTF_GATEWAY: u16 = 0x0002;
+
+// the scope of the link local addresses in /proc/net/if_inet6
+const IPV6_ADDR_LINKLOCAL: u8 = 0x20;
+// the metric linux gives to the default routes learnt from router adverts
+const IPV6_DEFAULT_ROUTE_METRIC: u32 = 1024;
+
+// HostNicConfig is the configuration taken away from the host interface
+#[derive(Debug, Clone)]
+struct HostNicConfig {
+    ifname: String,
+    ifindex: i32,
+    addr: u32,
+    netmask: u32,
+    gateway: u32,
+    ipv6: Option<HostNicIpv6Config>,
+}
+
+// HostNicIpv6Config is the ipv6 configuration of the host interface. The
+// netstack takes it over by disabling ipv6 on the interface, which removes
+// the addresses and the routes.
+#[derive(Debug, Clone)]
+struct HostNicIpv6Config {
+    // the value of disable_ipv6 before the takeover
+    disableIpv6: String,
+    // the global addresses and their prefix length
+    addrs: Vec<([u8; 16], u8)>,
+    gateway: [u8; 16],
+}
+
+lazy_static! {
+    static ref HOST_NIC_CONFIG: Mutex<Option<HostNicConfig>> = Mutex::new(None);
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IfReq {
+    name: [u8; libc::IFNAMSIZ],
+    data: [u8; 24],
+}
+
+impl IfReq {
+    fn New(ifname: &str) -> Self {
+        let mut req = Self {
+            name: [0; libc::IFNAMSIZ],
+            data: [0; 24],
+        };
+
+        let len = core::cmp::min(ifname.len(), libc::IFNAMSIZ - 1);
+        req.name[..len].copy_from_slice(&ifname.as_bytes()[..len]);
+        return req;
+    }
+
+    // the sin_addr of the sockaddr_in in ifr_addr
+    fn Ipv4Addr(&self) -> u32 {
+        return u32::from_be_bytes([self.data[4], self.data[5], self.data[6], self.data[7]]);
+    }
+
+    fn SetIpv4Addr(&mut self, addr: u32) {
+        self.data = [0; 24];
+        self.data[0..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+        self.data[4..8].copy_from_slice(&addr.to_be_bytes());
+    }
+
+    fn I32(&self) -> i32 {
+        return i32::from_ne_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]);
+    }
+}
+
+// struct rtentry, from linux/route.h
+#[repr(C)]
+struct RtEntry {
+    pad1: u64,
+    dst: [u8; 16],
+    gateway: [u8; 16],
+    genmask: [u8; 16],
+    flags: u16,
+    pad2: i16,
+    pad3: u64,
+    tos: u8,
+    class: u8,
+    pad4: [i16; 3],
+    metric: i16,
+    dev: *mut libc::c_char,
+    mtu: u64,
+    window: u64,
+    irtt: u16,
+}
+
+// struct in6_ifreq, from linux/ipv6.h
+#[repr(C)]
+struct In6IfReq {
+    addr: [u8; 16],
+    prefixLen: u32,
+    ifindex: i32,
+}
+
+// struct in6_rtmsg, from linux/ipv6_route.h
+#[repr(C)]
+struct In6RtMsg {
+    dst: [u8; 16],
+    src: [u8; 16],
+    gateway: [u8; 16],
+    typ: u32,
+    dstLen: u16,
+    srcLen: u16,
+    metric: u32,
+    info: u64,
+    flags: u32,
+    ifindex: i32,
+}
+
+// SockAddrIn returns the struct sockaddr_in of the host byte order addr
+fn SockAddrIn(addr: u32) -> [u8; 16] {
+    let mut sa = [0; 16];
+    sa[0..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+    sa[4..8].copy_from_slice(&addr.to_be_bytes());
+    return sa;
+}
+
+fn Ioctl(fd: i32, request: u64, req: &mut IfReq) -> Result<()> {
+    let ret = unsafe { libc::ioctl(fd, request as _, req as *mut IfReq) };
+    if ret < 0 {
+        return Err(Error::SysError(errno::errno().0));
+    }
+
+    return Ok(());
+}
+
+fn ControlSocket(family: i32) -> Result<i32> {
+    let ctlSock = unsafe { libc::socket(family, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if ctlSock < 0 {
+        return Err(Error::SysError(errno::errno().0));
+    }
+
+    return Ok(ctlSock);
+}
+
+// DefaultRoute returns the interface and the gateway (host byte order) of the
+// default route in /proc/net/route
+fn DefaultRoute() -> Option<(String, u32)> {
+    let content = fs::read_to_string("/proc/net/route").ok()?;
+    for line in content.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 || fields[1] != "00000000" || fields[7] != "00000000" {
+            continue;
+        }
+
+        // the address is printed as the hex of the network order u32 in memory
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        return Some((fields[0].to_string(), u32::from_be(gateway)));
+    }
+
+    return None;
+}
+
+// HexIpv6Addr parses the 32 hex digits ipv6 address of the /proc/net files
+fn HexIpv6Addr(s: &str) -> Option<[u8; 16]> {
+    if s.len() != 32 {
+        return None;
+    }
+
+    let mut addr = [0; 16];
+    for i in 0..16 {
+        addr[i] = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    return Some(addr);
+}
+
+// Ipv6Addrs returns the ipv6 addresses of the interface with their prefix
+// length and scope from /proc/net/if_inet6
+fn Ipv6Addrs(ifname: &str) -> Vec<([u8; 16], u8, u8)> {
+    let mut addrs = Vec::new();
+    let content = match fs::read_to_string("/proc/net/if_inet6") {
+        Err(_) => return addrs,
+        Ok(content) => content,
+    };
+
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || fields[5] != ifname {
+            continue;
+        }
+
+        let addr = match HexIpv6Addr(fields[0]) {
+            None => continue,
+            Some(addr) => addr,
+        };
+        let prefixLen = u8::from_str_radix(fields[2], 16).unwrap_or(64);
+        let scope = u8::from_str_radix(fields[3], 16).unwrap_or(0);
+        addrs.push((addr, prefixLen, scope));
+    }
+
+    return addrs;
+}
+
+// Ipv6DefaultRoute returns the gateway of the ipv6 default route through the
+// interface in /proc/net/ipv6_route
+fn Ipv6DefaultRoute(ifname: &str) -> Option<[u8; 16]> {
+    let content = fs::read_to_string("/proc/net/ipv6_route").ok()?;
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 || fields[9] != ifname || fields[1] != "00" {
+            continue;
+        }
+
+        let dst = HexIpv6Addr(fields[0])?;
+        let gateway = HexIpv6Addr(fields[4])?;
+        if dst != [0; 16] || gateway == [0; 16] {
+            continue;
+        }
+
+        return Some(gateway);
+    }
+
+    return None;
+}
+
+// Eui64LinkLocal returns the fe80::/64 address derived from the mac
+fn Eui64LinkLocal(mac: &[u8; 6]) -> [u8; 16] {
+    let mut addr = [0; 16];
+    addr[0] = 0xfe;
+    addr[1] = 0x80;
+    addr[8] = mac[0] ^ 0x02;
+    addr[9] = mac[1];
+    addr[10] = mac[2];
+    addr[11] = 0xff;
+    addr[12] = 0xfe;
+    addr[13..16].copy_from_slice(&mac[3..6]);
+    return addr;
+}
+
+fn DisableIpv6Path(ifname: &str) -> String {
+    return format!("/proc/sys/net/ipv6/conf/{}/disable_ipv6", ifname);
+}
+
+// FirstInterface returns the first interface except loopback
+fn FirstInterface() -> Option<String> {
+    let mut names: Vec<String> = fs::read_dir("/sys/class/net")
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| name != "lo")
+        .collect();
+    names.sort();
+    return names.into_iter().next();
+}
+
+pub fn InitNetstackNic() -> Result<NetstackNicInfo> {
+    let (ifname, gateway) = match DefaultRoute() {
+        Some(route) => route,
+        None => match FirstInterface() {
+            Some(ifname) => (ifname, 0),
+            None => {
+                return Err(Error::Common(format!(
+                    "netstack can't find the network interface"
+                )))
+            }
+        },
+    };
+
+    let ctlSock = ControlSocket(libc::AF_INET)?;
+    defer!(unsafe {
+        libc::close(ctlSock);
+    });
+
+    let mut nic = NetstackNicInfo::default();
+
+    let mut req = IfReq::New(&ifname);
+    Ioctl(ctlSock, libc::SIOCGIFADDR, &mut req)?;
+    nic.addr = req.Ipv4Addr();
+
+    let mut req = IfReq::New(&ifname);
+    Ioctl(ctlSock, libc::SIOCGIFNETMASK, &mut req)?;
+    let netmask = req.Ipv4Addr();
+    nic.prefixLen = netmask.count_ones() as u8;
+
+    let mut req = IfReq::New(&ifname);
+    Ioctl(ctlSock, libc::SIOCGIFHWADDR, &mut req)?;
+    // sa_data of ifr_hwaddr
+    nic.mac.copy_from_slice(&req.data[2..8]);
+
+    let mut req = IfReq::New(&ifname);
+    Ioctl(ctlSock, libc::SIOCGIFMTU, &mut req)?;
+    nic.mtu = req.I32() as u32;
+
+    let mut req = IfReq::New(&ifname);
+    Ioctl(ctlSock, libc::SIOCGIFINDEX, &mut req)?;
+    let ifindex = req.I32();
+
+    nic.gateway = gateway;
+    nic.ifindex = ifindex;
+    let len = ifname.len().min(nic.ifname.len() - 1);
+    nic.ifname[..len].copy_from_slice(&ifname.as_bytes()[..len]);
+
+    let addrs6 = Ipv6Addrs(&ifname);
+    let gateway6 = Ipv6DefaultRoute(&ifname).unwrap_or([0; 16]);
+    let mut globals6 = Vec::new();
+    if addrs6.len() > 0 {
+        for &(addr, prefixLen, scope) in &addrs6 {
+            if scope == IPV6_ADDR_LINKLOCAL {
+                if nic.linkLocal6.IsAny() {
+                    nic.linkLocal6 = IpAddr(addr);
+                }
+            } else if scope == 0 {
+                if nic.addr6.IsAny() {
+                    nic.addr6 = IpAddr(addr);
+                    nic.prefixLen6 = prefixLen;
+                }
+                globals6.push((addr, prefixLen));
+            }
+        }
+
+        // the interface may have only static global addresses
+        if nic.linkLocal6.IsAny() {
+            nic.linkLocal6 = IpAddr(Eui64LinkLocal(&nic.mac));
+        }
+        nic.gateway6 = IpAddr(gateway6);
+    }
+
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            ETH_P_ALL.to_be() as i32,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::SysError(errno::errno().0));
+    }
+
+    let mut addr: libc::sockaddr_ll = unsafe { core::mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = ETH_P_ALL.to_be();
+    addr.sll_ifindex = ifindex;
+    let ret = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const _ as *const libc::sockaddr,
+            core::mem::size_of::<libc::sockaddr_ll>() as u32,
+        )
+    };
+    if ret < 0 {
+        let errno = errno::errno().0;
+        unsafe {
+            libc::close(fd);
+        }
+        return Err(Error::SysError(errno));
+    }
+
+    // the netstack owns the address from now on. Removing the address also
+    // removes the routes through the interface, they are restored together
+    // with the address.
+    *HOST_NIC_CONFIG.lock() = Some(HostNicConfig {
+        ifname: ifname.clone(),
+        ifindex: ifindex,
+        addr: nic.addr,
+        netmask: netmask,
+        gateway: gateway,
+        ipv6: None,
+    });
+
+    let mut req = IfReq::New(&ifname);
+    req.SetIpv4Addr(0);
+    if let Err(e) = Ioctl(ctlSock, libc::SIOCSIFADDR, &mut req) {
+        HOST_NIC_CONFIG.lock().take();
+        unsafe {
+            libc::close(fd);
+        }
+        return Err(e);
+    }
+
+    // the host kernel would answer the neighbor solicitations and reset the
+    // tcp connections of the sandbox if it kept the ipv6 addresses
+    if addrs6.len() > 0 {
+        let path = DisableIpv6Path(&ifname);
+        let res = fs::read_to_string(&path).and_then(|old| {
+            fs::write(&path, "1")?;
+            return Ok(old.trim().to_string());
+        });
+
+        match res {
+            Err(e) => {
+                unsafe {
+                    libc::close(fd);
+                }
+                RestoreNetstackNic();
+                return Err(Error::Common(format!(
+                    "netstack can't disable ipv6 on {}: {:?}",
+                    ifname, e
+                )));
+            }
+            Ok(old) => {
+                if let Some(config) = HOST_NIC_CONFIG.lock().as_mut() {
+                    config.ipv6 = Some(HostNicIpv6Config {
+                        disableIpv6: old,
+                        addrs: globals6,
+                        gateway: gateway6,
+                    });
+                }
+            }
+        }
+    }
+
+    nic.fd = fd;
+    GlobalIOMgr().AddSocket(fd);
+
+    info!("netstack takes over interface {} {:x?}", ifname, &nic);
+    return Ok(nic);
+}
+
+// RestoreNetstackNic puts the configuration taken by InitNetstackNic
+// back to the host interface. It is called on the qvisor exit paths and does
+// nothing when the netstack hasn't taken over the interface.
+pub fn RestoreNetstackNic() {
+    let config = match HOST_NIC_CONFIG.lock().take() {
+        None => return,
+        Some(config) => config,
+    };
+
+    match RestoreHostNic(&config) {
+        Err(e) => error!(
+            "netstack restore interface {:x?} fail with error {:?}",
+            &config, e
+        ),
+        Ok(()) => info!("netstack restores interface {:x?}", &config),
+    }
+
+    if let Some(ipv6) = &config.ipv6 {
+        if let Err(e) = RestoreHostNicIpv6(&config, ipv6) {
+            error!(
+                "netstack restore ipv6 of interface {} fail with error {:?}",
+                &config.ifname, e
+            );
+        }
+    }
+}
+
+// RestoreHostNicIpv6 enables ipv6 again, the kernel brings back the link
+// local address and the autoconfigured ones. The static addresses and the
+// default route are added back here, they may already be back from a router
+// advert.
+fn RestoreHostNicIpv6(config: &HostNicConfig, ipv6: &HostNicIpv6Config) -> Result<()> {
+    if let Err(e) = fs::write(DisableIpv6Path(&config.ifname), &ipv6.disableIpv6) {
+        return Err(Error::Common(format!("write disable_ipv6 fail {:?}", e)));
+    }
+
+    if ipv6.disableIpv6 != "0" {
+        return Ok(());
+    }
+
+    let ctlSock = ControlSocket(libc::AF_INET6)?;
+    defer!(unsafe {
+        libc::close(ctlSock);
+    });
+
+    for &(addr, prefixLen) in &ipv6.addrs {
+        let mut req = In6IfReq {
+            addr: addr,
+            prefixLen: prefixLen as u32,
+            ifindex: config.ifindex,
+        };
+
+        let ret =
+            unsafe { libc::ioctl(ctlSock, libc::SIOCSIFADDR as _, &mut req as *mut In6IfReq) };
+        if ret < 0 {
+            let errno = errno::errno().0;
+            if errno != SysErr::EEXIST {
+                return Err(Error::SysError(errno));
+            }
+        }
+    }
+
+    if ipv6.gateway == [0; 16] {
+        return Ok(());
+    }
+
+    let mut route = In6RtMsg {
+        dst: [0; 16],
+        src: [0; 16],
+        gateway: ipv6.gateway,
+        typ: 0,
+        dstLen: 0,
+        srcLen: 0,
+        metric: IPV6_DEFAULT_ROUTE_METRIC,
+        info: 0,
+        flags: (RTF_UP | RTF_GATEWAY) as u32,
+        ifindex: config.ifindex,
+    };
+
+    let ret = unsafe { libc::ioctl(ctlSock, SIOCADDRT as _, &mut route as *mut In6RtMsg) };
+    if ret < 0 {
+        let errno = errno::errno().0;
+        if errno != SysErr::EEXIST {
+            return Err(Error::SysError(errno));
+        }
+    }
+
+    return Ok(());
+}
+
+fn RestoreHostNic(config: &HostNicConfig) -> Result<()> {
+    let ctlSock = ControlSocket(libc::AF_INET)?;
+    defer!(unsafe {
+        libc::close(ctlSock);
+    });
+
+    let mut req = IfReq::New(&config.ifname);
+    req.SetIpv4Addr(config.addr);
+    Ioctl(ctlSock, libc::SIOCSIFADDR, &mut req)?;
+
+    let mut req = IfReq::New(&config.ifname);
+    req.SetIpv4Addr(config.netmask);
+    Ioctl(ctlSock, libc::SIOCSIFNETMASK, &mut req)?;
+
+    if config.gateway == 0 {
+        return Ok(());
+    }
+
+    let mut dev = config.ifname.clone().into_bytes();
+    dev.push(0);
+    let mut route = RtEntry {
+        pad1: 0,
+        dst: SockAddrIn(0),
+        gateway: SockAddrIn(config.gateway),
+        genmask: SockAddrIn(0),
+        flags: RTF_UP | RTF_GATEWAY,
+        pad2: 0,
+        pad3: 0,
+        tos: 0,
+        class: 0,
+        pad4: [0; 3],
+        metric: 0,
+        dev: dev.as_mut_ptr() as *mut libc::c_char,
+        mtu: 0,
+        window: 0,
+        irtt: 0,
+    };
+
+    let ret = unsafe { libc::ioctl(ctlSock, SIOCADDRT as _, &mut route as *mut RtEntry) };
+    if ret < 0 {
+        let errno = errno::errno().0;
+        if errno != SysErr::EEXIST {
+            return Err(Error::SysError(errno));
+        }
+    }
+
+    return Ok(());
+}
+
+// InitNetstack hands the interface over to the netstack. The sandbox falls
+// back to the host network when the interface can't be taken over.
+pub fn InitNetstack() {
+    if !SHARESPACE.config.read().EnableNetstack {
+        return;
+    }
+
+    match InitNetstackNic() {
+        Err(e) => {
+            error!(
+                "netstack init fail with error {:?}, fall back to the host network",
+                e
+            );
+            SHARESPACE.config.write().EnableNetstack = false;
+        }
+        Ok(nic) => *SHARESPACE.netstackNic.lock() = nic,
+    }
+}
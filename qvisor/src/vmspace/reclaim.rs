@@ -0,0 +1,456 @@
+// Copyright (c) 2021 Quark Container Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::IntoRawFd;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::qlib::common::*;
+use crate::qlib::config::*;
+use crate::qlib::linux_def::*;
+use crate::runc::runtime::vm::IsRunning;
+use crate::vmspace::hibernate::GetRet;
+use crate::GLOBAL_ALLOCATOR;
+use crate::QUARK_CONFIG;
+use crate::RECLAIM_STORE;
+use crate::ROOT_CONTAINER_ID;
+use crate::SHARE_SPACE;
+
+// RECLAIM_SLOT_FLAG is set in the HiberMgr pageMap offset of the pages swapped
+// out to the reclaim swap store, the other offsets are in the hibernate swap file
+pub const RECLAIM_SLOT_FLAG: u64 = 1 << 63;
+
+// the swap store space is allocated in sectors
+pub const RECLAIM_SECTOR_SIZE: u64 = 512;
+
+pub const DEFAULT_RECLAIM_INTERVAL: u64 = 10; // second
+pub const RECLAIM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+pub const RECLAIM_ZSTD_LEVEL: i32 = 3;
+
+// PWriteAll writes the whole buffer at offset, it retries the short writes and
+// EINTR. A write which makes no progress fails with EIO.
+pub fn PWriteAll(fd: i32, buf: &[u8], offset: u64) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let ret = unsafe {
+            libc::pwrite(
+                fd,
+                buf[done..].as_ptr() as _,
+                buf.len() - done,
+                (offset + done as u64) as _,
+            )
+        };
+
+        match GetRet(ret as _) {
+            Ok(0) => return Err(Error::SysError(SysErr::EIO)),
+            Ok(count) => done += count as usize,
+            Err(Error::SysError(e)) if e == -SysErr::EINTR => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    return Ok(());
+}
+
+// PReadAll fills the whole buffer from offset, it retries the short reads and
+// EINTR. A read which hits the end of the file fails with EIO.
+pub fn PReadAll(fd: i32, buf: &mut [u8], offset: u64) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let ret = unsafe {
+            libc::pread(
+                fd,
+                buf[done..].as_mut_ptr() as _,
+                buf.len() - done,
+                (offset + done as u64) as _,
+            )
+        };
+
+        match GetRet(ret as _) {
+            Ok(0) => return Err(Error::SysError(SysErr::EIO)),
+            Ok(count) => done += count as usize,
+            Err(Error::SysError(e)) if e == -SysErr::EINTR => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    return Ok(());
+}
+
+// GetRandom fills the buffer with random bytes, it retries the short reads
+pub fn GetRandom(buf: &mut [u8]) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let ret = unsafe { libc::getrandom(buf[done..].as_mut_ptr() as _, buf.len() - done, 0) };
+        match GetRet(ret as _) {
+            Ok(count) => done += count as usize,
+            Err(Error::SysError(e)) if e == -SysErr::EINTR => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    return Ok(());
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapRecord {
+    pub len: usize,       // the stored data length
+    pub sectors: u64,     // the allocated sector count
+    pub compressed: bool, // whether the data is compressed
+    pub nonce: u64,       // the encryption nonce, 0 if the data is not encrypted
+}
+
+// ReclaimStore is the per sandbox swap store of the reclaimed pages. The pages
+// are compressed and optionally encrypted with a random key of the sandbox.
+#[derive(Default)]
+pub struct ReclaimStore {
+    pub fd: Option<i32>, // None until the first page is swapped out
+    pub path: String,
+    pub size: u64,                          // the file end offset
+    pub records: BTreeMap<u64, SwapRecord>, // file offset --> record
+    pub freeSlots: BTreeMap<u64, Vec<u64>>, // sector count --> free file offsets
+    pub compression: ReclaimCompression,
+    pub cipher: Option<Aes256Gcm>,
+    pub nextNonce: u64,
+    pub storedBytes: u64,
+}
+
+impl ReclaimStore {
+    pub fn Init(&mut self) -> Result<()> {
+        let config = *QUARK_CONFIG.lock();
+
+        let mut dir = config.ReclaimSwapPath.Str().to_string();
+        if dir.len() == 0 {
+            dir = DEFAULT_RECLAIM_SWAP_PATH.to_string();
+        }
+        fs::create_dir_all(&dir)
+            .map_err(|e| Error::Common(format!("create reclaim swap path {} fail {:?}", dir, e)))?;
+
+        let mut id = ROOT_CONTAINER_ID.lock().clone();
+        if id.len() == 0 {
+            id = format!("{}", std::process::id());
+        }
+
+        let path = format!("{}/{}.swap", dir, id);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| Error::Common(format!("open reclaim swap store {} fail {:?}", path, e)))?;
+
+        if config.ReclaimEncrypt {
+            let mut key = [0u8; 32];
+            GetRandom(&mut key)?;
+            self.cipher = Some(Aes256Gcm::new_from_slice(&key).unwrap());
+        }
+
+        self.fd = Some(file.into_raw_fd());
+        self.path = path;
+        self.compression = config.ReclaimCompression;
+        info!(
+            "reclaim swap store {} compression {:?} encrypt {}",
+            self.path, self.compression, config.ReclaimEncrypt
+        );
+        return Ok(());
+    }
+
+    pub fn Close(&mut self) {
+        let fd = match self.fd.take() {
+            None => return,
+            Some(fd) => fd,
+        };
+
+        unsafe {
+            libc::close(fd);
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+
+    pub fn Fd(&self) -> Result<i32> {
+        match self.fd {
+            None => return Err(Error::SysError(SysErr::EBADF)),
+            Some(fd) => return Ok(fd),
+        }
+    }
+
+    pub fn AllocateSlot(&mut self, sectors: u64) -> u64 {
+        match self.freeSlots.get_mut(&sectors) {
+            Some(slots) => match slots.pop() {
+                Some(offset) => return offset,
+                None => (),
+            },
+            None => (),
+        }
+
+        let offset = self.size;
+        self.size += sectors * RECLAIM_SECTOR_SIZE;
+        return offset;
+    }
+
+    pub fn FreeSlot(&mut self, offset: u64, sectors: u64) -> Result<()> {
+        if self.records.len() == 0 {
+            // all the pages are swapped in, shrink the file
+            let ret = unsafe { libc::ftruncate(self.Fd()?, 0) };
+            GetRet(ret as _)?;
+            self.size = 0;
+            self.freeSlots.clear();
+            return Ok(());
+        }
+
+        self.freeSlots
+            .entry(sectors)
+            .or_insert_with(Vec::new)
+            .push(offset);
+        return Ok(());
+    }
+
+    pub fn Nonce(nonce: u64) -> [u8; 12] {
+        let mut ret = [0u8; 12];
+        ret[..8].copy_from_slice(&nonce.to_le_bytes());
+        return ret;
+    }
+
+    pub fn Compress(&self, page: &[u8]) -> Option<Vec<u8>> {
+        match self.compression {
+            ReclaimCompression::None => return None,
+            ReclaimCompression::Lz4 => return Some(lz4_flex::block::compress(page)),
+            ReclaimCompression::Zstd => return zstd::bulk::compress(page, RECLAIM_ZSTD_LEVEL).ok(),
+        }
+    }
+
+    pub fn Decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let size = MemoryDef::PAGE_SIZE_4K as usize;
+        match self.compression {
+            ReclaimCompression::None => {
+                return Err(Error::Common(
+                    "reclaim swap store is not compressed".to_string(),
+                ))
+            }
+            ReclaimCompression::Lz4 => {
+                return lz4_flex::block::decompress(data, size)
+                    .map_err(|e| Error::Common(format!("reclaim lz4 decompress fail {:?}", e)))
+            }
+            ReclaimCompression::Zstd => {
+                return zstd::bulk::decompress(data, size)
+                    .map_err(|e| Error::Common(format!("reclaim zstd decompress fail {:?}", e)))
+            }
+        }
+    }
+
+    // input: memory page address
+    // ret: file offset
+    pub fn SwapOutPage(&mut self, addr: u64) -> Result<u64> {
+        if self.fd.is_none() {
+            self.Init()?;
+        }
+
+        let page =
+            unsafe { slice::from_raw_parts(addr as *const u8, MemoryDef::PAGE_SIZE_4K as _) };
+        let (mut data, compressed) = match self.Compress(page) {
+            Some(data) if data.len() < page.len() => (data, true),
+            _ => (page.to_vec(), false),
+        };
+
+        let mut nonce = 0;
+        if let Some(cipher) = &self.cipher {
+            self.nextNonce += 1;
+            nonce = self.nextNonce;
+            data = cipher
+                .encrypt(Nonce::from_slice(&Self::Nonce(nonce)), data.as_ref())
+                .map_err(|e| Error::Common(format!("reclaim encrypt fail {:?}", e)))?;
+        }
+
+        let sectors = (data.len() as u64 + RECLAIM_SECTOR_SIZE - 1) / RECLAIM_SECTOR_SIZE;
+        let offset = self.AllocateSlot(sectors);
+        match PWriteAll(self.Fd()?, &data, offset) {
+            Ok(()) => (),
+            Err(e) => {
+                // the page stays in memory, the slot is reused
+                self.freeSlots
+                    .entry(sectors)
+                    .or_insert_with(Vec::new)
+                    .push(offset);
+                return Err(e);
+            }
+        }
+
+        // the page has been written out, it is swapped out even if the memory
+        // can't be released
+        let ret =
+            unsafe { libc::madvise(addr as _, MemoryDef::PAGE_SIZE_4K as _, libc::MADV_DONTNEED) };
+        if let Err(e) = GetRet(ret as _) {
+            error!("reclaim madvise page {:x} fail {:?}", addr, e);
+        }
+
+        self.storedBytes += data.len() as u64;
+        self.records.insert(
+            offset,
+            SwapRecord {
+                len: data.len(),
+                sectors: sectors,
+                compressed: compressed,
+                nonce: nonce,
+            },
+        );
+        return Ok(offset);
+    }
+
+    pub fn DropPage(&mut self, offset: u64) -> Result<()> {
+        let record = match self.records.remove(&offset) {
+            None => return Err(Error::SysError(SysErr::EINVAL)),
+            Some(r) => r,
+        };
+
+        self.storedBytes -= record.len as u64;
+        return self.FreeSlot(offset, record.sectors);
+    }
+
+    // input: memory page address, file offset
+    pub fn SwapInPage(&mut self, addr: u64, offset: u64) -> Result<()> {
+        let record = match self.records.get(&offset) {
+            None => return Err(Error::SysError(SysErr::EINVAL)),
+            Some(r) => *r,
+        };
+
+        let mut data = vec![0u8; record.len];
+        PReadAll(self.Fd()?, &mut data, offset)?;
+
+        if record.nonce != 0 {
+            let cipher = self.cipher.as_ref().unwrap();
+            data = cipher
+                .decrypt(Nonce::from_slice(&Self::Nonce(record.nonce)), data.as_ref())
+                .map_err(|e| Error::Common(format!("reclaim decrypt fail {:?}", e)))?;
+        }
+
+        if record.compressed {
+            data = self.Decompress(&data)?;
+        }
+
+        if data.len() != MemoryDef::PAGE_SIZE_4K as usize {
+            error!(
+                "reclaim swap in page {:x} get {} bytes from offset {:x}",
+                addr,
+                data.len(),
+                offset
+            );
+            return Err(Error::SysError(SysErr::EIO));
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), addr as *mut u8, data.len());
+        }
+
+        return self.DropPage(offset);
+    }
+}
+
+// Reclaimer swaps out the cold user pages of the running sandbox when all the
+// application vcpus are halted, so an idle sandbox shrinks without hibernate.
+// The accessed bit of the user pages is cleared in each round and the pages
+// which are not accessed in the next round are swapped out.
+#[derive(Default)]
+pub struct Reclaimer {
+    // gate blocks the halted vcpus from returning to the guest when the
+    // reclaimer is updating the page tables
+    pub gate: Mutex<()>,
+
+    // waitingVcpus is the number of vcpus halted in the host
+    pub waitingVcpus: AtomicUsize,
+}
+
+pub struct VcpuWaitGuard<'a>(&'a Reclaimer);
+
+impl<'a> Drop for VcpuWaitGuard<'a> {
+    fn drop(&mut self) {
+        let _gate = self.0.gate.lock().unwrap();
+        self.0.waitingVcpus.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Reclaimer {
+    // EnterWait is called when the vcpu halts in the host, the vcpu can't
+    // return to the guest before the guard is dropped
+    pub fn EnterWait(&self) -> VcpuWaitGuard {
+        self.waitingVcpus.fetch_add(1, Ordering::SeqCst);
+        return VcpuWaitGuard(self);
+    }
+
+    pub fn Reclaim(&self) -> Result<u64> {
+        let _gate = self.gate.lock().unwrap();
+
+        // all the vcpus except the io vcpu have to be halted
+        if self.waitingVcpus.load(Ordering::SeqCst) + 1 < SHARE_SPACE.scheduler.vcpuCnt {
+            return Ok(0);
+        }
+
+        if SHARE_SPACE.hibernatePause.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+
+        // the host may be working on the guest messages of the halted vcpus,
+        // skip the round instead of racing with their buffers
+        if SHARE_SPACE.HostProcessor() > 0 || !SHARE_SPACE.QOutput.is_empty() {
+            return Ok(0);
+        }
+
+        let (heapStart, heapEnd) = GLOBAL_ALLOCATOR.HeapRange();
+        return SHARE_SPACE
+            .hiberMgr
+            .ReclaimColdPages(heapStart, heapEnd - heapStart);
+    }
+
+    pub fn Process(&self) {
+        let mut interval = QUARK_CONFIG.lock().ReclaimInterval;
+        if interval == 0 {
+            interval = DEFAULT_RECLAIM_INTERVAL;
+        }
+        let interval = Duration::from_secs(interval);
+
+        let mut last = Instant::now();
+        while IsRunning() {
+            thread::sleep(RECLAIM_POLL_INTERVAL);
+            if last.elapsed() < interval {
+                continue;
+            }
+
+            last = Instant::now();
+            match self.Reclaim() {
+                Ok(0) => (),
+                Ok(cnt) => {
+                    let store = RECLAIM_STORE.lock();
+                    info!(
+                        "reclaim {} cold pages, swap store {} pages {} bytes",
+                        cnt,
+                        store.records.len(),
+                        store.storedBytes
+                    );
+                }
+                Err(e) => error!("reclaim cold pages fail {:?}", e),
+            }
+        }
+    }
+}
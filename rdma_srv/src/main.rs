@@ -77,6 +77,7 @@ pub mod rdma_conn;
 pub mod rdma_ctrlconn;
 pub mod rdma_def;
 pub mod rdma_srv;
+pub mod rdma_transport;
 pub mod unix_socket_def;
 
 pub mod common;
@@ -170,7 +171,9 @@ pub const IO_WAIT_CYCLES: i64 = 100_000_000; // 1ms
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("RDMA Service is starting!");
-    RDMA.Init("", 1);
+    if let Err(e) = RDMA.Init("", 1) {
+        return Err(format!("RDMA init fail: {:?}", e).into());
+    }
     let hostname_os = hostname::get()?;
     match hostname_os.into_string() {
         Ok(v) => RDMA_CTLINFO.hostname_set(v),
@@ -545,15 +548,30 @@ fn HandleEvents(epoll_fd: i32, events: &Vec<EpollEvent>, hostname: &String) -> R
 
                 epoll_add(epoll_fd, stream_fd, read_write_event(stream_fd as u64))?;
             }
-            Srv_FdType::TCPSocketConnect(ipAddr) => match RDMA_SRV.conns.lock().get(&ipAddr) {
-                Some(rdmaConn) => {
-                    // println!("TCPSocketConnect, ipAddr: {}", ipAddr);
-                    rdmaConn.Notify(ev.Events as u64);
-                }
-                _ => {
-                    panic!("no RDMA connection for {} found!", ipAddr)
+            Srv_FdType::TCPSocketConnect(ipAddr) => {
+                let ipAddr = *ipAddr;
+                let rdmaConn = match RDMA_SRV.conns.lock().get(&ipAddr) {
+                    Some(rdmaConn) => rdmaConn.clone(),
+                    _ => {
+                        panic!("no RDMA connection for {} found!", ipAddr)
+                    }
+                };
+                // println!("TCPSocketConnect, ipAddr: {}", ipAddr);
+                rdmaConn.Notify(ev.Events as u64);
+                if rdmaConn.SocketState() == SocketState::Error {
+                    // the transport connection is broken, drop it together
+                    // with its queue pairs so that a new one can be set up
+                    error!("TCPSocketConnect, remove broken connection to {}", ipAddr);
+                    RDMA_SRV.conns.lock().remove(&ipAddr);
+                    for qp in rdmaConn.GetQueuePairs() {
+                        RDMA_SRV.controlChannels.lock().remove(&qp.qpNum());
+                        RDMA_SRV.controlChannels2.lock().remove(&qp.qpNum());
+                    }
+                    fds.remove(&rdmaConn.fd);
+                    epoll_delete(epoll_fd, rdmaConn.fd)?;
+                    close(rdmaConn.fd);
                 }
-            },
+            }
             Srv_FdType::UnixDomainSocketServer(_srv_sock) => {
                 // println!("UnixDomainSocketServer");
                 let conn_sock = UnixSocket::Accept(ev.U64 as i32).unwrap();
@@ -7,10 +7,12 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::mem::MaybeUninit;
 use std::ptr;
+use std::sync::Arc;
 
 use super::qlib::common::*;
 use super::qlib::linux_def::*;
 use super::rdma_srv::RDMA_SRV;
+use super::rdma_transport::*;
 // use super::qlib::kernel::TSC;
 //use super::super::super::IO_MGR;
 
@@ -378,8 +380,13 @@ pub const MAX_SEND_SGE: u32 = 1;
 pub const MAX_RECV_SGE: u32 = 1;
 
 impl RDMAContext {
-    pub fn Init(&self, deviceName: &str, ibPort: u8) {
+    pub fn Init(&self, deviceName: &str, ibPort: u8) -> Result<()> {
+        if RDMA_TRANSPORT.IsTCP() {
+            info!("RDMA transport is TCP, skip IB device init");
+            return TCP_TRANSPORT.Init();
+        }
         *self.0.lock() = RDMAContextIntern::New(deviceName, ibPort);
+        return Ok(());
     }
 
     pub fn Lid(&self) -> u16 {
@@ -421,7 +428,7 @@ impl RDMAContext {
             return Err(Error::SysError(errno::errno().0));
         }
 
-        return Ok(AddressHandler(Mutex::new(ah)));
+        return Ok(AddressHandler::Verbs(Mutex::new(ah)));
     }
 
     // Create Queue Pair
@@ -453,18 +460,33 @@ impl RDMAContext {
             return Err(Error::SysError(errno::errno().0));
         }
 
-        return Ok(QueuePair(Mutex::new(qp)));
+        return Ok(QueuePair(Arc::new(VerbsQueuePair(Mutex::new(qp)))));
     }
 
-    pub fn CreateRCQueuePair(&self) -> Result<QueuePair> {
+    // fd is the TCP connection to the peer node, the TCP transport carries the
+    // queue pair traffic over it
+    pub fn CreateRCQueuePair(&self, fd: i32) -> Result<QueuePair> {
+        if RDMA_TRANSPORT.IsTCP() {
+            return Ok(QueuePair(Arc::new(TcpQueuePair::New(fd))));
+        }
         self.CreateQueuePair(rdmaffi::ibv_qp_type::IBV_QPT_RC)
     }
 
     pub fn CreateUDQueuePair(&self) -> Result<QueuePair> {
+        if RDMA_TRANSPORT.IsTCP() {
+            let qp = Arc::new(TcpQueuePair::New(-1));
+            TCP_TRANSPORT.SetUDQueuePair(qp.clone());
+            return Ok(QueuePair(qp));
+        }
         self.CreateQueuePair(rdmaffi::ibv_qp_type::IBV_QPT_UD)
     }
 
     pub fn CreateMemoryRegion(&self, addr: u64, size: usize) -> Result<MemoryRegion> {
+        if RDMA_TRANSPORT.IsTCP() {
+            let key = TCP_TRANSPORT.RegisterMemoryRegion(addr, size as u64);
+            return Ok(MemoryRegion::TCP(key));
+        }
+
         let context = self.lock();
         let access = rdmaffi::ibv_access_flags::IBV_ACCESS_LOCAL_WRITE
             | rdmaffi::ibv_access_flags::IBV_ACCESS_REMOTE_WRITE
@@ -484,7 +506,7 @@ impl RDMAContext {
             return Err(Error::SysError(errno::errno().0));
         }
 
-        return Ok(MemoryRegion(mr));
+        return Ok(MemoryRegion::Verbs(mr));
     }
 
     pub fn CompleteQueue(&self) -> *mut rdmaffi::ibv_cq {
@@ -496,6 +518,9 @@ impl RDMAContext {
     }
 
     pub fn CompleteChannelFd(&self) -> i32 {
+        if RDMA_TRANSPORT.IsTCP() {
+            return TCP_TRANSPORT.EventFd();
+        }
         let fd = self.lock().ccfd;
         // println!("XXXX, fd: {} ", fd);
         return fd;
@@ -506,6 +531,15 @@ impl RDMAContext {
         channels: &mut HashMap<u32, HashSet<u32>>,
     ) -> usize {
         // println!("PollCompletionQueueAndProcess");
+        if RDMA_TRANSPORT.IsTCP() {
+            let mut count = 0;
+            while let Some(wc) = TCP_TRANSPORT.PollCompletion() {
+                count += 1;
+                self.ProcessWorkCompletion(&wc, channels);
+            }
+            return count;
+        }
+
         let mut wc = rdmaffi::ibv_wc {
             //TODO: find a better way to initialize
             wr_id: 0,
@@ -570,6 +604,11 @@ impl RDMAContext {
     // }
 
     pub fn HandleCQEvent(&self) -> Result<()> {
+        if RDMA_TRANSPORT.IsTCP() {
+            TCP_TRANSPORT.AckEvent();
+            return Ok(());
+        }
+
         let mut cq_ptr: *mut rdmaffi::ibv_cq = ptr::null_mut();
         let mut cq_context: *mut std::os::raw::c_void = ptr::null_mut();
         let ret = unsafe {
@@ -595,6 +634,14 @@ impl RDMAContext {
     }
 
     pub fn PollCompletion(&self) -> Result<()> {
+        if RDMA_TRANSPORT.IsTCP() {
+            TCP_TRANSPORT.AckEvent();
+            while let Some(wc) = TCP_TRANSPORT.PollCompletion() {
+                self.ProcessWorkCompletion(&wc, &mut HashMap::new());
+            }
+            return Ok(());
+        }
+
         let mut wc = rdmaffi::ibv_wc {
             //TODO: find a better way to initialize
             wr_id: 0,
@@ -726,74 +773,57 @@ impl RDMAContext {
                 wc.status, wc.wr_id
             );
         }
-        if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_RDMA_WRITE {
-            // debug!(
-            //     "ProcessWC::2, writeIMM status: {}, id: {}",
-            //     wc.status, wc.wr_id
-            // );
-            //IO_MGR.ProcessRDMAWriteImmFinish(fd);
-            // channels.insert(wc.wr_id as u32);
-            // let channelId = wc.wr_id as u32 & 0x7FFFFFFF;
-            // if channelId != 0 {
-            //     if channels.contains_key(&wc.qp_num) {
-            //         channels.get_mut(&wc.qp_num).unwrap().insert(channelId);
-            //     }
-            //     else {
-            //         channels.insert(wc.qp_num, vec![channelId].into_iter().collect());
-            //     }
-            // }
-            // println!("qq1: IBV_WC_RDMA_WRITE");
-            // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
-            // let len = RDMA_SRV.timestamps.lock().len();
-            // let mut i = 1;
-            // let mut v1 = RDMA_SRV.timestamps.lock()[0];
-            // let mut v2 = RDMA_SRV.timestamps.lock()[1];
-            // println!("qq, Handle connect request time is: {}", RDMA_SRV.timestamps.lock()[len - 1] - v1);
-            // loop {
-            //     println!("{}", v2 - v1);
-            //     i += 1;
-            //     if i == len {
-            //         break;
-            //     }
-            //     v1 = v2;
-            //     v2 = RDMA_SRV.timestamps.lock()[i];            
-            // }
-            // RDMA_SRV.timestamps.lock().clear();
-
-            RDMA_SRV.ProcessRDMAWriteImmFinish(wc.wr_id as u32, wc.qp_num);
+        let op = if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_RDMA_WRITE {
+            WorkCompletionOp::RDMAWrite
         } else if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_RECV_RDMA_WITH_IMM {
-            let imm = unsafe { wc.imm_data_invalidated_rkey_union.imm_data };
-            // error!("ProcessWC. received len: {}, qpNum: {}", wc.byte_len, wc.qp_num);
-            let immData = ImmData(imm);
-            // debug!(
-            //     "ProcessWC::2, recv len:{}, writelen: {}, status: {}, id: 0x{:x}",
-            //     wc.byte_len,
-            //     immData.ReadCount(),
-            //     wc.status,
-            //     wc.wr_id
-            // );
-            //IO_MGR.ProcessRDMARecvWriteImm(fd, wc.byte_len as _, immData.ReadCount() as _);
-            // debug!("ProcessWC imm: {}", imm);
-            // channels.insert(imm);
-            let channelId = imm & 0x7FFFFFFF;
-            if channelId != 0 {
-                if channels.contains_key(&wc.qp_num) {
-                    channels.get_mut(&wc.qp_num).unwrap().insert(channelId);
-                } else {
-                    channels.insert(wc.qp_num, vec![channelId].into_iter().collect());
-                }
-            }
-            // println!("qq1: IBV_WC_RECV_RDMA_WITH_IMM");
-            // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
-            RDMA_SRV.ProcessRDMARecvWriteImm(immData.ReadCount() as _, wc.qp_num, wc.byte_len as _);
+            WorkCompletionOp::RecvRDMAWithImm
         } else if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_RECV {
-            // error!("ProcessWC::3");
-            RDMA_SRV.ProcessRDMARecv(wc.qp_num, wc.wr_id, wc.byte_len);
-        }else if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_SEND {
-            // error!("ProcessWC::4");
-            RDMA_SRV.ProcessRDMASend(wc.wr_id);
+            WorkCompletionOp::Recv
+        } else if wc.opcode == rdmaffi::ibv_wc_opcode::IBV_WC_SEND {
+            WorkCompletionOp::Send
         } else {
             // debug!("ProcessWC::5, opcode: {}, wr_id: {}", wc.opcode, wc.wr_id);
+            return;
+        };
+
+        let wc = WorkCompletion {
+            wrId: wc.wr_id,
+            op: op,
+            qpNum: wc.qp_num,
+            imm: unsafe { wc.imm_data_invalidated_rkey_union.imm_data },
+            byteLen: wc.byte_len,
+        };
+        self.ProcessWorkCompletion(&wc, channels);
+    }
+
+    // dispatch the work completion from either the RDMA CQ or the TCP transport
+    pub fn ProcessWorkCompletion(
+        &self,
+        wc: &WorkCompletion,
+        channels: &mut HashMap<u32, HashSet<u32>>,
+    ) {
+        match wc.op {
+            WorkCompletionOp::RDMAWrite => {
+                RDMA_SRV.ProcessRDMAWriteImmFinish(wc.wrId as u32, wc.qpNum);
+            }
+            WorkCompletionOp::RecvRDMAWithImm => {
+                let immData = ImmData(wc.imm);
+                let channelId = wc.imm & 0x7FFFFFFF;
+                if channelId != 0 {
+                    if channels.contains_key(&wc.qpNum) {
+                        channels.get_mut(&wc.qpNum).unwrap().insert(channelId);
+                    } else {
+                        channels.insert(wc.qpNum, vec![channelId].into_iter().collect());
+                    }
+                }
+                RDMA_SRV.ProcessRDMARecvWriteImm(immData.ReadCount() as _, wc.qpNum, wc.byteLen as _);
+            }
+            WorkCompletionOp::Recv => {
+                RDMA_SRV.ProcessRDMARecv(wc.qpNum, wc.wrId, wc.byteLen);
+            }
+            WorkCompletionOp::Send => {
+                RDMA_SRV.ProcessRDMASend(wc.wrId);
+            }
         }
     }
 }
@@ -843,11 +873,15 @@ impl WorkRequestId {
     // }
 }
 
-pub struct AddressHandler(pub Mutex<*mut rdmaffi::ibv_ah>);
+pub enum AddressHandler {
+    Verbs(Mutex<*mut rdmaffi::ibv_ah>),
+    // the TCP transport reaches the remote UD QP through the RC connection
+    TCP(Arc<TcpQueuePair>),
+}
 
 impl Default for AddressHandler {
     fn default() -> Self {
-        return Self(Mutex::new(0 as _));
+        return Self::Verbs(Mutex::new(0 as _));
     }
 }
 
@@ -860,35 +894,18 @@ impl Drop for AddressHandler {
 
 impl AddressHandler {
     pub fn Data(&self) -> *mut rdmaffi::ibv_ah {
-        return *self.0.lock();
-    }
-}
-
-pub struct QueuePair(pub Mutex<*mut rdmaffi::ibv_qp>);
-
-impl Default for QueuePair {
-    fn default() -> Self {
-        return Self(Mutex::new(0 as _));
+        match self {
+            Self::Verbs(ah) => return *ah.lock(),
+            Self::TCP(_) => return ptr::null_mut(),
+        }
     }
 }
 
-unsafe impl Send for QueuePair {}
-unsafe impl Sync for QueuePair {}
-
-impl Drop for QueuePair {
-    fn drop(&mut self) {}
-}
-
-impl QueuePair {
-    pub fn Data(&self) -> *mut rdmaffi::ibv_qp {
-        return *self.0.lock();
-    }
-
-    pub fn qpNum(&self) -> u32 {
-        return unsafe { (*self.Data()).qp_num };
-    }
-
-    pub fn WriteImm(
+// the queue pair of a transport, VerbsQueuePair on the RDMA NIC or
+// TcpQueuePair when the traffic is carried over TCP connections
+pub trait QueuePairOps: Send + Sync {
+    fn qpNum(&self) -> u32;
+    fn WriteImm(
         &self,
         wrId: u64,
         laddr: u64,
@@ -897,77 +914,9 @@ impl QueuePair {
         raddr: u64,
         rkey: u32,
         imm: u32,
-    ) -> Result<()> {
-        let opcode = rdmaffi::ibv_wr_opcode::IBV_WR_RDMA_WRITE_WITH_IMM;
-        let mut sge = rdmaffi::ibv_sge {
-            addr: laddr,
-            length: len,
-            lkey: lkey,
-        };
-
-        let mut sw = rdmaffi::ibv_send_wr {
-            wr_id: wrId,
-            next: ptr::null_mut(),
-            sg_list: &mut sge,
-            num_sge: 1,
-            opcode: opcode,
-            send_flags: rdmaffi::ibv_send_flags::IBV_SEND_SIGNALED.0,
-            imm_data_invalidated_rkey_union: rdmaffi::imm_data_invalidated_rkey_union_t {
-                imm_data: imm,
-            }, //TODO: need double check
-            qp_type: rdmaffi::qp_type_t {
-                xrc: rdmaffi::xrc_t { remote_srqn: 0 },
-            },
-            wr: rdmaffi::wr_t {
-                rdma: rdmaffi::rdma_t {
-                    //TODO: this is not needed when opcode is IBV_WR_SEND
-                    remote_addr: raddr,
-                    rkey: rkey,
-                },
-            },
-            bind_mw_tso_union: rdmaffi::bind_mw_tso_union_t {
-                //TODO: need a better init solution
-                tso: rdmaffi::tso_t {
-                    hdr: ptr::null_mut(),
-                    hdr_sz: 0,
-                    mss: 0,
-                },
-            },
-        };
-
-        let mut bad_wr: *mut rdmaffi::ibv_send_wr = ptr::null_mut();
-
-        // println!("qq1: WriteImm, before post_send");
-        // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
-        let rc = unsafe { rdmaffi::ibv_post_send(self.Data(), &mut sw, &mut bad_wr) };
-        // println!("qq1: WriteImm, after post_send");
-        // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
-        if rc != 0 {
-            return Err(Error::SysError(errno::errno().0));
-        }
-        return Ok(());
-    }
-
-    pub fn PostRecv(&self, wrId: u64, addr: u64, lkey: u32, length: u32) -> Result<()> {
-        let mut sge = rdmaffi::ibv_sge { addr, length, lkey };
-        let mut rw = rdmaffi::ibv_recv_wr {
-            wr_id: wrId,
-            next: ptr::null_mut(),
-            sg_list: &mut sge,
-            num_sge: 1,
-        };
-        let mut bad_wr: *mut rdmaffi::ibv_recv_wr = ptr::null_mut();
-        let rc = unsafe { rdmaffi::ibv_post_recv(self.Data(), &mut rw, &mut bad_wr) };
-        if rc != 0 {
-            return Err(Error::SysError(errno::errno().0));
-        }
-
-        // println!("QP::PostRecv");
-
-        return Ok(());
-    }
-
-    pub fn PostSendUDQP(
+    ) -> Result<()>;
+    fn PostRecv(&self, wrId: u64, addr: u64, lkey: u32, length: u32) -> Result<()>;
+    fn PostSendUDQP(
         &self,
         ah: &AddressHandler,
         remote_qpn: u32,
@@ -975,76 +924,59 @@ impl QueuePair {
         laddr: u64,
         len: u32,
         lkey: u32,
-    ) -> Result<()> {
-        // error!(
-        //     "PostSendUDQP, remote_qpn: {}, wrId: {}, laddr: 0x{:x}, len: {}, lkey: {}",
-        //     remote_qpn, wrId, laddr, len, lkey
-        // );
-        let opcode = rdmaffi::ibv_wr_opcode::IBV_WR_SEND;
-        let mut sge = rdmaffi::ibv_sge {
-            addr: laddr,
-            length: len,
-            lkey: lkey,
-        };
+    ) -> Result<()>;
+    fn SetupRCQP(&self, context: &RDMAContext, remote_qpn: u32, dlid: u16, dgid: Gid)
+        -> Result<()>;
+    fn SetupUDQP(&self, context: &RDMAContext) -> Result<()>;
+    // address handler to reach the UD queue pair of the peer of this RC queue pair
+    fn CreateAddressHandler(
+        self: Arc<Self>,
+        context: &RDMAContext,
+        lid: u16,
+        gid: Gid,
+    ) -> Result<AddressHandler>;
 
-        //TODO: delete!
-        let mut sw = rdmaffi::ibv_send_wr {
-            wr_id: wrId,
-            next: ptr::null_mut(),
-            sg_list: &mut sge,
-            num_sge: 1,
-            opcode: opcode,
-            send_flags: rdmaffi::ibv_send_flags::IBV_SEND_SIGNALED.0,
-            imm_data_invalidated_rkey_union: rdmaffi::imm_data_invalidated_rkey_union_t {
-                imm_data: 0,
-            },
-            qp_type: rdmaffi::qp_type_t {
-                xrc: rdmaffi::xrc_t { remote_srqn: 0 },
-            },
-            wr: rdmaffi::wr_t {
-                ud: rdmaffi::ud_t {
-                    ah: ah.Data(),
-                    remote_qpn,
-                    remote_qkey: 0x11111111,
-                },
-            },
-            bind_mw_tso_union: rdmaffi::bind_mw_tso_union_t {
-                tso: rdmaffi::tso_t {
-                    hdr: ptr::null_mut(),
-                    hdr_sz: 0,
-                    mss: 0,
-                },
-            },
-        };
+    // epoll events of the connection fd, the RDMA NIC doesn't need them
+    fn Notify(&self, _eventmask: EventMask) -> Result<()> {
+        return Ok(());
+    }
+}
 
-        let mut bad_wr: *mut rdmaffi::ibv_send_wr = ptr::null_mut();
+#[derive(Clone)]
+pub struct QueuePair(pub Arc<dyn QueuePairOps>);
 
-        let rc = unsafe { rdmaffi::ibv_post_send(self.Data(), &mut sw, &mut bad_wr) };
-        if rc != 0 {
-            error!("PostSendUDQP, rc: {}", rc);
-            return Err(Error::SysError(errno::errno().0));
-        }
-        return Ok(());
+impl Default for QueuePair {
+    fn default() -> Self {
+        return Self(Arc::new(VerbsQueuePair::default()));
     }
+}
 
-    pub fn SetupRCQP(
-        &self,
-        context: &RDMAContext,
-        remote_qpn: u32,
-        dlid: u16,
-        dgid: Gid,
-    ) -> Result<()> {
-        self.ToInitRCQP(context)?;
-        self.ToRtrRCQP(context, remote_qpn, dlid, dgid)?;
-        self.ToRtsRCQP()?;
-        return Ok(());
+impl Deref for QueuePair {
+    type Target = Arc<dyn QueuePairOps>;
+
+    fn deref(&self) -> &Arc<dyn QueuePairOps> {
+        &self.0
     }
+}
 
-    pub fn SetupUDQP(&self, context: &RDMAContext) -> Result<()> {
-        self.ToInitUDQP(context)?;
-        self.ToRtrUDQP(context)?;
-        self.ToRtsUDQP()?;
-        return Ok(());
+pub struct VerbsQueuePair(pub Mutex<*mut rdmaffi::ibv_qp>);
+
+impl Default for VerbsQueuePair {
+    fn default() -> Self {
+        return Self(Mutex::new(0 as _));
+    }
+}
+
+unsafe impl Send for VerbsQueuePair {}
+unsafe impl Sync for VerbsQueuePair {}
+
+impl Drop for VerbsQueuePair {
+    fn drop(&mut self) {}
+}
+
+impl VerbsQueuePair {
+    pub fn Data(&self) -> *mut rdmaffi::ibv_qp {
+        return *self.0.lock();
     }
 
     pub fn ToInitRCQP(&self, context: &RDMAContext) -> Result<()> {
@@ -1599,30 +1531,219 @@ impl QueuePair {
     }
 }
 
-pub struct MemoryRegion(pub *mut rdmaffi::ibv_mr);
+impl QueuePairOps for VerbsQueuePair {
+    fn qpNum(&self) -> u32 {
+        return unsafe { (*self.Data()).qp_num };
+    }
+
+    fn WriteImm(
+        &self,
+        wrId: u64,
+        laddr: u64,
+        len: u32,
+        lkey: u32,
+        raddr: u64,
+        rkey: u32,
+        imm: u32,
+    ) -> Result<()> {
+        let opcode = rdmaffi::ibv_wr_opcode::IBV_WR_RDMA_WRITE_WITH_IMM;
+        let mut sge = rdmaffi::ibv_sge {
+            addr: laddr,
+            length: len,
+            lkey: lkey,
+        };
+
+        let mut sw = rdmaffi::ibv_send_wr {
+            wr_id: wrId,
+            next: ptr::null_mut(),
+            sg_list: &mut sge,
+            num_sge: 1,
+            opcode: opcode,
+            send_flags: rdmaffi::ibv_send_flags::IBV_SEND_SIGNALED.0,
+            imm_data_invalidated_rkey_union: rdmaffi::imm_data_invalidated_rkey_union_t {
+                imm_data: imm,
+            }, //TODO: need double check
+            qp_type: rdmaffi::qp_type_t {
+                xrc: rdmaffi::xrc_t { remote_srqn: 0 },
+            },
+            wr: rdmaffi::wr_t {
+                rdma: rdmaffi::rdma_t {
+                    //TODO: this is not needed when opcode is IBV_WR_SEND
+                    remote_addr: raddr,
+                    rkey: rkey,
+                },
+            },
+            bind_mw_tso_union: rdmaffi::bind_mw_tso_union_t {
+                //TODO: need a better init solution
+                tso: rdmaffi::tso_t {
+                    hdr: ptr::null_mut(),
+                    hdr_sz: 0,
+                    mss: 0,
+                },
+            },
+        };
+
+        let mut bad_wr: *mut rdmaffi::ibv_send_wr = ptr::null_mut();
+
+        // println!("qq1: WriteImm, before post_send");
+        // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
+        let rc = unsafe { rdmaffi::ibv_post_send(self.Data(), &mut sw, &mut bad_wr) };
+        // println!("qq1: WriteImm, after post_send");
+        // RDMA_SRV.timestamps.lock().push(TSC.Rdtsc());
+        if rc != 0 {
+            return Err(Error::SysError(errno::errno().0));
+        }
+        return Ok(());
+    }
+
+    fn PostRecv(&self, wrId: u64, addr: u64, lkey: u32, length: u32) -> Result<()> {
+        let mut sge = rdmaffi::ibv_sge { addr, length, lkey };
+        let mut rw = rdmaffi::ibv_recv_wr {
+            wr_id: wrId,
+            next: ptr::null_mut(),
+            sg_list: &mut sge,
+            num_sge: 1,
+        };
+        let mut bad_wr: *mut rdmaffi::ibv_recv_wr = ptr::null_mut();
+        let rc = unsafe { rdmaffi::ibv_post_recv(self.Data(), &mut rw, &mut bad_wr) };
+        if rc != 0 {
+            return Err(Error::SysError(errno::errno().0));
+        }
+
+        // println!("QP::PostRecv");
+
+        return Ok(());
+    }
+
+    fn PostSendUDQP(
+        &self,
+        ah: &AddressHandler,
+        remote_qpn: u32,
+        wrId: u64,
+        laddr: u64,
+        len: u32,
+        lkey: u32,
+    ) -> Result<()> {
+        // error!(
+        //     "PostSendUDQP, remote_qpn: {}, wrId: {}, laddr: 0x{:x}, len: {}, lkey: {}",
+        //     remote_qpn, wrId, laddr, len, lkey
+        // );
+        let opcode = rdmaffi::ibv_wr_opcode::IBV_WR_SEND;
+        let mut sge = rdmaffi::ibv_sge {
+            addr: laddr,
+            length: len,
+            lkey: lkey,
+        };
+
+        //TODO: delete!
+        let mut sw = rdmaffi::ibv_send_wr {
+            wr_id: wrId,
+            next: ptr::null_mut(),
+            sg_list: &mut sge,
+            num_sge: 1,
+            opcode: opcode,
+            send_flags: rdmaffi::ibv_send_flags::IBV_SEND_SIGNALED.0,
+            imm_data_invalidated_rkey_union: rdmaffi::imm_data_invalidated_rkey_union_t {
+                imm_data: 0,
+            },
+            qp_type: rdmaffi::qp_type_t {
+                xrc: rdmaffi::xrc_t { remote_srqn: 0 },
+            },
+            wr: rdmaffi::wr_t {
+                ud: rdmaffi::ud_t {
+                    ah: ah.Data(),
+                    remote_qpn,
+                    remote_qkey: 0x11111111,
+                },
+            },
+            bind_mw_tso_union: rdmaffi::bind_mw_tso_union_t {
+                tso: rdmaffi::tso_t {
+                    hdr: ptr::null_mut(),
+                    hdr_sz: 0,
+                    mss: 0,
+                },
+            },
+        };
+
+        let mut bad_wr: *mut rdmaffi::ibv_send_wr = ptr::null_mut();
+
+        let rc = unsafe { rdmaffi::ibv_post_send(self.Data(), &mut sw, &mut bad_wr) };
+        if rc != 0 {
+            error!("PostSendUDQP, rc: {}", rc);
+            return Err(Error::SysError(errno::errno().0));
+        }
+        return Ok(());
+    }
+
+    fn SetupRCQP(
+        &self,
+        context: &RDMAContext,
+        remote_qpn: u32,
+        dlid: u16,
+        dgid: Gid,
+    ) -> Result<()> {
+        self.ToInitRCQP(context)?;
+        self.ToRtrRCQP(context, remote_qpn, dlid, dgid)?;
+        self.ToRtsRCQP()?;
+        return Ok(());
+    }
+
+    fn SetupUDQP(&self, context: &RDMAContext) -> Result<()> {
+        self.ToInitUDQP(context)?;
+        self.ToRtrUDQP(context)?;
+        self.ToRtsUDQP()?;
+        return Ok(());
+    }
+
+    fn CreateAddressHandler(
+        self: Arc<Self>,
+        context: &RDMAContext,
+        lid: u16,
+        gid: Gid,
+    ) -> Result<AddressHandler> {
+        // todo: get port number from the context
+        return context.CreateAddressHandler(1, lid, gid);
+    }
+}
+
+pub enum MemoryRegion {
+    Verbs(*mut rdmaffi::ibv_mr),
+    // the TCP transport uses the same key as lkey and rkey
+    TCP(u32),
+}
+
 impl Drop for MemoryRegion {
     fn drop(&mut self) {
-        unsafe {
-            if self.0 as *const _ as u64 != 0 {
-                let _ret = rdmaffi::ibv_dereg_mr(self.0);
-            }
+        match self {
+            Self::Verbs(mr) => unsafe {
+                if *mr as *const _ as u64 != 0 {
+                    let _ret = rdmaffi::ibv_dereg_mr(*mr);
+                }
+            },
+            Self::TCP(key) => TCP_TRANSPORT.DeregisterMemoryRegion(*key),
         }
     }
 }
 
 impl Default for MemoryRegion {
     fn default() -> Self {
-        return Self(0 as _);
+        return Self::Verbs(0 as _);
     }
 }
 
 impl MemoryRegion {
     pub fn LKey(&self) -> u32 {
-        return unsafe { (*self.0).lkey };
+        match self {
+            Self::Verbs(mr) => return unsafe { (**mr).lkey },
+            Self::TCP(key) => return *key,
+        }
     }
 
     pub fn RKey(&self) -> u32 {
-        return unsafe { (*self.0).rkey };
+        match self {
+            Self::Verbs(mr) => return unsafe { (**mr).rkey },
+            Self::TCP(key) => return *key,
+        }
     }
 }
 
@@ -41,7 +41,7 @@ pub struct RDMAQueuePair {}
 
 pub const RECV_REQUEST_COUNT: u32 = 64;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u64)]
 pub enum SocketState {
     Init,
@@ -112,7 +112,7 @@ impl Drop for RDMAConn {
 
 impl RDMAConn {
     pub fn New(fd: i32, sockBuf: SocketBuff, controlRKey: u32, udpQPNum: u32) -> Self {
-        let rc_qp = RDMA.CreateRCQueuePair().expect("RDMA create RC QP fail");
+        let rc_qp = RDMA.CreateRCQueuePair(fd).expect("RDMA create RC QP fail");
         println!("after create RC qp");
         let (addr, len) = sockBuf.ReadBuf();
         let localRDMAInfo = RDMAInfo {
@@ -189,8 +189,10 @@ impl RDMAConn {
                 )
                 .expect("SetupRDMA PostRecv fail");
         }
-        *self.addressHandler.lock() = RDMA
-            .CreateAddressHandler(1, remoteInfo.lid, remoteInfo.gid) //TODO: port_num is local port num, should be configurable in the future
+        *self.addressHandler.lock() = self.qps[0]
+            .0
+            .clone()
+            .CreateAddressHandler(&RDMA, remoteInfo.lid, remoteInfo.gid)
             .expect("Create AddressHandler fail...");
     }
 
@@ -230,7 +232,9 @@ impl RDMAConn {
             }
             SocketState::Ready => {
                 // println!("Read::Ready, fd:{} ", self.fd);
+                self.NotifyQueuePair(EVENT_READ);
             }
+            SocketState::Error => (),
             _ => {
                 panic!(
                     "RDMA socket read state error with state {:?}",
@@ -256,7 +260,9 @@ impl RDMAConn {
             }
             SocketState::Ready => {
                 // println!("Write::Ready, fd:{} ", self.fd);
+                self.NotifyQueuePair(EVENT_WRITE);
             }
+            SocketState::Error => (),
             _ => {
                 panic!(
                     "RDMA socket Write state error with state {:?}",
@@ -268,7 +274,7 @@ impl RDMAConn {
 
     pub fn SocketState(&self) -> SocketState {
         let state = self.socketState.load(Ordering::Relaxed);
-        assert!(state <= SocketState::Ready as u64);
+        assert!(state <= SocketState::Error as u64);
         let state: SocketState = unsafe { mem::transmute(state) };
         return state;
     }
@@ -277,8 +283,23 @@ impl RDMAConn {
         self.socketState.store(state as u64, Ordering::SeqCst)
     }
 
+    // the transport connection is broken, the owner of the connection
+    // removes it when it sees the Error state
+    pub fn NotifyQueuePair(&self, eventmask: EventMask) {
+        match self.qps[0].Notify(eventmask) {
+            Err(e) => {
+                error!("RDMAConn::NotifyQueuePair, fd: {}, error: {:?}", self.fd, e);
+                self.SetSocketState(SocketState::Error);
+            }
+            Ok(()) => (),
+        }
+    }
+
     pub fn SetReady(&self) {
         self.SetSocketState(SocketState::Ready);
+        // the socket is edge triggered, the TCP transport has to consume the
+        // frames which arrived together with the ack
+        self.NotifyQueuePair(EVENT_READ);
         // println!("Ready!!!");
         //self.ctrlChan.lock().SendData();
         // let laddr = RDMA_SRV.udpMemRegion.addr + 10 * (mem::size_of::<UDPPacket>() + 40) as u64 + 40;
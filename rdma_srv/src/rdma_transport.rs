@@ -0,0 +1,636 @@
+// Copyright (c) 2021 Quark Container Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// TCP transport for rdma_srv.
+//
+// When the node has no RDMA NIC, the queue pairs, memory regions and the
+// completion queue are emulated in software. An RC queue pair is carried over
+// the TCP connection rdma_srv already uses for the RDMAInfo handshake with the
+// peer node, so RDMAChannel, the credit flow and the control channel work the
+// same on both transports. The UD queue pair used for UDP packets sends its
+// frames over the RC connection of the destination node.
+
+use alloc::sync::Arc;
+use core::mem;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use lazy_static::lazy_static;
+use libc::*;
+use spin::Mutex;
+use std::collections::{BTreeMap, VecDeque};
+use std::{env, fs, ptr, slice};
+
+use super::qlib::common::*;
+use super::qlib::linux_def::*;
+use super::rdma::*;
+
+lazy_static! {
+    pub static ref RDMA_TRANSPORT: TransportType = TransportType::Detect();
+    pub static ref TCP_TRANSPORT: TcpTransport = TcpTransport::New();
+}
+
+// environment variable used to force the transport: "tcp" or "rdma"
+pub const RDMA_TRANSPORT_ENV: &str = "QUARK_RDMA_TRANSPORT";
+pub const RDMA_SYSFS_CLASS: &str = "/sys/class/infiniband";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportType {
+    RDMA,
+    TCP,
+}
+
+impl TransportType {
+    pub fn Detect() -> Self {
+        match env::var(RDMA_TRANSPORT_ENV) {
+            Ok(val) => match val.to_lowercase().as_str() {
+                "tcp" => return Self::TCP,
+                "rdma" => return Self::RDMA,
+                _ => {
+                    error!(
+                        "unknown {} value {}, probe RDMA devices instead",
+                        RDMA_TRANSPORT_ENV, val
+                    );
+                }
+            },
+            Err(_) => (),
+        }
+
+        if Self::RDMADeviceCount() > 0 {
+            return Self::RDMA;
+        }
+
+        info!("no RDMA device is found, fall back to TCP transport");
+        return Self::TCP;
+    }
+
+    // count the devices from sysfs as libibverbs does, so that the TCP
+    // transport doesn't depend on the verbs library at all
+    pub fn RDMADeviceCount() -> i32 {
+        match fs::read_dir(RDMA_SYSFS_CLASS) {
+            Ok(entries) => return entries.count() as i32,
+            Err(_) => return 0,
+        }
+    }
+
+    pub fn IsTCP(&self) -> bool {
+        return *self == Self::TCP;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkCompletionOp {
+    RDMAWrite,
+    RecvRDMAWithImm,
+    Recv,
+    Send,
+}
+
+// transport neutral view of a work completion, ibv_wc is converted to it
+// before it is dispatched to RDMA_SRV
+#[derive(Debug, Clone, Copy)]
+pub struct WorkCompletion {
+    pub wrId: u64,
+    pub op: WorkCompletionOp,
+    pub qpNum: u32,
+    pub imm: u32,
+    pub byteLen: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecvRequest {
+    pub wrId: u64,
+    pub addr: u64,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SoftMemoryRegion {
+    pub addr: u64,
+    pub len: u64,
+}
+
+pub struct TcpTransport {
+    pub completeQueue: Mutex<VecDeque<WorkCompletion>>,
+    // signaled when a completion is queued, created by Init
+    eventfd: AtomicI32,
+    pub memoryRegions: Mutex<BTreeMap<u32, SoftMemoryRegion>>,
+    pub udQP: Mutex<Option<Arc<TcpQueuePair>>>,
+    nextKey: AtomicU32,
+    nextQPNum: AtomicU32,
+}
+
+impl TcpTransport {
+    pub fn New() -> Self {
+        return Self {
+            completeQueue: Mutex::new(VecDeque::new()),
+            eventfd: AtomicI32::new(-1),
+            memoryRegions: Mutex::new(BTreeMap::new()),
+            udQP: Mutex::new(None),
+            nextKey: AtomicU32::new(1),
+            nextQPNum: AtomicU32::new(1),
+        };
+    }
+
+    // Init creates the completion eventfd, rdma_srv can't run the transport
+    // without it
+    pub fn Init(&self) -> Result<()> {
+        let fd = unsafe { eventfd(0, EFD_NONBLOCK) };
+        if fd < 0 {
+            let errno = errno::errno().0;
+            error!("TcpTransport::Init, eventfd fail, errno: {}", errno);
+            return Err(Error::SysError(errno));
+        }
+
+        self.eventfd.store(fd, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    pub fn EventFd(&self) -> i32 {
+        return self.eventfd.load(Ordering::SeqCst);
+    }
+
+    pub fn RegisterMemoryRegion(&self, addr: u64, len: u64) -> u32 {
+        let key = self.nextKey.fetch_add(1, Ordering::SeqCst);
+        self.memoryRegions
+            .lock()
+            .insert(key, SoftMemoryRegion { addr, len });
+        return key;
+    }
+
+    pub fn DeregisterMemoryRegion(&self, key: u32) {
+        self.memoryRegions.lock().remove(&key);
+    }
+
+    // check that [addr, addr + len) is covered by the memory region of key
+    pub fn CheckAccess(&self, key: u32, addr: u64, len: u32) -> Result<()> {
+        match self.memoryRegions.lock().get(&key) {
+            Some(mr) => {
+                let end = match addr.checked_add(len as u64) {
+                    Some(end) => end,
+                    None => return Err(Error::SysError(SysErr::EFAULT)),
+                };
+                let mrEnd = match mr.addr.checked_add(mr.len) {
+                    Some(end) => end,
+                    None => return Err(Error::SysError(SysErr::EFAULT)),
+                };
+                if addr >= mr.addr && end <= mrEnd {
+                    return Ok(());
+                }
+                return Err(Error::SysError(SysErr::EFAULT));
+            }
+            None => return Err(Error::SysError(SysErr::EINVAL)),
+        }
+    }
+
+    pub fn NewQPNum(&self) -> u32 {
+        return self.nextQPNum.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn Complete(&self, wc: WorkCompletion) {
+        self.completeQueue.lock().push_back(wc);
+        let data: u64 = 1;
+        unsafe {
+            write(self.EventFd(), &data as *const _ as *const c_void, 8);
+        }
+    }
+
+    pub fn PollCompletion(&self) -> Option<WorkCompletion> {
+        return self.completeQueue.lock().pop_front();
+    }
+
+    pub fn AckEvent(&self) {
+        let mut data: u64 = 0;
+        unsafe {
+            read(self.EventFd(), &mut data as *mut _ as *mut c_void, 8);
+        }
+    }
+
+    pub fn SetUDQueuePair(&self, qp: Arc<TcpQueuePair>) {
+        *self.udQP.lock() = Some(qp);
+    }
+
+    pub fn UDQueuePair(&self) -> Option<Arc<TcpQueuePair>> {
+        return self.udQP.lock().clone();
+    }
+}
+
+pub const FRAME_OP_WRITE_IMM: u32 = 1;
+pub const FRAME_OP_UD_SEND: u32 = 2;
+
+// the UD receive buffer starts with the 40 bytes GRH which is filled by the
+// NIC on the RDMA transport
+pub const UD_GRH_SIZE: u32 = 40;
+
+// the payload of a frame is at most the socket buffer of a connection, a
+// larger length comes from a broken or malicious peer
+pub const MAX_FRAME_PAYLOAD: u32 =
+    (MemoryDef::DEFAULT_BUF_PAGE_COUNT * MemoryDef::PAGE_SIZE) as u32;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct FrameHeader {
+    pub op: u32,
+    pub imm: u32,
+    pub len: u32,
+    pub rkey: u32,
+    pub raddr: u64,
+}
+
+impl FrameHeader {
+    pub fn Size() -> usize {
+        return mem::size_of::<Self>();
+    }
+}
+
+// RC queue pair over the TCP connection to the peer node, or the UD queue
+// pair when fd is -1
+pub struct TcpQueuePair {
+    pub fd: i32,
+    pub qpNum: u32,
+    pub recvQueue: Mutex<VecDeque<RecvRequest>>,
+    // WRITE_IMM arrived before a receive request was posted, completed by
+    // the next PostRecv
+    pub pendingRecvs: Mutex<VecDeque<WorkCompletion>>,
+    pub writeBuf: Mutex<VecDeque<u8>>,
+    pub readBuf: Mutex<Vec<u8>>,
+    pub closed: AtomicBool,
+}
+
+impl TcpQueuePair {
+    pub fn New(fd: i32) -> Self {
+        return Self {
+            fd: fd,
+            qpNum: TCP_TRANSPORT.NewQPNum(),
+            recvQueue: Mutex::new(VecDeque::new()),
+            pendingRecvs: Mutex::new(VecDeque::new()),
+            writeBuf: Mutex::new(VecDeque::new()),
+            readBuf: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+        };
+    }
+
+    pub fn IsClosed(&self) -> bool {
+        return self.closed.load(Ordering::Acquire);
+    }
+
+    // the connection is broken: fail the later work requests and drop the
+    // queued ones, the fd is closed by the owner of the connection
+    pub fn Close(&self) {
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        error!(
+            "TcpQueuePair::Close, fd: {}, qpNum: {}",
+            self.fd, self.qpNum
+        );
+        self.recvQueue.lock().clear();
+        self.pendingRecvs.lock().clear();
+        self.writeBuf.lock().clear();
+        self.readBuf.lock().clear();
+    }
+
+    pub fn SendUD(&self, wrId: u64, laddr: u64, len: u32, lkey: u32) -> Result<()> {
+        TCP_TRANSPORT.CheckAccess(lkey, laddr, len)?;
+        let header = FrameHeader {
+            op: FRAME_OP_UD_SEND,
+            imm: 0,
+            len: len,
+            rkey: 0,
+            raddr: 0,
+        };
+        self.SendFrame(&header, laddr)?;
+        TCP_TRANSPORT.Complete(WorkCompletion {
+            wrId: wrId,
+            op: WorkCompletionOp::Send,
+            qpNum: self.qpNum,
+            imm: 0,
+            byteLen: len,
+        });
+        return Ok(());
+    }
+
+    fn SendFrame(&self, header: &FrameHeader, laddr: u64) -> Result<()> {
+        if self.fd < 0 || self.IsClosed() {
+            return Err(Error::SysError(SysErr::ENOTCONN));
+        }
+
+        let headerBuf =
+            unsafe { slice::from_raw_parts(header as *const _ as *const u8, FrameHeader::Size()) };
+        let payload = unsafe { slice::from_raw_parts(laddr as *const u8, header.len as usize) };
+        let mut writeBuf = self.writeBuf.lock();
+        writeBuf.extend(headerBuf.iter());
+        writeBuf.extend(payload.iter());
+        return self.FlushLocked(&mut writeBuf);
+    }
+
+    fn FlushLocked(&self, writeBuf: &mut VecDeque<u8>) -> Result<()> {
+        while writeBuf.len() > 0 {
+            let (data, _) = writeBuf.as_slices();
+            let ret = unsafe { write(self.fd, data.as_ptr() as *const c_void, data.len()) };
+            if ret < 0 {
+                let errno = errno::errno().0;
+                if errno == SysErr::EAGAIN {
+                    // the rest is sent when the socket is writable again
+                    return Ok(());
+                }
+                return Err(Error::SysError(errno));
+            }
+
+            writeBuf.drain(..ret as usize);
+        }
+
+        return Ok(());
+    }
+
+    pub fn Flush(&self) -> Result<()> {
+        let mut writeBuf = self.writeBuf.lock();
+        return self.FlushLocked(&mut writeBuf);
+    }
+
+    // read all the available data from the connection and process the
+    // complete frames
+    pub fn Recv(&self) -> Result<()> {
+        let mut readBuf = self.readBuf.lock();
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let ret = unsafe { read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            if ret < 0 {
+                let errno = errno::errno().0;
+                if errno == SysErr::EAGAIN {
+                    break;
+                }
+                return Err(Error::SysError(errno));
+            }
+
+            if ret == 0 {
+                error!("TcpQueuePair::Recv, peer closed, fd: {}", self.fd);
+                return Err(Error::SysError(SysErr::ECONNRESET));
+            }
+
+            readBuf.extend_from_slice(&buf[..ret as usize]);
+        }
+
+        let mut offset = 0;
+        while readBuf.len() - offset >= FrameHeader::Size() {
+            let header =
+                unsafe { ptr::read_unaligned(readBuf[offset..].as_ptr() as *const FrameHeader) };
+            // validate the header before buffering the payload
+            Self::CheckFrame(&header)?;
+
+            let frameLen = FrameHeader::Size() + header.len as usize;
+            if readBuf.len() - offset < frameLen {
+                break;
+            }
+
+            let payload = &readBuf[offset + FrameHeader::Size()..offset + frameLen];
+            match header.op {
+                FRAME_OP_WRITE_IMM => self.ProcessWriteImm(&header, payload),
+                FRAME_OP_UD_SEND => Self::ProcessUDSend(payload),
+                _ => unreachable!(),
+            }
+            offset += frameLen;
+        }
+
+        readBuf.drain(..offset);
+        return Ok(());
+    }
+
+    fn CheckFrame(header: &FrameHeader) -> Result<()> {
+        if header.len > MAX_FRAME_PAYLOAD {
+            error!(
+                "TcpQueuePair::CheckFrame, frame len {} exceeds {}",
+                header.len, MAX_FRAME_PAYLOAD
+            );
+            return Err(Error::SysError(SysErr::EPROTO));
+        }
+
+        match header.op {
+            FRAME_OP_WRITE_IMM => {
+                match TCP_TRANSPORT.CheckAccess(header.rkey, header.raddr, header.len) {
+                    Err(e) => {
+                        error!(
+                            "TcpQueuePair::CheckFrame, invalid remote access rkey: {}, raddr: {:x}, len: {}, error: {:?}",
+                            header.rkey, header.raddr, header.len, e
+                        );
+                        return Err(e);
+                    }
+                    Ok(()) => return Ok(()),
+                }
+            }
+            FRAME_OP_UD_SEND => return Ok(()),
+            _ => {
+                error!("TcpQueuePair::CheckFrame, unknown frame op: {}", header.op);
+                return Err(Error::SysError(SysErr::EPROTO));
+            }
+        }
+    }
+
+    fn ProcessWriteImm(&self, header: &FrameHeader, payload: &[u8]) {
+        match TCP_TRANSPORT.CheckAccess(header.rkey, header.raddr, header.len) {
+            Err(e) => {
+                error!(
+                    "TcpQueuePair::ProcessWriteImm, invalid remote access rkey: {}, raddr: {:x}, len: {}, error: {:?}",
+                    header.rkey, header.raddr, header.len, e
+                );
+                return;
+            }
+            Ok(()) => (),
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(payload.as_ptr(), header.raddr as *mut u8, payload.len());
+        }
+
+        let mut wc = WorkCompletion {
+            wrId: 0,
+            op: WorkCompletionOp::RecvRDMAWithImm,
+            qpNum: self.qpNum,
+            imm: header.imm,
+            byteLen: header.len,
+        };
+
+        let recv = self.recvQueue.lock().pop_front();
+        match recv {
+            Some(recv) => {
+                wc.wrId = recv.wrId;
+                TCP_TRANSPORT.Complete(wc);
+            }
+            None => {
+                self.pendingRecvs.lock().push_back(wc);
+            }
+        }
+    }
+
+    fn ProcessUDSend(payload: &[u8]) {
+        let udQP = match TCP_TRANSPORT.UDQueuePair() {
+            Some(qp) => qp,
+            None => return,
+        };
+
+        // same as UD on RDMA, the packet is dropped when there is no
+        // receive request
+        let recv = match udQP.recvQueue.lock().pop_front() {
+            Some(recv) => recv,
+            None => return,
+        };
+
+        if payload.len() as u32 + UD_GRH_SIZE > recv.len {
+            error!(
+                "TcpQueuePair::ProcessUDSend, packet len {} exceeds recv buffer len {}",
+                payload.len(),
+                recv.len
+            );
+            udQP.recvQueue.lock().push_front(recv);
+            return;
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                payload.as_ptr(),
+                (recv.addr + UD_GRH_SIZE as u64) as *mut u8,
+                payload.len(),
+            );
+        }
+
+        TCP_TRANSPORT.Complete(WorkCompletion {
+            wrId: recv.wrId,
+            op: WorkCompletionOp::Recv,
+            qpNum: udQP.qpNum,
+            imm: 0,
+            byteLen: payload.len() as u32 + UD_GRH_SIZE,
+        });
+    }
+
+    fn HandleEvents(&self, eventmask: EventMask) -> Result<()> {
+        if self.IsClosed() {
+            return Err(Error::SysError(SysErr::ENOTCONN));
+        }
+
+        if eventmask & EVENT_WRITE != 0 {
+            self.Flush()?;
+        }
+
+        if eventmask & EVENT_READ != 0 {
+            self.Recv()?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl QueuePairOps for TcpQueuePair {
+    fn qpNum(&self) -> u32 {
+        return self.qpNum;
+    }
+
+    fn WriteImm(
+        &self,
+        wrId: u64,
+        laddr: u64,
+        len: u32,
+        lkey: u32,
+        raddr: u64,
+        rkey: u32,
+        imm: u32,
+    ) -> Result<()> {
+        TCP_TRANSPORT.CheckAccess(lkey, laddr, len)?;
+        let header = FrameHeader {
+            op: FRAME_OP_WRITE_IMM,
+            imm: imm,
+            len: len,
+            rkey: rkey,
+            raddr: raddr,
+        };
+        self.SendFrame(&header, laddr)?;
+        TCP_TRANSPORT.Complete(WorkCompletion {
+            wrId: wrId,
+            op: WorkCompletionOp::RDMAWrite,
+            qpNum: self.qpNum,
+            imm: 0,
+            byteLen: len,
+        });
+        return Ok(());
+    }
+
+    fn PostRecv(&self, wrId: u64, addr: u64, _lkey: u32, length: u32) -> Result<()> {
+        if self.IsClosed() {
+            return Err(Error::SysError(SysErr::ENOTCONN));
+        }
+
+        let pending = self.pendingRecvs.lock().pop_front();
+        match pending {
+            Some(mut wc) => {
+                wc.wrId = wrId;
+                TCP_TRANSPORT.Complete(wc);
+            }
+            None => {
+                self.recvQueue.lock().push_back(RecvRequest {
+                    wrId: wrId,
+                    addr: addr,
+                    len: length,
+                });
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn PostSendUDQP(
+        &self,
+        ah: &AddressHandler,
+        _remote_qpn: u32,
+        wrId: u64,
+        laddr: u64,
+        len: u32,
+        lkey: u32,
+    ) -> Result<()> {
+        match ah {
+            AddressHandler::TCP(rcQP) => return rcQP.SendUD(wrId, laddr, len, lkey),
+            AddressHandler::Verbs(_) => return Err(Error::SysError(SysErr::EINVAL)),
+        }
+    }
+
+    fn SetupRCQP(
+        &self,
+        _context: &RDMAContext,
+        _remote_qpn: u32,
+        _dlid: u16,
+        _dgid: Gid,
+    ) -> Result<()> {
+        return Ok(());
+    }
+
+    fn SetupUDQP(&self, _context: &RDMAContext) -> Result<()> {
+        return Ok(());
+    }
+
+    // the UD packets to the peer go through this RC connection
+    fn CreateAddressHandler(
+        self: Arc<Self>,
+        _context: &RDMAContext,
+        _lid: u16,
+        _gid: Gid,
+    ) -> Result<AddressHandler> {
+        return Ok(AddressHandler::TCP(self));
+    }
+
+    fn Notify(&self, eventmask: EventMask) -> Result<()> {
+        let ret = self.HandleEvents(eventmask);
+        if let Err(e) = &ret {
+            error!("TcpQueuePair::Notify, fd: {}, error: {:?}", self.fd, e);
+            self.Close();
+        }
+        return ret;
+    }
+}